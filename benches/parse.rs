@@ -0,0 +1,206 @@
+//! Performance benchmarks for parsing, text extraction and resource access
+//!
+//! Books are generated on the fly so the numbers don't depend on fixtures
+//! that can't be redistributed. Run with `cargo bench`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use epubie_lib::Epub;
+use std::hint::black_box;
+use std::io::Write;
+use std::path::PathBuf;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+/// Size profile of a generated book
+struct BookSize {
+    name: &'static str,
+    chapters: usize,
+    paragraphs_per_chapter: usize,
+    images: usize,
+}
+
+const SIZES: &[BookSize] = &[
+    BookSize {
+        name: "small",
+        chapters: 5,
+        paragraphs_per_chapter: 20,
+        images: 2,
+    },
+    BookSize {
+        name: "medium",
+        chapters: 50,
+        paragraphs_per_chapter: 100,
+        images: 20,
+    },
+    BookSize {
+        name: "huge",
+        chapters: 400,
+        paragraphs_per_chapter: 200,
+        images: 200,
+    },
+];
+
+const PARAGRAPH: &str = "It was the best of times, it was the worst of times, it was the age \
+    of wisdom, it was the age of foolishness, it was the epoch of belief, it was the epoch \
+    of incredulity, it was the season of Light, it was the season of Darkness.";
+
+/// Write a synthetic EPUB 3 book to the temp directory and return its path
+fn generate_book(size: &BookSize) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("epubie-bench-{}.epub", size.name));
+    let file = std::fs::File::create(&path).expect("create bench book");
+    let mut zip = ZipWriter::new(file);
+    let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default();
+
+    zip.start_file("mimetype", stored).unwrap();
+    zip.write_all(b"application/epub+zip").unwrap();
+
+    zip.start_file("META-INF/container.xml", deflated).unwrap();
+    zip.write_all(
+        br#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>"#,
+    )
+    .unwrap();
+
+    let mut manifest = String::from(
+        r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+    );
+    let mut spine = String::new();
+    let mut nav = String::new();
+
+    for i in 0..size.images {
+        let properties = if i == 0 {
+            r#" properties="cover-image""#
+        } else {
+            ""
+        };
+        manifest.push_str(&format!(
+            r#"<item id="img{i}" href="images/img{i}.png" media-type="image/png"{properties}/>"#
+        ));
+        zip.start_file(format!("OEBPS/images/img{}.png", i), stored)
+            .unwrap();
+        zip.write_all(&vec![0u8; 16 * 1024]).unwrap();
+    }
+
+    for i in 0..size.chapters {
+        manifest.push_str(&format!(
+            r#"<item id="chapter_{i}" href="chapter{i}.xhtml" media-type="application/xhtml+xml"/>"#
+        ));
+        spine.push_str(&format!(r#"<itemref idref="chapter_{i}"/>"#));
+        nav.push_str(&format!(
+            r#"<li><a href="chapter{i}.xhtml">Chapter {i}</a></li>"#
+        ));
+
+        let mut body = format!("<h1>Chapter {}</h1>", i);
+        for _ in 0..size.paragraphs_per_chapter {
+            body.push_str("<p>");
+            body.push_str(PARAGRAPH);
+            body.push_str("</p>");
+        }
+
+        zip.start_file(format!("OEBPS/chapter{}.xhtml", i), deflated)
+            .unwrap();
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><head><title>Chapter {i}</title></head><body>{body}</body></html>"#
+        )
+        .unwrap();
+    }
+
+    zip.start_file("OEBPS/nav.xhtml", deflated).unwrap();
+    write!(
+        zip,
+        r#"<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"><body><nav epub:type="toc"><ol>{nav}</ol></nav></body></html>"#
+    )
+    .unwrap();
+
+    zip.start_file("OEBPS/content.opf", deflated).unwrap();
+    write!(
+        zip,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:uuid:bench-{name}</dc:identifier>
+    <dc:title>Benchmark Book ({name})</dc:title>
+    <dc:creator>Bench</dc:creator>
+    <dc:language>en</dc:language>
+  </metadata>
+  <manifest>{manifest}</manifest>
+  <spine>{spine}</spine>
+</package>"#,
+        name = size.name
+    )
+    .unwrap();
+
+    zip.finish().unwrap();
+    path
+}
+
+fn bench_open(c: &mut Criterion) {
+    let mut group = c.benchmark_group("open");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let path = generate_book(size);
+        let bytes = std::fs::metadata(&path).unwrap().len();
+        group.throughput(Throughput::Bytes(bytes));
+        group.bench_with_input(BenchmarkId::from_parameter(size.name), &path, |b, path| {
+            b.iter(|| Epub::new(path.to_string_lossy().into_owned()).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_text(c: &mut Criterion) {
+    let mut group = c.benchmark_group("text");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let path = generate_book(size);
+        let epub = Epub::new(path.to_string_lossy().into_owned()).unwrap();
+        let html_bytes: usize = epub.get_all_files().iter().map(|f| f.content.len()).sum();
+        group.throughput(Throughput::Bytes(html_bytes as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size.name), &epub, |b, epub| {
+            b.iter(|| {
+                for file in epub.get_all_files() {
+                    black_box(file.get_text());
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_resources(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resources");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let path = generate_book(size);
+        let epub = Epub::new(path.to_string_lossy().into_owned()).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("cover", size.name), &epub, |b, epub| {
+            b.iter(|| black_box(epub.get_cover_bytes()))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("last_image", size.name),
+            &epub,
+            |b, epub| {
+                let href = format!("images/img{}.png", size.images - 1);
+                b.iter(|| black_box(epub.get_resource_bytes(&href)))
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_open, bench_text, bench_resources);
+criterion_main!(benches);
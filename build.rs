@@ -0,0 +1,5 @@
+fn main() {
+    // Node addons need platform-specific linker flags (e.g. dynamic lookup on macOS)
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
@@ -10,11 +10,11 @@ fn main() {
         Ok(epub) => {
             // Display basic metadata
             println!("\n=== EPUB Metadata ===");
-            println!("Title: {}", epub.get_title());
-            println!("Creator: {}", epub.get_creator());
-            println!("Language: {}", epub.get_language());
+            println!("Title: {}", epub.get_title().unwrap_or("Unknown"));
+            println!("Creator: {}", epub.get_creator().unwrap_or("Unknown"));
+            println!("Language: {}", epub.get_language().unwrap_or("Unknown"));
             println!("Identifier: {}", epub.get_identifier());
-            println!("Date: {}", epub.get_date());
+            println!("Date: {}", epub.get_date().unwrap_or("Unknown"));
 
             if let Some(publisher) = epub.get_publisher() {
                 println!("Publisher: {}", publisher);
@@ -62,7 +62,7 @@ fn main() {
                 toc.get_entry_count()
             );
             for (i, entry) in toc.get_entries().iter().enumerate() {
-                let indent = "  ".repeat(entry.get_level() as usize);
+                let indent = "  ".repeat(entry.get_level());
                 println!(
                     "{}{}: {} ({})",
                     indent,
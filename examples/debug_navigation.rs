@@ -4,6 +4,7 @@ use std::io::Read;
 use zip::ZipArchive;
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Package {
     metadata: OpfMetadata,
     manifest: Manifest,
@@ -11,6 +12,7 @@ struct Package {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct OpfMetadata {
     #[serde(rename = "dc:identifier", default)]
     identifier: Vec<String>,
@@ -29,6 +31,7 @@ struct OpfMetadata {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Meta {
     #[serde(rename = "@name", default)]
     name: Option<String>,
@@ -41,12 +44,14 @@ struct Meta {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Manifest {
     #[serde(rename = "item", default)]
     item: Vec<ManifestItem>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct ManifestItem {
     #[serde(rename = "@id")]
     id: String,
@@ -59,12 +64,14 @@ struct ManifestItem {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Spine {
     #[serde(rename = "itemref", default)]
     itemref: Vec<ItemRef>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct ItemRef {
     #[serde(rename = "@idref")]
     idref: String,
@@ -113,9 +120,7 @@ fn main() {
                                                 package.manifest.item.iter().find(|item| {
                                                     item.properties
                                                         .as_ref()
-                                                        .map_or(false, |props| {
-                                                            props.contains("nav")
-                                                        })
+                                                        .is_some_and(|props| props.contains("nav"))
                                                 });
 
                                             if let Some(nav) = nav_item {
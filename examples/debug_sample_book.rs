@@ -4,18 +4,21 @@ use std::io::Read;
 use zip::ZipArchive;
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Container {
     #[serde(rename = "rootfiles")]
     rootfiles: RootFiles,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct RootFiles {
     #[serde(rename = "rootfile")]
     rootfile: Vec<RootFile>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct RootFile {
     #[serde(rename = "@full-path", default)]
     full_path: String,
@@ -24,6 +27,7 @@ struct RootFile {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Package {
     metadata: OpfMetadata,
     manifest: Manifest,
@@ -31,6 +35,7 @@ struct Package {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct OpfMetadata {
     #[serde(rename = "dc:identifier", default)]
     identifier: Vec<String>,
@@ -49,6 +54,7 @@ struct OpfMetadata {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Meta {
     #[serde(rename = "@name", default)]
     name: Option<String>,
@@ -61,12 +67,14 @@ struct Meta {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Manifest {
     #[serde(rename = "item", default)]
     item: Vec<ManifestItem>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct ManifestItem {
     #[serde(rename = "@id")]
     id: String,
@@ -79,12 +87,14 @@ struct ManifestItem {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct Spine {
     #[serde(rename = "itemref", default)]
     itemref: Vec<ItemRef>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct ItemRef {
     #[serde(rename = "@idref")]
     idref: String,
@@ -95,6 +105,7 @@ fn parse_container_xml(xml: &str) -> Result<Container, Box<dyn std::error::Error
     Ok(container)
 }
 
+#[allow(dead_code)]
 fn parse_opf_xml(xml: &str) -> Result<Package, Box<dyn std::error::Error>> {
     let package: Package = serde_xml_rs::from_str(xml)?;
     Ok(package)
@@ -0,0 +1,127 @@
+/* Generated by cbindgen from src/capi.rs. Do not edit by hand. */
+
+#ifndef EPUBIE_H
+#define EPUBIE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Main EPUB container that holds all parsed data
+typedef struct Epub Epub;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Open and parse the EPUB file at `path`
+//
+// Returns `NULL` on failure; see `epub_last_error`.
+//
+// # Safety
+// `path` must be null or a valid NUL-terminated string.
+struct Epub *epub_open(const char *path);
+
+// Release a handle returned by `epub_open`
+//
+// # Safety
+// `epub` must be null or a handle from `epub_open` that has not been freed.
+void epub_free(struct Epub *epub);
+
+// Describe the most recent error on the calling thread, or `NULL`
+//
+// The returned string is owned by the library and valid until the next
+// failing call on the same thread.
+const char *epub_last_error(void);
+
+// Release a string returned by this library
+//
+// # Safety
+// `s` must be null or a string returned by one of the `epub_*` functions.
+void epub_string_free(char *s);
+
+// Release a byte buffer returned by this library
+//
+// # Safety
+// `data` and `len` must come from the same `epub_*` call, and the buffer must
+// not have been freed already.
+void epub_bytes_free(uint8_t *data, size_t len);
+
+// Book title, or `NULL` if the book has none
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_title(const struct Epub *epub);
+
+// First creator (author), or `NULL`
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_creator(const struct Epub *epub);
+
+// Book language, or `NULL`
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_language(const struct Epub *epub);
+
+// Unique identifier of the book
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_identifier(const struct Epub *epub);
+
+// Number of chapters, or 0 for a null handle
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+size_t epub_chapter_count(const struct Epub *epub);
+
+// Title of the chapter at `index`, or `NULL` if out of range
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_chapter_title(const struct Epub *epub, size_t index);
+
+// Number of content documents, or 0 for a null handle
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+size_t epub_file_count(const struct Epub *epub);
+
+// Href of the content document at `index`, or `NULL` if out of range
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_file_href(const struct Epub *epub, size_t index);
+
+// Raw XHTML of the content document at `index`, or `NULL` if out of range
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_file_content(const struct Epub *epub, size_t index);
+
+// Plain text of the content document at `index`, or `NULL` if out of range
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`.
+char *epub_file_text(const struct Epub *epub, size_t index);
+
+// Cover image bytes, or `NULL` if the book has no cover
+//
+// The length is written to `out_len`. Release with `epub_bytes_free`.
+// Without somewhere to put the length the buffer couldn't be freed, so a
+// null `out_len` gets `NULL`.
+//
+// # Safety
+// `epub` must be null or a live handle returned by `epub_open`, and
+// `out_len` must be null or point to writable memory.
+uint8_t *epub_cover_bytes(const struct Epub *epub, size_t *out_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* EPUBIE_H */
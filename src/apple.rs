@@ -0,0 +1,181 @@
+//! Apple Books display options
+//!
+//! Books made for Apple Books often carry
+//! `META-INF/com.apple.ibooks.display-options.xml`, and many fixed-layout
+//! books declare their layout only there rather than with EPUB 3 rendition
+//! properties:
+//!
+//! ```xml
+//! <display_options>
+//!   <platform name="*">
+//!     <option name="fixed-layout">true</option>
+//!     <option name="specified-fonts">true</option>
+//!   </platform>
+//!   <platform name="iphone">
+//!     <option name="orientation-lock">landscape-only</option>
+//!   </platform>
+//! </display_options>
+//! ```
+
+use crate::epub::Epub;
+use crate::text::decode_entities;
+use crate::xhtml::find_elements;
+
+/// Archive path of the display options file
+const DISPLAY_OPTIONS_PATH: &str = "/META-INF/com.apple.ibooks.display-options.xml";
+
+/// One `<option>`, with the platform it applies to (`*` for all)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppleDisplayOption {
+    platform: String,
+    name: String,
+    value: String,
+}
+
+impl AppleDisplayOption {
+    /// `*`, `ipad` or `iphone`
+    pub fn get_platform(&self) -> &str {
+        &self.platform
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// The contents of `com.apple.ibooks.display-options.xml`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppleDisplayOptions {
+    options: Vec<AppleDisplayOption>,
+}
+
+impl AppleDisplayOptions {
+    /// Parse the display options document
+    pub fn parse(xml: &str) -> Self {
+        let option = |platform: &str, element: &crate::xhtml::Element| AppleDisplayOption {
+            platform: platform.to_string(),
+            name: element.attribute("name").unwrap_or("").trim().to_string(),
+            value: decode_entities(element.inner.trim()),
+        };
+        let mut options = Vec::new();
+        for element in find_elements(xml, |e| e.name == "platform" || e.name == "option") {
+            if element.name == "option" {
+                options.push(option("*", &element));
+                continue;
+            }
+            let platform = element.attribute("name").unwrap_or("*").trim().to_string();
+            for element in find_elements(element.inner, |e| e.name == "option") {
+                options.push(option(&platform, &element));
+            }
+        }
+        options.retain(|option| !option.name.is_empty());
+        AppleDisplayOptions { options }
+    }
+
+    pub fn get_options(&self) -> &[AppleDisplayOption] {
+        &self.options
+    }
+
+    /// The value of option `name` on `platform`, falling back to the one for all platforms
+    pub fn get_for_platform(&self, platform: &str, name: &str) -> Option<&str> {
+        let find = |platform: &str| {
+            self.options
+                .iter()
+                .find(|option| option.platform == platform && option.name == name)
+                .map(|option| option.value.as_str())
+        };
+        find(platform).or_else(|| find("*"))
+    }
+
+    /// The value of option `name` for all platforms, else for any platform
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_for_platform("*", name).or_else(|| {
+            self.options
+                .iter()
+                .find(|option| option.name == name)
+                .map(|option| option.value.as_str())
+        })
+    }
+
+    /// Whether the book's embedded fonts should be used
+    pub fn specified_fonts(&self) -> bool {
+        self.flag("specified-fonts")
+    }
+
+    /// Whether the book is fixed-layout
+    pub fn fixed_layout(&self) -> bool {
+        self.flag("fixed-layout")
+    }
+
+    /// Whether a fixed-layout book opens as a two-page spread
+    pub fn open_to_spread(&self) -> bool {
+        self.flag("open-to-spread")
+    }
+
+    /// `portrait-only` or `landscape-only`; `None` when unlocked
+    pub fn orientation_lock(&self) -> Option<&str> {
+        self.get("orientation-lock")
+            .map(str::trim)
+            .filter(|lock| !lock.is_empty() && *lock != "none")
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.get(name)
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    }
+}
+
+impl Epub {
+    /// The Apple Books display options, if the book has them
+    pub fn apple_display_options(&self) -> Option<AppleDisplayOptions> {
+        let bytes = self.get_resource_bytes(DISPLAY_OPTIONS_PATH)?;
+        Some(AppleDisplayOptions::parse(&String::from_utf8_lossy(&bytes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn display_options_are_read_per_platform() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let options = r#"<?xml version="1.0" encoding="UTF-8"?>
+<display_options>
+  <platform name="*">
+    <option name="fixed-layout">true</option>
+    <option name="specified-fonts">true</option>
+  </platform>
+  <platform name="iphone">
+    <option name="orientation-lock">landscape-only</option>
+  </platform>
+</display_options>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("META-INF/com.apple.ibooks.display-options.xml", options),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let options = epub.apple_display_options().unwrap();
+        assert!(options.fixed_layout());
+        assert!(options.specified_fonts());
+        assert!(!options.open_to_spread());
+        assert_eq!(options.orientation_lock(), Some("landscape-only"));
+        assert_eq!(options.get_for_platform("ipad", "orientation-lock"), None);
+        assert_eq!(
+            options.get_for_platform("iphone", "fixed-layout"),
+            Some("true")
+        );
+        assert_eq!(options.get_options().len(), 3);
+    }
+}
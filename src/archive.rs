@@ -0,0 +1,110 @@
+//! Where the bytes of an EPUB come from
+//!
+//! A book can be opened from a path on disk, from an in-memory buffer or,
+//! with the `remote` feature, from a URL. All are wrapped in `ArchiveReader` so the parser works against a single
+//! `ZipArchive` type regardless of the origin.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use zip::read::{ZipArchive, ZipFile};
+use zip::result::{ZipError, ZipResult};
+
+/// The origin of an EPUB archive, kept so resources can be read on demand
+#[derive(Debug, Clone)]
+pub(crate) enum Source {
+    Path(String),
+    Bytes(Arc<[u8]>),
+    #[cfg(feature = "remote")]
+    Remote(Arc<crate::remote::RemoteFile>),
+}
+
+impl Source {
+    /// Open a fresh zip archive over the source
+    pub(crate) fn open(&self) -> Result<ZipArchive<ArchiveReader>, Box<dyn Error>> {
+        let reader = match self {
+            Source::Path(path) => ArchiveReader::File(File::open(path)?),
+            Source::Bytes(bytes) => ArchiveReader::Bytes(Cursor::new(Arc::clone(bytes))),
+            #[cfg(feature = "remote")]
+            Source::Remote(file) => {
+                ArchiveReader::Remote(crate::remote::RemoteReader::new(Arc::clone(file)))
+            }
+        };
+        Ok(ZipArchive::new(reader)?)
+    }
+}
+
+/// Index of the archive entry named `name`
+///
+/// In tolerant mode backslashes count as separators on both sides and, if no
+/// entry matches exactly, names are compared case-insensitively.
+pub(crate) fn entry_index(
+    archive: &ZipArchive<ArchiveReader>,
+    name: &str,
+    tolerant: bool,
+) -> Option<usize> {
+    if let Some(index) = archive.index_for_name(name) {
+        return Some(index);
+    }
+    if !tolerant {
+        return None;
+    }
+
+    let wanted = name.replace('\\', "/");
+    let normalized = |index: usize| {
+        archive
+            .name_for_index(index)
+            .map(|entry| entry.replace('\\', "/"))
+    };
+    (0..archive.len())
+        .find(|&index| normalized(index).is_some_and(|entry| entry == wanted))
+        .or_else(|| {
+            let wanted = wanted.to_lowercase();
+            (0..archive.len()).find(|&index| {
+                normalized(index).is_some_and(|entry| entry.to_lowercase() == wanted)
+            })
+        })
+}
+
+/// Open the archive entry named `name`, see `entry_index` for tolerant matching
+pub(crate) fn open_entry<'a>(
+    archive: &'a mut ZipArchive<ArchiveReader>,
+    name: &str,
+    tolerant: bool,
+) -> ZipResult<ZipFile<'a, ArchiveReader>> {
+    match entry_index(archive, name, tolerant) {
+        Some(index) => archive.by_index(index),
+        None => Err(ZipError::FileNotFound),
+    }
+}
+
+/// A seekable reader over a file, a shared byte buffer or a remote file
+pub(crate) enum ArchiveReader {
+    File(File),
+    Bytes(Cursor<Arc<[u8]>>),
+    #[cfg(feature = "remote")]
+    Remote(crate::remote::RemoteReader),
+}
+
+impl Read for ArchiveReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ArchiveReader::File(file) => file.read(buf),
+            ArchiveReader::Bytes(cursor) => cursor.read(buf),
+            #[cfg(feature = "remote")]
+            ArchiveReader::Remote(remote) => remote.read(buf),
+        }
+    }
+}
+
+impl Seek for ArchiveReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            ArchiveReader::File(file) => file.seek(pos),
+            ArchiveReader::Bytes(cursor) => cursor.seek(pos),
+            #[cfg(feature = "remote")]
+            ArchiveReader::Remote(remote) => remote.seek(pos),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Bibliographies
+//!
+//! `Epub::get_bibliography` collects the entries of a book's bibliography
+//! sections: elements typed `biblioentry`, or else the list items or
+//! paragraphs of anything with an `epub:type` or class of `bibliography`
+//! (or a document whose file name says it is one). Citation styles vary too
+//! much to parse reliably, so each entry keeps its raw text and only the
+//! identifiers that can be recognized unambiguously are pulled out.
+
+use crate::epub::Epub;
+use crate::text::html_to_text;
+use crate::xhtml::find_elements;
+use regex::Regex;
+use std::sync::LazyLock;
+
+/// One work cited in a bibliography
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BibliographyEntry {
+    text: String,
+    href: String,
+    id: Option<String>,
+    dois: Vec<String>,
+    isbns: Vec<String>,
+    urls: Vec<String>,
+}
+
+impl BibliographyEntry {
+    /// The entry as plain text, as the book formats it
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Href of the file holding the bibliography
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// DOIs such as `10.1000/182`, without any `doi:` or resolver prefix
+    pub fn get_dois(&self) -> &[String] {
+        &self.dois
+    }
+
+    /// ISBNs with valid check digits, reduced to their digits
+    pub fn get_isbns(&self) -> &[String] {
+        &self.isbns
+    }
+
+    /// Web addresses from the text and from links in the entry
+    pub fn get_urls(&self) -> &[String] {
+        &self.urls
+    }
+}
+
+impl Epub {
+    /// Every bibliography entry in the book, in reading order
+    pub fn get_bibliography(&self) -> Vec<BibliographyEntry> {
+        let mut entries = Vec::new();
+        for file in self.get_spine_files() {
+            let content = file.get_content();
+            let typed = find_elements(content, |e| e.has_token("epub:type", "biblioentry"));
+            let items = if !typed.is_empty() {
+                typed
+            } else {
+                let name = file.get_href().rsplit('/').next().unwrap_or("");
+                let regions: Vec<&str> = if name.to_ascii_lowercase().contains("bibliography") {
+                    vec![content]
+                } else {
+                    find_elements(content, |e| {
+                        e.has_token("epub:type", "bibliography")
+                            || e.has_token("class", "bibliography")
+                    })
+                    .into_iter()
+                    .map(|region| region.inner)
+                    .collect()
+                };
+                regions
+                    .into_iter()
+                    .flat_map(|region| {
+                        let items = find_elements(region, |e| e.name == "li");
+                        if items.is_empty() {
+                            find_elements(region, |e| e.name == "p")
+                        } else {
+                            items
+                        }
+                    })
+                    .collect()
+            };
+
+            for item in items {
+                let text = html_to_text(item.inner).replace('\n', " ");
+                if text.is_empty() {
+                    continue;
+                }
+                let links: Vec<String> = find_elements(item.inner, |e| e.name == "a")
+                    .iter()
+                    .filter_map(|link| link.attribute("href"))
+                    .map(str::to_string)
+                    .collect();
+                entries.push(BibliographyEntry {
+                    dois: find_dois(&text, &links),
+                    isbns: find_isbns(&text),
+                    urls: find_urls(&text, &links),
+                    text,
+                    href: file.get_href().to_string(),
+                    id: item.attribute("id").map(str::to_string),
+                });
+            }
+        }
+        entries
+    }
+}
+
+/// Trailing punctuation that ends a sentence rather than an identifier
+fn trim_trailing(identifier: &str) -> &str {
+    identifier.trim_end_matches(['.', ',', ';', ':', ')', ']', '\'', '"'])
+}
+
+fn push_unique(list: &mut Vec<String>, item: String) {
+    if !list.contains(&item) {
+        list.push(item);
+    }
+}
+
+fn find_dois(text: &str, links: &[String]) -> Vec<String> {
+    static DOI: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\b10\.\d{4,9}/[^\s<>]+").expect("DOI pattern"));
+    let mut dois = Vec::new();
+    for source in std::iter::once(text).chain(links.iter().map(String::as_str)) {
+        for found in DOI.find_iter(source) {
+            push_unique(&mut dois, trim_trailing(found.as_str()).to_string());
+        }
+    }
+    dois
+}
+
+fn find_urls(text: &str, links: &[String]) -> Vec<String> {
+    static URL: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"https?://[^\s<>]+").expect("URL pattern"));
+    let mut urls = Vec::new();
+    for link in links {
+        if link.starts_with("http://") || link.starts_with("https://") {
+            push_unique(&mut urls, link.clone());
+        }
+    }
+    for found in URL.find_iter(text) {
+        push_unique(&mut urls, trim_trailing(found.as_str()).to_string());
+    }
+    urls
+}
+
+fn find_isbns(text: &str) -> Vec<String> {
+    // Labelled ISBNs may be ISBN-10s; unlabelled ones must be ISBN-13s
+    static LABELLED: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r"(?i)\bISBN(?:-1[03])?:?\s*([0-9][0-9 -]{8,16}[0-9X])\b").expect("ISBN pattern")
+    });
+    static BARE: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"\b97[89](?:[ -]?[0-9]){10}\b").expect("ISBN-13 pattern"));
+    let mut isbns = Vec::new();
+    let candidates = LABELLED
+        .captures_iter(text)
+        .map(|cap| cap.get(1).expect("ISBN digits").as_str())
+        .chain(BARE.find_iter(text).map(|found| found.as_str()));
+    for candidate in candidates {
+        let digits: String = candidate
+            .chars()
+            .filter(|c| c.is_ascii_digit() || *c == 'X' || *c == 'x')
+            .map(|c| c.to_ascii_uppercase())
+            .collect();
+        if is_valid_isbn(&digits) {
+            push_unique(&mut isbns, digits);
+        }
+    }
+    isbns
+}
+
+fn is_valid_isbn(digits: &str) -> bool {
+    let values: Vec<u32> = digits
+        .chars()
+        .map(|c| c.to_digit(10).unwrap_or(10))
+        .collect();
+    match values.len() {
+        10 => {
+            let sum: u32 = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| (10 - i as u32) * value)
+                .sum();
+            values[..9].iter().all(|&value| value < 10) && sum.is_multiple_of(11)
+        }
+        13 => {
+            let sum: u32 = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| if i % 2 == 0 { *value } else { value * 3 })
+                .sum();
+            values.iter().all(|&value| value < 10) && sum.is_multiple_of(10)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn entries_keep_text_and_identifiers() {
+        let opf = package(
+            r#"<item id="refs" href="refs.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="refs"/>"#,
+        );
+        let refs = r#"<html><body><section epub:type="bibliography"><h1>References</h1><ul>
+<li id="knuth">Knuth, D. <cite>The Art of Computer Programming</cite>. ISBN 0-201-89683-4.</li>
+<li>Shannon, C. (1948). A mathematical theory. doi:10.1002/j.1538-7305.1948.tb01338.x.</li>
+<li>Example. <a href="https://example.com/paper">Online</a>, see also https://example.org/a. ISBN 978-3-16-148410-0 (not 978-3-16-148410-1).</li>
+</ul></section></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("refs.xhtml", refs),
+        ]))
+        .unwrap();
+
+        let entries = epub.get_bibliography();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[0].get_text(),
+            "Knuth, D. The Art of Computer Programming. ISBN 0-201-89683-4."
+        );
+        assert_eq!(entries[0].get_id(), Some("knuth"));
+        assert_eq!(entries[0].get_isbns(), ["0201896834"]);
+        assert_eq!(
+            entries[1].get_dois(),
+            ["10.1002/j.1538-7305.1948.tb01338.x"]
+        );
+        assert_eq!(
+            entries[2].get_urls(),
+            ["https://example.com/paper", "https://example.org/a"]
+        );
+        assert_eq!(entries[2].get_isbns(), ["9783161484100"]);
+    }
+}
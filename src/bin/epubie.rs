@@ -0,0 +1,223 @@
+//! `epubie` command-line tool
+//!
+//! A thin shell around the library, built with `--features cli`:
+//!
+//! ```text
+//! epubie info book.epub
+//! epubie toc book.epub
+//! epubie extract book.epub text/chapter1.xhtml -o chapter1.xhtml
+//! epubie text book.epub --chapter 3
+//! epubie cover book.epub -o cover.jpg
+//! epubie validate book.epub
+//! epubie search book.epub "white whale"
+//! ```
+
+use clap::{Parser, Subcommand};
+use epubie_lib::Epub;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "epubie", version, about = "Inspect and extract EPUB files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show book metadata and a content summary
+    Info {
+        /// Path to the EPUB file
+        path: String,
+    },
+    /// Print the table of contents
+    Toc {
+        /// Path to the EPUB file
+        path: String,
+    },
+    /// Extract a single resource by its href
+    Extract {
+        /// Path to the EPUB file
+        path: String,
+        /// Href of the resource, relative to the OPF file
+        href: String,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Print the plain text of the book or a single chapter
+    Text {
+        /// Path to the EPUB file
+        path: String,
+        /// 1-based chapter number to print
+        #[arg(short, long)]
+        chapter: Option<usize>,
+    },
+    /// Save the cover image
+    Cover {
+        /// Path to the EPUB file
+        path: String,
+        /// Where to write the cover image
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Check the book for structural problems
+    Validate {
+        /// Path to the EPUB file
+        path: String,
+    },
+    /// Search the text of the book
+    Search {
+        /// Path to the EPUB file
+        path: String,
+        /// Text to search for (case-insensitive)
+        query: String,
+    },
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli.command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("epubie: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> Result<ExitCode, Box<dyn Error>> {
+    match command {
+        Command::Info { path } => {
+            let epub = Epub::new(path)?;
+            print_info(&epub);
+        }
+        Command::Toc { path } => {
+            let epub = Epub::new(path)?;
+            for entry in epub.get_table_of_contents().get_entries() {
+                let indent = "  ".repeat(entry.get_level());
+                println!("{}{} -> {}", indent, entry.get_title(), entry.get_href());
+            }
+        }
+        Command::Extract { path, href, output } => {
+            let epub = Epub::new(path)?;
+            let bytes = epub
+                .get_resource_bytes(&href)
+                .ok_or_else(|| format!("no resource found at '{}'", href))?;
+            write_output(output.as_ref(), &bytes)?;
+        }
+        Command::Text { path, chapter } => {
+            let epub = Epub::new(path)?;
+            let chapters = epub.get_chapters();
+            let selected = match chapter {
+                Some(n) => {
+                    let chapter =
+                        n.checked_sub(1)
+                            .and_then(|i| chapters.get(i))
+                            .ok_or_else(|| {
+                                format!("chapter {} out of range (1-{})", n, chapters.len())
+                            })?;
+                    std::slice::from_ref(chapter)
+                }
+                None => chapters,
+            };
+
+            for chapter in selected {
+                println!("# {}\n", chapter.get_title());
+                for file in chapter.get_files() {
+                    println!("{}\n", file.get_text());
+                }
+            }
+        }
+        Command::Cover { path, output } => {
+            let epub = Epub::new(path)?;
+            let bytes = epub.get_cover_bytes().ok_or("book has no cover image")?;
+            fs::write(&output, bytes)?;
+            println!("Wrote cover to {}", output.display());
+        }
+        Command::Validate { path } => {
+            let epub = Epub::new(path)?;
+            let issues = epub.validate();
+
+            for issue in &issues {
+                println!("{}", issue);
+            }
+
+            if issues.iter().any(|issue| issue.is_error()) {
+                return Ok(ExitCode::FAILURE);
+            }
+            if issues.is_empty() {
+                println!("No issues found");
+            }
+        }
+        Command::Search { path, query } => {
+            let epub = Epub::new(path)?;
+            let matches = epub.search(&query);
+
+            for m in &matches {
+                let chapter = &epub.get_chapters()[m.get_chapter_index()];
+                println!(
+                    "[{}] {}: ...{}...",
+                    chapter.get_title(),
+                    m.get_href(),
+                    m.get_snippet()
+                );
+            }
+            println!("{} match(es)", matches.len());
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_info(epub: &Epub) {
+    println!("Title:      {}", epub.get_title().unwrap_or("Unknown"));
+    println!("Creators:   {}", epub.get_creators().join(", "));
+    println!("Language:   {}", epub.get_language().unwrap_or("Unknown"));
+    println!("Identifier: {}", epub.get_identifier());
+    println!("Date:       {}", epub.get_date().unwrap_or("Unknown"));
+
+    if let Some(publisher) = epub.get_publisher() {
+        println!("Publisher:  {}", publisher);
+    }
+    if let Some(rights) = epub.get_rights() {
+        println!("Rights:     {}", rights);
+    }
+    if !epub.get_tags().is_empty() {
+        println!("Tags:       {}", epub.get_tags().join(", "));
+    }
+    if let Some(cover) = epub.get_cover() {
+        println!("Cover:      {}", cover);
+    }
+    if let Some(description) = epub.get_description() {
+        println!("Description:\n  {}", description);
+    }
+
+    println!();
+    println!("Chapters:   {}", epub.get_chapter_count());
+    println!("Documents:  {}", epub.get_file_count());
+    println!("Resources:  {}", epub.get_resources().len());
+
+    let report = epub.get_parse_report();
+    if !report.is_clean() {
+        println!("\nParse report:");
+        for skipped in report.get_skipped_files() {
+            println!("  skipped {}: {}", skipped.get_href(), skipped.get_reason());
+        }
+        for fallback in report.get_fallbacks() {
+            println!("  fallback: {}", fallback);
+        }
+    }
+}
+
+fn write_output(output: Option<&PathBuf>, bytes: &[u8]) -> io::Result<()> {
+    match output {
+        Some(path) => fs::write(path, bytes),
+        None => io::stdout().write_all(bytes),
+    }
+}
@@ -0,0 +1,5 @@
+//! Generates Swift/Kotlin bindings for the `uniffi` feature
+
+fn main() {
+    uniffi::uniffi_bindgen_main()
+}
@@ -0,0 +1,274 @@
+//! Finding wasted space in a book
+//!
+//! `Epub::size_report` says where the bytes are; `Epub::bloat_report` says
+//! which of them shouldn't be there, and what to do about each: images big
+//! enough to be unscaled camera or scanner output, the same image stored
+//! under several names, TrueType/OpenType fonts stored without compression,
+//! and images pasted into XHTML or CSS as base64 `data:` URIs, which cost a
+//! third more than the image and are decoded again on every page load.
+
+use crate::embedded::DATA_URI;
+use crate::epub::{Epub, is_content_media_type};
+use crate::sizes::ResourceSize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Images above this many bytes are reported as large
+const LARGE_IMAGE: u64 = 1_000_000;
+/// Other resources above this many bytes are reported as large
+const LARGE_RESOURCE: u64 = 5_000_000;
+/// Fonts that compress to more than this fraction of their size count as stored uncompressed
+const STORED_RATIO: f64 = 0.95;
+
+/// What kind of waste a `BloatFinding` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BloatKind {
+    /// A single resource far bigger than it needs to be
+    LargeResource,
+    /// Byte-identical images stored more than once
+    DuplicateImages,
+    /// A TrueType/OpenType font stored without compression
+    UncompressedFont,
+    /// Base64 `data:` images inside an XHTML or CSS file
+    EmbeddedImages,
+}
+
+/// One problem found by `Epub::bloat_report`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloatFinding {
+    kind: BloatKind,
+    hrefs: Vec<String>,
+    bytes: u64,
+    suggestion: String,
+}
+
+impl BloatFinding {
+    pub fn get_kind(&self) -> BloatKind {
+        self.kind
+    }
+
+    /// The resources involved; for duplicates, the first is the one to keep
+    pub fn get_hrefs(&self) -> &[String] {
+        &self.hrefs
+    }
+
+    /// Bytes involved: the resource's size, the size of the redundant
+    /// copies, or the size of the embedded data
+    pub fn get_bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// What to do about it
+    pub fn get_suggestion(&self) -> &str {
+        &self.suggestion
+    }
+}
+
+/// Everything `Epub::bloat_report` found, largest first
+#[derive(Debug, Clone, Default)]
+pub struct BloatReport {
+    findings: Vec<BloatFinding>,
+}
+
+impl BloatReport {
+    pub fn get_findings(&self) -> &[BloatFinding] {
+        &self.findings
+    }
+
+    /// True when nothing was found
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Total bytes across all findings
+    pub fn get_total_bytes(&self) -> u64 {
+        self.findings.iter().map(|finding| finding.bytes).sum()
+    }
+}
+
+impl fmt::Display for BloatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "{:?} ({} bytes): {} - {}",
+                finding.kind,
+                finding.bytes,
+                finding.hrefs.join(", "),
+                finding.suggestion
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Epub {
+    /// Large, duplicated, uncompressed and embedded resources, with suggested fixes
+    pub fn bloat_report(&self) -> BloatReport {
+        enter_span!("bloat_report");
+        let sizes = self.resource_sizes();
+        let mut findings = Vec::new();
+
+        for size in &sizes {
+            let image = size.get_media_type().starts_with("image/");
+            let limit = if image { LARGE_IMAGE } else { LARGE_RESOURCE };
+            if size.get_uncompressed_size() > limit {
+                findings.push(BloatFinding {
+                    kind: BloatKind::LargeResource,
+                    hrefs: vec![size.get_href().to_string()],
+                    bytes: size.get_uncompressed_size(),
+                    suggestion: if image {
+                        "downscale and recompress it, e.g. with optimize_images".to_string()
+                    } else {
+                        "check whether the book needs it at full size".to_string()
+                    },
+                });
+            }
+            if is_sfnt(size) && is_stored(size) {
+                findings.push(BloatFinding {
+                    kind: BloatKind::UncompressedFont,
+                    hrefs: vec![size.get_href().to_string()],
+                    bytes: size.get_uncompressed_size(),
+                    suggestion: "save with deflate compression, subset it with subset_fonts, or convert it to WOFF2".to_string(),
+                });
+            }
+        }
+
+        findings.extend(self.duplicate_images(&sizes));
+        findings.extend(self.embedded_image_findings());
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.bytes));
+        BloatReport { findings }
+    }
+
+    /// Groups of byte-identical images
+    fn duplicate_images(&self, sizes: &[ResourceSize]) -> Vec<BloatFinding> {
+        let images: Vec<&ResourceSize> = sizes
+            .iter()
+            .filter(|size| size.get_media_type().starts_with("image/"))
+            .collect();
+        // Only images sharing a size can be identical, so read just those
+        let mut same_size: HashMap<u64, usize> = HashMap::new();
+        for image in &images {
+            *same_size.entry(image.get_uncompressed_size()).or_default() += 1;
+        }
+
+        let mut groups: Vec<(Vec<u8>, Vec<String>)> = Vec::new();
+        let mut index: HashMap<Vec<u8>, usize> = HashMap::new();
+        for image in images {
+            if same_size[&image.get_uncompressed_size()] < 2 {
+                continue;
+            }
+            let Some(bytes) = self.get_resource_bytes(image.get_href()) else {
+                continue;
+            };
+            let href = image.get_href().to_string();
+            match index.get(&bytes) {
+                Some(&group) => groups[group].1.push(href),
+                None => {
+                    index.insert(bytes.clone(), groups.len());
+                    groups.push((bytes, vec![href]));
+                }
+            }
+        }
+
+        groups
+            .into_iter()
+            .filter(|(_, hrefs)| hrefs.len() > 1)
+            .map(|(bytes, hrefs)| BloatFinding {
+                kind: BloatKind::DuplicateImages,
+                bytes: bytes.len() as u64 * (hrefs.len() as u64 - 1),
+                suggestion: format!(
+                    "point every reference at {} and remove the copies",
+                    hrefs[0]
+                ),
+                hrefs,
+            })
+            .collect()
+    }
+
+    /// XHTML and CSS files with base64 images in them
+    fn embedded_image_findings(&self) -> Vec<BloatFinding> {
+        self.resources
+            .iter()
+            .filter(|resource| {
+                is_content_media_type(&resource.media_type) || resource.media_type == "text/css"
+            })
+            .filter_map(|resource| {
+                let bytes = self.get_resource_bytes(&resource.href)?;
+                let text = String::from_utf8_lossy(&bytes);
+                let embedded: usize = DATA_URI.find_iter(&text).map(|m| m.len()).sum();
+                (embedded > 0).then(|| BloatFinding {
+                    kind: BloatKind::EmbeddedImages,
+                    hrefs: vec![resource.href.clone()],
+                    bytes: embedded as u64,
+                    suggestion: "move the data: images into separate image resources, e.g. with externalize_embedded_images".to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Whether the resource is a TrueType/OpenType font (WOFF is compressed already)
+fn is_sfnt(size: &ResourceSize) -> bool {
+    let media_type = size.get_media_type().to_ascii_lowercase();
+    let href = size.get_href().to_ascii_lowercase();
+    (media_type.contains("font") || media_type.contains("opentype"))
+        && !media_type.contains("woff")
+        && !href.ends_with(".woff")
+        && !href.ends_with(".woff2")
+}
+
+fn is_stored(size: &ResourceSize) -> bool {
+    size.get_compressed_size().is_some_and(|compressed| {
+        compressed as f64 >= size.get_uncompressed_size() as f64 * STORED_RATIO
+    }) && size.get_uncompressed_size() > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn duplicates_embedded_images_and_large_images_are_found() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="a" href="a.png" media-type="image/png"/>
+    <item id="b" href="images/b.png" media-type="image/png"/>
+    <item id="c" href="c.png" media-type="image/png"/>
+    <item id="big" href="big.jpg" media-type="image/jpeg"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter =
+            r#"<html><body><img src="data:image/png;base64,iVBORw0KGgo="/></body></html>"#;
+        let big = "x".repeat(1_200_000);
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+            ("a.png", "same"),
+            ("images/b.png", "same"),
+            ("c.png", "diff"),
+            ("big.jpg", &big),
+        ]))
+        .unwrap();
+
+        let report = epub.bloat_report();
+        let kinds: Vec<BloatKind> = report
+            .get_findings()
+            .iter()
+            .map(BloatFinding::get_kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                BloatKind::LargeResource,
+                BloatKind::EmbeddedImages,
+                BloatKind::DuplicateImages
+            ]
+        );
+        let duplicates = &report.get_findings()[2];
+        assert_eq!(duplicates.get_hrefs(), ["a.png", "images/b.png"]);
+        assert_eq!(duplicates.get_bytes(), 4);
+    }
+}
@@ -0,0 +1,182 @@
+//! Quotations, epigraphs and verse
+//!
+//! `Epub::extract_blocks` pulls one kind of set-off text out of every
+//! chapter, for quote collections and citation tools. Epigraphs and verse are
+//! recognized by their `epub:type` (`epigraph`, `z3998:poem`, `z3998:verse`,
+//! ...) or, for books without structural semantics, by a class of the same
+//! name. Only the outermost block is returned when blocks nest.
+
+use crate::epub::Epub;
+use crate::text::html_to_text;
+use crate::xhtml::{Element, find_elements};
+
+/// `epub:type` values that mark verse
+const VERSE_TYPES: &[&str] = &["z3998:poem", "z3998:verse", "z3998:song", "z3998:hymn"];
+
+/// Classes EPUB 2 books commonly use for verse
+const VERSE_CLASSES: &[&str] = &["poem", "verse", "poetry"];
+
+/// The kind of block to extract
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockKind {
+    /// `<blockquote>`s that are neither epigraphs nor verse
+    Blockquote,
+    Epigraph,
+    /// Poems, songs and other verse, with line breaks kept
+    Verse,
+}
+
+impl BlockKind {
+    fn matches(&self, element: &Element) -> bool {
+        let epigraph =
+            element.has_token("epub:type", "epigraph") || element.has_token("class", "epigraph");
+        let verse = VERSE_TYPES
+            .iter()
+            .any(|t| element.has_token("epub:type", t))
+            || VERSE_CLASSES.iter().any(|c| element.has_token("class", c));
+        match self {
+            BlockKind::Blockquote => element.name == "blockquote" && !epigraph && !verse,
+            BlockKind::Epigraph => epigraph,
+            BlockKind::Verse => verse && !epigraph,
+        }
+    }
+}
+
+/// A block of set-off text and where it appears
+#[derive(Debug, Clone)]
+pub struct TextBlock {
+    kind: BlockKind,
+    chapter_index: usize,
+    chapter_title: String,
+    href: String,
+    id: Option<String>,
+    text: String,
+    attribution: Option<String>,
+}
+
+impl TextBlock {
+    pub fn get_kind(&self) -> BlockKind {
+        self.kind
+    }
+
+    /// Index of the containing chapter in `Epub::get_chapters`
+    pub fn get_chapter_index(&self) -> usize {
+        self.chapter_index
+    }
+
+    pub fn get_chapter_title(&self) -> &str {
+        &self.chapter_title
+    }
+
+    /// Href of the file containing the block
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// The block's `id`, for linking straight to it
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The quoted text, without the attribution
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Who or what is quoted, from a `<footer>`, `<cite>` or an element
+    /// with an `attribution` class inside the block
+    pub fn get_attribution(&self) -> Option<&str> {
+        self.attribution.as_deref()
+    }
+}
+
+impl Epub {
+    /// Every block of the given kind, in reading order
+    pub fn extract_blocks(&self, kind: BlockKind) -> Vec<TextBlock> {
+        let mut blocks = Vec::new();
+        for (chapter_index, chapter) in self.get_chapters().iter().enumerate() {
+            for file in chapter.get_files() {
+                for element in find_elements(file.get_content(), |e| kind.matches(e)) {
+                    let (text, attribution) = split_attribution(element.inner);
+                    if text.is_empty() {
+                        continue;
+                    }
+                    blocks.push(TextBlock {
+                        kind,
+                        chapter_index,
+                        chapter_title: chapter.get_title().to_string(),
+                        href: file.get_href().to_string(),
+                        id: element.attribute("id").map(str::to_string),
+                        text,
+                        attribution,
+                    });
+                }
+            }
+        }
+        blocks
+    }
+}
+
+/// A block's text and, if it ends with one, its attribution
+///
+/// Only a trailing `<cite>` counts, since one mid-quote is usually a title.
+fn split_attribution(html: &str) -> (String, Option<String>) {
+    let attributions = find_elements(html, |e| {
+        matches!(e.name.as_str(), "footer" | "cite") || e.has_token("class", "attribution")
+    });
+    let Some(last) = attributions
+        .last()
+        .filter(|last| last.name != "cite" || html_to_text(&html[last.range.end..]).is_empty())
+    else {
+        return (html_to_text(html), None);
+    };
+    let quote = format!("{}{}", &html[..last.range.start], &html[last.range.end..]);
+    let attribution = html_to_text(last.inner).replace('\n', " ");
+    let attribution = attribution
+        .trim_start_matches(['—', '–', '-', ' '])
+        .to_string();
+    (
+        html_to_text(&quote),
+        Some(attribution).filter(|a| !a.is_empty()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn blocks_are_extracted_by_kind() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html xmlns:epub="http://www.idpf.org/2007/ops"><body>
+<blockquote epub:type="epigraph" id="epi"><p>Call me Ishmael.</p><footer>— Herman Melville</footer></blockquote>
+<p>Text.</p>
+<blockquote><p>Outer <blockquote><p>inner</p></blockquote> quote from <cite>Dune</cite>.</p></blockquote>
+<div class="poem"><p>Roses are red,<br/>violets are blue.</p></div>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let epigraphs = epub.extract_blocks(BlockKind::Epigraph);
+        assert_eq!(epigraphs.len(), 1);
+        assert_eq!(epigraphs[0].get_text(), "Call me Ishmael.");
+        assert_eq!(epigraphs[0].get_attribution(), Some("Herman Melville"));
+        assert_eq!(epigraphs[0].get_id(), Some("epi"));
+        assert_eq!(epigraphs[0].get_href(), "ch1.xhtml");
+
+        let quotes = epub.extract_blocks(BlockKind::Blockquote);
+        assert_eq!(quotes.len(), 1);
+        assert_eq!(quotes[0].get_text(), "Outer\ninner\nquote from Dune.");
+
+        let verse = epub.extract_blocks(BlockKind::Verse);
+        assert_eq!(verse[0].get_text(), "Roses are red,\nviolets are blue.");
+    }
+}
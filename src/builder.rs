@@ -0,0 +1,650 @@
+//! Generating new EPUBs
+//!
+//! `EpubBuilder` turns a title, authors, an optional cover and a list of
+//! chapters into a complete EPUB 3 with a stylesheet, navigation document
+//! and NCX, so the result also opens on EPUB 2 reading systems.
+//!
+//! ```no_run
+//! use epubie_lib::builder::EpubBuilder;
+//!
+//! let book = EpubBuilder::new("Moby-Dick")
+//!     .with_author("Herman Melville")
+//!     .with_chapter("Loomings", "<p>Call me Ishmael.</p>")
+//!     .with_chapter("The Carpet-Bag", "<p>I stuffed a shirt or two…</p>");
+//! book.save("moby-dick.epub")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::clipper::{self, Clipped, WebPage, WebResource};
+use crate::epub::{Epub, Metadata, Resource, TableOfContents};
+use crate::error::EpubError;
+use crate::feed::{self, FeedEntry};
+use crate::href;
+use crate::nav::NavDocument;
+use crate::package::{MetaEntry, Package};
+use crate::semantics::Landmark;
+use crate::text::{escape_xml, first_heading};
+use crate::xhtml;
+use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{Cursor, Write};
+use std::path::Path;
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+/// Directory holding everything but `mimetype` and `META-INF`
+const CONTENT_DIR: &str = "OEBPS";
+
+/// Stylesheet used when none is given
+const DEFAULT_CSS: &str = "\
+body { margin: 0 5%; font-family: serif; line-height: 1.5; text-align: justify; hyphens: auto; }
+h1, h2, h3, h4 { font-family: sans-serif; line-height: 1.2; text-align: left; hyphens: none; }
+h1 { margin: 2em 0 1em; font-size: 1.6em; }
+p { margin: 0; text-indent: 1.5em; }
+h1 + p, h2 + p, h3 + p, hr + p, blockquote p { text-indent: 0; }
+blockquote { margin: 1em 2em; font-style: italic; }
+img { max-width: 100%; }
+pre, code { font-family: monospace; white-space: pre-wrap; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #999; padding: 0.2em 0.5em; }
+p.byline, p.source { text-indent: 0; font-size: 0.9em; font-style: italic; }
+";
+
+/// A chapter as it will be written
+#[derive(Debug, Clone)]
+struct Section {
+    title: String,
+    body: String,
+    /// Entries nested under the chapter in the TOC: `(level, title, fragment id)`
+    subsections: Vec<(usize, String, String)>,
+}
+
+/// A file bundled alongside the chapters
+#[derive(Debug, Clone)]
+struct Asset {
+    href: String,
+    media_type: String,
+    bytes: Vec<u8>,
+}
+
+/// Builds an EPUB 3 from chapters and assets
+#[derive(Debug, Clone)]
+pub struct EpubBuilder {
+    title: String,
+    authors: Vec<String>,
+    language: String,
+    identifier: Option<String>,
+    publisher: Option<String>,
+    description: Option<String>,
+    date: Option<String>,
+    cover: Option<(Vec<u8>, String)>,
+    stylesheet: String,
+    sections: Vec<Section>,
+    assets: Vec<Asset>,
+}
+
+impl EpubBuilder {
+    /// Start a book with the given title, in English and without chapters
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            authors: Vec::new(),
+            language: "en".to_string(),
+            identifier: None,
+            publisher: None,
+            description: None,
+            date: None,
+            cover: None,
+            stylesheet: DEFAULT_CSS.to_string(),
+            sections: Vec::new(),
+            assets: Vec::new(),
+        }
+    }
+
+    /// Start an issue of a periodical, titled and dated after `issue`
+    ///
+    /// The title becomes e.g. "Morning Digest — 2026-10-17", so successive
+    /// issues can be told apart in a library.
+    pub fn new_periodical(name: impl Into<String>, issue: NaiveDate) -> Self {
+        let date = issue.format("%Y-%m-%d").to_string();
+        let mut builder = Self::new(format!("{} — {}", name.into(), date));
+        builder.date = Some(date);
+        builder
+    }
+
+    /// Add an author; call repeatedly for several
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.authors.push(author.into());
+        self
+    }
+
+    /// Set the language as a BCP 47 tag such as `en` or `pt-BR`
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Set the unique identifier; a random `urn:uuid:` is used otherwise
+    pub fn with_identifier(mut self, identifier: impl Into<String>) -> Self {
+        self.identifier = Some(identifier.into());
+        self
+    }
+
+    pub fn with_publisher(mut self, publisher: impl Into<String>) -> Self {
+        self.publisher = Some(publisher.into());
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the publication date, e.g. `2024-05-17`
+    pub fn with_date(mut self, date: impl Into<String>) -> Self {
+        self.date = Some(date.into());
+        self
+    }
+
+    /// Use an image as the cover; a cover page is generated for it
+    pub fn with_cover(mut self, bytes: Vec<u8>, media_type: impl Into<String>) -> Self {
+        self.cover = Some((bytes, media_type.into()));
+        self
+    }
+
+    /// Replace the default stylesheet
+    pub fn with_stylesheet(mut self, css: impl Into<String>) -> Self {
+        self.stylesheet = css.into();
+        self
+    }
+
+    /// Append a chapter with an HTML body fragment
+    ///
+    /// The fragment is cleaned up into XHTML (void elements closed, HTML
+    /// entities converted). If it has no heading of its own, the title is
+    /// added as an `<h1>`. Chapters live in `text/`, so reference bundled
+    /// assets as `../images/figure.png`.
+    pub fn with_chapter(mut self, title: impl Into<String>, html: impl AsRef<str>) -> Self {
+        self.sections.push(Section {
+            title: title.into(),
+            body: xhtml::normalize_fragment(html.as_ref()),
+            subsections: Vec::new(),
+        });
+        self
+    }
+
+    /// Append a chapter written in CommonMark
+    ///
+    /// The chapter is titled after its first `#` heading. Headings get ids,
+    /// and `##`/`###` headings become nested entries in the table of
+    /// contents. Image paths are kept as written; use `with_markdown_file`
+    /// to bundle local images.
+    #[cfg(feature = "markdown")]
+    pub fn with_markdown_chapter(self, markdown: impl AsRef<str>) -> Self {
+        let rendered = crate::markdown::render(markdown.as_ref(), &Default::default());
+        self.with_rendered_markdown(rendered)
+    }
+
+    /// Append a CommonMark file as a chapter, bundling the local images it uses
+    ///
+    /// Images are resolved against the file's directory, added under
+    /// `images/`, and their references rewritten. Remote images are left as
+    /// they are.
+    #[cfg(feature = "markdown")]
+    pub fn with_markdown_file(mut self, path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let markdown = std::fs::read_to_string(path)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        let mut images = HashMap::new();
+        for src in crate::markdown::image_sources(&markdown) {
+            if href::has_scheme(&src) || src.starts_with('/') {
+                continue;
+            }
+            let file = dir.join(href::percent_decode(href::strip_fragment(&src)));
+            let bytes = std::fs::read(&file)?;
+            let name = file
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "image".to_string());
+            let href = unique_asset_href(&self.assets, "images", &name);
+            self.assets.push(Asset {
+                media_type: href::media_type_for(&name).to_string(),
+                href: href.clone(),
+                bytes,
+            });
+            images.insert(src, format!("../{}", href));
+        }
+
+        let rendered = crate::markdown::render(&markdown, &images);
+        Ok(self.with_rendered_markdown(rendered))
+    }
+
+    #[cfg(feature = "markdown")]
+    fn with_rendered_markdown(mut self, rendered: crate::markdown::Rendered) -> Self {
+        let mut headings = rendered.headings.into_iter().peekable();
+        let title = match headings.next_if(|(level, ..)| *level == 1) {
+            Some((_, text, _)) => text,
+            None => format!("Chapter {}", self.sections.len() + 1),
+        };
+        let subsections = headings
+            .filter(|(level, ..)| (2..=3).contains(level))
+            .map(|(level, text, id)| (level - 1, text, id))
+            .collect();
+
+        self.sections.push(Section {
+            title,
+            body: xhtml::normalize_fragment(&rendered.html),
+            subsections,
+        });
+        self
+    }
+
+    /// Append fetched web pages as chapters, bundling the images they use
+    ///
+    /// Each page is reduced to its main content (see the `clipper` module).
+    /// Images are matched to `resources` by absolute URL and added under
+    /// `images/`; images that weren't fetched are dropped, since EPUB
+    /// doesn't allow remote images. Links between the pages point at the
+    /// matching chapters, and other links become absolute URLs.
+    pub fn with_web_pages(mut self, pages: &[WebPage], resources: &[WebResource]) -> Self {
+        for clipped in self.clip_pages(pages, resources) {
+            self.sections.push(Section {
+                title: clipped.title,
+                body: xhtml::normalize_fragment(&clipped.html),
+                subsections: Vec::new(),
+            });
+        }
+        self
+    }
+
+    /// Append feed entries as articles, one chapter each
+    ///
+    /// Each article opens with its title, a byline with the author and
+    /// publication date, and ends with a link to the original. Content is
+    /// cleaned and its images bundled as with `with_web_pages`, resolving
+    /// relative URLs against the entry's link.
+    pub fn with_feed_entries(mut self, entries: &[FeedEntry], resources: &[WebResource]) -> Self {
+        let pages: Vec<WebPage> = entries
+            .iter()
+            .map(|entry| WebPage::new(entry.get_link().unwrap_or_default(), entry.get_content()))
+            .collect();
+        let clipped = self.clip_pages(&pages, resources);
+        for (entry, clipped) in entries.iter().zip(clipped) {
+            self.sections.push(Section {
+                title: entry.get_title().to_string(),
+                body: xhtml::normalize_fragment(&feed::article_body(entry, &clipped.html)),
+                subsections: Vec::new(),
+            });
+        }
+        self
+    }
+
+    /// Clean up pages that are about to become the next chapters
+    ///
+    /// Images found in `resources` are added as assets, and links between
+    /// the pages are pointed at their chapters.
+    fn clip_pages(&mut self, pages: &[WebPage], resources: &[WebResource]) -> Vec<Clipped> {
+        let chapters: HashMap<&str, String> = pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| !page.get_url().is_empty())
+            .map(|(index, page)| {
+                let id = chapter_id(self.sections.len() + index + 1);
+                (clipper::url_key(page.get_url()), format!("{}.xhtml", id))
+            })
+            .collect();
+        let mut bundled: HashMap<&str, String> = HashMap::new();
+
+        pages
+            .iter()
+            .map(|page| {
+                clipper::clip(
+                    page,
+                    |url| match chapters.get(clipper::url_key(url)) {
+                        Some(chapter) => match href::fragment(url) {
+                            Some(fragment) => format!("{}#{}", chapter, fragment),
+                            None => chapter.clone(),
+                        },
+                        None => url.to_string(),
+                    },
+                    |url| {
+                        let resource = resources.iter().find(|resource| {
+                            clipper::url_key(resource.get_url()) == clipper::url_key(url)
+                        })?;
+                        let href = bundled.entry(resource.get_url()).or_insert_with(|| {
+                            let href =
+                                unique_asset_href(&self.assets, "images", &resource.file_name());
+                            self.assets.push(Asset {
+                                href: href.clone(),
+                                media_type: resource.get_media_type().to_string(),
+                                bytes: resource.get_bytes().to_vec(),
+                            });
+                            href
+                        });
+                        Some(format!("../{}", href))
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Bundle a file such as an image or font, at `href` relative to the package
+    pub fn with_asset(
+        mut self,
+        href: impl Into<String>,
+        media_type: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Self {
+        self.assets.push(Asset {
+            href: href.into(),
+            media_type: media_type.into(),
+            bytes,
+        });
+        self
+    }
+
+    /// Write the book to an EPUB file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Parse the generated book, ready for further inspection or editing
+    pub fn build(&self) -> Result<Epub, Box<dyn Error>> {
+        Epub::from_bytes(self.to_bytes()?)
+    }
+
+    /// Serialize the book as EPUB bytes
+    ///
+    /// # Errors
+    /// `EpubError::InvalidMetadata` for an empty title or a book without
+    /// chapters, and `EpubError::ResourceExists` when two assets share an href.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.title.trim().is_empty() {
+            return Err(EpubError::InvalidMetadata("title must not be empty".to_string()).into());
+        }
+        if self.sections.is_empty() {
+            return Err(EpubError::InvalidMetadata(
+                "a book needs at least one chapter".to_string(),
+            )
+            .into());
+        }
+
+        let identifier = self
+            .identifier
+            .clone()
+            .unwrap_or_else(|| format!("urn:uuid:{}", uuid::Uuid::new_v4()));
+        let mut metadata = Metadata::new(
+            Some(self.title.clone()),
+            self.authors.clone(),
+            Some(self.language.clone()),
+            identifier.clone(),
+            self.date.clone(),
+        );
+        metadata.publisher = self.publisher.clone();
+        metadata.description = self.description.clone();
+
+        let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut manifest = Vec::new();
+        let mut spine = Vec::new();
+        let mut toc = TableOfContents::new();
+        let mut landmarks = Vec::new();
+        let stylesheet = xhtml::stylesheet_link("../style.css");
+
+        manifest.push(Resource::new("style", "style.css", "text/css", None));
+        files.push((
+            "style.css".to_string(),
+            self.stylesheet.clone().into_bytes(),
+        ));
+
+        if let Some((bytes, media_type)) = &self.cover {
+            let image_href = format!("images/cover.{}", href::extension_for(media_type));
+            manifest.push(Resource::new(
+                "cover-image",
+                &image_href,
+                media_type,
+                Some("cover-image".to_string()),
+            ));
+            files.push((image_href.clone(), bytes.clone()));
+            metadata.cover = Some("cover-image".to_string());
+
+            let page = xhtml::cover_page(&self.language, &format!("../{}", image_href));
+            manifest.push(Resource::new(
+                "cover",
+                "text/cover.xhtml",
+                "application/xhtml+xml",
+                None,
+            ));
+            files.push(("text/cover.xhtml".to_string(), page.into_bytes()));
+            spine.push("cover".to_string());
+            landmarks.push(Landmark::new("cover", "text/cover.xhtml"));
+        }
+
+        for (index, section) in self.sections.iter().enumerate() {
+            let id = chapter_id(index + 1);
+            let href = format!("text/{}.xhtml", id);
+            let body = if first_heading(&section.body).is_some() {
+                section.body.clone()
+            } else {
+                format!("<h1>{}</h1>\n{}", escape_xml(&section.title), section.body)
+            };
+            let body = format!(
+                "<section epub:type=\"chapter\" id=\"{}\">\n{}\n</section>",
+                id, body
+            );
+            let page = xhtml::document(
+                &section.title,
+                &self.language,
+                &stylesheet,
+                Some("bodymatter"),
+                &body,
+            );
+
+            manifest.push(Resource::new(&id, &href, "application/xhtml+xml", None));
+            files.push((href.clone(), page.into_bytes()));
+            spine.push(id);
+            toc.add_entry(section.title.clone(), href.clone(), 0);
+            for (level, title, fragment) in &section.subsections {
+                toc.add_entry(title.clone(), format!("{}#{}", href, fragment), *level);
+            }
+            if index == 0 {
+                landmarks.push(Landmark::new("bodymatter", &href));
+            }
+        }
+        landmarks.push(Landmark::new("toc", "nav.xhtml#toc"));
+
+        let mut hrefs: HashSet<String> = files.iter().map(|(href, _)| href.clone()).collect();
+        hrefs.extend([
+            "nav.xhtml".to_string(),
+            "toc.ncx".to_string(),
+            "content.opf".to_string(),
+        ]);
+        for asset in &self.assets {
+            if !hrefs.insert(asset.href.clone()) {
+                return Err(EpubError::ResourceExists(asset.href.clone()).into());
+            }
+            let id = format!("asset-{}", manifest.len() + 1);
+            manifest.push(Resource::new(&id, &asset.href, &asset.media_type, None));
+            files.push((asset.href.clone(), asset.bytes.clone()));
+        }
+
+        let nav = NavDocument::new(&self.title, toc)
+            .with_language(&self.language)
+            .with_landmarks(landmarks);
+        manifest.push(Resource::new(
+            "nav",
+            "nav.xhtml",
+            "application/xhtml+xml",
+            Some("nav".to_string()),
+        ));
+        manifest.push(Resource::new(
+            "ncx",
+            "toc.ncx",
+            "application/x-dtbncx+xml",
+            None,
+        ));
+        files.push(("nav.xhtml".to_string(), nav.to_xhtml().into_bytes()));
+        files.push(("toc.ncx".to_string(), nav.to_ncx(&identifier).into_bytes()));
+
+        let mut package = Package::new(metadata);
+        for (index, _) in self.authors.iter().enumerate() {
+            package.add_meta(
+                MetaEntry::property("role", "aut")
+                    .with_refines(format!("creator{}", index + 1))
+                    .with_scheme("marc:relators"),
+            );
+        }
+        for resource in manifest {
+            package.add_resource(resource);
+        }
+        for idref in spine {
+            package.add_spine_item(idref);
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default();
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+        zip.start_file("META-INF/container.xml", deflated)?;
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="{}/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+            CONTENT_DIR
+        )?;
+        zip.start_file(format!("{}/content.opf", CONTENT_DIR), deflated)?;
+        zip.write_all(package.to_xml().as_bytes())?;
+        for (href, bytes) in files {
+            zip.start_file(format!("{}/{}", CONTENT_DIR, href), deflated)?;
+            zip.write_all(&bytes)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+}
+
+/// Id of the `number`th chapter, which is also its file name in `text/`
+fn chapter_id(number: usize) -> String {
+    format!("chapter-{:03}", number)
+}
+
+/// `dir/name`, numbered if an asset already uses it
+fn unique_asset_href(assets: &[Asset], dir: &str, name: &str) -> String {
+    let taken = |href: &str| assets.iter().any(|asset| asset.href == href);
+    let first = format!("{}/{}", dir, name);
+    if !taken(&first) {
+        return first;
+    }
+    let (stem, extension) = name.rsplit_once('.').unwrap_or((name, ""));
+    (2..)
+        .map(|n| format!("{}/{}-{}.{}", dir, stem, n, extension))
+        .find(|href| !taken(href))
+        .unwrap_or(first)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::semantics::SemanticRole;
+
+    #[test]
+    fn builds_a_valid_book() {
+        let epub = EpubBuilder::new("Moby-Dick")
+            .with_author("Herman Melville")
+            .with_identifier("urn:isbn:9780142437247")
+            .with_cover(b"png".to_vec(), "image/png")
+            .with_chapter(
+                "Loomings",
+                "<p>Call me Ishmael&mdash;<br>some years ago</p>",
+            )
+            .with_chapter(
+                "The Carpet-Bag",
+                "<h1>Chapter 2</h1><p>I stuffed a shirt</p>",
+            )
+            .with_asset("images/map.png", "image/png", b"map".to_vec())
+            .build()
+            .unwrap();
+
+        assert_eq!(epub.get_title(), Some("Moby-Dick"));
+        assert_eq!(epub.get_creator(), Some("Herman Melville"));
+        assert_eq!(epub.get_cover_bytes().as_deref(), Some(&b"png"[..]));
+        assert_eq!(epub.get_spine().len(), 3);
+        let toc = epub.get_table_of_contents().get_entries();
+        assert_eq!(toc[1].get_title(), "Loomings");
+        assert_eq!(toc[2].get_href(), "text/chapter-002.xhtml");
+        assert_eq!(
+            epub.get_spine_files()[0].semantic_role(),
+            SemanticRole::Cover
+        );
+        assert_eq!(
+            epub.start_of_content().as_deref(),
+            Some("text/chapter-001.xhtml")
+        );
+
+        let first = &epub.get_spine_files()[1];
+        assert!(first.get_content().contains("<h1>Loomings</h1>"));
+        assert!(first.get_content().contains("&#8212;<br/>"));
+        let second = &epub.get_spine_files()[2];
+        assert!(!second.get_content().contains("<h1>The Carpet-Bag</h1>"));
+
+        let errors: Vec<_> = epub
+            .validate()
+            .into_iter()
+            .filter(|issue| issue.is_error())
+            .collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        assert!(EpubBuilder::new("Empty").to_bytes().is_err());
+        assert!(
+            EpubBuilder::new("Twice")
+                .with_chapter("One", "<p/>")
+                .with_asset("style.css", "text/css", Vec::new())
+                .to_bytes()
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "markdown")]
+    #[test]
+    fn markdown_files_bundle_images_and_nest_headings() {
+        let dir = std::env::temp_dir().join(format!("epubie-markdown-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("img")).unwrap();
+        std::fs::write(dir.join("img/map.png"), b"png").unwrap();
+        std::fs::write(
+            dir.join("ch1.md"),
+            "# Loomings\n\nCall me Ishmael.\n\n## The Map\n\n![map](img/map.png)\n",
+        )
+        .unwrap();
+
+        let epub = EpubBuilder::new("Moby-Dick")
+            .with_markdown_file(dir.join("ch1.md"))
+            .unwrap()
+            .with_markdown_chapter("Untitled prose")
+            .build()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let chapter = &epub.get_spine_files()[0];
+        assert!(
+            chapter
+                .get_content()
+                .contains(r#"<img src="../images/map.png" alt="map"/>"#)
+        );
+        assert_eq!(
+            epub.get_resource_bytes("images/map.png").as_deref(),
+            Some(&b"png"[..])
+        );
+
+        let nav = String::from_utf8(epub.get_resource_bytes("nav.xhtml").unwrap()).unwrap();
+        assert!(nav.contains(r#"<a href="text/chapter-001.xhtml#the-map">The Map</a>"#));
+        assert!(nav.contains(">Chapter 2</a>"));
+    }
+}
@@ -0,0 +1,279 @@
+//! C API for embedding the parser in non-Rust applications
+//!
+//! Enabled with the `capi` feature. The header `include/epubie.h` is generated
+//! from this module with `cbindgen --config cbindgen.toml --output include/epubie.h`.
+//!
+//! Conventions:
+//! - `epub_open` returns an opaque handle that must be released with `epub_free`.
+//! - Functions returning `char *` hand ownership to the caller; release the
+//!   string with `epub_string_free`. `NULL` means the value is absent.
+//! - Byte buffers are released with `epub_bytes_free`, passing back the length.
+//! - On failure, `epub_last_error` describes what went wrong on this thread.
+
+use crate::epub::Epub;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<String>) {
+    let message = CString::new(message.into().replace('\0', " ")).ok();
+    LAST_ERROR.with(|cell| *cell.borrow_mut() = message);
+}
+
+fn to_c_string(value: &str) -> *mut c_char {
+    match CString::new(value.replace('\0', " ")) {
+        Ok(s) => s.into_raw(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+fn optional_c_string(value: Option<&str>) -> *mut c_char {
+    value.map_or(ptr::null_mut(), to_c_string)
+}
+
+/// Borrow the `Epub` behind a handle, returning `None` for a null pointer
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+unsafe fn handle<'a>(epub: *const Epub) -> Option<&'a Epub> {
+    // SAFETY: guaranteed by the caller
+    unsafe { epub.as_ref() }
+}
+
+/// Open and parse the EPUB file at `path`
+///
+/// Returns `NULL` on failure; see `epub_last_error`.
+///
+/// # Safety
+/// `path` must be null or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_open(path: *const c_char) -> *mut Epub {
+    if path.is_null() {
+        set_last_error("path is null");
+        return ptr::null_mut();
+    }
+
+    // SAFETY: checked for null above, validity guaranteed by the caller
+    let path = match unsafe { CStr::from_ptr(path) }.to_str() {
+        Ok(path) => path.to_string(),
+        Err(_) => {
+            set_last_error("path is not valid UTF-8");
+            return ptr::null_mut();
+        }
+    };
+
+    match Epub::new(path) {
+        Ok(epub) => Box::into_raw(Box::new(epub)),
+        Err(e) => {
+            set_last_error(e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Release a handle returned by `epub_open`
+///
+/// # Safety
+/// `epub` must be null or a handle from `epub_open` that has not been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_free(epub: *mut Epub) {
+    if !epub.is_null() {
+        // SAFETY: the pointer came from Box::into_raw in epub_open
+        drop(unsafe { Box::from_raw(epub) });
+    }
+}
+
+/// Describe the most recent error on the calling thread, or `NULL`
+///
+/// The returned string is owned by the library and valid until the next
+/// failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn epub_last_error() -> *const c_char {
+    LAST_ERROR.with(|cell| cell.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+/// Release a string returned by this library
+///
+/// # Safety
+/// `s` must be null or a string returned by one of the `epub_*` functions.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: the pointer came from CString::into_raw
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Release a byte buffer returned by this library
+///
+/// # Safety
+/// `data` and `len` must come from the same `epub_*` call, and the buffer must
+/// not have been freed already.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_bytes_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        // SAFETY: the buffer was created from a boxed slice of exactly `len` bytes
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)) });
+    }
+}
+
+/// Book title, or `NULL` if the book has none
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_title(epub: *const Epub) -> *mut c_char {
+    unsafe { handle(epub) }.map_or(ptr::null_mut(), |epub| optional_c_string(epub.get_title()))
+}
+
+/// First creator (author), or `NULL`
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_creator(epub: *const Epub) -> *mut c_char {
+    unsafe { handle(epub) }.map_or(ptr::null_mut(), |epub| {
+        optional_c_string(epub.get_creator())
+    })
+}
+
+/// Book language, or `NULL`
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_language(epub: *const Epub) -> *mut c_char {
+    unsafe { handle(epub) }.map_or(ptr::null_mut(), |epub| {
+        optional_c_string(epub.get_language())
+    })
+}
+
+/// Unique identifier of the book
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_identifier(epub: *const Epub) -> *mut c_char {
+    unsafe { handle(epub) }.map_or(ptr::null_mut(), |epub| to_c_string(epub.get_identifier()))
+}
+
+/// Number of chapters, or 0 for a null handle
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_chapter_count(epub: *const Epub) -> usize {
+    unsafe { handle(epub) }.map_or(0, |epub| epub.get_chapter_count())
+}
+
+/// Title of the chapter at `index`, or `NULL` if out of range
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_chapter_title(epub: *const Epub, index: usize) -> *mut c_char {
+    unsafe { handle(epub) }
+        .and_then(|epub| epub.get_chapters().get(index))
+        .map_or(ptr::null_mut(), |chapter| to_c_string(chapter.get_title()))
+}
+
+/// Number of content documents, or 0 for a null handle
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_file_count(epub: *const Epub) -> usize {
+    unsafe { handle(epub) }.map_or(0, |epub| epub.get_file_count())
+}
+
+/// Href of the content document at `index`, or `NULL` if out of range
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_file_href(epub: *const Epub, index: usize) -> *mut c_char {
+    unsafe { handle(epub) }
+        .and_then(|epub| epub.get_all_files().get(index))
+        .map_or(ptr::null_mut(), |file| to_c_string(file.get_href()))
+}
+
+/// Raw XHTML of the content document at `index`, or `NULL` if out of range
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_file_content(epub: *const Epub, index: usize) -> *mut c_char {
+    unsafe { handle(epub) }
+        .and_then(|epub| epub.get_all_files().get(index))
+        .map_or(ptr::null_mut(), |file| to_c_string(file.get_content()))
+}
+
+/// Plain text of the content document at `index`, or `NULL` if out of range
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_file_text(epub: *const Epub, index: usize) -> *mut c_char {
+    unsafe { handle(epub) }
+        .and_then(|epub| epub.get_all_files().get(index))
+        .map_or(ptr::null_mut(), |file| to_c_string(&file.get_text()))
+}
+
+/// Cover image bytes, or `NULL` if the book has no cover
+///
+/// The length is written to `out_len`. Release with `epub_bytes_free`.
+/// Without somewhere to put the length the buffer couldn't be freed, so a
+/// null `out_len` gets `NULL`.
+///
+/// # Safety
+/// `epub` must be null or a live handle returned by `epub_open`, and
+/// `out_len` must be null or point to writable memory.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn epub_cover_bytes(epub: *const Epub, out_len: *mut usize) -> *mut u8 {
+    if out_len.is_null() {
+        return ptr::null_mut();
+    }
+    let bytes = unsafe { handle(epub) }.and_then(|epub| epub.get_cover_bytes());
+    let Some(bytes) = bytes else {
+        // SAFETY: checked for null, writability guaranteed by the caller
+        unsafe { *out_len = 0 };
+        return ptr::null_mut();
+    };
+
+    // SAFETY: checked for null, writability guaranteed by the caller
+    unsafe { *out_len = bytes.len() };
+    Box::into_raw(bytes.into_boxed_slice()) as *mut u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn cover_bytes_need_somewhere_to_put_the_length() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="cover" href="cover.png" media-type="image/png" properties="cover-image"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("cover.png", "png"),
+        ]))
+        .unwrap();
+
+        let mut len = 0;
+        let bytes = unsafe { epub_cover_bytes(&epub, &mut len) };
+        assert!(!bytes.is_null());
+        assert_eq!(len, 3);
+        unsafe { epub_bytes_free(bytes, len) };
+        assert!(unsafe { epub_cover_bytes(&epub, ptr::null_mut()) }.is_null());
+    }
+}
@@ -0,0 +1,328 @@
+//! BISAC and THEMA subject codes as category paths
+//!
+//! Stores build genre filters from subject codes, not from the free text of
+//! `dc:subject`. Publishers record the codes in three ways: as the subject
+//! text itself (`FIC028000`), with an EPUB 2 `opf:authority` attribute, or
+//! with EPUB 3 `authority` and `term` refinements. `Epub::categories` reads
+//! all three and decodes each code into its path of labels, e.g. `FMB` into
+//! Fiction & related items > Fantasy > Epic fantasy.
+//!
+//! THEMA codes are short enough to collide with ordinary words, so they are
+//! only recognized with a THEMA authority or a `THEMA:` prefix. Interest age
+//! qualifiers (`5AJ`, "from c 8 years") are decoded too, for age filters.
+//!
+//! The tables cover every BISAC section and THEMA top-level subject, the
+//! common fiction codes below them, and the interest ages; unknown codes
+//! decode to their nearest known ancestor. They are behind the
+//! `subject-codes` feature to keep them out of builds that don't need them.
+
+use crate::epub::Epub;
+use crate::subjects::bisac_heading;
+
+/// BISAC codes beyond the ones `subjects` knows, named without the section
+pub(crate) const BISAC_EXTENDED: &[(&str, &str)] = &[
+    ("FIC003000", "Anthologies (multiple authors)"),
+    ("FIC005000", "Erotica / General"),
+    ("FIC006000", "Thrillers / Espionage"),
+    ("FIC009020", "Fantasy / Epic"),
+    ("FIC009030", "Fantasy / Historical"),
+    ("FIC009040", "Fantasy / Contemporary"),
+    ("FIC009050", "Fantasy / Paranormal"),
+    ("FIC010000", "Fairy Tales, Folk Tales, Legends & Mythology"),
+    ("FIC012000", "Ghost"),
+    ("FIC016000", "Humorous / General"),
+    ("FIC022010", "Mystery & Detective / Hard-Boiled"),
+    ("FIC022020", "Mystery & Detective / Police Procedural"),
+    ("FIC022040", "Mystery & Detective / Women Sleuths"),
+    ("FIC024000", "Occult & Supernatural"),
+    ("FIC025000", "Psychological"),
+    ("FIC027020", "Romance / Contemporary"),
+    ("FIC027050", "Romance / Historical / General"),
+    ("FIC028010", "Science Fiction / Action & Adventure"),
+    ("FIC028020", "Science Fiction / Hard Science Fiction"),
+    ("FIC028030", "Science Fiction / Space Opera"),
+    ("FIC029000", "Short Stories (single author)"),
+    ("FIC030000", "Thrillers / Suspense"),
+    ("FIC031010", "Thrillers / Crime"),
+    ("FIC032000", "War & Military"),
+    ("FIC033000", "Westerns / General"),
+    ("FIC037000", "Political"),
+    ("FIC040000", "Alternative History"),
+    ("FIC045000", "Family Life / General"),
+];
+
+/// THEMA subjects and qualifiers; a code's ancestors are its prefixes
+const THEMA: &[(&str, &str)] = &[
+    ("A", "The Arts"),
+    ("C", "Language & Linguistics"),
+    ("D", "Biography, Literature & Literary studies"),
+    ("F", "Fiction & related items"),
+    ("FB", "Fiction: general & literary"),
+    ("FBA", "Modern & contemporary fiction"),
+    ("FBC", "Classic fiction"),
+    ("FF", "Crime & mystery fiction"),
+    ("FH", "Thriller / suspense fiction"),
+    ("FJ", "Adventure fiction"),
+    ("FK", "Horror & supernatural fiction"),
+    ("FL", "Science fiction"),
+    ("FM", "Fantasy"),
+    ("FMB", "Epic fantasy"),
+    ("FR", "Romance"),
+    ("FU", "Humorous fiction"),
+    ("FV", "Historical fiction"),
+    ("G", "Reference, Information & Interdisciplinary subjects"),
+    ("J", "Society & Social Sciences"),
+    ("K", "Economics, Finance, Business & Management"),
+    ("L", "Law"),
+    ("M", "Medicine & Nursing"),
+    ("N", "History & Archaeology"),
+    ("P", "Mathematics & Science"),
+    ("Q", "Philosophy & Religion"),
+    ("R", "Earth Sciences, Geography, Environment, Planning"),
+    ("S", "Sports & Active outdoor recreation"),
+    (
+        "T",
+        "Technology, Engineering, Agriculture, Industrial processes",
+    ),
+    ("U", "Computing & Information Technology"),
+    ("V", "Health, Relationships & Personal development"),
+    ("W", "Lifestyle, Hobbies & Leisure"),
+    ("X", "Graphic novels, Comic books, Cartoons"),
+    ("Y", "Children's, Teenage & Educational"),
+    ("YF", "Children's / Teenage fiction & true stories"),
+    ("1", "Place qualifiers"),
+    ("2", "Language qualifiers"),
+    ("3", "Time period qualifiers"),
+    ("4", "Educational purpose qualifiers"),
+    ("5", "Interest qualifiers"),
+    ("5A", "Interest age / level"),
+    ("6", "Style qualifiers"),
+];
+
+/// THEMA interest age qualifiers and the age each starts at
+const THEMA_INTEREST_AGES: &[(&str, u8)] = &[
+    ("5AB", 0),
+    ("5AC", 3),
+    ("5AD", 4),
+    ("5AF", 5),
+    ("5AG", 6),
+    ("5AH", 7),
+    ("5AJ", 8),
+    ("5AK", 9),
+    ("5AL", 10),
+    ("5AM", 11),
+    ("5AN", 12),
+    ("5AP", 13),
+    ("5AQ", 14),
+    ("5AR", 15),
+    ("5AS", 16),
+];
+
+/// The classification a subject code belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubjectScheme {
+    /// Book Industry Standards and Communications, used in North America
+    Bisac,
+    /// The international THEMA scheme from EDItEUR
+    Thema,
+}
+
+/// A decoded subject code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Category {
+    scheme: SubjectScheme,
+    code: String,
+    path: Vec<String>,
+    interest_age: Option<u8>,
+}
+
+impl Category {
+    pub fn get_scheme(&self) -> SubjectScheme {
+        self.scheme
+    }
+
+    pub fn get_code(&self) -> &str {
+        &self.code
+    }
+
+    /// Labels from the top-level subject down to this code
+    pub fn get_path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// The most specific label
+    pub fn get_label(&self) -> &str {
+        self.path.last().map_or("", String::as_str)
+    }
+
+    /// The path joined with ` / `, as stores usually show it
+    pub fn get_display_label(&self) -> String {
+        self.path.join(" / ")
+    }
+
+    /// The youngest age the book is meant for, from a THEMA interest age
+    pub fn get_interest_age(&self) -> Option<u8> {
+        self.interest_age
+    }
+}
+
+/// Decode a BISAC code such as `FIC009020`
+pub fn decode_bisac(code: &str) -> Option<Category> {
+    let code = code.trim().to_ascii_uppercase();
+    let heading = bisac_heading(&code)?;
+    Some(Category {
+        scheme: SubjectScheme::Bisac,
+        path: heading.split(" / ").map(str::to_string).collect(),
+        code,
+        interest_age: None,
+    })
+}
+
+/// Decode a THEMA subject or qualifier code such as `FMB` or `5AJ`
+///
+/// Unknown codes under a known subject decode to that subject's path.
+pub fn decode_thema(code: &str) -> Option<Category> {
+    let code = code.trim().to_ascii_uppercase();
+    if code.is_empty() || !code.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let mut path: Vec<String> = (1..=code.len())
+        .filter_map(|end| thema_label(&code[..end]))
+        .map(str::to_string)
+        .collect();
+    let interest_age = THEMA_INTEREST_AGES
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, age)| *age);
+    if let Some(age) = interest_age {
+        path.push(match age {
+            0 => "Interest age: from birth".to_string(),
+            age => format!("Interest age: from c {} years", age),
+        });
+    }
+    if path.is_empty() {
+        return None;
+    }
+    Some(Category {
+        scheme: SubjectScheme::Thema,
+        code,
+        path,
+        interest_age,
+    })
+}
+
+fn thema_label(code: &str) -> Option<&'static str> {
+    THEMA
+        .iter()
+        .find(|(known, _)| *known == code)
+        .map(|(_, label)| *label)
+}
+
+impl Epub {
+    /// Every BISAC and THEMA code in the subjects, decoded, without duplicates
+    pub fn categories(&self) -> Vec<Category> {
+        let refinement = |id: &str, property: &str| {
+            self.meta
+                .iter()
+                .find(|meta| {
+                    meta.refines.as_deref().and_then(|r| r.strip_prefix('#')) == Some(id)
+                        && meta.property.as_deref() == Some(property)
+                })
+                .and_then(|meta| meta.value.as_deref())
+        };
+
+        let mut categories: Vec<Category> = Vec::new();
+        let subjects = self
+            .get_metadata_elements()
+            .iter()
+            .filter(|element| element.is_dublin_core() && element.get_local_name() == "subject");
+        for subject in subjects {
+            let id = subject.get_attribute("id");
+            let authority = subject
+                .get_attributes()
+                .iter()
+                .find(|(name, _)| name.rsplit(':').next() == Some("authority"))
+                .map(|(_, value)| value.as_str())
+                .or_else(|| id.and_then(|id| refinement(id, "authority")));
+            let term = id
+                .and_then(|id| refinement(id, "term"))
+                .unwrap_or(subject.get_text());
+
+            let category = match authority.map(str::to_ascii_uppercase).as_deref() {
+                Some("BISAC") => decode_bisac(term),
+                Some("THEMA") => decode_thema(term),
+                Some(_) => None,
+                None => match term.split_once(':') {
+                    Some((scheme, code)) if scheme.eq_ignore_ascii_case("thema") => {
+                        decode_thema(code)
+                    }
+                    Some((scheme, code)) if scheme.eq_ignore_ascii_case("bisac") => {
+                        decode_bisac(code)
+                    }
+                    _ => decode_bisac(term),
+                },
+            };
+            if let Some(category) = category
+                && !categories
+                    .iter()
+                    .any(|c| c.scheme == category.scheme && c.code == category.code)
+            {
+                categories.push(category);
+            }
+        }
+        categories
+    }
+
+    /// The youngest interest age among the THEMA qualifiers, if any
+    pub fn interest_age(&self) -> Option<u8> {
+        self.categories()
+            .iter()
+            .filter_map(Category::get_interest_age)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    #[test]
+    fn codes_are_read_from_text_attributes_and_refinements() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:identifier>id</dc:identifier>
+    <dc:title>T</dc:title>
+    <dc:subject>FIC009020</dc:subject>
+    <dc:subject opf:authority="BISAC">fic009020</dc:subject>
+    <dc:subject id="s1">Epic fantasy</dc:subject>
+    <meta refines="#s1" property="authority">THEMA</meta>
+    <meta refines="#s1" property="term">FMB</meta>
+    <dc:subject>THEMA: 5AJ</dc:subject>
+    <dc:subject>FR</dc:subject>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"##;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let categories = epub.categories();
+        let labels: Vec<String> = categories.iter().map(Category::get_display_label).collect();
+        assert_eq!(
+            labels,
+            [
+                "Fiction / Fantasy / Epic",
+                "Fiction & related items / Fantasy / Epic fantasy",
+                "Interest qualifiers / Interest age / level / Interest age: from c 8 years",
+            ]
+        );
+        assert_eq!(categories[1].get_scheme(), SubjectScheme::Thema);
+        assert_eq!(categories[1].get_label(), "Epic fantasy");
+        assert_eq!(epub.interest_age(), Some(8));
+    }
+}
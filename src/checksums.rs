@@ -0,0 +1,221 @@
+//! Checksum manifests for archival workflows
+//!
+//! With `WriteOptions::with_checksums`, saving adds `META-INF/checksums.json`
+//! holding the SHA-256 digest of every other entry except `mimetype`.
+//! `Epub::verify_checksums`, or `ParseOptions::with_checksum_verification` at
+//! load time, compares an archive against it to catch corruption and
+//! tampering. Requires the `checksums` feature.
+//!
+//! The manifest is plain JSON so it can be checked without this crate:
+//!
+//! ```text
+//! {
+//!   "algorithm": "sha-256",
+//!   "files": {
+//!     "META-INF/container.xml": "0b1a…",
+//!     "OEBPS/content.opf": "9f86…"
+//!   }
+//! }
+//! ```
+
+use crate::epub::Epub;
+use crate::report::ChecksumMismatch;
+use crate::writer::CHECKSUMS_PATH;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Read, Seek};
+use std::sync::LazyLock;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+impl Epub {
+    /// Check the archive the book was loaded from against its checksum manifest
+    ///
+    /// Returns `None` when the book has no `META-INF/checksums.json`. Edits
+    /// made since loading aren't checked; saving with checksums covers them.
+    pub fn verify_checksums(&self) -> Result<Option<Vec<ChecksumMismatch>>, Box<dyn Error>> {
+        verify(&mut self.open_archive()?)
+    }
+}
+
+/// Lowercase hex SHA-256 of `bytes`
+pub(crate) fn digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The JSON manifest for `digests`, keyed by archive path
+pub(crate) fn manifest(digests: &BTreeMap<String, String>) -> String {
+    let files: Vec<String> = digests
+        .iter()
+        .map(|(path, digest)| format!("    \"{}\": \"{}\"", escape_json(path), digest))
+        .collect();
+    format!(
+        "{{\n  \"algorithm\": \"sha-256\",\n  \"files\": {{\n{}\n  }}\n}}\n",
+        files.join(",\n")
+    )
+}
+
+/// Compare every entry of `archive` with its manifest; `None` without one
+///
+/// Entries that can't be read (a failed CRC, say) count as missing.
+pub(crate) fn verify<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Option<Vec<ChecksumMismatch>>, Box<dyn Error>> {
+    let mut json = String::new();
+    match archive.by_name(CHECKSUMS_PATH) {
+        Ok(mut file) => file.read_to_string(&mut json)?,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut expected = parse_manifest(&json);
+
+    let mut mismatches = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let name = entry.name().to_string();
+        if entry.is_dir() || name == "mimetype" || name == CHECKSUMS_PATH {
+            continue;
+        }
+        let mut bytes = Vec::new();
+        let actual = entry.read_to_end(&mut bytes).ok().map(|_| digest(&bytes));
+        match expected.remove(&name) {
+            Some(recorded) if actual.as_deref() == Some(recorded.as_str()) => {}
+            recorded => mismatches.push(ChecksumMismatch::new(name, recorded, actual)),
+        }
+    }
+    for (path, recorded) in expected {
+        mismatches.push(ChecksumMismatch::new(path, Some(recorded), None));
+    }
+    Ok(Some(mismatches))
+}
+
+/// Path to digest pairs from a manifest; anything unrecognized is ignored
+fn parse_manifest(json: &str) -> BTreeMap<String, String> {
+    static ENTRY: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#""((?:[^"\\]|\\.)*)"\s*:\s*"([0-9a-fA-F]{64})""#)
+            .expect("manifest entry pattern")
+    });
+    ENTRY
+        .captures_iter(json)
+        .map(|cap| (unescape_json(&cap[1]), cap[2].to_ascii_lowercase()))
+        .collect()
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape_json(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('u') => {
+                let code: String = chars.by_ref().take(4).collect();
+                if let Some(c) = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                    unescaped.push(c);
+                }
+            }
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::{ParseOptions, WriteOptions};
+    use crate::test_support::{CONTAINER, build, package};
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn saved_checksums_catch_tampering() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>Original</p></body></html>"),
+        ]))
+        .unwrap();
+        let bytes = epub
+            .to_bytes_with_options(&WriteOptions::new().with_checksums(true))
+            .unwrap();
+
+        let verified = Epub::from_bytes_with_options(
+            bytes.clone(),
+            ParseOptions::new().with_checksum_verification(true),
+        )
+        .unwrap();
+        assert_eq!(verified.verify_checksums().unwrap(), Some(Vec::new()));
+        assert!(
+            verified
+                .get_parse_report()
+                .get_checksum_mismatches()
+                .is_empty()
+        );
+
+        // Swap one chapter's contents and slip in an extra file
+        let mut original = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut tampered = ZipWriter::new(Cursor::new(Vec::new()));
+        for index in 0..original.len() {
+            let entry = original.by_index_raw(index).unwrap();
+            if entry.name() == "ch1.xhtml" {
+                drop(entry);
+                tampered
+                    .start_file("ch1.xhtml", SimpleFileOptions::default())
+                    .unwrap();
+                tampered
+                    .write_all(b"<html><body><p>Forged</p></body></html>")
+                    .unwrap();
+            } else {
+                tampered.raw_copy_file(entry).unwrap();
+            }
+        }
+        tampered
+            .start_file("extra.txt", SimpleFileOptions::default())
+            .unwrap();
+        let tampered = tampered.finish().unwrap().into_inner();
+
+        let epub = Epub::from_bytes_with_options(
+            tampered,
+            ParseOptions::new().with_checksum_verification(true),
+        )
+        .unwrap();
+        let mismatches = epub.get_parse_report().get_checksum_mismatches();
+        let paths: Vec<&str> = mismatches.iter().map(|m| m.get_path()).collect();
+        assert_eq!(paths, ["ch1.xhtml", "extra.txt"]);
+        assert!(mismatches[0].get_expected().is_some() && mismatches[0].get_actual().is_some());
+        assert_eq!(mismatches[1].get_expected(), None);
+
+        let digests = BTreeMap::from([("Text/\"quoted\" \\ name.xhtml".to_string(), digest(b""))]);
+        assert_eq!(parse_manifest(&manifest(&digests)), digests);
+    }
+}
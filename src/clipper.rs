@@ -0,0 +1,469 @@
+//! Turning saved web pages into chapters
+//!
+//! This is the core of "send to e-reader" tools. The caller fetches the
+//! pages and the images they use; the crate does no networking. Each page is
+//! reduced to its main content: the `<article>`, else `<main>`, else the
+//! `<body>`. Scripts, navigation, forms and inline styling are stripped,
+//! and URLs are resolved against the page's own URL. See
+//! `EpubBuilder::with_web_pages`.
+//!
+//! Like the rest of the crate this works on markup with regular expressions
+//! rather than a DOM, so badly broken HTML (unclosed `<p>`s, say) is passed
+//! through as it is.
+
+use crate::href;
+use crate::text::{escape_xml, first_heading};
+use crate::xhtml::parse_attributes;
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// Elements dropped together with everything inside them
+const DROPPED_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "iframe", "object", "form", "button",
+    "select", "textarea", "nav", "aside", "footer", "dialog", "svg", "canvas", "video", "audio",
+];
+
+/// Attributes kept on clipped elements, besides `href` on links and `src` on images
+const KEPT_ATTRIBUTES: &[&str] = &[
+    "id", "alt", "title", "lang", "dir", "colspan", "rowspan", "headers", "scope", "start",
+    "reversed", "cite", "datetime",
+];
+
+static COMMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->|<!\[CDATA\[.*?\]\]>").expect("comment pattern"));
+static HEAD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<head\b.*?</head\s*>").expect("head pattern"));
+/// A start or end tag of one of `DROPPED_ELEMENTS`: slash, name
+static DROPPED_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"(?i)<(/?)({})\b[^>]*>",
+        DROPPED_ELEMENTS.join("|")
+    ))
+    .expect("dropped element pattern")
+});
+/// Opening and closing tags of the elements `main_content` looks for, in order
+static CONTENT_TAGS: LazyLock<[(Regex, Regex); 3]> = LazyLock::new(|| {
+    ["article", "main", "body"].map(|name| {
+        (
+            Regex::new(&format!(r"(?i)<{}\b[^>]*>", name)).expect("open tag pattern"),
+            Regex::new(&format!(r"(?i)</{}\s*>", name)).expect("close tag pattern"),
+        )
+    })
+});
+static UNWANTED_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)</?(picture|font|center)\b[^>]*>|<(input|link|meta|base|source|embed|track|param|area)\b[^>]*>",
+    )
+    .expect("unwanted tag pattern")
+});
+/// A start tag: name, attributes
+static START_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<([A-Za-z][A-Za-z0-9-]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#).expect("tag pattern")
+});
+static BLANK_LINES: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\n\s*\n").expect("blank line pattern"));
+
+/// A fetched HTML page
+#[derive(Debug, Clone)]
+pub struct WebPage {
+    url: String,
+    html: String,
+}
+
+impl WebPage {
+    /// A page as fetched from `url`, which relative URLs in it are resolved against
+    pub fn new(url: impl Into<String>, html: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            html: html.into(),
+        }
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_html(&self) -> &str {
+        &self.html
+    }
+}
+
+/// A fetched image used by one of the pages
+#[derive(Debug, Clone)]
+pub struct WebResource {
+    url: String,
+    media_type: String,
+    bytes: Vec<u8>,
+}
+
+impl WebResource {
+    /// An image as fetched from the absolute `url`
+    pub fn new(url: impl Into<String>, media_type: impl Into<String>, bytes: Vec<u8>) -> Self {
+        Self {
+            url: url.into(),
+            media_type: media_type.into(),
+            bytes,
+        }
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// A file name for the bundled copy, with an extension matching the media type
+    pub(crate) fn file_name(&self) -> String {
+        let path = self.url.split(['?', '#']).next().unwrap_or("");
+        let name: String = href::percent_decode(path.rsplit('/').next().unwrap_or(""))
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let name = name.trim_matches(['.', '-']);
+        let stem = match name.rsplit_once('.') {
+            _ if name.is_empty() => "image",
+            _ if href::media_type_for(name) == self.media_type => return name.to_string(),
+            Some((stem, _)) => stem,
+            None => name,
+        };
+        format!("{}.{}", stem, href::extension_for(&self.media_type))
+    }
+}
+
+/// A page reduced to a chapter body
+pub(crate) struct Clipped {
+    pub(crate) title: String,
+    pub(crate) html: String,
+}
+
+/// Reduce a page to its main content
+///
+/// `link` maps the absolute URL of each link to the href to use instead;
+/// `image` does the same for images, returning `None` for images that
+/// weren't fetched, which are then dropped.
+pub(crate) fn clip(
+    page: &WebPage,
+    mut link: impl FnMut(&str) -> String,
+    mut image: impl FnMut(&str) -> Option<String>,
+) -> Clipped {
+    let html = COMMENT.replace_all(&page.html, "");
+    let head_title = HEAD
+        .find(&html)
+        .and_then(|head| first_heading(head.as_str()));
+
+    let html = remove_dropped_elements(&html);
+    let content = main_content(&html);
+
+    // Wrappers are unwrapped, and void elements that don't render are removed
+    let content = UNWANTED_TAG.replace_all(content, "");
+
+    let content = START_TAG.replace_all(&content, |cap: &Captures| {
+        let name = cap[1].to_ascii_lowercase();
+        let self_closing = cap[2].trim_end().ends_with('/');
+        let attributes = parse_attributes(&cap[2]);
+        let value = |wanted: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| name == wanted)
+                .map(|(_, value)| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        let mut kept: Vec<(&str, String)> = Vec::new();
+        match name.as_str() {
+            "a" => {
+                if let Some(target) = value("href")
+                    && !target.to_ascii_lowercase().starts_with("javascript:")
+                {
+                    let target = if target.starts_with('#') {
+                        target.to_string()
+                    } else {
+                        link(&absolute_url(&page.url, target))
+                    };
+                    kept.push(("href", target));
+                }
+            }
+            "img" => {
+                // Lazy-loading pages keep the real source in `data-src`
+                let Some(source) = value("data-src").or_else(|| value("src")) else {
+                    return String::new();
+                };
+                let source = if source.starts_with("data:") {
+                    source.to_string()
+                } else {
+                    match image(&absolute_url(&page.url, source)) {
+                        Some(bundled) => bundled,
+                        None => return String::new(),
+                    }
+                };
+                kept.push(("src", source));
+                if value("alt").is_none() {
+                    kept.push(("alt", String::new()));
+                }
+            }
+            _ => {}
+        }
+        for (attribute, value) in &attributes {
+            if KEPT_ATTRIBUTES.contains(&attribute.as_str()) {
+                kept.push((attribute, value.clone()));
+            }
+        }
+
+        let mut out = format!("<{}", name);
+        for (attribute, value) in kept {
+            out.push_str(&format!(" {}=\"{}\"", attribute, escape_xml(&value)));
+        }
+        out.push_str(if self_closing { "/>" } else { ">" });
+        out
+    });
+
+    let html = BLANK_LINES.replace_all(content.trim(), "\n").into_owned();
+    let title = first_heading(&html)
+        .or(head_title)
+        .unwrap_or_else(|| page.url.clone());
+    Clipped { title, html }
+}
+
+/// Remove `DROPPED_ELEMENTS` along with their contents
+///
+/// Nesting is tracked so `<aside><aside></aside>…</aside>` goes as a whole.
+/// An element that is never closed runs to the end of the document, as it
+/// would in a browser. Stray closing tags are dropped.
+fn remove_dropped_elements(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut kept_from = 0;
+    let mut open: Option<(String, usize)> = None;
+
+    for cap in DROPPED_TAG.captures_iter(html) {
+        let whole = cap.get(0).expect("whole match");
+        let closing = !cap[1].is_empty();
+        let name = cap[2].to_ascii_lowercase();
+        let self_closing = whole.as_str().ends_with("/>");
+
+        match &mut open {
+            None => {
+                out.push_str(&html[kept_from..whole.start()]);
+                kept_from = whole.end();
+                if !closing && !self_closing {
+                    open = Some((name, 1));
+                }
+            }
+            Some((open_name, depth)) if *open_name == name => {
+                if !closing && !self_closing {
+                    *depth += 1;
+                } else if closing {
+                    *depth -= 1;
+                    if *depth == 0 {
+                        kept_from = whole.end();
+                        open = None;
+                    }
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    if open.is_none() {
+        out.push_str(&html[kept_from..]);
+    }
+    out
+}
+
+/// The inside of the first of `<article>`, `<main>` or `<body>` the page has
+fn main_content(html: &str) -> &str {
+    for (open, close) in CONTENT_TAGS.iter() {
+        if let Some(start) = open.find(html)
+            && let Some(end) = close.find_iter(&html[start.end()..]).last()
+        {
+            return &html[start.end()..start.end() + end.start()];
+        }
+    }
+    html
+}
+
+/// Resolve a URL reference against the URL of the page it appears on
+///
+/// Unlike `href::resolve`, which produces archive paths, this keeps the
+/// scheme, host, query and fragment, and doesn't percent-decode.
+pub(crate) fn absolute_url(base: &str, reference: &str) -> String {
+    let reference = reference.trim();
+    if href::has_scheme(reference) {
+        return reference.to_string();
+    }
+    let base = base.split('#').next().unwrap_or(base);
+    if reference.is_empty() || reference.starts_with('#') {
+        return format!("{}{}", base, reference);
+    }
+    if let Some(rest) = reference.strip_prefix("//") {
+        let scheme = base.split_once("://").map_or("https", |(scheme, _)| scheme);
+        return format!("{}://{}", scheme, rest);
+    }
+
+    let (origin, path) = match base.find("://") {
+        Some(pos) => {
+            // The authority ends at the path, query or fragment, whichever comes first
+            let path_start = base[pos + 3..]
+                .find(['/', '?', '#'])
+                .map_or(base.len(), |i| pos + 3 + i);
+            base.split_at(path_start)
+        }
+        None => ("", base),
+    };
+    let path = path.split('?').next().unwrap_or(path);
+    let merged = if reference.starts_with('?') {
+        format!("{}{}", path, reference)
+    } else if reference.starts_with('/') {
+        reference.to_string()
+    } else {
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let separator = if dir.is_empty() && origin.is_empty() {
+            ""
+        } else {
+            "/"
+        };
+        format!("{}{}{}", dir, separator, reference)
+    };
+
+    let (merged_path, suffix) = merged.split_at(merged.find(['?', '#']).unwrap_or(merged.len()));
+    let parts: Vec<&str> = merged_path.split('/').collect();
+    let mut segments: Vec<&str> = Vec::new();
+    for (i, segment) in parts.iter().enumerate() {
+        let is_last = i + 1 == parts.len();
+        match *segment {
+            "." | ".." => {
+                // Never pop the empty segment before a leading `/`
+                if *segment == ".." && segments.len() > usize::from(merged_path.starts_with('/')) {
+                    segments.pop();
+                }
+                if is_last {
+                    segments.push("");
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+    format!("{}{}{}", origin, segments.join("/"), suffix)
+}
+
+/// A URL without its fragment, for matching links to pages and resources
+pub(crate) fn url_key(url: &str) -> &str {
+    url.split('#').next().unwrap_or(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::EpubBuilder;
+
+    #[test]
+    fn resolves_urls_against_the_page() {
+        let base = "https://example.com/blog/post/?page=2#top";
+        assert_eq!(
+            absolute_url(base, "../img/a.png"),
+            "https://example.com/blog/img/a.png"
+        );
+        assert_eq!(
+            absolute_url(base, "/x?y=1#z"),
+            "https://example.com/x?y=1#z"
+        );
+        assert_eq!(
+            absolute_url(base, "//cdn.example.com/a.png"),
+            "https://cdn.example.com/a.png"
+        );
+        assert_eq!(
+            absolute_url(base, "#notes"),
+            "https://example.com/blog/post/?page=2#notes"
+        );
+        assert_eq!(
+            absolute_url("https://example.com", "a.png"),
+            "https://example.com/a.png"
+        );
+        assert_eq!(absolute_url("saved/page.html", "../a.png"), "a.png");
+        // A query straight after the host isn't part of the authority
+        assert_eq!(
+            absolute_url("https://example.com?ref=a/b", "img/c.png"),
+            "https://example.com/img/c.png"
+        );
+        assert_eq!(
+            absolute_url("https://example.com?ref=a/b", "?page=2"),
+            "https://example.com?page=2"
+        );
+    }
+
+    #[test]
+    fn pages_become_clean_linked_chapters() {
+        let first = WebPage::new(
+            "https://example.com/story/part-1.html",
+            r#"<html><head><title>Part One | Example</title><script>alert(1)</script></head>
+<body><nav><ul><li><a href="/">Home</a></li></ul></nav>
+<article class="post" style="color: red"><h1 onclick="x()">Part One</h1>
+<p>Read <a href="part-2.html#start">part two</a> or <a href="javascript:void(0)">share</a>.</p>
+<picture><source srcset="big.webp"><img data-src="../img/map.png?w=800" src="placeholder.gif" width="800"></picture>
+<img src="https://tracker.example.net/pixel.gif">
+<aside>Related <aside>nested</aside> posts</aside>
+</article><footer>&copy; Example</footer></body></html>"#,
+        );
+        let second = WebPage::new(
+            "https://example.com/story/part-2.html",
+            r#"<html><head><title>Part Two</title></head><body><main>
+<p id="start">The end.</p><!-- <h1>Draft</h1> --></main></body></html>"#,
+        );
+        let map = WebResource::new(
+            "https://example.com/img/map.png?w=800",
+            "image/png",
+            vec![1],
+        );
+        assert_eq!(map.file_name(), "map.png");
+
+        let epub = EpubBuilder::new("Story")
+            .with_web_pages(&[first, second], &[map])
+            .build()
+            .unwrap();
+
+        let toc: Vec<&str> = epub
+            .get_table_of_contents()
+            .get_entries()
+            .iter()
+            .map(|entry| entry.get_title())
+            .collect();
+        assert_eq!(toc, ["Part One", "Part Two"]);
+        assert_eq!(
+            epub.get_resource_bytes("images/map.png").as_deref(),
+            Some(&[1u8][..])
+        );
+
+        let part_one = epub.get_all_files()[0].get_content();
+        assert!(part_one.contains(r#"<a href="chapter-002.xhtml#start">part two</a>"#));
+        assert!(part_one.contains(r#"<img src="../images/map.png" alt=""/>"#));
+        assert!(part_one.contains(r#"<h1>Part One</h1>"#));
+        for gone in [
+            "alert",
+            "Home",
+            "class=",
+            "style=\"",
+            "javascript",
+            "tracker",
+            "Related",
+            "posts",
+            "Example",
+        ] {
+            assert!(!part_one.contains(gone), "{} should be stripped", gone);
+        }
+        assert!(
+            epub.get_all_files()[1]
+                .get_content()
+                .contains("<p id=\"start\">The end.</p>")
+        );
+    }
+}
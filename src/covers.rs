@@ -0,0 +1,152 @@
+//! Undoable cover replacement
+//!
+//! Library managers swap covers for better scans all the time, and users
+//! want the original back when the new one turns out wrong. `replace_cover`
+//! keeps each replaced image inside the book, under
+//! `META-INF/epubie/previous-covers/`, numbered in the order they were
+//! replaced. `META-INF` is outside the package, so reading systems ignore
+//! the history, and it survives `save` and re-parsing. `revert_cover`
+//! restores the most recent one.
+
+use crate::edit::{COVER_MEDIA_TYPES, cover_extension};
+use crate::epub::Epub;
+use crate::error::EpubError;
+
+/// Where replaced covers are kept, relative to the archive root
+const HISTORY_DIR: &str = "META-INF/epubie/previous-covers/";
+
+impl Epub {
+    /// Replace the cover image, keeping the old one for `revert_cover`
+    ///
+    /// Works like `set_cover_image`; a book without a cover simply gets one.
+    pub fn replace_cover(&mut self, bytes: Vec<u8>, media_type: &str) -> Result<(), EpubError> {
+        let current = self
+            .get_cover()
+            .and_then(|id| self.get_resource_by_id(id))
+            .map(|resource| {
+                (
+                    resource.get_href().to_string(),
+                    resource.get_media_type().to_string(),
+                )
+            });
+        let previous = current.and_then(|(href, media_type)| {
+            let extension = cover_extension(&media_type)?;
+            Some((self.get_resource_bytes(&href)?, extension))
+        });
+        self.set_cover_image(bytes, media_type)?;
+
+        if let Some((bytes, extension)) = previous {
+            let number = self
+                .get_previous_covers()
+                .iter()
+                .filter_map(|path| history_number(path))
+                .max()
+                .unwrap_or(0)
+                + 1;
+            let path = format!("/{}{}.{}", HISTORY_DIR, number, extension);
+            self.put_resource_bytes(&path, bytes);
+        }
+        Ok(())
+    }
+
+    /// Archive paths of the replaced covers, oldest first
+    pub fn get_previous_covers(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .open_archive()
+            .map(|archive| {
+                archive
+                    .file_names()
+                    .filter(|name| !self.removed.contains(*name))
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.extend(self.overrides.keys().cloned());
+        paths.retain(|path| history_number(path).is_some());
+        paths.sort_by_key(|path| history_number(path));
+        paths.dedup();
+        paths
+    }
+
+    /// Put back the cover replaced most recently by `replace_cover`
+    ///
+    /// The restored image leaves the history, so calling this repeatedly
+    /// walks back through older covers. Returns `false` when there is no
+    /// history.
+    pub fn revert_cover(&mut self) -> Result<bool, EpubError> {
+        let Some(path) = self.get_previous_covers().pop() else {
+            return Ok(false);
+        };
+        let href = format!("/{}", path);
+        let media_type = path
+            .rsplit_once('.')
+            .and_then(|(_, extension)| {
+                COVER_MEDIA_TYPES
+                    .iter()
+                    .find(|(_, known)| *known == extension)
+            })
+            .map(|(media_type, _)| *media_type);
+        let (Some(media_type), Some(bytes)) = (media_type, self.get_resource_bytes(&href)) else {
+            return Err(EpubError::InvalidMetadata(format!(
+                "previous cover {} can't be read",
+                path
+            )));
+        };
+        self.set_cover_image(bytes, media_type)?;
+        if self.overrides.remove(&path).is_none() {
+            self.removed.insert(path);
+        }
+        Ok(true)
+    }
+}
+
+/// The number of a cover history entry such as `META-INF/epubie/previous-covers/2.jpg`
+fn history_number(path: &str) -> Option<u32> {
+    let name = path.strip_prefix(HISTORY_DIR)?;
+    name.split_once('.')?.0.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn replaced_covers_can_be_reverted_after_saving() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="cover" href="cover.jpg" media-type="image/jpeg" properties="cover-image"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("cover.jpg", "original"),
+        ]))
+        .unwrap();
+
+        epub.replace_cover(b"scan".to_vec(), "image/png").unwrap();
+        epub.replace_cover(b"better scan".to_vec(), "image/png")
+            .unwrap();
+        let mut epub = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            epub.get_previous_covers(),
+            [
+                "META-INF/epubie/previous-covers/1.jpg",
+                "META-INF/epubie/previous-covers/2.png"
+            ]
+        );
+        assert_eq!(epub.get_cover_bytes().unwrap(), b"better scan");
+
+        assert!(epub.revert_cover().unwrap());
+        assert_eq!(epub.get_cover_bytes().unwrap(), b"scan");
+        assert!(epub.revert_cover().unwrap());
+        assert_eq!(epub.get_cover_bytes().unwrap(), b"original");
+        assert!(!epub.revert_cover().unwrap());
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert!(saved.get_previous_covers().is_empty());
+        assert_eq!(saved.get_cover_bytes().unwrap(), b"original");
+    }
+}
@@ -0,0 +1,260 @@
+//! Colours that break dark themes
+//!
+//! Reading apps implement night mode by swapping the page to light text on
+//! a dark background. CSS that hardcodes one side of that pair defeats it:
+//! `color: #222` on its own leaves dark text on the dark page, and
+//! `background: white` on its own leaves the app's light text on a white
+//! box. A rule that sets both is a self-contained panel and reads fine
+//! either way, so only the one-sided ones are reported.
+//!
+//! `Epub::dark_mode_audit` lists them, and `Epub::patch_dark_mode` (or
+//! `WriteOptions::with_dark_mode_patch`) removes them or resets them to
+//! `inherit` and `transparent`, in stylesheets, `<style>` elements and
+//! `style` attributes alike.
+
+use crate::epub::{Epub, is_content_media_type};
+use crate::styles::{
+    BLACK, Declaration, StyleIssue, StyleIssueKind, WHITE, contrast, declarations, parse_color,
+    style_rules,
+};
+use crate::text::{decode_entities, escape_xml};
+use crate::xhtml::{TAG, find_elements};
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// Contrast ratio (as in WCAG) below which a colour is taken to be
+/// unreadable against the opposite theme
+const ILLEGIBLE_CONTRAST: f64 = 3.0;
+
+static STYLE_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?i)(\sstyle\s*=\s*)(?:"([^"]*)"|'([^']*)')"#).expect("style attribute pattern")
+});
+
+/// What `Epub::patch_dark_mode` does with a colour that breaks dark themes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorPatch {
+    /// Remove the declaration
+    Strip,
+    /// Replace the value with `inherit` (text) or `transparent` (background),
+    /// which also overrides any rule it used to override
+    Neutralize,
+}
+
+impl Epub {
+    /// One-sided text and background colours that break dark themes, in
+    /// manifest order
+    pub fn dark_mode_audit(&self) -> Vec<StyleIssue> {
+        enter_span!("dark_mode_audit");
+        let mut issues = Vec::new();
+        self.for_each_style_rule(|href, selector, block| {
+            let declarations = declarations(block);
+            issues.extend(
+                dark_mode_issues(&declarations)
+                    .into_iter()
+                    .map(|(kind, d)| StyleIssue::new(kind, href, selector, d.to_css())),
+            );
+        });
+        issues
+    }
+
+    /// Strip or neutralize the colours `dark_mode_audit` reports, returning
+    /// the hrefs of the stylesheets and documents that changed
+    pub fn patch_dark_mode(&mut self, patch: ColorPatch) -> Vec<String> {
+        enter_span!("patch_dark_mode");
+        let updates: Vec<(String, String)> = self
+            .resources
+            .iter()
+            .filter_map(|resource| {
+                let is_css = resource.media_type == "text/css";
+                if !is_css && !is_content_media_type(&resource.media_type) {
+                    return None;
+                }
+                let content = String::from_utf8(self.get_resource_bytes(&resource.href)?).ok()?;
+                let patched = if is_css {
+                    patch_stylesheet(&content, patch)
+                } else {
+                    patch_document(&content, patch)
+                };
+                (patched != content).then(|| (resource.href.clone(), patched))
+            })
+            .collect();
+
+        let changed = updates.iter().map(|(href, _)| href.clone()).collect();
+        for (href, content) in updates {
+            self.put_resource_bytes(&href, content.into_bytes());
+        }
+        changed
+    }
+}
+
+/// The declarations in one rule that break dark themes
+fn dark_mode_issues(declarations: &[Declaration]) -> Vec<(StyleIssueKind, &Declaration)> {
+    // The last declaration of a property is the one that applies
+    let foreground = declarations
+        .iter()
+        .rev()
+        .find(|d| d.property == "color" && parse_color(&d.value).is_some());
+    let background = declarations
+        .iter()
+        .rev()
+        .filter(|d| d.property == "background-color" || d.property == "background")
+        .find(|d| parse_color(&d.value).is_some() || d.value.contains("url("));
+
+    match (foreground, background) {
+        (Some(d), None) => parse_color(&d.value)
+            .filter(|&color| contrast(color, BLACK) < ILLEGIBLE_CONTRAST)
+            .map(|_| vec![(StyleIssueKind::DarkText, d)])
+            .unwrap_or_default(),
+        // A background image is part of the design, not a page colour
+        (None, Some(d)) if !d.value.contains("url(") => parse_color(&d.value)
+            .filter(|&color| contrast(color, WHITE) < ILLEGIBLE_CONTRAST)
+            .map(|_| vec![(StyleIssueKind::LightBackground, d)])
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// A declaration block with its dark-mode issues patched
+fn patch_block(block: &str, patch: ColorPatch) -> String {
+    let declarations = declarations(block);
+    let mut issues = dark_mode_issues(&declarations);
+    issues.sort_by_key(|(_, d)| std::cmp::Reverse(d.range.start));
+    let mut patched = block.to_string();
+    for (kind, d) in issues {
+        match patch {
+            ColorPatch::Strip => {
+                let rest = &block[d.range.end..];
+                let end = match rest.strip_prefix(';') {
+                    Some(after) => block.len() - after.trim_start().len(),
+                    None => d.range.end,
+                };
+                patched.replace_range(d.range.start..end, "");
+            }
+            ColorPatch::Neutralize => {
+                let value = match kind {
+                    StyleIssueKind::DarkText => "inherit",
+                    _ => "transparent",
+                };
+                patched.replace_range(d.range.clone(), &format!("{}: {}", d.property, value));
+            }
+        }
+    }
+    patched
+}
+
+/// A stylesheet with every rule's dark-mode issues patched
+fn patch_stylesheet(css: &str, patch: ColorPatch) -> String {
+    let mut patched = css.to_string();
+    for (_, block) in style_rules(css).into_iter().rev() {
+        patched.replace_range(block.clone(), &patch_block(&css[block], patch));
+    }
+    patched
+}
+
+/// A content document with its `<style>` elements and `style` attributes
+/// patched
+fn patch_document(html: &str, patch: ColorPatch) -> String {
+    let mut patched = html.to_string();
+    for style in find_elements(html, |e| e.name == "style").into_iter().rev() {
+        let start = style.range.start + html[style.range.clone()].find('>').map_or(0, |i| i + 1);
+        let inner = start..start + style.inner.len();
+        patched.replace_range(inner, &patch_stylesheet(style.inner, patch));
+    }
+
+    TAG.replace_all(&patched, |tag: &Captures| {
+        let whole = &tag[0];
+        if !tag[1].is_empty() {
+            return whole.to_string();
+        }
+        STYLE_ATTRIBUTE
+            .replace(whole, |attribute: &Captures| {
+                let value = attribute.get(2).or_else(|| attribute.get(3));
+                let style = decode_entities(value.map_or("", |m| m.as_str()));
+                let fixed = patch_block(&style, patch);
+                if fixed == style {
+                    return attribute[0].to_string();
+                }
+                format!("{}\"{}\"", &attribute[1], escape_xml(fixed.trim()))
+            })
+            .into_owned()
+    })
+    .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book() -> Epub {
+        let opf = package(
+            r#"<item id="css" href="style.css" media-type="text/css"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let css = "body { color: #111; margin: 0 }\n.box { color: #eee; background: #222 }\n.card { background-color: white; padding: 1em }\n.hero { background: url(sky.jpg) #fff }";
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("style.css", css),
+            (
+                "ch1.xhtml",
+                r#"<html><head><style>p { color: black }</style></head><body><p style="color: #000; font-style: italic">Text</p></body></html>"#,
+            ),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn audit_reports_one_sided_colours() {
+        let issues: Vec<(StyleIssueKind, String)> = book()
+            .dark_mode_audit()
+            .iter()
+            .map(|issue| (issue.get_kind(), issue.to_string()))
+            .collect();
+        use StyleIssueKind::*;
+        assert_eq!(
+            issues,
+            [
+                (
+                    DarkText,
+                    "style.css: dark text in body { color: #111 }".to_string()
+                ),
+                (
+                    LightBackground,
+                    "style.css: light background in .card { background-color: white }".to_string()
+                ),
+                (
+                    DarkText,
+                    "ch1.xhtml: dark text in p { color: black }".to_string()
+                ),
+                (
+                    DarkText,
+                    "ch1.xhtml: dark text in p { color: #000 }".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn patch_strips_or_neutralizes_colours() {
+        let mut epub = book();
+        assert_eq!(
+            epub.patch_dark_mode(ColorPatch::Strip),
+            ["style.css", "ch1.xhtml"]
+        );
+        let css = String::from_utf8(epub.get_resource_bytes("style.css").unwrap()).unwrap();
+        assert!(css.starts_with("body { margin: 0 }\n.box { color: #eee; background: #222 }"));
+        assert!(css.contains(".card { padding: 1em }"));
+        let html = String::from_utf8(epub.get_resource_bytes("ch1.xhtml").unwrap()).unwrap();
+        assert!(html.contains("<style>p {  }</style>"));
+        assert!(html.contains(r#"<p style="font-style: italic">"#));
+        assert!(epub.dark_mode_audit().is_empty());
+
+        let mut epub = book();
+        epub.patch_dark_mode(ColorPatch::Neutralize);
+        let css = String::from_utf8(epub.get_resource_bytes("style.css").unwrap()).unwrap();
+        assert!(css.contains("body { color: inherit; margin: 0 }"));
+        assert!(css.contains(".card { background-color: transparent; padding: 1em }"));
+    }
+}
@@ -0,0 +1,383 @@
+//! Finding the same book twice in a library
+//!
+//! Libraries collect duplicates: the same title from two stores, a
+//! re-download with fixed typos, an ISBN-10 and an ISBN-13 copy. No single
+//! test catches them all, so `find_duplicates` groups summaries that match
+//! on any of three keys:
+//!
+//! - a shared identifier, with ISBNs compared in ISBN-13 form and `urn:`
+//!   prefixes dropped (`normalize_identifier`);
+//! - the same normalized title (`title_key`) with at least one author in
+//!   common (`author_key`);
+//! - near-identical text at the start of the book (`content_hash`), for
+//!   summaries made with `Epub::summary`.
+//!
+//! The keys are public so a library database can store and index them.
+
+use crate::epub::Epub;
+use crate::summary::EpubSummary;
+use std::collections::HashMap;
+
+/// Identifiers shorter than this (`1`, `B0`) are too generic to match on
+const MIN_IDENTIFIER_LENGTH: usize = 8;
+/// Chapters with fewer words (cover pages, title pages) don't identify a book
+const MIN_CHAPTER_WORDS: usize = 100;
+/// Content hashes differing in at most this many bits are the same text
+const MAX_HASH_DISTANCE: u32 = 8;
+/// Words hashed together when building a content hash
+const SHINGLE_WORDS: usize = 3;
+
+/// Why two books were put in the same `DuplicateGroup`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MatchReason {
+    Identifier,
+    TitleAndAuthor,
+    Content,
+}
+
+/// Summaries that are probably the same book, from `find_duplicates`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    indices: Vec<usize>,
+    reasons: Vec<MatchReason>,
+}
+
+impl DuplicateGroup {
+    /// Positions in the slice given to `find_duplicates`, ascending
+    pub fn get_indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    /// Every kind of match that linked the group, in `MatchReason` order
+    pub fn get_reasons(&self) -> &[MatchReason] {
+        &self.reasons
+    }
+}
+
+/// A title reduced for comparison
+///
+/// Lowercased, with punctuation dropped, a leading article removed, and any
+/// subtitle (after `:`) or parenthesized edition note left out, so "The
+/// Hobbit: Or There and Back Again" and "Hobbit (Illustrated)" agree.
+pub fn title_key(title: &str) -> String {
+    let main = title.split([':', '(', '[']).next().unwrap_or(title);
+    let words = words(main);
+    let start = match words.first().map(String::as_str) {
+        Some("the" | "a" | "an") if words.len() > 1 => 1,
+        _ => 0,
+    };
+    words[start..].join(" ")
+}
+
+/// An author name reduced for comparison
+///
+/// Lowercased, with punctuation dropped and the words sorted, so "Tolkien,
+/// J. R. R." and "J.R.R. Tolkien" agree.
+pub fn author_key(name: &str) -> String {
+    let mut words = words(name);
+    words.sort();
+    words.join(" ")
+}
+
+/// An identifier in a comparable form, or `None` if it's too short to trust
+///
+/// `urn:isbn:`, `isbn:`, `urn:uuid:` and similar prefixes are dropped, the
+/// rest is lowercased, and ISBNs (with or without hyphens, ISBN-10 or
+/// ISBN-13) become bare ISBN-13 digits.
+pub fn normalize_identifier(identifier: &str) -> Option<String> {
+    let mut id = identifier.trim().to_lowercase();
+    for prefix in ["urn:", "isbn:", "uuid:", "doi:"] {
+        if let Some(rest) = id.strip_prefix(prefix) {
+            id = rest.to_string();
+        }
+    }
+    let digits: String = id.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    let id = isbn13(&digits).unwrap_or(id);
+    (id.chars().count() >= MIN_IDENTIFIER_LENGTH).then_some(id)
+}
+
+/// A 64-bit similarity hash of `text`
+///
+/// Texts that differ only a little (a fixed typo, different whitespace or
+/// punctuation) hash to values a few bits apart; unrelated texts differ in
+/// about half their bits. Compare with `hash_distance`.
+pub fn content_hash(text: &str) -> u64 {
+    let words = words(text);
+    let mut weights = [0i64; 64];
+    for shingle in words.windows(SHINGLE_WORDS.min(words.len()).max(1)) {
+        let hash = fnv1a(&shingle.join(" "));
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            *weight += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+/// Number of bits two content hashes differ in
+pub fn hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Group the summaries that are probably the same book
+///
+/// Only groups of two or more are returned, ordered by their first index.
+pub fn find_duplicates(summaries: &[EpubSummary]) -> Vec<DuplicateGroup> {
+    let mut links: Vec<(usize, usize, MatchReason)> = Vec::new();
+
+    let mut by_identifier: HashMap<String, usize> = HashMap::new();
+    for (index, summary) in summaries.iter().enumerate() {
+        for id in summary
+            .get_identifiers()
+            .iter()
+            .filter_map(|id| normalize_identifier(id))
+        {
+            match by_identifier.get(&id) {
+                Some(&first) if first != index => {
+                    links.push((first, index, MatchReason::Identifier))
+                }
+                Some(_) => {}
+                None => {
+                    by_identifier.insert(id, index);
+                }
+            }
+        }
+    }
+
+    let mut by_title: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, summary) in summaries.iter().enumerate() {
+        let key = summary.get_title().map(title_key).unwrap_or_default();
+        if !key.is_empty() {
+            by_title.entry(key).or_default().push(index);
+        }
+    }
+    for indices in by_title.values() {
+        for (position, &a) in indices.iter().enumerate() {
+            let authors: Vec<String> = summaries[a]
+                .get_creators()
+                .iter()
+                .map(|name| author_key(name))
+                .collect();
+            for &b in &indices[position + 1..] {
+                if summaries[b]
+                    .get_creators()
+                    .iter()
+                    .any(|name| authors.contains(&author_key(name)))
+                {
+                    links.push((a, b, MatchReason::TitleAndAuthor));
+                }
+            }
+        }
+    }
+
+    let hashed: Vec<(usize, u64)> = summaries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, summary)| Some((index, summary.get_content_hash()?)))
+        .collect();
+    for (position, &(a, hash_a)) in hashed.iter().enumerate() {
+        for &(b, hash_b) in &hashed[position + 1..] {
+            if hash_distance(hash_a, hash_b) <= MAX_HASH_DISTANCE {
+                links.push((a, b, MatchReason::Content));
+            }
+        }
+    }
+
+    group(summaries.len(), links)
+}
+
+/// Join linked indices into groups (union-find)
+fn group(count: usize, links: Vec<(usize, usize, MatchReason)>) -> Vec<DuplicateGroup> {
+    fn root(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+
+    let mut parents: Vec<usize> = (0..count).collect();
+    for &(a, b, _) in &links {
+        let (a, b) = (root(&mut parents, a), root(&mut parents, b));
+        parents[a.max(b)] = a.min(b);
+    }
+
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    let mut group_of: HashMap<usize, usize> = HashMap::new();
+    for index in 0..count {
+        let root = root(&mut parents, index);
+        let position = *group_of.entry(root).or_insert_with(|| {
+            groups.push(DuplicateGroup {
+                indices: Vec::new(),
+                reasons: Vec::new(),
+            });
+            groups.len() - 1
+        });
+        groups[position].indices.push(index);
+    }
+    for (a, _, reason) in links {
+        let position = group_of[&root(&mut parents, a)];
+        if !groups[position].reasons.contains(&reason) {
+            groups[position].reasons.push(reason);
+        }
+    }
+
+    groups.retain(|group| group.indices.len() > 1);
+    for group in &mut groups {
+        group.reasons.sort();
+    }
+    groups
+}
+
+impl Epub {
+    /// `content_hash` of the first chapter long enough to identify the book
+    ///
+    /// Cover and title pages are skipped by requiring a hundred words.
+    /// `None` for books without such a chapter, including books parsed with
+    /// `ParseOptions::with_metadata_only`.
+    pub fn content_fingerprint(&self) -> Option<u64> {
+        self.chapters
+            .iter()
+            .map(|chapter| {
+                chapter
+                    .get_files()
+                    .iter()
+                    .map(|file| file.get_text())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .find(|text| text.split_whitespace().count() >= MIN_CHAPTER_WORDS)
+            .map(|text| content_hash(&text))
+    }
+}
+
+/// Lowercased alphanumeric words
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// `digits` as an ISBN-13, if it is a valid ISBN-10 or ISBN-13
+fn isbn13(digits: &str) -> Option<String> {
+    let values: Vec<u32> = digits
+        .chars()
+        .map(|c| if c == 'x' { Some(10) } else { c.to_digit(10) })
+        .collect::<Option<_>>()?;
+    match values.len() {
+        13 if values[..12].iter().all(|&v| v < 10) => {
+            let sum: u32 = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| if i % 2 == 0 { *v } else { v * 3 })
+                .sum();
+            sum.is_multiple_of(10).then(|| digits.to_string())
+        }
+        10 if values[..9].iter().all(|&v| v < 10) => {
+            let sum: u32 = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| v * (10 - i as u32))
+                .sum();
+            if !sum.is_multiple_of(11) {
+                return None;
+            }
+            let body = format!("978{}", &digits[..9]);
+            let sum: u32 = body
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .enumerate()
+                .map(|(i, v)| if i % 2 == 0 { v } else { v * 3 })
+                .sum();
+            Some(format!("{}{}", body, (10 - sum % 10) % 10))
+        }
+        _ => None,
+    }
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is the same in every build
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    fn book(title: &str, author: &str, id: &str, text: &str) -> EpubSummary {
+        let opf = format!(
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>{}</dc:identifier><dc:title>{}</dc:title><dc:creator>{}</dc:creator>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#,
+            id, title, author
+        );
+        let chapter = format!("<html><body><p>{}</p></body></html>", text);
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", &chapter),
+        ]))
+        .unwrap()
+        .summary()
+    }
+
+    #[test]
+    fn duplicates_are_grouped_by_any_key() {
+        let text: String = (0..150).map(|i| format!("word{} ", i)).collect();
+        let edited = text.replacen("word70", "wordseventy", 1);
+        let other: String = (0..150).map(|i| format!("other{} ", i)).collect();
+        let summaries = [
+            book(
+                "The Hobbit",
+                "J.R.R. Tolkien",
+                "urn:isbn:0-261-10221-4",
+                &other,
+            ),
+            book("Dune", "Frank Herbert", "urn:uuid:1111-2222", &text),
+            book(
+                "Hobbit (Illustrated)",
+                "Tolkien, J. R. R.",
+                "urn:uuid:3333-4444",
+                "Short",
+            ),
+            book(
+                "There and Back Again",
+                "Bilbo Baggins",
+                "9780261102217",
+                "Short",
+            ),
+            book("Unrelated", "Someone Else", "urn:uuid:5555-6666", &edited),
+        ];
+
+        assert_eq!(
+            normalize_identifier("urn:isbn:0-261-10221-4").as_deref(),
+            Some("9780261102217")
+        );
+        let groups = find_duplicates(&summaries);
+        let groups: Vec<(&[usize], &[MatchReason])> = groups
+            .iter()
+            .map(|group| (group.get_indices(), group.get_reasons()))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (
+                    &[0, 2, 3][..],
+                    &[MatchReason::Identifier, MatchReason::TitleAndAuthor][..]
+                ),
+                (&[1, 4][..], &[MatchReason::Content][..]),
+            ]
+        );
+    }
+}
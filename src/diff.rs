@@ -0,0 +1,296 @@
+//! Word-level diffs of chapter text
+//!
+//! `Chapter::diff` compares the extracted text of two chapters, for example
+//! the same chapter in two editions of a book. Words are compared exactly,
+//! so a changed word shows up as a deletion followed by an insertion.
+//! Whitespace and markup changes don't count.
+
+use crate::epub::Chapter;
+
+/// Whether a change adds or removes text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insertion,
+    Deletion,
+}
+
+/// A run of consecutive words inserted or deleted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChange {
+    kind: ChangeKind,
+    offset: usize,
+    text: String,
+}
+
+impl TextChange {
+    pub fn get_kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    /// Character offset of the change in the old text for deletions, or in
+    /// the new text for insertions
+    pub fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The words, with the whitespace between them as it appears in the text
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// The changes between two texts, in reading order
+#[derive(Debug, Clone, Default)]
+pub struct TextDiff {
+    changes: Vec<TextChange>,
+    words_inserted: usize,
+    words_deleted: usize,
+}
+
+impl TextDiff {
+    pub fn get_changes(&self) -> &[TextChange] {
+        &self.changes
+    }
+
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn get_words_inserted(&self) -> usize {
+        self.words_inserted
+    }
+
+    pub fn get_words_deleted(&self) -> usize {
+        self.words_deleted
+    }
+}
+
+impl Chapter {
+    /// Word-level diff from this chapter's text to `other`'s
+    ///
+    /// Offsets refer to the text returned by `get_text`.
+    pub fn diff(&self, other: &Chapter) -> TextDiff {
+        diff_text(&self.get_text(), &other.get_text())
+    }
+}
+
+/// Word-level diff between two plain texts
+pub fn diff_text(old: &str, new: &str) -> TextDiff {
+    let old_words = words(old);
+    let new_words = words(new);
+    let a: Vec<&str> = old_words.iter().map(|word| word.text).collect();
+    let b: Vec<&str> = new_words.iter().map(|word| word.text).collect();
+
+    // Common leading and trailing words are cheap to skip and usually most of the text
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let script = edit_script(&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut diff = TextDiff::default();
+    let mut run: Option<(ChangeKind, usize, usize)> = None;
+    for edit in script.into_iter().map(|edit| match edit {
+        Edit::Delete(i) => Some((ChangeKind::Deletion, prefix + i)),
+        Edit::Insert(j) => Some((ChangeKind::Insertion, prefix + j)),
+        Edit::Keep => None,
+    }) {
+        match (&mut run, edit) {
+            (Some((kind, _, last)), Some((next_kind, index)))
+                if *kind == next_kind && *last + 1 == index =>
+            {
+                *last = index;
+            }
+            (_, edit) => {
+                if let Some(finished) = run.take() {
+                    diff.push(finished, old, &old_words, new, &new_words);
+                }
+                run = edit.map(|(kind, index)| (kind, index, index));
+            }
+        }
+    }
+    if let Some(finished) = run {
+        diff.push(finished, old, &old_words, new, &new_words);
+    }
+    diff
+}
+
+impl TextDiff {
+    /// Record the words `first..=last` of the old or new text as one change
+    fn push(
+        &mut self,
+        (kind, first, last): (ChangeKind, usize, usize),
+        old: &str,
+        old_words: &[Word],
+        new: &str,
+        new_words: &[Word],
+    ) {
+        let (text, words) = match kind {
+            ChangeKind::Deletion => {
+                self.words_deleted += last - first + 1;
+                (old, old_words)
+            }
+            ChangeKind::Insertion => {
+                self.words_inserted += last - first + 1;
+                (new, new_words)
+            }
+        };
+        let start = words[first].byte_offset;
+        let end = words[last].byte_offset + words[last].text.len();
+        self.changes.push(TextChange {
+            kind,
+            offset: words[first].offset,
+            text: text[start..end].to_string(),
+        });
+    }
+}
+
+/// A whitespace-separated word and where it starts
+struct Word<'a> {
+    text: &'a str,
+    offset: usize,
+    byte_offset: usize,
+}
+
+fn words(text: &str) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut start: Option<(usize, usize)> = None;
+    for (offset, (byte_offset, c)) in text.char_indices().enumerate() {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some((offset, byte_offset)),
+            (Some((offset, word_start)), true) => {
+                words.push(Word {
+                    text: &text[word_start..byte_offset],
+                    offset,
+                    byte_offset: word_start,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((offset, word_start)) = start {
+        words.push(Word {
+            text: &text[word_start..],
+            offset,
+            byte_offset: word_start,
+        });
+    }
+    words
+}
+
+/// One step of an edit script, with indices into the old or new sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Keep,
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script from `a` to `b` (Myers' O(ND) algorithm)
+fn edit_script(a: &[&str], b: &[&str]) -> Vec<Edit> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let index = |k: isize| (k + max) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut script = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = v[index(previous_k)];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            script.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == previous_x {
+                script.push(Edit::Insert((y - 1) as usize));
+            } else {
+                script.push(Edit::Delete((x - 1) as usize));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    script.reverse();
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_words_are_reported_with_offsets() {
+        let diff = diff_text(
+            "It was the best of times, it was the worst of times.",
+            "It was the best of times, it was truly the   worst of   all times.\nThe end.",
+        );
+        let changes: Vec<(ChangeKind, usize, &str)> = diff
+            .get_changes()
+            .iter()
+            .map(|change| (change.get_kind(), change.get_offset(), change.get_text()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (ChangeKind::Insertion, 33, "truly"),
+                (ChangeKind::Insertion, 56, "all"),
+                (ChangeKind::Insertion, 67, "The end."),
+            ]
+        );
+        assert_eq!(
+            (diff.get_words_inserted(), diff.get_words_deleted()),
+            (4, 0)
+        );
+
+        let diff = diff_text("a quick brown fox", "a slow brown fox");
+        let changes: Vec<(ChangeKind, usize, &str)> = diff
+            .get_changes()
+            .iter()
+            .map(|change| (change.get_kind(), change.get_offset(), change.get_text()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (ChangeKind::Deletion, 2, "quick"),
+                (ChangeKind::Insertion, 2, "slow"),
+            ]
+        );
+        assert!(diff_text("same  words", "same\nwords").is_unchanged());
+        assert_eq!(diff_text("", "new").get_changes().len(), 1);
+    }
+}
@@ -0,0 +1,617 @@
+//! A parsed tree for each content document
+//!
+//! Most of the library reads XHTML with small regex scanners, which is fast
+//! for one question about a document but means every question parses the
+//! string again. `Epub::get_dom` parses a document once and keeps the tree,
+//! so a text extractor, a link audit and a sanitizer can all walk the same
+//! `Document`. With this feature on, the library's own link extraction and
+//! character inventories read these cached trees too.
+//!
+//! XHTML documents are parsed as XML, as reading systems do: to html5ever,
+//! the HTML5 parser browsers use, `<div/>` is an unclosed `<div>` that
+//! swallows the rest of the page. HTML documents, and XHTML that isn't
+//! well-formed, go through html5ever.
+//!
+//! The tree is a flat arena of nodes, so it is `Send + Sync` like `Epub`
+//! and cheap to share behind an `Arc`. Cached trees are checked against a
+//! hash of the document's current content, so edits made after a tree was
+//! built are never hidden by it. Behind the `dom` feature.
+
+use crate::epub::{Epub, EpubFile};
+use crate::text::decode_entities;
+use html5ever::interface::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::{Attribute, QualName, parse_document};
+use quick_xml::Reader;
+use quick_xml::events::{BytesStart, Event};
+use std::borrow::Cow;
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+/// Elements whose text isn't part of the readable text
+const NON_TEXT: &[&str] = &["head", "script", "style", "template"];
+
+/// What a `NodeRef` is
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NodeData {
+    Document,
+    Element {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    Text(String),
+    Comment(String),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    data: NodeData,
+}
+
+/// A parsed content document
+#[derive(Debug, Clone)]
+pub struct Document {
+    nodes: Vec<Node>,
+}
+
+impl Document {
+    /// Parse HTML or XHTML the way a browser would
+    pub fn parse(html: &str) -> Self {
+        let sink = Sink {
+            nodes: RefCell::new(vec![Node {
+                parent: None,
+                children: Vec::new(),
+                data: NodeData::Document,
+            }]),
+            names: RefCell::default(),
+        };
+        parse_document(sink, Default::default()).one(html)
+    }
+
+    /// Parse XHTML as XML, falling back to `parse` when it isn't well-formed
+    pub fn parse_xml(xhtml: &str) -> Self {
+        Self::parse_well_formed(xhtml).unwrap_or_else(|| Self::parse(xhtml))
+    }
+
+    fn parse_well_formed(xhtml: &str) -> Option<Self> {
+        let mut document = Document {
+            nodes: vec![Node {
+                parent: None,
+                children: Vec::new(),
+                data: NodeData::Document,
+            }],
+        };
+        let mut open = vec![0];
+        let mut reader = Reader::from_str(xhtml);
+        loop {
+            let parent = *open.last()?;
+            match reader.read_event().ok()? {
+                Event::Start(start) => {
+                    let element = document.append(parent, xml_element(&start)?);
+                    open.push(element);
+                }
+                Event::Empty(start) => {
+                    document.append(parent, xml_element(&start)?);
+                }
+                Event::End(_) => {
+                    open.pop();
+                }
+                Event::Text(text) => {
+                    let text = decode_entities(&String::from_utf8_lossy(&text));
+                    if parent != 0 || !text.trim().is_empty() {
+                        document.append_text(parent, &text);
+                    }
+                }
+                Event::CData(text) => document.append_text(parent, &String::from_utf8_lossy(&text)),
+                Event::Comment(text) => {
+                    let comment = NodeData::Comment(String::from_utf8_lossy(&text).into_owned());
+                    document.append(parent, comment);
+                }
+                Event::Decl(_) | Event::PI(_) | Event::DocType(_) => {}
+                Event::Eof => break,
+            }
+        }
+        (open.len() == 1).then_some(document)
+    }
+
+    /// Add a node as the last child of `parent`
+    fn append(&mut self, parent: usize, data: NodeData) -> usize {
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            parent: Some(parent),
+            children: Vec::new(),
+            data,
+        });
+        self.nodes[parent].children.push(index);
+        index
+    }
+
+    /// Add text to `parent`, merging it into a text node just before it
+    fn append_text(&mut self, parent: usize, text: &str) {
+        if let Some(&last) = self.nodes[parent].children.last()
+            && let NodeData::Text(existing) = &mut self.nodes[last].data
+        {
+            existing.push_str(text);
+        } else {
+            self.append(parent, NodeData::Text(text.to_string()));
+        }
+    }
+
+    /// The document node, parent of `<html>`
+    pub fn root(&self) -> NodeRef<'_> {
+        self.node(0)
+    }
+
+    pub fn get_body(&self) -> Option<NodeRef<'_>> {
+        self.root()
+            .descendants()
+            .find(|node| node.get_name() == Some("body"))
+    }
+
+    pub fn get_element_by_id(&self, id: &str) -> Option<NodeRef<'_>> {
+        self.root()
+            .descendants()
+            .find(|node| node.get_attribute("id") == Some(id))
+    }
+
+    /// Every element named `name`, in document order
+    pub fn get_elements_by_name(&self, name: &str) -> Vec<NodeRef<'_>> {
+        self.find(|node| node.get_name() == Some(name))
+    }
+
+    /// Every node for which `predicate` holds, in document order
+    pub fn find(&self, mut predicate: impl FnMut(&NodeRef) -> bool) -> Vec<NodeRef<'_>> {
+        self.root()
+            .descendants()
+            .filter(|node| predicate(node))
+            .collect()
+    }
+
+    /// The `href` of every `<a>` and `<area>`, in document order
+    pub fn get_links(&self) -> Vec<&str> {
+        self.root()
+            .descendants()
+            .filter(|node| matches!(node.get_name(), Some("a" | "area")))
+            .filter_map(|node| node.get_attribute("href"))
+            .collect()
+    }
+
+    /// The text of the body, without scripts and styles
+    pub fn get_text(&self) -> String {
+        self.get_body().unwrap_or(self.root()).text_content()
+    }
+
+    fn node(&self, index: usize) -> NodeRef<'_> {
+        NodeRef {
+            document: self,
+            index,
+        }
+    }
+}
+
+/// A node in a `Document`
+#[derive(Debug, Clone, Copy)]
+pub struct NodeRef<'a> {
+    document: &'a Document,
+    index: usize,
+}
+
+impl<'a> NodeRef<'a> {
+    fn data(&self) -> &'a NodeData {
+        &self.document.nodes[self.index].data
+    }
+
+    pub fn is_element(&self) -> bool {
+        matches!(self.data(), NodeData::Element { .. })
+    }
+
+    /// The lowercase element name, e.g. `p` or `epub:switch`; `None` for
+    /// other nodes
+    pub fn get_name(&self) -> Option<&'a str> {
+        match self.data() {
+            NodeData::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    pub fn get_attributes(&self) -> &'a [(String, String)] {
+        match self.data() {
+            NodeData::Element { attributes, .. } => attributes,
+            _ => &[],
+        }
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&'a str> {
+        self.get_attributes()
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the space-separated `class` attribute lists `class`
+    pub fn has_class(&self, class: &str) -> bool {
+        self.get_attribute("class")
+            .is_some_and(|value| value.split_whitespace().any(|c| c == class))
+    }
+
+    /// The text of a text node
+    pub fn get_text(&self) -> Option<&'a str> {
+        match self.data() {
+            NodeData::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// The text of a comment node
+    pub fn get_comment(&self) -> Option<&'a str> {
+        match self.data() {
+            NodeData::Comment(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// All the text under this node, skipping scripts, styles and `<head>`
+    pub fn text_content(&self) -> String {
+        let mut text = String::new();
+        self.collect_text(&mut text);
+        text
+    }
+
+    fn collect_text(&self, text: &mut String) {
+        match self.data() {
+            NodeData::Text(content) => text.push_str(content),
+            NodeData::Element { name, .. } if NON_TEXT.contains(&name.as_str()) => {}
+            _ => self.children().for_each(|child| child.collect_text(text)),
+        }
+    }
+
+    pub fn parent(&self) -> Option<NodeRef<'a>> {
+        let parent = self.document.nodes[self.index].parent?;
+        Some(self.document.node(parent))
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = NodeRef<'a>> + use<'a> {
+        let document = self.document;
+        document.nodes[self.index]
+            .children
+            .iter()
+            .map(move |&child| document.node(child))
+    }
+
+    /// Child elements, skipping text and comments
+    pub fn child_elements(&self) -> impl Iterator<Item = NodeRef<'a>> + use<'a> {
+        self.children().filter(NodeRef::is_element)
+    }
+
+    /// Every node below this one, in document order
+    pub fn descendants(&self) -> impl Iterator<Item = NodeRef<'a>> + use<'a> {
+        let document = self.document;
+        let mut stack: Vec<usize> = document.nodes[self.index]
+            .children
+            .iter()
+            .rev()
+            .copied()
+            .collect();
+        std::iter::from_fn(move || {
+            let index = stack.pop()?;
+            stack.extend(document.nodes[index].children.iter().rev());
+            Some(document.node(index))
+        })
+    }
+}
+
+/// A parsed document and the hash of the content it was parsed from
+type CachedDocument = (u64, Arc<Document>);
+
+/// Parsed documents by href, shared by clones of an `Epub`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DomCache(Arc<Mutex<HashMap<String, CachedDocument>>>);
+
+impl Epub {
+    /// The parsed tree of the content document at `href`
+    ///
+    /// Parsed on first use and kept until the document changes.
+    pub fn get_dom(&self, href: &str) -> Option<Arc<Document>> {
+        let file = self.all_files.iter().find(|file| &*file.href == href)?;
+        Some(self.file_dom(file))
+    }
+
+    /// The parsed tree of one of this book's files, through the cache
+    pub(crate) fn file_dom(&self, file: &EpubFile) -> Arc<Document> {
+        let mut hasher = DefaultHasher::new();
+        file.content.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let mut cache = self.dom_cache.0.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((cached, document)) = cache.get(&*file.href)
+            && *cached == hash
+        {
+            return document.clone();
+        }
+        let document = Arc::new(file.parse_dom());
+        cache.insert(file.href.to_string(), (hash, document.clone()));
+        document
+    }
+}
+
+impl EpubFile {
+    /// Parse this file into a `Document`, without caching it
+    ///
+    /// XHTML is parsed as XML (see `Document::parse_xml`), HTML as HTML.
+    pub fn parse_dom(&self) -> Document {
+        if &*self.media_type == "application/xhtml+xml" {
+            Document::parse_xml(&self.content)
+        } else {
+            Document::parse(&self.content)
+        }
+    }
+}
+
+/// An element from an XML start tag; `None` for malformed attributes
+fn xml_element(start: &BytesStart) -> Option<NodeData> {
+    let attributes = start
+        .attributes()
+        .map(|attribute| {
+            let attribute = attribute.ok()?;
+            Some((
+                String::from_utf8_lossy(attribute.key.as_ref()).into_owned(),
+                decode_entities(&String::from_utf8_lossy(&attribute.value)),
+            ))
+        })
+        .collect::<Option<_>>()?;
+    Some(NodeData::Element {
+        name: String::from_utf8_lossy(start.name().as_ref()).into_owned(),
+        attributes,
+    })
+}
+
+/// Builds the arena as html5ever parses
+struct Sink {
+    nodes: RefCell<Vec<Node>>,
+    /// Full element names, which html5ever asks for again while parsing
+    names: RefCell<HashMap<usize, QualName>>,
+}
+
+impl Sink {
+    fn push(&self, data: NodeData) -> usize {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(Node {
+            parent: None,
+            children: Vec::new(),
+            data,
+        });
+        nodes.len() - 1
+    }
+
+    /// Insert `child` into `parent`'s children at `position`, merging text
+    /// into a text node just before it
+    fn insert(&self, parent: usize, position: usize, child: NodeOrText<usize>) {
+        let child = match child {
+            NodeOrText::AppendNode(node) => node,
+            NodeOrText::AppendText(text) => {
+                let mut nodes = self.nodes.borrow_mut();
+                let previous = position
+                    .checked_sub(1)
+                    .map(|before| nodes[parent].children[before]);
+                if let Some(previous) = previous
+                    && let NodeData::Text(existing) = &mut nodes[previous].data
+                {
+                    existing.push_str(&text);
+                    return;
+                }
+                drop(nodes);
+                self.push(NodeData::Text(text.to_string()))
+            }
+        };
+        self.remove_from_parent(&child);
+        let mut nodes = self.nodes.borrow_mut();
+        nodes[child].parent = Some(parent);
+        nodes[parent].children.insert(position, child);
+    }
+}
+
+fn attribute_pairs(attributes: Vec<Attribute>) -> impl Iterator<Item = (String, String)> {
+    attributes.into_iter().map(|attribute| {
+        let name = match &attribute.name.prefix {
+            Some(prefix) => format!("{}:{}", prefix, attribute.name.local),
+            None => attribute.name.local.to_string(),
+        };
+        (name, attribute.value.to_string())
+    })
+}
+
+impl TreeSink for Sink {
+    type Handle = usize;
+    type Output = Document;
+    type ElemName<'a> = Ref<'a, QualName>;
+
+    fn finish(self) -> Document {
+        Document {
+            nodes: self.nodes.into_inner(),
+        }
+    }
+
+    fn parse_error(&self, _msg: Cow<'static, str>) {}
+
+    fn get_document(&self) -> usize {
+        0
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a usize) -> Ref<'a, QualName> {
+        Ref::map(self.names.borrow(), |names| &names[target])
+    }
+
+    fn create_element(&self, name: QualName, attrs: Vec<Attribute>, _: ElementFlags) -> usize {
+        let index = self.push(NodeData::Element {
+            name: name.local.to_string(),
+            attributes: attribute_pairs(attrs).collect(),
+        });
+        self.names.borrow_mut().insert(index, name);
+        index
+    }
+
+    fn create_comment(&self, text: StrTendril) -> usize {
+        self.push(NodeData::Comment(text.to_string()))
+    }
+
+    fn create_pi(&self, _target: StrTendril, data: StrTendril) -> usize {
+        self.push(NodeData::Comment(data.to_string()))
+    }
+
+    fn append(&self, parent: &usize, child: NodeOrText<usize>) {
+        let position = self.nodes.borrow()[*parent].children.len();
+        self.insert(*parent, position, child);
+    }
+
+    fn append_based_on_parent_node(
+        &self,
+        element: &usize,
+        prev_element: &usize,
+        child: NodeOrText<usize>,
+    ) {
+        if self.nodes.borrow()[*element].parent.is_some() {
+            self.append_before_sibling(element, child);
+        } else {
+            self.append(prev_element, child);
+        }
+    }
+
+    fn append_doctype_to_document(&self, _: StrTendril, _: StrTendril, _: StrTendril) {}
+
+    fn get_template_contents(&self, target: &usize) -> usize {
+        *target
+    }
+
+    fn same_node(&self, x: &usize, y: &usize) -> bool {
+        x == y
+    }
+
+    fn set_quirks_mode(&self, _mode: QuirksMode) {}
+
+    fn append_before_sibling(&self, sibling: &usize, new_node: NodeOrText<usize>) {
+        let Some(parent) = self.nodes.borrow()[*sibling].parent else {
+            return;
+        };
+        let position = self.nodes.borrow()[parent]
+            .children
+            .iter()
+            .position(|child| child == sibling)
+            .unwrap_or(0);
+        self.insert(parent, position, new_node);
+    }
+
+    fn add_attrs_if_missing(&self, target: &usize, attrs: Vec<Attribute>) {
+        if let NodeData::Element { attributes, .. } = &mut self.nodes.borrow_mut()[*target].data {
+            for (name, value) in attribute_pairs(attrs) {
+                if !attributes.iter().any(|(existing, _)| *existing == name) {
+                    attributes.push((name, value));
+                }
+            }
+        }
+    }
+
+    fn remove_from_parent(&self, target: &usize) {
+        let mut nodes = self.nodes.borrow_mut();
+        if let Some(parent) = nodes[*target].parent.take() {
+            nodes[parent].children.retain(|child| child != target);
+        }
+    }
+
+    fn reparent_children(&self, node: &usize, new_parent: &usize) {
+        let mut nodes = self.nodes.borrow_mut();
+        let children = std::mem::take(&mut nodes[*node].children);
+        for &child in &children {
+            nodes[child].parent = Some(*new_parent);
+        }
+        nodes[*new_parent].children.extend(children);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn documents_are_parsed_once_and_queried() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<?xml version="1.0" encoding="utf-8"?>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>One</title><style>p { color: red }</style></head>
+<body><h1 id="top" class="title main">Chapter One</h1>
+<p>Call me <a href="ch2.xhtml#ishmael">Ishmael</a>.<br/>Some years ago</p>
+<epub:switch><epub:default><p>Fallback</p></epub:default></epub:switch>
+</body></html>"#;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let dom = epub.get_dom("ch1.xhtml").unwrap();
+        assert!(Arc::ptr_eq(&dom, &epub.get_dom("ch1.xhtml").unwrap()));
+        let heading = dom.get_element_by_id("top").unwrap();
+        assert_eq!(heading.get_name(), Some("h1"));
+        assert!(heading.has_class("main"));
+        assert_eq!(heading.parent().unwrap().get_name(), Some("body"));
+        assert_eq!(dom.get_links(), ["ch2.xhtml#ishmael"]);
+        assert_eq!(dom.get_elements_by_name("epub:switch").len(), 1);
+        let paragraph = dom.get_elements_by_name("p")[0];
+        assert_eq!(paragraph.text_content(), "Call me Ishmael.Some years ago");
+        assert_eq!(paragraph.child_elements().count(), 2);
+        assert!(!dom.get_text().contains("color"));
+
+        epub.put_resource_bytes(
+            "ch1.xhtml",
+            b"<html><body><p>New</p></body></html>".to_vec(),
+        );
+        let edited = epub.get_dom("ch1.xhtml").unwrap();
+        assert_eq!(edited.get_text(), "New");
+        assert!(epub.get_dom("missing.xhtml").is_none());
+    }
+
+    #[test]
+    fn xhtml_is_parsed_as_xml() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.html" media-type="text/html"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let chapter = r#"<?xml version="1.0"?>
+<html xmlns="http://www.w3.org/1999/xhtml"><body><div id="anchor"/><p>Fish &amp; chips&nbsp;<![CDATA[<raw>]]></p></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+            ("ch2.html", chapter),
+        ]))
+        .unwrap();
+
+        // As XML the div is empty; as HTML it swallows the paragraph
+        let xhtml = epub.get_dom("ch1.xhtml").unwrap();
+        let div = xhtml.get_element_by_id("anchor").unwrap();
+        assert_eq!(div.children().count(), 0);
+        assert_eq!(div.parent().unwrap().get_name(), Some("body"));
+        assert_eq!(xhtml.get_text(), "Fish & chips\u{a0}<raw>");
+        let html = epub.get_dom("ch2.html").unwrap();
+        assert_eq!(
+            html.get_element_by_id("anchor")
+                .unwrap()
+                .child_elements()
+                .count(),
+            1
+        );
+
+        // Markup that isn't well-formed falls back to the HTML parser
+        let broken = Document::parse_xml("<html><body><p>One<br></p></body></html>");
+        assert_eq!(broken.get_text(), "One");
+    }
+}
@@ -0,0 +1,218 @@
+//! DRM detection
+//!
+//! Encrypted books open fine (the container and OPF are plain) but their
+//! content documents read as garbage. `Epub::drm` tells such books apart
+//! from damaged ones, so an app can send the reader back to the store or
+//! library that issued the book instead of showing decode errors.
+//!
+//! Resources listed in `META-INF/encryption.xml` with anything other than
+//! the font obfuscation algorithms count as encrypted. Adobe ADEPT books
+//! (also used by Barnes & Noble and most library lending) carry a
+//! `META-INF/rights.xml` license token naming the operator that issued it;
+//! Apple FairPlay books carry `META-INF/sinf.xml`.
+
+use crate::epub::Epub;
+use crate::text::decode_entities;
+use crate::xhtml::{Element, find_elements};
+
+/// Algorithms that only obfuscate fonts, per the IDPF and Adobe
+pub(crate) const FONT_OBFUSCATION_ALGORITHMS: &[&str] = &[
+    "http://www.idpf.org/2008/embedding",
+    "http://ns.adobe.com/pdf/enc#RC",
+];
+
+/// Who encrypted a book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrmScheme {
+    /// Adobe Content Server (ADEPT), with a license in `rights.xml`
+    Adept,
+    /// Apple FairPlay, with a license in `sinf.xml`
+    FairPlay,
+    /// Encrypted resources without a license this crate recognises
+    Unknown,
+}
+
+/// A resource listed in `encryption.xml`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedResource {
+    path: String,
+    algorithm: String,
+}
+
+impl EncryptedResource {
+    /// Archive path of the resource
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    /// Algorithm URI, e.g. `http://www.w3.org/2001/04/xmlenc#aes128-cbc`
+    pub fn get_algorithm(&self) -> &str {
+        &self.algorithm
+    }
+}
+
+/// How a book is protected, from `Epub::drm`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrmInfo {
+    scheme: DrmScheme,
+    operator_url: Option<String>,
+    resource_id: Option<String>,
+    encrypted: Vec<EncryptedResource>,
+}
+
+impl DrmInfo {
+    pub fn get_scheme(&self) -> DrmScheme {
+        self.scheme
+    }
+
+    /// The ADEPT operator (fulfillment server) that issued the license
+    pub fn get_operator_url(&self) -> Option<&str> {
+        self.operator_url.as_deref()
+    }
+
+    /// The ADEPT resource id, usually a `urn:uuid:`
+    pub fn get_resource_id(&self) -> Option<&str> {
+        self.resource_id.as_deref()
+    }
+
+    /// Resources encrypted with something other than font obfuscation
+    pub fn get_encrypted_resources(&self) -> &[EncryptedResource] {
+        &self.encrypted
+    }
+}
+
+impl Epub {
+    /// How the book is protected, or `None` if it isn't
+    ///
+    /// Obfuscated fonts alone don't count.
+    pub fn drm(&self) -> Option<DrmInfo> {
+        let read = |path: &str| {
+            self.get_resource_bytes(path)
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        };
+        let encrypted: Vec<EncryptedResource> = read("/META-INF/encryption.xml")
+            .map(|xml| encrypted_resources(&xml))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|resource| !FONT_OBFUSCATION_ALGORITHMS.contains(&resource.algorithm.as_str()))
+            .collect();
+        let rights = read("/META-INF/rights.xml");
+        let license = |name: &str| {
+            let rights = rights.as_deref()?;
+            find_elements(rights, |e| local_name(e) == name)
+                .first()
+                .map(|element| decode_entities(element.inner.trim()))
+                .filter(|value| !value.is_empty())
+        };
+
+        let scheme = if rights
+            .as_deref()
+            .is_some_and(|xml| xml.contains("http://ns.adobe.com/adept"))
+        {
+            DrmScheme::Adept
+        } else if self.get_resource_bytes("/META-INF/sinf.xml").is_some() {
+            DrmScheme::FairPlay
+        } else if !encrypted.is_empty() {
+            DrmScheme::Unknown
+        } else {
+            return None;
+        };
+        Some(DrmInfo {
+            scheme,
+            operator_url: license("operatorurl"),
+            resource_id: license("resource"),
+            encrypted,
+        })
+    }
+}
+
+/// Every `<EncryptedData>` in `encryption.xml` as a resource and algorithm
+pub(crate) fn encrypted_resources(xml: &str) -> Vec<EncryptedResource> {
+    let mut resources = Vec::new();
+    for data in find_elements(xml, |e| local_name(e) == "encrypteddata") {
+        let algorithm = find_elements(data.inner, |e| local_name(e) == "encryptionmethod")
+            .first()
+            .and_then(|method| method.attribute("algorithm").map(str::to_string))
+            .unwrap_or_default();
+        for reference in find_elements(data.inner, |e| local_name(e) == "cipherreference") {
+            if let Some(uri) = reference.attribute("uri") {
+                resources.push(EncryptedResource {
+                    path: crate::href::percent_decode(uri),
+                    algorithm: algorithm.clone(),
+                });
+            }
+        }
+    }
+    resources
+}
+
+/// An element's name without its namespace prefix
+fn local_name<'a>(element: &'a Element) -> &'a str {
+    element
+        .name
+        .rsplit_once(':')
+        .map_or(element.name.as_str(), |(_, local)| local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn adept_licenses_are_reported() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="font" href="font.otf" media-type="font/otf"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let encryption = r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container" xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+  <enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+    <enc:CipherData><enc:CipherReference URI="font.otf"/></enc:CipherData>
+  </enc:EncryptedData>
+  <enc:EncryptedData>
+    <enc:EncryptionMethod Algorithm="http://www.w3.org/2001/04/xmlenc#aes128-cbc"/>
+    <enc:CipherData><enc:CipherReference URI="ch1.xhtml"/></enc:CipherData>
+  </enc:EncryptedData>
+</encryption>"#;
+        let rights = r#"<adept:rights xmlns:adept="http://ns.adobe.com/adept">
+  <adept:licenseToken>
+    <adept:resource>urn:uuid:0b7d3c9e-1f2a-4b5c-8d6e-7f8091a2b3c4</adept:resource>
+    <adept:operatorURL>https://acs.example.com/fulfillment</adept:operatorURL>
+  </adept:licenseToken>
+</adept:rights>"#;
+        let files = [
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf.as_str()),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("font.otf", "OTTO"),
+        ];
+        let epub = Epub::from_bytes(build(&files)).unwrap();
+        assert_eq!(epub.drm(), None);
+
+        let mut obfuscated = files.to_vec();
+        obfuscated.push(("META-INF/encryption.xml", encryption));
+        let epub = Epub::from_bytes(build(&obfuscated)).unwrap();
+        assert_eq!(epub.drm().unwrap().get_scheme(), DrmScheme::Unknown);
+
+        obfuscated.push(("META-INF/rights.xml", rights));
+        let drm = Epub::from_bytes(build(&obfuscated)).unwrap().drm().unwrap();
+        assert_eq!(drm.get_scheme(), DrmScheme::Adept);
+        assert_eq!(
+            drm.get_operator_url(),
+            Some("https://acs.example.com/fulfillment")
+        );
+        assert_eq!(
+            drm.get_resource_id(),
+            Some("urn:uuid:0b7d3c9e-1f2a-4b5c-8d6e-7f8091a2b3c4")
+        );
+        assert_eq!(
+            drm.get_encrypted_resources(),
+            [EncryptedResource {
+                path: "ch1.xhtml".to_string(),
+                algorithm: "http://www.w3.org/2001/04/xmlenc#aes128-cbc".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,935 @@
+//! Editing a parsed book
+//!
+//! Edits are applied to the in-memory `Epub` and written out with
+//! `Epub::save`. Metadata goes through `Epub::edit_metadata`, whose setters
+//! refuse values that would make the package invalid and keep the manifest in
+//! step (for example when the cover image changes). Resources are added,
+//! renamed and removed directly on the `Epub`; renaming rewrites the
+//! references other documents make to the resource.
+
+use crate::epub::{
+    Chapter, Creator, Epub, EpubFile, Resource, Title, TitleType, is_content_media_type,
+};
+use crate::error::EpubError;
+use crate::href;
+use crate::package::MetaEntry;
+use crate::refs::{self, REFERENCING_MEDIA_TYPES};
+use crate::semantics::{self, Landmark, SemanticRole};
+use crate::xhtml;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Image types EPUB reading systems are required to support
+pub(crate) const COVER_MEDIA_TYPES: &[(&str, &str)] = &[
+    ("image/jpeg", "jpg"),
+    ("image/png", "png"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/svg+xml", "svg"),
+];
+
+/// Mutable handle to a book's metadata, from `Epub::edit_metadata`
+pub struct MetadataEditor<'a> {
+    epub: &'a mut Epub,
+}
+
+impl Epub {
+    /// Start editing the book's metadata
+    pub fn edit_metadata(&mut self) -> MetadataEditor<'_> {
+        self.text_index_cache.invalidate();
+        MetadataEditor { epub: self }
+    }
+
+    /// Replace (or add) the contents of a resource, given its href relative to the OPF
+    ///
+    /// The manifest isn't touched; content documents are re-read so
+    /// `get_all_files` and `get_chapters` reflect the new markup.
+    pub(crate) fn put_resource_bytes(&mut self, href: &str, bytes: Vec<u8>) {
+        self.text_index_cache.invalidate();
+        if let Ok(content) = String::from_utf8(bytes.clone()) {
+            let files = self.all_files.iter_mut().chain(
+                self.chapters
+                    .iter_mut()
+                    .flat_map(|chapter| &mut chapter.files),
+            );
+            for file in files.filter(|file| &*file.href == href) {
+                file.content = content.clone();
+            }
+        }
+        let path = Self::resolve_path(&self.opf_path, href);
+        self.overrides.insert(path, bytes);
+    }
+
+    /// Replace the cover image and keep everything that points at it in sync
+    ///
+    /// Shorthand for `edit_metadata().set_cover(bytes, media_type)`. Call
+    /// `regenerate_cover_page` afterwards to also rebuild the cover page.
+    pub fn set_cover_image(&mut self, bytes: Vec<u8>, media_type: &str) -> Result<(), EpubError> {
+        self.edit_metadata().set_cover(bytes, media_type)
+    }
+
+    /// Move the cover image to an href whose extension matches its type
+    ///
+    /// `cover.jpg` becomes `cover.png`, and every document that referenced
+    /// it, such as the cover page, is updated.
+    fn match_cover_extension(&mut self, extension: &str) -> Result<(), EpubError> {
+        let Some(cover) = self.cover_resource() else {
+            return Ok(());
+        };
+        let old_href = cover.href.clone();
+        let current = old_href
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        let matches = match current.as_deref() {
+            Some("jpeg") => extension == "jpg",
+            Some(ext) => ext == extension,
+            None => false,
+        };
+        if !matches {
+            let stem = old_href
+                .rsplit_once('.')
+                .map_or(old_href.as_str(), |(stem, _)| stem);
+            let new_href = self.unique_resource_href(stem, extension);
+            self.rename_resource(&old_href, &new_href)?;
+        }
+        Ok(())
+    }
+
+    /// Write a cover page showing the current cover image
+    ///
+    /// The existing cover page (the spine document classified as the cover)
+    /// is overwritten; if there is none, `cover.xhtml` is created, put at
+    /// the start of the spine and given a `cover` landmark.
+    pub fn regenerate_cover_page(&mut self) -> Result<(), EpubError> {
+        let Some(cover) = self.cover_resource() else {
+            return Err(EpubError::InvalidMetadata(
+                "the book has no cover image".to_string(),
+            ));
+        };
+        let image_path = Self::resolve_path(&self.opf_path, &cover.href);
+
+        let existing = self
+            .get_spine_files()
+            .into_iter()
+            .find(|file| file.semantic_role == SemanticRole::Cover)
+            .map(|file| file.href.to_string());
+        let page_href = match existing {
+            Some(href) => href,
+            None => self.insert_cover_page(),
+        };
+
+        let page_path = Self::resolve_path(&self.opf_path, &page_href);
+        let page = xhtml::cover_page(
+            self.metadata.language.as_deref().unwrap_or("und"),
+            &href::relative(&page_path, &image_path),
+        );
+        self.put_resource_bytes(&page_href, page.into_bytes());
+        Ok(())
+    }
+
+    /// Add an empty cover page at the start of the spine and return its href
+    fn insert_cover_page(&mut self) -> String {
+        let id = self.unique_resource_id("cover-page");
+        let href = self.unique_resource_href("cover", "xhtml");
+        self.resources
+            .push(Resource::new(&id, &href, "application/xhtml+xml", None));
+
+        self.spine.insert(0, id.clone());
+        self.reindex_spine();
+
+        let file = EpubFile {
+            id: id.into(),
+            href: href.as_str().into(),
+            title: None,
+            content: String::new(),
+            media_type: "application/xhtml+xml".into(),
+            spine_index: Some(0),
+            semantic_role: SemanticRole::Cover,
+        };
+        self.all_files.push(file.clone());
+        self.chapters.insert(
+            0,
+            Chapter {
+                title: "Cover".to_string(),
+                files: vec![file],
+                language: self.metadata.language.clone(),
+            },
+        );
+        if !self
+            .landmarks
+            .iter()
+            .any(|landmark| landmark.kind == "cover")
+        {
+            self.landmarks.insert(0, Landmark::new("cover", &href));
+        }
+        href
+    }
+
+    fn cover_resource(&self) -> Option<&Resource> {
+        self.get_resource_by_id(self.metadata.cover.as_deref()?)
+    }
+
+    /// Add a resource to the manifest, returning its new id
+    ///
+    /// `href` is relative to the OPF. The id is derived from the file name.
+    /// Content documents show up in `get_all_files` but are not added to the
+    /// spine.
+    pub fn add_resource(
+        &mut self,
+        href: &str,
+        media_type: &str,
+        bytes: Vec<u8>,
+    ) -> Result<String, EpubError> {
+        let href = required("href", href.to_string())?;
+        let media_type = required("media type", media_type.to_string())?;
+        if self.get_resource_by_href(&href).is_some() {
+            return Err(EpubError::ResourceExists(href));
+        }
+
+        let id = self.unique_resource_id(&id_from_href(&href));
+        self.resources
+            .push(Resource::new(&id, &href, &media_type, None));
+        if is_content_media_type(&media_type) {
+            let path = Self::resolve_path(&self.opf_path, &href);
+            let content = String::from_utf8_lossy(&bytes).into_owned();
+            let role = semantics::classify(&[(&path, &id, &content, None)], &HashMap::new())[0];
+            self.all_files.push(EpubFile {
+                id: id.as_str().into(),
+                href: href.as_str().into(),
+                title: None,
+                content,
+                media_type: media_type.into(),
+                spine_index: None,
+                semantic_role: role,
+            });
+        }
+        self.removed
+            .remove(&Self::resolve_path(&self.opf_path, &href));
+        self.put_resource_bytes(&href, bytes);
+        Ok(id)
+    }
+
+    /// Remove a resource from the manifest, spine and navigation
+    ///
+    /// Returns the hrefs of documents that still reference it; those links
+    /// will dangle in the saved book, so callers should fix or report them.
+    pub fn remove_resource(&mut self, href: &str) -> Result<Vec<String>, EpubError> {
+        let Some(resource) = self.get_resource_by_href(href) else {
+            return Err(EpubError::UnknownResource(href.to_string()));
+        };
+        let id = resource.id.clone();
+        let path = Self::resolve_path(&self.opf_path, href);
+        self.text_index_cache.invalidate();
+
+        self.resources.retain(|resource| resource.id != id);
+        self.spine.retain(|idref| *idref != id);
+        self.spine_items.remove(&id);
+        self.all_files.retain(|file| *file.id != *id);
+        for chapter in &mut self.chapters {
+            chapter.files.retain(|file| *file.id != *id);
+        }
+        self.chapters.retain(|chapter| !chapter.files.is_empty());
+        self.table_of_contents
+            .entries
+            .retain(|entry| href::strip_fragment(&entry.href) != href);
+        self.landmarks
+            .retain(|landmark| href::strip_fragment(&landmark.href) != href);
+        if self.metadata.cover.as_deref() == Some(id.as_str()) {
+            self.metadata.cover = None;
+        }
+        self.meta.retain(|meta| {
+            meta.name.as_deref() != Some("cover") || meta.content.as_deref() != Some(id.as_str())
+        });
+        self.reindex_spine();
+
+        self.overrides.remove(&path);
+        self.removed.insert(path.clone());
+
+        let dangling: Vec<String> = self
+            .resources
+            .iter()
+            .filter(|resource| REFERENCING_MEDIA_TYPES.contains(&resource.media_type.as_str()))
+            .filter(|resource| {
+                let doc_path = Self::resolve_path(&self.opf_path, &resource.href);
+                self.get_resource_bytes(&resource.href)
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .is_some_and(|content| refs::find(&content, &doc_path).contains(&path))
+            })
+            .map(|resource| resource.href.clone())
+            .collect();
+        if !dangling.is_empty() {
+            trace_warn!(removed = %href, referrers = ?dangling, "removed resource is still referenced");
+        }
+        Ok(dangling)
+    }
+
+    /// Move a resource to `new_href`, rewriting every reference to it
+    ///
+    /// Both hrefs are relative to the OPF. References from content
+    /// documents, stylesheets, SVG and the NCX are rewritten, as are the
+    /// table of contents, chapters and landmarks. A moved document's own
+    /// relative references are rebased too.
+    pub fn rename_resource(&mut self, old_href: &str, new_href: &str) -> Result<(), EpubError> {
+        if self.get_resource_by_href(old_href).is_none() {
+            return Err(EpubError::UnknownResource(old_href.to_string()));
+        }
+        required("href", new_href.to_string())?;
+        if old_href == new_href {
+            return Ok(());
+        }
+        self.text_index_cache.invalidate();
+        if self.get_resource_by_href(new_href).is_some() {
+            return Err(EpubError::ResourceExists(new_href.to_string()));
+        }
+        let old_path = Self::resolve_path(&self.opf_path, old_href);
+        let new_path = Self::resolve_path(&self.opf_path, new_href);
+
+        // Work out the rewritten documents against the current layout first
+        let mut rewritten = Vec::new();
+        for resource in &self.resources {
+            if !REFERENCING_MEDIA_TYPES.contains(&resource.media_type.as_str()) {
+                continue;
+            }
+            let Some(content) = self
+                .get_resource_bytes(&resource.href)
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+            else {
+                continue;
+            };
+            let doc_path = Self::resolve_path(&self.opf_path, &resource.href);
+            let moved = doc_path == old_path;
+            let new_doc_path = if moved {
+                new_path.clone()
+            } else {
+                doc_path.clone()
+            };
+            let rename = |target: &str| {
+                if target == old_path {
+                    Some(new_path.clone())
+                } else {
+                    moved.then(|| target.to_string())
+                }
+            };
+            if let Some(content) = refs::rewrite(&content, &doc_path, &new_doc_path, rename) {
+                let href = if moved {
+                    new_href
+                } else {
+                    resource.href.as_str()
+                };
+                rewritten.push((href.to_string(), content));
+            }
+        }
+
+        // Move the bytes
+        let bytes = self.get_resource_bytes(old_href);
+        self.overrides.remove(&old_path);
+        self.removed.insert(old_path);
+        self.removed.remove(&new_path);
+        if let Some(bytes) = bytes {
+            self.overrides.insert(new_path, bytes);
+        }
+
+        // Update everything that names the resource by href
+        let renamed = |href: &str| {
+            (href::strip_fragment(href) == old_href).then(|| match href::fragment(href) {
+                Some(fragment) => format!("{}#{}", new_href, fragment),
+                None => new_href.to_string(),
+            })
+        };
+        // Files in chapters and the table of contents share the new href
+        let new_file_href: Arc<str> = new_href.into();
+        for resource in &mut self.resources {
+            if let Some(href) = renamed(&resource.href) {
+                resource.href = href;
+            }
+        }
+        for file in self.all_files.iter_mut().chain(
+            self.chapters
+                .iter_mut()
+                .flat_map(|chapter| &mut chapter.files),
+        ) {
+            if *file.href == *old_href {
+                file.href = Arc::clone(&new_file_href);
+            }
+        }
+        for entry in &mut self.table_of_contents.entries {
+            if let Some(href) = renamed(&entry.href) {
+                entry.href = href.into();
+            }
+        }
+        for landmark in &mut self.landmarks {
+            if let Some(href) = renamed(&landmark.href) {
+                landmark.href = href;
+            }
+        }
+
+        for (href, content) in rewritten {
+            self.put_resource_bytes(&href, content.into_bytes());
+        }
+        Ok(())
+    }
+
+    /// Point every file's `spine_index` at its first position in the current spine
+    pub(crate) fn reindex_spine(&mut self) {
+        self.text_index_cache.invalidate();
+        let spine = &self.spine;
+        let index_of = |id: &str| spine.iter().position(|idref| idref == id);
+        for file in &mut self.all_files {
+            file.spine_index = index_of(&file.id);
+        }
+        for chapter in &mut self.chapters {
+            for file in &mut chapter.files {
+                file.spine_index = index_of(&file.id);
+            }
+        }
+    }
+
+    /// A manifest id based on `base` that isn't in use yet
+    pub(crate) fn unique_resource_id(&self, base: &str) -> String {
+        let taken = |id: &str| self.resources.iter().any(|resource| resource.id == id);
+        if !taken(base) {
+            return base.to_string();
+        }
+        (2..)
+            .map(|n| format!("{}-{}", base, n))
+            .find(|id| !taken(id))
+            .unwrap_or_else(|| base.to_string())
+    }
+
+    /// An href `stem.extension` (numbered if needed) that no manifest item or added entry uses yet
+    pub(crate) fn unique_resource_href(&self, stem: &str, extension: &str) -> String {
+        let taken = |href: &str| {
+            self.resources.iter().any(|resource| resource.href == href)
+                || self
+                    .overrides
+                    .contains_key(&Self::resolve_path(&self.opf_path, href))
+        };
+        let first = format!("{}.{}", stem, extension);
+        if !taken(&first) {
+            return first;
+        }
+        (2..)
+            .map(|n| format!("{}-{}.{}", stem, n, extension))
+            .find(|href| !taken(href))
+            .unwrap_or(first)
+    }
+}
+
+impl MetadataEditor<'_> {
+    /// Set the main title
+    ///
+    /// Its old sort key (`file-as` or calibre's `title_sort`) is dropped,
+    /// since it would no longer match.
+    pub fn set_title(&mut self, title: impl Into<String>) -> Result<(), EpubError> {
+        let title = required("title", title.into())?;
+        let titles = &mut self.epub.metadata.titles;
+        let main = titles
+            .iter()
+            .position(|t| t.title_type == Some(TitleType::Main))
+            .unwrap_or(0);
+        let refines = match titles.get_mut(main) {
+            Some(main) => {
+                main.value = title;
+                main.file_as = None;
+                main.id.as_ref().map(|id| format!("#{}", id))
+            }
+            None => {
+                titles.push(Title {
+                    id: None,
+                    value: title,
+                    title_type: None,
+                    file_as: None,
+                });
+                None
+            }
+        };
+        self.epub.meta.retain(|meta| {
+            let file_as = meta.property.as_deref() == Some("file-as")
+                && refines.is_some()
+                && meta.refines == refines;
+            !file_as && meta.name.as_deref() != Some("calibre:title_sort")
+        });
+        Ok(())
+    }
+
+    /// Set the language as a BCP 47 tag such as `en` or `pt-BR`
+    pub fn set_language(&mut self, language: impl Into<String>) -> Result<(), EpubError> {
+        let language = required("language", language.into())?;
+        if !language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(EpubError::InvalidMetadata(format!(
+                "'{}' is not a language tag",
+                language
+            )));
+        }
+        for chapter in &mut self.epub.chapters {
+            chapter.language = Some(language.clone());
+        }
+        self.epub.metadata.language = Some(language);
+        Ok(())
+    }
+
+    /// Add a creator, optionally with a MARC relator role such as `aut` or `ill`
+    pub fn add_creator(
+        &mut self,
+        name: impl Into<String>,
+        role: Option<&str>,
+    ) -> Result<(), EpubError> {
+        let name = required("creator", name.into())?;
+        let mut creator = Creator::new(name.clone());
+        creator.role = role.map(str::to_string);
+        self.epub.metadata.creators.push(creator);
+        // The package writer records the role as a refinement of the creator
+        self.epub.metadata.creator.push(name);
+        Ok(())
+    }
+
+    /// Set the unique identifier, e.g. `set_identifier(Some("ISBN"), "9780142437247")`
+    ///
+    /// The scheme is recorded as an `identifier-type` refinement of the
+    /// package's unique identifier, replacing any earlier one.
+    pub fn set_identifier(
+        &mut self,
+        scheme: Option<&str>,
+        value: impl Into<String>,
+    ) -> Result<(), EpubError> {
+        let value = required("identifier", value.into())?;
+        self.epub.metadata.identifier = value;
+        let refines = format!("#{}", self.epub.unique_identifier);
+        self.epub.meta.retain(|meta| {
+            !(meta.refines.as_deref() == Some(refines.as_str())
+                && meta.property.as_deref() == Some("identifier-type"))
+        });
+        if let Some(scheme) = scheme {
+            let meta = MetaEntry::property("identifier-type", scheme)
+                .with_refines(&self.epub.unique_identifier);
+            self.epub.meta.push(meta);
+        }
+        Ok(())
+    }
+
+    /// Put the book in a series, replacing any previous series
+    ///
+    /// Written both as an EPUB 3 `belongs-to-collection` and as the
+    /// `calibre:series` metadata most EPUB 2 readers understand.
+    pub fn set_series(
+        &mut self,
+        name: impl Into<String>,
+        position: Option<f32>,
+    ) -> Result<(), EpubError> {
+        let name = required("series", name.into())?;
+
+        let collection_ids: Vec<String> = self
+            .epub
+            .meta
+            .iter()
+            .filter(|meta| meta.property.as_deref() == Some("belongs-to-collection"))
+            .filter_map(|meta| meta.id.as_ref().map(|id| format!("#{}", id)))
+            .collect();
+        self.epub.meta.retain(|meta| {
+            meta.property.as_deref() != Some("belongs-to-collection")
+                && !meta
+                    .refines
+                    .as_ref()
+                    .is_some_and(|refines| collection_ids.contains(refines))
+                && !matches!(
+                    meta.name.as_deref(),
+                    Some("calibre:series" | "calibre:series_index")
+                )
+        });
+
+        self.epub
+            .meta
+            .push(MetaEntry::property("belongs-to-collection", &name).with_id("series"));
+        self.epub
+            .meta
+            .push(MetaEntry::property("collection-type", "series").with_refines("series"));
+        self.epub
+            .meta
+            .push(MetaEntry::named("calibre:series", name));
+        if let Some(position) = position {
+            self.epub.meta.push(
+                MetaEntry::property("group-position", position.to_string()).with_refines("series"),
+            );
+            self.epub.meta.push(MetaEntry::named(
+                "calibre:series_index",
+                position.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Replace the cover image
+    ///
+    /// The existing cover manifest item is reused when there is one;
+    /// otherwise a new item is added next to the OPF. Either way it ends up
+    /// as the only item with the `cover-image` property and is named by the
+    /// EPUB 2 `<meta name="cover">`. When the old href's extension doesn't
+    /// fit `media_type` the image is renamed (see `Epub::set_cover_image`).
+    pub fn set_cover(&mut self, bytes: Vec<u8>, media_type: &str) -> Result<(), EpubError> {
+        let Some(extension) = cover_extension(media_type) else {
+            return Err(EpubError::InvalidMetadata(format!(
+                "'{}' is not a supported cover image type",
+                media_type
+            )));
+        };
+        if bytes.is_empty() {
+            return Err(EpubError::InvalidMetadata(
+                "cover image is empty".to_string(),
+            ));
+        }
+
+        let epub = &mut *self.epub;
+        let existing = epub.metadata.cover.as_ref().and_then(|id| {
+            epub.resources
+                .iter()
+                .position(|resource| &resource.id == id)
+        });
+        let index = match existing {
+            Some(index) => index,
+            None => {
+                let id = epub.unique_resource_id("cover-image");
+                let href = epub.unique_resource_href("cover", extension);
+                epub.resources
+                    .push(Resource::new(id, href, media_type, None));
+                epub.resources.len() - 1
+            }
+        };
+
+        for (i, resource) in epub.resources.iter_mut().enumerate() {
+            let mut properties: Vec<&str> = resource
+                .properties
+                .as_deref()
+                .unwrap_or_default()
+                .split_whitespace()
+                .filter(|property| *property != "cover-image")
+                .collect();
+            if i == index {
+                properties.push("cover-image");
+            }
+            resource.properties = (!properties.is_empty()).then(|| properties.join(" "));
+        }
+
+        let resource = &mut epub.resources[index];
+        resource.media_type = media_type.to_string();
+        let id = resource.id.clone();
+        let href = resource.href.clone();
+
+        for meta in &mut epub.meta {
+            if meta.name.as_deref() == Some("cover") {
+                meta.content = Some(id.clone());
+            }
+        }
+        epub.metadata.cover = Some(id);
+        epub.put_resource_bytes(&href, bytes);
+        epub.match_cover_extension(extension)
+    }
+}
+
+/// A manifest id from a file name: `Images/Cover Art.png` becomes `Cover-Art`
+fn id_from_href(href: &str) -> String {
+    let name = href.rsplit('/').next().unwrap_or(href);
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let id: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    match id.chars().next() {
+        Some(first) if first.is_alphabetic() || first == '_' => id,
+        _ => format!("item-{}", id),
+    }
+}
+
+/// File extension for a supported cover image type
+pub(crate) fn cover_extension(media_type: &str) -> Option<&'static str> {
+    COVER_MEDIA_TYPES
+        .iter()
+        .find(|(known, _)| *known == media_type)
+        .map(|(_, extension)| *extension)
+}
+
+/// Reject empty values for fields the package can't do without
+fn required(field: &str, value: String) -> Result<String, EpubError> {
+    let value = value.trim().to_string();
+    if value.is_empty() {
+        Err(EpubError::InvalidMetadata(format!(
+            "{} must not be empty",
+            field
+        )))
+    } else {
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn sample() -> Epub {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><h1>One</h1></body></html>"),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn edits_survive_a_save() {
+        let mut epub = sample();
+        {
+            let mut metadata = epub.edit_metadata();
+            metadata.set_title("Moby Dick").unwrap();
+            metadata
+                .add_creator("Herman Melville", Some("aut"))
+                .unwrap();
+            metadata
+                .set_identifier(Some("ISBN"), "9780142437247")
+                .unwrap();
+            metadata.set_series("Sea Stories", Some(2.0)).unwrap();
+            metadata.set_cover(b"png".to_vec(), "image/png").unwrap();
+            assert!(metadata.set_title("  ").is_err());
+            assert!(metadata.set_cover(b"x".to_vec(), "text/plain").is_err());
+        }
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(saved.get_title(), Some("Moby Dick"));
+        assert_eq!(saved.get_creator(), Some("Herman Melville"));
+        assert_eq!(saved.get_identifier(), "9780142437247");
+        assert_eq!(saved.get_cover_bytes().as_deref(), Some(&b"png"[..]));
+        assert_eq!(saved.get_all_files().len(), 1);
+
+        let package = saved.to_package();
+        let cover = package
+            .get_manifest()
+            .iter()
+            .find(|resource| resource.has_property("cover-image"))
+            .unwrap();
+        assert_eq!(cover.get_href(), "cover.png");
+        assert!(
+            package
+                .get_meta()
+                .iter()
+                .any(|meta| meta.get_name() == Some("calibre:series"))
+        );
+    }
+
+    #[test]
+    fn an_added_creator_keeps_its_role_beside_creators_with_their_own_ids() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>T</dc:title>
+    <dc:identifier id="uid">urn:uuid:1</dc:identifier>
+    <dc:creator id="creator2">Herman Melville</dc:creator>
+    <meta refines="#creator2" property="role" scheme="marc:relators">aut</meta>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"##;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+        epub.edit_metadata()
+            .add_creator("Rockwell Kent", Some("ill"))
+            .unwrap();
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        let creators: Vec<(&str, Option<&str>)> = saved
+            .get_metadata()
+            .get_creator_entries()
+            .iter()
+            .map(|creator| (creator.get_name(), creator.get_role()))
+            .collect();
+        assert_eq!(
+            creators,
+            [
+                ("Herman Melville", Some("aut")),
+                ("Rockwell Kent", Some("ill"))
+            ]
+        );
+        let roles = saved
+            .to_package()
+            .get_meta()
+            .iter()
+            .filter(|meta| meta.get_property() == Some("role"))
+            .count();
+        assert_eq!(roles, 2);
+    }
+
+    #[test]
+    fn identifier_scheme_refines_the_unique_identifier() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="pub-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>T</dc:title>
+    <dc:identifier id="pub-id">urn:uuid:1</dc:identifier>
+    <meta refines="#pub-id" property="identifier-type">UUID</meta>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="cover" href="cover.png" media-type="image/png" properties="cover-image"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"##;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("cover.png", "png"),
+        ]))
+        .unwrap();
+        {
+            let mut metadata = epub.edit_metadata();
+            metadata
+                .set_identifier(Some("ISBN"), "9780142437247")
+                .unwrap();
+            metadata.set_cover(b"jpeg".to_vec(), "image/jpeg").unwrap();
+        }
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(saved.get_identifier(), "9780142437247");
+        let package = saved.to_package();
+        assert_eq!(package.get_unique_identifier(), "pub-id");
+        let types: Vec<_> = package
+            .get_meta()
+            .iter()
+            .filter(|meta| meta.get_property() == Some("identifier-type"))
+            .map(|meta| (meta.get_refines(), meta.get_value()))
+            .collect();
+        assert_eq!(types, [(Some("#pub-id"), Some("ISBN"))]);
+
+        assert_eq!(saved.get_cover_bytes().as_deref(), Some(&b"jpeg"[..]));
+        assert!(saved.get_resource_bytes("cover.png").is_none());
+        assert!(saved.get_resource_bytes("cover.jpg").is_some());
+    }
+
+    #[test]
+    fn cover_replacement_updates_the_cover_page() {
+        let opf = package(
+            r#"<item id="cover-page" href="text/cover.xhtml" media-type="application/xhtml+xml"/>
+               <item id="cover" href="images/cover.png" media-type="image/png" properties="cover-image"/>
+               <item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="cover-page"/><itemref idref="ch1"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "text/cover.xhtml",
+                r#"<html><body epub:type="cover"><img src="../images/cover.png"/></body></html>"#,
+            ),
+            ("images/cover.png", "png"),
+            ("text/ch1.xhtml", "<html><body><h1>One</h1></body></html>"),
+        ]))
+        .unwrap();
+
+        epub.set_cover_image(b"jpeg".to_vec(), "image/jpeg")
+            .unwrap();
+        let page = epub.get_resource_bytes("text/cover.xhtml").unwrap();
+        assert!(
+            String::from_utf8(page)
+                .unwrap()
+                .contains(r#"src="../images/cover.jpg""#)
+        );
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(saved.get_cover_bytes().as_deref(), Some(&b"jpeg"[..]));
+        assert!(saved.get_resource_bytes("images/cover.png").is_none());
+        assert_eq!(saved.get_spine().len(), 2);
+
+        // Without a cover page one is created at the start of the spine
+        let mut epub = sample();
+        assert!(epub.regenerate_cover_page().is_err());
+        epub.set_cover_image(b"png".to_vec(), "image/png").unwrap();
+        epub.regenerate_cover_page().unwrap();
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        let first = &saved.get_spine_files()[0];
+        assert_eq!(first.semantic_role(), SemanticRole::Cover);
+        assert!(
+            first
+                .content
+                .contains(r#"<img src="cover.png" alt="Cover"/>"#)
+        );
+        assert_eq!(saved.start_of_content().as_deref(), Some("ch1.xhtml"));
+    }
+
+    #[test]
+    fn renaming_and_removing_resources_keeps_references_straight() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="css" href="style.css" media-type="text/css"/>
+               <item id="bg" href="bg.png" media-type="image/png"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                r#"<html><head><link href="style.css"/></head><body><a href="ch2.xhtml#s">2</a></body></html>"#,
+            ),
+            ("ch2.xhtml", r#"<html><body><h1 id="s">Two</h1></body></html>"#),
+            ("style.css", "body { background: url(bg.png) }"),
+            ("bg.png", "png"),
+        ]))
+        .unwrap();
+
+        epub.rename_resource("ch2.xhtml", "text/ch2.xhtml").unwrap();
+        epub.rename_resource("bg.png", "images/bg.png").unwrap();
+        assert!(matches!(
+            epub.rename_resource("ch1.xhtml", "style.css"),
+            Err(EpubError::ResourceExists(_))
+        ));
+        assert!(matches!(
+            epub.rename_resource("missing.png", "x.png"),
+            Err(EpubError::UnknownResource(_))
+        ));
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        let text = |epub: &Epub, href: &str| {
+            String::from_utf8(epub.get_resource_bytes(href).unwrap()).unwrap()
+        };
+        assert!(text(&saved, "ch1.xhtml").contains(r#"href="text/ch2.xhtml#s""#));
+        assert!(text(&saved, "style.css").contains("url(images/bg.png)"));
+        assert_eq!(saved.get_spine_files()[1].get_href(), "text/ch2.xhtml");
+        assert!(saved.get_resource_bytes("bg.png").is_none());
+
+        let mut epub = saved;
+        assert_eq!(
+            epub.remove_resource("text/ch2.xhtml").unwrap(),
+            ["ch1.xhtml"]
+        );
+        assert_eq!(epub.get_spine(), ["ch1"]);
+        assert_eq!(
+            epub.add_resource("Notes 1.xhtml", "application/xhtml+xml", b"<p/>".to_vec())
+                .unwrap(),
+            "Notes-1"
+        );
+        assert!(
+            epub.add_resource("style.css", "text/css", Vec::new())
+                .is_err()
+        );
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(saved.get_resources().len(), 4);
+        assert!(saved.get_resource_bytes("text/ch2.xhtml").is_none());
+        assert_eq!(
+            saved.get_resource_bytes("Notes 1.xhtml").as_deref(),
+            Some(&b"<p/>"[..])
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Images embedded in content documents as `data:` URIs
+//!
+//! Converters from Word and web pages often paste images straight into the
+//! markup as base64 `data:` URIs. Such an image is a third bigger than the
+//! file it came from, is parsed again with every page, can't be cached, and
+//! is invisible to anything that walks the manifest. `Epub::embedded_images`
+//! finds them and decodes each into a virtual resource, addressed as
+//! `chapter.xhtml#embedded-image-1`, that can be read like any other.
+//! `Epub::externalize_embedded_images` (or
+//! `WriteOptions::with_externalize_images` when saving) turns them into
+//! real manifest items and points the markup at those.
+
+use crate::edit::cover_extension;
+use crate::epub::Epub;
+use crate::error::EpubError;
+use crate::href;
+use regex::Regex;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// A base64 image `data:` URI, capturing the media type and the payload
+pub(crate) static DATA_URI: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"data:(image/[A-Za-z0-9.+-]+);base64,([A-Za-z0-9+/=\s]+)").unwrap()
+});
+
+/// Fragment prefix of an embedded image's virtual href
+const VIRTUAL_FRAGMENT: &str = "embedded-image-";
+
+/// A decoded `data:` image, from `Epub::embedded_images`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedImage {
+    document: String,
+    number: usize,
+    media_type: String,
+    bytes: Vec<u8>,
+    range: Range<usize>,
+}
+
+impl EmbeddedImage {
+    /// Href (relative to the OPF) of the document the image is in
+    pub fn get_document_href(&self) -> &str {
+        &self.document
+    }
+
+    /// The image's virtual href, e.g. `chapter.xhtml#embedded-image-1`
+    pub fn get_virtual_href(&self) -> String {
+        format!("{}#{}{}", self.document, VIRTUAL_FRAGMENT, self.number)
+    }
+
+    pub fn get_media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    /// The decoded image
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Byte range of the `data:` URI in the document's content
+    pub fn get_range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+}
+
+impl Epub {
+    /// Every base64 `data:` image in the content documents, in document order
+    ///
+    /// URIs whose payload isn't valid base64 are skipped.
+    pub fn embedded_images(&self) -> Vec<EmbeddedImage> {
+        self.all_files
+            .iter()
+            .flat_map(|file| {
+                DATA_URI
+                    .captures_iter(&file.content)
+                    .filter_map(|captures| {
+                        let uri = captures.get(0)?;
+                        Some((
+                            captures[1].to_ascii_lowercase(),
+                            decode_base64(&captures[2])?,
+                            uri.range(),
+                        ))
+                    })
+                    .enumerate()
+                    .map(|(index, (media_type, bytes, range))| EmbeddedImage {
+                        document: file.href.to_string(),
+                        number: index + 1,
+                        media_type,
+                        bytes,
+                        range,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The embedded image at a virtual href from `EmbeddedImage::get_virtual_href`
+    pub fn get_embedded_image(&self, virtual_href: &str) -> Option<EmbeddedImage> {
+        let (document, fragment) = virtual_href.split_once('#')?;
+        let number: usize = fragment.strip_prefix(VIRTUAL_FRAGMENT)?.parse().ok()?;
+        self.embedded_images()
+            .into_iter()
+            .find(|image| image.document == document && image.number == number)
+    }
+
+    /// Move every embedded image into its own manifest item
+    ///
+    /// Images are written next to the OPF as `embedded-image.png`,
+    /// `embedded-image-2.jpg` and so on; identical images share one item.
+    /// Each `data:` URI is replaced by a relative link to its item. Images
+    /// of types EPUB doesn't support as core media are left embedded.
+    /// Returns the hrefs of the new items.
+    pub fn externalize_embedded_images(&mut self) -> Result<Vec<String>, EpubError> {
+        enter_span!("externalize_embedded_images");
+        let mut by_document: Vec<(String, Vec<EmbeddedImage>)> = Vec::new();
+        for image in self.embedded_images() {
+            match by_document.last_mut() {
+                Some((document, images)) if *document == image.document => images.push(image),
+                _ => by_document.push((image.document.clone(), vec![image])),
+            }
+        }
+
+        let mut added: Vec<String> = Vec::new();
+        let mut hrefs: HashMap<Vec<u8>, String> = HashMap::new();
+        for (document, images) in by_document {
+            let Some(mut content) = self
+                .all_files
+                .iter()
+                .find(|file| *file.href == *document)
+                .map(|file| file.content.clone())
+            else {
+                continue;
+            };
+            let document_path = Self::resolve_path(&self.opf_path, &document);
+            let mut replacements = Vec::new();
+            for image in images {
+                let Some(extension) = cover_extension(&image.media_type) else {
+                    continue;
+                };
+                let href = match hrefs.get(&image.bytes) {
+                    Some(href) => href.clone(),
+                    None => {
+                        let href = self.unique_resource_href("embedded-image", extension);
+                        self.add_resource(&href, &image.media_type, image.bytes.clone())?;
+                        hrefs.insert(image.bytes, href.clone());
+                        added.push(href.clone());
+                        href
+                    }
+                };
+                let image_path = Self::resolve_path(&self.opf_path, &href);
+                replacements.push((image.range, href::relative(&document_path, &image_path)));
+            }
+            // Later ranges first, so earlier ones stay valid
+            for (range, link) in replacements.into_iter().rev() {
+                content.replace_range(range, &link);
+            }
+            self.put_resource_bytes(&document, content.into_bytes());
+        }
+        Ok(added)
+    }
+}
+
+/// Decode standard base64, ignoring whitespace; `None` if it isn't valid
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        buffer = buffer << 6 | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    (!bytes.is_empty()).then_some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    #[test]
+    fn embedded_images_become_manifest_items() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>id</dc:identifier><dc:title>T</dc:title>
+  </metadata>
+  <manifest><item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        // "PNG!" and "GIF" in base64, the first one twice
+        let chapter = r#"<html><body><img src="data:image/png;base64,UE5H
+IQ=="/><img src="data:image/gif;base64,R0lG"/><img src="data:image/png;base64,UE5HIQ=="/></body></html>"#;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("text/ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let images = epub.embedded_images();
+        assert_eq!(images.len(), 3);
+        assert_eq!(images[0].get_bytes(), b"PNG!");
+        let second = epub
+            .get_embedded_image("text/ch1.xhtml#embedded-image-2")
+            .unwrap();
+        assert_eq!(second.get_media_type(), "image/gif");
+        assert_eq!(second.get_bytes(), b"GIF");
+
+        let added = epub.externalize_embedded_images().unwrap();
+        assert_eq!(added, ["embedded-image.png", "embedded-image.gif"]);
+        assert_eq!(
+            epub.get_resource_bytes("text/ch1.xhtml").unwrap(),
+            br#"<html><body><img src="../embedded-image.png"/><img src="../embedded-image.gif"/><img src="../embedded-image.png"/></body></html>"#
+        );
+        assert!(epub.embedded_images().is_empty());
+    }
+}
@@ -0,0 +1,450 @@
+//! Filling in metadata from online catalogues
+//!
+//! Looking a book up in Open Library or Google Books needs an HTTP client
+//! and an API key policy, which don't belong in a parsing library. Instead,
+//! a tool implements `MetadataProvider` (or `AsyncMetadataProvider`, for
+//! async HTTP clients) against the service it uses, and
+//! `Epub::enrich_metadata` does the rest: it builds a `LookupQuery` from the
+//! book's ISBNs, title and authors, and copies the answer into the fields
+//! the book leaves empty. Existing values are never overwritten. Every value
+//! filled in is returned as a `Provenance` naming the provider, and is also
+//! listed in `ParseReport::get_enriched`.
+//!
+//! Covers are the exception: with `EnrichOptions::with_replace_cover`, a
+//! provider's cover replaces the book's through `Epub::replace_cover`, so
+//! the swap can be undone with `revert_cover`.
+
+use crate::dedup::normalize_identifier;
+use crate::epub::Epub;
+use std::error::Error;
+use std::future::Future;
+
+/// What `Epub::enrich_metadata` asks a provider to look up
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LookupQuery {
+    isbns: Vec<String>,
+    title: Option<String>,
+    authors: Vec<String>,
+}
+
+impl LookupQuery {
+    /// The book's ISBNs as bare ISBN-13 digits
+    pub fn get_isbns(&self) -> &[String] {
+        &self.isbns
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn get_authors(&self) -> &[String] {
+        &self.authors
+    }
+}
+
+/// A provider's answer to a `LookupQuery`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProvidedMetadata {
+    title: Option<String>,
+    authors: Vec<String>,
+    description: Option<String>,
+    publisher: Option<String>,
+    date: Option<String>,
+    language: Option<String>,
+    subjects: Vec<String>,
+    series: Option<(String, Option<f32>)>,
+    cover: Option<(Vec<u8>, String)>,
+}
+
+impl ProvidedMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.authors.push(author.into());
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_publisher(mut self, publisher: impl Into<String>) -> Self {
+        self.publisher = Some(publisher.into());
+        self
+    }
+
+    /// Publication date, preferably ISO 8601 (`2005`, `2005-08-02`)
+    pub fn with_date(mut self, date: impl Into<String>) -> Self {
+        self.date = Some(date.into());
+        self
+    }
+
+    /// Language as a BCP 47 tag such as `en`
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    pub fn with_subject(mut self, subject: impl Into<String>) -> Self {
+        self.subjects.push(subject.into());
+        self
+    }
+
+    pub fn with_series(mut self, name: impl Into<String>, position: Option<f32>) -> Self {
+        self.series = Some((name.into(), position));
+        self
+    }
+
+    /// Cover image bytes and their media type, e.g. `image/jpeg`
+    pub fn with_cover(mut self, bytes: Vec<u8>, media_type: impl Into<String>) -> Self {
+        self.cover = Some((bytes, media_type.into()));
+        self
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn get_authors(&self) -> &[String] {
+        &self.authors
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn get_publisher(&self) -> Option<&str> {
+        self.publisher.as_deref()
+    }
+
+    pub fn get_date(&self) -> Option<&str> {
+        self.date.as_deref()
+    }
+
+    pub fn get_language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn get_subjects(&self) -> &[String] {
+        &self.subjects
+    }
+
+    pub fn get_series(&self) -> Option<(&str, Option<f32>)> {
+        self.series
+            .as_ref()
+            .map(|(name, position)| (name.as_str(), *position))
+    }
+
+    pub fn get_cover(&self) -> Option<(&[u8], &str)> {
+        self.cover
+            .as_ref()
+            .map(|(bytes, media_type)| (bytes.as_slice(), media_type.as_str()))
+    }
+}
+
+/// Settings for `Epub::enrich_metadata_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct EnrichOptions {
+    replace_cover: bool,
+}
+
+impl EnrichOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace an existing cover with the provider's, keeping the old one
+    /// for `Epub::revert_cover`; off by default
+    pub fn with_replace_cover(mut self, replace: bool) -> Self {
+        self.replace_cover = replace;
+        self
+    }
+
+    pub fn get_replace_cover(&self) -> bool {
+        self.replace_cover
+    }
+}
+
+/// A catalogue `Epub::enrich_metadata` can look books up in
+pub trait MetadataProvider {
+    /// Recorded as the source of every value this provider supplies
+    fn name(&self) -> &str;
+
+    /// Find the book, or `None` if the catalogue doesn't know it
+    fn lookup(&self, query: &LookupQuery) -> Result<Option<ProvidedMetadata>, Box<dyn Error>>;
+}
+
+/// A `MetadataProvider` for async HTTP clients
+pub trait AsyncMetadataProvider {
+    /// Recorded as the source of every value this provider supplies
+    fn name(&self) -> &str;
+
+    /// Find the book, or `None` if the catalogue doesn't know it
+    fn lookup(
+        &self,
+        query: &LookupQuery,
+    ) -> impl Future<Output = Result<Option<ProvidedMetadata>, Box<dyn Error>>>;
+}
+
+/// A metadata field `Epub::enrich_metadata` filled in, and where from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    field: String,
+    value: String,
+    provider: String,
+}
+
+impl Provenance {
+    /// The field's name: `title`, `creator`, `description`, `publisher`,
+    /// `date`, `language`, `subject`, `series` or `cover`
+    pub fn get_field(&self) -> &str {
+        &self.field
+    }
+
+    /// The value filled in; the media type for a cover
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+
+    /// The `name` of the provider the value came from
+    pub fn get_provider(&self) -> &str {
+        &self.provider
+    }
+}
+
+impl Epub {
+    /// The query `enrich_metadata` sends to a provider
+    pub fn lookup_query(&self) -> LookupQuery {
+        let mut isbns: Vec<String> = crate::summary::identifiers(self)
+            .iter()
+            .filter_map(|id| normalize_identifier(id))
+            .filter(|id| {
+                id.len() == 13 && id.starts_with("97") && id.bytes().all(|b| b.is_ascii_digit())
+            })
+            .collect();
+        isbns.dedup();
+        LookupQuery {
+            isbns,
+            title: self.get_title().map(str::to_string),
+            authors: self.get_creators().to_vec(),
+        }
+    }
+
+    /// Fill the book's empty metadata fields from `provider`
+    ///
+    /// Returns what was filled in; an unknown book fills in nothing. Values
+    /// the metadata editor refuses (such as a malformed language tag) are
+    /// skipped.
+    ///
+    /// # Errors
+    /// Whatever the provider's lookup returns.
+    pub fn enrich_metadata(
+        &mut self,
+        provider: &impl MetadataProvider,
+    ) -> Result<Vec<Provenance>, Box<dyn Error>> {
+        self.enrich_metadata_with_options(provider, &EnrichOptions::default())
+    }
+
+    /// Like `enrich_metadata`, but with non-default options
+    pub fn enrich_metadata_with_options(
+        &mut self,
+        provider: &impl MetadataProvider,
+        options: &EnrichOptions,
+    ) -> Result<Vec<Provenance>, Box<dyn Error>> {
+        enter_span!("enrich_metadata", provider = provider.name());
+        let provided = provider.lookup(&self.lookup_query())?;
+        Ok(self.apply_enrichment(provider.name(), provided, options))
+    }
+
+    /// `enrich_metadata` with an `AsyncMetadataProvider`
+    pub async fn enrich_metadata_async(
+        &mut self,
+        provider: &impl AsyncMetadataProvider,
+    ) -> Result<Vec<Provenance>, Box<dyn Error>> {
+        self.enrich_metadata_async_with_options(provider, &EnrichOptions::default())
+            .await
+    }
+
+    /// `enrich_metadata_with_options` with an `AsyncMetadataProvider`
+    pub async fn enrich_metadata_async_with_options(
+        &mut self,
+        provider: &impl AsyncMetadataProvider,
+        options: &EnrichOptions,
+    ) -> Result<Vec<Provenance>, Box<dyn Error>> {
+        let provided = provider.lookup(&self.lookup_query()).await?;
+        Ok(self.apply_enrichment(provider.name(), provided, options))
+    }
+
+    fn apply_enrichment(
+        &mut self,
+        provider: &str,
+        provided: Option<ProvidedMetadata>,
+        options: &EnrichOptions,
+    ) -> Vec<Provenance> {
+        let Some(provided) = provided else {
+            return Vec::new();
+        };
+        let mut filled = Vec::new();
+        let mut record = |field: &str, value: &str| {
+            filled.push(Provenance {
+                field: field.to_string(),
+                value: value.to_string(),
+                provider: provider.to_string(),
+            });
+        };
+        let missing = |value: Option<&str>| value.is_none_or(|value| value.trim().is_empty());
+        let given = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        if let Some(title) = given(&provided.title)
+            && missing(self.get_title())
+            && self.edit_metadata().set_title(&title).is_ok()
+        {
+            record("title", &title);
+        }
+        if self.get_creators().is_empty() {
+            for author in &provided.authors {
+                if self
+                    .edit_metadata()
+                    .add_creator(author, Some("aut"))
+                    .is_ok()
+                {
+                    record("creator", author);
+                }
+            }
+        }
+        if let Some(language) = given(&provided.language)
+            && missing(self.get_language())
+            && self.edit_metadata().set_language(&language).is_ok()
+        {
+            record("language", &language);
+        }
+        let metadata = &mut self.metadata;
+        for (field, slot, value) in [
+            (
+                "description",
+                &mut metadata.description,
+                &provided.description,
+            ),
+            ("publisher", &mut metadata.publisher, &provided.publisher),
+            ("date", &mut metadata.date, &provided.date),
+        ] {
+            if let Some(value) = given(value)
+                && missing(slot.as_deref())
+            {
+                record(field, &value);
+                *slot = Some(value);
+            }
+        }
+        if metadata.tags.is_empty() {
+            for subject in &provided.subjects {
+                let subject = subject.trim();
+                if !subject.is_empty() && !metadata.tags.iter().any(|tag| tag == subject) {
+                    record("subject", subject);
+                    metadata.tags.push(subject.to_string());
+                }
+            }
+        }
+        if let Some((name, position)) = provided.series
+            && crate::library::series(self).0.is_none()
+            && self.edit_metadata().set_series(&name, position).is_ok()
+        {
+            record("series", &name);
+        }
+        if let Some((bytes, media_type)) = provided.cover
+            && (self.get_cover().is_none() || options.replace_cover)
+            && self.replace_cover(bytes, &media_type).is_ok()
+        {
+            record("cover", &media_type);
+        }
+
+        for provenance in &filled {
+            self.report.enriched(provenance.clone());
+        }
+        filled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    struct Catalogue;
+
+    impl MetadataProvider for Catalogue {
+        fn name(&self) -> &str {
+            "catalogue"
+        }
+
+        fn lookup(&self, query: &LookupQuery) -> Result<Option<ProvidedMetadata>, Box<dyn Error>> {
+            if query.get_isbns() != ["9780441013593"] {
+                return Ok(None);
+            }
+            Ok(Some(
+                ProvidedMetadata::new()
+                    .with_title("Wrong Title")
+                    .with_author("Frank Herbert")
+                    .with_publisher("Ace")
+                    .with_language("not a tag!")
+                    .with_subject("Science fiction")
+                    .with_series("Dune", Some(1.0))
+                    .with_cover(b"jpeg".to_vec(), "image/jpeg"),
+            ))
+        }
+    }
+
+    #[test]
+    fn only_missing_fields_are_filled_in() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>urn:uuid:1234</dc:identifier>
+    <dc:identifier>urn:isbn:0-441-01359-7</dc:identifier>
+    <dc:title>Dune</dc:title>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let filled = epub.enrich_metadata(&Catalogue).unwrap();
+        let fields: Vec<&str> = filled.iter().map(Provenance::get_field).collect();
+        assert_eq!(
+            fields,
+            ["creator", "publisher", "subject", "series", "cover"]
+        );
+        assert!(filled.iter().all(|p| p.get_provider() == "catalogue"));
+        assert_eq!(epub.get_title(), Some("Dune"));
+        assert_eq!(epub.get_creators(), ["Frank Herbert"]);
+        assert_eq!(epub.get_publisher(), Some("Ace"));
+        assert_eq!(epub.get_language(), None);
+        assert_eq!(epub.get_parse_report().get_enriched().len(), 5);
+
+        // A second lookup only replaces the cover, and only when asked to
+        assert!(epub.enrich_metadata(&Catalogue).unwrap().is_empty());
+        let options = EnrichOptions::new().with_replace_cover(true);
+        let filled = epub
+            .enrich_metadata_with_options(&Catalogue, &options)
+            .unwrap();
+        assert_eq!(filled[0].get_field(), "cover");
+        assert_eq!(epub.get_previous_covers().len(), 1);
+    }
+}
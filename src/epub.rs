@@ -14,6 +14,14 @@
 //! - Full XHTML content extraction for use with external HTML parsers
 //! - Fallback to manifest IDs when navigation titles are not available
 //!
+//! ## Untrusted Input
+//!
+//! Parsing never panics on malformed input. Structural problems that make a
+//! book unreadable (no `container.xml`, no rootfile, a missing or invalid
+//! package document) are returned as an [`EpubError`](crate::error::EpubError)
+//! inside the `Box<dyn Error>`; anything less severe is recorded in the
+//! [`ParseReport`] and parsing carries on.
+//!
 //! ## HTML Content Access
 //!
 //! Each `EpubFile` contains the complete XHTML content which can be parsed using
@@ -35,22 +43,56 @@
 //! }
 //! ```
 
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::reader::{NsReader, Reader};
 use regex::Regex;
-use serde::Deserialize;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error::Error;
-use std::fs::File;
 use std::io::Read;
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
 use zip::read::ZipArchive;
+use zip::result::ZipError;
+
+use crate::archive::{self, ArchiveReader, Source};
+use crate::error::EpubError;
+use crate::events::{ParseEvent, ParseEventSink};
+use crate::extensions::MetadataElement;
+use crate::href;
+use crate::offsets::SpineWeight;
+use crate::options::{ParseOptions, TextOptions};
+use crate::package::{MetaEntry, Package};
+use crate::report::ParseReport;
+use crate::semantics::{self, Landmark, SemanticRole};
+
+/// Whether manifest items of this media type are read as content documents
+///
+/// Besides XHTML this accepts `text/html`, which some converters emit for
+/// documents that aren't well-formed XML.
+pub(crate) fn is_content_media_type(media_type: &str) -> bool {
+    let media_type = media_type.split(';').next().unwrap_or("").trim();
+    media_type.eq_ignore_ascii_case("application/xhtml+xml")
+        || media_type.eq_ignore_ascii_case("text/html")
+}
 
 /// Represents a single file within an EPUB
+///
+/// The id, href and media type are shared (`Arc<str>`) with the copies in
+/// chapters and the table of contents, and every file of a media type
+/// shares one string, so large books don't store them many times over.
 #[derive(Debug, Clone)]
 pub struct EpubFile {
-    pub id: String,
-    pub href: String,
+    pub id: Arc<str>,
+    pub href: Arc<str>,
     pub title: Option<String>,
     pub content: String,
-    pub media_type: String,
+    pub media_type: Arc<str>,
+    /// Position of this file in the spine, `None` for files only reachable by links
+    pub spine_index: Option<usize>,
+    /// What this file is for (cover, title page, body, ...)
+    pub semantic_role: SemanticRole,
 }
 
 impl EpubFile {
@@ -74,27 +116,228 @@ impl EpubFile {
         &self.media_type
     }
 
+    /// Position of this file in the spine (reading order)
+    pub fn get_spine_index(&self) -> Option<usize> {
+        self.spine_index
+    }
+
+    /// Check whether this file is part of the linear reading order
+    pub fn is_in_spine(&self) -> bool {
+        self.spine_index.is_some()
+    }
+
+    /// What this file is for, so readers can skip front matter
+    ///
+    /// See the `semantics` module for how the role is determined.
+    pub fn semantic_role(&self) -> SemanticRole {
+        self.semantic_role
+    }
+
     /// Get HTML content as bytes for parsing with external libraries
     pub fn get_html_bytes(&self) -> &[u8] {
         self.content.as_bytes()
     }
 
-    /// Check if this file contains HTML content
+    /// Check if this file contains HTML content, XHTML or plain `text/html`
     pub fn is_html(&self) -> bool {
-        self.media_type == "application/xhtml+xml"
+        is_content_media_type(&self.media_type)
     }
 
     /// Get the HTML content ready for parsing with external HTML parsers
     pub fn get_parsable_html(&self) -> &str {
         &self.content
     }
+
+    /// Get the readable text of this file with all markup stripped
+    pub fn get_text(&self) -> String {
+        crate::text::html_to_text(&self.content)
+    }
+
+    /// Like `get_text`, with `options` deciding how ruby and the like come out
+    pub fn get_text_with(&self, options: &TextOptions) -> String {
+        crate::text::html_to_text_with(&self.content, options)
+    }
+}
+
+/// A manifest item of any media type (images, stylesheets, fonts, documents)
+#[derive(Debug, Clone)]
+pub struct Resource {
+    pub(crate) id: String,
+    pub(crate) href: String,
+    pub(crate) media_type: String,
+    pub(crate) properties: Option<String>,
+    pub(crate) media_overlay: Option<String>,
+    pub(crate) fallback: Option<String>,
+}
+
+impl Resource {
+    pub fn new(
+        id: impl Into<String>,
+        href: impl Into<String>,
+        media_type: impl Into<String>,
+        properties: Option<String>,
+    ) -> Self {
+        Resource {
+            id: id.into(),
+            href: href.into(),
+            media_type: media_type.into(),
+            properties,
+            media_overlay: None,
+            fallback: None,
+        }
+    }
+
+    /// Set the manifest id of the item to use where this one's media type
+    /// isn't supported
+    pub fn with_fallback(mut self, fallback: impl Into<String>) -> Self {
+        self.fallback = Some(fallback.into());
+        self
+    }
+
+    /// Set the manifest id of the SMIL media overlay that narrates this item
+    pub fn with_media_overlay(mut self, media_overlay: impl Into<String>) -> Self {
+        self.media_overlay = Some(media_overlay.into());
+        self
+    }
+
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    pub fn get_properties(&self) -> Option<&str> {
+        self.properties.as_deref()
+    }
+
+    /// Manifest id of the item's media overlay, from `media-overlay`
+    pub fn get_media_overlay(&self) -> Option<&str> {
+        self.media_overlay.as_deref()
+    }
+
+    /// Manifest id of the item's `fallback`
+    pub fn get_fallback(&self) -> Option<&str> {
+        self.fallback.as_deref()
+    }
+
+    /// Check whether the space-separated `properties` attribute contains `property`
+    pub fn has_property(&self, property: &str) -> bool {
+        self.properties
+            .as_deref()
+            .is_some_and(|props| props.split_whitespace().any(|p| p == property))
+    }
+}
+
+/// An `<itemref>` in the spine
+#[derive(Debug, Clone)]
+pub struct SpineItem {
+    pub(crate) idref: String,
+    pub(crate) linear: bool,
+    pub(crate) properties: Option<String>,
+    pub(crate) weight: Option<SpineWeight>,
+}
+
+// The weight is measured from the content, so it doesn't count towards
+// whether two itemrefs are the same
+impl PartialEq for SpineItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.idref == other.idref
+            && self.linear == other.linear
+            && self.properties == other.properties
+    }
+}
+
+impl Eq for SpineItem {}
+
+impl SpineItem {
+    /// A linear itemref without properties
+    pub fn new(idref: impl Into<String>) -> Self {
+        SpineItem {
+            idref: idref.into(),
+            linear: true,
+            properties: None,
+            weight: None,
+        }
+    }
+
+    pub fn with_linear(mut self, linear: bool) -> Self {
+        self.linear = linear;
+        self
+    }
+
+    pub fn with_properties(mut self, properties: impl Into<String>) -> Self {
+        self.properties = Some(properties.into());
+        self
+    }
+
+    pub fn get_idref(&self) -> &str {
+        &self.idref
+    }
+
+    /// False for `linear="no"` items, which readers may skip when paging
+    pub fn is_linear(&self) -> bool {
+        self.linear
+    }
+
+    /// How much of the book the item is: its text length and, when it has
+    /// a media overlay, its narration time
+    ///
+    /// Only items from `Epub::get_weighted_spine_items` have one; it comes
+    /// from `Epub::text_index`, the same measure progress and locations use.
+    pub fn weight(&self) -> Option<SpineWeight> {
+        self.weight
+    }
+
+    pub fn get_properties(&self) -> Option<&str> {
+        self.properties.as_deref()
+    }
+
+    /// Check whether the space-separated `properties` attribute contains `property`
+    pub fn has_property(&self, property: &str) -> bool {
+        self.properties
+            .as_deref()
+            .is_some_and(|props| props.split_whitespace().any(|p| p == property))
+    }
+
+    /// Which side of a two-page spread a fixed-layout page goes on
+    ///
+    /// Both `page-spread-left` and `rendition:page-spread-left` are accepted.
+    pub fn get_page_spread(&self) -> Option<PageSpread> {
+        self.properties
+            .as_deref()?
+            .split_whitespace()
+            .find_map(
+                |property| match property.strip_prefix("rendition:").unwrap_or(property) {
+                    "page-spread-left" => Some(PageSpread::Left),
+                    "page-spread-right" => Some(PageSpread::Right),
+                    "page-spread-center" => Some(PageSpread::Center),
+                    _ => None,
+                },
+            )
+    }
+}
+
+/// Where a page goes in a two-page spread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PageSpread {
+    Left,
+    Right,
+    /// Centered on its own, across both halves of the screen
+    Center,
 }
 
 /// Represents a chapter that can contain multiple files
 #[derive(Debug, Clone)]
 pub struct Chapter {
-    title: String,
-    files: Vec<EpubFile>,
+    pub(crate) title: String,
+    pub(crate) files: Vec<EpubFile>,
+    pub(crate) language: Option<String>,
 }
 
 impl Chapter {
@@ -109,13 +352,36 @@ impl Chapter {
     pub fn get_file_count(&self) -> usize {
         self.files.len()
     }
+
+    /// The language the book declares in `dc:language`
+    pub fn get_language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// The readable text of all the chapter's files, one after another
+    pub fn get_text(&self) -> String {
+        self.files
+            .iter()
+            .map(|file| file.get_text())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Like `get_text`, with `options` applied to each file
+    pub fn get_text_with(&self, options: &TextOptions) -> String {
+        self.files
+            .iter()
+            .map(|file| file.get_text_with(options))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 /// Table of Contents entry
 #[derive(Debug, Clone)]
 pub struct TocEntry {
     pub title: String,
-    pub href: String,
+    pub href: Arc<str>,
     pub level: usize,
 }
 
@@ -136,7 +402,13 @@ impl TocEntry {
 /// Complete Table of Contents
 #[derive(Debug, Clone)]
 pub struct TableOfContents {
-    entries: Vec<TocEntry>,
+    pub(crate) entries: Vec<TocEntry>,
+}
+
+impl Default for TableOfContents {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TableOfContents {
@@ -146,8 +418,12 @@ impl TableOfContents {
         }
     }
 
-    pub fn add_entry(&mut self, title: String, href: String, level: usize) {
-        self.entries.push(TocEntry { title, href, level });
+    pub fn add_entry(&mut self, title: String, href: impl Into<Arc<str>>, level: usize) {
+        self.entries.push(TocEntry {
+            title,
+            href: href.into(),
+            level,
+        });
     }
 
     pub fn get_entries(&self) -> &[TocEntry] {
@@ -160,115 +436,277 @@ impl TableOfContents {
 }
 
 // Structs for parsing container.xml
-#[derive(Debug, Deserialize)]
-struct Container {
-    #[serde(rename = "rootfiles")]
+#[derive(Debug)]
+pub(crate) struct Container {
     rootfiles: RootFiles,
 }
 
-#[derive(Debug, Deserialize)]
-struct RootFiles {
-    #[serde(rename = "rootfile")]
+impl Container {
+    /// Path of the OPF package document, preferring a rootfile with the OPF media type
+    fn package_path(&self) -> Option<&str> {
+        let rootfiles = self
+            .rootfiles
+            .rootfile
+            .iter()
+            .filter(|rootfile| !rootfile.full_path.is_empty());
+        rootfiles
+            .clone()
+            .find(|rootfile| rootfile.media_type == "application/oebps-package+xml")
+            .or_else(|| rootfiles.clone().next())
+            .map(|rootfile| rootfile.full_path.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RootFiles {
     rootfile: Vec<RootFile>,
 }
 
-#[derive(Debug, Deserialize)]
-struct RootFile {
-    #[serde(rename = "@full-path", default)]
+#[derive(Debug)]
+pub(crate) struct RootFile {
     full_path: String,
-    #[serde(rename = "@media-type", default)]
     media_type: String,
 }
 
 // Structs for parsing OPF file
-#[derive(Debug, Deserialize)]
-struct Package {
+#[derive(Debug)]
+pub(crate) struct OpfPackage {
+    version: String,
+    /// Id of the `dc:identifier` that `unique-identifier` points at
+    unique_identifier: Option<String>,
+    prefix: Option<String>,
     metadata: OpfMetadata,
     manifest: Manifest,
     spine: Spine,
+    guide: Option<Guide>,
 }
 
-#[derive(Debug, Deserialize)]
-struct OpfMetadata {
-    #[serde(rename = "dc:identifier", default)]
-    identifier: Vec<String>,
-    #[serde(rename = "dc:title")]
-    title: Option<String>,
-    #[serde(rename = "dc:creator", default)]
-    creator: Option<Vec<String>>,
-    #[serde(rename = "dc:language")]
+#[derive(Debug, Default)]
+pub(crate) struct OpfMetadata {
+    identifier: Vec<DcIdentifier>,
+    title: Vec<DcTitle>,
+    creator: Vec<DcCreator>,
     language: Option<String>,
-    #[serde(rename = "dc:date")]
     date: Option<String>,
-    #[serde(rename = "dc:description")]
     description: Option<String>,
-    #[serde(rename = "dc:publisher")]
     publisher: Option<String>,
-    #[serde(rename = "dc:rights")]
     rights: Option<String>,
-    #[serde(rename = "dc:subject", default)]
     subject: Vec<String>,
-    #[serde(rename = "meta", default)]
     meta: Vec<Meta>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Meta {
-    #[serde(rename = "@name")]
+impl OpfMetadata {
+    /// The value of the `property` refinement of the element with `id`
+    fn refinement(&self, id: &Option<String>, property: &str) -> Option<String> {
+        let id = id.as_deref()?;
+        self.meta
+            .iter()
+            .find(|meta| {
+                meta.refines.as_deref().and_then(|r| r.strip_prefix('#')) == Some(id)
+                    && meta.property.as_deref() == Some(property)
+            })
+            .and_then(|meta| meta.value.as_deref())
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// The `content` of an EPUB 2 `<meta name="...">`
+    fn named(&self, name: &str) -> Option<String> {
+        self.meta
+            .iter()
+            .find(|meta| meta.name.as_deref() == Some(name))
+            .and_then(|meta| meta.content.as_deref())
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct DcIdentifier {
+    id: Option<String>,
+    value: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct DcTitle {
+    id: Option<String>,
+    value: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct DcCreator {
+    id: Option<String>,
+    file_as: Option<String>,
+    role: Option<String>,
+    value: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct Meta {
+    id: Option<String>,
     name: Option<String>,
-    #[serde(rename = "@content")]
     content: Option<String>,
-    #[serde(rename = "@property")]
     property: Option<String>,
-    #[serde(rename = "@refines")]
     refines: Option<String>,
-    #[serde(rename = "$text")]
+    scheme: Option<String>,
     value: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Manifest {
-    #[serde(rename = "item")]
+#[derive(Debug, Default)]
+pub(crate) struct Manifest {
     item: Vec<ManifestItem>,
 }
 
-#[derive(Debug, Deserialize)]
-struct ManifestItem {
-    #[serde(rename = "@id")]
+#[derive(Debug)]
+pub(crate) struct ManifestItem {
     id: String,
-    #[serde(rename = "@href")]
     href: String,
-    #[serde(rename = "@media-type")]
     media_type: String,
-    #[serde(rename = "@properties")]
     properties: Option<String>,
+    media_overlay: Option<String>,
+    fallback: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-struct Spine {
-    #[serde(rename = "itemref")]
+#[derive(Debug, Default)]
+pub(crate) struct Spine {
+    page_progression_direction: Option<String>,
     itemref: Vec<ItemRef>,
 }
 
-#[derive(Debug, Deserialize)]
-struct ItemRef {
-    #[serde(rename = "@idref")]
+#[derive(Debug)]
+pub(crate) struct ItemRef {
     idref: String,
+    linear: bool,
+    properties: Option<String>,
+}
+
+/// EPUB 2 `<guide>`, superseded by the landmarks nav in EPUB 3
+#[derive(Debug, Default)]
+pub(crate) struct Guide {
+    reference: Vec<GuideReference>,
+}
+
+#[derive(Debug)]
+pub(crate) struct GuideReference {
+    kind: String,
+    href: String,
+}
+
+/// What the navigation document (and guide) say about the content documents
+#[derive(Debug, Default)]
+struct Navigation {
+    /// Nav titles keyed by archive path
+    titles: HashMap<String, String>,
+    /// Landmarks in document order, with hrefs relative to the OPF
+    landmarks: Vec<Landmark>,
+}
+
+/// What a `dc:title` is, from its EPUB 3 `title-type` refinement
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleType {
+    Main,
+    Subtitle,
+    Short,
+    Collection,
+    Edition,
+    Expanded,
+}
+
+impl TitleType {
+    fn from_property(value: &str) -> Option<Self> {
+        match value.trim() {
+            "main" => Some(TitleType::Main),
+            "subtitle" => Some(TitleType::Subtitle),
+            "short" => Some(TitleType::Short),
+            "collection" => Some(TitleType::Collection),
+            "edition" => Some(TitleType::Edition),
+            "expanded" => Some(TitleType::Expanded),
+            _ => None,
+        }
+    }
+}
+
+/// One `dc:title` of a book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Title {
+    /// The element's `id`, kept so refinements still point at it when saved
+    pub(crate) id: Option<String>,
+    pub(crate) value: String,
+    pub(crate) title_type: Option<TitleType>,
+    pub(crate) file_as: Option<String>,
+}
+
+impl Title {
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+
+    /// The `file-as` refinement, e.g. "Hobbit, The"
+    pub fn get_file_as(&self) -> Option<&str> {
+        self.file_as.as_deref()
+    }
+
+    /// The `title-type` refinement, if any (EPUB 2 titles never have one)
+    pub fn get_title_type(&self) -> Option<TitleType> {
+        self.title_type
+    }
+}
+
+/// A `dc:creator` and how to sort and credit it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Creator {
+    /// The element's `id`, kept so refinements still point at it when saved
+    pub(crate) id: Option<String>,
+    pub(crate) name: String,
+    pub(crate) file_as: Option<String>,
+    pub(crate) role: Option<String>,
+}
+
+impl Creator {
+    pub(crate) fn new(name: String) -> Self {
+        Creator {
+            id: None,
+            name,
+            file_as: None,
+            role: None,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The `file-as` refinement (or EPUB 2 `opf:file-as`), e.g. "Tolkien, J. R. R."
+    pub fn get_file_as(&self) -> Option<&str> {
+        self.file_as.as_deref()
+    }
+
+    /// The MARC relator code, such as `aut` or `ill`
+    pub fn get_role(&self) -> Option<&str> {
+        self.role.as_deref()
+    }
+
+    /// The key to sort this creator by: `file-as` when given, else the name
+    pub fn sort_key(&self) -> &str {
+        self.file_as.as_deref().unwrap_or(&self.name)
+    }
 }
 
 /// Metadata structure containing all EPUB metadata
 #[derive(Debug, Clone)]
 pub struct Metadata {
-    title: Option<String>,
-    creator: Vec<String>,
-    language: Option<String>,
-    identifier: String,
-    date: Option<String>,
-    publisher: Option<String>,
-    description: Option<String>,
-    rights: Option<String>,
-    cover: Option<String>,
-    tags: Vec<String>,
+    pub(crate) titles: Vec<Title>,
+    /// Creator names, parallel to `creators`
+    pub(crate) creator: Vec<String>,
+    pub(crate) creators: Vec<Creator>,
+    pub(crate) language: Option<String>,
+    pub(crate) identifier: String,
+    pub(crate) date: Option<String>,
+    pub(crate) publisher: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) rights: Option<String>,
+    pub(crate) cover: Option<String>,
+    pub(crate) tags: Vec<String>,
 }
 
 impl Metadata {
@@ -280,7 +718,16 @@ impl Metadata {
         date: Option<String>,
     ) -> Self {
         Metadata {
-            title,
+            titles: title
+                .into_iter()
+                .map(|value| Title {
+                    id: None,
+                    value,
+                    title_type: None,
+                    file_as: None,
+                })
+                .collect(),
+            creators: creator.iter().cloned().map(Creator::new).collect(),
             creator,
             language,
             identifier,
@@ -293,14 +740,62 @@ impl Metadata {
         }
     }
 
+    /// The main title: the one typed `main`, else the first
     pub fn get_title(&self) -> Option<&str> {
-        self.title.as_deref()
+        self.main_title().map(|title| title.value.as_str())
+    }
+
+    /// Every `dc:title`, in `display-seq` order when the book gives one
+    pub fn get_titles(&self) -> &[Title] {
+        &self.titles
+    }
+
+    pub fn get_subtitle(&self) -> Option<&str> {
+        self.titles
+            .iter()
+            .find(|title| title.title_type == Some(TitleType::Subtitle))
+            .map(|title| title.value.as_str())
+    }
+
+    /// A title to show in a library: the `expanded` title if there is one,
+    /// else the main title followed by any subtitle ("Main: Subtitle")
+    pub fn get_display_title(&self) -> Option<String> {
+        if let Some(expanded) = self
+            .titles
+            .iter()
+            .find(|title| title.title_type == Some(TitleType::Expanded))
+        {
+            return Some(expanded.value.clone());
+        }
+        let main = self.get_title()?;
+        Some(match self.get_subtitle() {
+            Some(subtitle) => format!("{}: {}", main, subtitle),
+            None => main.to_string(),
+        })
+    }
+
+    pub(crate) fn main_title(&self) -> Option<&Title> {
+        self.titles
+            .iter()
+            .find(|title| title.title_type == Some(TitleType::Main))
+            .or(self.titles.first())
     }
 
     pub fn get_creators(&self) -> &[String] {
         &self.creator
     }
 
+    /// The creators with their sort keys and roles, in the same order as `get_creators`
+    pub fn get_creator_entries(&self) -> &[Creator] {
+        &self.creators
+    }
+
+    /// The key to sort the book by title: the main title's `file-as`
+    /// refinement, or calibre's `title_sort`
+    pub fn get_title_sort(&self) -> Option<&str> {
+        self.main_title()?.file_as.as_deref()
+    }
+
     pub fn get_language(&self) -> Option<&str> {
         self.language.as_deref()
     }
@@ -335,14 +830,50 @@ impl Metadata {
 }
 
 /// Main EPUB container that holds all parsed data
+///
+/// `Epub` is `Send + Sync`: server apps can parse a book once and share it
+/// across request handlers behind an `Arc`. No zip archive is kept open;
+/// reading a resource opens a fresh one over the source (the file path, or
+/// the `Arc<[u8]>` buffer, which clones share without copying).
+#[derive(Clone)]
 pub struct Epub {
-    metadata: Metadata,
-    chapters: Vec<Chapter>,
-    table_of_contents: TableOfContents,
-    all_files: Vec<EpubFile>,
-    file_path: String,
+    pub(crate) metadata: Metadata,
+    pub(crate) chapters: Vec<Chapter>,
+    pub(crate) table_of_contents: TableOfContents,
+    pub(crate) all_files: Vec<EpubFile>,
+    pub(crate) resources: Vec<Resource>,
+    pub(crate) spine: Vec<String>,
+    /// Itemref attributes by idref, for the itemrefs that have any
+    pub(crate) spine_items: BTreeMap<String, SpineItem>,
+    pub(crate) landmarks: Vec<Landmark>,
+    pub(crate) version: String,
+    /// Id of the unique `dc:identifier`, `uid` if it had none
+    pub(crate) unique_identifier: String,
+    /// The package `prefix` attribute, declaring vocabularies such as `ibooks:`
+    pub(crate) prefix: Option<String>,
+    pub(crate) page_progression_direction: Option<String>,
+    pub(crate) meta: Vec<MetaEntry>,
+    /// Every child of the OPF `<metadata>`, for `MetadataExtension`s
+    pub(crate) metadata_elements: Vec<MetadataElement>,
+    /// Contents added or replaced since parsing, keyed by archive path
+    pub(crate) overrides: BTreeMap<String, Vec<u8>>,
+    /// Archive entries dropped since parsing
+    pub(crate) removed: BTreeSet<String>,
+    pub(crate) opf_path: String,
+    pub(crate) source: Source,
+    pub(crate) options: ParseOptions,
+    pub(crate) report: ParseReport,
+    pub(crate) text_index_cache: crate::offsets::TextIndexCache,
+    #[cfg(feature = "dom")]
+    pub(crate) dom_cache: crate::dom::DomCache,
 }
 
+// Sharing one book between threads is part of the API; keep it compiling
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Epub>();
+};
+
 impl Epub {
     /// Creates a new Epub instance by parsing the EPUB file at the given path
     ///
@@ -351,68 +882,296 @@ impl Epub {
     ///
     /// # Returns
     /// * `Result<Epub, Box<dyn Error>>` - Parsed EPUB or error
+    ///
+    /// # Errors
+    /// I/O and zip errors are passed through; structural problems are
+    /// reported as [`EpubError`]. Malformed input never causes a panic.
     pub fn new(file_path: String) -> Result<Epub, Box<dyn Error>> {
-        let file = File::open(&file_path)?;
-        let mut archive = ZipArchive::new(file)?;
+        enter_span!("epub", path = %file_path);
+        Self::from_source(Source::Path(file_path), ParseOptions::default())
+    }
+
+    /// Like `new`, but with non-default parse options
+    pub fn with_options(file_path: String, options: ParseOptions) -> Result<Epub, Box<dyn Error>> {
+        enter_span!("epub", path = %file_path);
+        Self::from_source(Source::Path(file_path), options)
+    }
+
+    /// Creates a new Epub instance from an in-memory EPUB archive
+    ///
+    /// The buffer is kept alive (and shared, not copied) so resources such as
+    /// the cover image can still be read after parsing. This is also the entry
+    /// point used by the fuzz targets.
+    ///
+    /// # Arguments
+    /// * `bytes` - The complete contents of an EPUB file
+    pub fn from_bytes(bytes: impl Into<Arc<[u8]>>) -> Result<Epub, Box<dyn Error>> {
+        enter_span!("epub", source = "bytes");
+        Self::from_source(Source::Bytes(bytes.into()), ParseOptions::default())
+    }
+
+    /// Like `from_bytes`, but with non-default parse options
+    pub fn from_bytes_with_options(
+        bytes: impl Into<Arc<[u8]>>,
+        options: ParseOptions,
+    ) -> Result<Epub, Box<dyn Error>> {
+        enter_span!("epub", source = "bytes");
+        Self::from_source(Source::Bytes(bytes.into()), options)
+    }
+
+    pub(crate) fn from_source(
+        source: Source,
+        options: ParseOptions,
+    ) -> Result<Epub, Box<dyn Error>> {
+        Self::parse_source(source, options, &mut |_: ParseEvent<'_>| {})
+    }
+
+    /// Parse `source`, telling `sink` about each stage as it completes
+    pub(crate) fn parse_source(
+        source: Source,
+        options: ParseOptions,
+        sink: &mut dyn ParseEventSink,
+    ) -> Result<Epub, Box<dyn Error>> {
+        let tolerant = options.get_tolerant_hrefs();
+        let mut report = ParseReport::new();
+
+        let started = Instant::now();
+        let mut archive = source.open()?;
+        report.record_timing("archive", started.elapsed());
+
+        #[cfg(feature = "checksums")]
+        if options.get_checksum_verification() {
+            let started = Instant::now();
+            match crate::checksums::verify(&mut archive)? {
+                Some(mismatches) => mismatches
+                    .into_iter()
+                    .for_each(|mismatch| report.checksum_mismatch(mismatch)),
+                None => report.fallback("no META-INF/checksums.json to verify against"),
+            }
+            report.record_timing("checksums", started.elapsed());
+        }
 
         // Read and parse META-INF/container.xml
+        let started = Instant::now();
         let container = {
-            let mut container_file = archive.by_name("META-INF/container.xml")?;
+            enter_span!("container");
+            let mut container_file =
+                match archive::open_entry(&mut archive, "META-INF/container.xml", tolerant) {
+                    Ok(file) => file,
+                    Err(ZipError::FileNotFound) => return Err(EpubError::MissingContainer.into()),
+                    Err(e) => return Err(e.into()),
+                };
             let mut xml = String::new();
             container_file.read_to_string(&mut xml)?;
-            parse_container_xml(&xml)?
+            parse_container_xml(&xml).map_err(|e| EpubError::InvalidXml {
+                path: "META-INF/container.xml".to_string(),
+                message: e.to_string(),
+            })?
         };
+        report.record_timing("container", started.elapsed());
 
         // Get the OPF path and parse OPF file
-        let opf_path = &container.rootfiles.rootfile[0].full_path;
-        let package = {
-            let mut opf_file = archive.by_name(&opf_path)?;
+        let started = Instant::now();
+        let mut opf_path = container
+            .package_path()
+            .ok_or(EpubError::MissingRootfile)?
+            .to_string();
+        if tolerant {
+            opf_path = opf_path.replace('\\', "/");
+        }
+        let opf_path = opf_path.as_str();
+        let (package, metadata_elements) = {
+            enter_span!("opf", path = %opf_path);
+            let mut opf_file = match archive::open_entry(&mut archive, opf_path, tolerant) {
+                Ok(file) => file,
+                Err(ZipError::FileNotFound) => {
+                    return Err(EpubError::MissingPackage(opf_path.to_string()).into());
+                }
+                Err(e) => return Err(e.into()),
+            };
             let mut xml = String::new();
             opf_file.read_to_string(&mut xml)?;
-            parse_opf_xml(&xml)?
+            let package = parse_opf_xml(&xml).map_err(|e| EpubError::InvalidXml {
+                path: opf_path.to_string(),
+                message: e.to_string(),
+            })?;
+            (package, crate::extensions::metadata_elements(&xml))
         };
+        report.record_timing("opf", started.elapsed());
 
         // Parse navigation file to get chapter titles first
-        let nav_titles = Self::parse_navigation(&mut archive, &package, &opf_path)?;
+        let started = Instant::now();
+        let navigation = if options.get_metadata_only() {
+            Navigation::default()
+        } else {
+            Self::parse_navigation(&mut archive, &package, opf_path, tolerant, &mut report)?
+        };
+        report.record_timing("nav", started.elapsed());
 
         // Extract metadata from OPF
+        // The identifier `unique-identifier` points at, else the first
+        let identifiers = &package.metadata.identifier;
+        let identifier = identifiers
+            .iter()
+            .find(|identifier| {
+                identifier.id.is_some() && identifier.id == package.unique_identifier
+            })
+            .or_else(|| identifiers.first());
+        let unique_identifier = identifier
+            .and_then(|identifier| identifier.id.clone())
+            .unwrap_or_else(|| "uid".to_string());
         let mut metadata = Metadata::new(
-            package.metadata.title.clone(),
-            package.metadata.creator.clone().unwrap_or_default(),
+            None,
+            Vec::new(),
             package.metadata.language.clone(),
-            package
-                .metadata
-                .identifier
-                .first()
-                .cloned()
+            identifier
+                .map(|identifier| identifier.value.clone())
                 .unwrap_or_default(),
             package.metadata.date.clone(),
         );
 
         // Set optional metadata fields
+        metadata.titles = Self::parse_titles(&package.metadata);
+        metadata.creators = Self::parse_creators(&package.metadata);
+        metadata.creator = metadata.creators.iter().map(|c| c.name.clone()).collect();
         metadata.description = package.metadata.description.clone();
         metadata.publisher = package.metadata.publisher.clone();
         metadata.rights = package.metadata.rights.clone();
         metadata.tags = package.metadata.subject.clone();
 
         // Find cover from meta tags - handle both EPUB 2 and 3 formats
-        metadata.cover = Self::find_cover_id(&package);
+        metadata.cover = Self::find_cover_id(&package, &mut report);
+
+        if metadata.identifier.is_empty() {
+            report.fallback("no dc:identifier; identifier left empty");
+        }
+        sink.on_event(ParseEvent::MetadataReady(&metadata));
+        sink.on_event(ParseEvent::TocReady(&Self::preliminary_table_of_contents(
+            &package,
+            &navigation,
+            opf_path,
+        )));
 
         // Parse all XHTML files and create EpubFile objects
-        let all_files = Self::parse_all_files(&mut archive, &package, &nav_titles, &opf_path)?;
+        let started = Instant::now();
+        let all_files = if options.get_metadata_only() {
+            Vec::new()
+        } else {
+            Self::parse_all_files(
+                &mut archive,
+                &package,
+                &navigation,
+                opf_path,
+                tolerant,
+                &mut report,
+                sink,
+            )?
+        };
+        report.record_timing("files", started.elapsed());
+
+        let untitled = all_files.iter().filter(|file| file.title.is_none()).count();
+        if untitled > 0 {
+            report.fallback(format!(
+                "{} content document(s) have no navigation title; using {}",
+                untitled,
+                if options.get_heading_titles() {
+                    "first headings or manifest IDs"
+                } else {
+                    "manifest IDs"
+                }
+            ));
+        }
 
         // Create table of contents from navigation
-        let table_of_contents = Self::create_table_of_contents(&nav_titles, &all_files);
+        let started = Instant::now();
+        let table_of_contents =
+            Self::create_table_of_contents(&all_files, options.get_heading_titles());
 
         // Group files into chapters
-        let chapters = Self::group_files_into_chapters(&all_files, &package.spine);
+        let chapters = Self::group_files_into_chapters(
+            &all_files,
+            &package.spine,
+            options.get_heading_titles(),
+            metadata.get_language(),
+        );
+        report.record_timing("chapters", started.elapsed());
+
+        let resources = package
+            .manifest
+            .item
+            .iter()
+            .map(|item| Resource {
+                id: item.id.clone(),
+                href: item.href.clone(),
+                media_type: item.media_type.clone(),
+                properties: item.properties.clone(),
+                media_overlay: item.media_overlay.clone(),
+                fallback: item.fallback.clone(),
+            })
+            .collect();
+        let spine = package
+            .spine
+            .itemref
+            .iter()
+            .map(|itemref| itemref.idref.clone())
+            .collect();
+        let spine_items = package
+            .spine
+            .itemref
+            .iter()
+            .filter(|itemref| !itemref.linear || itemref.properties.is_some())
+            .map(|itemref| {
+                (
+                    itemref.idref.clone(),
+                    SpineItem {
+                        idref: itemref.idref.clone(),
+                        linear: itemref.linear,
+                        properties: itemref.properties.clone(),
+                        weight: None,
+                    },
+                )
+            })
+            .collect();
+
+        let meta = package
+            .metadata
+            .meta
+            .iter()
+            .map(|meta| MetaEntry {
+                id: meta.id.clone(),
+                property: meta.property.clone(),
+                refines: meta.refines.clone(),
+                scheme: meta.scheme.clone(),
+                value: meta.value.clone(),
+                name: meta.name.clone(),
+                content: meta.content.clone(),
+            })
+            .collect();
 
         Ok(Epub {
             metadata,
             chapters,
             table_of_contents,
             all_files,
-            file_path,
+            resources,
+            spine,
+            spine_items,
+            landmarks: navigation.landmarks,
+            version: package.version.clone(),
+            unique_identifier,
+            prefix: package.prefix.clone(),
+            page_progression_direction: package.spine.page_progression_direction.clone(),
+            meta,
+            metadata_elements,
+            overrides: BTreeMap::new(),
+            removed: BTreeSet::new(),
+            opf_path: opf_path.to_string(),
+            source,
+            options,
+            report,
+            text_index_cache: Default::default(),
+            #[cfg(feature = "dom")]
+            dom_cache: Default::default(),
         })
     }
 
@@ -465,52 +1224,167 @@ impl Epub {
         &self.metadata
     }
 
+    /// Get the diagnostics collected while parsing: skipped files, fallbacks and timings
+    pub fn get_parse_report(&self) -> &ParseReport {
+        &self.report
+    }
+
     /// Get cover image as bytes
     pub fn get_cover_bytes(&self) -> Option<Vec<u8>> {
         let cover_id = self.metadata.cover.as_ref()?;
-        
-        // Open the EPUB file
-        let file = File::open(&self.file_path).ok()?;
-        let mut archive = ZipArchive::new(file).ok()?;
+        let resource = self.get_resource_by_id(cover_id)?;
+        self.get_resource_bytes(resource.get_href())
+    }
 
-        // Read container.xml
-        let mut xml = String::new();
-        {
-            let mut container_file = archive.by_name("META-INF/container.xml").ok()?;
-            container_file.read_to_string(&mut xml).ok()?;
+    /// Get every manifest item, including non-HTML resources such as images and CSS
+    pub fn get_resources(&self) -> &[Resource] {
+        &self.resources
+    }
+
+    pub fn get_resource_by_id(&self, id: &str) -> Option<&Resource> {
+        self.resources.iter().find(|resource| resource.id == id)
+    }
+
+    pub fn get_resource_by_href(&self, href: &str) -> Option<&Resource> {
+        self.resources.iter().find(|resource| resource.href == href)
+    }
+
+    /// Get the manifest IDs referenced by the spine, in reading order
+    pub fn get_spine(&self) -> &[String] {
+        &self.spine
+    }
+
+    /// Get the spine itemrefs with their `linear` and `properties` attributes
+    pub fn get_spine_items(&self) -> Vec<SpineItem> {
+        self.spine
+            .iter()
+            .map(|idref| {
+                self.spine_items
+                    .get(idref)
+                    .cloned()
+                    .unwrap_or_else(|| SpineItem::new(idref))
+            })
+            .collect()
+    }
+
+    /// Like `get_spine_items`, with each item's `weight`
+    ///
+    /// Measuring needs the text index, which extracts the text of the whole
+    /// book the first time it is built.
+    pub fn get_weighted_spine_items(&self) -> Vec<SpineItem> {
+        let index = self.text_index();
+        let mut items = self.get_spine_items();
+        for item in &mut items {
+            item.weight = self
+                .get_resource_by_id(&item.idref)
+                .and_then(|resource| index.get_span(&resource.href))
+                .map(|span| span.get_weight());
         }
-        let container = parse_container_xml(&xml).ok()?;
-        let opf_path = &container.rootfiles.rootfile[0].full_path;
+        items
+    }
 
-        // Read OPF file
-        let mut opf_xml = String::new();
-        {
-            let mut opf_file = archive.by_name(opf_path).ok()?;
-            opf_file.read_to_string(&mut opf_xml).ok()?;
+    /// Get an editable copy of the package document (metadata, `<meta>`
+    /// elements, manifest and spine), e.g. to serialize with `Package::to_xml`
+    pub fn to_package(&self) -> Package {
+        let mut package = Package::from_parts(
+            self.version.clone(),
+            self.metadata.clone(),
+            self.meta.clone(),
+            self.resources.clone(),
+            self.spine.clone(),
+            self.spine_items.clone(),
+        );
+        package.set_unique_identifier(&self.unique_identifier);
+        if let Some(prefix) = &self.prefix {
+            package.set_prefix(prefix);
         }
-        let package = parse_opf_xml(&opf_xml).ok()?;
+        if let Some(direction) = &self.page_progression_direction {
+            package.set_page_progression_direction(direction);
+        }
+        for element in self.unmodeled_metadata_elements() {
+            package.add_element(element.clone());
+        }
+        package
+    }
 
-        // Find the manifest item with the cover id
-        let manifest_item = package
-            .manifest
-            .item
+    /// Metadata elements `Metadata` has no field for, which the package
+    /// writer copies through as they were: identifiers other than the
+    /// unique one, Dublin Core elements such as `dc:contributor` and
+    /// `dc:source`, repeats of single-valued ones, and `<link>`s
+    fn unmodeled_metadata_elements(&self) -> Vec<&MetadataElement> {
+        const MODELED: &[&str] = &[
+            "title",
+            "creator",
+            "language",
+            "date",
+            "publisher",
+            "description",
+            "rights",
+            "subject",
+        ];
+        let identifiers: Vec<&MetadataElement> = self
+            .metadata_elements
+            .iter()
+            .filter(|e| e.is_dublin_core() && e.get_local_name() == "identifier")
+            .collect();
+        let unique = identifiers
             .iter()
-            .find(|item| &item.id == cover_id)?;
-        
-        let cover_href = &manifest_item.href;
+            .position(|e| e.get_attribute("id") == Some(self.unique_identifier.as_str()))
+            .or((!identifiers.is_empty()).then_some(0))
+            .map(|index| identifiers[index]);
 
-        // Resolve the cover file path relative to the OPF directory
-        let cover_path = Self::resolve_path(opf_path, cover_href);
+        let mut seen = BTreeSet::new();
+        self.metadata_elements
+            .iter()
+            .filter(|element| {
+                let name = element.get_local_name();
+                if !element.is_dublin_core() {
+                    return name == "link" && element.get_namespace() == Some(OPF_NAMESPACE_URI);
+                }
+                if name == "identifier" {
+                    return !unique.is_some_and(|unique| std::ptr::eq(unique, *element));
+                }
+                let repeats = matches!(name, "title" | "creator" | "subject");
+                !MODELED.contains(&name) || !repeats && !seen.insert(name)
+            })
+            .collect()
+    }
+
+    /// Path of the OPF package document inside the archive
+    pub fn get_opf_path(&self) -> &str {
+        &self.opf_path
+    }
 
-        // Extract the cover file as bytes
+    /// Read the raw bytes of a resource, given its href relative to the OPF file
+    pub fn get_resource_bytes(&self, href: &str) -> Option<Vec<u8>> {
+        let path = Self::resolve_path(&self.opf_path, href);
+        if let Some(bytes) = self.overrides.get(&path) {
+            return Some(bytes.clone());
+        }
+        if self.removed.contains(&path) {
+            return None;
+        }
+
+        let mut archive = self.open_archive().ok()?;
         let mut buf = Vec::new();
         {
-            let mut cover_file = archive.by_name(&cover_path).ok()?;
-            cover_file.read_to_end(&mut buf).ok()?;
+            let mut entry =
+                archive::open_entry(&mut archive, &path, self.options.get_tolerant_hrefs()).ok()?;
+            entry.read_to_end(&mut buf).ok()?;
         }
         Some(buf)
     }
 
+    /// Re-open the underlying zip archive for on-demand resource access
+    pub(crate) fn open_archive(&self) -> Result<ZipArchive<ArchiveReader>, Box<dyn Error>> {
+        self.source.open()
+    }
+
+    /// Options this book was parsed with
+    pub fn get_parse_options(&self) -> &ParseOptions {
+        &self.options
+    }
+
     pub fn get_chapters(&self) -> &[Chapter] {
         &self.chapters
     }
@@ -523,168 +1397,447 @@ impl Epub {
         &self.table_of_contents
     }
 
+    /// Get every content document in manifest order, including ones not in the spine
     pub fn get_all_files(&self) -> &[EpubFile] {
         &self.all_files
     }
 
+    /// Get the content documents referenced by the spine, in reading order
+    ///
+    /// Unlike `get_all_files`, this is the right list for progress math. Each
+    /// file's `get_spine_index()` points into `get_spine()`; files that are
+    /// only reachable through links are left out.
+    pub fn get_spine_files(&self) -> Vec<&EpubFile> {
+        let mut files: Vec<&EpubFile> = self
+            .all_files
+            .iter()
+            .filter(|file| file.is_in_spine())
+            .collect();
+        files.sort_by_key(|file| file.spine_index);
+        files
+    }
+
+    /// Get the landmarks from the navigation document, plus any EPUB 2 guide
+    /// references for documents the landmarks don't cover
+    pub fn get_landmarks(&self) -> &[Landmark] {
+        &self.landmarks
+    }
+
+    /// Build a table of contents from the headings in the spine documents
+    ///
+    /// Intended for books whose navigation is missing or useless. Headings up
+    /// to `<h{max_level}>` are collected in reading order; the shallowest
+    /// heading level found becomes level 0. Entries link to the heading's
+    /// `id` when it has one, otherwise to the start of its document.
+    pub fn generate_toc_from_headings(&self, max_level: usize) -> TableOfContents {
+        let mut headings = Vec::new();
+        for file in self.get_spine_files() {
+            for (level, title, id) in crate::text::find_headings(&file.content, max_level) {
+                let href = match id {
+                    Some(id) => format!("{}#{}", file.href, id),
+                    None => file.href.to_string(),
+                };
+                headings.push((level, title, href));
+            }
+        }
+
+        let top_level = headings.iter().map(|(level, ..)| *level).min().unwrap_or(1);
+        let mut toc = TableOfContents::new();
+        for (level, title, href) in headings {
+            toc.add_entry(title, href, level - top_level);
+        }
+        toc
+    }
+
+    /// Where reading should begin, as an href relative to the OPF file
+    ///
+    /// Uses the `bodymatter` landmark or EPUB 2 guide `text` reference when
+    /// the book has one, then the first spine item classified as body
+    /// content, and finally the first spine item. The href may carry a
+    /// fragment pointing inside the document.
+    pub fn start_of_content(&self) -> Option<String> {
+        if let Some(landmark) = self
+            .landmarks
+            .iter()
+            .find(|landmark| landmark.is_body_start())
+        {
+            return Some(landmark.get_href().to_string());
+        }
+
+        let spine_files = self.get_spine_files();
+        spine_files
+            .iter()
+            .find(|file| file.semantic_role == SemanticRole::Body)
+            .or_else(|| spine_files.first())
+            .map(|file| file.href.to_string())
+    }
+
     pub fn get_file_count(&self) -> usize {
         self.all_files.len()
     }
 
+    /// Collect the non-empty `dc:title`s with their `title-type` and
+    /// `display-seq` refinements, sorted by `display-seq`
+    fn parse_titles(metadata: &OpfMetadata) -> Vec<Title> {
+        let refinement = |id: &Option<String>, property| metadata.refinement(id, property);
+        let mut titles: Vec<(Option<u32>, Title)> = metadata
+            .title
+            .iter()
+            .filter(|title| !title.value.trim().is_empty())
+            .map(|title| {
+                let display_seq =
+                    refinement(&title.id, "display-seq").and_then(|seq| seq.trim().parse().ok());
+                let title_type = refinement(&title.id, "title-type")
+                    .and_then(|value| TitleType::from_property(&value));
+                let title = Title {
+                    id: title.id.clone(),
+                    value: title.value.trim().to_string(),
+                    title_type,
+                    file_as: refinement(&title.id, "file-as"),
+                };
+                (display_seq, title)
+            })
+            .collect();
+        // Titles without a display-seq keep their place after the numbered ones
+        titles.sort_by_key(|(seq, _)| seq.unwrap_or(u32::MAX));
+        let mut titles: Vec<Title> = titles.into_iter().map(|(_, title)| title).collect();
+
+        let main = titles
+            .iter()
+            .position(|t| t.title_type == Some(TitleType::Main))
+            .unwrap_or(0);
+        if let Some(main) = titles.get_mut(main)
+            && main.file_as.is_none()
+        {
+            main.file_as = metadata.named("calibre:title_sort");
+        }
+        titles
+    }
+
+    /// Collect the non-empty `dc:creator`s with their `file-as` and `role`,
+    /// from EPUB 3 refinements or EPUB 2 `opf:` attributes
+    ///
+    /// calibre's `author_sort` is used for the first creator when nothing
+    /// else gives it a sort key.
+    fn parse_creators(metadata: &OpfMetadata) -> Vec<Creator> {
+        let mut creators: Vec<Creator> = metadata
+            .creator
+            .iter()
+            .filter(|creator| !creator.value.trim().is_empty())
+            .map(|creator| Creator {
+                id: creator.id.clone(),
+                name: creator.value.trim().to_string(),
+                file_as: creator
+                    .file_as
+                    .clone()
+                    .or_else(|| metadata.refinement(&creator.id, "file-as")),
+                role: creator
+                    .role
+                    .clone()
+                    .or_else(|| metadata.refinement(&creator.id, "role")),
+            })
+            .collect();
+        if let Some(first) = creators.first_mut()
+            && first.file_as.is_none()
+        {
+            first.file_as = metadata.named("calibre:author_sort");
+        }
+        creators
+    }
+
     /// Find cover ID from metadata - handles both EPUB 2 and 3 formats
-    fn find_cover_id(package: &Package) -> Option<String> {
+    fn find_cover_id(package: &OpfPackage, report: &mut ParseReport) -> Option<String> {
         // EPUB 2: Look for meta with name="cover"
         for meta in &package.metadata.meta {
-            if let (Some(name), Some(content)) = (&meta.name, &meta.content) {
-                if name == "cover" {
-                    return Some(content.clone());
-                }
+            if let (Some(name), Some(content)) = (&meta.name, &meta.content)
+                && name == "cover"
+            {
+                return Some(content.clone());
             }
         }
 
         // EPUB 3: Look for meta with property="cover-image"
         for meta in &package.metadata.meta {
-            if let Some(property) = &meta.property {
-                if property == "cover-image" {
-                    if let Some(content) = &meta.content {
-                        return Some(content.clone());
-                    }
-                    // Sometimes the ID is in the text content
-                    if let Some(value) = &meta.value {
-                        return Some(value.clone());
-                    }
+            if let Some(property) = &meta.property
+                && property == "cover-image"
+            {
+                if let Some(content) = &meta.content {
+                    report
+                        .fallback("cover taken from non-standard <meta property=\"cover-image\">");
+                    return Some(content.clone());
+                }
+                // Sometimes the ID is in the text content
+                if let Some(value) = &meta.value {
+                    report
+                        .fallback("cover taken from non-standard <meta property=\"cover-image\">");
+                    return Some(value.clone());
                 }
             }
         }
 
         // Fallback: Look for manifest items with properties="cover-image"
         for item in &package.manifest.item {
-            if let Some(properties) = &item.properties {
-                if properties.contains("cover-image") {
-                    return Some(item.id.clone());
-                }
+            if let Some(properties) = &item.properties
+                && properties.contains("cover-image")
+            {
+                return Some(item.id.clone());
             }
         }
 
         None
     }
 
-    /// Resolve a relative path against a base path
-    fn resolve_path(base_path: &str, relative_path: &str) -> String {
-        if let Some(slash_pos) = base_path.rfind('/') {
-            format!("{}/{}", &base_path[..slash_pos], relative_path)
-        } else {
-            relative_path.to_string()
+    /// Resolve an href against the archive path of the document it appears in
+    ///
+    /// Handles `..` segments, fragments and percent-encoding; see `href::resolve`.
+    pub(crate) fn resolve_path(base_path: &str, relative_path: &str) -> String {
+        href::resolve(base_path, relative_path)
+    }
+
+    /// Re-express an href found in the document at `from` relative to the OPF,
+    /// keeping its fragment
+    pub(crate) fn rebase_href(from: &str, opf_path: &str, reference: &str) -> String {
+        let path = Self::resolve_path(from, reference);
+        if href::has_scheme(reference) {
+            return reference.to_string();
+        }
+        let rebased = href::relative(opf_path, &path);
+        match href::fragment(reference) {
+            Some(fragment) => format!("{}#{}", rebased, fragment),
+            None => rebased,
         }
     }
 
     fn parse_navigation(
-        archive: &mut ZipArchive<File>,
-        package: &Package,
+        archive: &mut ZipArchive<ArchiveReader>,
+        package: &OpfPackage,
         opf_path: &str,
-    ) -> Result<HashMap<String, String>, Box<dyn Error>> {
-        let mut nav_titles = HashMap::new();
+        tolerant: bool,
+        report: &mut ParseReport,
+    ) -> Result<Navigation, Box<dyn Error>> {
+        enter_span!("nav");
+        let mut navigation = Navigation::default();
 
         // Find the navigation file in the manifest
         if let Some(nav_item) = package.manifest.item.iter().find(|item| {
             item.properties
                 .as_ref()
-                .map_or(false, |props| props.contains("nav"))
+                .is_some_and(|props| props.contains("nav"))
         }) {
             let nav_path = Self::resolve_path(opf_path, &nav_item.href);
+            trace_debug!(path = %nav_path, "parsing navigation document");
 
             // Try to parse the navigation file
-            if let Ok(mut nav_file) = archive.by_name(&nav_path) {
+            if let Ok(mut nav_file) = archive::open_entry(archive, &nav_path, tolerant) {
                 let mut html = String::new();
                 if nav_file.read_to_string(&mut html).is_ok() {
-                    // Use regex to extract href and text from <a> tags
-                    let pattern = r#"<a\s+href="([^"]+)"[^>]*>([^<]+)</a>"#;
-                    if let Ok(re) = Regex::new(pattern) {
-                        for cap in re.captures_iter(&html) {
-                            if let (Some(href), Some(text)) = (cap.get(1), cap.get(2)) {
-                                let href_str = href.as_str().to_string();
-                                let text_str = text.as_str().trim().to_string();
-                                nav_titles.insert(href_str, text_str);
-                            }
-                        }
+                    // Key titles by archive path so they match manifest items no
+                    // matter how either side spells the href. The first link to
+                    // a file wins, since later ones usually point at sections.
+                    for (href, title) in extract_nav_titles(&html) {
+                        navigation
+                            .titles
+                            .entry(Self::resolve_path(&nav_path, &href))
+                            .or_insert(title);
+                    }
+                    for (href, kind) in semantics::extract_landmarks(&html) {
+                        let target = Self::rebase_href(&nav_path, opf_path, &href);
+                        navigation.landmarks.push(Landmark::new(kind, target));
                     }
                 }
+            } else {
+                trace_warn!(path = %nav_path, "navigation document missing from archive");
+                report.skip(&nav_item.href, "navigation document missing from archive");
             }
+        } else {
+            trace_debug!("no navigation document in manifest");
+            report.fallback("no navigation document in manifest");
         }
 
-        Ok(nav_titles)
+        // EPUB 2 guide references fill in for documents without a landmark
+        if let Some(guide) = &package.guide {
+            for reference in &guide.reference {
+                let path = Self::resolve_path(opf_path, &reference.href);
+                let covered = navigation
+                    .landmarks
+                    .iter()
+                    .any(|landmark| Self::resolve_path(opf_path, landmark.get_href()) == path);
+                if !covered {
+                    let target = Self::rebase_href(opf_path, opf_path, &reference.href);
+                    navigation
+                        .landmarks
+                        .push(Landmark::new(&reference.kind, target));
+                }
+            }
+        }
+
+        Ok(navigation)
     }
 
     fn parse_all_files(
-        archive: &mut ZipArchive<File>,
-        package: &Package,
-        nav_titles: &HashMap<String, String>,
+        archive: &mut ZipArchive<ArchiveReader>,
+        package: &OpfPackage,
+        navigation: &Navigation,
         opf_path: &str,
+        tolerant: bool,
+        report: &mut ParseReport,
+        sink: &mut dyn ParseEventSink,
     ) -> Result<Vec<EpubFile>, Box<dyn Error>> {
+        enter_span!("files");
         let mut files = Vec::new();
+        // One shared string per media type, however many files use it
+        let mut media_types: HashMap<&str, Arc<str>> = HashMap::new();
+
+        let mut spine_positions = HashMap::new();
+        for (index, itemref) in package.spine.itemref.iter().enumerate() {
+            spine_positions
+                .entry(itemref.idref.as_str())
+                .or_insert(index);
+        }
 
         for manifest_item in &package.manifest.item {
-            if manifest_item.media_type == "application/xhtml+xml" {
+            if is_content_media_type(&manifest_item.media_type) {
                 // Skip navigation files
                 let is_nav = manifest_item
                     .properties
                     .as_ref()
-                    .map_or(false, |props| props.contains("nav"));
+                    .is_some_and(|props| props.contains("nav"));
 
                 if is_nav {
                     continue;
                 }
 
                 let file_path = Self::resolve_path(opf_path, &manifest_item.href);
+                enter_span!("file", href = %manifest_item.href);
 
-                if let Ok(mut file) = archive.by_name(&file_path) {
+                if let Ok(mut file) = archive::open_entry(archive, &file_path, tolerant) {
                     let mut content = String::new();
                     if file.read_to_string(&mut content).is_ok() {
                         let epub_file = EpubFile {
-                            id: manifest_item.id.clone(),
-                            href: manifest_item.href.clone(),
-                            title: nav_titles.get(&manifest_item.href).cloned(),
+                            id: manifest_item.id.as_str().into(),
+                            href: manifest_item.href.as_str().into(),
+                            title: navigation.titles.get(&file_path).cloned(),
                             content,
-                            media_type: manifest_item.media_type.clone(),
+                            media_type: media_types
+                                .entry(&manifest_item.media_type)
+                                .or_insert_with(|| manifest_item.media_type.as_str().into())
+                                .clone(),
+                            spine_index: spine_positions.get(manifest_item.id.as_str()).copied(),
+                            semantic_role: SemanticRole::Body,
                         };
 
+                        sink.on_event(ParseEvent::FileParsed(&epub_file.href));
                         files.push(epub_file);
+                    } else {
+                        trace_warn!(path = %file_path, "content document is not valid UTF-8");
+                        report.skip(&manifest_item.href, "content document is not valid UTF-8");
                     }
+                } else {
+                    trace_warn!(path = %file_path, "content document missing from archive");
+                    report.skip(&manifest_item.href, "content document missing from archive");
                 }
             }
         }
 
+        let paths: Vec<String> = files
+            .iter()
+            .map(|file| Self::resolve_path(opf_path, &file.href))
+            .collect();
+        let described: Vec<_> = files
+            .iter()
+            .zip(&paths)
+            .map(|(file, path)| {
+                (
+                    path.as_str(),
+                    &*file.id,
+                    file.content.as_str(),
+                    file.spine_index,
+                )
+            })
+            .collect();
+        let mut landmarks = HashMap::new();
+        for landmark in &navigation.landmarks {
+            landmarks
+                .entry(Self::resolve_path(opf_path, landmark.get_href()))
+                .or_insert_with(|| landmark.get_kind().to_string());
+        }
+        let roles = semantics::classify(&described, &landmarks);
+        for (file, role) in files.iter_mut().zip(roles) {
+            file.semantic_role = role;
+        }
+
+        trace_debug!(count = files.len(), "parsed content documents");
         Ok(files)
     }
 
-    fn create_table_of_contents(
-        _nav_titles: &HashMap<String, String>,
-        all_files: &[EpubFile],
+    /// The table of contents `create_table_of_contents` will produce, as far
+    /// as the navigation alone tells: untitled documents use their manifest
+    /// ID, and documents that turn out to be unreadable are still listed
+    fn preliminary_table_of_contents(
+        package: &OpfPackage,
+        navigation: &Navigation,
+        opf_path: &str,
     ) -> TableOfContents {
         let mut toc = TableOfContents::new();
+        for item in &package.manifest.item {
+            let is_nav = item
+                .properties
+                .as_ref()
+                .is_some_and(|props| props.contains("nav"));
+            if !is_content_media_type(&item.media_type) || is_nav {
+                continue;
+            }
+            let title = navigation
+                .titles
+                .get(&Self::resolve_path(opf_path, &item.href))
+                .cloned()
+                .unwrap_or_else(|| item.id.clone());
+            toc.add_entry(title, item.href.clone(), 0);
+        }
+        toc
+    }
+
+    fn create_table_of_contents(all_files: &[EpubFile], heading_titles: bool) -> TableOfContents {
+        let mut toc = TableOfContents::new();
 
         // Add entries for all content files in spine order
         for file in all_files {
-            let title = file.title.clone().unwrap_or_else(|| file.id.clone());
+            let title = Self::display_title(file, heading_titles);
             toc.add_entry(title, file.href.clone(), 0);
         }
 
         toc
     }
 
-    fn group_files_into_chapters(all_files: &[EpubFile], spine: &Spine) -> Vec<Chapter> {
+    /// Title to show for a file: its navigation title, else its first heading
+    /// (if enabled), else its manifest ID
+    fn display_title(file: &EpubFile, heading_titles: bool) -> String {
+        file.title
+            .clone()
+            .or_else(|| {
+                heading_titles
+                    .then(|| crate::text::first_heading(&file.content))
+                    .flatten()
+            })
+            .unwrap_or_else(|| file.id.to_string())
+    }
+
+    fn group_files_into_chapters(
+        all_files: &[EpubFile],
+        spine: &Spine,
+        heading_titles: bool,
+        language: Option<&str>,
+    ) -> Vec<Chapter> {
         let mut chapters = Vec::new();
         let mut current_chapter_files = Vec::new();
         let mut current_chapter_title = String::new();
 
         // Create a map from ID to file for easy lookup
-        let file_map: HashMap<String, &EpubFile> = all_files
-            .iter()
-            .map(|file| (file.id.clone(), file))
-            .collect();
+        let file_map: HashMap<&str, &EpubFile> =
+            all_files.iter().map(|file| (&*file.id, file)).collect();
 
         for itemref in &spine.itemref {
-            if let Some(file) = file_map.get(&itemref.idref) {
+            if let Some(file) = file_map.get(itemref.idref.as_str()) {
                 // Determine if this should start a new chapter
                 let should_start_new_chapter = if current_chapter_files.is_empty() {
                     true
@@ -693,7 +1846,9 @@ impl Epub {
                     // 1. The file has a title from navigation
                     // 2. The base name changes significantly
                     file.title.is_some()
-                        && !Self::files_belong_to_same_chapter(&current_chapter_files[0], file)
+                        && current_chapter_files
+                            .first()
+                            .is_some_and(|first| !Self::files_belong_to_same_chapter(first, file))
                 };
 
                 if should_start_new_chapter && !current_chapter_files.is_empty() {
@@ -701,6 +1856,7 @@ impl Epub {
                     let chapter = Chapter {
                         title: current_chapter_title.clone(),
                         files: current_chapter_files.clone(),
+                        language: language.map(str::to_string),
                     };
                     chapters.push(chapter);
                     current_chapter_files.clear();
@@ -708,7 +1864,7 @@ impl Epub {
 
                 if current_chapter_files.is_empty() {
                     // Starting a new chapter
-                    current_chapter_title = file.title.clone().unwrap_or_else(|| file.id.clone());
+                    current_chapter_title = Self::display_title(file, heading_titles);
                 }
 
                 current_chapter_files.push((*file).clone());
@@ -720,6 +1876,7 @@ impl Epub {
             let chapter = Chapter {
                 title: current_chapter_title,
                 files: current_chapter_files,
+                language: language.map(str::to_string),
             };
             chapters.push(chapter);
         }
@@ -749,14 +1906,598 @@ impl Epub {
     }
 }
 
-// Function to parse container.xml using serde-xml-rs
-fn parse_container_xml(xml: &str) -> Result<Container, Box<dyn Error>> {
-    let container: Container = serde_xml_rs::from_str(xml)?;
-    Ok(container)
+/// Extract (href, link text) pairs from a navigation document, in document order
+pub(crate) fn extract_nav_titles(html: &str) -> Vec<(String, String)> {
+    let mut nav_titles = Vec::new();
+
+    // Use regex to extract href and text from <a> tags
+    static LINK: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"<a\s+href="([^"]+)"[^>]*>([^<]+)</a>"#).expect("nav link pattern")
+    });
+    for cap in LINK.captures_iter(html) {
+        if let (Some(href), Some(text)) = (cap.get(1), cap.get(2)) {
+            let href_str = href.as_str().to_string();
+            let text_str = text.as_str().trim().to_string();
+            nav_titles.push((href_str, text_str));
+        }
+    }
+
+    nav_titles
+}
+
+/// The Dublin Core elements and terms namespaces, both used for OPF metadata
+pub(crate) const DC_NAMESPACES: [&str; 2] = [
+    "http://purl.org/dc/elements/1.1/",
+    "http://purl.org/dc/terms/",
+];
+const OPF_NAMESPACE_URI: &str = "http://www.idpf.org/2007/opf";
+const OPF_NAMESPACE: &[u8] = OPF_NAMESPACE_URI.as_bytes();
+
+/// The vocabulary an OPF element or attribute name belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Vocabulary {
+    DublinCore,
+    Opf,
+    Other,
+}
+
+impl Vocabulary {
+    /// Unprefixed names count as OPF, since plenty of books leave out the
+    /// default namespace, and the usual `dc:`/`opf:` prefixes are honoured
+    /// even when they were never declared
+    fn of(namespace: &ResolveResult) -> Self {
+        match namespace {
+            ResolveResult::Bound(Namespace(uri))
+                if DC_NAMESPACES.iter().any(|dc| dc.as_bytes() == *uri) =>
+            {
+                Vocabulary::DublinCore
+            }
+            ResolveResult::Bound(Namespace(uri)) if *uri == OPF_NAMESPACE => Vocabulary::Opf,
+            ResolveResult::Unbound => Vocabulary::Opf,
+            ResolveResult::Unknown(prefix) => match prefix.as_slice() {
+                b"dc" | b"dcterms" => Vocabulary::DublinCore,
+                b"opf" => Vocabulary::Opf,
+                _ => Vocabulary::Other,
+            },
+            _ => Vocabulary::Other,
+        }
+    }
+}
+
+/// Unescape XML text, decoding HTML entities quick-xml doesn't know
+fn unescape(raw: &[u8], unescaped: quick_xml::Result<Cow<'_, str>>) -> String {
+    match unescaped {
+        Ok(text) => text.into_owned(),
+        Err(_) => crate::text::decode_entities(&String::from_utf8_lossy(raw)),
+    }
+}
+
+/// OPF attributes of `element` by local name
+fn opf_attributes(reader: &NsReader<&[u8]>, element: &BytesStart) -> HashMap<String, String> {
+    element
+        .attributes()
+        .with_checks(false)
+        .flatten()
+        .filter_map(|attribute| {
+            let (namespace, local) = reader.resolve_attribute(attribute.key);
+            (Vocabulary::of(&namespace) == Vocabulary::Opf).then(|| {
+                (
+                    String::from_utf8_lossy(local.as_ref()).into_owned(),
+                    unescape(&attribute.value, attribute.unescape_value()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Parse `META-INF/container.xml`
+pub(crate) fn parse_container_xml(xml: &str) -> Result<Container, Box<dyn Error>> {
+    let mut reader = Reader::from_str(xml);
+    let mut rootfiles = RootFiles::default();
+    let mut in_rootfiles = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) if element.local_name().as_ref() == b"rootfiles" => {
+                in_rootfiles = true;
+            }
+            Event::End(element) if element.local_name().as_ref() == b"rootfiles" => {
+                in_rootfiles = false;
+            }
+            Event::Start(element) | Event::Empty(element)
+                if in_rootfiles && element.local_name().as_ref() == b"rootfile" =>
+            {
+                let mut rootfile = RootFile {
+                    full_path: String::new(),
+                    media_type: String::new(),
+                };
+                for attribute in element.attributes().with_checks(false).flatten() {
+                    let value = unescape(&attribute.value, attribute.unescape_value());
+                    match attribute.key.local_name().as_ref() {
+                        b"full-path" => rootfile.full_path = value,
+                        b"media-type" => rootfile.media_type = value,
+                        _ => {}
+                    }
+                }
+                rootfiles.rootfile.push(rootfile);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(Container { rootfiles })
+}
+
+/// A metadata element whose text is being collected
+struct OpenField {
+    depth: usize,
+    vocabulary: Vocabulary,
+    name: String,
+    attributes: HashMap<String, String>,
+    text: String,
+}
+
+/// Parse an OPF package document
+///
+/// Elements and attributes are matched by namespace rather than prefix, so
+/// `<ns0:title xmlns:ns0="http://purl.org/dc/elements/1.1/">` is a title.
+pub(crate) fn parse_opf_xml(xml: &str) -> Result<OpfPackage, Box<dyn Error>> {
+    let mut reader = NsReader::from_str(xml);
+    let mut version = None;
+    let mut unique_identifier = None;
+    let mut prefix = None;
+    let mut metadata = None;
+    let mut manifest = None;
+    let mut spine = None;
+    let mut guide = None;
+    // The OPF element that encloses the current one at depth 1
+    let mut section = String::new();
+    let mut depth = 0;
+    let mut field: Option<OpenField> = None;
+
+    loop {
+        let (namespace, event) = reader.read_resolved_event()?;
+        let vocabulary = Vocabulary::of(&namespace);
+        let (element, empty) = match event {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::Text(text) => {
+                if let Some(field) = &mut field {
+                    field.text.push_str(&unescape(&text, text.unescape()));
+                }
+                continue;
+            }
+            Event::CData(data) => {
+                if let Some(field) = &mut field {
+                    field.text.push_str(&String::from_utf8_lossy(&data));
+                }
+                continue;
+            }
+            Event::End(_) => {
+                if field.as_ref().is_some_and(|field| field.depth == depth)
+                    && let Some(field) = field.take()
+                    && let Some(metadata) = &mut metadata
+                {
+                    add_metadata_field(metadata, field);
+                }
+                depth = depth.saturating_sub(1);
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        depth += 1;
+        let name = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+        let opf = vocabulary == Vocabulary::Opf;
+        match depth {
+            1 => {
+                let mut attributes = opf_attributes(&reader, &element);
+                version = attributes.remove("version");
+                unique_identifier = attributes.remove("unique-identifier");
+                prefix = attributes.remove("prefix");
+            }
+            2 if opf => {
+                section = name.clone();
+                match name.as_str() {
+                    "metadata" => metadata = metadata.or_else(|| Some(OpfMetadata::default())),
+                    "manifest" => manifest = manifest.or_else(|| Some(Manifest::default())),
+                    "spine" => {
+                        spine = spine.or_else(|| {
+                            Some(Spine {
+                                page_progression_direction: opf_attributes(&reader, &element)
+                                    .remove("page-progression-direction"),
+                                ..Spine::default()
+                            })
+                        })
+                    }
+                    "guide" => guide = guide.or_else(|| Some(Guide::default())),
+                    _ => section.clear(),
+                }
+            }
+            2 => section.clear(),
+            _ => {}
+        }
+
+        if depth > 2 {
+            match (section.as_str(), name.as_str()) {
+                // Text inside a field's child elements belongs to the field
+                _ if field.is_some() => {}
+                ("metadata", _)
+                    if vocabulary == Vocabulary::DublinCore || opf && name == "meta" =>
+                {
+                    field = Some(OpenField {
+                        depth,
+                        vocabulary,
+                        name,
+                        attributes: opf_attributes(&reader, &element),
+                        text: String::new(),
+                    });
+                }
+                ("manifest", "item") if opf => {
+                    let mut attributes = opf_attributes(&reader, &element);
+                    let mut required = |key: &str| {
+                        attributes
+                            .remove(key)
+                            .ok_or_else(|| format!("manifest item without {}", key))
+                    };
+                    let item = ManifestItem {
+                        id: required("id")?,
+                        href: required("href")?,
+                        media_type: required("media-type")?,
+                        properties: attributes.remove("properties"),
+                        media_overlay: attributes.remove("media-overlay"),
+                        fallback: attributes.remove("fallback"),
+                    };
+                    if let Some(manifest) = &mut manifest {
+                        manifest.item.push(item);
+                    }
+                }
+                ("spine", "itemref") if opf => {
+                    let mut attributes = opf_attributes(&reader, &element);
+                    let idref = attributes
+                        .remove("idref")
+                        .ok_or("spine itemref without idref")?;
+                    if let Some(spine) = &mut spine {
+                        spine.itemref.push(ItemRef {
+                            idref,
+                            linear: attributes.remove("linear").as_deref() != Some("no"),
+                            properties: attributes.remove("properties"),
+                        });
+                    }
+                }
+                ("guide", "reference") if opf => {
+                    let mut attributes = opf_attributes(&reader, &element);
+                    if let Some(guide) = &mut guide {
+                        guide.reference.push(GuideReference {
+                            kind: attributes.remove("type").unwrap_or_default(),
+                            href: attributes.remove("href").unwrap_or_default(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if empty {
+            if field.as_ref().is_some_and(|field| field.depth == depth)
+                && let Some(field) = field.take()
+                && let Some(metadata) = &mut metadata
+            {
+                add_metadata_field(metadata, field);
+            }
+            depth -= 1;
+        }
+    }
+
+    Ok(OpfPackage {
+        version: version.unwrap_or_default(),
+        unique_identifier,
+        prefix,
+        metadata: metadata.ok_or("package has no <metadata>")?,
+        manifest: manifest.ok_or("package has no <manifest>")?,
+        spine: spine.ok_or("package has no <spine>")?,
+        guide,
+    })
+}
+
+/// Store a finished metadata element; the first of each single-valued field wins
+fn add_metadata_field(metadata: &mut OpfMetadata, field: OpenField) {
+    let OpenField {
+        vocabulary,
+        name,
+        mut attributes,
+        text,
+        ..
+    } = field;
+    let value = text.trim().to_string();
+    let first = |slot: &mut Option<String>| {
+        slot.get_or_insert(value.clone());
+    };
+    match (vocabulary, name.as_str()) {
+        (Vocabulary::DublinCore, "identifier") => metadata.identifier.push(DcIdentifier {
+            id: attributes.remove("id"),
+            value,
+        }),
+        (Vocabulary::DublinCore, "title") => metadata.title.push(DcTitle {
+            id: attributes.remove("id"),
+            value,
+        }),
+        (Vocabulary::DublinCore, "creator") => metadata.creator.push(DcCreator {
+            id: attributes.remove("id"),
+            file_as: attributes.remove("file-as"),
+            role: attributes.remove("role"),
+            value,
+        }),
+        (Vocabulary::DublinCore, "language") => first(&mut metadata.language),
+        (Vocabulary::DublinCore, "date") => first(&mut metadata.date),
+        (Vocabulary::DublinCore, "description") => first(&mut metadata.description),
+        (Vocabulary::DublinCore, "publisher") => first(&mut metadata.publisher),
+        (Vocabulary::DublinCore, "rights") => first(&mut metadata.rights),
+        (Vocabulary::DublinCore, "subject") => metadata.subject.push(value),
+        (Vocabulary::Opf, "meta") => metadata.meta.push(Meta {
+            id: attributes.remove("id"),
+            name: attributes.remove("name"),
+            content: attributes.remove("content"),
+            property: attributes.remove("property"),
+            refines: attributes.remove("refines"),
+            scheme: attributes.remove("scheme"),
+            value: (!value.is_empty()).then_some(value),
+        }),
+        _ => {}
+    }
 }
 
-// Function to parse OPF file using serde-xml-rs
-fn parse_opf_xml(xml: &str) -> Result<Package, Box<dyn Error>> {
-    let package: Package = serde_xml_rs::from_str(xml)?;
-    Ok(package)
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn file_strings_are_shared_with_chapters_and_the_toc() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><h1>One</h1></body></html>"),
+            ("ch2.xhtml", "<html><body><h1>Two</h1></body></html>"),
+        ]))
+        .unwrap();
+
+        let files = epub.get_all_files();
+        let chapter_files: Vec<&EpubFile> = epub
+            .get_chapters()
+            .iter()
+            .flat_map(|chapter| chapter.get_files())
+            .collect();
+        let entries = epub.get_table_of_contents().get_entries();
+        assert_eq!(chapter_files.len(), files.len());
+        assert_eq!(entries.len(), files.len());
+        for ((file, copy), entry) in files.iter().zip(&chapter_files).zip(entries) {
+            assert!(Arc::ptr_eq(&file.id, &copy.id));
+            assert!(Arc::ptr_eq(&file.href, &copy.href));
+            assert!(Arc::ptr_eq(&file.media_type, &copy.media_type));
+            assert!(Arc::ptr_eq(&file.href, &entry.href));
+        }
+        // Every file of a media type shares one string
+        assert!(Arc::ptr_eq(&files[0].media_type, &files[1].media_type));
+    }
+
+    #[test]
+    fn spine_files_follow_the_first_itemref_of_each_document() {
+        let opf = package(
+            r#"<item id="a" href="a.xhtml" media-type="application/xhtml+xml"/>
+               <item id="b" href="b.xhtml" media-type="application/xhtml+xml"/>
+               <item id="c" href="c.xhtml" media-type="application/xhtml+xml"/>
+               <item id="extra" href="extra.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="c"/><itemref idref="a"/><itemref idref="c"/><itemref idref="b"/>"#,
+        );
+        let page = "<html><body><p>Text</p></body></html>";
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("a.xhtml", page),
+            ("b.xhtml", page),
+            ("c.xhtml", page),
+            ("extra.xhtml", page),
+        ]))
+        .unwrap();
+
+        let spine: Vec<_> = epub
+            .get_spine_files()
+            .into_iter()
+            .map(|file| (file.get_href(), file.get_spine_index()))
+            .collect();
+        assert_eq!(
+            spine,
+            [
+                ("c.xhtml", Some(0)),
+                ("a.xhtml", Some(1)),
+                ("b.xhtml", Some(3))
+            ]
+        );
+        // Manifest order is untouched, and files outside the spine are flagged
+        let all: Vec<_> = epub
+            .get_all_files()
+            .iter()
+            .map(|file| file.get_href())
+            .collect();
+        assert_eq!(all, ["a.xhtml", "b.xhtml", "c.xhtml", "extra.xhtml"]);
+        assert!(!epub.get_all_files()[3].is_in_spine());
+    }
+
+    #[test]
+    fn text_html_documents_are_content() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.html" media-type="text/html"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.html",
+                "<html><body><p>Unclosed<br><p>tags</body></html>",
+            ),
+            ("ch2.xhtml", "<html><body><p>Two</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let files = epub.get_spine_files();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| file.is_html()));
+        assert_eq!(files[0].get_text(), "Unclosed\ntags");
+        assert_eq!(epub.get_chapters()[0].get_text(), "Unclosed\ntags\nTwo");
+    }
+
+    #[test]
+    fn titles_are_typed_and_ordered() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>id</dc:identifier>
+    <dc:title id="t2">A Novel</dc:title>
+    <dc:title id="t1">The Hobbit</dc:title>
+    <dc:title id="t3">Middle-earth Classics</dc:title>
+    <meta refines="#t1" property="title-type">main</meta>
+    <meta refines="#t1" property="display-seq">1</meta>
+    <meta refines="#t2" property="title-type">subtitle</meta>
+    <meta refines="#t2" property="display-seq">2</meta>
+    <meta refines="#t3" property="title-type">collection</meta>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"##;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let metadata = epub.get_metadata();
+        assert_eq!(metadata.get_title(), Some("The Hobbit"));
+        assert_eq!(metadata.get_subtitle(), Some("A Novel"));
+        assert_eq!(
+            metadata.get_display_title().as_deref(),
+            Some("The Hobbit: A Novel")
+        );
+        let types: Vec<Option<TitleType>> = metadata
+            .get_titles()
+            .iter()
+            .map(|t| t.get_title_type())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                Some(TitleType::Main),
+                Some(TitleType::Subtitle),
+                Some(TitleType::Collection)
+            ]
+        );
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(saved.get_metadata().get_titles(), metadata.get_titles());
+    }
+
+    #[test]
+    fn sort_keys_come_from_file_as_and_calibre() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    <dc:identifier>id</dc:identifier>
+    <dc:title>The Hobbit</dc:title>
+    <dc:creator opf:role="aut">J. R. R. Tolkien</dc:creator>
+    <dc:creator opf:file-as="Lee, Alan" opf:role="ill">Alan Lee</dc:creator>
+    <meta name="calibre:title_sort" content="Hobbit, The"/>
+    <meta name="calibre:author_sort" content="Tolkien, J. R. R."/>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"##;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let check = |epub: &Epub| {
+            let metadata = epub.get_metadata();
+            assert_eq!(metadata.get_title_sort(), Some("Hobbit, The"));
+            let creators: Vec<(&str, Option<&str>)> = metadata
+                .get_creator_entries()
+                .iter()
+                .map(|c| (c.sort_key(), c.get_role()))
+                .collect();
+            assert_eq!(
+                creators,
+                [
+                    ("Tolkien, J. R. R.", Some("aut")),
+                    ("Lee, Alan", Some("ill"))
+                ]
+            );
+        };
+        check(&epub);
+        check(&Epub::from_bytes(epub.to_bytes().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn metadata_is_matched_by_namespace_not_prefix() {
+        let opf = r#"<ns0:package xmlns:ns0="http://www.idpf.org/2007/opf" version="2.0">
+  <ns0:metadata xmlns:ns1="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/">
+    <ns1:title>Prefixed</ns1:title>
+    <ns1:creator ns0:file-as="Doe, Jane">Jane Doe</ns1:creator>
+    <language xmlns="http://purl.org/dc/elements/1.1/">fr</language>
+    <dcterms:publisher>Press &amp; Co</dcterms:publisher>
+    <dc:subject>Undeclared</dc:subject>
+    <other:title xmlns:other="urn:other">Not a title</other:title>
+  </ns0:metadata>
+  <ns0:manifest><ns0:item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></ns0:manifest>
+  <ns0:spine><ns0:itemref idref="ch1"/></ns0:spine>
+</ns0:package>"#;
+        let package = parse_opf_xml(opf).unwrap();
+        let metadata = &package.metadata;
+        assert_eq!(package.version, "2.0");
+        let titles: Vec<&str> = metadata.title.iter().map(|t| t.value.as_str()).collect();
+        assert_eq!(titles, ["Prefixed"]);
+        assert_eq!(metadata.creator[0].file_as.as_deref(), Some("Doe, Jane"));
+        assert_eq!(metadata.language.as_deref(), Some("fr"));
+        assert_eq!(metadata.publisher.as_deref(), Some("Press & Co"));
+        assert_eq!(metadata.subject, ["Undeclared"]);
+        assert_eq!(package.manifest.item[0].href, "ch1.xhtml");
+        assert_eq!(package.spine.itemref[0].idref, "ch1");
+    }
+
+    #[test]
+    fn page_spreads_survive_saving() {
+        let opf = package(
+            r#"<item id="p1" href="p1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="p2" href="p2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="p3" href="p3.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="p1" properties="page-spread-left"/><itemref idref="p2" properties="rendition:page-spread-right" linear="no"/><itemref idref="p3"/>"#,
+        );
+        let page = "<html><body><p>Page</p></body></html>";
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("p1.xhtml", page),
+            ("p2.xhtml", page),
+            ("p3.xhtml", page),
+        ]))
+        .unwrap();
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+
+        let items = saved.get_spine_items();
+        let spreads: Vec<Option<PageSpread>> =
+            items.iter().map(SpineItem::get_page_spread).collect();
+        assert_eq!(
+            spreads,
+            [Some(PageSpread::Left), Some(PageSpread::Right), None]
+        );
+        assert!(!items[1].is_linear());
+        assert_eq!(items[2], SpineItem::new("p3"));
+    }
+}
@@ -0,0 +1,159 @@
+//! Typed errors for malformed EPUB files
+//!
+//! Constructors keep returning `Box<dyn Error>`, so these can be recovered
+//! with `downcast_ref::<EpubError>()` when a caller needs to tell the
+//! failure modes apart. I/O and zip errors are passed through unchanged.
+//! Editing methods return `EpubError` directly.
+//!
+//! ```
+//! use epubie_lib::{Epub, EpubError};
+//! use std::io::Cursor;
+//! use zip::write::SimpleFileOptions;
+//!
+//! // A zip archive that lists no package document
+//! let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+//! zip.start_file("META-INF/container.xml", SimpleFileOptions::default())?;
+//! std::io::Write::write_all(&mut zip, b"<container><rootfiles/></container>")?;
+//! let bytes = zip.finish()?.into_inner();
+//! # let error = Epub::from_bytes(bytes.clone()).err().unwrap();
+//! # assert_eq!(error.downcast_ref(), Some(&EpubError::MissingRootfile));
+//!
+//! match Epub::from_bytes(bytes) {
+//!     Err(e) => match e.downcast_ref::<EpubError>() {
+//!         Some(EpubError::MissingRootfile) => eprintln!("no package document listed"),
+//!         _ => eprintln!("failed to open: {}", e),
+//!     },
+//!     Ok(epub) => println!("{}", epub.get_title().unwrap_or("Untitled")),
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpubError {
+    /// `META-INF/container.xml` is not present in the archive
+    MissingContainer,
+    /// `container.xml` does not list any rootfile
+    MissingRootfile,
+    /// The OPF package document named by the container is not in the archive
+    MissingPackage(String),
+    /// An XML document could not be parsed
+    InvalidXml { path: String, message: String },
+    /// An edit would leave the metadata invalid (e.g. an empty title)
+    InvalidMetadata(String),
+    /// No manifest item has the given href
+    UnknownResource(String),
+    /// A manifest item with the given href already exists
+    ResourceExists(String),
+    /// A locator range doesn't point into the spine, or ends before it starts
+    InvalidRange(String),
+    /// A search pattern isn't a valid regular expression
+    InvalidPattern(String),
+}
+
+impl fmt::Display for EpubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpubError::MissingContainer => write!(f, "META-INF/container.xml not found"),
+            EpubError::MissingRootfile => write!(f, "container.xml does not list a rootfile"),
+            EpubError::MissingPackage(path) => write!(f, "package document '{}' not found", path),
+            EpubError::InvalidXml { path, message } => {
+                write!(f, "invalid XML in '{}': {}", path, message)
+            }
+            EpubError::InvalidMetadata(reason) => write!(f, "invalid metadata: {}", reason),
+            EpubError::UnknownResource(href) => write!(f, "no manifest item with href '{}'", href),
+            EpubError::ResourceExists(href) => {
+                write!(f, "a manifest item with href '{}' already exists", href)
+            }
+            EpubError::InvalidRange(reason) => write!(f, "invalid range: {}", reason),
+            EpubError::InvalidPattern(reason) => write!(f, "invalid pattern: {}", reason),
+        }
+    }
+}
+
+impl Error for EpubError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn open_err(files: &[(&str, &str)]) -> EpubError {
+        let err = Epub::from_bytes(build(files))
+            .err()
+            .expect("parse should fail");
+        err.downcast_ref::<EpubError>()
+            .cloned()
+            .unwrap_or_else(|| panic!("expected EpubError, got {}", err))
+    }
+
+    #[test]
+    fn garbage_bytes_are_an_error() {
+        assert!(Epub::from_bytes(&b"not a zip at all"[..]).is_err());
+    }
+
+    #[test]
+    fn missing_container() {
+        assert_eq!(
+            open_err(&[("mimetype", "application/epub+zip")]),
+            EpubError::MissingContainer
+        );
+    }
+
+    #[test]
+    fn empty_rootfiles() {
+        let container = r#"<container version="1.0"><rootfiles></rootfiles></container>"#;
+        assert_eq!(
+            open_err(&[("META-INF/container.xml", container)]),
+            EpubError::MissingRootfile
+        );
+    }
+
+    #[test]
+    fn missing_package() {
+        assert_eq!(
+            open_err(&[("META-INF/container.xml", CONTAINER)]),
+            EpubError::MissingPackage("content.opf".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_package_xml() {
+        let err = open_err(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", "<package><metadata>"),
+        ]);
+        assert!(matches!(err, EpubError::InvalidXml { path, .. } if path == "content.opf"));
+    }
+
+    #[test]
+    fn zero_length_spine() {
+        let opf = package("", "");
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+        ]))
+        .unwrap();
+        assert!(epub.get_chapters().is_empty());
+        assert!(epub.validate().iter().any(|issue| issue.is_error()));
+    }
+
+    #[test]
+    fn self_referencing_nav() {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+            r#"<itemref idref="nav"/>"#,
+        );
+        let nav = r#"<html><body><nav><ol><li><a href="nav.xhtml">Contents</a></li></ol></nav></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+        ]))
+        .unwrap();
+        assert!(epub.get_chapters().is_empty());
+    }
+}
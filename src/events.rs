@@ -0,0 +1,114 @@
+//! Progress events while parsing
+//!
+//! Parsing a large book mostly means reading its content documents, and a
+//! reader UI doesn't need those to show the cover page, title and table of
+//! contents. `Epub::parse_with_events` reports each stage to a
+//! `ParseEventSink` as it completes, in this order: `MetadataReady`,
+//! `TocReady`, then `FileParsed` for every content document. Run it on a
+//! background thread and forward the events to the UI.
+
+use crate::archive::Source;
+use crate::epub::{Epub, Metadata, TableOfContents};
+use crate::options::ParseOptions;
+use std::error::Error;
+use std::sync::Arc;
+
+/// A parse stage that has completed
+#[derive(Debug, Clone, Copy)]
+pub enum ParseEvent<'a> {
+    /// The package metadata is parsed
+    MetadataReady(&'a Metadata),
+    /// The table of contents, from the navigation document
+    ///
+    /// Documents the navigation doesn't title are listed by manifest ID; the
+    /// final `Epub::get_table_of_contents` may title them by their first
+    /// heading instead, and leaves out documents that couldn't be read.
+    TocReady(&'a TableOfContents),
+    /// The content document with this href (relative to the OPF) is read
+    FileParsed(&'a str),
+}
+
+/// Receives `ParseEvent`s; implemented for any `FnMut(ParseEvent)`
+pub trait ParseEventSink {
+    fn on_event(&mut self, event: ParseEvent<'_>);
+}
+
+impl<F: FnMut(ParseEvent<'_>)> ParseEventSink for F {
+    fn on_event(&mut self, event: ParseEvent<'_>) {
+        self(event)
+    }
+}
+
+impl Epub {
+    /// Like `from_bytes`, reporting each parse stage to `sink` as it completes
+    pub fn parse_with_events(
+        bytes: impl Into<Arc<[u8]>>,
+        sink: &mut impl ParseEventSink,
+    ) -> Result<Epub, Box<dyn Error>> {
+        Self::parse_with_events_and_options(bytes, ParseOptions::default(), sink)
+    }
+
+    /// Like `parse_with_events`, but with non-default parse options
+    pub fn parse_with_events_and_options(
+        bytes: impl Into<Arc<[u8]>>,
+        options: ParseOptions,
+        sink: &mut impl ParseEventSink,
+    ) -> Result<Epub, Box<dyn Error>> {
+        enter_span!("epub", source = "bytes");
+        Self::parse_source(Source::Bytes(bytes.into()), options, sink)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn stages_are_reported_in_order() {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let nav = r#"<html><body><nav epub:type="toc"><ol><li><a href="ch1.xhtml">One</a></li></ol></nav></body></html>"#;
+        let bytes = build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("ch2.xhtml", "<html><body><h1>Two</h1></body></html>"),
+        ]);
+
+        let mut events = Vec::new();
+        let epub = Epub::parse_with_events(bytes, &mut |event: ParseEvent<'_>| {
+            events.push(match event {
+                ParseEvent::MetadataReady(metadata) => {
+                    format!("metadata {}", metadata.get_title().unwrap_or_default())
+                }
+                ParseEvent::TocReady(toc) => {
+                    let titles: Vec<&str> = toc
+                        .get_entries()
+                        .iter()
+                        .map(|entry| entry.get_title())
+                        .collect();
+                    format!("toc {}", titles.join(", "))
+                }
+                ParseEvent::FileParsed(href) => format!("file {}", href),
+            })
+        })
+        .unwrap();
+
+        assert_eq!(
+            events,
+            [
+                "metadata T",
+                "toc One, ch2",
+                "file ch1.xhtml",
+                "file ch2.xhtml"
+            ]
+        );
+        assert_eq!(epub.get_table_of_contents().get_entry_count(), 2);
+    }
+}
@@ -0,0 +1,390 @@
+//! Cutting a book down to, or out of, ranges of its content
+//!
+//! Classroom handouts keep a few passages of a book; rights-limited previews
+//! keep some and drop others. Both start from a list of `LocatorRange`s:
+//! `Epub::excerpt` keeps only what they cover and `Epub::redact` keeps
+//! everything else. Either way the result is an ordinary `Epub`.
+//!
+//! A range runs from one element, or the start of a document, up to the
+//! start of another and may span documents. Where a cut falls inside an
+//! element, the element is closed at the cut and reopened after it (without
+//! its `id`, which is already taken), so every document stays well-formed.
+//! Documents left empty are removed, links to content that is gone lose
+//! their `href`, and the navigation is rebuilt from what remains.
+
+use crate::epub::{Epub, EpubFile};
+use crate::error::EpubError;
+use crate::href;
+use crate::locator::Locator;
+use crate::nav::is_navigation;
+use crate::xhtml::{self, TAG, VOID_ELEMENTS, find_elements};
+use regex::Regex;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+static ID_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\s+id\s*=\s*(?:"[^"]*"|'[^']*'|[^\s"'>/]+)"#).expect("id attribute pattern")
+});
+static HREF_ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"\s+href\s*=\s*(?:"[^"]*"|'[^']*'|[^\s"'>/]+)"#).expect("href attribute pattern")
+});
+
+/// A stretch of a book's content, for `Epub::excerpt` and `Epub::redact`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocatorRange {
+    start: Locator,
+    end: Option<Locator>,
+}
+
+impl LocatorRange {
+    /// From `start` to the end of its document
+    pub fn new(start: Locator) -> Self {
+        Self { start, end: None }
+    }
+
+    /// End the range where `end` begins instead, possibly in a later document
+    ///
+    /// A locator without a fragment is the start of its document, so a range
+    /// ending at `ch2.xhtml` covers the rest of the document before it.
+    pub fn with_end(mut self, end: Locator) -> Self {
+        self.end = Some(end);
+        self
+    }
+
+    pub fn get_start(&self) -> &Locator {
+        &self.start
+    }
+
+    pub fn get_end(&self) -> Option<&Locator> {
+        self.end.as_ref()
+    }
+}
+
+impl Epub {
+    /// A copy of the book with only the content inside `ranges`
+    pub fn excerpt(&self, ranges: &[LocatorRange]) -> Result<Epub, EpubError> {
+        self.cut_ranges(ranges, true)
+    }
+
+    /// A copy of the book with the content inside `ranges` removed
+    pub fn redact(&self, ranges: &[LocatorRange]) -> Result<Epub, EpubError> {
+        self.cut_ranges(ranges, false)
+    }
+
+    fn cut_ranges(&self, ranges: &[LocatorRange], keep_selected: bool) -> Result<Epub, EpubError> {
+        enter_span!("cut_ranges");
+        let files = self.get_spine_files();
+        let mut selected: Vec<Vec<Range<usize>>> = vec![Vec::new(); files.len()];
+        for range in ranges {
+            let (first, start) = position(&files, &range.start)?;
+            let (last, end) = match &range.end {
+                Some(end) => position(&files, end)?,
+                None => (first, body(&files[first].content).end),
+            };
+            if (last, end) < (first, start) {
+                return Err(EpubError::InvalidRange(format!(
+                    "'{}' ends before it starts",
+                    range.start.to_link()
+                )));
+            }
+            for (index, selected) in selected.iter_mut().enumerate().take(last + 1).skip(first) {
+                let body = body(&files[index].content);
+                let from = if index == first { start } else { body.start };
+                let to = if index == last { end } else { body.end };
+                if from < to {
+                    selected.push(from..to);
+                }
+            }
+        }
+
+        let mut book = self.clone();
+        let mut removed = Vec::new();
+        for (file, selected) in files.iter().zip(selected) {
+            if selected.is_empty() && !keep_selected {
+                continue;
+            }
+            let body = body(&file.content);
+            let selected = merge(selected);
+            let kept = if keep_selected {
+                selected
+            } else {
+                complement(body.clone(), &selected)
+            };
+            if kept.is_empty() {
+                removed.push(file.href.to_string());
+                continue;
+            }
+            let content = format!(
+                "{}{}{}",
+                &file.content[..body.start],
+                stitch(&file.content, body.start, &kept),
+                &file.content[body.end..]
+            );
+            book.put_resource_bytes(&file.href, content.into_bytes());
+        }
+        for href in &removed {
+            let is_nav = book.get_resource_by_href(href).is_some_and(is_navigation);
+            if !is_nav {
+                let _ = book.remove_resource(href);
+            }
+        }
+        if book.spine.is_empty() {
+            return Err(EpubError::InvalidRange(
+                "the ranges leave nothing of the book".to_string(),
+            ));
+        }
+
+        book.unlink_dangling();
+        book.regenerate_navigation();
+        book.prune_orphans();
+        Ok(book)
+    }
+
+    /// Take the `href` off links to documents or fragments that no longer exist
+    pub(crate) fn unlink_dangling(&mut self) {
+        let updates: Vec<(String, String)> = self
+            .all_files
+            .iter()
+            .filter_map(|file| {
+                let path = Self::resolve_path(&self.opf_path, &file.href);
+                let mut content = file.content.clone();
+                let anchors = find_elements(&file.content, |e| {
+                    e.name == "a" && e.attribute("href").is_some()
+                });
+                let mut changed = false;
+                for anchor in anchors.iter().rev() {
+                    let reference = anchor.attribute("href").unwrap_or_default().trim();
+                    if href::has_scheme(reference) || self.has_link_target(&path, reference) {
+                        continue;
+                    }
+                    let Some(tag) = TAG.find(&content[anchor.range.start..]) else {
+                        continue;
+                    };
+                    let tag = anchor.range.start..anchor.range.start + tag.end();
+                    let unlinked = HREF_ATTRIBUTE
+                        .replace(&content[tag.clone()], "")
+                        .into_owned();
+                    content.replace_range(tag, &unlinked);
+                    changed = true;
+                }
+                changed.then(|| (file.href.to_string(), content))
+            })
+            .collect();
+        for (href, content) in updates {
+            self.put_resource_bytes(&href, content.into_bytes());
+        }
+    }
+
+    /// Whether `reference`, found in the document at `doc_path`, still leads somewhere
+    fn has_link_target(&self, doc_path: &str, reference: &str) -> bool {
+        let target = match reference.split_once('#') {
+            Some(("", _)) => doc_path.to_string(),
+            _ => href::resolve(doc_path, reference),
+        };
+        let mut target = href::relative(&self.opf_path, &target);
+        if let Some(fragment) = href::fragment(reference) {
+            target = format!("{}#{}", target, fragment);
+        }
+        self.has_target(&target)
+    }
+}
+
+/// Spine position and byte offset in the document of where `locator` points
+fn position(files: &[&EpubFile], locator: &Locator) -> Result<(usize, usize), EpubError> {
+    let index = files
+        .iter()
+        .position(|file| file.get_href() == locator.get_href())
+        .ok_or_else(|| {
+            EpubError::InvalidRange(format!("'{}' is not in the spine", locator.get_href()))
+        })?;
+    let content = &files[index].content;
+    let body = body(content);
+    let offset = match locator.get_fragment() {
+        Some(id) => xhtml::find_id(content, id).ok_or_else(|| {
+            EpubError::InvalidRange(format!("no element '{}' in '{}'", id, locator.get_href()))
+        })?,
+        None => body.start,
+    };
+    Ok((index, offset.clamp(body.start, body.end)))
+}
+
+/// Byte range of the markup inside `<body>`, or of the whole document without one
+fn body(html: &str) -> Range<usize> {
+    let mut range = 0..html.len();
+    for cap in TAG.captures_iter(html) {
+        if !cap[2].eq_ignore_ascii_case("body") {
+            continue;
+        }
+        let tag = cap.get(0).expect("whole match");
+        if cap[1].is_empty() {
+            range.start = tag.end();
+        } else {
+            range.end = tag.start().max(range.start);
+            break;
+        }
+    }
+    range
+}
+
+/// Sort ranges and join the ones that overlap or touch
+fn merge(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// The parts of `whole` outside the sorted, disjoint `ranges`
+fn complement(whole: Range<usize>, ranges: &[Range<usize>]) -> Vec<Range<usize>> {
+    let mut gaps = Vec::new();
+    let mut start = whole.start;
+    for range in ranges {
+        if range.start > start {
+            gaps.push(start..range.start);
+        }
+        start = start.max(range.end);
+    }
+    if start < whole.end {
+        gaps.push(start..whole.end);
+    }
+    gaps
+}
+
+/// An element open at some point of a document: name, start tag, and
+/// whether the start tag has been written out already
+type OpenElement = (String, String, bool);
+
+/// The markup of `html` in the sorted, disjoint `ranges`, joined so that
+/// elements opened after `from` and cut through are closed and reopened
+/// around each range
+fn stitch(html: &str, from: usize, ranges: &[Range<usize>]) -> String {
+    let mut out = String::new();
+    let mut open: Vec<OpenElement> = Vec::new();
+    let mut position = from;
+    for range in ranges {
+        track(&html[position..range.start], &mut open, false);
+        for (_, tag, written) in &mut open {
+            if *written {
+                out.push_str(&ID_ATTRIBUTE.replace(tag, ""));
+            } else {
+                out.push_str(tag);
+                *written = true;
+            }
+        }
+        out.push_str(&html[range.clone()]);
+        track(&html[range.clone()], &mut open, true);
+        for (name, _, _) in open.iter().rev() {
+            out.push_str(&format!("</{}>", name));
+        }
+        position = range.end;
+    }
+    out
+}
+
+/// Follow the start and end tags in `html`, updating the open elements
+fn track(html: &str, open: &mut Vec<OpenElement>, written: bool) {
+    for cap in TAG.captures_iter(html) {
+        let name = cap[2].to_ascii_lowercase();
+        if !cap[1].is_empty() {
+            if let Some(position) = open.iter().rposition(|(open, _, _)| *open == name) {
+                open.truncate(position);
+            }
+        } else if !cap[3].trim_end().ends_with('/')
+            && !VOID_ELEMENTS.split('|').any(|void| void == name)
+        {
+            open.push((name, cap[0].to_string(), written));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book() -> Epub {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch3" href="ch3.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/><itemref idref="ch3"/>"#,
+        );
+        let nav = r#"<html xmlns:epub="http://www.idpf.org/2007/ops"><body><nav epub:type="toc"><ol>
+<li><a href="ch1.xhtml">One</a></li><li><a href="ch1.xhtml#p1">A</a></li>
+<li><a href="ch2.xhtml">Two</a></li><li><a href="ch3.xhtml">Three</a></li>
+</ol></nav></body></html>"#;
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            (
+                "ch1.xhtml",
+                r#"<html><body><section id="s1"><h1>One</h1><p id="p1">A</p><p id="p2">B, see <a href="ch3.xhtml">three</a></p><p id="p3">C</p></section></body></html>"#,
+            ),
+            (
+                "ch2.xhtml",
+                r#"<html><body><p id="q1">D, <a href="ch1.xhtml#p1">back</a></p><p id="q2">E</p></body></html>"#,
+            ),
+            ("ch3.xhtml", "<html><body><p>F</p></body></html>"),
+        ]))
+        .unwrap()
+    }
+
+    fn content(epub: &Epub, href: &str) -> String {
+        String::from_utf8(epub.get_resource_bytes(href).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn excerpt_keeps_only_the_ranges() {
+        let range = LocatorRange::new(Locator::new("ch1.xhtml").with_fragment("p2"))
+            .with_end(Locator::new("ch2.xhtml").with_fragment("q2"));
+        let excerpt = book().excerpt(&[range]).unwrap();
+
+        assert_eq!(excerpt.get_spine(), ["ch1", "ch2"]);
+        assert_eq!(
+            content(&excerpt, "ch1.xhtml"),
+            r#"<html><body><section id="s1"><p id="p2">B, see <a>three</a></p><p id="p3">C</p></section></body></html>"#
+        );
+        assert_eq!(
+            content(&excerpt, "ch2.xhtml"),
+            r#"<html><body><p id="q1">D, <a>back</a></p></body></html>"#
+        );
+        let titles: Vec<&str> = excerpt
+            .get_table_of_contents()
+            .get_entries()
+            .iter()
+            .map(|entry| entry.get_title())
+            .collect();
+        assert_eq!(titles, ["One", "Two"]);
+        assert!(!content(&excerpt, "nav.xhtml").contains("ch3.xhtml"));
+    }
+
+    #[test]
+    fn redaction_closes_and_reopens_cut_elements() {
+        let range = LocatorRange::new(Locator::new("ch1.xhtml").with_fragment("p2"))
+            .with_end(Locator::new("ch1.xhtml").with_fragment("p3"));
+        let whole = LocatorRange::new(Locator::new("ch3.xhtml"));
+        let redacted = book().redact(&[range, whole]).unwrap();
+
+        assert_eq!(
+            content(&redacted, "ch1.xhtml"),
+            r#"<html><body><section id="s1"><h1>One</h1><p id="p1">A</p></section><section><p id="p3">C</p></section></body></html>"#
+        );
+        assert!(content(&redacted, "ch2.xhtml").contains(r#"<a href="ch1.xhtml#p1">back</a>"#));
+        assert_eq!(redacted.get_spine(), ["ch1", "ch2"]);
+
+        let backwards =
+            LocatorRange::new(Locator::new("ch2.xhtml")).with_end(Locator::new("ch1.xhtml"));
+        assert!(matches!(
+            book().redact(&[backwards]),
+            Err(EpubError::InvalidRange(_))
+        ));
+        assert!(book().excerpt(&[]).is_err());
+    }
+}
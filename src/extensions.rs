@@ -0,0 +1,311 @@
+//! Vendor metadata through extensions
+//!
+//! Apple, Kobo, Google and calibre all put their own metadata in the OPF:
+//! `<meta property="ibooks:version">`, `<meta name="calibre:series">`,
+//! elements in private namespaces. `Metadata` only models the standard
+//! fields, so the parser keeps every child of `<metadata>` as a
+//! `MetadataElement`, and a `MetadataExtension` can be run over them to
+//! build whatever typed structure a downstream crate wants.
+//!
+//! ```
+//! use epubie_lib::extensions::{MetadataElement, MetadataExtension};
+//!
+//! #[derive(Default)]
+//! struct AppleMetadata {
+//!     specified_fonts: bool,
+//! }
+//!
+//! impl MetadataExtension for AppleMetadata {
+//!     fn on_element(&mut self, element: &MetadataElement) {
+//!         if element.get_attribute("property") == Some("ibooks:specified-fonts") {
+//!             self.specified_fonts = element.get_text() == "true";
+//!         }
+//!     }
+//! }
+//!
+//! # fn run(epub: &epubie_lib::Epub) {
+//! let apple: AppleMetadata = epub.metadata_extension();
+//! # }
+//! ```
+
+use crate::epub::{DC_NAMESPACES, Epub};
+use crate::text::{decode_entities, escape_xml};
+use crate::xhtml::find_elements;
+use std::fmt::Write;
+
+/// Elements that only group other metadata, in OEB 1.2 style packages
+const GROUPING_ELEMENTS: &[&str] = &["dc-metadata", "x-metadata"];
+
+/// A child element of the OPF `<metadata>`, as written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataElement {
+    name: String,
+    namespace: Option<String>,
+    attributes: Vec<(String, String)>,
+    text: String,
+}
+
+impl MetadataElement {
+    /// The element name with its prefix, lowercased, e.g. `dc:title` or `meta`
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The element name without its prefix, e.g. `title` for `dc:title`
+    pub fn get_local_name(&self) -> &str {
+        local_name(&self.name)
+    }
+
+    /// Whether this is a Dublin Core element, whatever prefix it was written with
+    ///
+    /// An undeclared `dc:` prefix counts, as reading systems accept it too.
+    pub fn is_dublin_core(&self) -> bool {
+        match &self.namespace {
+            Some(namespace) => DC_NAMESPACES.contains(&namespace.as_str()),
+            None => self.name.starts_with("dc:"),
+        }
+    }
+
+    /// The namespace URI the name's prefix (or the default namespace) is bound to
+    pub fn get_namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Attributes as `(lowercased name, decoded value)`, in document order
+    pub fn get_attributes(&self) -> &[(String, String)] {
+        &self.attributes
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The text content, entity-decoded and trimmed
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// The element as XML, Dublin Core ones with the `dc:` prefix
+    pub(crate) fn to_xml(&self) -> String {
+        let name = if self.is_dublin_core() {
+            format!("dc:{}", self.get_local_name())
+        } else {
+            self.name.clone()
+        };
+        let mut xml = format!("<{}", name);
+        for (attribute, value) in &self.attributes {
+            let _ = write!(xml, " {}=\"{}\"", attribute, escape_xml(value));
+        }
+        if self.text.is_empty() {
+            xml.push_str("/>");
+        } else {
+            let _ = write!(xml, ">{}</{}>", escape_xml(&self.text), name);
+        }
+        xml
+    }
+}
+
+/// Receives the raw metadata of a book, one element at a time
+pub trait MetadataExtension {
+    /// Called for each metadata element, in document order
+    fn on_element(&mut self, element: &MetadataElement);
+
+    /// Called after the last element, e.g. to resolve refinements
+    fn finish(&mut self) {}
+}
+
+impl Epub {
+    /// Every child element of the OPF `<metadata>`, as parsed
+    ///
+    /// Edits made through `Epub::edit_metadata` aren't reflected here.
+    pub fn get_metadata_elements(&self) -> &[MetadataElement] {
+        &self.metadata_elements
+    }
+
+    /// Feed every metadata element to `extension`, then call its `finish`
+    pub fn apply_metadata_extension<E: MetadataExtension + ?Sized>(&self, extension: &mut E) {
+        for element in &self.metadata_elements {
+            extension.on_element(element);
+        }
+        extension.finish();
+    }
+
+    /// Build a default `E` and run it over the metadata
+    pub fn metadata_extension<E: MetadataExtension + Default>(&self) -> E {
+        let mut extension = E::default();
+        self.apply_metadata_extension(&mut extension);
+        extension
+    }
+}
+
+/// `name` without its namespace prefix
+fn local_name(name: &str) -> &str {
+    name.split_once(':').map_or(name, |(_, local)| local)
+}
+
+/// Collect the children of the `<metadata>` element of an OPF document
+///
+/// The package and metadata elements are found by local name, so packages
+/// written with a prefix for the OPF namespace (`<opf:metadata>`) work too.
+pub(crate) fn metadata_elements(opf: &str) -> Vec<MetadataElement> {
+    let mut namespaces = Vec::new();
+    if let Some(package) = find_elements(opf, |e| local_name(&e.name) == "package").first() {
+        namespaces.extend(declarations(&package.attributes));
+    }
+    let Some(metadata) = find_elements(opf, |e| local_name(&e.name) == "metadata")
+        .into_iter()
+        .next()
+    else {
+        return Vec::new();
+    };
+    namespaces.extend(declarations(&metadata.attributes));
+
+    let mut elements = Vec::new();
+    collect(metadata.inner, &namespaces, &mut elements);
+    elements
+}
+
+fn collect(markup: &str, namespaces: &[(String, String)], elements: &mut Vec<MetadataElement>) {
+    for element in find_elements(markup, |_| true) {
+        let mut namespaces = namespaces.to_vec();
+        namespaces.extend(declarations(&element.attributes));
+        if GROUPING_ELEMENTS.contains(&local_name(&element.name)) {
+            collect(element.inner, &namespaces, elements);
+            continue;
+        }
+        let prefix = element
+            .name
+            .split_once(':')
+            .map_or("", |(prefix, _)| prefix);
+        let namespace = namespaces
+            .iter()
+            .rev()
+            .find(|(declared, _)| declared == prefix)
+            .map(|(_, uri)| uri.clone());
+        elements.push(MetadataElement {
+            namespace,
+            text: decode_entities(element.inner.trim()),
+            name: element.name,
+            attributes: element.attributes,
+        });
+    }
+}
+
+/// Namespace declarations among `attributes`, as `(prefix, uri)` with `""`
+/// for the default namespace
+fn declarations(attributes: &[(String, String)]) -> Vec<(String, String)> {
+    attributes
+        .iter()
+        .filter_map(|(name, uri)| {
+            let prefix = match name.as_str() {
+                "xmlns" => "",
+                name => name.strip_prefix("xmlns:")?,
+            };
+            Some((prefix.to_string(), uri.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    #[derive(Default)]
+    struct Vendor {
+        properties: Vec<(String, String)>,
+        finished: bool,
+    }
+
+    impl MetadataExtension for Vendor {
+        fn on_element(&mut self, element: &MetadataElement) {
+            if let Some(property) = element.get_attribute("property")
+                && property.starts_with("ibooks:")
+            {
+                self.properties
+                    .push((property.to_string(), element.get_text().to_string()));
+            }
+            if element.get_name() == "kobo:series" {
+                self.properties.push((
+                    element.get_name().to_string(),
+                    element.get_text().to_string(),
+                ));
+            }
+        }
+
+        fn finish(&mut self) {
+            self.finished = true;
+        }
+    }
+
+    #[test]
+    fn extensions_see_every_metadata_element() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" prefix="ibooks: http://vocabulary.itunes.apple.com/rdf/ibooks/vocabulary-extensions-1.0/">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:kobo="urn:kobo">
+    <dc:identifier>id</dc:identifier>
+    <dc:title>T</dc:title>
+    <meta property="ibooks:specified-fonts">true</meta>
+    <kobo:series>Dune &amp; Sequels</kobo:series>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let elements = epub.get_metadata_elements();
+        assert_eq!(elements.len(), 4);
+        assert_eq!(
+            elements[1].get_namespace(),
+            Some("http://purl.org/dc/elements/1.1/")
+        );
+        assert_eq!(
+            elements[2].get_namespace(),
+            Some("http://www.idpf.org/2007/opf")
+        );
+
+        let vendor: Vendor = epub.metadata_extension();
+        assert!(vendor.finished);
+        assert_eq!(
+            vendor.properties,
+            [
+                ("ibooks:specified-fonts".to_string(), "true".to_string()),
+                ("kobo:series".to_string(), "Dune & Sequels".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefixes_are_resolved_to_namespaces() {
+        let opf = r#"<opf:package xmlns:opf="http://www.idpf.org/2007/opf" version="2.0">
+  <opf:metadata xmlns:ns0="http://purl.org/dc/elements/1.1/">
+    <ns0:identifier>id</ns0:identifier>
+    <ns0:title>Renamed Prefix</ns0:title>
+    <ns0:language>en</ns0:language>
+  </opf:metadata>
+  <opf:manifest><opf:item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></opf:manifest>
+  <opf:spine><opf:itemref idref="ch1"/></opf:spine>
+</opf:package>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        assert_eq!(epub.get_title(), Some("Renamed Prefix"));
+        assert_eq!(epub.get_metadata().get_language(), Some("en"));
+        let elements = epub.get_metadata_elements();
+        assert_eq!(elements.len(), 3);
+        assert!(elements.iter().all(|element| element.is_dublin_core()));
+        assert_eq!(elements[1].get_name(), "ns0:title");
+        assert_eq!(elements[1].get_local_name(), "title");
+    }
+}
@@ -0,0 +1,164 @@
+//! Periodicals from RSS and Atom feeds
+//!
+//! The crate doesn't fetch or parse feeds; callers hand over entries from
+//! whatever feed parser they use. `EpubBuilder::new_periodical` and
+//! `EpubBuilder::with_feed_entries` turn them into a dated issue with one
+//! chapter per article, the shape e-ink news readers expect.
+//!
+//! ```no_run
+//! use epubie_lib::builder::EpubBuilder;
+//! use epubie_lib::feed::FeedEntry;
+//!
+//! let entries = [FeedEntry::new("Hello", "<p>First post.</p>")
+//!     .with_author("Ada")
+//!     .with_link("https://example.com/hello")];
+//! let today = chrono::Local::now().date_naive();
+//! EpubBuilder::new_periodical("Example Blog", today)
+//!     .with_feed_entries(&entries, &[])
+//!     .save("example-blog.epub")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use crate::text::escape_xml;
+
+/// One article from a feed
+#[derive(Debug, Clone)]
+pub struct FeedEntry {
+    title: String,
+    content: String,
+    author: Option<String>,
+    link: Option<String>,
+    published: Option<String>,
+}
+
+impl FeedEntry {
+    /// An entry with its title and HTML content (`content:encoded`,
+    /// `<content>` or `<description>`, whichever is fullest)
+    pub fn new(title: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            content: content.into(),
+            author: None,
+            link: None,
+            published: None,
+        }
+    }
+
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// The article's URL, which relative URLs in its content are resolved against
+    pub fn with_link(mut self, link: impl Into<String>) -> Self {
+        self.link = Some(link.into());
+        self
+    }
+
+    /// Publication date, shown in the byline as given
+    pub fn with_published(mut self, published: impl Into<String>) -> Self {
+        self.published = Some(published.into());
+        self
+    }
+
+    pub fn get_title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn get_content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn get_author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    pub fn get_link(&self) -> Option<&str> {
+        self.link.as_deref()
+    }
+
+    pub fn get_published(&self) -> Option<&str> {
+        self.published.as_deref()
+    }
+}
+
+/// An article's chapter body: heading, byline, the cleaned content and a source link
+pub(crate) fn article_body(entry: &FeedEntry, content: &str) -> String {
+    let mut body = format!("<header>\n<h1>{}</h1>\n", escape_xml(&entry.title));
+    let byline: Vec<String> = [
+        entry.author.as_ref().map(|author| format!("By {}", author)),
+        entry.published.clone(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !byline.is_empty() {
+        body.push_str(&format!(
+            "<p class=\"byline\">{}</p>\n",
+            escape_xml(&byline.join(" · "))
+        ));
+    }
+    body.push_str("</header>\n");
+    body.push_str(content);
+    if let Some(link) = &entry.link {
+        body.push_str(&format!(
+            "\n<p class=\"source\"><a href=\"{}\">Read the original</a></p>",
+            escape_xml(link)
+        ));
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::EpubBuilder;
+    use crate::clipper::WebResource;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn entries_become_a_dated_issue() {
+        let entries = [
+            FeedEntry::new("Rust 2027 and beyond", r#"<p>See <img src="chart.png"> and <a href="/posts/two">the follow-up</a>.</p><script>track()</script>"#)
+                .with_author("Ferris")
+                .with_link("https://blog.example.com/posts/one")
+                .with_published("Fri, 16 Oct 2026"),
+            FeedEntry::new("The follow-up", "<p>More.</p>")
+                .with_link("https://blog.example.com/posts/two"),
+        ];
+        let chart = WebResource::new(
+            "https://blog.example.com/posts/chart.png",
+            "image/png",
+            vec![0],
+        );
+        let epub = EpubBuilder::new_periodical(
+            "Example Blog",
+            NaiveDate::from_ymd_opt(2026, 10, 17).unwrap(),
+        )
+        .with_feed_entries(&entries, &[chart])
+        .build()
+        .unwrap();
+
+        assert_eq!(epub.get_title(), Some("Example Blog — 2026-10-17"));
+        assert_eq!(epub.get_metadata().get_date(), Some("2026-10-17"));
+        let toc: Vec<&str> = epub
+            .get_table_of_contents()
+            .get_entries()
+            .iter()
+            .map(|entry| entry.get_title())
+            .collect();
+        assert_eq!(toc, ["Rust 2027 and beyond", "The follow-up"]);
+
+        let article = epub.get_all_files()[0].get_content();
+        assert!(article.contains("<h1>Rust 2027 and beyond</h1>"));
+        assert!(article.contains(r#"<p class="byline">By Ferris · Fri, 16 Oct 2026</p>"#));
+        assert!(article.contains(r#"<img src="../images/chart.png" alt=""/>"#));
+        assert!(article.contains(r#"<a href="chapter-002.xhtml">the follow-up</a>"#));
+        assert!(
+            article
+                .contains(r#"<a href="https://blog.example.com/posts/one">Read the original</a>"#)
+        );
+        assert!(!article.contains("track()"));
+        assert!(!epub.get_all_files()[1].get_content().contains("byline"));
+    }
+}
@@ -0,0 +1,198 @@
+//! Images, their alt text and captions
+//!
+//! `Epub::get_figures` lists every `<img>` in reading order with its alt text
+//! and, when it sits in a `<figure>`, the `<figcaption>`. `alt_text_report`
+//! builds on it to flag images a screen reader can say nothing about.
+
+use crate::epub::Epub;
+use crate::href;
+use crate::text::html_to_text;
+use crate::xhtml::find_elements;
+
+/// An image and where it appears
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Figure {
+    chapter_index: usize,
+    chapter_title: String,
+    href: String,
+    id: Option<String>,
+    src: String,
+    alt: Option<String>,
+    caption: Option<String>,
+}
+
+impl Figure {
+    /// Index of the containing chapter in `Epub::get_chapters`
+    pub fn get_chapter_index(&self) -> usize {
+        self.chapter_index
+    }
+
+    pub fn get_chapter_title(&self) -> &str {
+        &self.chapter_title
+    }
+
+    /// Href of the file containing the image
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// The `id` of the image, or else of its `<figure>`
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The image's href, relative to the OPF like manifest hrefs, so it can be
+    /// passed to `Epub::get_resource_bytes`; remote images keep their URL
+    pub fn get_src(&self) -> &str {
+        &self.src
+    }
+
+    /// The `alt` attribute; `Some("")` marks a decorative image
+    pub fn get_alt(&self) -> Option<&str> {
+        self.alt.as_deref()
+    }
+
+    pub fn get_caption(&self) -> Option<&str> {
+        self.caption.as_deref()
+    }
+
+    /// Whether the image is marked as decorative, with an empty `alt` or
+    /// `role="presentation"`
+    pub fn is_decorative(&self) -> bool {
+        self.alt.as_deref().is_some_and(|alt| alt.trim().is_empty())
+    }
+}
+
+/// Alt text coverage of a book's images
+#[derive(Debug, Clone, Default)]
+pub struct AltTextReport {
+    total: usize,
+    decorative: usize,
+    missing: Vec<Figure>,
+}
+
+impl AltTextReport {
+    /// Number of images in the book
+    pub fn get_total(&self) -> usize {
+        self.total
+    }
+
+    /// Number of images marked as decorative
+    pub fn get_decorative(&self) -> usize {
+        self.decorative
+    }
+
+    /// Images without an `alt` attribute
+    pub fn get_missing(&self) -> &[Figure] {
+        &self.missing
+    }
+
+    /// Whether every image has alt text or is marked as decorative
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+impl Epub {
+    /// Every image in the book, in reading order
+    pub fn get_figures(&self) -> Vec<Figure> {
+        let mut figures = Vec::new();
+        for (chapter_index, chapter) in self.get_chapters().iter().enumerate() {
+            for file in chapter.get_files() {
+                let content = file.get_content();
+                let containers = find_elements(content, |e| e.name == "figure");
+                for image in find_elements(content, |e| e.name == "img") {
+                    let Some(src) = image.attribute("src") else {
+                        continue;
+                    };
+                    let figure = containers.iter().find(|figure| {
+                        figure.range.start <= image.range.start
+                            && image.range.end <= figure.range.end
+                    });
+                    let caption = figure.and_then(|figure| {
+                        find_elements(figure.inner, |e| e.name == "figcaption")
+                            .first()
+                            .map(|caption| html_to_text(caption.inner).replace('\n', " "))
+                            .filter(|caption| !caption.is_empty())
+                    });
+                    let decorative =
+                        image.has_token("role", "presentation") || image.has_token("role", "none");
+                    let alt = image
+                        .attribute("alt")
+                        .map(str::to_string)
+                        .or_else(|| decorative.then(String::new));
+                    figures.push(Figure {
+                        chapter_index,
+                        chapter_title: chapter.get_title().to_string(),
+                        href: file.get_href().to_string(),
+                        id: image
+                            .attribute("id")
+                            .or_else(|| figure.and_then(|figure| figure.attribute("id")))
+                            .map(str::to_string),
+                        src: href::resolve(file.get_href(), src),
+                        alt,
+                        caption,
+                    });
+                }
+            }
+        }
+        figures
+    }
+
+    /// Which images lack alt text, for accessibility checks
+    ///
+    /// A caption doesn't count as alt text: it's read in the flow of the text
+    /// anyway, and an image it describes should still say what it shows.
+    pub fn alt_text_report(&self) -> AltTextReport {
+        let mut report = AltTextReport::default();
+        for figure in self.get_figures() {
+            report.total += 1;
+            if figure.is_decorative() {
+                report.decorative += 1;
+            } else if figure.alt.is_none() {
+                report.missing.push(figure);
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn figures_carry_alt_text_and_captions() {
+        let opf = package(
+            r#"<item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html><body>
+<figure id="fig1"><img src="../images/map.png" alt="Map of the island"/><figcaption>The <em>island</em></figcaption></figure>
+<p><img src="../images/rule.png" alt=""/> <img src="../images/flourish.png" role="presentation"/></p>
+<img src="../images/photo%20one.jpg"/>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("text/ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let figures = epub.get_figures();
+        assert_eq!(figures.len(), 4);
+        assert_eq!(figures[0].get_src(), "images/map.png");
+        assert_eq!(figures[0].get_alt(), Some("Map of the island"));
+        assert_eq!(figures[0].get_caption(), Some("The island"));
+        assert_eq!(figures[0].get_id(), Some("fig1"));
+        assert_eq!(figures[1].get_caption(), None);
+        assert!(figures[1].is_decorative() && figures[2].is_decorative());
+
+        let report = epub.alt_text_report();
+        assert_eq!(report.get_total(), 4);
+        assert_eq!(report.get_decorative(), 2);
+        assert!(!report.is_complete());
+        assert_eq!(report.get_missing()[0].get_src(), "images/photo one.jpg");
+    }
+}
@@ -0,0 +1,272 @@
+//! File names from metadata
+//!
+//! Library organizers rename books after their metadata, e.g.
+//! `{author} - {series #index} - {title}.epub`. Each `{...}` group in the
+//! template holds placeholders and literal text; a group whose first
+//! placeholder has no value is left out along with the separator before
+//! it, so a book outside any series becomes `Author - Title.epub` rather
+//! than `Author -  - Title.epub`. Values are sanitized for every common
+//! filesystem; the template itself is used as written, so it can contain
+//! `/` to sort books into directories.
+//!
+//! Placeholders: `author`, `authors`, `author_sort`, `title`, `series`,
+//! `index`, `year`, `language`, `publisher` and `identifier`.
+
+use crate::epub::Epub;
+
+/// Characters no value may contain, because some filesystem forbids them
+const ILLEGAL_CHARACTERS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+/// Names Windows reserves for devices, with or without an extension
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+/// Longest value put into a file name, in characters
+const MAX_VALUE_LENGTH: usize = 100;
+
+/// A piece of a parsed template
+enum Part<'t> {
+    Literal(&'t str),
+    /// A `{...}` group, split into placeholders and literal text
+    Group(Vec<Token<'t>>),
+}
+
+enum Token<'t> {
+    Field(&'t str),
+    Literal(&'t str),
+}
+
+impl Epub {
+    /// A file name for the book, from `template`
+    ///
+    /// See the `filename` module for the template syntax. Unknown
+    /// placeholders are kept as literal text.
+    pub fn suggest_filename(&self, template: &str) -> String {
+        let parts = parse(template);
+        let values: Vec<Option<String>> = parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(_) => None,
+                Part::Group(tokens) => self.render_group(tokens),
+            })
+            .collect();
+
+        let missing = |index: usize| {
+            matches!(parts.get(index), Some(Part::Group(_))) && values[index].is_none()
+        };
+        let mut name = String::new();
+        let mut emitted = false;
+        for (index, part) in parts.iter().enumerate() {
+            match part {
+                Part::Group(_) => {
+                    if let Some(value) = &values[index] {
+                        name.push_str(value);
+                        emitted = true;
+                    }
+                }
+                Part::Literal(text) => {
+                    // A separator goes with the missing group after it, or,
+                    // before any group has been written, the one before it.
+                    // The template's prefix and suffix always stay.
+                    let separator = index > 0 && index + 1 < parts.len();
+                    let dropped = separator
+                        && if emitted {
+                            missing(index + 1)
+                        } else {
+                            missing(index - 1)
+                        };
+                    if !dropped {
+                        name.push_str(text);
+                    }
+                }
+            }
+        }
+        name
+    }
+
+    /// A group's text, or `None` when its first placeholder has no value
+    fn render_group(&self, tokens: &[Token<'_>]) -> Option<String> {
+        let mut text = String::new();
+        let mut pending = "";
+        let mut first = true;
+        for token in tokens {
+            match token {
+                Token::Literal(literal) => pending = literal,
+                Token::Field(field) => {
+                    match self.filename_field(field) {
+                        Some(value) => {
+                            text.push_str(pending);
+                            text.push_str(&sanitize_filename(&value));
+                        }
+                        None if first => return None,
+                        None => {}
+                    }
+                    pending = "";
+                    first = false;
+                }
+            }
+        }
+        text.push_str(pending);
+        (!first).then_some(text)
+    }
+
+    fn filename_field(&self, field: &str) -> Option<String> {
+        let metadata = self.get_metadata();
+        let value = match field {
+            "author" => self.get_creator().map(str::to_string),
+            "authors" => Some(metadata.get_creators().join(", ")),
+            "author_sort" => metadata
+                .get_creator_entries()
+                .first()
+                .map(|creator| creator.sort_key().to_string()),
+            "title" => self.get_title().map(str::to_string),
+            "series" => crate::library::series(self).0,
+            "index" => crate::library::series(self)
+                .1
+                .map(|index| index.to_string()),
+            "year" => self
+                .get_date()
+                .and_then(|date| date.get(..4))
+                .filter(|year| year.chars().all(|c| c.is_ascii_digit()))
+                .map(str::to_string),
+            "language" => self.get_language().map(str::to_string),
+            "publisher" => self.get_publisher().map(str::to_string),
+            "identifier" => Some(self.get_identifier().to_string()),
+            _ => return Some(format!("{{{}}}", field)),
+        };
+        value.filter(|value| !value.trim().is_empty())
+    }
+}
+
+/// `value` made safe as (part of) a file name on Windows, macOS and Linux
+///
+/// Path separators and characters Windows forbids become `_`, control
+/// characters are dropped, whitespace is collapsed, trailing dots are
+/// removed, reserved device names get a leading `_`, and the result is cut
+/// to 100 characters.
+pub fn sanitize_filename(value: &str) -> String {
+    let cleaned: String = value
+        .chars()
+        .filter(|c| !c.is_control() || c.is_whitespace())
+        .map(|c| {
+            if ILLEGAL_CHARACTERS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let mut name: String = cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_VALUE_LENGTH)
+        .collect();
+    while name.ends_with(['.', ' ']) {
+        name.pop();
+    }
+    let stem = name
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if RESERVED_NAMES.contains(&stem.as_str()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Split a template into literal text and `{...}` groups
+fn parse(template: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+            break;
+        };
+        if open > 0 {
+            parts.push(Part::Literal(&rest[..open]));
+        }
+        parts.push(Part::Group(tokenize(&rest[open + 1..close])));
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Literal(rest));
+    }
+    parts
+}
+
+/// Placeholder names are runs of letters and `_`; everything else is literal
+fn tokenize(group: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_field = false;
+    for (index, c) in group.char_indices() {
+        let field_char = c.is_ascii_alphabetic() || c == '_';
+        if field_char != in_field && index > start {
+            tokens.push(if in_field {
+                Token::Field(&group[start..index])
+            } else {
+                Token::Literal(&group[start..index])
+            });
+            start = index;
+        }
+        in_field = field_char;
+    }
+    if start < group.len() {
+        tokens.push(if in_field {
+            Token::Field(&group[start..])
+        } else {
+            Token::Literal(&group[start..])
+        });
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    fn book(metadata: &str) -> Epub {
+        let opf = format!(
+            r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>id</dc:identifier>{}
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#,
+            metadata
+        );
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn templates_skip_missing_values_and_their_separators() {
+        let template = "{author} - {series #index} - {title}.epub";
+        let messiah = book(
+            r#"<dc:title>Dune Messiah: Book 2</dc:title><dc:creator>Frank Herbert</dc:creator>
+    <dc:date>1969-10-15</dc:date>
+    <meta name="calibre:series" content="Dune"/><meta name="calibre:series_index" content="2"/>"#,
+        );
+        assert_eq!(
+            messiah.suggest_filename(template),
+            "Frank Herbert - Dune #2 - Dune Messiah_ Book 2.epub"
+        );
+        assert_eq!(
+            messiah.suggest_filename("{year}/{title}.epub"),
+            "1969/Dune Messiah_ Book 2.epub"
+        );
+
+        let standalone = book(r#"<dc:title>AUX</dc:title>"#);
+        assert_eq!(standalone.suggest_filename(template), "_AUX.epub");
+        assert_eq!(sanitize_filename("  What?\tNow...  "), "What_ Now");
+    }
+}
@@ -0,0 +1,614 @@
+//! Embedded font licensing and coverage
+//!
+//! Before a book ships, a production editor checks two things about every
+//! embedded font: that its license lets it be embedded at all (the OS/2
+//! `fsType` flags), and that it has a glyph for every character the text
+//! uses. A font that covers most of a script but not all of it is the
+//! dangerous case: the reading system renders the missing characters as
+//! empty boxes ("tofu") or in a fallback font mid-word. `Epub::font_report`
+//! answers both per font. Requires the `fonts` feature.
+//!
+//! Fonts obfuscated per `META-INF/encryption.xml` are de-obfuscated with the
+//! book's identifier first, using the IDPF or Adobe algorithm, as a reading
+//! system would. WOFF and WOFF2 files are listed but can't be read.
+//!
+//! Coverage is measured against the text of the whole book, since which
+//! font styles which element would need a full CSS cascade. For the same
+//! reason `Epub::fallback_characters` only lists characters that no embedded
+//! font has, which are certain to be drawn in a system font instead.
+
+use crate::drm::{FONT_OBFUSCATION_ALGORITHMS, encrypted_resources};
+use crate::epub::{Epub, EpubFile};
+use crate::fonts::is_sfnt;
+use crate::summary::identifiers;
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeMap, BTreeSet};
+
+const WOFF_MEDIA_TYPES: &[&str] = &["font/woff", "font/woff2", "application/font-woff"];
+const IDPF_OBFUSCATION: &str = "http://www.idpf.org/2008/embedding";
+/// Bytes the IDPF and Adobe algorithms obfuscate at the start of a font
+const IDPF_OBFUSCATED_LENGTH: usize = 1040;
+const ADOBE_OBFUSCATED_LENGTH: usize = 1024;
+
+/// Writing systems, coarsely, by Unicode block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Script {
+    /// Digits, punctuation and symbols shared by every script
+    Common,
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+    Devanagari,
+    Bengali,
+    Thai,
+    Georgian,
+    Hangul,
+    Hiragana,
+    Katakana,
+    Han,
+    /// Letters of any other script
+    Other,
+}
+
+impl Script {
+    /// The script a character belongs to
+    pub fn of(c: char) -> Script {
+        match c as u32 {
+            0x41..=0x5a | 0x61..=0x7a | 0xc0..=0x24f | 0x1e00..=0x1eff => Script::Latin,
+            0x370..=0x3ff | 0x1f00..=0x1fff => Script::Greek,
+            0x400..=0x52f => Script::Cyrillic,
+            0x530..=0x58f => Script::Armenian,
+            0x590..=0x5ff => Script::Hebrew,
+            0x600..=0x6ff | 0x750..=0x77f | 0xfb50..=0xfdff | 0xfe70..=0xfeff => Script::Arabic,
+            0x900..=0x97f => Script::Devanagari,
+            0x980..=0x9ff => Script::Bengali,
+            0xe00..=0xe7f => Script::Thai,
+            0x10a0..=0x10ff => Script::Georgian,
+            0x1100..=0x11ff | 0x3130..=0x318f | 0xac00..=0xd7af => Script::Hangul,
+            0x3040..=0x309f => Script::Hiragana,
+            0x30a0..=0x30ff => Script::Katakana,
+            0x3400..=0x4dbf | 0x4e00..=0x9fff | 0xf900..=0xfaff | 0x20000..=0x2ffff => Script::Han,
+            _ if c.is_alphabetic() => Script::Other,
+            _ => Script::Common,
+        }
+    }
+}
+
+/// What a font's license allows, from its OS/2 `fsType`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingPermission {
+    /// May be embedded and installed permanently
+    Installable,
+    /// Must not be embedded without the rights holder's permission
+    Restricted,
+    /// May be embedded for viewing and printing only
+    PreviewAndPrint,
+    /// May be embedded for viewing and editing
+    Editable,
+}
+
+/// How much of one script in the book's text a font covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptCoverage {
+    script: Script,
+    needed: usize,
+    missing: Vec<char>,
+}
+
+impl ScriptCoverage {
+    pub fn get_script(&self) -> Script {
+        self.script
+    }
+
+    /// Number of distinct characters of the script in the book's text
+    pub fn get_needed(&self) -> usize {
+        self.needed
+    }
+
+    /// Characters of the script the font has no glyph for
+    pub fn get_missing(&self) -> &[char] {
+        &self.missing
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// Whether the font has some of the script's characters but not all
+    pub fn is_partial(&self) -> bool {
+        !self.missing.is_empty() && self.missing.len() < self.needed
+    }
+}
+
+/// One embedded font, from `Epub::font_report`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontInfo {
+    href: String,
+    family: Option<String>,
+    obfuscated: bool,
+    readable: bool,
+    subsetted: bool,
+    permission: Option<EmbeddingPermission>,
+    subsetting_allowed: bool,
+    glyph_count: usize,
+    coverage: Vec<ScriptCoverage>,
+}
+
+impl FontInfo {
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// The family name from the font's `name` table
+    pub fn get_family(&self) -> Option<&str> {
+        self.family.as_deref()
+    }
+
+    /// Whether `encryption.xml` lists the font as obfuscated
+    pub fn is_obfuscated(&self) -> bool {
+        self.obfuscated
+    }
+
+    /// Whether the font could be parsed; the rest of the report is empty
+    /// when it couldn't
+    pub fn is_readable(&self) -> bool {
+        self.readable
+    }
+
+    /// Whether the font looks subsetted: its name carries a subset tag such
+    /// as `ABCDEF+`, or it lacks some of the basic Latin letters and digits
+    /// while having others
+    pub fn is_subsetted(&self) -> bool {
+        self.subsetted
+    }
+
+    /// `None` when the font has no OS/2 table
+    pub fn get_permission(&self) -> Option<EmbeddingPermission> {
+        self.permission
+    }
+
+    /// Whether the license allows embedding a subset of the font
+    pub fn is_subsetting_allowed(&self) -> bool {
+        self.subsetting_allowed
+    }
+
+    pub fn get_glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    /// Coverage of each script in the book's text, in `Script` order
+    pub fn get_coverage(&self) -> &[ScriptCoverage] {
+        &self.coverage
+    }
+
+    /// Scripts in the book's text the font fully covers
+    pub fn get_covered_scripts(&self) -> Vec<Script> {
+        self.coverage
+            .iter()
+            .filter(|coverage| coverage.is_complete())
+            .map(|coverage| coverage.script)
+            .collect()
+    }
+
+    /// Every character in the book's text the font has no glyph for
+    pub fn get_missing_characters(&self) -> Vec<char> {
+        self.coverage
+            .iter()
+            .flat_map(|coverage| coverage.missing.iter().copied())
+            .collect()
+    }
+
+    /// Whether text in a script the font is meant for may hit a missing
+    /// glyph, i.e. some script is only partly covered
+    pub fn has_tofu_risk(&self) -> bool {
+        self.coverage.iter().any(ScriptCoverage::is_partial)
+    }
+
+    /// Whether the license forbids embedding the font as it is
+    pub fn has_license_problem(&self) -> bool {
+        self.permission == Some(EmbeddingPermission::Restricted)
+            || (self.subsetted && !self.subsetting_allowed)
+    }
+}
+
+/// A character no embedded font covers, from `Epub::fallback_characters`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackCharacter {
+    character: char,
+    occurrences: usize,
+    hrefs: Vec<String>,
+}
+
+impl FallbackCharacter {
+    pub fn get_character(&self) -> char {
+        self.character
+    }
+
+    /// The code point as `U+XXXX`
+    pub fn get_code_point(&self) -> String {
+        format!("U+{:04X}", u32::from(self.character))
+    }
+
+    pub fn get_script(&self) -> Script {
+        Script::of(self.character)
+    }
+
+    /// How many times the character appears in the text
+    pub fn get_occurrences(&self) -> usize {
+        self.occurrences
+    }
+
+    /// The documents it appears in
+    pub fn get_hrefs(&self) -> &[String] {
+        &self.hrefs
+    }
+}
+
+/// A font's href and, if it can be parsed, its plain sfnt data
+struct EmbeddedFont {
+    href: String,
+    obfuscated: bool,
+    data: Option<Vec<u8>>,
+}
+
+impl Epub {
+    /// Family, license, obfuscation and coverage of every embedded font,
+    /// in manifest order
+    pub fn font_report(&self) -> Vec<FontInfo> {
+        enter_span!("font_report");
+        let needed = self.needed_characters();
+        self.embedded_fonts()
+            .into_iter()
+            .map(|font| {
+                let mut info = font
+                    .data
+                    .as_deref()
+                    .and_then(|data| ttf_parser::Face::parse(data, 0).ok())
+                    .map(|face| describe(&face, &needed))
+                    .unwrap_or_else(|| FontInfo {
+                        href: String::new(),
+                        family: None,
+                        obfuscated: false,
+                        readable: false,
+                        subsetted: false,
+                        permission: None,
+                        subsetting_allowed: false,
+                        glyph_count: 0,
+                        coverage: Vec::new(),
+                    });
+                info.href = font.href;
+                info.obfuscated = font.obfuscated;
+                info
+            })
+            .collect()
+    }
+
+    /// Characters in the text of the content documents that no embedded
+    /// font has a glyph for, so they will be drawn in a system font
+    ///
+    /// Empty when the book embeds no fonts that can be read, since then all
+    /// of its text is meant for system fonts. Fonts that can't be read, such
+    /// as WOFF files, don't count as covering anything.
+    pub fn fallback_characters(&self) -> Vec<FallbackCharacter> {
+        enter_span!("fallback_characters");
+        let fonts: Vec<Vec<u8>> = self
+            .embedded_fonts()
+            .into_iter()
+            .filter_map(|font| font.data)
+            .collect();
+        let faces: Vec<ttf_parser::Face> = fonts
+            .iter()
+            .filter_map(|data| ttf_parser::Face::parse(data, 0).ok())
+            .collect();
+        if faces.is_empty() {
+            return Vec::new();
+        }
+
+        self.uncovered_characters(|c| faces.iter().any(|face| face.glyph_index(c).is_some()))
+    }
+
+    /// Characters in the text of the content documents for which `covered`
+    /// is false
+    fn uncovered_characters(&self, covered: impl Fn(char) -> bool) -> Vec<FallbackCharacter> {
+        let mut inventory: BTreeMap<char, FallbackCharacter> = BTreeMap::new();
+        for file in &self.all_files {
+            for c in self
+                .file_text(file)
+                .chars()
+                .filter(|c| !c.is_whitespace() && !c.is_control())
+            {
+                let entry = inventory.entry(c).or_insert_with(|| FallbackCharacter {
+                    character: c,
+                    occurrences: 0,
+                    hrefs: Vec::new(),
+                });
+                entry.occurrences += 1;
+                if entry.hrefs.last().is_none_or(|href| **href != *file.href) {
+                    entry.hrefs.push(file.href.to_string());
+                }
+            }
+        }
+        inventory
+            .into_values()
+            .filter(|entry| !covered(entry.character))
+            .collect()
+    }
+
+    /// Every embedded font in manifest order, de-obfuscated where needed
+    fn embedded_fonts(&self) -> Vec<EmbeddedFont> {
+        let obfuscation: BTreeMap<String, String> = self
+            .get_resource_bytes("/META-INF/encryption.xml")
+            .map(|bytes| encrypted_resources(&String::from_utf8_lossy(&bytes)))
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|resource| FONT_OBFUSCATION_ALGORITHMS.contains(&resource.get_algorithm()))
+            .map(|resource| {
+                (
+                    resource.get_path().to_string(),
+                    resource.get_algorithm().to_string(),
+                )
+            })
+            .collect();
+        let identifiers = identifiers(self);
+
+        self.resources
+            .iter()
+            .filter(|resource| {
+                is_sfnt(resource)
+                    || WOFF_MEDIA_TYPES.contains(&resource.media_type.to_ascii_lowercase().as_str())
+                    || ["woff", "woff2"].iter().any(|extension| {
+                        resource
+                            .href
+                            .rsplit('.')
+                            .next()
+                            .is_some_and(|e| e.eq_ignore_ascii_case(extension))
+                    })
+            })
+            .map(|resource| {
+                let path = Self::resolve_path(&self.opf_path, &resource.href);
+                let algorithm = obfuscation.get(&path);
+                let data = self.get_resource_bytes(&resource.href).unwrap_or_default();
+                let data = match algorithm {
+                    // The identifier the key comes from is the package's
+                    // unique one, which isn't necessarily listed first
+                    Some(algorithm) => identifiers
+                        .iter()
+                        .filter_map(|id| deobfuscate(&data, algorithm, id))
+                        .find(|data| ttf_parser::Face::parse(data, 0).is_ok()),
+                    None => Some(data).filter(|data| ttf_parser::Face::parse(data, 0).is_ok()),
+                };
+                EmbeddedFont {
+                    href: resource.href.clone(),
+                    obfuscated: algorithm.is_some(),
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    /// The readable text of a content document, for taking stock of the
+    /// characters it uses
+    ///
+    /// With the `dom` feature it comes from the document's cached tree.
+    pub(crate) fn file_text(&self, file: &EpubFile) -> String {
+        #[cfg(feature = "dom")]
+        let text = self.file_dom(file).get_text();
+        #[cfg(not(feature = "dom"))]
+        let text = file.get_text();
+        text
+    }
+
+    /// Distinct visible characters in the text of the content documents and
+    /// the table of contents
+    fn needed_characters(&self) -> BTreeSet<char> {
+        let mut characters = BTreeSet::new();
+        let mut add = |text: &str| {
+            characters.extend(
+                text.chars()
+                    .filter(|c| !c.is_whitespace() && !c.is_control()),
+            );
+        };
+        for file in &self.all_files {
+            add(&self.file_text(file));
+        }
+        for entry in self.table_of_contents.get_entries() {
+            add(entry.get_title());
+        }
+        characters
+    }
+}
+
+/// What a parsed font has to say about itself; the href is left for the
+/// caller to fill in
+fn describe(face: &ttf_parser::Face, needed: &BTreeSet<char>) -> FontInfo {
+    let name = |id: u16| {
+        face.names()
+            .into_iter()
+            .filter(|name| name.name_id == id)
+            .find_map(|name| name.to_string())
+    };
+    let family =
+        name(ttf_parser::name_id::TYPOGRAPHIC_FAMILY).or_else(|| name(ttf_parser::name_id::FAMILY));
+    let tagged = [family.clone(), name(ttf_parser::name_id::POST_SCRIPT_NAME)]
+        .into_iter()
+        .flatten()
+        .any(|name| has_subset_tag(&name));
+    let basic = ('A'..='Z').chain('a'..='z').chain('0'..='9');
+    let (present, absent): (Vec<char>, Vec<char>) =
+        basic.partition(|&c| face.glyph_index(c).is_some());
+
+    let mut scripts: BTreeMap<Script, ScriptCoverage> = BTreeMap::new();
+    for &c in needed {
+        let script = Script::of(c);
+        let coverage = scripts.entry(script).or_insert(ScriptCoverage {
+            script,
+            needed: 0,
+            missing: Vec::new(),
+        });
+        coverage.needed += 1;
+        if face.glyph_index(c).is_none() {
+            coverage.missing.push(c);
+        }
+    }
+
+    FontInfo {
+        href: String::new(),
+        family,
+        obfuscated: false,
+        readable: true,
+        subsetted: tagged || (!present.is_empty() && !absent.is_empty()),
+        permission: face.permissions().map(|permission| match permission {
+            ttf_parser::Permissions::Installable => EmbeddingPermission::Installable,
+            ttf_parser::Permissions::Restricted => EmbeddingPermission::Restricted,
+            ttf_parser::Permissions::PreviewAndPrint => EmbeddingPermission::PreviewAndPrint,
+            ttf_parser::Permissions::Editable => EmbeddingPermission::Editable,
+        }),
+        subsetting_allowed: face.is_subsetting_allowed(),
+        glyph_count: usize::from(face.number_of_glyphs()),
+        coverage: scripts.into_values().collect(),
+    }
+}
+
+/// Whether a font name starts with a subset tag: six capitals and a `+`
+fn has_subset_tag(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() > 7 && bytes[..6].iter().all(u8::is_ascii_uppercase) && bytes[6] == b'+'
+}
+
+/// Undo font obfuscation with the key derived from `identifier`; `None`
+/// when the algorithm is unknown or the identifier can't be a key for it
+fn deobfuscate(data: &[u8], algorithm: &str, identifier: &str) -> Option<Vec<u8>> {
+    let (key, length) = if algorithm == IDPF_OBFUSCATION {
+        let identifier: String = identifier
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '\t' | '\r' | '\n'))
+            .collect();
+        (
+            Sha1::digest(identifier.as_bytes()).to_vec(),
+            IDPF_OBFUSCATED_LENGTH,
+        )
+    } else {
+        // Adobe's key is the 16 bytes of the book's UUID
+        let hex: String = identifier
+            .trim()
+            .trim_start_matches("urn:uuid:")
+            .chars()
+            .filter(|c| *c != '-')
+            .collect();
+        if hex.len() != 32 || !hex.is_ascii() {
+            return None;
+        }
+        let key = (0..16)
+            .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        (key, ADOBE_OBFUSCATED_LENGTH)
+    };
+    let mut data = data.to_vec();
+    for (i, byte) in data.iter_mut().take(length).enumerate() {
+        *byte ^= key[i % key.len()];
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn scripts_and_subset_tags() {
+        assert_eq!(Script::of('a'), Script::Latin);
+        assert_eq!(Script::of('ж'), Script::Cyrillic);
+        assert_eq!(Script::of('漢'), Script::Han);
+        assert_eq!(Script::of('“'), Script::Common);
+        assert!(has_subset_tag("ABCDEF+Minion-Regular"));
+        assert!(!has_subset_tag("Minion+Pro"));
+    }
+
+    #[test]
+    fn obfuscation_round_trips() {
+        let font = b"\x00\x01\x00\x00font data".to_vec();
+        let id = "urn:uuid:12345678-1234-1234-1234-123456789abc";
+        for algorithm in FONT_OBFUSCATION_ALGORITHMS {
+            let obfuscated = deobfuscate(&font, algorithm, id).unwrap();
+            assert_ne!(obfuscated, font);
+            assert_eq!(deobfuscate(&obfuscated, algorithm, id).unwrap(), font);
+        }
+        assert!(deobfuscate(&font, "http://ns.adobe.com/pdf/enc#RC", "isbn").is_none());
+    }
+
+    #[test]
+    fn uncovered_characters_are_counted_per_document() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>ẞ and ß</p></body></html>"),
+            ("ch2.xhtml", "<html><body><p>ß ☃</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let uncovered = epub.uncovered_characters(|c| c.is_ascii());
+        let summary: Vec<(String, usize, &[String])> = uncovered
+            .iter()
+            .map(|c| (c.get_code_point(), c.get_occurrences(), c.get_hrefs()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "U+00DF".to_string(),
+                    2,
+                    &["ch1.xhtml".to_string(), "ch2.xhtml".to_string()][..]
+                ),
+                ("U+1E9E".to_string(), 1, &["ch1.xhtml".to_string()][..]),
+                ("U+2603".to_string(), 1, &["ch2.xhtml".to_string()][..]),
+            ]
+        );
+        assert_eq!(uncovered[2].get_script(), Script::Common);
+        // No readable embedded fonts: everything is meant for system fonts
+        assert!(epub.fallback_characters().is_empty());
+    }
+
+    #[test]
+    fn unreadable_fonts_are_still_listed() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="serif" href="serif.otf" media-type="font/otf"/>
+               <item id="sans" href="sans.woff2" media-type="font/woff2"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let encryption = r#"<encryption xmlns="urn:oasis:names:tc:opendocument:xmlns:container" xmlns:enc="http://www.w3.org/2001/04/xmlenc#">
+<enc:EncryptedData><enc:EncryptionMethod Algorithm="http://www.idpf.org/2008/embedding"/>
+<enc:CipherData><enc:CipherReference URI="serif.otf"/></enc:CipherData></enc:EncryptedData></encryption>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("META-INF/encryption.xml", encryption),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>Ça va</p></body></html>"),
+            ("serif.otf", "not a font"),
+            ("sans.woff2", "wOF2"),
+        ]))
+        .unwrap();
+
+        let report = epub.font_report();
+        let summary: Vec<(&str, bool, bool)> = report
+            .iter()
+            .map(|font| (font.get_href(), font.is_obfuscated(), font.is_readable()))
+            .collect();
+        assert_eq!(
+            summary,
+            [("serif.otf", true, false), ("sans.woff2", false, false)]
+        );
+        assert!(!report[0].has_tofu_risk());
+        let needed = epub.needed_characters();
+        assert!(needed.contains(&'Ç') && needed.contains(&'v') && !needed.contains(&' '));
+    }
+}
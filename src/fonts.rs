@@ -0,0 +1,396 @@
+//! Subsetting embedded fonts
+//!
+//! Books often embed complete font families, several megabytes each, to set
+//! a few hundred distinct characters. `Epub::subset_fonts` cuts every
+//! TrueType/OpenType font down to the glyphs the book's text can need. Glyph
+//! ids and hrefs don't change, so `@font-face` rules keep working as written.
+//! Requires the `fonts` feature.
+//!
+//! The subset drops the layout tables (GSUB, GPOS), so ligatures and kerning
+//! are lost. That is only cosmetic for scripts that render one glyph per
+//! character, but text in Arabic, Indic scripts, Thai or with combining marks
+//! comes out unjoined or misplaced without shaping, and glyphs reached only
+//! through GSUB (small caps, contextual forms) disappear. So when the book
+//! has such text, or its CSS asks for OpenType features, fonts with layout
+//! tables are left whole. Fonts obfuscated per `META-INF/encryption.xml` and
+//! WOFF/WOFF2 files are left alone too.
+
+use crate::epub::{Epub, Resource};
+use crate::fontinfo::Script;
+use crate::styles::declarations;
+use std::collections::BTreeSet;
+
+/// Media types used for uncompressed TrueType/OpenType fonts in the wild
+pub(crate) const SFNT_MEDIA_TYPES: &[&str] = &[
+    "font/otf",
+    "font/ttf",
+    "font/sfnt",
+    "application/font-sfnt",
+    "application/vnd.ms-opentype",
+    "application/x-font-otf",
+    "application/x-font-ttf",
+    "application/x-font-truetype",
+    "application/x-font-opentype",
+];
+
+/// A font that `subset_fonts` replaced
+#[derive(Debug, Clone)]
+pub struct SubsetFont {
+    href: String,
+    original_size: usize,
+    subset_size: usize,
+}
+
+impl SubsetFont {
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_original_size(&self) -> usize {
+        self.original_size
+    }
+
+    pub fn get_subset_size(&self) -> usize {
+        self.subset_size
+    }
+}
+
+impl Epub {
+    /// Reduce embedded fonts to the glyphs used by the book's text
+    ///
+    /// Every font is kept large enough for all text in the book, since which
+    /// font styles which element would need a full CSS cascade. Upper- and
+    /// lowercase forms of each letter are kept so `text-transform` still
+    /// renders. Fonts that fail to parse, or don't get smaller, are left alone,
+    /// as are fonts with layout tables when the book needs them (see the
+    /// module docs).
+    pub fn subset_fonts(&mut self) -> Vec<SubsetFont> {
+        enter_span!("subset_fonts");
+        let characters = self.used_characters();
+        let needs_layout =
+            characters.iter().any(|&c| needs_shaping(c)) || self.uses_font_features();
+        let obfuscated = self
+            .get_resource_bytes("/META-INF/encryption.xml")
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default();
+
+        let fonts: Vec<String> = self
+            .resources
+            .iter()
+            .filter(|resource| is_sfnt(resource))
+            .map(|resource| resource.href.clone())
+            .collect();
+
+        let mut subset = Vec::new();
+        for href in fonts {
+            let path = Self::resolve_path(&self.opf_path, &href);
+            if obfuscated.contains(&format!("URI=\"{}\"", path)) {
+                trace_debug!(href = %href, "skipping obfuscated font");
+                continue;
+            }
+            let Some(original) = self.get_resource_bytes(&href) else {
+                continue;
+            };
+            if needs_layout && has_layout_tables(&original) {
+                trace_debug!(href = %href, "skipping font the text needs shaped");
+                continue;
+            }
+            let Some(bytes) = subset_font(&original, &characters) else {
+                trace_warn!(href = %href, "font could not be subset");
+                continue;
+            };
+            if bytes.len() >= original.len() {
+                continue;
+            }
+
+            subset.push(SubsetFont {
+                href: href.clone(),
+                original_size: original.len(),
+                subset_size: bytes.len(),
+            });
+            self.put_resource_bytes(&href, bytes);
+        }
+        subset
+    }
+
+    /// Every character that may be rendered: document text, TOC labels and the title
+    fn used_characters(&self) -> BTreeSet<char> {
+        let mut text = String::new();
+        for file in &self.all_files {
+            text.push_str(&self.file_text(file));
+        }
+        for entry in self.table_of_contents.get_entries() {
+            text.push_str(entry.get_title());
+        }
+        text.push_str(self.get_title().unwrap_or_default());
+
+        let mut characters: BTreeSet<char> = (' '..='~').collect();
+        for c in text.chars().filter(|c| !c.is_control()) {
+            characters.insert(c);
+            characters.extend(c.to_uppercase());
+            characters.extend(c.to_lowercase());
+        }
+        characters
+    }
+
+    /// Whether any stylesheet asks for OpenType features, which live in GSUB
+    fn uses_font_features(&self) -> bool {
+        let mut found = false;
+        self.for_each_style_rule(|_, _, block| {
+            found |= declarations(block).iter().any(|d| {
+                d.property == "font-feature-settings"
+                    || d.property.starts_with("font-variant")
+                        && !d.value.eq_ignore_ascii_case("normal")
+                        && !d.value.eq_ignore_ascii_case("none")
+            });
+        });
+        found
+    }
+}
+
+/// Whether `c` renders wrongly without the shaping GSUB and GPOS provide
+fn needs_shaping(c: char) -> bool {
+    let combining = matches!(
+        c as u32,
+        0x300..=0x36f | 0x591..=0x5c7 | 0x1ab0..=0x1aff | 0x1dc0..=0x1dff | 0x20d0..=0x20ff
+    );
+    combining
+        || matches!(
+            Script::of(c),
+            Script::Arabic | Script::Devanagari | Script::Bengali | Script::Thai | Script::Other
+        )
+}
+
+/// Whether a font has GSUB or GPOS tables; `false` if it can't be parsed
+fn has_layout_tables(data: &[u8]) -> bool {
+    ttf_parser::Face::parse(data, 0)
+        .is_ok_and(|face| face.tables().gsub.is_some() || face.tables().gpos.is_some())
+}
+
+/// Whether a manifest item is an uncompressed TrueType/OpenType font
+pub(crate) fn is_sfnt(resource: &Resource) -> bool {
+    let media_type = resource.media_type.to_ascii_lowercase();
+    let extension = resource.href.rsplit('.').next().unwrap_or("");
+    SFNT_MEDIA_TYPES.contains(&media_type.as_str())
+        || extension.eq_ignore_ascii_case("otf")
+        || extension.eq_ignore_ascii_case("ttf")
+}
+
+/// Subset one sfnt font to the glyphs for `characters`; `None` if it can't be parsed
+fn subset_font(data: &[u8], characters: &BTreeSet<char>) -> Option<Vec<u8>> {
+    let face = ttf_parser::Face::parse(data, 0).ok()?;
+    // Glyph 0 is .notdef, which renderers fall back to for anything missing
+    let mut glyphs = vec![0u16];
+    glyphs.extend(
+        characters
+            .iter()
+            .filter_map(|&c| face.glyph_index(c))
+            .map(|glyph| glyph.0),
+    );
+    glyphs.sort_unstable();
+    glyphs.dedup();
+    subsetter::subset(data, 0, subsetter::Profile::pdf(&glyphs)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build, package};
+
+    /// A TrueType font with a triangle glyph for each of `characters`, and
+    /// an empty GSUB table if `gsub`
+    fn font(characters: &str, gsub: bool) -> Vec<u8> {
+        let be16 = |out: &mut Vec<u8>, value: u16| out.extend(value.to_be_bytes());
+        let be32 = |out: &mut Vec<u8>, value: u32| out.extend(value.to_be_bytes());
+        let glyph_count = characters.chars().count() as u16 + 1;
+
+        // One contour of three on-curve points, with 16-bit coordinates
+        let mut glyph = Vec::new();
+        for value in [1u16, 0, 0, 100, 100, 2, 0] {
+            be16(&mut glyph, value);
+        }
+        glyph.extend([1, 1, 1]);
+        for value in [0i16, 100, -50, 0, 0, 100] {
+            glyph.extend(value.to_be_bytes());
+        }
+        glyph.push(0);
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for _ in 0..glyph_count {
+            be32(&mut loca, glyf.len() as u32);
+            glyf.extend(&glyph);
+        }
+        be32(&mut loca, glyf.len() as u32);
+
+        let mut head = Vec::new();
+        be32(&mut head, 0x10000);
+        be32(&mut head, 0x10000);
+        be32(&mut head, 0);
+        be32(&mut head, 0x5f0f3cf5);
+        be16(&mut head, 0);
+        be16(&mut head, 1000);
+        head.extend([0; 16]);
+        for value in [0u16, 0, 100, 100, 0, 8, 2, 1, 0] {
+            be16(&mut head, value);
+        }
+        let mut hhea = Vec::new();
+        be32(&mut hhea, 0x10000);
+        for value in [800u16, 0xff38, 0, 100, 0, 0, 100, 1, 0, 0, 0, 0, 0, 0, 0] {
+            be16(&mut hhea, value);
+        }
+        be16(&mut hhea, glyph_count);
+        let mut maxp = Vec::new();
+        be32(&mut maxp, 0x10000);
+        be16(&mut maxp, glyph_count);
+        for value in [3u16, 1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0] {
+            be16(&mut maxp, value);
+        }
+        let mut hmtx = Vec::new();
+        for _ in 0..glyph_count {
+            be16(&mut hmtx, 120);
+            be16(&mut hmtx, 0);
+        }
+        // A format 12 subtable with one group per character
+        let mut cmap = Vec::new();
+        let mut mapped: Vec<char> = characters.chars().collect();
+        mapped.sort_unstable();
+        for value in [0u16, 1, 3, 10] {
+            be16(&mut cmap, value);
+        }
+        be32(&mut cmap, 12);
+        be16(&mut cmap, 12);
+        be16(&mut cmap, 0);
+        be32(&mut cmap, 16 + 12 * mapped.len() as u32);
+        be32(&mut cmap, 0);
+        be32(&mut cmap, mapped.len() as u32);
+        for (index, c) in mapped.iter().enumerate() {
+            be32(&mut cmap, *c as u32);
+            be32(&mut cmap, *c as u32);
+            be32(&mut cmap, index as u32 + 1);
+        }
+
+        let mut tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        if gsub {
+            // Version 1.0 with empty script, feature and lookup lists
+            let mut table = Vec::new();
+            for value in [1u16, 0, 10, 12, 14, 0, 0, 0] {
+                be16(&mut table, value);
+            }
+            tables.insert(0, (b"GSUB", table));
+        }
+
+        let mut out = Vec::new();
+        be32(&mut out, 0x10000);
+        be16(&mut out, tables.len() as u16);
+        let selector = tables.len().ilog2();
+        let search_range = 16 << selector;
+        for value in [
+            search_range,
+            selector,
+            16 * tables.len() as u32 - search_range,
+        ] {
+            be16(&mut out, value as u16);
+        }
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in &tables {
+            out.extend(*tag);
+            be32(&mut out, 0);
+            be32(&mut out, offset as u32);
+            be32(&mut out, data.len() as u32);
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tables {
+            out.extend(data);
+            out.resize(out.len().next_multiple_of(4), 0);
+        }
+        out
+    }
+
+    fn book(text: &str) -> Epub {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="font" href="serif.ttf" media-type="font/ttf"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = format!("<html><body><p>{}</p></body></html>", text);
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", &chapter),
+            ("serif.ttf", "placeholder"),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn subset_keeps_the_glyphs_the_text_uses() {
+        let mut epub = book("Añb");
+        epub.put_resource_bytes("serif.ttf", font("Añbжщыэюя", false));
+
+        let subset = epub.subset_fonts();
+        assert_eq!(subset.len(), 1);
+        assert!(subset[0].get_subset_size() < subset[0].get_original_size());
+
+        let data = epub.get_resource_bytes("serif.ttf").unwrap();
+        let face = ttf_parser::Face::parse(&data, 0).unwrap();
+        let outline = |c: char| {
+            let glyph = face.glyph_index(c).expect("character still in cmap");
+            face.glyph_bounding_box(glyph).is_some()
+        };
+        assert!(outline('A') && outline('ñ') && outline('b'));
+        assert!(!outline('ж') && !outline('я'));
+    }
+
+    #[test]
+    fn fonts_with_layout_tables_are_kept_for_text_that_needs_shaping() {
+        let mut epub = book("سلام");
+        let original = font("سلام", true);
+        epub.put_resource_bytes("serif.ttf", original.clone());
+        assert!(epub.subset_fonts().is_empty());
+        assert_eq!(epub.get_resource_bytes("serif.ttf"), Some(original));
+
+        // Without layout tables there is nothing to lose
+        epub.put_resource_bytes("serif.ttf", font("سلامжщыэюя", false));
+        assert_eq!(epub.subset_fonts().len(), 1);
+    }
+
+    #[test]
+    fn used_characters_cover_both_cases_and_skip_markup() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="font" href="serif.otf" media-type="font/otf"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                r#"<html><body class="ж"><p>ñandú</p></body></html>"#,
+            ),
+            ("serif.otf", "not a font"),
+        ]))
+        .unwrap();
+
+        let characters = epub.used_characters();
+        assert!(
+            characters.contains(&'ñ') && characters.contains(&'Ñ') && characters.contains(&'Ú')
+        );
+        assert!(!characters.contains(&'ж'));
+
+        // Unparseable fonts are left as they are
+        assert!(epub.subset_fonts().is_empty());
+        assert_eq!(
+            epub.get_resource_bytes("serif.otf").as_deref(),
+            Some(&b"not a font"[..])
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! Flattening footnotes for reading systems without popups
+//!
+//! EPUB 3 books mark note references with `epub:type="noteref"` and expect
+//! the reading system to show the note in a popup. Many e-ink readers
+//! instead follow the link to the end of the book, and getting back is
+//! tedious. `Epub::flatten_notes` produces a copy where the notes no longer
+//! depend on that: either each note's text is put in brackets right where
+//! it is referenced, or all notes are gathered into one numbered notes
+//! chapter at the end, with plain links there and back.
+//!
+//! Besides `noteref` markup (and the `doc-noteref` role), links wrapped in
+//! or wrapping a `<sup>` with a short label count as note references, since
+//! that is how most converted books mark them. The note is the element the
+//! link points at; when that is only an anchor inside a paragraph or list
+//! item, the whole paragraph or item is taken. Documents that held nothing
+//! but notes are removed.
+
+use crate::epub::{Chapter, Epub};
+use crate::error::EpubError;
+use crate::href;
+use crate::nav::is_navigation;
+use crate::text::{escape_xml, find_headings, html_to_text};
+use crate::xhtml::{self, Element, TAG, VOID_ELEMENTS, find_elements};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Longest label, in characters, a `<sup>` link can have to count as a note reference
+const MAX_LABEL: usize = 4;
+/// Elements taken as the note when a link points at an anchor inside one
+const NOTE_BLOCKS: &[&str] = &["aside", "li", "p", "div", "dd", "section"];
+/// Text of links that lead from a note back to its reference
+const BACKLINK_TEXTS: &[&str] = &["↩", "↩\u{fe0e}", "↑", "^", "back", "return"];
+const NOTES_TITLE: &str = "Notes";
+
+/// Where `Epub::flatten_notes` puts the notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoteStyle {
+    /// In brackets at the point of reference
+    Inline,
+    /// Numbered in a notes chapter at the end of the book
+    NotesChapter,
+}
+
+/// A note reference in a spine document
+struct NoteRef {
+    href: String,
+    range: Range<usize>,
+    note: usize,
+}
+
+/// The element a note reference points at
+struct Note {
+    href: String,
+    range: Range<usize>,
+    text: String,
+}
+
+impl Epub {
+    /// A copy of the book with its notes inline or in one notes chapter,
+    /// instead of behind popup links
+    ///
+    /// Returns an unchanged copy when the book has no note references.
+    pub fn flatten_notes(&self, style: NoteStyle) -> Result<Epub, EpubError> {
+        enter_span!("flatten_notes");
+        let (notes, refs) = self.find_notes();
+        let mut book = self.clone();
+        if refs.is_empty() {
+            return Ok(book);
+        }
+
+        let notes_href = match style {
+            NoteStyle::Inline => None,
+            NoteStyle::NotesChapter => Some(book.unique_resource_href("notes", "xhtml")),
+        };
+        let notes_path = notes_href
+            .as_deref()
+            .map(|notes_href| Self::resolve_path(&self.opf_path, notes_href));
+
+        let mut edits: BTreeMap<&str, Vec<(Range<usize>, String)>> = BTreeMap::new();
+        for note in &notes {
+            edits
+                .entry(&note.href)
+                .or_default()
+                .push((note.range.clone(), String::new()));
+        }
+        for note_ref in &refs {
+            let replacement = match &notes_path {
+                None => format!(
+                    "<span class=\"note\">[{}]</span>",
+                    escape_xml(&notes[note_ref.note].text)
+                ),
+                Some(notes_path) => {
+                    let path = Self::resolve_path(&self.opf_path, &note_ref.href);
+                    let number = note_ref.note + 1;
+                    let first = refs.iter().position(|r| r.note == note_ref.note);
+                    let id = if first.is_some_and(|first| refs[first].range == note_ref.range) {
+                        format!(" id=\"noteref-{}\"", number)
+                    } else {
+                        String::new()
+                    };
+                    format!(
+                        "<sup><a{} href=\"{}#note-{}\">{}</a></sup>",
+                        id,
+                        href::relative(&path, notes_path),
+                        number,
+                        number
+                    )
+                }
+            };
+            edits
+                .entry(&note_ref.href)
+                .or_default()
+                .push((note_ref.range.clone(), replacement));
+        }
+
+        let mut emptied = Vec::new();
+        for (doc, mut doc_edits) in edits {
+            let Some(file) = self.all_files.iter().find(|file| *file.href == *doc) else {
+                continue;
+            };
+            let mut content = file.content.clone();
+            doc_edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+            let mut applied_from = usize::MAX;
+            for (range, replacement) in doc_edits {
+                if range.end > applied_from {
+                    continue;
+                }
+                content.replace_range(range.clone(), &replacement);
+                applied_from = range.start;
+            }
+            let held_notes = notes.iter().any(|note| note.href == doc);
+            if held_notes && !has_text_besides_headings(&content) {
+                emptied.push(doc.to_string());
+            }
+            book.put_resource_bytes(doc, content.into_bytes());
+        }
+        for doc in &emptied {
+            if !book.get_resource_by_href(doc).is_some_and(is_navigation) {
+                let _ = book.remove_resource(doc);
+            }
+        }
+
+        if let Some(notes_href) = notes_href {
+            book.append_notes_chapter(&notes_href, &notes, &refs)?;
+        }
+        book.unlink_dangling();
+        book.regenerate_navigation();
+        book.prune_orphans();
+        Ok(book)
+    }
+
+    /// Note references in spine order and the notes they point at, each
+    /// note once, in order of first reference
+    fn find_notes(&self) -> (Vec<Note>, Vec<NoteRef>) {
+        let mut notes: Vec<Note> = Vec::new();
+        let mut refs: Vec<NoteRef> = Vec::new();
+        for file in self.get_spine_files() {
+            let path = Self::resolve_path(&self.opf_path, &file.href);
+            for (range, anchor) in find_noterefs(&file.content) {
+                let Some(reference) = anchor.attribute("href").map(str::trim) else {
+                    continue;
+                };
+                let Some(id) = href::fragment(reference) else {
+                    continue;
+                };
+                let target_path = match reference.split_once('#') {
+                    Some(("", _)) => path.clone(),
+                    _ => href::resolve(&path, reference),
+                };
+                let target_href = href::relative(&self.opf_path, &target_path);
+                let Some(target) = self.all_files.iter().find(|file| *file.href == target_href)
+                else {
+                    continue;
+                };
+                let Some(body) = note_element(&target.content, id) else {
+                    continue;
+                };
+                let note = match notes
+                    .iter()
+                    .position(|note| note.href == target_href && note.range == body.range)
+                {
+                    Some(note) => note,
+                    None => {
+                        let label = html_to_text(anchor.inner).trim().to_string();
+                        let text = note_text(body.inner, anchor.attribute("id"), &label);
+                        notes.push(Note {
+                            href: target_href,
+                            range: body.range,
+                            text,
+                        });
+                        notes.len() - 1
+                    }
+                };
+                refs.push(NoteRef {
+                    href: file.href.to_string(),
+                    range,
+                    note,
+                });
+            }
+        }
+        // References inside notes go away with the note
+        refs.retain(|note_ref| {
+            !notes.iter().any(|note| {
+                note.href == note_ref.href
+                    && note.range.start <= note_ref.range.start
+                    && note_ref.range.end <= note.range.end
+            })
+        });
+        (notes, refs)
+    }
+
+    /// Add a chapter listing every note, linked back to its first reference
+    fn append_notes_chapter(
+        &mut self,
+        notes_href: &str,
+        notes: &[Note],
+        refs: &[NoteRef],
+    ) -> Result<(), EpubError> {
+        let notes_path = Self::resolve_path(&self.opf_path, notes_href);
+        let mut items = String::new();
+        for (index, note) in notes.iter().enumerate() {
+            let number = index + 1;
+            let backlink = refs
+                .iter()
+                .find(|note_ref| note_ref.note == index)
+                .map(|note_ref| {
+                    let path = Self::resolve_path(&self.opf_path, &note_ref.href);
+                    format!(
+                        " <a href=\"{}#noteref-{}\" role=\"doc-backlink\">↩</a>",
+                        href::relative(&notes_path, &path),
+                        number
+                    )
+                })
+                .unwrap_or_default();
+            items.push_str(&format!(
+                "<li id=\"note-{}\"><p>{}{}</p></li>\n",
+                number,
+                escape_xml(&note.text),
+                backlink
+            ));
+        }
+        let body = format!(
+            "<section epub:type=\"endnotes\" role=\"doc-endnotes\">\n<h1>{}</h1>\n<ol>\n{}</ol>\n</section>",
+            NOTES_TITLE, items
+        );
+        let language = self.metadata.language.as_deref().unwrap_or("und");
+        let page = xhtml::document(NOTES_TITLE, language, "", Some("backmatter"), &body);
+
+        let id = self.add_resource(notes_href, "application/xhtml+xml", page.into_bytes())?;
+        self.spine.push(id.clone());
+        self.reindex_spine();
+        if let Some(file) = self.all_files.iter().find(|file| *file.id == *id) {
+            self.chapters.push(Chapter {
+                title: NOTES_TITLE.to_string(),
+                files: vec![file.clone()],
+                language: self.metadata.language.clone(),
+            });
+        }
+        self.table_of_contents
+            .add_entry(NOTES_TITLE.to_string(), notes_href, 0);
+        Ok(())
+    }
+}
+
+/// Note reference links in `html`: the byte range to replace (the link, or
+/// the `<sup>` around it) and the link itself
+fn find_noterefs(html: &str) -> Vec<(Range<usize>, Element<'_>)> {
+    let is_short_fragment_link = |anchor: &Element| {
+        anchor
+            .attribute("href")
+            .is_some_and(|reference| href::fragment(reference).is_some())
+            && html_to_text(anchor.inner).trim().chars().count() <= MAX_LABEL
+    };
+    find_elements(html, |e| e.name == "a" || e.name == "sup")
+        .into_iter()
+        .filter_map(|element| {
+            if element.name == "a" {
+                let semantic = element.has_token("epub:type", "noteref")
+                    || element.attribute("role") == Some("doc-noteref");
+                let superscript =
+                    element.inner.contains("<sup") && is_short_fragment_link(&element);
+                return (semantic || superscript).then(|| (element.range.clone(), element));
+            }
+            let range = element.range.clone();
+            let offset = html[range.clone()].find('>')? + 1 + range.start;
+            let mut anchors = find_elements(element.inner, |e| e.name == "a");
+            let mut anchor = (anchors.len() == 1).then(|| anchors.remove(0))?;
+            if !is_short_fragment_link(&anchor)
+                || html_to_text(element.inner).trim() != html_to_text(anchor.inner).trim()
+            {
+                return None;
+            }
+            anchor.range = anchor.range.start + offset..anchor.range.end + offset;
+            Some((range, anchor))
+        })
+        .collect()
+}
+
+/// The note the element with `id` stands for in `html`
+fn note_element<'a>(html: &'a str, id: &str) -> Option<Element<'a>> {
+    let start = xhtml::find_id(html, id)?;
+    if let Some(mut element) = find_elements(&html[start..], |_| true).into_iter().next()
+        && element.range.start == 0
+        && NOTE_BLOCKS.contains(&element.name.as_str())
+    {
+        element.range = start..start + element.range.end;
+        return Some(element);
+    }
+
+    // An anchor inside the note: take the innermost block around it
+    let mut open: Vec<(String, usize)> = Vec::new();
+    for cap in TAG.captures_iter(&html[..start]) {
+        let name = cap[2].to_ascii_lowercase();
+        let tag = cap.get(0).expect("whole match");
+        if !cap[1].is_empty() {
+            if let Some(position) = open.iter().rposition(|(open, _)| *open == name) {
+                open.truncate(position);
+            }
+        } else if !cap[3].trim_end().ends_with('/')
+            && !VOID_ELEMENTS.split('|').any(|void| void == name)
+        {
+            open.push((name, tag.start()));
+        }
+    }
+    let (_, block) = open
+        .iter()
+        .rev()
+        .find(|(name, _)| NOTE_BLOCKS.contains(&name.as_str()))?;
+    let mut element = find_elements(&html[*block..], |_| true)
+        .into_iter()
+        .next()?;
+    element.range = *block + element.range.start..*block + element.range.end;
+    Some(element)
+}
+
+/// Plain text of a note, without its label and links back to the reference
+fn note_text(html: &str, ref_id: Option<&str>, label: &str) -> String {
+    let mut kept = String::new();
+    let mut position = 0;
+    for anchor in find_elements(html, |e| e.name == "a") {
+        let text = html_to_text(anchor.inner);
+        let backlink = anchor.attribute("role") == Some("doc-backlink")
+            || anchor.has_token("epub:type", "backlink")
+            || ref_id.is_some_and(|id| {
+                anchor
+                    .attribute("href")
+                    .and_then(href::fragment)
+                    .is_some_and(|fragment| fragment == id)
+            })
+            || BACKLINK_TEXTS.contains(&text.trim().to_lowercase().as_str());
+        if backlink {
+            kept.push_str(&html[position..anchor.range.start]);
+            position = anchor.range.end;
+        }
+    }
+    kept.push_str(&html[position..]);
+
+    let text = html_to_text(&kept)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let text = match text.strip_prefix(label) {
+        Some(rest) if !label.is_empty() && !rest.starts_with(char::is_alphanumeric) => rest,
+        _ => &text,
+    };
+    text.trim_start_matches(['.', ')', ']', ':', ' '])
+        .to_string()
+}
+
+/// Whether a document has any text left that isn't a heading
+fn has_text_besides_headings(html: &str) -> bool {
+    let headings: Vec<String> = find_headings(html, 6)
+        .into_iter()
+        .map(|(_, text, _)| text)
+        .collect();
+    html_to_text(html)
+        .lines()
+        .map(str::trim)
+        .any(|line| !line.is_empty() && !headings.iter().any(|heading| heading == line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book() -> Epub {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="endnotes" href="endnotes.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/><itemref idref="endnotes"/>"#,
+        );
+        let nav = r#"<html xmlns:epub="http://www.idpf.org/2007/ops"><body><nav epub:type="toc"><ol>
+<li><a href="ch1.xhtml">One</a></li><li><a href="ch2.xhtml">Two</a></li><li><a href="endnotes.xhtml">Notes</a></li>
+</ol></nav></body></html>"#;
+        let ch1 = r##"<html xmlns:epub="http://www.idpf.org/2007/ops"><body><h1>One</h1>
+<p>Whales<a epub:type="noteref" href="#fn1" id="r1">1</a> swim.</p>
+<aside epub:type="footnote" id="fn1"><p><a href="#r1">1</a>. Large mammals.</p></aside>
+</body></html>"##;
+        let ch2 = r#"<html><body><h1>Two</h1><p>Ships<sup><a href="endnotes.xhtml#n1">1</a></sup> sail.</p></body></html>"#;
+        let endnotes = r#"<html><body><h1>Notes</h1><ol><li><a id="n1"></a>Mostly <i>whalers</i>. <a href="ch2.xhtml">↩</a></li></ol></body></html>"#;
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            ("ch1.xhtml", ch1),
+            ("ch2.xhtml", ch2),
+            ("endnotes.xhtml", endnotes),
+        ]))
+        .unwrap()
+    }
+
+    fn content(epub: &Epub, href: &str) -> String {
+        String::from_utf8(epub.get_resource_bytes(href).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn notes_are_put_inline() {
+        let flat = book().flatten_notes(NoteStyle::Inline).unwrap();
+        let ch1 = content(&flat, "ch1.xhtml");
+        assert!(ch1.contains(r#"Whales<span class="note">[Large mammals.]</span> swim."#));
+        assert!(!ch1.contains("<aside"));
+        assert!(
+            content(&flat, "ch2.xhtml")
+                .contains(r#"Ships<span class="note">[Mostly whalers.]</span> sail."#)
+        );
+        assert!(flat.get_resource_by_href("endnotes.xhtml").is_none());
+        assert_eq!(flat.get_spine(), ["ch1", "ch2"]);
+    }
+
+    #[test]
+    fn notes_are_gathered_into_a_chapter() {
+        let flat = book().flatten_notes(NoteStyle::NotesChapter).unwrap();
+        assert!(content(&flat, "ch1.xhtml").contains(
+            r##"Whales<sup><a id="noteref-1" href="notes.xhtml#note-1">1</a></sup> swim."##
+        ));
+        let notes = content(&flat, "notes.xhtml");
+        assert!(notes.contains(
+            r##"<li id="note-2"><p>Mostly whalers. <a href="ch2.xhtml#noteref-2" role="doc-backlink">↩</a></p></li>"##
+        ));
+        assert_eq!(flat.get_spine().last().map(String::as_str), Some("notes"));
+        let titles: Vec<&str> = flat
+            .get_table_of_contents()
+            .get_entries()
+            .iter()
+            .map(|entry| entry.get_title())
+            .collect();
+        assert_eq!(titles, ["One", "Two", "Notes"]);
+    }
+}
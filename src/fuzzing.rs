@@ -0,0 +1,93 @@
+//! Entry points for fuzz targets
+//!
+//! Enabled with the `fuzzing` feature. Each function drives one stage of the
+//! parser with arbitrary input and discards the result; the only thing a fuzz
+//! target cares about is that none of them panic. See `fuzz/` for the
+//! `cargo fuzz` targets built on top of these.
+
+use crate::epub::{self, Epub};
+
+/// Parse arbitrary text as `META-INF/container.xml`
+pub fn parse_container(xml: &str) {
+    let _ = epub::parse_container_xml(xml);
+}
+
+/// Parse arbitrary text as an OPF package document
+pub fn parse_opf(xml: &str) {
+    let _ = epub::parse_opf_xml(xml);
+}
+
+/// Parse arbitrary text as a navigation document
+pub fn parse_nav(html: &str) {
+    let _ = epub::extract_nav_titles(html);
+}
+
+/// Parse arbitrary bytes as a complete EPUB archive, then touch every
+/// lazily-read part of the result
+pub fn parse_archive(bytes: &[u8]) {
+    if let Ok(epub) = Epub::from_bytes(bytes) {
+        let _ = epub.get_cover_bytes();
+        let _ = epub.validate();
+        for file in epub.get_all_files() {
+            let _ = file.get_text();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book() -> Vec<u8> {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="cover" href="cover.png" media-type="image/png" properties="cover-image"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                "<html><body><h1>One</h1><p>Call me Ishmael.</p></body></html>",
+            ),
+            ("cover.png", "png"),
+        ])
+    }
+
+    #[test]
+    fn truncated_archives_do_not_panic() {
+        let book = book();
+        for len in 0..book.len() {
+            parse_archive(&book[..len]);
+        }
+    }
+
+    #[test]
+    fn garbage_and_corrupted_archives_do_not_panic() {
+        // A fixed xorshift sequence, so failures reproduce
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for len in [1, 22, 64, 512, 4096] {
+            let garbage: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            parse_archive(&garbage);
+        }
+
+        let book = book();
+        for _ in 0..500 {
+            let mut corrupted = book.clone();
+            for _ in 0..4 {
+                let at = next() as usize % corrupted.len();
+                corrupted[at] = next() as u8;
+            }
+            parse_archive(&corrupted);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Glossaries
+//!
+//! `Epub::get_glossary` finds glossary sections and splits them into terms
+//! and definitions. A glossary is anything with an `epub:type` or class of
+//! `glossary`, or a whole document whose file name says it is one. Inside
+//! it, terms and definitions come from `<dt>`/`<dd>` pairs or from elements
+//! typed `glossterm` and `glossdef`.
+
+use crate::epub::Epub;
+use crate::text::html_to_text;
+use crate::xhtml::{Element, find_elements};
+
+/// A term and what it means
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlossaryEntry {
+    term: String,
+    definition: String,
+    href: String,
+    id: Option<String>,
+}
+
+impl GlossaryEntry {
+    pub fn get_term(&self) -> &str {
+        &self.term
+    }
+
+    /// The definition as plain text; several definitions of one term are
+    /// separated by newlines
+    pub fn get_definition(&self) -> &str {
+        &self.definition
+    }
+
+    /// Href of the file holding the glossary
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// The term element's `id`, which links into the glossary point at
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+}
+
+impl Epub {
+    /// Every glossary entry in the book, in reading order
+    ///
+    /// Terms listed together before a shared definition each get an entry.
+    pub fn get_glossary(&self) -> Vec<GlossaryEntry> {
+        let mut entries = Vec::new();
+        for file in self.get_spine_files() {
+            let content = file.get_content();
+            let name = file.get_href().rsplit('/').next().unwrap_or("");
+            let regions: Vec<&str> = if name.to_ascii_lowercase().contains("glossary") {
+                vec![content]
+            } else {
+                find_elements(content, |e| {
+                    e.has_token("epub:type", "glossary") || e.has_token("class", "glossary")
+                })
+                .into_iter()
+                .map(|region| region.inner)
+                .collect()
+            };
+            for region in regions {
+                entries.extend(parse_entries(region, file.get_href()));
+            }
+        }
+        entries
+    }
+}
+
+/// Pair up terms and definitions in document order
+fn parse_entries(html: &str, href: &str) -> Vec<GlossaryEntry> {
+    let is_term = |e: &Element| e.name == "dt" || e.has_token("epub:type", "glossterm");
+    let parts = find_elements(html, |e| {
+        is_term(e) || e.name == "dd" || e.has_token("epub:type", "glossdef")
+    });
+
+    let mut entries = Vec::new();
+    let mut terms: Vec<(String, Option<String>)> = Vec::new();
+    let mut definitions: Vec<String> = Vec::new();
+    let mut flush = |terms: &mut Vec<(String, Option<String>)>, definitions: &mut Vec<String>| {
+        if !definitions.is_empty() {
+            let definition = definitions.join("\n");
+            for (term, id) in terms.drain(..) {
+                entries.push(GlossaryEntry {
+                    term,
+                    definition: definition.clone(),
+                    href: href.to_string(),
+                    id,
+                });
+            }
+        }
+        definitions.clear();
+    };
+
+    for part in parts {
+        let text = html_to_text(part.inner).replace('\n', " ");
+        if is_term(&part) {
+            // A term after definitions starts the next entry
+            flush(&mut terms, &mut definitions);
+            // `<dt><dfn id="...">` puts the id on the inner element
+            let id = part.attribute("id").map(str::to_string).or_else(|| {
+                find_elements(part.inner, |e| e.attribute("id").is_some())
+                    .first()
+                    .and_then(|e| e.attribute("id"))
+                    .map(str::to_string)
+            });
+            if !text.is_empty() {
+                terms.push((text, id));
+            }
+        } else if !terms.is_empty() && !text.is_empty() {
+            definitions.push(text);
+        }
+    }
+    flush(&mut terms, &mut definitions);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn glossary_terms_pair_with_definitions() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="gl" href="glossary.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="gl"/>"#,
+        );
+        let chapter = r#"<html><body>
+<dl><dt>Not</dt><dd>a glossary</dd></dl>
+<aside epub:type="glossary"><p epub:type="glossterm" id="g-id">Id</p><p epub:type="glossdef">The instinctive self.</p></aside>
+</body></html>"#;
+        let glossary = r#"<html><body><h1>Glossary</h1><dl>
+<dt><dfn id="g-ego">Ego</dfn></dt><dt>Self</dt><dd>The <em>conscious</em> mind.</dd>
+<dt>Superego</dt><dd>The conscience.</dd><dd>An internalized parent.</dd>
+</dl></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+            ("glossary.xhtml", glossary),
+        ]))
+        .unwrap();
+
+        let entries = epub.get_glossary();
+        let pairs: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|entry| (entry.get_term(), entry.get_definition()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("Id", "The instinctive self."),
+                ("Ego", "The conscious mind."),
+                ("Self", "The conscious mind."),
+                ("Superego", "The conscience.\nAn internalized parent."),
+            ]
+        );
+        assert_eq!(entries[0].get_id(), Some("g-id"));
+        assert_eq!(entries[1].get_id(), Some("g-ego"));
+        assert_eq!(entries[1].get_href(), "glossary.xhtml");
+    }
+}
@@ -0,0 +1,89 @@
+//! A cheaply cloneable, shareable book
+//!
+//! Cloning an `Epub` copies every parsed document. GUI apps that hand the
+//! same book to a render thread, a search thread and a text-to-speech
+//! thread want a handle instead: `EpubHandle` wraps the parsed book in an
+//! `Arc`, clones in constant time, and derefs to `Epub` for every reading
+//! method. Editing needs an `Epub` of its own; `EpubHandle::to_epub` makes
+//! one.
+
+use crate::epub::Epub;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// A shared, read-only `Epub`
+#[derive(Clone)]
+pub struct EpubHandle {
+    epub: Arc<Epub>,
+}
+
+impl EpubHandle {
+    pub fn new(epub: Epub) -> Self {
+        Self {
+            epub: Arc::new(epub),
+        }
+    }
+
+    /// An editable copy of the book, without copying when this is the only handle
+    pub fn to_epub(self) -> Epub {
+        Arc::try_unwrap(self.epub).unwrap_or_else(|shared| (*shared).clone())
+    }
+
+    /// Whether two handles share the same parsed book
+    pub fn ptr_eq(&self, other: &EpubHandle) -> bool {
+        Arc::ptr_eq(&self.epub, &other.epub)
+    }
+}
+
+impl Deref for EpubHandle {
+    type Target = Epub;
+
+    fn deref(&self) -> &Epub {
+        &self.epub
+    }
+}
+
+impl From<Epub> for EpubHandle {
+    fn from(epub: Epub) -> Self {
+        Self::new(epub)
+    }
+}
+
+impl Epub {
+    /// Wrap the book in a cheaply cloneable `EpubHandle`
+    pub fn into_handle(self) -> EpubHandle {
+        EpubHandle::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn handles_share_one_book_across_threads() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let handle = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One fish</p></body></html>"),
+        ]))
+        .unwrap()
+        .into_handle();
+
+        let searcher = handle.clone();
+        assert!(searcher.ptr_eq(&handle));
+        let found = std::thread::spawn(move || searcher.get_all_files()[0].get_text())
+            .join()
+            .unwrap();
+        assert!(found.contains("One fish"));
+
+        let mut epub = handle.to_epub();
+        epub.edit_metadata().set_title("Edited").unwrap();
+        assert_eq!(epub.get_title(), Some("Edited"));
+    }
+}
@@ -0,0 +1,252 @@
+//! Highlights and notes, and exporting them
+//!
+//! A `Highlight` is a passage the reader marked: where it is, the quoted
+//! text and an optional note. `Epub::highlights_to_markdown`,
+//! `highlights_to_json` and `highlights_to_csv` export a set of them in
+//! reading order, each with its chapter and location number (see
+//! `Epub::locations`) so the notes still point somewhere useful outside
+//! the reader.
+
+use crate::epub::Epub;
+use crate::locator::Locator;
+use crate::tables::csv_field;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// A marked passage and the reader's note on it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Highlight {
+    locator: Locator,
+    text: String,
+    note: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl Highlight {
+    /// Highlight `text`, found at `locator`, as of now
+    pub fn new(locator: Locator, text: &str) -> Self {
+        Self {
+            locator,
+            text: text.to_string(),
+            note: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.note = Some(note.to_string());
+        self
+    }
+
+    pub fn with_created_at(mut self, created_at: DateTime<Utc>) -> Self {
+        self.created_at = created_at;
+        self
+    }
+
+    pub fn get_locator(&self) -> &Locator {
+        &self.locator
+    }
+
+    /// The highlighted passage
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn get_note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    pub fn get_created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+/// A highlight with where it sits in the book, as exported
+#[derive(Serialize)]
+struct ExportedHighlight<'a> {
+    chapter_index: Option<usize>,
+    chapter_title: Option<&'a str>,
+    location: Option<usize>,
+    href: &'a str,
+    fragment: Option<&'a str>,
+    text: &'a str,
+    note: Option<&'a str>,
+    created_at: DateTime<Utc>,
+}
+
+impl Epub {
+    /// Export highlights as Markdown notes, grouped by chapter
+    ///
+    /// Each highlight becomes a block quote followed by its note and a
+    /// reference to its location, or to its deep link when the passage
+    /// can't be found in the text. Highlights outside the book's chapters
+    /// come last, under "Other".
+    pub fn highlights_to_markdown(&self, highlights: &[Highlight]) -> String {
+        let mut markdown = match self.get_title() {
+            Some(title) => format!("# Highlights from {}\n", title),
+            None => "# Highlights\n".to_string(),
+        };
+        let mut chapter = None;
+        for (index, highlight) in self.export_highlights(highlights).iter().enumerate() {
+            if index == 0 || highlight.chapter_index != chapter {
+                chapter = highlight.chapter_index;
+                markdown.push_str(&format!(
+                    "\n## {}\n",
+                    highlight.chapter_title.unwrap_or("Other")
+                ));
+            }
+            markdown.push('\n');
+            for line in highlight.text.lines() {
+                if line.trim().is_empty() {
+                    markdown.push_str(">\n");
+                } else {
+                    markdown.push_str(&format!("> {}\n", line));
+                }
+            }
+            if let Some(note) = highlight.note {
+                markdown.push_str(&format!("\n{}\n", note));
+            }
+            let reference = match highlight.location {
+                Some(location) => format!("Location {}", location),
+                None => match highlight.fragment {
+                    Some(fragment) => format!("{}#{}", highlight.href, fragment),
+                    None => highlight.href.to_string(),
+                },
+            };
+            markdown.push_str(&format!("\n*{}*\n", reference));
+        }
+        markdown
+    }
+
+    /// Export highlights as a JSON array, in reading order
+    pub fn highlights_to_json(&self, highlights: &[Highlight]) -> String {
+        serde_json::to_string_pretty(&self.export_highlights(highlights))
+            .expect("highlights always serialize")
+    }
+
+    /// Export highlights as CSV with a header row, in reading order
+    pub fn highlights_to_csv(&self, highlights: &[Highlight]) -> String {
+        let mut csv = String::from("chapter,location,href,text,note,created_at\n");
+        for highlight in self.export_highlights(highlights) {
+            let href = match highlight.fragment {
+                Some(fragment) => format!("{}#{}", highlight.href, fragment),
+                None => highlight.href.to_string(),
+            };
+            let fields = [
+                csv_field(highlight.chapter_title.unwrap_or("")),
+                highlight
+                    .location
+                    .map(|l| l.to_string())
+                    .unwrap_or_default(),
+                csv_field(&href),
+                csv_field(highlight.text),
+                csv_field(highlight.note.unwrap_or("")),
+                highlight.created_at.to_rfc3339(),
+            ];
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Place each highlight in the book and sort them into reading order
+    fn export_highlights<'a>(&'a self, highlights: &'a [Highlight]) -> Vec<ExportedHighlight<'a>> {
+        let locations = self.locations();
+        let mut exported: Vec<ExportedHighlight> = highlights
+            .iter()
+            .map(|highlight| {
+                let href = highlight.locator.get_href();
+                let chapter = self
+                    .get_chapters()
+                    .iter()
+                    .enumerate()
+                    .find(|(_, c)| c.get_files().iter().any(|f| f.get_href() == href));
+                let location = self
+                    .get_all_files()
+                    .iter()
+                    .find(|f| f.get_href() == href)
+                    .and_then(|file| {
+                        let text = file.get_text();
+                        let byte = text.find(highlight.text.trim())?;
+                        locations.location_at(href, text[..byte].chars().count())
+                    });
+                ExportedHighlight {
+                    chapter_index: chapter.map(|(index, _)| index),
+                    chapter_title: chapter.map(|(_, c)| c.get_title()),
+                    location,
+                    href,
+                    fragment: highlight.locator.get_fragment(),
+                    text: &highlight.text,
+                    note: highlight.note.as_deref(),
+                    created_at: highlight.created_at,
+                }
+            })
+            .collect();
+        exported.sort_by_key(|h| (h.chapter_index.is_none(), h.chapter_index, h.location));
+        exported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+    use chrono::TimeZone;
+
+    #[test]
+    fn highlights_export_in_reading_order() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let nav = r#"<html><body><nav epub:type="toc"><ol><li><a href="ch1.xhtml">One</a></li><li><a href="ch2.xhtml">Two</a></li></ol></nav></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            (
+                "ch1.xhtml",
+                "<html><body><p>Call me Ishmael.</p></body></html>",
+            ),
+            (
+                "ch2.xhtml",
+                r#"<html><body><p id="p1">It was "the" whale.</p></body></html>"#,
+            ),
+        ]))
+        .unwrap();
+
+        let at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let highlights = [
+            Highlight::new(
+                Locator::new("ch2.xhtml").with_fragment("p1"),
+                "\"the\" whale",
+            )
+            .with_note("Which one?")
+            .with_created_at(at),
+            Highlight::new(Locator::new("ch1.xhtml"), "Ishmael").with_created_at(at),
+        ];
+
+        let markdown = epub.highlights_to_markdown(&highlights);
+        let one = markdown
+            .find("## One\n\n> Ishmael\n\n*Location 1*")
+            .unwrap();
+        let two = markdown
+            .find("## Two\n\n> \"the\" whale\n\nWhich one?\n")
+            .unwrap();
+        assert!(one < two);
+
+        assert_eq!(
+            epub.highlights_to_csv(&highlights),
+            "chapter,location,href,text,note,created_at\n\
+             One,1,ch1.xhtml,Ishmael,,2024-05-01T12:00:00+00:00\n\
+             Two,1,ch2.xhtml#p1,\"\"\"the\"\" whale\",Which one?,2024-05-01T12:00:00+00:00\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&epub.highlights_to_json(&highlights)).unwrap();
+        assert_eq!(json[1]["chapter_title"], "Two");
+        assert_eq!(json[1]["note"], "Which one?");
+    }
+}
@@ -0,0 +1,221 @@
+//! Resolving hrefs to zip entry names
+//!
+//! Hrefs in the OPF and navigation documents are URL references (RFC 3986),
+//! relative to the document they appear in. Zip entry names are plain paths,
+//! so resolving means merging with the base document's directory, removing
+//! `.` and `..` segments, dropping any query or fragment and percent-decoding
+//! what is left.
+
+/// Resolve `reference` against the archive path of the document containing it
+///
+/// References with a scheme (`http:`, `mailto:`, ...) are returned unchanged
+/// since they don't point into the archive.
+pub(crate) fn resolve(base: &str, reference: &str) -> String {
+    let reference = strip_fragment(reference);
+    if has_scheme(reference) {
+        return reference.to_string();
+    }
+
+    let merged = if let Some(absolute) = reference.strip_prefix('/') {
+        absolute.to_string()
+    } else {
+        match base.rfind('/') {
+            Some(slash_pos) => format!("{}/{}", &base[..slash_pos], reference),
+            None => reference.to_string(),
+        }
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    let mut parts = merged.split('/').peekable();
+    while let Some(segment) = parts.next() {
+        let is_last = parts.peek().is_none();
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            "" if !is_last => {}
+            _ => segments.push(segment),
+        }
+    }
+
+    segments
+        .iter()
+        .map(|segment| percent_decode(segment))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Express the archive path `target` relative to the directory of `base`
+///
+/// The inverse of `resolve` for paths inside the archive, used to turn
+/// resolved paths back into hrefs as they'd appear in the OPF.
+pub(crate) fn relative(base: &str, target: &str) -> String {
+    let base_dir: Vec<&str> = match base.rfind('/') {
+        Some(slash_pos) => base[..slash_pos].split('/').collect(),
+        None => Vec::new(),
+    };
+    let target: Vec<&str> = target.split('/').collect();
+
+    let common = base_dir
+        .iter()
+        .zip(&target)
+        .take_while(|(a, b)| a == b)
+        .count()
+        .min(target.len().saturating_sub(1));
+
+    let mut parts = vec![".."; base_dir.len() - common];
+    parts.extend(&target[common..]);
+    parts.join("/")
+}
+
+/// The fragment of a reference, without the leading `#`
+pub(crate) fn fragment(reference: &str) -> Option<&str> {
+    reference
+        .split_once('#')
+        .map(|(_, fragment)| fragment)
+        .filter(|fragment| !fragment.is_empty())
+}
+
+/// The part of a reference before any `?query` or `#fragment`
+pub(crate) fn strip_fragment(reference: &str) -> &str {
+    match reference.find(['#', '?']) {
+        Some(pos) => &reference[..pos],
+        None => reference,
+    }
+}
+
+/// Decode `%XX` escapes, leaving the input untouched if the result isn't UTF-8
+pub(crate) fn percent_decode(input: &str) -> String {
+    if !input.contains('%') {
+        return input.to_string();
+    }
+
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).unwrap_or_else(|_| input.to_string())
+}
+
+/// Whether a reference is an absolute URL (`http:`, `mailto:`, ...) rather than a path
+pub(crate) fn has_scheme(reference: &str) -> bool {
+    match reference.find(':') {
+        Some(colon) => {
+            let scheme = &reference[..colon];
+            scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Media type for a file, guessed from its extension
+pub(crate) fn media_type_for(path: &str) -> &'static str {
+    let extension = path.rsplit('.').next().unwrap_or("").to_ascii_lowercase();
+    match extension.as_str() {
+        "xhtml" | "html" | "htm" => "application/xhtml+xml",
+        "css" => "text/css",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "mp4" | "m4a" => "audio/mp4",
+        "otf" => "font/otf",
+        "ttf" => "font/ttf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "js" => "application/javascript",
+        "ncx" => "application/x-dtbncx+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+/// File extension conventionally used for a media type
+pub(crate) fn extension_for(media_type: &str) -> &str {
+    match media_type.rsplit('/').next().unwrap_or("") {
+        "jpeg" => "jpg",
+        "svg+xml" => "svg",
+        "xhtml+xml" => "xhtml",
+        "" => "bin",
+        other => other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_relative_references() {
+        assert_eq!(
+            resolve("OEBPS/content.opf", "Text/ch1.xhtml"),
+            "OEBPS/Text/ch1.xhtml"
+        );
+        assert_eq!(resolve("content.opf", "ch1.xhtml"), "ch1.xhtml");
+        assert_eq!(
+            resolve("OEBPS/Text/nav.xhtml", "../Images/a.png"),
+            "OEBPS/Images/a.png"
+        );
+        assert_eq!(
+            resolve("OEBPS/Text/nav.xhtml", "./ch1.xhtml#sec2"),
+            "OEBPS/Text/ch1.xhtml"
+        );
+        assert_eq!(resolve("OEBPS/content.opf", "../../x.css"), "x.css");
+        assert_eq!(resolve("OEBPS/content.opf", "/cover.jpg"), "cover.jpg");
+        assert_eq!(
+            resolve("OEBPS/content.opf", "My%20Chapter.xhtml"),
+            "OEBPS/My Chapter.xhtml"
+        );
+        assert_eq!(
+            resolve("OEBPS/content.opf", "https://example.com/a"),
+            "https://example.com/a"
+        );
+    }
+
+    #[test]
+    fn relative_inverts_resolve() {
+        assert_eq!(
+            relative("OEBPS/content.opf", "OEBPS/Text/ch1.xhtml"),
+            "Text/ch1.xhtml"
+        );
+        assert_eq!(
+            relative("OEBPS/content.opf", "cover.xhtml"),
+            "../cover.xhtml"
+        );
+        assert_eq!(relative("content.opf", "Text/ch1.xhtml"), "Text/ch1.xhtml");
+        assert_eq!(
+            relative("OEBPS/Text/nav.xhtml", "OEBPS/Images/a.png"),
+            "../Images/a.png"
+        );
+        assert_eq!(fragment("ch1.xhtml#start"), Some("start"));
+        assert_eq!(fragment("ch1.xhtml#"), None);
+    }
+
+    #[test]
+    fn percent_decode_is_lenient() {
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+        assert_eq!(percent_decode("%FF"), "%FF");
+    }
+}
@@ -0,0 +1,375 @@
+//! Recompressing images to shrink a book
+//!
+//! Published EPUBs often ship print-resolution scans and PNG photographs.
+//! `Epub::optimize_images` downsizes JPEG and PNG resources that exceed the
+//! configured dimensions, re-encodes them, and optionally converts them to
+//! WebP (or AVIF, with the `avif` feature). Images that are neither too
+//! large nor being converted are left untouched, and a result is only kept
+//! when it is smaller than the original. Requires the `image` feature.
+//!
+//! The encoders don't carry EXIF over, so an image's EXIF orientation is
+//! applied to its pixels before re-encoding; otherwise rotated photos would
+//! come out sideways.
+
+use crate::epub::Epub;
+use crate::error::EpubError;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader, imageops};
+use std::io::Cursor;
+
+/// Output format for recompressed images
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    /// Lossless WebP
+    WebP,
+    #[cfg(feature = "avif")]
+    Avif,
+}
+
+impl ImageFormat {
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::WebP => "image/webp",
+            #[cfg(feature = "avif")]
+            ImageFormat::Avif => "image/avif",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::WebP => "webp",
+            #[cfg(feature = "avif")]
+            ImageFormat::Avif => "avif",
+        }
+    }
+
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "image/jpeg" => Some(ImageFormat::Jpeg),
+            "image/png" => Some(ImageFormat::Png),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for `Epub::optimize_images`
+#[derive(Debug, Clone)]
+pub struct ImageOptions {
+    max_width: u32,
+    max_height: u32,
+    quality: u8,
+    convert_to: Option<ImageFormat>,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            max_width: 1600,
+            max_height: 2560,
+            quality: 80,
+            convert_to: None,
+        }
+    }
+}
+
+impl ImageOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scale down (keeping the aspect ratio) images larger than this
+    pub fn with_max_dimensions(mut self, width: u32, height: u32) -> Self {
+        self.max_width = width.max(1);
+        self.max_height = height.max(1);
+        self
+    }
+
+    /// Encoder quality from 1 to 100, used for JPEG and AVIF
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Re-encode every image in `format` instead of its original one
+    ///
+    /// Images with transparency are never converted to JPEG.
+    pub fn with_conversion(mut self, format: ImageFormat) -> Self {
+        self.convert_to = Some(format);
+        self
+    }
+
+    pub fn get_max_dimensions(&self) -> (u32, u32) {
+        (self.max_width, self.max_height)
+    }
+
+    pub fn get_quality(&self) -> u8 {
+        self.quality
+    }
+
+    pub fn get_conversion(&self) -> Option<ImageFormat> {
+        self.convert_to
+    }
+}
+
+/// An image that `optimize_images` replaced
+#[derive(Debug, Clone)]
+pub struct OptimizedImage {
+    href: String,
+    original_size: usize,
+    optimized_size: usize,
+}
+
+impl OptimizedImage {
+    /// Href of the image after optimization; differs from the original when
+    /// the format changed
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_original_size(&self) -> usize {
+        self.original_size
+    }
+
+    pub fn get_optimized_size(&self) -> usize {
+        self.optimized_size
+    }
+}
+
+impl Epub {
+    /// Downsize and recompress the book's JPEG and PNG images
+    ///
+    /// Images that fail to decode are left alone. When an image changes
+    /// format, its manifest media type and href are updated and references
+    /// to it are rewritten, as with `rename_resource`.
+    pub fn optimize_images(
+        &mut self,
+        options: &ImageOptions,
+    ) -> Result<Vec<OptimizedImage>, EpubError> {
+        enter_span!("optimize_images");
+        let candidates: Vec<(String, ImageFormat)> = self
+            .resources
+            .iter()
+            .filter_map(|resource| {
+                ImageFormat::from_media_type(&resource.media_type)
+                    .map(|format| (resource.href.clone(), format))
+            })
+            .collect();
+
+        let mut optimized = Vec::new();
+        for (href, format) in candidates {
+            let Some(original) = self.get_resource_bytes(&href) else {
+                continue;
+            };
+            let Some((bytes, target)) = recompress(&original, format, options) else {
+                continue;
+            };
+            if bytes.len() >= original.len() {
+                continue;
+            }
+            trace_debug!(href = %href, before = original.len(), after = bytes.len(), "recompressed image");
+
+            let optimized_size = bytes.len();
+            self.put_resource_bytes(&href, bytes);
+            let mut new_href = href.clone();
+            if target != format {
+                if let Some(resource) = self.resources.iter_mut().find(|r| r.href == href) {
+                    resource.media_type = target.media_type().to_string();
+                }
+                let stem = href
+                    .rsplit_once('.')
+                    .map_or(href.as_str(), |(stem, _)| stem);
+                new_href = self.unique_resource_href(stem, target.extension());
+                self.rename_resource(&href, &new_href)?;
+            }
+            optimized.push(OptimizedImage {
+                href: new_href,
+                original_size: original.len(),
+                optimized_size,
+            });
+        }
+        Ok(optimized)
+    }
+}
+
+/// Decode, downsize and re-encode an image
+///
+/// `None` if any step fails, or if the image needs neither a resize nor a
+/// format change, since re-encoding it would only lose quality.
+fn recompress(
+    bytes: &[u8],
+    format: ImageFormat,
+    options: &ImageOptions,
+) -> Option<(Vec<u8>, ImageFormat)> {
+    let mut decoder = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let (width, height) = match orientation {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => {
+            let (width, height) = decoder.dimensions();
+            (height, width)
+        }
+        _ => decoder.dimensions(),
+    };
+    let oversized = width > options.max_width || height > options.max_height;
+    let target = match options.convert_to {
+        Some(ImageFormat::Jpeg) if decoder.color_type().has_alpha() => format,
+        Some(target) => target,
+        None => format,
+    };
+    if !oversized && target == format {
+        return None;
+    }
+
+    let mut image = DynamicImage::from_decoder(decoder).ok()?;
+    image.apply_orientation(orientation);
+    if oversized {
+        image = image.resize(
+            options.max_width,
+            options.max_height,
+            imageops::FilterType::Lanczos3,
+        );
+    }
+    encode(&image, target, options.quality).map(|bytes| (bytes, target))
+}
+
+pub(crate) fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let result = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality)),
+        ImageFormat::Png => image.write_with_encoder(PngEncoder::new_with_quality(
+            &mut out,
+            CompressionType::Best,
+            FilterType::Adaptive,
+        )),
+        ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_with_encoder(WebPEncoder::new_lossless(&mut out)),
+        #[cfg(feature = "avif")]
+        ImageFormat::Avif => DynamicImage::ImageRgba8(image.to_rgba8()).write_with_encoder(
+            image::codecs::avif::AvifEncoder::new_with_speed_quality(&mut out, 8, quality),
+        ),
+    };
+    result.ok().map(|_| out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, package};
+    use image::{ImageEncoder, ImageFormat as Codec, RgbImage};
+    use std::io::Write;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    /// A one-chapter book holding `image` as `photo.<extension>`
+    fn book_with(image: &[u8], media_type: &str, extension: &str) -> Epub {
+        let href = format!("photo.{}", extension);
+        let opf = package(
+            &format!(
+                r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="photo" href="{}" media-type="{}"/>"#,
+                href, media_type
+            ),
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = format!(r#"<html><body><img src="{}"/></body></html>"#, href);
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in [
+            ("META-INF/container.xml", CONTAINER.as_bytes()),
+            ("content.opf", opf.as_bytes()),
+            ("ch1.xhtml", chapter.as_bytes()),
+            (href.as_str(), image),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        Epub::from_bytes(zip.finish().unwrap().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn oversized_images_are_scaled_and_converted() {
+        let mut png = Vec::new();
+        // Noise, so the PNG is photograph-sized rather than trivially compressible
+        let photo = RgbImage::from_fn(1200, 300, |x, y| {
+            let n = (x * 7919 + y * 104_729).wrapping_mul(2_654_435_761);
+            image::Rgb([(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8])
+        });
+        photo
+            .write_to(&mut Cursor::new(&mut png), Codec::Png)
+            .unwrap();
+
+        let mut epub = book_with(&png, "image/png", "png");
+
+        let options = ImageOptions::new()
+            .with_max_dimensions(400, 400)
+            .with_conversion(ImageFormat::Jpeg);
+        let optimized = epub.optimize_images(&options).unwrap();
+        assert_eq!(optimized.len(), 1);
+        assert_eq!(optimized[0].get_href(), "photo.jpg");
+        assert!(optimized[0].get_optimized_size() < optimized[0].get_original_size());
+
+        let resource = epub.get_resource_by_id("photo").unwrap();
+        assert_eq!(resource.get_media_type(), "image/jpeg");
+        let scaled =
+            image::load_from_memory(&epub.get_resource_bytes("photo.jpg").unwrap()).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (400, 100));
+        assert!(
+            epub.get_all_files()[0]
+                .get_content()
+                .contains(r#"src="photo.jpg""#)
+        );
+    }
+
+    #[test]
+    fn small_images_keeping_their_format_are_untouched() {
+        let mut png = Vec::new();
+        RgbImage::from_pixel(40, 10, image::Rgb([200, 10, 10]))
+            .write_to(&mut Cursor::new(&mut png), Codec::Png)
+            .unwrap();
+        let mut epub = book_with(&png, "image/png", "png");
+
+        let optimized = epub.optimize_images(&ImageOptions::new()).unwrap();
+        assert!(optimized.is_empty());
+        assert_eq!(epub.get_resource_bytes("photo.png").unwrap(), png);
+    }
+
+    #[test]
+    fn exif_orientation_is_applied_before_reencoding() {
+        // A little-endian TIFF header with one IFD entry: Orientation = 6,
+        // "rotate 90 degrees clockwise to display"
+        let exif = [
+            0x49, 0x49, 42, 0, 8, 0, 0, 0, 1, 0, 0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0,
+            0,
+        ];
+        let photo = RgbImage::from_pixel(40, 10, image::Rgb([200, 10, 10]));
+        let mut jpeg = Vec::new();
+        let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, 90);
+        encoder.set_exif_metadata(exif.to_vec()).unwrap();
+        encoder
+            .write_image(photo.as_raw(), 40, 10, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        let mut epub = book_with(&jpeg, "image/jpeg", "jpg");
+
+        let options = ImageOptions::new().with_conversion(ImageFormat::Png);
+        let optimized = epub.optimize_images(&options).unwrap();
+        assert_eq!(optimized[0].get_href(), "photo.png");
+        let rotated =
+            image::load_from_memory(&epub.get_resource_bytes("photo.png").unwrap()).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (10, 40));
+    }
+}
@@ -0,0 +1,238 @@
+//! Content that needs more than a page renderer
+//!
+//! Many reading systems render XHTML but don't run scripts, and some of the
+//! ones that do only run them in fixed-layout books. `Epub::interactive_report`
+//! lists, per content document, the constructs that depend on that support:
+//! scripts, forms, `<canvas>`, and the EPUB-specific `epub:switch` and
+//! `epub:trigger`. For each it notes whether a static fallback exists (an
+//! `epub:default` branch, or fallback content inside the canvas), so a
+//! reading system can tell pages that degrade gracefully from pages that
+//! break, and whether the manifest declares the document `scripted`.
+
+use crate::epub::Epub;
+use crate::text::html_to_text;
+use crate::xhtml::{Element, find_elements};
+
+/// What an `InteractiveConstruct` is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InteractiveKind {
+    /// `<script>`
+    Script,
+    /// `<form>`
+    Form,
+    /// `<canvas>`
+    Canvas,
+    /// `epub:switch`, content shown only to reading systems that support a namespace
+    Switch,
+    /// `epub:trigger`, declarative media controls
+    Trigger,
+}
+
+impl InteractiveKind {
+    /// Whether the construct does nothing without a scripting-capable reading system
+    pub fn needs_scripting(self) -> bool {
+        matches!(self, Self::Script | Self::Form | Self::Canvas)
+    }
+}
+
+/// One interactive element in a content document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InteractiveConstruct {
+    kind: InteractiveKind,
+    id: Option<String>,
+    fallback: bool,
+}
+
+impl InteractiveConstruct {
+    pub fn get_kind(&self) -> InteractiveKind {
+        self.kind
+    }
+
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Whether something static is shown when the construct isn't supported
+    pub fn has_fallback(&self) -> bool {
+        self.fallback
+    }
+}
+
+/// The interactive constructs of one content document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InteractiveFile {
+    href: String,
+    declared_scripted: bool,
+    constructs: Vec<InteractiveConstruct>,
+}
+
+impl InteractiveFile {
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Whether the manifest item has the `scripted` property
+    pub fn is_declared_scripted(&self) -> bool {
+        self.declared_scripted
+    }
+
+    /// The constructs in document order
+    pub fn get_constructs(&self) -> &[InteractiveConstruct] {
+        &self.constructs
+    }
+
+    /// Whether any construct needs a scripting-capable reading system
+    pub fn requires_scripting(&self) -> bool {
+        self.constructs
+            .iter()
+            .any(|construct| construct.kind.needs_scripting())
+    }
+
+    /// Whether every construct has a static fallback
+    pub fn has_static_fallback(&self) -> bool {
+        self.constructs.iter().all(|construct| construct.fallback)
+    }
+}
+
+/// Interactive content of a book, from `Epub::interactive_report`
+#[derive(Debug, Clone, Default)]
+pub struct InteractiveReport {
+    files: Vec<InteractiveFile>,
+}
+
+impl InteractiveReport {
+    /// Documents with at least one interactive construct
+    pub fn get_files(&self) -> &[InteractiveFile] {
+        &self.files
+    }
+
+    /// True when the book has no interactive content
+    pub fn is_static(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Documents that need scripting but aren't declared `scripted`
+    pub fn undeclared_scripted(&self) -> Vec<&InteractiveFile> {
+        self.files
+            .iter()
+            .filter(|file| file.requires_scripting() && !file.declared_scripted)
+            .collect()
+    }
+}
+
+impl Epub {
+    /// Scripts, forms, canvases, `epub:switch` and `epub:trigger`, per content document
+    pub fn interactive_report(&self) -> InteractiveReport {
+        enter_span!("interactive_report");
+        let files = self
+            .all_files
+            .iter()
+            .filter_map(|file| {
+                let constructs = find_constructs(&file.content);
+                if constructs.is_empty() {
+                    return None;
+                }
+                let declared_scripted = self
+                    .get_resource_by_href(&file.href)
+                    .is_some_and(|resource| resource.has_property("scripted"));
+                Some(InteractiveFile {
+                    href: file.href.to_string(),
+                    declared_scripted,
+                    constructs,
+                })
+            })
+            .collect();
+        InteractiveReport { files }
+    }
+}
+
+fn find_constructs(content: &str) -> Vec<InteractiveConstruct> {
+    let kind_of = |element: &Element| match element.name.as_str() {
+        "script" => Some(InteractiveKind::Script),
+        "form" => Some(InteractiveKind::Form),
+        "canvas" => Some(InteractiveKind::Canvas),
+        "epub:switch" => Some(InteractiveKind::Switch),
+        "epub:trigger" => Some(InteractiveKind::Trigger),
+        _ => None,
+    };
+    find_elements(content, |element| kind_of(element).is_some())
+        .into_iter()
+        .filter_map(|element| {
+            let kind = kind_of(&element)?;
+            let fallback = match kind {
+                InteractiveKind::Switch => {
+                    !find_elements(element.inner, |e| e.name == "epub:default").is_empty()
+                }
+                InteractiveKind::Canvas => {
+                    !html_to_text(element.inner).trim().is_empty()
+                        || !find_elements(element.inner, |e| e.name == "img").is_empty()
+                }
+                _ => false,
+            };
+            Some(InteractiveConstruct {
+                kind,
+                id: element.attribute("id").map(str::to_string),
+                fallback,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn constructs_are_reported_per_file() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml" properties="scripted"/>
+    <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ch3" href="ch3.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/><itemref idref="ch3"/>"#,
+        );
+        let ch1 = r#"<html><head><script src="quiz.js"></script></head><body>
+<form id="quiz"><input type="text"/></form>
+<canvas id="chart"><img src="chart.png" alt="Sales by year"/></canvas>
+</body></html>"#;
+        let ch2 = r#"<html xmlns:epub="http://www.idpf.org/2007/ops"><body>
+<epub:switch id="formula"><epub:case required-namespace="http://www.w3.org/1998/Math/MathML"><math/></epub:case>
+<epub:default><img src="formula.png"/></epub:default></epub:switch>
+<canvas id="empty"></canvas>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", ch1),
+            ("ch2.xhtml", ch2),
+            ("ch3.xhtml", "<html><body><p>Plain</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let report = epub.interactive_report();
+        let files = report.get_files();
+        assert_eq!(files.len(), 2);
+        let kinds: Vec<InteractiveKind> = files[0]
+            .get_constructs()
+            .iter()
+            .map(InteractiveConstruct::get_kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                InteractiveKind::Script,
+                InteractiveKind::Form,
+                InteractiveKind::Canvas
+            ]
+        );
+        assert!(files[0].get_constructs()[2].has_fallback());
+        assert!(files[0].is_declared_scripted() && !files[0].has_static_fallback());
+
+        let switch = &files[1].get_constructs()[0];
+        assert_eq!(switch.get_kind(), InteractiveKind::Switch);
+        assert_eq!(switch.get_id(), Some("formula"));
+        assert!(switch.has_fallback());
+        assert!(!files[1].get_constructs()[1].has_fallback());
+        assert_eq!(report.undeclared_scripted()[0].get_href(), "ch2.xhtml");
+    }
+}
@@ -0,0 +1,438 @@
+//! Languages and directions of chapters, files and passages
+//!
+//! Content documents declare their language with `xml:lang` (or HTML's
+//! `lang`) on the root element, and mark passages in other languages the
+//! same way further down; `xml:lang` wins when an element has both. The
+//! `dir` attribute sets the writing direction the same way.
+//! `EpubFile::get_language_segments` splits a file's text wherever the
+//! language or direction changes, for SSML, screen readers and
+//! right-to-left display.
+//!
+//! Multi-language books often declare only one `dc:language`. With the
+//! `language-detection` feature, `detected_language` runs whatlang over the
+//! extracted text and reports a two-letter BCP 47 tag, the same form
+//! `dc:language` uses, so the two can be compared directly.
+
+#[cfg(feature = "language-detection")]
+use crate::epub::Chapter;
+use crate::epub::EpubFile;
+use crate::text::html_to_text;
+use crate::xhtml::{TAG, VOID_ELEMENTS, find_elements, parse_attributes};
+use regex::Regex;
+use std::sync::LazyLock;
+#[cfg(feature = "language-detection")]
+use whatlang::Lang;
+
+/// Writing direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+/// A run of text in one language and direction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageSegment {
+    text: String,
+    language: Option<String>,
+    direction: Direction,
+}
+
+impl LanguageSegment {
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// The declared language tag; `None` when nothing declares one, in which
+    /// case the book's `dc:language` applies
+    pub fn get_language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// The direction set by a `dir` attribute, or else the direction of the
+    /// text's first strongly directional character (as in the Unicode bidi
+    /// algorithm), or else the document's
+    pub fn get_direction(&self) -> Direction {
+        self.direction
+    }
+}
+
+#[cfg(feature = "language-detection")]
+impl Chapter {
+    /// The language the chapter's text is written in
+    ///
+    /// Falls back to the language its first file declares, then to the
+    /// book's `dc:language`, when the text is too short or too mixed for a
+    /// reliable guess.
+    pub fn detected_language(&self) -> Option<String> {
+        detect(&self.get_text())
+            .map(str::to_string)
+            .or_else(|| self.files.first().and_then(EpubFile::get_language))
+            .or_else(|| self.language.clone())
+    }
+}
+
+#[cfg(feature = "language-detection")]
+impl EpubFile {
+    /// The language this file's text is written in, if it can be told reliably
+    pub fn detected_language(&self) -> Option<String> {
+        detect(&self.get_text()).map(str::to_string)
+    }
+}
+
+impl EpubFile {
+    /// The language declared on the document's root `<html>` element
+    pub fn get_language(&self) -> Option<String> {
+        declared_language(&root_attributes(&self.content)).and_then(non_empty)
+    }
+
+    /// The direction declared with `dir` on `<html>` or `<body>`
+    pub fn get_direction(&self) -> Option<Direction> {
+        let body = find_elements(&self.content, |e| e.name == "body");
+        body.first()
+            .and_then(|body| declared_direction(&body.attributes))
+            .or_else(|| declared_direction(&root_attributes(&self.content)))
+            .flatten()
+    }
+
+    /// The text of the document split wherever its language or direction
+    /// changes
+    ///
+    /// Each segment's text is extracted like `get_text`. Text outside any
+    /// element with a language of its own gets the root element's language.
+    pub fn get_language_segments(&self) -> Vec<LanguageSegment> {
+        let root = Context::default().within(&root_attributes(&self.content));
+        let (body, root) = match find_elements(&self.content, |e| e.name == "body").first() {
+            Some(body) => (body.inner, root.within(&body.attributes)),
+            None => (self.content.as_str(), root),
+        };
+
+        // Markup for each run of one context, tags included so block
+        // boundaries still become line breaks
+        let mut runs: Vec<(Context, String)> = Vec::new();
+        let mut push = |context: &Context, html: &str| match runs.last_mut() {
+            Some((last, run)) if last == context => run.push_str(html),
+            _ => runs.push((context.clone(), html.to_string())),
+        };
+        let mut open: Vec<(String, Context)> = Vec::new();
+        let mut last = 0;
+        for cap in TAG.captures_iter(body) {
+            let whole = cap.get(0).expect("whole match");
+            let current = open.last().map_or(&root, |(_, context)| context).clone();
+            push(&current, &body[last..whole.start()]);
+            last = whole.end();
+
+            let name = cap[2].to_ascii_lowercase();
+            if !cap[1].is_empty() {
+                push(&current, whole.as_str());
+                if let Some(position) = open.iter().rposition(|(open, _)| *open == name) {
+                    open.truncate(position);
+                }
+                continue;
+            }
+            let context = current.within(&parse_attributes(&cap[3]));
+            push(&context, whole.as_str());
+            let void = VOID_ELEMENTS.split('|').any(|void| void == name);
+            if !void && !cap[3].trim_end().ends_with('/') {
+                open.push((name, context));
+            }
+        }
+        let current = open.last().map_or(&root, |(_, context)| context).clone();
+        push(&current, &body[last..]);
+
+        // Runs without text (an empty `<span lang>`, say) shouldn't split
+        // their neighbours
+        let mut merged: Vec<(Context, String)> = Vec::new();
+        for (context, html) in runs {
+            if html_to_text(&html).is_empty() {
+                continue;
+            }
+            match merged.last_mut() {
+                Some((last, run)) if *last == context => run.push_str(&html),
+                _ => merged.push((context, html)),
+            }
+        }
+        let fallback = self.get_direction().unwrap_or(Direction::Ltr);
+        merged
+            .into_iter()
+            .map(|(context, html)| {
+                let text = html_to_text(&html);
+                let direction = context
+                    .direction
+                    .or_else(|| first_strong_direction(&text))
+                    .unwrap_or(fallback);
+                LanguageSegment {
+                    text,
+                    language: context.language,
+                    direction,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The language and explicit direction in effect inside an element
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Context {
+    language: Option<String>,
+    /// `None` when no `dir` applies or it is `auto`
+    direction: Option<Direction>,
+}
+
+impl Context {
+    /// The context inside a child element with `attributes`
+    fn within(&self, attributes: &[(String, String)]) -> Context {
+        Context {
+            language: declared_language(attributes).map_or(self.language.clone(), non_empty),
+            direction: declared_direction(attributes).unwrap_or(self.direction),
+        }
+    }
+}
+
+/// Attributes of the root `<html>` element
+fn root_attributes(html: &str) -> Vec<(String, String)> {
+    static ROOT: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?i)<html\b((?:[^>"']|"[^"]*"|'[^']*')*)>"#).expect("html tag pattern")
+    });
+    ROOT.captures(html)
+        .map(|cap| parse_attributes(&cap[1]))
+        .unwrap_or_default()
+}
+
+/// The `xml:lang` or `lang` of an element, possibly empty
+pub(crate) fn declared_language(attributes: &[(String, String)]) -> Option<String> {
+    ["xml:lang", "lang"].iter().find_map(|name| {
+        attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.trim().to_string())
+    })
+}
+
+/// The `dir` of an element: `Some(None)` for `auto`, `None` when unset or
+/// unrecognized
+fn declared_direction(attributes: &[(String, String)]) -> Option<Option<Direction>> {
+    let (_, dir) = attributes
+        .iter()
+        .find(|(attribute, _)| attribute == "dir")?;
+    match dir.trim().to_ascii_lowercase().as_str() {
+        "ltr" => Some(Some(Direction::Ltr)),
+        "rtl" => Some(Some(Direction::Rtl)),
+        "auto" => Some(None),
+        _ => None,
+    }
+}
+
+/// An empty language tag means the language is unknown
+pub(crate) fn non_empty(tag: String) -> Option<String> {
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// Direction of the first letter in `text` that has a strong direction
+fn first_strong_direction(text: &str) -> Option<Direction> {
+    let c = text.chars().find(|c| c.is_alphabetic())?;
+    let rtl = matches!(c as u32,
+        0x0590..=0x08FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF | 0x10800..=0x10FFF | 0x1E800..=0x1EFFF);
+    Some(if rtl { Direction::Rtl } else { Direction::Ltr })
+}
+
+#[cfg(feature = "language-detection")]
+fn detect(text: &str) -> Option<&'static str> {
+    whatlang::detect(text)
+        .filter(|info| info.is_reliable())
+        .map(|info| tag(info.lang()))
+}
+
+/// The BCP 47 tag for a detected language
+#[cfg(feature = "language-detection")]
+fn tag(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Afr => "af",
+        Lang::Aka => "ak",
+        Lang::Amh => "am",
+        Lang::Ara => "ar",
+        Lang::Aze => "az",
+        Lang::Bel => "be",
+        Lang::Ben => "bn",
+        Lang::Bul => "bg",
+        Lang::Cat => "ca",
+        Lang::Ces => "cs",
+        Lang::Cmn => "zh",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Epo => "eo",
+        Lang::Est => "et",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Guj => "gu",
+        Lang::Heb => "he",
+        Lang::Hin => "hi",
+        Lang::Hrv => "hr",
+        Lang::Hun => "hu",
+        Lang::Hye => "hy",
+        Lang::Ind => "id",
+        Lang::Ita => "it",
+        Lang::Jav => "jv",
+        Lang::Jpn => "ja",
+        Lang::Kan => "kn",
+        Lang::Kat => "ka",
+        Lang::Khm => "km",
+        Lang::Kor => "ko",
+        Lang::Lat => "la",
+        Lang::Lav => "lv",
+        Lang::Lit => "lt",
+        Lang::Mal => "ml",
+        Lang::Mar => "mr",
+        Lang::Mkd => "mk",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Nld => "nl",
+        Lang::Nob => "nb",
+        Lang::Ori => "or",
+        Lang::Pan => "pa",
+        Lang::Pes => "fa",
+        Lang::Pol => "pl",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Sin => "si",
+        Lang::Slk => "sk",
+        Lang::Slv => "sl",
+        Lang::Sna => "sn",
+        Lang::Spa => "es",
+        Lang::Srp => "sr",
+        Lang::Swe => "sv",
+        Lang::Tam => "ta",
+        Lang::Tel => "te",
+        Lang::Tgl => "tl",
+        Lang::Tha => "th",
+        Lang::Tuk => "tk",
+        Lang::Tur => "tr",
+        Lang::Ukr => "uk",
+        Lang::Urd => "ur",
+        Lang::Uzb => "uz",
+        Lang::Vie => "vi",
+        Lang::Yid => "yi",
+        Lang::Zul => "zu",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn segments_follow_declared_languages() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en" lang="en"><head><title>T</title></head><body>
+<p>He said <i xml:lang="fr" lang="de">bonjour<br/> mon ami</i> and left.<span lang="ja"></span> Then:</p>
+<blockquote lang="la"><p>Veni, vidi, <b>vici</b>.</p><p lang="">Unknown</p></blockquote>
+<p>The end.</p>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let file = &epub.get_spine_files()[0];
+        assert_eq!(file.get_language().as_deref(), Some("en"));
+        let segments = file.get_language_segments();
+        let segments: Vec<(&str, Option<&str>)> = segments
+            .iter()
+            .map(|segment| (segment.get_text(), segment.get_language()))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                ("He said", Some("en")),
+                ("bonjour\nmon ami", Some("fr")),
+                ("and left. Then:", Some("en")),
+                ("Veni, vidi, vici.", Some("la")),
+                ("Unknown", None),
+                ("The end.", Some("en")),
+            ]
+        );
+    }
+
+    #[test]
+    fn segments_carry_direction() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html xml:lang="ar" dir="rtl"><body>
+<p>مرحبا بالعالم</p><p><span lang="en">Hello</span> <span dir="ltr">2024</span></p><p>42</p>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let file = &epub.get_spine_files()[0];
+        assert_eq!(file.get_direction(), Some(Direction::Rtl));
+        let segments = file.get_language_segments();
+        let found: Vec<(&str, Option<&str>, Direction)> = segments
+            .iter()
+            .map(|s| (s.get_text(), s.get_language(), s.get_direction()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("مرحبا بالعالم", Some("ar"), Direction::Rtl),
+                ("Hello", Some("en"), Direction::Rtl),
+                ("\u{2066}2024\u{2069}", Some("ar"), Direction::Ltr),
+                ("42", Some("ar"), Direction::Rtl),
+            ]
+        );
+    }
+
+    #[cfg(feature = "language-detection")]
+    #[test]
+    fn chapters_detect_their_own_language() {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch3" href="ch3.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/><itemref idref="ch3"/>"#,
+        );
+        let nav = r#"<html><body><nav epub:type="toc"><ol><li><a href="ch1.xhtml">One</a></li><li><a href="ch2.xhtml">Two</a></li><li><a href="ch3.xhtml">Three</a></li></ol></nav></body></html>"#;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            (
+                "ch1.xhtml",
+                "<html><body><p>It was the best of times, it was the worst of times, it was the age of wisdom, it was the age of foolishness.</p></body></html>",
+            ),
+            (
+                "ch2.xhtml",
+                "<html><body><p>Longtemps, je me suis couché de bonne heure. Parfois, à peine ma bougie éteinte, mes yeux se fermaient si vite que je n'avais pas le temps de me dire.</p></body></html>",
+            ),
+            ("ch3.xhtml", "<html><body><p>42</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let chapters = epub.get_chapters();
+        assert_eq!(chapters[0].detected_language().as_deref(), Some("en"));
+        assert_eq!(chapters[1].detected_language().as_deref(), Some("fr"));
+        assert_eq!(chapters[2].detected_language(), None);
+        assert_eq!(chapters[2].get_files()[0].detected_language(), None);
+
+        epub.edit_metadata().set_language("de").unwrap();
+        let chapters = epub.get_chapters();
+        assert_eq!(chapters[1].detected_language().as_deref(), Some("fr"));
+        assert_eq!(chapters[2].detected_language().as_deref(), Some("de"));
+    }
+}
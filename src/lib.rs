@@ -1,6 +1,110 @@
+#[macro_use]
+mod macros;
+
+pub mod apple;
+mod archive;
+pub mod bibliography;
+pub mod bloat;
+pub mod blocks;
+pub mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "subject-codes")]
+pub mod categories;
+#[cfg(feature = "checksums")]
+pub mod checksums;
+pub mod clipper;
+pub mod covers;
+pub mod darkmode;
+pub mod dedup;
+pub mod diff;
+#[cfg(feature = "dom")]
+pub mod dom;
+pub mod drm;
+pub mod edit;
+pub mod embedded;
+pub mod enrich;
 pub mod epub;
+pub mod error;
+pub mod events;
+pub mod excerpt;
+pub mod extensions;
+pub mod feed;
+pub mod figures;
+pub mod filename;
+#[cfg(feature = "fonts")]
+pub mod fontinfo;
+#[cfg(feature = "fonts")]
+pub mod fonts;
+pub mod footnotes;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod glossary;
+pub mod handle;
+pub mod highlights;
+mod href;
+#[cfg(feature = "image")]
+pub mod images;
+pub mod interactive;
+pub mod language;
+pub mod library;
+pub mod links;
+pub mod locations;
+pub mod locator;
+#[cfg(feature = "markdown")]
+mod markdown;
+pub mod media;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+pub mod nav;
+#[cfg(feature = "node")]
+mod node;
+pub mod offline;
+pub mod offsets;
+pub mod options;
+mod orphans;
+pub mod package;
+pub mod pages;
+pub mod parser;
+pub mod prefetch;
+pub mod preview;
+#[cfg(feature = "python")]
+mod python;
+mod refs;
+pub mod regions;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod replace;
+pub mod report;
+pub mod ruby;
+pub mod sample;
+pub mod scan;
+pub mod search;
+pub mod semantics;
+pub mod session;
+pub mod sizes;
+pub mod sniff;
+pub mod spellcheck;
+pub mod styles;
+pub mod subjects;
+pub mod summary;
+pub mod tables;
+#[cfg(test)]
+mod test_support;
+pub mod text;
+pub mod typography;
+pub mod validate;
+pub mod vocabulary;
+pub mod watermarks;
+mod writer;
+mod xhtml;
 
 pub use epub::Epub;
+pub use error::EpubError;
+pub use options::{ParseOptions, WriteOptions};
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!("epubie");
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
@@ -18,7 +122,7 @@ mod tests {
         match Epub::new(path.to_string()) {
             Ok(epub) => {
                 for (i, entry) in epub.get_chapters().iter().enumerate() {
-                    println!(c
+                    println!(
                         "Chapter {}: {} ({} file{})",
                         i + 1,
                         entry.get_title(),
@@ -0,0 +1,439 @@
+//! Scanning a directory of books
+//!
+//! The core loop of a library manager: find every EPUB under a directory and
+//! pull out what a shelf view needs. `library::scan` parses each book with
+//! `ParseOptions::with_metadata_only`, which skips the content documents,
+//! and spreads the books over one worker thread per CPU. Summaries arrive as
+//! each book finishes, not in directory order. A book that fails to parse
+//! yields a `ScanError` and the scan carries on.
+
+use crate::epub::Epub;
+use crate::options::ParseOptions;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// What a library view shows for one book, from `library::scan`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookSummary {
+    path: PathBuf,
+    title: Option<String>,
+    authors: Vec<String>,
+    series: Option<String>,
+    series_index: Option<f32>,
+    cover_thumb: Option<Vec<u8>>,
+    ids: Vec<String>,
+}
+
+impl BookSummary {
+    fn from_epub(path: PathBuf, epub: &Epub, options: &ScanOptions) -> Self {
+        let (series, series_index) = series(epub);
+        Self {
+            path,
+            title: epub.get_title().map(str::to_string),
+            authors: epub.get_creators().to_vec(),
+            series,
+            series_index,
+            cover_thumb: cover_thumb(epub, options),
+            ids: crate::summary::identifiers(epub),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn get_authors(&self) -> &[String] {
+        &self.authors
+    }
+
+    /// The series name, from `belongs-to-collection` or `calibre:series`
+    pub fn get_series(&self) -> Option<&str> {
+        self.series.as_deref()
+    }
+
+    /// The book's position in its series
+    pub fn get_series_index(&self) -> Option<f32> {
+        self.series_index
+    }
+
+    /// A JPEG thumbnail of the cover
+    ///
+    /// Requires the `image` feature; without it this is always `None`.
+    pub fn get_cover_thumb(&self) -> Option<&[u8]> {
+        self.cover_thumb.as_deref()
+    }
+
+    /// Every `dc:identifier` (ISBN, UUID, ...), in document order
+    pub fn get_ids(&self) -> &[String] {
+        &self.ids
+    }
+}
+
+/// A file (or directory) `library::scan` couldn't read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanError {
+    path: PathBuf,
+    message: String,
+}
+
+impl ScanError {
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+impl Error for ScanError {}
+
+/// Settings for `library::scan_with_options`
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    threads: usize,
+    thumbnail_size: (u32, u32),
+    parse_options: ParseOptions,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+            thumbnail_size: (160, 240),
+            parse_options: ParseOptions::default(),
+        }
+    }
+}
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Worker threads; one per CPU by default
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Largest thumbnail width and height; 160x240 by default
+    pub fn with_thumbnail_size(mut self, width: u32, height: u32) -> Self {
+        self.thumbnail_size = (width.max(1), height.max(1));
+        self
+    }
+
+    /// Options for parsing each book; metadata-only parsing is always on
+    pub fn with_parse_options(mut self, options: ParseOptions) -> Self {
+        self.parse_options = options;
+        self
+    }
+
+    pub fn get_threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn get_thumbnail_size(&self) -> (u32, u32) {
+        self.thumbnail_size
+    }
+
+    pub fn get_parse_options(&self) -> &ParseOptions {
+        &self.parse_options
+    }
+}
+
+/// Summarize every `.epub` under `dir`, as `scan_with_options` with the defaults
+pub fn scan(
+    dir: impl AsRef<Path>,
+) -> io::Result<impl Iterator<Item = Result<BookSummary, ScanError>>> {
+    scan_with_options(dir, ScanOptions::default())
+}
+
+/// Summarize every `.epub` under `dir`, in parallel
+///
+/// Subdirectories are searched too. Only a `dir` that can't be read is an
+/// error up front; everything else is reported through the iterator, which
+/// yields each summary as soon as its book is parsed.
+pub fn scan_with_options(
+    dir: impl AsRef<Path>,
+    options: ScanOptions,
+) -> io::Result<impl Iterator<Item = Result<BookSummary, ScanError>>> {
+    let (sender, receiver) = mpsc::channel();
+    let mut paths = Vec::new();
+    let entries = std::fs::read_dir(dir.as_ref())?;
+    collect_books(entries, &mut paths, &sender);
+    // Workers take paths from the end, so reverse to start with the first found
+    paths.reverse();
+
+    let threads = options.threads.min(paths.len());
+    let paths = Arc::new(Mutex::new(paths));
+    let options = Arc::new(options);
+    for _ in 0..threads {
+        let paths = Arc::clone(&paths);
+        let options = Arc::clone(&options);
+        let sender = sender.clone();
+        thread::spawn(move || {
+            while let Some(path) = next_path(&paths, &sender) {
+                if sender.send(summarize(path, &options)).is_err() {
+                    // The caller dropped the iterator
+                    break;
+                }
+            }
+        });
+    }
+    Ok(receiver.into_iter())
+}
+
+/// Take the next book to parse
+///
+/// If another worker panicked while holding the lock, the books still queued
+/// are reported as errors instead of silently dropped.
+fn next_path(
+    paths: &Mutex<Vec<PathBuf>>,
+    errors: &mpsc::Sender<Result<BookSummary, ScanError>>,
+) -> Option<PathBuf> {
+    match paths.lock() {
+        Ok(mut paths) => paths.pop(),
+        Err(poisoned) => {
+            for path in std::mem::take(&mut *poisoned.into_inner())
+                .into_iter()
+                .rev()
+            {
+                let _ = errors.send(Err(ScanError {
+                    path,
+                    message: "not scanned: a worker thread panicked".to_string(),
+                }));
+            }
+            None
+        }
+    }
+}
+
+/// Add the EPUBs among `entries` (recursively) to `paths`, reporting unreadable directories
+///
+/// Symlinked directories aren't followed, so a link back up the tree can't
+/// loop forever.
+fn collect_books(
+    entries: std::fs::ReadDir,
+    paths: &mut Vec<PathBuf>,
+    errors: &mpsc::Sender<Result<BookSummary, ScanError>>,
+) {
+    let mut entries: Vec<(PathBuf, bool)> = entries
+        .flatten()
+        .map(|entry| {
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            (entry.path(), is_dir)
+        })
+        .collect();
+    entries.sort();
+    for (path, is_dir) in entries {
+        if is_dir {
+            match std::fs::read_dir(&path) {
+                Ok(entries) => collect_books(entries, paths, errors),
+                Err(e) => {
+                    let _ = errors.send(Err(ScanError {
+                        path,
+                        message: e.to_string(),
+                    }));
+                }
+            }
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("epub"))
+        {
+            paths.push(path);
+        }
+    }
+}
+
+fn summarize(path: PathBuf, options: &ScanOptions) -> Result<BookSummary, ScanError> {
+    enter_span!("scan", path = %path.display());
+    let parse_options = options.parse_options.clone().with_metadata_only(true);
+    let parsed = match path.to_str() {
+        Some(file_path) => Epub::with_options(file_path.to_string(), parse_options),
+        // Epub::with_options takes a UTF-8 path; read anything else into memory
+        None => std::fs::read(&path)
+            .map_err(Into::into)
+            .and_then(|bytes| Epub::from_bytes_with_options(bytes, parse_options)),
+    };
+    match parsed {
+        Ok(epub) => Ok(BookSummary::from_epub(path, &epub, options)),
+        Err(e) => Err(ScanError {
+            message: e.to_string(),
+            path,
+        }),
+    }
+}
+
+/// The series name and position, preferring EPUB 3 collections to calibre's metadata
+pub(crate) fn series(epub: &Epub) -> (Option<String>, Option<f32>) {
+    let refinement = |id: &str, property: &str| {
+        epub.meta
+            .iter()
+            .find(|meta| {
+                meta.refines.as_deref().and_then(|r| r.strip_prefix('#')) == Some(id)
+                    && meta.property.as_deref() == Some(property)
+            })
+            .and_then(|meta| meta.value.as_deref())
+            .map(str::trim)
+    };
+    let collection = epub.meta.iter().find(|meta| {
+        meta.property.as_deref() == Some("belongs-to-collection")
+            && meta.id.as_deref().is_none_or(|id| {
+                refinement(id, "collection-type").is_none_or(|kind| kind == "series")
+            })
+    });
+    if let Some(name) = collection.and_then(|meta| meta.value.as_deref()) {
+        let index = collection
+            .and_then(|meta| meta.id.as_deref())
+            .and_then(|id| refinement(id, "group-position"))
+            .and_then(|position| position.parse().ok());
+        return (Some(name.trim().to_string()), index);
+    }
+
+    let named = |name: &str| {
+        epub.meta
+            .iter()
+            .find(|meta| meta.name.as_deref() == Some(name))
+            .and_then(|meta| meta.content.as_deref())
+            .map(str::trim)
+    };
+    (
+        named("calibre:series").map(str::to_string),
+        named("calibre:series_index").and_then(|index| index.parse().ok()),
+    )
+}
+
+#[cfg(feature = "image")]
+fn cover_thumb(epub: &Epub, options: &ScanOptions) -> Option<Vec<u8>> {
+    use crate::images::{ImageFormat, encode};
+    let cover = image::load_from_memory(&epub.get_cover_bytes()?).ok()?;
+    let (width, height) = options.thumbnail_size;
+    encode(&cover.thumbnail(width, height), ImageFormat::Jpeg, 80)
+}
+
+#[cfg(not(feature = "image"))]
+fn cover_thumb(_epub: &Epub, _options: &ScanOptions) -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    #[test]
+    fn every_book_under_the_directory_is_summarized() {
+        let dir = std::env::temp_dir().join(format!("epubie-library-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        let book = |title: &str, extra: &str| {
+            let opf = format!(
+                r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>urn:uuid:{0}</dc:identifier>
+    <dc:identifier>978000000000{0}</dc:identifier>
+    <dc:title>{1}</dc:title>
+    <dc:creator>Frank Herbert</dc:creator>
+    {2}
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#,
+                title.len(),
+                title,
+                extra
+            );
+            build(&[("META-INF/container.xml", CONTAINER), ("content.opf", &opf)])
+        };
+        let series = r##"<meta property="belongs-to-collection" id="c1">Dune</meta>
+    <meta refines="#c1" property="group-position">2</meta>"##;
+        std::fs::write(dir.join("dune.epub"), book("Dune", "")).unwrap();
+        std::fs::write(
+            dir.join("nested/messiah.EPUB"),
+            book("Dune Messiah", series),
+        )
+        .unwrap();
+        std::fs::write(dir.join("broken.epub"), b"not a zip").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"skipped").unwrap();
+
+        let mut results: Vec<_> = scan_with_options(&dir, ScanOptions::new().with_threads(2))
+            .unwrap()
+            .collect();
+        results.sort_by_key(|result| match result {
+            Ok(summary) => summary.get_path().to_path_buf(),
+            Err(e) => e.get_path().to_path_buf(),
+        });
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[0].as_ref().unwrap_err().get_path(),
+            dir.join("broken.epub")
+        );
+        let dune = results[1].as_ref().unwrap();
+        assert_eq!(dune.get_title(), Some("Dune"));
+        assert_eq!(dune.get_authors(), ["Frank Herbert"]);
+        assert_eq!(dune.get_ids(), ["urn:uuid:4", "9780000000004"]);
+        assert_eq!(dune.get_series(), None);
+        let messiah = results[2].as_ref().unwrap();
+        assert_eq!(messiah.get_series(), Some("Dune"));
+        assert_eq!(messiah.get_series_index(), Some(2.0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_directories_are_not_followed() {
+        let dir = std::env::temp_dir().join(format!("epubie-symlinks-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("books")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("books/loop")).unwrap();
+        std::fs::write(dir.join("books/broken.epub"), b"not a zip").unwrap();
+
+        let results: Vec<_> = scan(&dir).unwrap().collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].as_ref().unwrap_err().get_path(),
+            dir.join("books/broken.epub")
+        );
+    }
+
+    #[test]
+    fn a_poisoned_queue_reports_the_books_left_in_it() {
+        let paths = Arc::new(Mutex::new(vec![
+            PathBuf::from("b.epub"),
+            PathBuf::from("a.epub"),
+        ]));
+        let poisoner = Arc::clone(&paths);
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("worker panicked");
+        })
+        .join();
+
+        let (sender, receiver) = mpsc::channel();
+        assert_eq!(next_path(&paths, &sender), None);
+        assert_eq!(next_path(&paths, &sender), None);
+        drop(sender);
+        let skipped: Vec<_> = receiver
+            .into_iter()
+            .map(|result| result.unwrap_err().get_path().to_path_buf())
+            .collect();
+        assert_eq!(skipped, [PathBuf::from("a.epub"), PathBuf::from("b.epub")]);
+    }
+}
@@ -0,0 +1,400 @@
+//! Hyperlinks between content documents
+//!
+//! `Epub::link_graph` records which documents link to which through `<a>`
+//! and `<area>` hrefs. Documents are identified by their manifest hrefs.
+//! Links to the same document, to external URLs and to resources that
+//! aren't content documents are left out.
+//!
+//! `Epub::get_external_links` covers the links that leave the book instead.
+//!
+//! With the `dom` feature, anchors are read from each document's cached tree
+//! (`Epub::get_dom`) rather than scanned out of the markup.
+
+use crate::epub::{Epub, EpubFile};
+use crate::href;
+use std::collections::{HashMap, HashSet};
+
+/// Links from one document to another, however many there are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentLink {
+    from: String,
+    to: String,
+    count: usize,
+}
+
+impl DocumentLink {
+    pub fn get_from(&self) -> &str {
+        &self.from
+    }
+
+    pub fn get_to(&self) -> &str {
+        &self.to
+    }
+
+    /// Number of hyperlinks `from` has to `to`
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+}
+
+/// A link out of the book and where it appears
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalLink {
+    url: String,
+    text: String,
+    chapter_index: usize,
+    chapter_title: String,
+    href: String,
+}
+
+impl ExternalLink {
+    /// The `href` as written, such as `https://...` or `mailto:...`
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    /// The anchor text, or the `alt` text of an image map `<area>`
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Index of the containing chapter in `Epub::get_chapters`
+    pub fn get_chapter_index(&self) -> usize {
+        self.chapter_index
+    }
+
+    pub fn get_chapter_title(&self) -> &str {
+        &self.chapter_title
+    }
+
+    /// Href of the file containing the link
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Whether the link opens an email client rather than a web page
+    pub fn is_mailto(&self) -> bool {
+        self.url
+            .get(..7)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+    }
+}
+
+/// The documents of a book and the hyperlinks between them
+#[derive(Debug, Clone, Default)]
+pub struct LinkGraph {
+    documents: Vec<String>,
+    links: Vec<DocumentLink>,
+    unreachable: Vec<String>,
+}
+
+impl LinkGraph {
+    /// Every content document, spine documents first in reading order
+    pub fn get_documents(&self) -> &[String] {
+        &self.documents
+    }
+
+    /// Every edge, in the order the first link of each appears
+    pub fn get_links(&self) -> &[DocumentLink] {
+        &self.links
+    }
+
+    /// Edges leaving `href`
+    pub fn links_from(&self, href: &str) -> Vec<&DocumentLink> {
+        self.links.iter().filter(|link| link.from == href).collect()
+    }
+
+    /// Edges arriving at `href`
+    pub fn links_to(&self, href: &str) -> Vec<&DocumentLink> {
+        self.links.iter().filter(|link| link.to == href).collect()
+    }
+
+    /// Documents a reader can't get to: outside the spine, not listed in the
+    /// navigation document, and not linked from anything that is
+    pub fn get_unreachable(&self) -> &[String] {
+        &self.unreachable
+    }
+
+    /// The graph in Graphviz DOT format, for visualizing with `dot`
+    pub fn to_dot(&self) -> String {
+        let quote = |href: &str| format!("\"{}\"", href.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut dot = String::from("digraph links {\n");
+        for document in &self.documents {
+            dot.push_str(&format!("  {};\n", quote(document)));
+        }
+        for link in &self.links {
+            dot.push_str(&format!("  {} -> {}", quote(&link.from), quote(&link.to)));
+            if link.count > 1 {
+                dot.push_str(&format!(" [label=\"{}\"]", link.count));
+            }
+            dot.push_str(";\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl Epub {
+    /// Every `http`, `https` and `mailto` link in the book, in reading order
+    pub fn get_external_links(&self) -> Vec<ExternalLink> {
+        let mut links = Vec::new();
+        for (chapter_index, chapter) in self.get_chapters().iter().enumerate() {
+            for file in chapter.get_files() {
+                for (url, text) in self.anchors(file) {
+                    let url = url.trim();
+                    let scheme = url
+                        .split_once(':')
+                        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+                    if !matches!(scheme.as_deref(), Some("http" | "https" | "mailto")) {
+                        continue;
+                    }
+                    links.push(ExternalLink {
+                        url: url.to_string(),
+                        text,
+                        chapter_index,
+                        chapter_title: chapter.get_title().to_string(),
+                        href: file.get_href().to_string(),
+                    });
+                }
+            }
+        }
+        links
+    }
+
+    /// Build the graph of hyperlinks between the book's content documents
+    pub fn link_graph(&self) -> LinkGraph {
+        let mut files = self.get_spine_files();
+        files.extend(self.all_files.iter().filter(|file| !file.is_in_spine()));
+
+        // Hrefs in the graph, keyed by their resolved form
+        let documents: HashMap<String, &str> = files
+            .iter()
+            .map(|file| (href::resolve("", file.get_href()), file.get_href()))
+            .collect();
+
+        let mut graph = LinkGraph {
+            documents: files
+                .iter()
+                .map(|file| file.get_href().to_string())
+                .collect(),
+            ..LinkGraph::default()
+        };
+        let mut edges: HashMap<(&str, &str), usize> = HashMap::new();
+        for file in &files {
+            let from = file.get_href();
+            for (reference, _) in self.anchors(file) {
+                let reference = reference.trim();
+                if reference.is_empty() || reference.starts_with('#') || href::has_scheme(reference)
+                {
+                    continue;
+                }
+                let Some(&to) = documents.get(&href::resolve(from, reference)) else {
+                    continue;
+                };
+                if to == from {
+                    continue;
+                }
+                match edges.get(&(from, to)) {
+                    Some(&index) => graph.links[index].count += 1,
+                    None => {
+                        edges.insert((from, to), graph.links.len());
+                        graph.links.push(DocumentLink {
+                            from: from.to_string(),
+                            to: to.to_string(),
+                            count: 1,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Files the nav or NCX lists are the only ones with a title
+        let mut pending: Vec<&str> = files
+            .iter()
+            .filter(|file| file.is_in_spine() || file.get_title().is_some())
+            .map(|file| file.get_href())
+            .collect();
+        let mut reachable: HashSet<&str> = pending.iter().copied().collect();
+        while let Some(document) = pending.pop() {
+            for link in graph.links.iter().filter(|link| link.from == document) {
+                if reachable.insert(&link.to) {
+                    pending.push(&link.to);
+                }
+            }
+        }
+        graph.unreachable = graph
+            .documents
+            .iter()
+            .filter(|document| !reachable.contains(document.as_str()))
+            .cloned()
+            .collect();
+        graph
+    }
+
+    /// `(href, text)` of every `<a>` and `<area>` in `file` with an href
+    ///
+    /// The text is the anchor's text on one line, or an `<area>`'s `alt`.
+    #[cfg(not(feature = "dom"))]
+    fn anchors(&self, file: &EpubFile) -> Vec<(String, String)> {
+        use crate::text::html_to_text;
+        use crate::xhtml::find_elements;
+
+        find_elements(file.get_content(), |e| e.name == "a" || e.name == "area")
+            .into_iter()
+            .filter_map(|anchor| {
+                let href = anchor.attribute("href")?.to_string();
+                let text = if anchor.name == "area" {
+                    anchor.attribute("alt").unwrap_or("").to_string()
+                } else {
+                    html_to_text(anchor.inner).replace('\n', " ")
+                };
+                Some((href, text))
+            })
+            .collect()
+    }
+
+    /// `(href, text)` of every `<a>` and `<area>` in `file` with an href
+    ///
+    /// The text is the anchor's text on one line, or an `<area>`'s `alt`.
+    #[cfg(feature = "dom")]
+    fn anchors(&self, file: &EpubFile) -> Vec<(String, String)> {
+        let document = self.file_dom(file);
+        document
+            .find(|node| matches!(node.get_name(), Some("a" | "area")))
+            .into_iter()
+            .filter_map(|anchor| {
+                let href = anchor.get_attribute("href")?.to_string();
+                let text = if anchor.get_name() == Some("area") {
+                    anchor.get_attribute("alt").unwrap_or("").to_string()
+                } else {
+                    let text = anchor.text_content();
+                    text.split_whitespace().collect::<Vec<_>>().join(" ")
+                };
+                Some((href, text))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn links_between_documents_form_a_graph() {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="notes" href="notes.xhtml" media-type="application/xhtml+xml"/>
+               <item id="cut" href="cut.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let nav = r#"<html><body><nav epub:type="toc"><ol><li><a href="text/ch1.xhtml">One</a></li><li><a href="text/ch2.xhtml">Two</a></li></ol></nav></body></html>"#;
+        let ch1 = r##"<html><body><p><a href="../notes.xhtml#n1">1</a> <a href="../notes.xhtml#n2">2</a>
+<a href="#top">top</a> <a href="ch2.xhtml">next</a> <a href="https://example.com/">web</a></p></body></html>"##;
+        let ch2 = r#"<html><body><p><a href="ch1.xhtml">back</a> <a href="MAILTO:editor@example.com">Write <em>to us</em></a></p></body></html>"#;
+        let notes =
+            r#"<html><body><p id="n1"><a href="text/ch1.xhtml">return</a></p></body></html>"#;
+        let cut = r#"<html><body><p><a href="text/ch1.xhtml">start</a></p></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            ("text/ch1.xhtml", ch1),
+            ("text/ch2.xhtml", ch2),
+            ("notes.xhtml", notes),
+            ("cut.xhtml", cut),
+        ]))
+        .unwrap();
+
+        let graph = epub.link_graph();
+        assert_eq!(
+            graph.get_documents(),
+            [
+                "text/ch1.xhtml",
+                "text/ch2.xhtml",
+                "notes.xhtml",
+                "cut.xhtml"
+            ]
+        );
+        let from_ch1 = graph.links_from("text/ch1.xhtml");
+        assert_eq!(from_ch1.len(), 2);
+        assert_eq!(from_ch1[0].get_to(), "notes.xhtml");
+        assert_eq!(from_ch1[0].get_count(), 2);
+        assert_eq!(graph.links_to("text/ch1.xhtml").len(), 3);
+        assert_eq!(graph.get_unreachable(), ["cut.xhtml"]);
+        assert!(
+            graph
+                .to_dot()
+                .contains("\"text/ch1.xhtml\" -> \"notes.xhtml\" [label=\"2\"];")
+        );
+
+        let external = epub.get_external_links();
+        let urls: Vec<&str> = external.iter().map(|link| link.get_url()).collect();
+        assert_eq!(urls, ["https://example.com/", "MAILTO:editor@example.com"]);
+        assert_eq!(external[0].get_text(), "web");
+        assert_eq!(external[0].get_href(), "text/ch1.xhtml");
+        assert!(external[1].is_mailto());
+        assert_eq!(external[1].get_text(), "Write to us");
+    }
+
+    #[test]
+    fn external_links_are_decoded_and_relative_ones_left_out() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let ch1 = r#"<html><body>
+<p><a href="mailto:a@example.com?subject=Hi&amp;body=There">Mail</a>
+<a href=" https://example.com/search?q=moby&amp;page=2 ">Search &amp; find</a></p>
+<p><a href="ch2.xhtml#s">next</a> <a href="../images/map.png">map</a> <a href="//cdn.example.com/x">cdn</a>
+<a href="ftp://example.com/file">ftp</a> <a href="javascript:void(0)">script</a> <a>no href</a></p>
+<map><area href="http://example.com/region" alt="Region"/></map>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", ch1),
+            (
+                "ch2.xhtml",
+                r#"<html><body><p id="s">Two</p></body></html>"#,
+            ),
+        ]))
+        .unwrap();
+
+        let links: Vec<_> = epub
+            .get_external_links()
+            .into_iter()
+            .map(|link| {
+                (
+                    link.get_url().to_string(),
+                    link.get_text().to_string(),
+                    link.is_mailto(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            links,
+            [
+                (
+                    "mailto:a@example.com?subject=Hi&body=There".to_string(),
+                    "Mail".to_string(),
+                    true
+                ),
+                (
+                    "https://example.com/search?q=moby&page=2".to_string(),
+                    "Search & find".to_string(),
+                    false
+                ),
+                (
+                    "http://example.com/region".to_string(),
+                    "Region".to_string(),
+                    false
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Kindle-style location numbers
+//!
+//! Page numbers depend on screen and font size, so readers that sync a
+//! position between devices need a unit that doesn't. `Epub::locations`
+//! cuts the extracted text of the spine documents, in reading order, into
+//! runs of `BYTES_PER_LOCATION` UTF-8 bytes and numbers them from 1. The
+//! numbering only depends on the book's text, so every consumer of this
+//! crate gets the same numbers for the same book.
+
+use crate::epub::Epub;
+
+/// Bytes of extracted text per location, as Kindle uses
+pub const BYTES_PER_LOCATION: usize = 150;
+
+/// Where a location starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    number: usize,
+    href: String,
+    offset: usize,
+    file_index: usize,
+}
+
+impl Location {
+    /// The location number, starting at 1
+    pub fn get_number(&self) -> usize {
+        self.number
+    }
+
+    /// Href of the file the location starts in
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Character offset within the file's extracted text, as in `SearchMatch::get_offset`
+    pub fn get_offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// Every location in a book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locations {
+    bytes_per_location: usize,
+    locations: Vec<Location>,
+    hrefs: Vec<String>,
+}
+
+impl Locations {
+    pub fn get_bytes_per_location(&self) -> usize {
+        self.bytes_per_location
+    }
+
+    pub fn get_locations(&self) -> &[Location] {
+        &self.locations
+    }
+
+    /// Number of locations, which is also the last location number
+    pub fn get_count(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Look up a location by its number
+    pub fn get(&self, number: usize) -> Option<&Location> {
+        self.locations.get(number.checked_sub(1)?)
+    }
+
+    /// The number of the location containing character `offset` of the
+    /// spine file `href`
+    ///
+    /// Returns `None` when `href` isn't a spine file or the book has no text.
+    pub fn location_at(&self, href: &str, offset: usize) -> Option<usize> {
+        let file_index = self.hrefs.iter().position(|h| h == href)?;
+        let after = self
+            .locations
+            .partition_point(|l| (l.file_index, l.offset) <= (file_index, offset));
+        after
+            .checked_sub(1)
+            .map(|index| self.locations[index].number)
+    }
+}
+
+impl Epub {
+    /// Number the book's text in locations of `BYTES_PER_LOCATION` bytes
+    pub fn locations(&self) -> Locations {
+        self.locations_every(BYTES_PER_LOCATION)
+    }
+
+    /// Number the book's text in locations of `bytes_per_location` bytes
+    ///
+    /// Only spine documents count, in reading order. A location that starts
+    /// in the middle of a character starts at that character.
+    pub fn locations_every(&self, bytes_per_location: usize) -> Locations {
+        let bytes_per_location = bytes_per_location.max(1);
+        let mut locations = Vec::new();
+        let mut hrefs = Vec::new();
+        let index = self.text_index();
+
+        for (file_index, file) in self.get_spine_files().into_iter().enumerate() {
+            let text = file.get_text();
+            let file_start = index.get_spans()[file_index].get_byte_start();
+            let mut next = locations.len() * bytes_per_location;
+            for (offset, (byte, c)) in text.char_indices().enumerate() {
+                let end = file_start + byte + c.len_utf8();
+                while next < end {
+                    locations.push(Location {
+                        number: locations.len() + 1,
+                        href: file.get_href().to_string(),
+                        offset,
+                        file_index,
+                    });
+                    next += bytes_per_location;
+                }
+            }
+            hrefs.push(file.get_href().to_string());
+        }
+
+        Locations {
+            bytes_per_location,
+            locations,
+            hrefs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn locations_run_across_spine_files() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>abcdefgh</p></body></html>"),
+            ("ch2.xhtml", "<html><body><p>ééééé</p></body></html>"),
+        ]))
+        .unwrap();
+
+        // 8 + 10 bytes of text, cut every 5 bytes
+        let locations = epub.locations_every(5);
+        let starts: Vec<(usize, &str, usize)> = locations
+            .get_locations()
+            .iter()
+            .map(|l| (l.get_number(), l.get_href(), l.get_offset()))
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                (1, "ch1.xhtml", 0),
+                (2, "ch1.xhtml", 5),
+                (3, "ch2.xhtml", 1),
+                (4, "ch2.xhtml", 3),
+            ]
+        );
+        assert_eq!(locations.location_at("ch2.xhtml", 0), Some(2));
+        assert_eq!(locations.location_at("ch2.xhtml", 4), Some(4));
+        assert_eq!(locations.location_at("nope.xhtml", 0), None);
+        assert_eq!(locations.get(3).map(|l| l.get_offset()), Some(1));
+        assert_eq!(epub.locations().get_count(), 1);
+    }
+}
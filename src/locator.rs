@@ -0,0 +1,256 @@
+//! Positions in a book and the links that encode them
+//!
+//! A `Locator` points at a content document, optionally at an element in it.
+//! Web readers put positions in shareable URLs in one of two forms: a plain
+//! `href#fragment` deep link, or an EPUB Canonical Fragment Identifier such
+//! as `epubcfi(/6/4[ch1]!/4/2[intro])`. `Epub::parse_link` reads either into
+//! a `Locator`, and `Locator::to_link` and `Epub::format_cfi` write them back.
+//!
+//! Only element-level CFIs are supported: character offsets, spatial and
+//! temporal terms and ranges are accepted but dropped, since a locator
+//! doesn't go below the element.
+
+use crate::epub::Epub;
+use crate::href;
+use crate::xhtml::{TAG, VOID_ELEMENTS};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+/// A content document, and optionally an element in it by `id`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locator {
+    href: String,
+    fragment: Option<String>,
+}
+
+impl Locator {
+    /// Point at the start of the document `href` (OPF-relative, as `EpubFile::get_href`)
+    pub fn new(href: &str) -> Self {
+        Self {
+            href: href.to_string(),
+            fragment: None,
+        }
+    }
+
+    /// Point at the element with this `id` instead of the start of the document
+    pub fn with_fragment(mut self, fragment: &str) -> Self {
+        self.fragment = Some(fragment.to_string());
+        self
+    }
+
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_fragment(&self) -> Option<&str> {
+        self.fragment.as_deref()
+    }
+
+    /// Format as an `href#fragment` deep link
+    pub fn to_link(&self) -> String {
+        match &self.fragment {
+            Some(fragment) => format!("{}#{}", self.href, fragment),
+            None => self.href.clone(),
+        }
+    }
+}
+
+impl Epub {
+    /// Read a deep link into a locator
+    ///
+    /// Accepts `href`, `href#fragment`, `epubcfi(...)` and any of those
+    /// after a `#`, percent-encoded or not. Returns `None` when the link
+    /// doesn't point at one of the book's content documents.
+    pub fn parse_link(&self, link: &str) -> Option<Locator> {
+        let link = href::percent_decode(link.trim());
+        let link = link.strip_prefix('#').unwrap_or(&link);
+        if let Some(cfi) = link
+            .strip_prefix("epubcfi(")
+            .or_else(|| link.split_once("#epubcfi(").map(|(_, cfi)| cfi))
+        {
+            return self.parse_cfi(cfi.strip_suffix(')')?);
+        }
+
+        let (path, fragment) = match link.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment).filter(|f| !f.is_empty())),
+            None => (link, None),
+        };
+        let file = self.get_all_files().iter().find(|f| f.get_href() == path)?;
+        let locator = Locator::new(file.get_href());
+        Some(match fragment {
+            Some(fragment) => locator.with_fragment(fragment),
+            None => locator,
+        })
+    }
+
+    /// Format a locator as an `epubcfi(...)` fragment
+    ///
+    /// Returns `None` when the document isn't in the spine, since CFIs
+    /// address documents through it, or when the fragment names no element.
+    pub fn format_cfi(&self, locator: &Locator) -> Option<String> {
+        let file = self
+            .get_all_files()
+            .iter()
+            .find(|f| f.get_href() == locator.get_href())?;
+        let spine_index = file.get_spine_index()?;
+        let mut cfi = format!("epubcfi(/6/{}[{}]!", (spine_index + 1) * 2, file.get_id());
+        if let Some(fragment) = locator.get_fragment() {
+            let (steps, _) = element_steps(file.get_content())
+                .into_iter()
+                .find(|(_, id)| id.as_deref() == Some(fragment))?;
+            for step in steps {
+                cfi.push_str(&format!("/{}", step));
+            }
+            cfi.push_str(&format!("[{}]", fragment));
+        }
+        cfi.push(')');
+        Some(cfi)
+    }
+
+    /// Resolve the inside of `epubcfi(...)`
+    fn parse_cfi(&self, cfi: &str) -> Option<Locator> {
+        // Only the start of a range counts
+        let cfi = cfi.split(',').next()?;
+        let (package, document) = cfi.split_once('!').unwrap_or((cfi, ""));
+        let package = cfi_steps(package);
+        if package.first().map(|(step, _)| *step) != Some(6) {
+            return None;
+        }
+        let (step, idref) = package.get(1)?;
+        let spine_index = (step / 2).checked_sub(1)?;
+        let idref = idref
+            .as_deref()
+            .or(self.get_spine().get(spine_index).map(String::as_str))?;
+        let file = self
+            .get_all_files()
+            .iter()
+            .find(|f| f.get_id() == idref && f.is_in_spine())?;
+        let locator = Locator::new(file.get_href());
+
+        let document = cfi_steps(document);
+        if let Some((_, Some(id))) = document.last() {
+            return Some(locator.with_fragment(id));
+        }
+        // No id assertion: use the deepest element on the path that has an id
+        let path: Vec<usize> = document.iter().map(|(step, _)| *step).collect();
+        let fragment = element_steps(file.get_content())
+            .into_iter()
+            .filter(|(steps, id)| id.is_some() && path.starts_with(steps))
+            .max_by_key(|(steps, _)| steps.len())
+            .and_then(|(_, id)| id);
+        Some(match fragment {
+            Some(fragment) => locator.with_fragment(&fragment),
+            None => locator,
+        })
+    }
+}
+
+/// Split a CFI path into its element steps and their `[id]` assertions
+///
+/// Parsing stops at the first odd (character data) step or offset.
+fn cfi_steps(path: &str) -> Vec<(usize, Option<String>)> {
+    let mut steps = Vec::new();
+    for step in path.split('/').skip(1) {
+        let digits = step
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(step.len());
+        let Ok(number) = step[..digits].parse::<usize>() else {
+            break;
+        };
+        if number % 2 == 1 {
+            break;
+        }
+        let assertion = step[digits..]
+            .strip_prefix('[')
+            .and_then(|rest| rest.split(']').next())
+            .map(|id| id.split(';').next().unwrap_or(id).to_string())
+            .filter(|id| !id.is_empty());
+        steps.push((number, assertion));
+        if step[digits..].contains(':') {
+            break;
+        }
+    }
+    steps
+}
+
+/// Every element below the root with its CFI steps and `id`, in document order
+fn element_steps(html: &str) -> Vec<(Vec<usize>, Option<String>)> {
+    static ID: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"(?:^|\s)id\s*=\s*["']([^"']*)["']"#).expect("id pattern"));
+    let mut elements = Vec::new();
+    // Open elements as (name, step, element children seen so far)
+    let mut open: Vec<(String, usize, usize)> = Vec::new();
+
+    for cap in TAG.captures_iter(html) {
+        let name = cap[2].to_ascii_lowercase();
+        if !cap[1].is_empty() {
+            if let Some(position) = open.iter().rposition(|(open, _, _)| *open == name) {
+                open.truncate(position);
+            }
+            continue;
+        }
+        let step = match open.last_mut() {
+            Some((_, _, children)) => {
+                *children += 1;
+                *children * 2
+            }
+            None => 0,
+        };
+        open.push((name.clone(), step, 0));
+        if open.len() > 1 {
+            let steps = open[1..].iter().map(|(_, step, _)| *step).collect();
+            elements.push((steps, ID.captures(&cap[3]).map(|id| id[1].to_string())));
+        }
+        if cap[3].trim_end().ends_with('/') || VOID_ELEMENTS.split('|').any(|void| void == name) {
+            open.pop();
+        }
+    }
+    elements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn links_and_cfis_round_trip() {
+        let opf = package(
+            r#"<item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let chapter = r#"<html><head><title>Two</title></head><body><p>One</p><br/><div id="intro"><p id="first">Hi</p></div></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("text/ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("text/ch2.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let locator = Locator::new("text/ch2.xhtml").with_fragment("first");
+        let cfi = epub.format_cfi(&locator).unwrap();
+        assert_eq!(cfi, "epubcfi(/6/4[ch2]!/4/6/2[first])");
+        assert_eq!(epub.parse_link(&format!("#{}", cfi)), Some(locator.clone()));
+        assert_eq!(epub.parse_link(&locator.to_link()), Some(locator));
+
+        // Without assertions, offsets are dropped and the nearest id is used
+        let intro = epub.parse_link("epubcfi(/6/4!/4/6/2/1:3)").unwrap();
+        assert_eq!(intro.get_fragment(), Some("first"));
+        let parent = epub.parse_link("epubcfi(/6/4!/4/6/3:0)").unwrap();
+        assert_eq!(parent.get_fragment(), Some("intro"));
+        assert_eq!(
+            epub.parse_link("text/ch1.xhtml")
+                .map(|l| l.to_link())
+                .as_deref(),
+            Some("text/ch1.xhtml")
+        );
+        assert_eq!(epub.parse_link("missing.xhtml#x"), None);
+        assert_eq!(
+            epub.format_cfi(&Locator::new("text/ch1.xhtml")).as_deref(),
+            Some("epubcfi(/6/2[ch1]!)")
+        );
+    }
+}
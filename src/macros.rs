@@ -0,0 +1,32 @@
+//! Internal instrumentation macros
+//!
+//! With the `tracing` feature these forward to the `tracing` crate; without it
+//! they expand to nothing, so call sites need no `cfg` attributes of their own.
+
+/// Enter an info-level span that lasts until the end of the enclosing block
+macro_rules! enter_span {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($($args)*).entered();
+    };
+}
+
+/// Emit a debug-level event
+macro_rules! trace_debug {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!($($args)*);
+        }
+    };
+}
+
+/// Emit a warn-level event
+macro_rules! trace_warn {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::warn!($($args)*);
+        }
+    };
+}
@@ -0,0 +1,142 @@
+//! Rendering CommonMark chapters
+//!
+//! Used by `EpubBuilder::with_markdown_chapter` and `with_markdown_file`.
+//! Headings get slug ids so they can be linked from the table of contents,
+//! and image sources can be redirected to bundled copies. Requires the
+//! `markdown` feature.
+
+use pulldown_cmark::{CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use std::collections::{HashMap, HashSet};
+
+/// A chapter converted to an XHTML fragment
+pub(crate) struct Rendered {
+    pub(crate) html: String,
+    /// `(level, text, id)` for every heading, in document order
+    pub(crate) headings: Vec<(usize, String, String)>,
+}
+
+fn options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_SMART_PUNCTUATION
+        | Options::ENABLE_HEADING_ATTRIBUTES
+}
+
+/// Image sources in `markdown`, in document order, without duplicates
+pub(crate) fn image_sources(markdown: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    Parser::new_ext(markdown, options())
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image { dest_url, .. }) => Some(dest_url.to_string()),
+            _ => None,
+        })
+        .filter(|src| seen.insert(src.clone()))
+        .collect()
+}
+
+/// Render `markdown`, replacing image sources found in `images`
+pub(crate) fn render(markdown: &str, images: &HashMap<String, String>) -> Rendered {
+    let mut events: Vec<Event> = Parser::new_ext(markdown, options()).collect();
+    let mut headings = Vec::new();
+    let mut used_ids = HashSet::new();
+
+    for index in 0..events.len() {
+        match &mut events[index] {
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                if let Some(bundled) = images.get(dest_url.as_ref()) {
+                    *dest_url = CowStr::from(bundled.clone());
+                }
+            }
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                let level = heading_level(*level);
+                let explicit = id.as_ref().map(|id| id.to_string());
+                let text = heading_text(&events[index + 1..]);
+                let slug = explicit.unwrap_or_else(|| unique_slug(&text, &mut used_ids));
+                used_ids.insert(slug.clone());
+                if let Event::Start(Tag::Heading { id, .. }) = &mut events[index] {
+                    *id = Some(CowStr::from(slug.clone()));
+                }
+                headings.push((level, text, slug));
+            }
+            _ => {}
+        }
+    }
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    Rendered { html, headings }
+}
+
+fn heading_level(level: HeadingLevel) -> usize {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Plain text of a heading, given the events following its start tag
+fn heading_text(events: &[Event]) -> String {
+    let mut text = String::new();
+    for event in events {
+        match event {
+            Event::End(TagEnd::Heading(_)) => break,
+            Event::Text(part) | Event::Code(part) => text.push_str(part),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => {}
+        }
+    }
+    text.trim().to_string()
+}
+
+/// An XML-safe id from heading text, numbered if already taken
+fn unique_slug(text: &str, used: &mut HashSet<String>) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    let slug = match slug.chars().next() {
+        Some(first) if first.is_alphabetic() => slug.to_string(),
+        Some(_) => format!("h-{}", slug),
+        None => "section".to_string(),
+    };
+
+    if !used.contains(&slug) {
+        return slug;
+    }
+    (2..)
+        .map(|n| format!("{}-{}", slug, n))
+        .find(|candidate| !used.contains(candidate))
+        .unwrap_or(slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_get_ids_and_images_are_redirected() {
+        let markdown = "# Loomings\n\nCall me *Ishmael*.\n\n## The Sea\n\n![map](img/map.png)\n\n## The Sea\n\n## 1851 {#year}\n";
+        assert_eq!(image_sources(markdown), ["img/map.png"]);
+
+        let images = HashMap::from([("img/map.png".to_string(), "../images/map.png".to_string())]);
+        let rendered = render(markdown, &images);
+        assert!(rendered.html.contains(r#"<h2 id="the-sea-2">"#));
+        assert!(rendered.html.contains(r#"src="../images/map.png""#));
+        let ids: Vec<&str> = rendered
+            .headings
+            .iter()
+            .map(|(_, _, id)| id.as_str())
+            .collect();
+        assert_eq!(ids, ["loomings", "the-sea", "the-sea-2", "year"]);
+    }
+}
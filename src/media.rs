@@ -0,0 +1,263 @@
+//! `<audio>` and `<video>` in content documents
+//!
+//! `Chapter::get_media_elements` lists each audio and video element with
+//! its sources resolved to manifest-style hrefs, its poster image and the
+//! fallback text shown by reading systems that can't play it. A reader can
+//! use it to pre-buffer a chapter's media before the page turns, or to warn
+//! up front about sources no reading system is required to play.
+//!
+//! A source's media type is taken from its `type` attribute, or guessed from
+//! the file extension when there is none.
+
+use crate::epub::{Chapter, EpubFile};
+use crate::href;
+use crate::text::html_to_text;
+use crate::xhtml::find_elements;
+
+/// Audio media types every EPUB 3 reading system with audio support plays
+const CORE_AUDIO: &[&str] = &["audio/mpeg", "audio/mp4", "audio/ogg"];
+/// Video media types EPUB recommends reading systems support (H.264 and VP8/VP9)
+const RECOMMENDED_VIDEO: &[&str] = &["video/mp4", "video/webm"];
+
+/// Whether a `MediaElement` is `<audio>` or `<video>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MediaKind {
+    Audio,
+    Video,
+}
+
+/// One place a `MediaElement` can be played from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaSource {
+    src: String,
+    media_type: Option<String>,
+    codecs: Option<String>,
+}
+
+impl MediaSource {
+    /// The source's href, relative to the OPF like manifest hrefs; remote
+    /// sources keep their URL
+    pub fn get_src(&self) -> &str {
+        &self.src
+    }
+
+    /// The declared media type without parameters, or one guessed from the
+    /// extension
+    pub fn get_media_type(&self) -> Option<&str> {
+        self.media_type.as_deref()
+    }
+
+    /// The `codecs` parameter of the declared type, e.g. `opus`
+    pub fn get_codecs(&self) -> Option<&str> {
+        self.codecs.as_deref()
+    }
+
+    /// Whether the source is served from outside the book
+    pub fn is_remote(&self) -> bool {
+        href::has_scheme(&self.src)
+    }
+
+    /// Whether reading systems can be expected to play it: an EPUB core
+    /// audio type, or one of the recommended video types
+    pub fn is_widely_supported(&self) -> bool {
+        self.media_type
+            .as_deref()
+            .is_some_and(|t| CORE_AUDIO.contains(&t) || RECOMMENDED_VIDEO.contains(&t))
+    }
+}
+
+/// An `<audio>` or `<video>` element, from `Chapter::get_media_elements`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaElement {
+    kind: MediaKind,
+    href: String,
+    id: Option<String>,
+    sources: Vec<MediaSource>,
+    poster: Option<String>,
+    fallback: String,
+    controls: bool,
+    autoplay: bool,
+}
+
+impl MediaElement {
+    pub fn get_kind(&self) -> MediaKind {
+        self.kind
+    }
+
+    /// Href of the file containing the element
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// The element's `src` followed by its `<source>` children, in the
+    /// order a reading system tries them
+    pub fn get_sources(&self) -> &[MediaSource] {
+        &self.sources
+    }
+
+    /// The `poster` image of a video, resolved like the sources
+    pub fn get_poster(&self) -> Option<&str> {
+        self.poster.as_deref()
+    }
+
+    /// Text of the content shown when the element can't be played
+    pub fn get_fallback(&self) -> &str {
+        &self.fallback
+    }
+
+    pub fn has_controls(&self) -> bool {
+        self.controls
+    }
+
+    pub fn is_autoplay(&self) -> bool {
+        self.autoplay
+    }
+
+    /// Whether no source is of a type reading systems can be expected to play
+    pub fn is_unsupported(&self) -> bool {
+        !self.sources.iter().any(MediaSource::is_widely_supported)
+    }
+}
+
+impl Chapter {
+    /// Every audio and video element in the chapter's files, in document order
+    pub fn get_media_elements(&self) -> Vec<MediaElement> {
+        self.files
+            .iter()
+            .flat_map(EpubFile::get_media_elements)
+            .collect()
+    }
+}
+
+impl EpubFile {
+    /// Every audio and video element in this file, in document order
+    pub fn get_media_elements(&self) -> Vec<MediaElement> {
+        find_elements(&self.content, |e| e.name == "audio" || e.name == "video")
+            .into_iter()
+            .map(|element| {
+                let kind = if element.name == "audio" {
+                    MediaKind::Audio
+                } else {
+                    MediaKind::Video
+                };
+                let resolve = |src: &str| href::resolve(&self.href, src.trim());
+                let mut sources: Vec<MediaSource> = element
+                    .attribute("src")
+                    .map(|src| source(kind, resolve(src), None))
+                    .into_iter()
+                    .collect();
+                sources.extend(
+                    find_elements(element.inner, |e| e.name == "source")
+                        .iter()
+                        .filter_map(|s| {
+                            Some(source(
+                                kind,
+                                resolve(s.attribute("src")?),
+                                s.attribute("type"),
+                            ))
+                        }),
+                );
+                MediaElement {
+                    kind,
+                    href: self.href.to_string(),
+                    id: element.attribute("id").map(str::to_string),
+                    sources,
+                    poster: element.attribute("poster").map(resolve),
+                    fallback: html_to_text(element.inner).trim().replace('\n', " "),
+                    controls: element.attribute("controls").is_some(),
+                    autoplay: element.attribute("autoplay").is_some(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A source with its media type from `declared`, or guessed from `src`
+fn source(kind: MediaKind, src: String, declared: Option<&str>) -> MediaSource {
+    let mut parameters = declared.unwrap_or("").split(';');
+    let media_type = parameters
+        .next()
+        .map(|t| t.trim().to_ascii_lowercase())
+        .filter(|t| !t.is_empty())
+        .or_else(|| guess_media_type(kind, &src).map(str::to_string));
+    let codecs = parameters.find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        (name.trim() == "codecs").then(|| value.trim().trim_matches(['"', '\'']).to_string())
+    });
+    MediaSource {
+        src,
+        media_type,
+        codecs,
+    }
+}
+
+fn guess_media_type(kind: MediaKind, src: &str) -> Option<&'static str> {
+    let path = href::strip_fragment(src);
+    let extension = path.rsplit('.').next()?.to_ascii_lowercase();
+    let video = kind == MediaKind::Video;
+    match extension.as_str() {
+        "mp4" | "m4v" if video => Some("video/mp4"),
+        "webm" if video => Some("video/webm"),
+        "ogg" | "ogv" if video => Some("video/ogg"),
+        "mov" => Some("video/quicktime"),
+        "webm" => Some("audio/webm"),
+        "ogg" | "oga" | "opus" => Some("audio/ogg"),
+        "wav" => Some("audio/wav"),
+        "aac" => Some("audio/aac"),
+        _ => match href::media_type_for(path) {
+            "application/octet-stream" => None,
+            media_type => Some(media_type),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn audio_and_video_elements_are_listed() {
+        let opf = package(
+            r#"<item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html><body>
+<audio id="song" src="../audio/song.mp3" controls="controls">Your reader can't play audio.</audio>
+<video poster="../images/still.jpg" autoplay="">
+  <source src="../video/clip.webm" type='video/webm; codecs="vp9"'/>
+  <source src="https://cdn.example/clip.mov"/>
+  <p>See the <a href="clip.xhtml">transcript</a>.</p>
+</video>
+<video src="../video/old.avi"></video>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("text/ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let media = epub.get_chapters()[0].get_media_elements();
+        assert_eq!(media.len(), 3);
+        let audio = &media[0];
+        assert_eq!(audio.get_kind(), MediaKind::Audio);
+        assert_eq!(audio.get_sources()[0].get_src(), "audio/song.mp3");
+        assert_eq!(audio.get_sources()[0].get_media_type(), Some("audio/mpeg"));
+        assert_eq!(audio.get_fallback(), "Your reader can't play audio.");
+        assert!(audio.has_controls() && !audio.is_autoplay());
+
+        let video = &media[1];
+        assert_eq!(video.get_poster(), Some("images/still.jpg"));
+        assert_eq!(video.get_sources()[0].get_codecs(), Some("vp9"));
+        assert!(video.get_sources()[1].is_remote());
+        assert_eq!(video.get_fallback(), "See the transcript.");
+        assert!(video.is_autoplay() && !video.is_unsupported());
+        assert!(media[2].is_unsupported());
+    }
+}
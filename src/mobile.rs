@@ -0,0 +1,196 @@
+//! UniFFI bindings for Swift and Kotlin
+//!
+//! Enabled with the `uniffi` feature. The interface is declared with UniFFI's
+//! proc-macros, so the Swift/Kotlin sources are generated straight from the
+//! compiled library:
+//!
+//! ```bash
+//! cargo build --release --features uniffi
+//! cargo run --features uniffi --bin uniffi-bindgen -- generate \
+//!     --library target/release/libepubie_lib.so --language swift --out-dir bindings/swift
+//! ```
+
+use crate::epub::{Epub, EpubFile};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// Errors surfaced to Swift/Kotlin callers
+#[derive(Debug, uniffi::Error)]
+pub enum BookError {
+    /// The file could not be read
+    Io { message: String },
+    /// The file was read but is not a valid EPUB
+    Parse { message: String },
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::Io { message } => write!(f, "I/O error: {}", message),
+            BookError::Parse { message } => write!(f, "parse error: {}", message),
+        }
+    }
+}
+
+impl Error for BookError {}
+
+impl From<Box<dyn Error>> for BookError {
+    fn from(e: Box<dyn Error>) -> Self {
+        if e.is::<std::io::Error>() {
+            BookError::Io {
+                message: e.to_string(),
+            }
+        } else {
+            BookError::Parse {
+                message: e.to_string(),
+            }
+        }
+    }
+}
+
+/// Book metadata from the OPF package document
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct BookMetadata {
+    pub title: Option<String>,
+    pub creators: Vec<String>,
+    pub language: Option<String>,
+    pub identifier: String,
+    pub date: Option<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub rights: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// A table of contents entry
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct TocItem {
+    pub title: String,
+    pub href: String,
+    pub level: u32,
+}
+
+/// A chapter and the hrefs of the documents it spans
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct ChapterInfo {
+    pub title: String,
+    pub hrefs: Vec<String>,
+}
+
+/// A position in the book expressed as a chapter/file pair
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct ChapterLocation {
+    pub chapter_index: u32,
+    pub file_index: u32,
+    pub href: String,
+}
+
+/// A parsed EPUB book, shared by reference with the host language
+#[derive(uniffi::Object)]
+pub struct Book {
+    inner: Epub,
+}
+
+#[uniffi::export]
+impl Book {
+    /// Open and parse the EPUB file at `path`
+    #[uniffi::constructor]
+    pub fn open(path: String) -> Result<Arc<Self>, BookError> {
+        Ok(Arc::new(Book {
+            inner: Epub::new(path)?,
+        }))
+    }
+
+    pub fn metadata(&self) -> BookMetadata {
+        let metadata = self.inner.get_metadata();
+        BookMetadata {
+            title: metadata.get_title().map(str::to_string),
+            creators: metadata.get_creators().to_vec(),
+            language: metadata.get_language().map(str::to_string),
+            identifier: metadata.get_identifier().to_string(),
+            date: metadata.get_date().map(str::to_string),
+            publisher: metadata.get_publisher().map(str::to_string),
+            description: metadata.get_description().map(str::to_string),
+            rights: metadata.get_rights().map(str::to_string),
+            tags: metadata.get_tags().to_vec(),
+        }
+    }
+
+    pub fn table_of_contents(&self) -> Vec<TocItem> {
+        self.inner
+            .get_table_of_contents()
+            .get_entries()
+            .iter()
+            .map(|entry| TocItem {
+                title: entry.get_title().to_string(),
+                href: entry.get_href().to_string(),
+                level: entry.get_level() as u32,
+            })
+            .collect()
+    }
+
+    pub fn chapters(&self) -> Vec<ChapterInfo> {
+        self.inner
+            .get_chapters()
+            .iter()
+            .map(|chapter| ChapterInfo {
+                title: chapter.get_title().to_string(),
+                hrefs: chapter
+                    .get_files()
+                    .iter()
+                    .map(|file| file.get_href().to_string())
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Find which chapter contains `href`; any `#fragment` is ignored
+    pub fn locate(&self, href: String) -> Option<ChapterLocation> {
+        let path = href.split('#').next().unwrap_or_default();
+
+        self.inner
+            .get_chapters()
+            .iter()
+            .enumerate()
+            .find_map(|(chapter_index, chapter)| {
+                chapter
+                    .get_files()
+                    .iter()
+                    .position(|file| file.get_href() == path)
+                    .map(|file_index| ChapterLocation {
+                        chapter_index: chapter_index as u32,
+                        file_index: file_index as u32,
+                        href: path.to_string(),
+                    })
+            })
+    }
+
+    /// Raw XHTML of the document at `href`
+    pub fn content(&self, href: String) -> Option<String> {
+        self.find_file(&href)
+            .map(|file| file.get_content().to_string())
+    }
+
+    /// Plain text of the document at `href`
+    pub fn text(&self, href: String) -> Option<String> {
+        self.find_file(&href).map(|file| file.get_text())
+    }
+
+    pub fn cover_bytes(&self) -> Option<Vec<u8>> {
+        self.inner.get_cover_bytes()
+    }
+
+    pub fn resource_bytes(&self, href: String) -> Option<Vec<u8>> {
+        self.inner.get_resource_bytes(&href)
+    }
+}
+
+impl Book {
+    fn find_file(&self, href: &str) -> Option<&EpubFile> {
+        self.inner
+            .get_all_files()
+            .iter()
+            .find(|file| file.get_href() == href)
+    }
+}
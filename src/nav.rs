@@ -0,0 +1,513 @@
+//! Writing navigation documents
+//!
+//! `NavDocument` turns a `TableOfContents` (plus optional landmarks and page
+//! list) into an EPUB 3 `nav.xhtml`, and into an NCX for EPUB 2 reading
+//! systems. Hrefs are written exactly as given, so they must be relative to
+//! wherever the document will be stored; OPF-relative hrefs such as those in
+//! a parsed `TableOfContents` work unchanged when the nav sits next to the OPF.
+
+use crate::epub::{Epub, Resource, TableOfContents, TocEntry};
+use crate::href;
+use crate::semantics::Landmark;
+use crate::text::escape_xml;
+use crate::xhtml;
+use std::fmt::Write;
+
+/// A printed page boundary, for the `page-list` nav
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageTarget {
+    label: String,
+    href: String,
+}
+
+impl PageTarget {
+    pub fn new(label: impl Into<String>, href: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            href: href.into(),
+        }
+    }
+
+    /// The page number or name as printed, e.g. `"xii"` or `"42"`
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+}
+
+/// Navigation for a book, ready to be serialized
+#[derive(Debug, Clone)]
+pub struct NavDocument {
+    title: String,
+    language: Option<String>,
+    toc: TableOfContents,
+    landmarks: Vec<Landmark>,
+    page_list: Vec<PageTarget>,
+}
+
+impl NavDocument {
+    /// Create a navigation document for a book with the given title
+    pub fn new(title: impl Into<String>, toc: TableOfContents) -> Self {
+        Self {
+            title: title.into(),
+            language: None,
+            toc,
+            landmarks: Vec::new(),
+            page_list: Vec::new(),
+        }
+    }
+
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    pub fn with_landmarks(mut self, landmarks: Vec<Landmark>) -> Self {
+        self.landmarks = landmarks;
+        self
+    }
+
+    pub fn with_page_list(mut self, page_list: Vec<PageTarget>) -> Self {
+        self.page_list = page_list;
+        self
+    }
+
+    pub fn get_toc(&self) -> &TableOfContents {
+        &self.toc
+    }
+
+    pub fn get_landmarks(&self) -> &[Landmark] {
+        &self.landmarks
+    }
+
+    pub fn get_page_list(&self) -> &[PageTarget] {
+        &self.page_list
+    }
+
+    /// Serialize as an EPUB 3 navigation document
+    ///
+    /// The `landmarks` and `page-list` navs are only written when non-empty.
+    pub fn to_xhtml(&self) -> String {
+        let mut out = String::new();
+        let lang = self
+            .language
+            .as_deref()
+            .map(|lang| {
+                let lang = escape_xml(lang);
+                format!(" xml:lang=\"{}\" lang=\"{}\"", lang, lang)
+            })
+            .unwrap_or_default();
+
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n");
+        let _ = writeln!(
+            out,
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\"{}>",
+            lang
+        );
+        let _ = writeln!(
+            out,
+            "<head>\n  <meta charset=\"utf-8\"/>\n  <title>{}</title>\n</head>\n<body>",
+            escape_xml(&self.title)
+        );
+
+        out.push_str("  <nav epub:type=\"toc\" id=\"toc\">\n");
+        let _ = writeln!(out, "    <h1>{}</h1>", escape_xml(&self.title));
+        write_xhtml_list(&mut out, &build_tree(self.toc.get_entries()), 2);
+        out.push_str("  </nav>\n");
+
+        if !self.landmarks.is_empty() {
+            out.push_str("  <nav epub:type=\"landmarks\" id=\"landmarks\" hidden=\"\">\n");
+            out.push_str("    <ol>\n");
+            for landmark in &self.landmarks {
+                let _ = writeln!(
+                    out,
+                    "      <li><a epub:type=\"{}\" href=\"{}\">{}</a></li>",
+                    escape_xml(landmark.get_kind()),
+                    escape_xml(landmark.get_href()),
+                    escape_xml(&landmark_label(landmark.get_kind()))
+                );
+            }
+            out.push_str("    </ol>\n  </nav>\n");
+        }
+
+        if !self.page_list.is_empty() {
+            out.push_str("  <nav epub:type=\"page-list\" id=\"page-list\" hidden=\"\">\n");
+            out.push_str("    <ol>\n");
+            for page in &self.page_list {
+                let _ = writeln!(
+                    out,
+                    "      <li><a href=\"{}\">{}</a></li>",
+                    escape_xml(&page.href),
+                    escape_xml(&page.label)
+                );
+            }
+            out.push_str("    </ol>\n  </nav>\n");
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Serialize as an EPUB 2 NCX, for reading systems that predate nav documents
+    ///
+    /// `identifier` must match the package's unique identifier.
+    pub fn to_ncx(&self, identifier: &str) -> String {
+        let tree = build_tree(self.toc.get_entries());
+        let mut out = String::new();
+
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(
+            "<ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n<head>\n",
+        );
+        let _ = writeln!(
+            out,
+            "  <meta name=\"dtb:uid\" content=\"{}\"/>",
+            escape_xml(identifier)
+        );
+        let _ = writeln!(
+            out,
+            "  <meta name=\"dtb:depth\" content=\"{}\"/>",
+            tree_depth(&tree).max(1)
+        );
+        out.push_str("  <meta name=\"dtb:totalPageCount\" content=\"0\"/>\n");
+        out.push_str("  <meta name=\"dtb:maxPageNumber\" content=\"0\"/>\n</head>\n");
+        let _ = writeln!(
+            out,
+            "<docTitle><text>{}</text></docTitle>",
+            escape_xml(&self.title)
+        );
+
+        out.push_str("<navMap>\n");
+        let mut play_order = 0;
+        write_nav_points(&mut out, &tree, 1, &mut play_order);
+        out.push_str("</navMap>\n");
+
+        if !self.page_list.is_empty() {
+            out.push_str("<pageList>\n");
+            for (index, page) in self.page_list.iter().enumerate() {
+                play_order += 1;
+                let _ = writeln!(
+                    out,
+                    "  <pageTarget id=\"page-{}\" type=\"normal\" value=\"{}\" playOrder=\"{}\">\
+                     <navLabel><text>{}</text></navLabel><content src=\"{}\"/></pageTarget>",
+                    index + 1,
+                    index + 1,
+                    play_order,
+                    escape_xml(&page.label),
+                    escape_xml(&page.href)
+                );
+            }
+            out.push_str("</pageList>\n");
+        }
+
+        out.push_str("</ncx>\n");
+        out
+    }
+}
+
+impl Epub {
+    /// Replace the table of contents with one built from the headings, and
+    /// write it to the nav document and NCX
+    ///
+    /// See `generate_toc_from_headings`. With `assign_ids`, headings that
+    /// have no `id` get one (`heading-1`, `heading-2`, ...) so their entries
+    /// point at the heading rather than the start of its document. A book
+    /// with neither a nav document nor an NCX only gets the in-memory table.
+    pub fn rebuild_toc_from_headings(&mut self, max_level: usize, assign_ids: bool) {
+        if assign_ids {
+            let documents: Vec<(String, String)> = self
+                .get_spine_files()
+                .into_iter()
+                .filter_map(|file| {
+                    let mut n = 0;
+                    let html = crate::text::assign_heading_ids(&file.content, max_level, || {
+                        loop {
+                            n += 1;
+                            let id = format!("heading-{}", n);
+                            if xhtml::find_id(&file.content, &id).is_none() {
+                                return id;
+                            }
+                        }
+                    })?;
+                    Some((file.href.to_string(), html))
+                })
+                .collect();
+            for (href, html) in documents {
+                self.put_resource_bytes(&href, html.into_bytes());
+            }
+        }
+        self.table_of_contents = self.generate_toc_from_headings(max_level);
+        self.regenerate_navigation();
+    }
+
+    /// Rewrite the nav document and NCX from the current table of contents,
+    /// landmarks and page list
+    ///
+    /// For books whose content was cut down: entries pointing at documents
+    /// or fragments that no longer exist are dropped first.
+    pub(crate) fn regenerate_navigation(&mut self) {
+        let pages: Vec<PageTarget> = self
+            .page_list()
+            .into_iter()
+            .filter(|page| self.has_target(&page.href))
+            .collect();
+        let mut entries = std::mem::take(&mut self.table_of_contents.entries);
+        entries.retain(|entry| self.has_target(&entry.href));
+        self.table_of_contents.entries = entries;
+        let mut landmarks = std::mem::take(&mut self.landmarks);
+        landmarks.retain(|landmark| self.has_target(&landmark.href));
+        self.landmarks = landmarks;
+
+        let documents: Vec<(String, bool)> = self
+            .resources
+            .iter()
+            .filter(|resource| is_navigation(resource))
+            .map(|resource| (resource.href.clone(), resource.has_property("nav")))
+            .collect();
+        for (nav_href, is_nav) in documents {
+            let nav_path = Self::resolve_path(&self.opf_path, &nav_href);
+            let rebase = |href: &str| Self::rebase_href(&self.opf_path, &nav_path, href);
+            let mut toc = TableOfContents::new();
+            for entry in self.table_of_contents.get_entries() {
+                toc.add_entry(entry.title.clone(), rebase(&entry.href), entry.level);
+            }
+            let landmarks = self
+                .landmarks
+                .iter()
+                .map(|landmark| Landmark::new(landmark.kind.as_str(), rebase(&landmark.href)))
+                .collect();
+            let page_list = pages
+                .iter()
+                .map(|page| PageTarget::new(page.get_label(), rebase(page.get_href())))
+                .collect();
+            let mut nav = NavDocument::new(self.get_title().unwrap_or_default(), toc)
+                .with_landmarks(landmarks)
+                .with_page_list(page_list);
+            if let Some(language) = self.get_language() {
+                nav = nav.with_language(language);
+            }
+            let document = if is_nav {
+                nav.to_xhtml()
+            } else {
+                nav.to_ncx(self.get_identifier())
+            };
+            self.put_resource_bytes(&nav_href, document.into_bytes());
+        }
+    }
+
+    /// Whether an OPF-relative href names a resource, and an element in it
+    /// when it has a fragment
+    pub(crate) fn has_target(&self, reference: &str) -> bool {
+        let Some(resource) = self.get_resource_by_href(href::strip_fragment(reference)) else {
+            return false;
+        };
+        match href::fragment(reference) {
+            Some(id) => self
+                .get_resource_bytes(&resource.href)
+                .is_none_or(|bytes| xhtml::find_id(&String::from_utf8_lossy(&bytes), id).is_some()),
+            None => true,
+        }
+    }
+}
+
+/// Whether a manifest item is the nav document or the NCX
+pub(crate) fn is_navigation(resource: &Resource) -> bool {
+    resource.has_property("nav") || resource.media_type == "application/x-dtbncx+xml"
+}
+
+/// A TOC entry with the entries nested below it
+struct Node<'a> {
+    entry: &'a TocEntry,
+    children: Vec<Node<'a>>,
+}
+
+/// Nest a flat, level-annotated entry list. A level deeper than its parent
+/// allows is clamped to one below the parent.
+fn build_tree(entries: &[TocEntry]) -> Vec<Node<'_>> {
+    fn insert<'a>(nodes: &mut Vec<Node<'a>>, entry: &'a TocEntry, depth: usize) {
+        match nodes.last_mut() {
+            Some(last) if depth > 0 => insert(&mut last.children, entry, depth - 1),
+            _ => nodes.push(Node {
+                entry,
+                children: Vec::new(),
+            }),
+        }
+    }
+
+    let mut roots = Vec::new();
+    for entry in entries {
+        insert(&mut roots, entry, entry.get_level());
+    }
+    roots
+}
+
+fn tree_depth(nodes: &[Node<'_>]) -> usize {
+    nodes
+        .iter()
+        .map(|node| 1 + tree_depth(&node.children))
+        .max()
+        .unwrap_or(0)
+}
+
+fn write_xhtml_list(out: &mut String, nodes: &[Node<'_>], indent: usize) {
+    let pad = "  ".repeat(indent);
+    let _ = writeln!(out, "{}<ol>", pad);
+    for node in nodes {
+        let link = format!(
+            "<a href=\"{}\">{}</a>",
+            escape_xml(node.entry.get_href()),
+            escape_xml(node.entry.get_title())
+        );
+        if node.children.is_empty() {
+            let _ = writeln!(out, "{}  <li>{}</li>", pad, link);
+        } else {
+            let _ = writeln!(out, "{}  <li>{}", pad, link);
+            write_xhtml_list(out, &node.children, indent + 2);
+            let _ = writeln!(out, "{}  </li>", pad);
+        }
+    }
+    let _ = writeln!(out, "{}</ol>", pad);
+}
+
+fn write_nav_points(out: &mut String, nodes: &[Node<'_>], indent: usize, play_order: &mut usize) {
+    let pad = "  ".repeat(indent);
+    for node in nodes {
+        *play_order += 1;
+        let _ = writeln!(
+            out,
+            "{}<navPoint id=\"navPoint-{}\" playOrder=\"{}\">",
+            pad, play_order, play_order
+        );
+        let _ = writeln!(
+            out,
+            "{}  <navLabel><text>{}</text></navLabel>",
+            pad,
+            escape_xml(node.entry.get_title())
+        );
+        let _ = writeln!(
+            out,
+            "{}  <content src=\"{}\"/>",
+            pad,
+            escape_xml(node.entry.get_href())
+        );
+        write_nav_points(out, &node.children, indent + 1, play_order);
+        let _ = writeln!(out, "{}</navPoint>", pad);
+    }
+}
+
+/// Human-readable link text for a landmark type
+fn landmark_label(kind: &str) -> String {
+    match kind {
+        "cover" => "Cover".to_string(),
+        "titlepage" => "Title Page".to_string(),
+        "toc" => "Table of Contents".to_string(),
+        "bodymatter" | "text" => "Start of Content".to_string(),
+        "copyright-page" => "Copyright".to_string(),
+        other => {
+            let mut label = other.replace('-', " ");
+            if let Some(first) = label.get_mut(..1) {
+                first.make_ascii_uppercase();
+            }
+            label
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_toc() -> TableOfContents {
+        let mut toc = TableOfContents::new();
+        toc.add_entry("Part One".to_string(), "part1.xhtml".to_string(), 0);
+        toc.add_entry("Loomings".to_string(), "ch1.xhtml".to_string(), 1);
+        toc.add_entry("Fish & Chips".to_string(), "ch2.xhtml#s1".to_string(), 3);
+        toc.add_entry("Part Two".to_string(), "part2.xhtml".to_string(), 0);
+        toc
+    }
+
+    #[test]
+    fn xhtml_nests_entries_and_escapes() {
+        let nav = NavDocument::new("Moby Dick", sample_toc())
+            .with_language("en")
+            .with_landmarks(vec![Landmark::new("bodymatter", "ch1.xhtml")])
+            .with_page_list(vec![PageTarget::new("1", "ch1.xhtml#p1")]);
+        let xhtml = nav.to_xhtml();
+
+        assert!(xhtml.contains("<a href=\"ch2.xhtml#s1\">Fish &amp; Chips</a>"));
+        assert_eq!(xhtml.matches("<ol>").count(), 5);
+        assert!(xhtml.contains("epub:type=\"bodymatter\" href=\"ch1.xhtml\""));
+        assert!(xhtml.contains("epub:type=\"page-list\""));
+
+        // The written document reads back with the same titles
+        let titles = crate::epub::extract_nav_titles(&xhtml);
+        assert_eq!(
+            titles[0],
+            ("part1.xhtml".to_string(), "Part One".to_string())
+        );
+    }
+
+    #[test]
+    fn ncx_has_play_order_and_depth() {
+        let ncx = NavDocument::new("Moby Dick", sample_toc()).to_ncx("urn:uuid:1");
+        assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"3\"/>"));
+        assert!(ncx.contains("playOrder=\"4\""));
+        assert!(!ncx.contains("pageList"));
+    }
+
+    #[test]
+    fn toc_rebuilt_from_headings_is_written_out() {
+        use crate::test_support::{CONTAINER, build, package};
+
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let empty = NavDocument::new("T", TableOfContents::new());
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", &empty.to_xhtml()),
+            ("toc.ncx", &empty.to_ncx("id")),
+            (
+                "ch1.xhtml",
+                r#"<html><body><h1>One</h1><p id="heading-1">x</p><h2 id="s">Sea</h2></body></html>"#,
+            ),
+            ("ch2.xhtml", "<html><body><h1>Two</h1></body></html>"),
+        ]))
+        .unwrap();
+
+        epub.rebuild_toc_from_headings(2, true);
+        let entries: Vec<_> = epub
+            .get_table_of_contents()
+            .get_entries()
+            .iter()
+            .map(|entry| (entry.get_title(), entry.get_href(), entry.get_level()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("One", "ch1.xhtml#heading-2", 0),
+                ("Sea", "ch1.xhtml#s", 1),
+                ("Two", "ch2.xhtml#heading-1", 0),
+            ]
+        );
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        let read = |href| String::from_utf8(saved.get_resource_bytes(href).unwrap()).unwrap();
+        assert!(read("ch1.xhtml").contains(r#"<h1 id="heading-2">One</h1>"#));
+        let titles = crate::epub::extract_nav_titles(&read("nav.xhtml"));
+        assert_eq!(
+            titles[0],
+            ("ch1.xhtml#heading-2".to_string(), "One".to_string())
+        );
+        assert_eq!(titles.len(), 3);
+        assert!(read("toc.ncx").contains(r#"<content src="ch2.xhtml#heading-1"/>"#));
+    }
+}
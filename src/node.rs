@@ -0,0 +1,238 @@
+//! Node.js native bindings
+//!
+//! Enabled with the `node` feature and built with `@napi-rs/cli`. The native
+//! addon reads books straight from disk, so large files are never copied into
+//! a JavaScript or WASM memory buffer:
+//!
+//! ```js
+//! const { Epub } = require('epubie')
+//!
+//! const book = new Epub('book.epub')
+//! console.log(book.title, book.creators)
+//! for (const chapter of book.chapters) {
+//!   console.log(chapter.title, chapter.text().length)
+//! }
+//! ```
+
+use crate::epub::{Chapter, Epub, EpubFile};
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+use std::error::Error;
+
+fn to_napi_err(e: Box<dyn Error>) -> napi::Error {
+    napi::Error::from_reason(e.to_string())
+}
+
+/// A parsed EPUB book
+#[napi(js_name = "Epub")]
+pub struct JsEpub {
+    inner: Epub,
+}
+
+#[napi]
+impl JsEpub {
+    #[napi(constructor)]
+    pub fn new(path: String) -> napi::Result<Self> {
+        Epub::new(path)
+            .map(|inner| JsEpub { inner })
+            .map_err(to_napi_err)
+    }
+
+    #[napi(getter)]
+    pub fn title(&self) -> Option<String> {
+        self.inner.get_title().map(str::to_string)
+    }
+
+    #[napi(getter)]
+    pub fn creators(&self) -> Vec<String> {
+        self.inner.get_creators().to_vec()
+    }
+
+    #[napi(getter)]
+    pub fn language(&self) -> Option<String> {
+        self.inner.get_language().map(str::to_string)
+    }
+
+    #[napi(getter)]
+    pub fn identifier(&self) -> String {
+        self.inner.get_identifier().to_string()
+    }
+
+    #[napi(getter)]
+    pub fn metadata(&self) -> JsMetadata {
+        let metadata = self.inner.get_metadata();
+        JsMetadata {
+            title: metadata.get_title().map(str::to_string),
+            creators: metadata.get_creators().to_vec(),
+            language: metadata.get_language().map(str::to_string),
+            identifier: metadata.get_identifier().to_string(),
+            date: metadata.get_date().map(str::to_string),
+            publisher: metadata.get_publisher().map(str::to_string),
+            description: metadata.get_description().map(str::to_string),
+            rights: metadata.get_rights().map(str::to_string),
+            cover: metadata.get_cover().map(str::to_string),
+            tags: metadata.get_tags().to_vec(),
+        }
+    }
+
+    #[napi(getter)]
+    pub fn chapters(&self) -> Vec<JsChapter> {
+        self.inner
+            .get_chapters()
+            .iter()
+            .map(|chapter| JsChapter {
+                inner: chapter.clone(),
+            })
+            .collect()
+    }
+
+    #[napi(getter)]
+    pub fn files(&self) -> Vec<JsEpubFile> {
+        self.inner
+            .get_all_files()
+            .iter()
+            .map(|file| JsEpubFile {
+                inner: file.clone(),
+            })
+            .collect()
+    }
+
+    /// Cover image bytes, or `null` if the book has no cover
+    #[napi]
+    pub fn cover_bytes(&self) -> Option<Buffer> {
+        self.inner.get_cover_bytes().map(Buffer::from)
+    }
+
+    /// Raw bytes of any resource by its href, or `null` if it does not exist
+    #[napi]
+    pub fn resource_bytes(&self, href: String) -> Option<Buffer> {
+        self.inner.get_resource_bytes(&href).map(Buffer::from)
+    }
+
+    /// Plain text of the whole book, chapters separated by blank lines
+    #[napi]
+    pub fn text(&self) -> String {
+        self.inner
+            .get_chapters()
+            .iter()
+            .map(Chapter::get_text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Case-insensitive full-text search
+    #[napi]
+    pub fn search(&self, query: String) -> Vec<JsSearchMatch> {
+        self.inner
+            .search(&query)
+            .into_iter()
+            .map(|m| JsSearchMatch {
+                chapter_index: m.get_chapter_index() as u32,
+                href: m.get_href().to_string(),
+                snippet: m.get_snippet().to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Book metadata from the OPF package document
+#[napi(object, js_name = "Metadata")]
+pub struct JsMetadata {
+    pub title: Option<String>,
+    pub creators: Vec<String>,
+    pub language: Option<String>,
+    pub identifier: String,
+    pub date: Option<String>,
+    pub publisher: Option<String>,
+    pub description: Option<String>,
+    pub rights: Option<String>,
+    pub cover: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// A single search hit
+#[napi(object, js_name = "SearchMatch")]
+pub struct JsSearchMatch {
+    pub chapter_index: u32,
+    pub href: String,
+    pub snippet: String,
+}
+
+/// A chapter made of one or more content documents
+#[napi(js_name = "Chapter")]
+pub struct JsChapter {
+    inner: Chapter,
+}
+
+#[napi]
+impl JsChapter {
+    #[napi(getter)]
+    pub fn title(&self) -> String {
+        self.inner.get_title().to_string()
+    }
+
+    #[napi(getter)]
+    pub fn files(&self) -> Vec<JsEpubFile> {
+        self.inner
+            .get_files()
+            .iter()
+            .map(|file| JsEpubFile {
+                inner: file.clone(),
+            })
+            .collect()
+    }
+
+    /// Plain text of every file in the chapter
+    #[napi]
+    pub fn text(&self) -> String {
+        self.inner.get_text()
+    }
+}
+
+/// A single XHTML content document
+#[napi(js_name = "EpubFile")]
+pub struct JsEpubFile {
+    inner: EpubFile,
+}
+
+#[napi]
+impl JsEpubFile {
+    #[napi(getter)]
+    pub fn id(&self) -> String {
+        self.inner.get_id().to_string()
+    }
+
+    #[napi(getter)]
+    pub fn href(&self) -> String {
+        self.inner.get_href().to_string()
+    }
+
+    #[napi(getter)]
+    pub fn title(&self) -> Option<String> {
+        self.inner.get_title().map(str::to_string)
+    }
+
+    #[napi(getter)]
+    pub fn media_type(&self) -> String {
+        self.inner.get_media_type().to_string()
+    }
+
+    /// Raw XHTML source
+    #[napi(getter)]
+    pub fn content(&self) -> String {
+        self.inner.get_content().to_string()
+    }
+
+    /// Plain text with markup stripped
+    #[napi]
+    pub fn text(&self) -> String {
+        self.inner.get_text()
+    }
+}
+
+/// Convert an XHTML string to plain text
+#[napi]
+#[cfg_attr(test, allow(dead_code))] // only referenced through napi registration
+pub fn html_to_text(html: String) -> String {
+    crate::text::html_to_text(&html)
+}
@@ -0,0 +1,309 @@
+//! Resources a book loads from the network
+//!
+//! EPUB 3 lets content documents use fonts, images, audio and video hosted
+//! elsewhere, as long as the manifest item of each such document carries
+//! the `remote-resources` property. A book like that breaks offline and
+//! when the host goes away. `Epub::remote_resources` lists every absolute
+//! `http(s)` URL the book loads (hyperlinks don't count), which documents
+//! load it, and whether those documents declare `remote-resources`.
+//!
+//! `Epub::bundle_remote_resources` makes the book self-contained. The
+//! library does no networking itself: the caller passes a fetcher, and each
+//! downloaded resource becomes a manifest item under `remote/`, with every
+//! reference rewritten to it and `remote-resources` dropped from documents
+//! that no longer need it.
+
+use crate::epub::Epub;
+use crate::href;
+use crate::refs::{self, REFERENCING_MEDIA_TYPES};
+use std::error::Error;
+
+/// A URL the book loads, from `Epub::remote_resources`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteResource {
+    url: String,
+    referrers: Vec<String>,
+    media_type: Option<String>,
+    declared: bool,
+}
+
+impl RemoteResource {
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    /// Hrefs (relative to the OPF) of the documents that load it
+    pub fn get_referrers(&self) -> &[String] {
+        &self.referrers
+    }
+
+    /// The media type, when the resource is also listed in the manifest
+    pub fn get_media_type(&self) -> Option<&str> {
+        self.media_type.as_deref()
+    }
+
+    /// Whether every referring document has the `remote-resources` property
+    pub fn is_declared(&self) -> bool {
+        self.declared
+    }
+}
+
+/// What `Epub::bundle_remote_resources` did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BundleReport {
+    bundled: Vec<(String, String)>,
+    failed: Vec<(String, String)>,
+}
+
+impl BundleReport {
+    /// `(url, href)` of each resource now in the book
+    pub fn get_bundled(&self) -> &[(String, String)] {
+        &self.bundled
+    }
+
+    /// `(url, error message)` of each resource that couldn't be fetched;
+    /// references to them are left as they were
+    pub fn get_failed(&self) -> &[(String, String)] {
+        &self.failed
+    }
+}
+
+impl Epub {
+    /// Every remote resource the book loads, in manifest order of first use
+    pub fn remote_resources(&self) -> Vec<RemoteResource> {
+        let mut found: Vec<RemoteResource> = Vec::new();
+        let mut add = |url: &str, referrer: Option<(&str, bool)>, media_type: Option<&str>| {
+            let index = match found.iter().position(|resource| resource.url == url) {
+                Some(index) => index,
+                None => {
+                    found.push(RemoteResource {
+                        url: url.to_string(),
+                        referrers: Vec::new(),
+                        media_type: None,
+                        declared: true,
+                    });
+                    found.len() - 1
+                }
+            };
+            let resource = &mut found[index];
+            if let Some((href, declared)) = referrer
+                && !resource.referrers.iter().any(|known| known == href)
+            {
+                resource.referrers.push(href.to_string());
+                resource.declared &= declared;
+            }
+            if let Some(media_type) = media_type {
+                resource.media_type = Some(media_type.to_string());
+            }
+        };
+
+        for resource in &self.resources {
+            if refs::is_remote(&resource.href) {
+                add(resource.href.trim(), None, Some(&resource.media_type));
+            }
+        }
+        for (href, declared, content) in self.referencing_documents() {
+            for (url, _) in refs::find_remote(&content) {
+                add(&url, Some((&href, declared)), None);
+            }
+        }
+        found
+    }
+
+    /// Download every remote resource with `fetch` and store it in the book
+    ///
+    /// `fetch` gets a URL and returns the bytes and media type. Failed
+    /// fetches are reported and skipped; the rest of the book is still
+    /// bundled.
+    pub fn bundle_remote_resources(
+        &mut self,
+        mut fetch: impl FnMut(&str) -> Result<(Vec<u8>, String), Box<dyn Error>>,
+    ) -> BundleReport {
+        enter_span!("bundle_remote_resources");
+        let mut report = BundleReport::default();
+        for remote in self.remote_resources() {
+            let (bytes, media_type) = match fetch(&remote.url) {
+                Ok(fetched) => fetched,
+                Err(e) => {
+                    report.failed.push((remote.url, e.to_string()));
+                    continue;
+                }
+            };
+            let href = self.unique_resource_href(
+                &format!("remote/{}", url_stem(&remote.url)),
+                href::extension_for(&media_type),
+            );
+            match self
+                .resources
+                .iter_mut()
+                .find(|resource| resource.href.trim() == remote.url)
+            {
+                Some(resource) => {
+                    resource.href = href.clone();
+                    resource.media_type = media_type;
+                    self.put_resource_bytes(&href, bytes);
+                }
+                None => {
+                    if let Err(e) = self.add_resource(&href, &media_type, bytes) {
+                        report.failed.push((remote.url, e.to_string()));
+                        continue;
+                    }
+                }
+            }
+            report.bundled.push((remote.url, href));
+        }
+        if !report.bundled.is_empty() {
+            self.relink_bundled(&report.bundled);
+        }
+        report
+    }
+
+    /// Point references at bundled copies and drop `remote-resources` where it's no longer true
+    fn relink_bundled(&mut self, bundled: &[(String, String)]) {
+        for (href, _, mut content) in self.referencing_documents() {
+            let document_path = Self::resolve_path(&self.opf_path, &href);
+            let remotes = refs::find_remote(&content);
+            let mut changed = false;
+            for (url, range) in remotes.iter().rev() {
+                if let Some((_, local)) = bundled.iter().find(|(bundled, _)| bundled == url) {
+                    let path = Self::resolve_path(&self.opf_path, local);
+                    content.replace_range(range.clone(), &href::relative(&document_path, &path));
+                    changed = true;
+                }
+            }
+            if !changed {
+                continue;
+            }
+            let still_remote = !refs::find_remote(&content).is_empty();
+            self.put_resource_bytes(&href, content.into_bytes());
+            if still_remote {
+                continue;
+            }
+            if let Some(resource) = self.resources.iter_mut().find(|r| r.href == href) {
+                let properties: Vec<&str> = resource
+                    .properties
+                    .as_deref()
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .filter(|property| *property != "remote-resources")
+                    .collect();
+                resource.properties = (!properties.is_empty()).then(|| properties.join(" "));
+            }
+        }
+    }
+
+    /// `(href, declares remote-resources, content)` of every document that can load resources
+    fn referencing_documents(&self) -> Vec<(String, bool, String)> {
+        self.resources
+            .iter()
+            .filter(|resource| REFERENCING_MEDIA_TYPES.contains(&resource.media_type.as_str()))
+            .filter(|resource| !refs::is_remote(&resource.href))
+            .filter_map(|resource| {
+                let bytes = self.get_resource_bytes(&resource.href)?;
+                let declared = resource
+                    .properties
+                    .as_deref()
+                    .is_some_and(|p| p.split_whitespace().any(|p| p == "remote-resources"));
+                Some((
+                    resource.href.clone(),
+                    declared,
+                    String::from_utf8_lossy(&bytes).into_owned(),
+                ))
+            })
+            .collect()
+    }
+}
+
+/// A file name stem from the last path segment of a URL
+fn url_stem(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit('/').next().unwrap_or("");
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let stem: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if stem.is_empty() {
+        "resource".to_string()
+    } else {
+        stem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn remote_resources_are_listed_and_bundled() {
+        let opf = package(
+            r#"<item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml" properties="remote-resources"/>
+    <item id="ch2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="font" href="https://fonts.example/serif.woff2" media-type="font/woff2"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let ch1 = r#"<html><head><link rel="stylesheet" href="https://cdn.example/style.css"/></head>
+<body><img src="https://img.example/a.png?v=2"/><a href="https://example.com/">site</a></body></html>"#;
+        let ch2 = r#"<html><body><img src="https://img.example/a.png?v=2"/><img src="https://down.example/b.png"/></body></html>"#;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("text/ch1.xhtml", ch1),
+            ("text/ch2.xhtml", ch2),
+        ]))
+        .unwrap();
+
+        let remote = epub.remote_resources();
+        let urls: Vec<&str> = remote.iter().map(RemoteResource::get_url).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://fonts.example/serif.woff2",
+                "https://cdn.example/style.css",
+                "https://img.example/a.png?v=2",
+                "https://down.example/b.png"
+            ]
+        );
+        assert_eq!(
+            remote[2].get_referrers(),
+            ["text/ch1.xhtml", "text/ch2.xhtml"]
+        );
+        assert!(!remote[2].is_declared());
+        assert!(remote[1].is_declared());
+
+        let report = epub.bundle_remote_resources(|url| {
+            if url.contains("down.example") {
+                return Err("unreachable".into());
+            }
+            let media_type = href::media_type_for(url.split('?').next().unwrap());
+            Ok((url.as_bytes().to_vec(), media_type.to_string()))
+        });
+        assert_eq!(report.get_bundled().len(), 3);
+        assert_eq!(report.get_failed()[0].0, "https://down.example/b.png");
+        assert_eq!(
+            epub.get_resource_bytes("remote/a.png").unwrap(),
+            b"https://img.example/a.png?v=2"
+        );
+        assert!(epub.get_resource_by_href("remote/serif.woff2").is_some());
+
+        let ch1 = String::from_utf8(epub.get_resource_bytes("text/ch1.xhtml").unwrap()).unwrap();
+        assert!(ch1.contains(r#"href="../remote/style.css""#));
+        assert!(ch1.contains(r#"src="../remote/a.png""#));
+        assert!(ch1.contains(r#"<a href="https://example.com/">"#));
+        assert_eq!(
+            epub.get_resource_by_href("text/ch1.xhtml")
+                .unwrap()
+                .get_properties(),
+            None
+        );
+        let remaining: Vec<String> = epub.remote_resources().into_iter().map(|r| r.url).collect();
+        assert_eq!(remaining, ["https://down.example/b.png"]);
+    }
+}
@@ -0,0 +1,371 @@
+//! Offsets into the book's text as a whole
+//!
+//! Search results, locations, highlights and reading progress all locate a
+//! point as a character offset into one spine file's extracted text, and
+//! each needs the lengths of the files before it to place that point in the
+//! book. Extracting every file's text to measure it is the expensive part,
+//! so `Epub::text_index` does it once and keeps the result: the character
+//! and byte span of every spine file in one continuous numbering, with
+//! conversions both ways.
+//!
+//! The index is rebuilt on the next call after an edit. Every editing path
+//! (`put_resource_bytes`, `remove_resource`, `rename_resource`, spine and
+//! metadata changes) moves the book to a new generation, and the index is
+//! kept with the generation it was built for, so checking it costs nothing.
+//!
+//! The same spans give each spine item its `SpineWeight`, together with the
+//! narration time of its media overlay (the `media:duration` the package
+//! declares for it), so anything that divides the book by spine item weighs
+//! the items the way progress and locations do.
+
+use crate::epub::Epub;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How much of the book one spine item is, from `SpineItem::weight`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SpineWeight {
+    chars: usize,
+    duration: Option<Duration>,
+}
+
+impl SpineWeight {
+    /// Characters of extracted text
+    pub fn get_char_count(&self) -> usize {
+        self.chars
+    }
+
+    /// Narration time of the item's media overlay
+    pub fn get_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+}
+
+/// Where one spine file's text sits in the book's text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    href: String,
+    start: usize,
+    chars: usize,
+    byte_start: usize,
+    bytes: usize,
+    duration: Option<Duration>,
+}
+
+impl TextSpan {
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Book-wide character offset of the file's first character
+    pub fn get_start(&self) -> usize {
+        self.start
+    }
+
+    /// Characters of extracted text in the file
+    pub fn get_char_count(&self) -> usize {
+        self.chars
+    }
+
+    /// Book-wide UTF-8 byte offset of the file's text
+    pub fn get_byte_start(&self) -> usize {
+        self.byte_start
+    }
+
+    pub fn get_byte_count(&self) -> usize {
+        self.bytes
+    }
+
+    /// Book-wide character offset just past the file's text
+    pub fn get_end(&self) -> usize {
+        self.start + self.chars
+    }
+
+    /// Narration time of the file's media overlay
+    pub fn get_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    pub fn get_weight(&self) -> SpineWeight {
+        SpineWeight {
+            chars: self.chars,
+            duration: self.duration,
+        }
+    }
+}
+
+/// Text lengths of the spine files, from `Epub::text_index`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextIndex {
+    spans: Vec<TextSpan>,
+}
+
+impl TextIndex {
+    /// One span per spine file, in reading order
+    pub fn get_spans(&self) -> &[TextSpan] {
+        &self.spans
+    }
+
+    pub fn get_span(&self, href: &str) -> Option<&TextSpan> {
+        self.spans.iter().find(|span| span.href == href)
+    }
+
+    pub fn get_total_chars(&self) -> usize {
+        self.spans.last().map_or(0, TextSpan::get_end)
+    }
+
+    pub fn get_total_bytes(&self) -> usize {
+        self.spans
+            .last()
+            .map_or(0, |span| span.byte_start + span.bytes)
+    }
+
+    /// Narration time of the whole book, if any spine file has an overlay
+    pub fn get_total_duration(&self) -> Option<Duration> {
+        self.spans
+            .iter()
+            .filter_map(TextSpan::get_duration)
+            .reduce(|total, duration| total + duration)
+    }
+
+    /// The book-wide offset of character `offset` of the spine file `href`
+    ///
+    /// Offsets past the end of the file are clamped to its end.
+    pub fn to_global(&self, href: &str, offset: usize) -> Option<usize> {
+        let span = self.get_span(href)?;
+        Some(span.start + offset.min(span.chars))
+    }
+
+    /// The spine file and character offset in it of a book-wide offset
+    ///
+    /// An offset on the boundary between two files is the start of the
+    /// later one. Empty files are skipped.
+    pub fn from_global(&self, offset: usize) -> Option<(&str, usize)> {
+        let index = self.spans.partition_point(|span| span.get_end() <= offset);
+        let span = self.spans.get(index)?;
+        Some((&span.href, offset - span.start))
+    }
+
+    /// How far through the book's text a position is, from 0.0 to 1.0
+    pub fn progress(&self, href: &str, offset: usize) -> Option<f64> {
+        let global = self.to_global(href, offset)?;
+        let total = self.get_total_chars();
+        Some(if total == 0 {
+            0.0
+        } else {
+            global as f64 / total as f64
+        })
+    }
+}
+
+/// Generations handed out by `TextIndexCache::invalidate`, unique across
+/// every book in the process so clones that were edited apart never match
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// An index and the generation of the book it was built for
+type GenerationIndex = (u64, Arc<TextIndex>);
+
+/// The last index built, shared by clones of an `Epub`
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TextIndexCache {
+    generation: u64,
+    index: Arc<Mutex<Option<GenerationIndex>>>,
+}
+
+impl TextIndexCache {
+    /// Mark the book as changed, so the next `text_index` rebuilds
+    pub(crate) fn invalidate(&mut self) {
+        self.generation = NEXT_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Epub {
+    /// Character and byte spans of every spine file's extracted text
+    ///
+    /// Built on first use and kept until the book is edited.
+    pub fn text_index(&self) -> Arc<TextIndex> {
+        let generation = self.text_index_cache.generation;
+        let mut cache = self
+            .text_index_cache
+            .index
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some((cached, index)) = cache.as_ref()
+            && *cached == generation
+        {
+            return index.clone();
+        }
+        enter_span!("text_index");
+        let files = self.get_spine_files();
+        let mut spans = Vec::with_capacity(files.len());
+        let (mut start, mut byte_start) = (0, 0);
+        for file in files {
+            let duration = self.overlay_duration(&file.href);
+            let text = file.get_text();
+            let span = TextSpan {
+                href: file.get_href().to_string(),
+                start,
+                chars: text.chars().count(),
+                byte_start,
+                bytes: text.len(),
+                duration,
+            };
+            start = span.get_end();
+            byte_start += span.bytes;
+            spans.push(span);
+        }
+        let index = Arc::new(TextIndex { spans });
+        *cache = Some((generation, index.clone()));
+        index
+    }
+
+    /// How far through the book character `offset` of the spine file `href` is
+    pub fn progress(&self, href: &str, offset: usize) -> Option<f64> {
+        self.text_index().progress(href, offset)
+    }
+
+    /// The `media:duration` declared for the media overlay of `href`
+    fn overlay_duration(&self, href: &str) -> Option<Duration> {
+        let overlay = self.get_resource_by_href(href)?.get_media_overlay()?;
+        self.meta
+            .iter()
+            .find(|meta| {
+                meta.refines.as_deref().and_then(|r| r.strip_prefix('#')) == Some(overlay)
+                    && meta.property.as_deref() == Some("media:duration")
+            })
+            .and_then(|meta| parse_clock_value(meta.value.as_deref()?))
+    }
+}
+
+/// A SMIL clock value: `1:02:03.5`, `02:03.5`, or a count such as `12.5s`,
+/// `1.5h`, `3min` or `250ms` (seconds when there's no unit)
+fn parse_clock_value(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let seconds = if value.contains(':') {
+        let parts: Vec<&str> = value.split(':').collect();
+        if parts.len() > 3 {
+            return None;
+        }
+        let (last, whole) = parts.split_last()?;
+        let mut seconds = last.parse::<f64>().ok()?;
+        for (place, part) in whole.iter().rev().enumerate() {
+            let part: u64 = part.parse().ok()?;
+            seconds += part as f64 * 60f64.powi(place as i32 + 1);
+        }
+        seconds
+    } else {
+        let split = value
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(value.len());
+        let count = value[..split].parse::<f64>().ok()?;
+        count
+            * match &value[split..] {
+                "h" => 3600.0,
+                "min" => 60.0,
+                "s" | "" => 1.0,
+                "ms" => 0.001,
+                _ => return None,
+            }
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn offsets_convert_both_ways_and_follow_edits() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>abcdef</p></body></html>"),
+            ("ch2.xhtml", "<html><body><p>éé</p></body></html>"),
+        ]))
+        .unwrap();
+
+        let index = epub.text_index();
+        assert!(Arc::ptr_eq(&index, &epub.text_index()));
+        assert_eq!(index.get_total_chars(), 8);
+        assert_eq!(index.get_total_bytes(), 10);
+        assert_eq!(index.get_span("ch2.xhtml").unwrap().get_byte_start(), 6);
+        assert_eq!(index.to_global("ch2.xhtml", 1), Some(7));
+        assert_eq!(index.from_global(6), Some(("ch2.xhtml", 0)));
+        assert_eq!(index.from_global(8), None);
+        assert_eq!(epub.progress("ch2.xhtml", 0), Some(0.75));
+
+        let original = epub.clone();
+        epub.put_resource_bytes("ch1.xhtml", b"<html><body><p>ab</p></body></html>".to_vec());
+        assert_eq!(epub.text_index().get_total_chars(), 4);
+        // A clone edited apart doesn't see the other's index
+        assert_eq!(original.text_index().get_total_chars(), 8);
+        assert_eq!(epub.text_index().get_total_chars(), 4);
+    }
+
+    #[test]
+    fn spine_items_carry_text_length_and_overlay_duration() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>T</dc:title><dc:identifier>id</dc:identifier>
+    <meta property="media:duration" refines="#mo1">0:01:30.5</meta>
+    <meta property="media:duration" refines="#mo2">45s</meta>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml" media-overlay="mo1"/>
+    <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+    <item id="mo1" href="ch1.smil" media-type="application/smil+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/><itemref idref="ch2"/></spine>
+</package>"##;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>abcdef</p></body></html>"),
+            ("ch2.xhtml", "<html><body><p>éé</p></body></html>"),
+            ("ch1.smil", "<smil/>"),
+        ]))
+        .unwrap();
+
+        assert_eq!(epub.get_spine_items()[0].weight(), None);
+        let weights: Vec<SpineWeight> = epub
+            .get_weighted_spine_items()
+            .iter()
+            .filter_map(|item| item.weight())
+            .collect();
+        assert_eq!(weights[0].get_char_count(), 6);
+        assert_eq!(
+            weights[0].get_duration(),
+            Some(Duration::from_secs_f64(90.5))
+        );
+        assert_eq!(weights[1].get_char_count(), 2);
+        assert_eq!(weights[1].get_duration(), None);
+        assert_eq!(
+            epub.text_index().get_total_duration(),
+            Some(Duration::from_secs_f64(90.5))
+        );
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            saved.get_weighted_spine_items()[0].weight(),
+            Some(weights[0])
+        );
+    }
+
+    #[test]
+    fn clock_values_parse() {
+        let secs = |s: f64| Some(Duration::from_secs_f64(s));
+        assert_eq!(parse_clock_value("1:02:03.5"), secs(3723.5));
+        assert_eq!(parse_clock_value("02:03"), secs(123.0));
+        assert_eq!(parse_clock_value("1.5h"), secs(5400.0));
+        assert_eq!(parse_clock_value("3min"), secs(180.0));
+        assert_eq!(parse_clock_value("250ms"), secs(0.25));
+        assert_eq!(parse_clock_value("12"), secs(12.0));
+        assert_eq!(parse_clock_value("soon"), None);
+    }
+}
@@ -0,0 +1,400 @@
+//! Knobs that change how an EPUB is parsed and written
+//!
+//! The parse defaults follow the spec strictly. Options relax that for books
+//! produced by sloppy tooling, at the cost of occasionally accepting a file
+//! the spec would reject. Write options trade fidelity to the original
+//! archive for a smaller or more reproducible file. Text options shape the
+//! plain text extracted from content documents.
+
+/// Options passed to `Epub::with_options` and `Epub::from_bytes_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    tolerant_hrefs: bool,
+    heading_titles: bool,
+    metadata_only: bool,
+    #[cfg(feature = "checksums")]
+    verify_checksums: bool,
+}
+
+impl ParseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve hrefs leniently against zip entry names
+    ///
+    /// Backslashes are treated as path separators and, when there is no exact
+    /// match, entries are looked up case-insensitively. Books written by some
+    /// Windows tools need this to load at all.
+    pub fn with_tolerant_hrefs(mut self, tolerant: bool) -> Self {
+        self.tolerant_hrefs = tolerant;
+        self
+    }
+
+    pub fn get_tolerant_hrefs(&self) -> bool {
+        self.tolerant_hrefs
+    }
+
+    /// Title chapters missing from the navigation document after their first heading
+    ///
+    /// Off by default, so such chapters keep the manifest ID titles earlier
+    /// versions gave them. Files with no `<h1>`-`<h3>` or `<title>` fall
+    /// back to their manifest ID either way.
+    pub fn with_heading_titles(mut self, enabled: bool) -> Self {
+        self.heading_titles = enabled;
+        self
+    }
+
+    pub fn get_heading_titles(&self) -> bool {
+        self.heading_titles
+    }
+
+    /// Read only the container and package document, skipping content documents
+    ///
+    /// The book's metadata, manifest and cover are available as usual, but it
+    /// has no files, chapters or table of contents. Resources can still be
+    /// read, and saving the book copies its content unchanged. Library
+    /// scanners use this to index books quickly.
+    pub fn with_metadata_only(mut self, metadata_only: bool) -> Self {
+        self.metadata_only = metadata_only;
+        self
+    }
+
+    pub fn get_metadata_only(&self) -> bool {
+        self.metadata_only
+    }
+
+    /// Check entries against `META-INF/checksums.json` while loading
+    ///
+    /// Mismatches end up in the `ParseReport`; loading carries on regardless.
+    /// Requires the `checksums` feature.
+    #[cfg(feature = "checksums")]
+    pub fn with_checksum_verification(mut self, verify: bool) -> Self {
+        self.verify_checksums = verify;
+        self
+    }
+
+    #[cfg(feature = "checksums")]
+    pub fn get_checksum_verification(&self) -> bool {
+        self.verify_checksums
+    }
+}
+
+/// Options passed to `EpubFile::get_text_with` and `Chapter::get_text_with`
+#[derive(Debug, Clone, Default)]
+pub struct TextOptions {
+    ruby: crate::ruby::RubyText,
+    collapse_lines: bool,
+    strip_soft_hyphens: bool,
+    br_as_space: bool,
+    list_markers: bool,
+}
+
+impl TextOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What to do with ruby readings (furigana); stripped by default
+    pub fn with_ruby(mut self, ruby: crate::ruby::RubyText) -> Self {
+        self.ruby = ruby;
+        self
+    }
+
+    pub fn get_ruby(&self) -> crate::ruby::RubyText {
+        self.ruby
+    }
+
+    /// Join all the text into one line instead of one line per block, as
+    /// search indexes and TTS engines usually want
+    pub fn with_collapse_lines(mut self, collapse: bool) -> Self {
+        self.collapse_lines = collapse;
+        self
+    }
+
+    pub fn get_collapse_lines(&self) -> bool {
+        self.collapse_lines
+    }
+
+    /// Remove soft hyphens (U+00AD), so hyphenation hints don't split words
+    /// for search; they are kept by default, for display
+    pub fn with_strip_soft_hyphens(mut self, strip: bool) -> Self {
+        self.strip_soft_hyphens = strip;
+        self
+    }
+
+    pub fn get_strip_soft_hyphens(&self) -> bool {
+        self.strip_soft_hyphens
+    }
+
+    /// Turn `<br>` into a space instead of a line break, e.g. for poetry
+    /// read aloud as running text
+    pub fn with_br_as_space(mut self, space: bool) -> Self {
+        self.br_as_space = space;
+        self
+    }
+
+    pub fn get_br_as_space(&self) -> bool {
+        self.br_as_space
+    }
+
+    /// Start list items with their bullet (`•`) or number (`1.`), as they
+    /// are displayed
+    pub fn with_list_markers(mut self, markers: bool) -> Self {
+        self.list_markers = markers;
+        self
+    }
+
+    pub fn get_list_markers(&self) -> bool {
+        self.list_markers
+    }
+}
+
+/// Options passed to `Epub::save_with_options` and `Epub::to_bytes_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    deflate_level: Option<u32>,
+    store_precompressed: bool,
+    timestamp: Option<chrono::NaiveDateTime>,
+    deterministic: bool,
+    prune_orphans: bool,
+    scrub_personal_data: bool,
+    externalize_images: bool,
+    typography: Option<crate::typography::TypographyOptions>,
+    dark_mode_patch: Option<crate::darkmode::ColorPatch>,
+    #[cfg(feature = "image")]
+    optimize_images: Option<crate::images::ImageOptions>,
+    #[cfg(feature = "fonts")]
+    subset_fonts: bool,
+    #[cfg(feature = "checksums")]
+    checksums: bool,
+}
+
+impl WriteOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deflate entries at this level, from 1 (fastest) to 9 (smallest)
+    ///
+    /// Setting any compression option re-compresses every entry instead of
+    /// copying the original archive's compressed data.
+    pub fn with_deflate_level(mut self, level: u32) -> Self {
+        self.deflate_level = Some(level.clamp(1, 9));
+        self
+    }
+
+    pub fn get_deflate_level(&self) -> Option<u32> {
+        self.deflate_level
+    }
+
+    /// Store images, audio, video and WOFF fonts without compression
+    ///
+    /// Those formats are compressed already, so deflating them again costs
+    /// time for next to no gain. Text is still deflated, and `mimetype` is
+    /// always stored.
+    pub fn with_store_precompressed(mut self, store: bool) -> Self {
+        self.store_precompressed = store;
+        self
+    }
+
+    pub fn get_store_precompressed(&self) -> bool {
+        self.store_precompressed
+    }
+
+    /// Stamp every entry with this modification time instead of the original
+    /// (or current) one
+    ///
+    /// Also used as the `dcterms:modified` date when the package has none.
+    /// Zip timestamps have two-second resolution and cover 1980 to 2107;
+    /// times outside that range are clamped.
+    pub fn with_timestamp(mut self, timestamp: chrono::NaiveDateTime) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn get_timestamp(&self) -> Option<chrono::NaiveDateTime> {
+        self.timestamp
+    }
+
+    /// Produce byte-for-byte identical output for identical input
+    ///
+    /// Entries after `mimetype`, `container.xml` and the OPF are written in
+    /// path order, every entry gets the same timestamp (the one from
+    /// `with_timestamp`, else 1980-01-01 00:00, the earliest a zip can hold),
+    /// and a missing `dcterms:modified` is filled in with that time instead
+    /// of the current one.
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn get_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// The time to stamp entries with, if it is fixed
+    pub(crate) fn fixed_timestamp(&self) -> Option<chrono::NaiveDateTime> {
+        self.timestamp.or_else(|| {
+            self.deterministic.then(|| {
+                chrono::NaiveDate::from_ymd_opt(1980, 1, 1)
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .unwrap_or_default()
+            })
+        })
+    }
+
+    /// Whether entries must be re-compressed rather than copied as they are
+    pub(crate) fn recompresses(&self) -> bool {
+        self.deflate_level.is_some() || self.store_precompressed
+    }
+
+    /// Leave out manifest items nothing in the book references
+    ///
+    /// See `Epub::find_orphan_resources`. The in-memory book is not changed.
+    pub fn with_prune_orphans(mut self, prune: bool) -> Self {
+        self.prune_orphans = prune;
+        self
+    }
+
+    pub fn get_prune_orphans(&self) -> bool {
+        self.prune_orphans
+    }
+
+    /// Remove watermarks and purchase data as `Epub::scrub_personal_data` would
+    ///
+    /// The in-memory book is not changed; `Epub::to_bytes_with_report`
+    /// returns what was removed.
+    pub fn with_scrub_personal_data(mut self, scrub: bool) -> Self {
+        self.scrub_personal_data = scrub;
+        self
+    }
+
+    pub fn get_scrub_personal_data(&self) -> bool {
+        self.scrub_personal_data
+    }
+
+    /// Move base64 `data:` images into manifest items as
+    /// `Epub::externalize_embedded_images` would
+    ///
+    /// The in-memory book is not changed.
+    pub fn with_externalize_images(mut self, externalize: bool) -> Self {
+        self.externalize_images = externalize;
+        self
+    }
+
+    pub fn get_externalize_images(&self) -> bool {
+        self.externalize_images
+    }
+
+    /// Curl quotes and fix dashes and ellipses as `Epub::fix_typography` would
+    ///
+    /// The in-memory book is not changed.
+    pub fn with_typography(mut self, options: crate::typography::TypographyOptions) -> Self {
+        self.typography = Some(options);
+        self
+    }
+
+    pub fn get_typography(&self) -> Option<&crate::typography::TypographyOptions> {
+        self.typography.as_ref()
+    }
+
+    /// Strip or neutralize colours that break dark themes as
+    /// `Epub::patch_dark_mode` would
+    ///
+    /// The in-memory book is not changed.
+    pub fn with_dark_mode_patch(mut self, patch: crate::darkmode::ColorPatch) -> Self {
+        self.dark_mode_patch = Some(patch);
+        self
+    }
+
+    pub fn get_dark_mode_patch(&self) -> Option<crate::darkmode::ColorPatch> {
+        self.dark_mode_patch
+    }
+
+    /// Recompress images as `Epub::optimize_images` would
+    #[cfg(feature = "image")]
+    pub fn with_image_optimization(mut self, options: crate::images::ImageOptions) -> Self {
+        self.optimize_images = Some(options);
+        self
+    }
+
+    #[cfg(feature = "image")]
+    pub fn get_image_optimization(&self) -> Option<&crate::images::ImageOptions> {
+        self.optimize_images.as_ref()
+    }
+
+    /// Subset embedded fonts as `Epub::subset_fonts` would
+    #[cfg(feature = "fonts")]
+    pub fn with_font_subsetting(mut self, subset: bool) -> Self {
+        self.subset_fonts = subset;
+        self
+    }
+
+    #[cfg(feature = "fonts")]
+    pub fn get_font_subsetting(&self) -> bool {
+        self.subset_fonts
+    }
+
+    /// Add `META-INF/checksums.json` with a SHA-256 digest of every entry
+    ///
+    /// Requires the `checksums` feature. Every entry is read an extra time to
+    /// hash it, so saving gets slower for large books.
+    #[cfg(feature = "checksums")]
+    pub fn with_checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    #[cfg(feature = "checksums")]
+    pub fn get_checksums(&self) -> bool {
+        self.checksums
+    }
+
+    /// Whether writing has to transform a copy of the book first
+    pub(crate) fn transforms_content(&self) -> bool {
+        #[cfg(feature = "image")]
+        if self.optimize_images.is_some() {
+            return true;
+        }
+        #[cfg(feature = "fonts")]
+        if self.subset_fonts {
+            return true;
+        }
+        self.prune_orphans
+            || self.scrub_personal_data
+            || self.externalize_images
+            || self.typography.is_some()
+            || self.dark_mode_patch.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::test_support::{build, package};
+
+    #[test]
+    fn tolerant_hrefs_match_backslashes_and_case() {
+        let container = r#"<container version="1.0"><rootfiles>
+  <rootfile full-path="OEBPS\content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles></container>"#;
+        let opf = package(
+            r#"<item id="ch1" href="Text\Chapter1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let bytes = build(&[
+            ("META-INF/container.xml", container),
+            ("OEBPS/content.opf", &opf),
+            ("OEBPS/text/chapter1.xhtml", "<html><body>One</body></html>"),
+        ]);
+
+        assert!(Epub::from_bytes(bytes.clone()).is_err());
+
+        let options = ParseOptions::new().with_tolerant_hrefs(true);
+        let epub = Epub::from_bytes_with_options(bytes, options).unwrap();
+        assert_eq!(epub.get_all_files().len(), 1);
+        assert!(epub.get_resource_bytes("Text\\Chapter1.xhtml").is_some());
+    }
+}
@@ -0,0 +1,166 @@
+//! Finding resources nothing in the book uses
+//!
+//! Reachability starts from the spine, the navigation documents and the
+//! cover image, and follows every reference those make (stylesheets, images,
+//! fonts, linked documents), transitively. The manifest links resources too:
+//! an item's `media-overlay` and its `fallback` chain are reached with it,
+//! and the overlay's SMIL in turn reaches its audio.
+//!
+//! Whatever is left over is dead weight: images from an earlier edition,
+//! fonts no stylesheet loads, chapters that were cut from the spine but not
+//! from the manifest.
+
+use crate::epub::{Epub, Resource};
+use crate::refs::{self, REFERENCING_MEDIA_TYPES};
+use std::collections::HashSet;
+
+impl Epub {
+    /// Manifest items that can't be reached from the spine, nav, NCX or cover
+    pub fn find_orphan_resources(&self) -> Vec<&Resource> {
+        let path_of = |resource: &Resource| Self::resolve_path(&self.opf_path, &resource.href);
+
+        let mut pending: Vec<&Resource> = self
+            .resources
+            .iter()
+            .filter(|resource| {
+                self.spine.contains(&resource.id)
+                    || resource.has_property("nav")
+                    || resource.has_property("cover-image")
+                    || resource.media_type == "application/x-dtbncx+xml"
+                    || self.metadata.cover.as_deref() == Some(resource.id.as_str())
+            })
+            .collect();
+        let mut reachable: HashSet<String> = pending.iter().map(|r| path_of(r)).collect();
+
+        while let Some(resource) = pending.pop() {
+            let linked = [&resource.media_overlay, &resource.fallback];
+            for target in linked
+                .into_iter()
+                .flatten()
+                .filter_map(|id| self.get_resource_by_id(id))
+            {
+                if reachable.insert(path_of(target)) {
+                    pending.push(target);
+                }
+            }
+            if !REFERENCING_MEDIA_TYPES.contains(&resource.media_type.as_str()) {
+                continue;
+            }
+            let Some(content) = self
+                .get_resource_bytes(&resource.href)
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+            else {
+                continue;
+            };
+            for path in refs::find(&content, &path_of(resource)) {
+                if reachable.insert(path.clone())
+                    && let Some(target) = self.resources.iter().find(|r| path_of(r) == path)
+                {
+                    pending.push(target);
+                }
+            }
+        }
+
+        self.resources
+            .iter()
+            .filter(|resource| !reachable.contains(&path_of(resource)))
+            .collect()
+    }
+
+    /// Remove every resource `find_orphan_resources` reports, returning their hrefs
+    pub fn prune_orphans(&mut self) -> Vec<String> {
+        let orphans: Vec<String> = self
+            .find_orphan_resources()
+            .into_iter()
+            .map(|resource| resource.href.clone())
+            .collect();
+        for href in &orphans {
+            let _ = self.remove_resource(href);
+        }
+        orphans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::epub::Epub;
+    use crate::options::WriteOptions;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn unreferenced_resources_are_found_and_pruned_on_save() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="css" href="style.css" media-type="text/css"/>
+               <item id="font" href="serif.otf" media-type="font/otf"/>
+               <item id="old-font" href="old.otf" media-type="font/otf"/>
+               <item id="old" href="old.xhtml" media-type="application/xhtml+xml"/>
+               <item id="img" href="old.png" media-type="image/png"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                r#"<html><head><link href="style.css"/></head><body>One</body></html>"#,
+            ),
+            ("style.css", "@font-face { src: url(serif.otf) }"),
+            ("serif.otf", "otf"),
+            ("old.otf", "otf"),
+            (
+                "old.xhtml",
+                r#"<html><body><img src="old.png"/></body></html>"#,
+            ),
+            ("old.png", "png"),
+        ]))
+        .unwrap();
+
+        let orphans: Vec<&str> = epub
+            .find_orphan_resources()
+            .iter()
+            .map(|resource| resource.get_href())
+            .collect();
+        assert_eq!(orphans, ["old.otf", "old.xhtml", "old.png"]);
+
+        let options = WriteOptions::new().with_prune_orphans(true);
+        let saved = Epub::from_bytes(epub.to_bytes_with_options(&options).unwrap()).unwrap();
+        assert_eq!(saved.get_resources().len(), 3);
+        assert!(saved.get_resource_bytes("old.png").is_none());
+        assert!(saved.find_orphan_resources().is_empty());
+        assert_eq!(epub.get_resources().len(), 6);
+    }
+
+    #[test]
+    fn media_overlays_and_fallbacks_are_reachable() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml" media-overlay="mo1"/>
+               <item id="mo1" href="ch1.smil" media-type="application/smil+xml"/>
+               <item id="audio" href="a.mp3" media-type="audio/mpeg"/>
+               <item id="scroll" href="scroll.xml" media-type="application/x-scroll+xml" fallback="scroll-svg"/>
+               <item id="scroll-svg" href="scroll.svg" media-type="image/svg+xml" fallback="scroll-html"/>
+               <item id="scroll-html" href="scroll.xhtml" media-type="application/xhtml+xml"/>
+               <item id="unused" href="unused.mp3" media-type="audio/mpeg"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="scroll"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", r#"<html><body><p id="p1">One</p></body></html>"#),
+            (
+                "ch1.smil",
+                r#"<smil><body><seq epub:textref="ch1.xhtml"><par><text src="ch1.xhtml#p1"/><audio src="a.mp3" clipBegin="0s" clipEnd="2s"/></par></seq></body></smil>"#,
+            ),
+            ("a.mp3", "mp3"),
+            ("scroll.xml", "<scroll/>"),
+            ("scroll.svg", "<svg/>"),
+            ("scroll.xhtml", "<html><body>Scroll</body></html>"),
+            ("unused.mp3", "mp3"),
+        ]))
+        .unwrap();
+
+        assert_eq!(epub.prune_orphans(), ["unused.mp3"]);
+        assert!(epub.get_resource_bytes("a.mp3").is_some());
+        assert!(epub.get_resource_bytes("scroll.xhtml").is_some());
+    }
+}
@@ -0,0 +1,589 @@
+//! Writing OPF package documents
+//!
+//! `Package` is an owned, editable view of everything the OPF describes:
+//! metadata, `<meta>` refinements, the manifest and the spine. Get one from a
+//! parsed book with `Epub::to_package`, or build one from scratch, and
+//! serialize it with `to_xml`.
+
+use crate::epub::{Metadata, Resource, SpineItem};
+use crate::extensions::MetadataElement;
+use crate::text::escape_xml;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Media type of an EPUB 2 NCX, referenced from the spine's `toc` attribute
+const NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+
+/// A `<meta>` element: an EPUB 3 property (optionally refining another
+/// element) or an EPUB 2 name/content pair
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetaEntry {
+    pub(crate) id: Option<String>,
+    pub(crate) property: Option<String>,
+    pub(crate) refines: Option<String>,
+    pub(crate) scheme: Option<String>,
+    pub(crate) value: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) content: Option<String>,
+}
+
+impl MetaEntry {
+    /// An EPUB 3 `<meta property="...">value</meta>`
+    pub fn property(property: impl Into<String>, value: impl Into<String>) -> Self {
+        MetaEntry {
+            property: Some(property.into()),
+            value: Some(value.into()),
+            ..Default::default()
+        }
+    }
+
+    /// An EPUB 2 `<meta name="..." content="..."/>`
+    pub fn named(name: impl Into<String>, content: impl Into<String>) -> Self {
+        MetaEntry {
+            name: Some(name.into()),
+            content: Some(content.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Make this a refinement of the element with the given id (without `#`)
+    pub fn with_refines(mut self, id: impl Into<String>) -> Self {
+        self.refines = Some(format!("#{}", id.into()));
+        self
+    }
+
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.scheme = Some(scheme.into());
+        self
+    }
+
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn get_property(&self) -> Option<&str> {
+        self.property.as_deref()
+    }
+
+    /// The `refines` attribute as written, including the leading `#`
+    pub fn get_refines(&self) -> Option<&str> {
+        self.refines.as_deref()
+    }
+
+    pub fn get_scheme(&self) -> Option<&str> {
+        self.scheme.as_deref()
+    }
+
+    pub fn get_value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn get_content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    fn to_xml(&self) -> String {
+        let mut attributes = String::new();
+        for (name, value) in [
+            ("id", &self.id),
+            ("property", &self.property),
+            ("refines", &self.refines),
+            ("scheme", &self.scheme),
+            ("name", &self.name),
+            ("content", &self.content),
+        ] {
+            if let Some(value) = value {
+                let _ = write!(attributes, " {}=\"{}\"", name, escape_xml(value));
+            }
+        }
+
+        match &self.value {
+            Some(value) => format!("<meta{}>{}</meta>", attributes, escape_xml(value)),
+            None => format!("<meta{}/>", attributes),
+        }
+    }
+}
+
+/// The contents of an OPF package document
+#[derive(Debug, Clone)]
+pub struct Package {
+    version: String,
+    /// Id of the `dc:identifier` holding `Metadata::get_identifier`
+    unique_identifier: String,
+    prefix: Option<String>,
+    metadata: Metadata,
+    meta: Vec<MetaEntry>,
+    /// Metadata elements `Metadata` doesn't model, written as they were
+    elements: Vec<MetadataElement>,
+    manifest: Vec<Resource>,
+    spine: Vec<String>,
+    /// Itemref attributes by idref, for the itemrefs that have any
+    spine_items: BTreeMap<String, SpineItem>,
+    page_progression_direction: Option<String>,
+}
+
+impl Package {
+    /// An EPUB 3.0 package with the given metadata and nothing in it yet
+    pub fn new(metadata: Metadata) -> Self {
+        Package {
+            version: "3.0".to_string(),
+            unique_identifier: "uid".to_string(),
+            prefix: None,
+            metadata,
+            meta: Vec::new(),
+            elements: Vec::new(),
+            manifest: Vec::new(),
+            spine: Vec::new(),
+            spine_items: BTreeMap::new(),
+            page_progression_direction: None,
+        }
+    }
+
+    pub(crate) fn from_parts(
+        version: String,
+        metadata: Metadata,
+        meta: Vec<MetaEntry>,
+        manifest: Vec<Resource>,
+        spine: Vec<String>,
+        spine_items: BTreeMap<String, SpineItem>,
+    ) -> Self {
+        Package {
+            version,
+            unique_identifier: "uid".to_string(),
+            prefix: None,
+            metadata,
+            meta,
+            elements: Vec::new(),
+            manifest,
+            spine,
+            spine_items,
+            page_progression_direction: None,
+        }
+    }
+
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    /// Id of the unique `dc:identifier`, which `unique-identifier` points at
+    pub fn get_unique_identifier(&self) -> &str {
+        &self.unique_identifier
+    }
+
+    /// The `prefix` attribute declaring non-default vocabularies
+    pub fn get_prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Metadata elements written as they were, such as `dc:contributor`,
+    /// `dc:source` and identifiers other than the unique one
+    pub fn get_elements(&self) -> &[MetadataElement] {
+        &self.elements
+    }
+
+    /// The spine's `page-progression-direction`: `ltr`, `rtl` or `default`
+    pub fn get_page_progression_direction(&self) -> Option<&str> {
+        self.page_progression_direction.as_deref()
+    }
+
+    pub fn get_meta(&self) -> &[MetaEntry] {
+        &self.meta
+    }
+
+    pub fn get_manifest(&self) -> &[Resource] {
+        &self.manifest
+    }
+
+    /// Manifest IDs in reading order
+    pub fn get_spine(&self) -> &[String] {
+        &self.spine
+    }
+
+    pub fn set_version(&mut self, version: impl Into<String>) {
+        self.version = version.into();
+    }
+
+    pub fn set_unique_identifier(&mut self, id: impl Into<String>) {
+        self.unique_identifier = id.into();
+    }
+
+    pub fn set_prefix(&mut self, prefix: impl Into<String>) {
+        self.prefix = Some(prefix.into());
+    }
+
+    pub fn set_page_progression_direction(&mut self, direction: impl Into<String>) {
+        self.page_progression_direction = Some(direction.into());
+    }
+
+    pub(crate) fn add_element(&mut self, element: MetadataElement) {
+        self.elements.push(element);
+    }
+
+    pub fn add_meta(&mut self, meta: MetaEntry) {
+        self.meta.push(meta);
+    }
+
+    pub fn add_resource(&mut self, resource: Resource) {
+        self.manifest.push(resource);
+    }
+
+    /// Append a manifest item to the reading order
+    pub fn add_spine_item(&mut self, idref: impl Into<String>) {
+        self.spine.push(idref.into());
+    }
+
+    /// Append an itemref with `linear` and `properties` attributes
+    pub fn add_itemref(&mut self, item: SpineItem) {
+        self.spine.push(item.idref.clone());
+        if !item.linear || item.properties.is_some() {
+            self.spine_items.insert(item.idref.clone(), item);
+        }
+    }
+
+    fn is_epub3(&self) -> bool {
+        !self.version.starts_with('2')
+    }
+
+    /// Serialize as an OPF package document
+    ///
+    /// `dc:language` is required, so `und` is written when it is unknown.
+    /// For EPUB 3 a `dcterms:modified` timestamp is added if the package
+    /// doesn't carry one, and the EPUB 2 `<meta name="cover">` is written
+    /// whenever the metadata names a cover, so both kinds of reading system
+    /// find it.
+    pub fn to_xml(&self) -> String {
+        self.to_xml_modified_at(chrono::Utc::now().naive_utc())
+    }
+
+    /// Like `to_xml`, with `modified` as the fallback `dcterms:modified` time
+    pub(crate) fn to_xml_modified_at(&self, modified: chrono::NaiveDateTime) -> String {
+        let metadata = &self.metadata;
+        let mut out = String::new();
+
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let prefix = self
+            .prefix
+            .as_deref()
+            .map(|prefix| format!(" prefix=\"{}\"", escape_xml(prefix)))
+            .unwrap_or_default();
+        let _ = writeln!(
+            out,
+            "<package xmlns=\"http://www.idpf.org/2007/opf\" version=\"{}\" unique-identifier=\"{}\"{}>",
+            escape_xml(&self.version),
+            escape_xml(&self.unique_identifier),
+            prefix
+        );
+        out.push_str(
+            "  <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\" xmlns:opf=\"http://www.idpf.org/2007/opf\">\n",
+        );
+
+        let _ = writeln!(
+            out,
+            "    <dc:identifier id=\"{}\">{}</dc:identifier>",
+            escape_xml(&self.unique_identifier),
+            escape_xml(metadata.get_identifier())
+        );
+        // Titles keep their ids so `title-type` and other refinements resolve
+        for title in metadata.get_titles() {
+            let id = match &title.id {
+                Some(id) => format!(" id=\"{}\"", escape_xml(id)),
+                None => String::new(),
+            };
+            let _ = writeln!(
+                out,
+                "    <dc:title{}>{}</dc:title>",
+                id,
+                escape_xml(title.get_value())
+            );
+        }
+        let mut element = |name: &str, value: Option<&str>| {
+            if let Some(value) = value {
+                let _ = writeln!(out, "    <dc:{0}>{1}</dc:{0}>", name, escape_xml(value));
+            }
+        };
+        element("language", Some(metadata.get_language().unwrap_or("und")));
+        element("date", metadata.get_date());
+        element("publisher", metadata.get_publisher());
+        element("description", metadata.get_description());
+        element("rights", metadata.get_rights());
+        for tag in metadata.get_tags() {
+            element("subject", Some(tag));
+        }
+        // Creators keep their ids, or get `creator1`, `creator2`, ... (skipping
+        // ids other creators already have) so refinements such as `role` can
+        // point at them. A sort key or role that no refinement records yet is
+        // written as one (EPUB 3) or as an `opf:` attribute (EPUB 2).
+        let creators = metadata.get_creator_entries();
+        let mut refinements = Vec::new();
+        for (index, creator) in creators.iter().enumerate() {
+            let id = creator.id.clone().unwrap_or_else(|| {
+                (index + 1..)
+                    .map(|n| format!("creator{}", n))
+                    .find(|id| creators.iter().all(|other| other.id.as_ref() != Some(id)))
+                    .expect("an unused creator id")
+            });
+            let refines = format!("#{}", id);
+            let mut attributes = String::new();
+            for (property, value) in [("file-as", &creator.file_as), ("role", &creator.role)] {
+                let Some(value) = value else {
+                    continue;
+                };
+                let refined = self.meta.iter().any(|meta| {
+                    meta.refines.as_deref() == Some(refines.as_str())
+                        && meta.property.as_deref() == Some(property)
+                });
+                if refined {
+                    continue;
+                }
+                if self.is_epub3() {
+                    let mut meta = MetaEntry::property(property, value).with_refines(id.clone());
+                    if property == "role" {
+                        meta = meta.with_scheme("marc:relators");
+                    }
+                    refinements.push(meta);
+                } else {
+                    let _ = write!(attributes, " opf:{}=\"{}\"", property, escape_xml(value));
+                }
+            }
+            let _ = writeln!(
+                out,
+                "    <dc:creator id=\"{}\"{}>{}</dc:creator>",
+                escape_xml(&id),
+                attributes,
+                escape_xml(&creator.name)
+            );
+        }
+
+        for element in &self.elements {
+            let _ = writeln!(out, "    {}", element.to_xml());
+        }
+        for meta in self.meta.iter().chain(&refinements) {
+            let _ = writeln!(out, "    {}", meta.to_xml());
+        }
+        let has_property = |property: &str| {
+            self.meta
+                .iter()
+                .any(|meta| meta.property.as_deref() == Some(property) && meta.refines.is_none())
+        };
+        if self.is_epub3() && !has_property("dcterms:modified") {
+            let modified = modified.format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            let _ = writeln!(
+                out,
+                "    {}",
+                MetaEntry::property("dcterms:modified", modified).to_xml()
+            );
+        }
+        if let Some(cover) = metadata.get_cover()
+            && !self
+                .meta
+                .iter()
+                .any(|meta| meta.name.as_deref() == Some("cover"))
+        {
+            let _ = writeln!(out, "    {}", MetaEntry::named("cover", cover).to_xml());
+        }
+        out.push_str("  </metadata>\n");
+
+        out.push_str("  <manifest>\n");
+        for resource in &self.manifest {
+            let properties = resource
+                .get_properties()
+                .map(|properties| format!(" properties=\"{}\"", escape_xml(properties)))
+                .unwrap_or_default();
+            let overlay = resource
+                .get_media_overlay()
+                .map(|overlay| format!(" media-overlay=\"{}\"", escape_xml(overlay)))
+                .unwrap_or_default();
+            let fallback = resource
+                .get_fallback()
+                .map(|fallback| format!(" fallback=\"{}\"", escape_xml(fallback)))
+                .unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "    <item id=\"{}\" href=\"{}\" media-type=\"{}\"{}{}{}/>",
+                escape_xml(resource.get_id()),
+                escape_xml(resource.get_href()),
+                escape_xml(resource.get_media_type()),
+                fallback,
+                properties,
+                overlay
+            );
+        }
+        out.push_str("  </manifest>\n");
+
+        let toc = self
+            .manifest
+            .iter()
+            .find(|resource| resource.get_media_type() == NCX_MEDIA_TYPE)
+            .map(|ncx| format!(" toc=\"{}\"", escape_xml(ncx.get_id())))
+            .unwrap_or_default();
+        let direction = self
+            .page_progression_direction
+            .as_deref()
+            .map(|direction| format!(" page-progression-direction=\"{}\"", escape_xml(direction)))
+            .unwrap_or_default();
+        let _ = writeln!(out, "  <spine{}{}>", toc, direction);
+        for idref in &self.spine {
+            let mut attributes = String::new();
+            if let Some(item) = self.spine_items.get(idref) {
+                if !item.linear {
+                    attributes.push_str(" linear=\"no\"");
+                }
+                if let Some(properties) = &item.properties {
+                    let _ = write!(attributes, " properties=\"{}\"", escape_xml(properties));
+                }
+            }
+            let _ = writeln!(
+                out,
+                "    <itemref idref=\"{}\"{}/>",
+                escape_xml(idref),
+                attributes
+            );
+        }
+        out.push_str("  </spine>\n</package>\n");
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build};
+
+    #[test]
+    fn round_trips_through_the_parser() {
+        let mut metadata = Metadata::new(
+            Some("Fish & Chips".to_string()),
+            vec!["Herman Melville".to_string()],
+            Some("en".to_string()),
+            "urn:isbn:123".to_string(),
+            None,
+        );
+        metadata.cover = Some("cover".to_string());
+
+        let mut package = Package::new(metadata);
+        package.add_meta(MetaEntry::property("role", "aut").with_refines("creator1"));
+        package.add_resource(Resource::new(
+            "cover",
+            "cover.jpg",
+            "image/jpeg",
+            Some("cover-image".to_string()),
+        ));
+        package.add_resource(Resource::new(
+            "ch1",
+            "ch1.xhtml",
+            "application/xhtml+xml",
+            None,
+        ));
+        package.add_spine_item("ch1");
+
+        let xml = package.to_xml();
+        assert!(xml.contains("<meta property=\"role\" refines=\"#creator1\">aut</meta>"));
+        assert!(xml.contains("property=\"dcterms:modified\""));
+        assert!(xml.contains("<meta name=\"cover\" content=\"cover\"/>"));
+
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &xml),
+            ("ch1.xhtml", "<html><body><h1>One</h1></body></html>"),
+            ("cover.jpg", "jpeg"),
+        ]))
+        .unwrap();
+        assert_eq!(epub.get_title(), Some("Fish & Chips"));
+        assert_eq!(epub.get_creator(), Some("Herman Melville"));
+        assert_eq!(epub.get_spine(), ["ch1"]);
+        assert_eq!(epub.get_cover_bytes().as_deref(), Some(&b"jpeg"[..]));
+
+        let reparsed = epub.to_package();
+        assert_eq!(reparsed.get_manifest().len(), 2);
+        assert!(
+            reparsed
+                .get_meta()
+                .iter()
+                .any(|meta| meta.get_refines() == Some("#creator1"))
+        );
+    }
+
+    #[test]
+    fn saving_keeps_what_metadata_does_not_model() {
+        let opf = r##"<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="pub-id" prefix="ibooks: http://vocabulary.itunes.apple.com/rdf/ibooks/vocabulary-extensions-1.0/">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="calibre_id">1234</dc:identifier>
+    <dc:identifier id="pub-id">urn:isbn:9780000000001</dc:identifier>
+    <meta refines="#pub-id" property="identifier-type" scheme="onix:codelist5">15</meta>
+    <dc:title>T</dc:title>
+    <dc:language>ja</dc:language>
+    <dc:language>en</dc:language>
+    <dc:contributor id="trl">A. Translator</dc:contributor>
+    <dc:source>urn:isbn:9780000000002</dc:source>
+    <meta property="ibooks:specified-fonts">true</meta>
+    <link rel="record" href="record.xml" media-type="application/marc"/>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="scroll" href="scroll.xml" media-type="application/x-scroll+xml" fallback="ch1"/>
+  </manifest>
+  <spine page-progression-direction="rtl"><itemref idref="scroll"/></spine>
+</package>"##;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("scroll.xml", "<scroll/>"),
+        ]))
+        .unwrap();
+        assert_eq!(
+            epub.get_metadata().get_identifier(),
+            "urn:isbn:9780000000001"
+        );
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            saved.get_metadata().get_identifier(),
+            "urn:isbn:9780000000001"
+        );
+        let package = saved.to_package();
+        assert_eq!(package.get_unique_identifier(), "pub-id");
+        assert!(package.get_prefix().unwrap().starts_with("ibooks: "));
+        assert_eq!(package.get_page_progression_direction(), Some("rtl"));
+        assert_eq!(package.get_manifest()[1].get_fallback(), Some("ch1"));
+        assert!(package.get_meta().iter().any(|meta| {
+            meta.get_refines() == Some("#pub-id") && meta.get_property() == Some("identifier-type")
+        }));
+
+        let elements: Vec<(&str, &str)> = package
+            .get_elements()
+            .iter()
+            .map(|element| (element.get_name(), element.get_text()))
+            .collect();
+        assert_eq!(
+            elements,
+            [
+                ("dc:identifier", "1234"),
+                ("dc:language", "en"),
+                ("dc:contributor", "A. Translator"),
+                ("dc:source", "urn:isbn:9780000000002"),
+                ("link", ""),
+            ]
+        );
+        assert_eq!(package.get_elements()[2].get_attribute("id"), Some("trl"));
+        assert_eq!(
+            package.get_elements()[4].get_attribute("href"),
+            Some("record.xml")
+        );
+    }
+}
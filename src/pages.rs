@@ -0,0 +1,242 @@
+//! Print page lists from every source a book may use
+//!
+//! EPUB 3 books list printed pages in the `page-list` nav and EPUB 2 books
+//! in the NCX `<pageList>`, but files from the Adobe and Kobo ecosystems
+//! often have neither:
+//!
+//! - Adobe Digital Editions reads `page-map.xml`, a manifest item of type
+//!   `application/oebps-page-map+xml` holding `<page name="12" href="..."/>`
+//! - Kobo files mark each page inline with an empty
+//!   `<span epub:type="pagebreak" id="..." title="12"/>` (or
+//!   `role="doc-pagebreak"`) in the content documents
+//!
+//! `Epub::page_list` tries these in that order and returns the first that
+//! has any pages, as `PageTarget`s with hrefs relative to the OPF.
+
+use crate::epub::Epub;
+use crate::nav::PageTarget;
+use crate::text::{decode_entities, html_to_text};
+use crate::xhtml::find_elements;
+
+const NCX_MEDIA_TYPE: &str = "application/x-dtbncx+xml";
+const PAGE_MAP_MEDIA_TYPE: &str = "application/oebps-page-map+xml";
+
+impl Epub {
+    /// The book's print page list, from the nav, NCX, Adobe page map or
+    /// inline page-break markers, whichever comes first
+    pub fn page_list(&self) -> Vec<PageTarget> {
+        let sources: [fn(&Epub) -> Vec<PageTarget>; 4] = [
+            Epub::nav_page_list,
+            Epub::ncx_page_list,
+            Epub::page_map,
+            Epub::page_break_markers,
+        ];
+        sources
+            .iter()
+            .map(|source| source(self))
+            .find(|pages| !pages.is_empty())
+            .unwrap_or_default()
+    }
+
+    fn nav_page_list(&self) -> Vec<PageTarget> {
+        let mut pages = Vec::new();
+        let nav = self.resources.iter().find(|resource| {
+            resource
+                .properties
+                .as_deref()
+                .is_some_and(|properties| properties.split_whitespace().any(|p| p == "nav"))
+        });
+        let Some((path, markup)) = nav.and_then(|nav| self.resource_text(&nav.href)) else {
+            return pages;
+        };
+        let lists = find_elements(&markup, |e| {
+            e.name == "nav" && e.has_token("epub:type", "page-list")
+        });
+        for list in lists {
+            for link in find_elements(list.inner, |e| e.name == "a") {
+                if let Some(href) = link.attribute("href") {
+                    let label = html_to_text(link.inner).trim().to_string();
+                    pages.push(PageTarget::new(label, self.rebase(&path, href)));
+                }
+            }
+        }
+        pages
+    }
+
+    fn ncx_page_list(&self) -> Vec<PageTarget> {
+        let mut pages = Vec::new();
+        let ncx = self
+            .resources
+            .iter()
+            .find(|resource| resource.media_type == NCX_MEDIA_TYPE);
+        let Some((path, markup)) = ncx.and_then(|ncx| self.resource_text(&ncx.href)) else {
+            return pages;
+        };
+        for target in find_elements(&markup, |e| e.name == "pagetarget") {
+            let label = find_elements(target.inner, |e| e.name == "text")
+                .first()
+                .map(|text| decode_entities(text.inner.trim()))
+                .or_else(|| target.attribute("value").map(str::to_string));
+            let src = find_elements(target.inner, |e| e.name == "content")
+                .first()
+                .and_then(|content| content.attribute("src").map(str::to_string));
+            if let (Some(label), Some(src)) = (label, src) {
+                pages.push(PageTarget::new(label, self.rebase(&path, &src)));
+            }
+        }
+        pages
+    }
+
+    fn page_map(&self) -> Vec<PageTarget> {
+        let mut pages = Vec::new();
+        let map = self
+            .resources
+            .iter()
+            .find(|resource| resource.media_type == PAGE_MAP_MEDIA_TYPE);
+        let Some((path, markup)) = map.and_then(|map| self.resource_text(&map.href)) else {
+            return pages;
+        };
+        for page in find_elements(&markup, |e| e.name == "page") {
+            if let (Some(name), Some(href)) = (page.attribute("name"), page.attribute("href")) {
+                pages.push(PageTarget::new(name, self.rebase(&path, href)));
+            }
+        }
+        pages
+    }
+
+    fn page_break_markers(&self) -> Vec<PageTarget> {
+        let mut pages = Vec::new();
+        for file in self.get_spine_files() {
+            let markers = find_elements(&file.content, |e| {
+                e.has_token("epub:type", "pagebreak") || e.has_token("role", "doc-pagebreak")
+            });
+            for marker in markers {
+                let Some(id) = marker.attribute("id") else {
+                    continue;
+                };
+                let label = marker
+                    .attribute("title")
+                    .or_else(|| marker.attribute("aria-label"))
+                    .map(str::to_string)
+                    .unwrap_or_else(|| html_to_text(marker.inner).trim().to_string());
+                if !label.is_empty() {
+                    pages.push(PageTarget::new(label, format!("{}#{}", file.href, id)));
+                }
+            }
+        }
+        pages
+    }
+
+    /// The archive path and text of the resource at `href`
+    fn resource_text(&self, href: &str) -> Option<(String, String)> {
+        let bytes = self.get_resource_bytes(href)?;
+        let path = Self::resolve_path(&self.opf_path, href);
+        Some((path, String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    /// Re-express an href found in the file at `path` relative to the OPF
+    fn rebase(&self, path: &str, href: &str) -> String {
+        Self::rebase_href(path, &self.opf_path, href)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn pages(epub: &Epub) -> Vec<(String, String)> {
+        epub.page_list()
+            .iter()
+            .map(|page| (page.get_label().to_string(), page.get_href().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn page_list_falls_back_to_page_map_and_markers() {
+        let chapter = r#"<html><body><p><span epub:type="pagebreak" id="p1" title="1"/>One</p>
+<p><span role="doc-pagebreak" id="p2">2</span>Two</p></body></html>"#;
+        let opf = package(
+            r#"<item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let files = [
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf.as_str()),
+            ("text/ch1.xhtml", chapter),
+        ];
+        let epub = Epub::from_bytes(build(&files)).unwrap();
+        assert_eq!(
+            pages(&epub),
+            [
+                ("1".to_string(), "text/ch1.xhtml#p1".to_string()),
+                ("2".to_string(), "text/ch1.xhtml#p2".to_string()),
+            ]
+        );
+
+        let opf = package(
+            r#"<item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="map" href="text/page-map.xml" media-type="application/oebps-page-map+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let map = r#"<page-map xmlns="http://www.idpf.org/2007/opf">
+  <page name="i" href="ch1.xhtml"/>
+  <page name="ii" href="ch1.xhtml#p2"/>
+</page-map>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("text/ch1.xhtml", chapter),
+            ("text/page-map.xml", map),
+        ]))
+        .unwrap();
+        assert_eq!(
+            pages(&epub),
+            [
+                ("i".to_string(), "text/ch1.xhtml".to_string()),
+                ("ii".to_string(), "text/ch1.xhtml#p2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nav_and_ncx_page_lists_are_read() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ncx" href="toc.ncx" media-type="application/x-dtbncx+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let ncx = r#"<ncx><pageList>
+  <pageTarget id="pt1" type="normal" value="7" playOrder="1"><navLabel><text>7</text></navLabel><content src="ch1.xhtml#p7"/></pageTarget>
+</pageList></ncx>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("toc.ncx", ncx),
+        ]))
+        .unwrap();
+        assert_eq!(
+            pages(&epub),
+            [("7".to_string(), "ch1.xhtml#p7".to_string())]
+        );
+
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="nav" href="nav/nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let nav = r#"<html><body><nav epub:type="page-list"><ol><li><a href="../ch1.xhtml#p3">3</a></li></ol></nav></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("nav/nav.xhtml", nav),
+        ]))
+        .unwrap();
+        assert_eq!(
+            pages(&epub),
+            [("3".to_string(), "ch1.xhtml#p3".to_string())]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Parsing many books with the same settings
+//!
+//! Ingesting a library means parsing thousands of books in one process. The
+//! regular expressions run over every content document are compiled once,
+//! on first use, and shared by every book and thread after that, so the
+//! per-book cost is the parsing itself. An `EpubParser` holds the options
+//! for a run; it is `Send + Sync`, so worker threads can share one by
+//! reference. `EpubParser::parse_batch` parses books one after another and
+//! yields each result as it's ready, so one broken book doesn't stop the
+//! rest.
+
+use crate::archive::Source;
+use crate::epub::Epub;
+use crate::options::ParseOptions;
+use std::error::Error;
+use std::sync::Arc;
+
+/// Parses books with a fixed set of `ParseOptions`
+#[derive(Debug, Clone, Default)]
+pub struct EpubParser {
+    options: ParseOptions,
+}
+
+impl EpubParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: ParseOptions) -> Self {
+        Self { options }
+    }
+
+    pub fn get_options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /// Parse an in-memory EPUB archive, as `Epub::from_bytes_with_options`
+    pub fn parse(&self, bytes: impl Into<Arc<[u8]>>) -> Result<Epub, Box<dyn Error>> {
+        enter_span!("epub", source = "bytes");
+        Epub::from_source(Source::Bytes(bytes.into()), self.options.clone())
+    }
+
+    /// Parse the EPUB file at `file_path`, as `Epub::with_options`
+    pub fn parse_file(&self, file_path: &str) -> Result<Epub, Box<dyn Error>> {
+        enter_span!("epub", path = %file_path);
+        Epub::from_source(Source::Path(file_path.to_string()), self.options.clone())
+    }
+
+    /// Parse each archive in `books` in turn, yielding results in the same order
+    pub fn parse_batch<I>(&self, books: I) -> impl Iterator<Item = Result<Epub, Box<dyn Error>>>
+    where
+        I: IntoIterator,
+        I::Item: Into<Arc<[u8]>>,
+    {
+        books.into_iter().map(|bytes| self.parse(bytes))
+    }
+
+    /// Parse each file in `paths` in turn, yielding `(path, result)` pairs
+    pub fn parse_files<'p, I>(
+        &self,
+        paths: I,
+    ) -> impl Iterator<Item = (&'p str, Result<Epub, Box<dyn Error>>)>
+    where
+        I: IntoIterator<Item = &'p str>,
+    {
+        paths.into_iter().map(|path| (path, self.parse_file(path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn batches_keep_going_past_broken_books() {
+        let book = |title: &str| {
+            let opf = package(
+                r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+                r#"<itemref idref="ch1"/>"#,
+            );
+            let chapter = format!("<html><body><h1>{}</h1></body></html>", title);
+            build(&[
+                ("META-INF/container.xml", CONTAINER),
+                ("content.opf", &opf),
+                ("ch1.xhtml", &chapter),
+            ])
+        };
+        let books = vec![book("One"), b"not a zip".to_vec(), book("Three")];
+
+        let parser = EpubParser::with_options(
+            ParseOptions::default()
+                .with_tolerant_hrefs(true)
+                .with_heading_titles(true),
+        );
+        let results: Vec<_> = parser.parse_batch(books).collect();
+        assert_eq!(results.len(), 3);
+        assert!(results[1].is_err());
+        let titles: Vec<String> = results
+            .iter()
+            .flatten()
+            .map(|epub| {
+                epub.get_table_of_contents().get_entries()[0]
+                    .get_title()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(titles, ["One", "Three"]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Prefetch plans for streaming readers
+//!
+//! A reader that downloads or decompresses a book lazily wants the document
+//! under the cursor first, then whatever the next few pages will need.
+//! `Epub::prefetch_plan` lists the resources for a reading position in that
+//! order: each spine document from the current one on, followed by the
+//! stylesheets, fonts, images and media it uses. Links to other content
+//! documents are not followed; those come in spine order.
+
+use crate::epub::{Epub, Resource, is_content_media_type};
+use crate::locator::Locator;
+use crate::refs::{self, REFERENCING_MEDIA_TYPES};
+use std::collections::HashSet;
+
+/// A resource to fetch, from `Epub::prefetch_plan`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefetchItem {
+    href: String,
+    media_type: String,
+    distance: usize,
+}
+
+impl PrefetchItem {
+    /// Href relative to the OPF, as `Resource::get_href`
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    /// Spine documents between the reading position and the one that needs
+    /// this resource: 0 for the current document
+    pub fn get_distance(&self) -> usize {
+        self.distance
+    }
+}
+
+impl Epub {
+    /// What to fetch for reading at `locator`, most urgent first
+    ///
+    /// Covers the document at `locator` and the next `window` spine
+    /// documents, each followed by the resources it references that an
+    /// earlier entry doesn't already cover. Stylesheets come before other
+    /// resources, so text can be laid out before images arrive.
+    pub fn prefetch_plan(&self, locator: &Locator, window: usize) -> Vec<PrefetchItem> {
+        let path_of = |resource: &Resource| Self::resolve_path(&self.opf_path, &resource.href);
+        let current = Self::resolve_path(&self.opf_path, locator.get_href());
+        let spine: Vec<&Resource> = self
+            .spine
+            .iter()
+            .filter_map(|id| self.get_resource_by_id(id))
+            .collect();
+        let documents: Vec<&Resource> = match spine
+            .iter()
+            .position(|resource| path_of(resource) == current)
+        {
+            Some(start) => spine.into_iter().skip(start).take(window + 1).collect(),
+            None => self
+                .resources
+                .iter()
+                .filter(|resource| path_of(resource) == current)
+                .collect(),
+        };
+
+        let mut plan = Vec::new();
+        let mut planned = HashSet::new();
+        for (distance, document) in documents.into_iter().enumerate() {
+            let mut add = |resource: &Resource| {
+                if planned.insert(path_of(resource)) {
+                    plan.push(PrefetchItem {
+                        href: resource.href.clone(),
+                        media_type: resource.media_type.clone(),
+                        distance,
+                    });
+                }
+            };
+            add(document);
+
+            let mut used = Vec::new();
+            self.collect_used(document, &mut used, &mut HashSet::new());
+            // A stable sort keeps document order within each group
+            used.sort_by_key(|resource| resource.media_type != "text/css");
+            for resource in used {
+                add(resource);
+            }
+        }
+        plan
+    }
+
+    /// Resources `resource` references, and those its stylesheets and
+    /// images reference in turn, in document order
+    fn collect_used<'a>(
+        &'a self,
+        resource: &Resource,
+        used: &mut Vec<&'a Resource>,
+        seen: &mut HashSet<String>,
+    ) {
+        if !REFERENCING_MEDIA_TYPES.contains(&resource.media_type.as_str()) {
+            return;
+        }
+        let path = Self::resolve_path(&self.opf_path, &resource.href);
+        let Some(content) = self
+            .get_resource_bytes(&resource.href)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        else {
+            return;
+        };
+        for target in refs::find(&content, &path) {
+            let Some(target) = self
+                .resources
+                .iter()
+                .find(|r| Self::resolve_path(&self.opf_path, &r.href) == target)
+            else {
+                continue;
+            };
+            if is_content_media_type(&target.media_type) || !seen.insert(target.href.clone()) {
+                continue;
+            }
+            used.push(target);
+            self.collect_used(target, used, seen);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn plan_covers_the_window_in_reading_order() {
+        let opf = package(
+            r#"<item id="ch1" href="text/ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="text/ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch3" href="text/ch3.xhtml" media-type="application/xhtml+xml"/>
+               <item id="css" href="style.css" media-type="text/css"/>
+               <item id="font" href="serif.woff2" media-type="font/woff2"/>
+               <item id="map" href="map.png" media-type="image/png"/>
+               <item id="photo" href="photo.jpg" media-type="image/jpeg"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/><itemref idref="ch3"/>"#,
+        );
+        let chapter = |body: &str| {
+            format!(
+                r#"<html><head><link rel="stylesheet" href="../style.css"/></head><body>{}</body></html>"#,
+                body
+            )
+        };
+        let ch1 = chapter(r#"<p>One</p>"#);
+        let ch2 = chapter(r#"<img src="../map.png"/><a href="ch3.xhtml">on</a>"#);
+        let ch3 = chapter(r#"<img src="../photo.jpg"/><img src="../map.png"/>"#);
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("text/ch1.xhtml", &ch1),
+            ("text/ch2.xhtml", &ch2),
+            ("text/ch3.xhtml", &ch3),
+            ("style.css", "@font-face { src: url(serif.woff2) }"),
+        ]))
+        .unwrap();
+
+        let plan = epub.prefetch_plan(&Locator::new("text/ch2.xhtml"), 1);
+        let plan: Vec<(&str, usize)> = plan
+            .iter()
+            .map(|item| (item.get_href(), item.get_distance()))
+            .collect();
+        assert_eq!(
+            plan,
+            [
+                ("text/ch2.xhtml", 0),
+                ("style.css", 0),
+                ("serif.woff2", 0),
+                ("map.png", 0),
+                ("text/ch3.xhtml", 1),
+                ("photo.jpg", 1),
+            ]
+        );
+    }
+}
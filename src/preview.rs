@@ -0,0 +1,114 @@
+//! Short excerpts for store listings and library tooltips
+//!
+//! The first characters of a book are usually a title page and a copyright
+//! notice, which make a poor teaser. `Epub::preview` starts at the body
+//! matter instead, using the same classification as `EpubFile::semantic_role`,
+//! leaves out headings such as "Chapter One", and cuts the text at a word
+//! boundary.
+
+use crate::epub::Epub;
+use crate::options::TextOptions;
+use crate::text::find_headings;
+
+/// Marks a preview that was cut short
+const ELLIPSIS: char = '\u{2026}';
+
+impl Epub {
+    /// An excerpt of at most `n_chars` characters from the start of the body
+    /// matter, paragraphs separated by newlines
+    ///
+    /// Front matter is skipped unless the book has nothing else. Text that
+    /// had to be cut ends at a word boundary with an ellipsis, which counts
+    /// towards `n_chars`.
+    pub fn preview(&self, n_chars: usize) -> String {
+        if n_chars == 0 {
+            return String::new();
+        }
+        let files = self.get_spine_files();
+        let body_start = files
+            .iter()
+            .position(|file| !file.semantic_role().is_front_matter())
+            .unwrap_or(0);
+        let options = TextOptions::new().with_strip_soft_hyphens(true);
+
+        let mut paragraphs: Vec<String> = Vec::new();
+        let mut length = 0;
+        for file in &files[body_start..] {
+            let headings: Vec<String> = find_headings(&file.content, 6)
+                .into_iter()
+                .map(|(_, text, _)| text)
+                .collect();
+            for line in file.get_text_with(&options).lines() {
+                if headings.iter().any(|heading| heading == line) {
+                    continue;
+                }
+                // One for the newline joining it to the previous paragraph
+                length += line.chars().count() + usize::from(!paragraphs.is_empty());
+                paragraphs.push(line.to_string());
+                if length > n_chars {
+                    return truncate(&paragraphs.join("\n"), n_chars);
+                }
+            }
+        }
+        paragraphs.join("\n")
+    }
+}
+
+/// Cut `text` to at most `n_chars` characters, ending with an ellipsis at a
+/// word boundary
+fn truncate(text: &str, n_chars: usize) -> String {
+    let limit = n_chars.saturating_sub(1);
+    let end = text
+        .char_indices()
+        .nth(limit)
+        .map_or(text.len(), |(byte, _)| byte);
+    let cut = &text[..end];
+    let cut = match cut.rfind(char::is_whitespace) {
+        // Don't break a word unless it is the only one
+        Some(space) if !text[end..].starts_with(char::is_whitespace) => &cut[..space],
+        _ => cut,
+    };
+    let mut preview = cut
+        .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | ':'))
+        .to_string();
+    preview.push(ELLIPSIS);
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn preview_starts_at_the_body_and_cuts_at_a_word() {
+        let opf = package(
+            r#"<item id="title" href="titlepage.xhtml" media-type="application/xhtml+xml"/>
+               <item id="copyright" href="copyright.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="title"/><itemref idref="copyright"/><itemref idref="ch1"/>"#,
+        );
+        let chapter = "<html><body><h1>Chapter One</h1><p>Call me Ishmael.</p><p>Some years ago, never mind how long precisely.</p></body></html>";
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "titlepage.xhtml",
+                "<html><body><h1>Moby-Dick</h1></body></html>",
+            ),
+            (
+                "copyright.xhtml",
+                "<html><body><p>All rights reserved.</p></body></html>",
+            ),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        assert_eq!(epub.preview(35), "Call me Ishmael.\nSome years ago\u{2026}");
+        assert_eq!(
+            epub.preview(1000),
+            "Call me Ishmael.\nSome years ago, never mind how long precisely."
+        );
+        assert_eq!(epub.preview(0), "");
+    }
+}
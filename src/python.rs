@@ -0,0 +1,297 @@
+//! Python bindings
+//!
+//! Enabled with the `python` feature and packaged with maturin (see
+//! `pyproject.toml`). The module is importable as `epubie`:
+//!
+//! ```python
+//! import epubie
+//!
+//! book = epubie.Epub("book.epub")
+//! print(book.title, book.creators)
+//! for chapter in book.chapters:
+//!     print(chapter.title, len(chapter.text()))
+//! ```
+
+use crate::epub::{Chapter, Epub, EpubFile, Metadata};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use std::error::Error;
+
+/// A parsed EPUB book
+#[pyclass(name = "Epub", module = "epubie", frozen)]
+struct PyEpub {
+    inner: Epub,
+}
+
+#[pymethods]
+impl PyEpub {
+    #[new]
+    fn new(path: String) -> PyResult<Self> {
+        Epub::new(path)
+            .map(|inner| PyEpub { inner })
+            .map_err(to_py_err)
+    }
+
+    #[getter]
+    fn title(&self) -> Option<&str> {
+        self.inner.get_title()
+    }
+
+    #[getter]
+    fn creators(&self) -> Vec<String> {
+        self.inner.get_creators().to_vec()
+    }
+
+    #[getter]
+    fn language(&self) -> Option<&str> {
+        self.inner.get_language()
+    }
+
+    #[getter]
+    fn identifier(&self) -> &str {
+        self.inner.get_identifier()
+    }
+
+    #[getter]
+    fn metadata(&self) -> PyMetadata {
+        PyMetadata {
+            inner: self.inner.get_metadata().clone(),
+        }
+    }
+
+    #[getter]
+    fn chapters(&self) -> Vec<PyChapter> {
+        self.inner
+            .get_chapters()
+            .iter()
+            .map(|chapter| PyChapter {
+                inner: chapter.clone(),
+            })
+            .collect()
+    }
+
+    #[getter]
+    fn files(&self) -> Vec<PyEpubFile> {
+        self.inner
+            .get_all_files()
+            .iter()
+            .map(|file| PyEpubFile {
+                inner: file.clone(),
+            })
+            .collect()
+    }
+
+    /// Cover image as `bytes`, or `None` if the book has no cover
+    fn cover_bytes<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        self.inner
+            .get_cover_bytes()
+            .map(|bytes| PyBytes::new(py, &bytes))
+    }
+
+    /// Raw bytes of any resource by its href, or `None` if it does not exist
+    fn resource_bytes<'py>(&self, py: Python<'py>, href: &str) -> Option<Bound<'py, PyBytes>> {
+        self.inner
+            .get_resource_bytes(href)
+            .map(|bytes| PyBytes::new(py, &bytes))
+    }
+
+    /// Plain text of the whole book, chapters separated by blank lines
+    fn text(&self) -> String {
+        self.inner
+            .get_chapters()
+            .iter()
+            .map(Chapter::get_text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Case-insensitive search returning `(chapter_index, href, snippet)` tuples
+    fn search(&self, query: &str) -> Vec<(usize, String, String)> {
+        self.inner
+            .search(query)
+            .into_iter()
+            .map(|m| {
+                (
+                    m.get_chapter_index(),
+                    m.get_href().to_string(),
+                    m.get_snippet().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Epub(title={:?}, chapters={})",
+            self.inner.get_title().unwrap_or(""),
+            self.inner.get_chapter_count()
+        )
+    }
+}
+
+/// Book metadata from the OPF package document
+#[pyclass(name = "Metadata", module = "epubie", frozen)]
+struct PyMetadata {
+    inner: Metadata,
+}
+
+#[pymethods]
+impl PyMetadata {
+    #[getter]
+    fn title(&self) -> Option<&str> {
+        self.inner.get_title()
+    }
+
+    #[getter]
+    fn creators(&self) -> Vec<String> {
+        self.inner.get_creators().to_vec()
+    }
+
+    #[getter]
+    fn language(&self) -> Option<&str> {
+        self.inner.get_language()
+    }
+
+    #[getter]
+    fn identifier(&self) -> &str {
+        self.inner.get_identifier()
+    }
+
+    #[getter]
+    fn date(&self) -> Option<&str> {
+        self.inner.get_date()
+    }
+
+    #[getter]
+    fn publisher(&self) -> Option<&str> {
+        self.inner.get_publisher()
+    }
+
+    #[getter]
+    fn description(&self) -> Option<&str> {
+        self.inner.get_description()
+    }
+
+    #[getter]
+    fn rights(&self) -> Option<&str> {
+        self.inner.get_rights()
+    }
+
+    #[getter]
+    fn cover(&self) -> Option<&str> {
+        self.inner.get_cover()
+    }
+
+    #[getter]
+    fn tags(&self) -> Vec<String> {
+        self.inner.get_tags().to_vec()
+    }
+}
+
+/// A chapter made of one or more content documents
+#[pyclass(name = "Chapter", module = "epubie", frozen)]
+struct PyChapter {
+    inner: Chapter,
+}
+
+#[pymethods]
+impl PyChapter {
+    #[getter]
+    fn title(&self) -> &str {
+        self.inner.get_title()
+    }
+
+    #[getter]
+    fn files(&self) -> Vec<PyEpubFile> {
+        self.inner
+            .get_files()
+            .iter()
+            .map(|file| PyEpubFile {
+                inner: file.clone(),
+            })
+            .collect()
+    }
+
+    /// Plain text of every file in the chapter
+    fn text(&self) -> String {
+        self.inner.get_text()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Chapter(title={:?}, files={})",
+            self.inner.get_title(),
+            self.inner.get_file_count()
+        )
+    }
+}
+
+/// A single XHTML content document
+#[pyclass(name = "EpubFile", module = "epubie", frozen)]
+struct PyEpubFile {
+    inner: EpubFile,
+}
+
+#[pymethods]
+impl PyEpubFile {
+    #[getter]
+    fn id(&self) -> &str {
+        self.inner.get_id()
+    }
+
+    #[getter]
+    fn href(&self) -> &str {
+        self.inner.get_href()
+    }
+
+    #[getter]
+    fn title(&self) -> Option<&str> {
+        self.inner.get_title()
+    }
+
+    #[getter]
+    fn media_type(&self) -> &str {
+        self.inner.get_media_type()
+    }
+
+    /// Raw XHTML source
+    #[getter]
+    fn content(&self) -> &str {
+        self.inner.get_content()
+    }
+
+    /// Plain text with markup stripped
+    fn text(&self) -> String {
+        self.inner.get_text()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("EpubFile(href={:?})", self.inner.get_href())
+    }
+}
+
+/// I/O failures become `IOError`; anything else means the book is malformed
+fn to_py_err(e: Box<dyn Error>) -> PyErr {
+    if e.is::<std::io::Error>() {
+        PyIOError::new_err(e.to_string())
+    } else {
+        PyValueError::new_err(e.to_string())
+    }
+}
+
+/// Convert an XHTML string to plain text
+#[pyfunction]
+fn html_to_text(html: &str) -> String {
+    crate::text::html_to_text(html)
+}
+
+#[pymodule]
+fn epubie(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyEpub>()?;
+    m.add_class::<PyMetadata>()?;
+    m.add_class::<PyChapter>()?;
+    m.add_class::<PyEpubFile>()?;
+    m.add_function(wrap_pyfunction!(html_to_text, m)?)?;
+    Ok(())
+}
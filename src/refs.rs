@@ -0,0 +1,224 @@
+//! Finding and rewriting references between resources
+//!
+//! Content documents point at other resources through `src`, `href`,
+//! `xlink:href`, `poster` and `data` attributes; stylesheets through `url()`
+//! and `@import`; media overlays through `src` and `epub:textref`. Everything here works on archive paths, so callers don't
+//! need to care how each document spells its relative references.
+
+use crate::href;
+use regex::{Captures, Regex};
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// Media types whose contents can reference other resources
+pub(crate) const REFERENCING_MEDIA_TYPES: &[&str] = &[
+    "application/xhtml+xml",
+    "text/html",
+    "text/css",
+    "image/svg+xml",
+    "application/x-dtbncx+xml",
+    "application/smil+xml",
+];
+
+const ATTRIBUTE_PATTERN: &str =
+    r#"(?i)(\b(?:src|href|xlink:href|poster|data|textref)\s*=\s*)(["'])([^"']*)(["'])"#;
+const URL_PATTERN: &str = r#"(?i)(url\(\s*)(["']?)([^"')]+?)(["']?)(\s*\))"#;
+const IMPORT_PATTERN: &str = r#"(?i)(@import\s+)(["'])([^"']+)(["'])"#;
+static REFERENCES: LazyLock<[Regex; 3]> = LazyLock::new(|| {
+    [ATTRIBUTE_PATTERN, URL_PATTERN, IMPORT_PATTERN]
+        .map(|pattern| Regex::new(pattern).expect("reference pattern"))
+});
+
+/// Archive paths referenced from `content`, a document stored at `doc_path`
+///
+/// External URLs and same-document fragments (`#note1`) are left out.
+pub(crate) fn find(content: &str, doc_path: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    for re in REFERENCES.iter() {
+        for cap in re.captures_iter(content) {
+            if let Some(path) = target(doc_path, &cap[3]) {
+                found.push(path);
+            }
+        }
+    }
+    found
+}
+
+/// Absolute `http(s)` URLs that `content` loads as resources, with the byte range of each
+///
+/// Unlike `find`, hyperlinks (`<a href>`, `<area href>`) don't count: only
+/// what a reading system would fetch to render the document, such as
+/// images, stylesheets, fonts and media.
+pub(crate) fn find_remote(content: &str) -> Vec<(String, Range<usize>)> {
+    let mut found = Vec::new();
+    for (index, re) in REFERENCES.iter().enumerate() {
+        for cap in re.captures_iter(content) {
+            let value = cap.get(3).expect("reference value");
+            let url = value.as_str().trim();
+            if !is_remote(url) {
+                continue;
+            }
+            if index == 0
+                && cap[1]
+                    .trim_end_matches(['=', ' '])
+                    .eq_ignore_ascii_case("href")
+            {
+                // Only `<link>` and SVG `<image>`/`<use>` load what `href` points at
+                let start = cap.get(0).expect("whole match").start();
+                let element = content[..start]
+                    .rsplit_once('<')
+                    .map(|(_, tag)| {
+                        tag.split(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                            .next()
+                            .unwrap_or("")
+                            .to_ascii_lowercase()
+                    })
+                    .unwrap_or_default();
+                let local = element.rsplit(':').next().unwrap_or("");
+                if !matches!(local, "link" | "image" | "use") {
+                    continue;
+                }
+            }
+            found.push((url.to_string(), value.range()));
+        }
+    }
+    found.sort_by_key(|(_, range)| range.start);
+    found
+}
+
+/// Whether a reference is an absolute `http:` or `https:` URL
+pub(crate) fn is_remote(reference: &str) -> bool {
+    let lower = reference
+        .trim_start()
+        .get(..8)
+        .unwrap_or(reference)
+        .to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Rewrite references in `content`, a document moving from `doc_path` to `new_doc_path`
+///
+/// `rename` is given the archive path each reference resolves to and returns
+/// the new archive path, or `None` to leave the reference alone. Rewritten
+/// references are made relative to `new_doc_path` and keep their fragments.
+/// Returns `None` when nothing changed.
+pub(crate) fn rewrite(
+    content: &str,
+    doc_path: &str,
+    new_doc_path: &str,
+    rename: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    replace(content, doc_path, new_doc_path, rename, true)
+}
+
+/// Point references in `content`, a document stored at `doc_path`, at other files
+///
+/// Like `rewrite` for a document that stays put, except that redirected
+/// references drop their fragments, which wouldn't exist in the new target.
+pub(crate) fn redirect(
+    content: &str,
+    doc_path: &str,
+    rename: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    replace(content, doc_path, doc_path, rename, false)
+}
+
+fn replace(
+    content: &str,
+    doc_path: &str,
+    new_doc_path: &str,
+    rename: impl Fn(&str) -> Option<String>,
+    keep_fragments: bool,
+) -> Option<String> {
+    let mut text = content.to_string();
+    let mut changed = false;
+
+    for re in REFERENCES.iter() {
+        text = re
+            .replace_all(&text, |cap: &Captures| {
+                let reference = &cap[3];
+                let renamed = target(doc_path, reference).and_then(|path| rename(&path));
+                match renamed {
+                    Some(new_path) => {
+                        changed = true;
+                        let mut new_reference = href::relative(new_doc_path, &new_path);
+                        if let Some(fragment) = href::fragment(reference).filter(|_| keep_fragments)
+                        {
+                            new_reference = format!("{}#{}", new_reference, fragment);
+                        }
+                        let tail = cap.get(5).map_or("", |m| m.as_str());
+                        format!("{}{}{}{}{}", &cap[1], &cap[2], new_reference, &cap[4], tail)
+                    }
+                    None => cap[0].to_string(),
+                }
+            })
+            .into_owned();
+    }
+
+    changed.then_some(text)
+}
+
+/// Archive path a reference points at, if it points into the archive at all
+fn target(doc_path: &str, reference: &str) -> Option<String> {
+    let reference = reference.trim();
+    if reference.is_empty() || reference.starts_with('#') || href::has_scheme(reference) {
+        return None;
+    }
+    Some(href::resolve(doc_path, reference))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_and_rewrites_markup_and_css_references() {
+        let html = r##"<link href="../Styles/a.css"/><img src="../Images/c.jpg"/>
+            <a href="#n1">1</a><a href="http://x.org/">x</a><a href='ch2.xhtml#s'>2</a>"##;
+        assert_eq!(
+            find(html, "OEBPS/Text/ch1.xhtml"),
+            [
+                "OEBPS/Styles/a.css",
+                "OEBPS/Images/c.jpg",
+                "OEBPS/Text/ch2.xhtml"
+            ]
+        );
+
+        let rewritten = rewrite(
+            html,
+            "OEBPS/Text/ch1.xhtml",
+            "OEBPS/Text/ch1.xhtml",
+            |path| {
+                (path == "OEBPS/Text/ch2.xhtml").then(|| "OEBPS/Text/part2/ch2.xhtml".to_string())
+            },
+        )
+        .unwrap();
+        assert!(rewritten.contains("href='part2/ch2.xhtml#s'"));
+
+        let css = r#"@import "fonts.css"; body { background: url( '../Images/bg.png' ) }"#;
+        let rewritten = rewrite(
+            css,
+            "OEBPS/Styles/main.css",
+            "OEBPS/Styles/main.css",
+            |path| (path == "OEBPS/Images/bg.png").then(|| "OEBPS/Images/bg.webp".to_string()),
+        )
+        .unwrap();
+        assert!(rewritten.contains("url( '../Images/bg.webp' )"));
+        assert_eq!(
+            rewrite(
+                css,
+                "OEBPS/Styles/main.css",
+                "OEBPS/Styles/main.css",
+                |_| None
+            ),
+            None
+        );
+
+        // A moved document keeps pointing at the same files
+        let moved = rewrite(html, "OEBPS/Text/ch1.xhtml", "OEBPS/ch1.xhtml", |path| {
+            Some(path.to_string())
+        })
+        .unwrap();
+        assert!(moved.contains(r#"src="Images/c.jpg""#));
+    }
+}
@@ -0,0 +1,365 @@
+//! Panel-by-panel zoom regions for fixed-layout comics
+//!
+//! Guided view steps through a comic page one panel at a time, zooming into
+//! each. Books describe the panels in one of two ways:
+//!
+//! - an EPUB Region-Based Navigation `<nav epub:type="region-based">` in the
+//!   navigation document, whose links point at pages with a media fragment
+//!   such as `page1.xhtml#xywh=percent:5,5,40,45`
+//! - Kindle Panel View markup: elements carrying a `data-app-amzn-magnify`
+//!   JSON attribute that names the tap target, the magnified copy and its
+//!   position in the reading order
+//!
+//! `Epub::zoom_regions` reads both and lists each page's regions in reading
+//! order; a page covered by the region-based nav ignores Kindle markup. Kindle
+//! books usually position panels from a stylesheet, so a Kindle region only
+//! has a rectangle when its source element sets one in an inline `style`.
+
+use crate::epub::Epub;
+use crate::href;
+use crate::xhtml::{find_elements, parse_attributes};
+use std::collections::HashMap;
+
+/// The unit of a `RegionRect`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RegionUnit {
+    /// Percentages of the page's width and height
+    Percent,
+    /// CSS pixels of the page's viewport
+    Pixel,
+}
+
+/// Where a region is on its page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    unit: RegionUnit,
+}
+
+impl RegionRect {
+    pub fn get_x(&self) -> f64 {
+        self.x
+    }
+
+    pub fn get_y(&self) -> f64 {
+        self.y
+    }
+
+    pub fn get_width(&self) -> f64 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> f64 {
+        self.height
+    }
+
+    pub fn get_unit(&self) -> RegionUnit {
+        self.unit
+    }
+
+    /// Parse a `xywh=` media fragment, e.g. `xywh=percent:10,20,50,40`
+    pub fn from_fragment(fragment: &str) -> Option<Self> {
+        let value = fragment
+            .split('&')
+            .find_map(|part| part.trim().strip_prefix("xywh="))?;
+        let (unit, numbers) = match value.split_once(':') {
+            Some(("percent", numbers)) => (RegionUnit::Percent, numbers),
+            Some(("pixel", numbers)) => (RegionUnit::Pixel, numbers),
+            Some(_) => return None,
+            None => (RegionUnit::Pixel, value),
+        };
+        let numbers: Vec<f64> = numbers
+            .split(',')
+            .map(|n| n.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let [x, y, width, height] = numbers[..] else {
+            return None;
+        };
+        Some(RegionRect {
+            x,
+            y,
+            width,
+            height,
+            unit,
+        })
+    }
+}
+
+/// One panel of a page, from `Epub::zoom_regions`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZoomRegion {
+    ordinal: usize,
+    kind: Option<String>,
+    rect: Option<RegionRect>,
+    source_id: Option<String>,
+    target_id: Option<String>,
+}
+
+impl ZoomRegion {
+    /// Position in the page's panel order, starting at 1
+    pub fn get_ordinal(&self) -> usize {
+        self.ordinal
+    }
+
+    /// The region's `epub:type`, e.g. `panel` or `panel-group`
+    pub fn get_kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    pub fn get_rect(&self) -> Option<RegionRect> {
+        self.rect
+    }
+
+    /// Kindle `sourceId`: the id of the element tapped to zoom
+    pub fn get_source_id(&self) -> Option<&str> {
+        self.source_id.as_deref()
+    }
+
+    /// Kindle `targetId`: the id of the element shown magnified
+    pub fn get_target_id(&self) -> Option<&str> {
+        self.target_id.as_deref()
+    }
+}
+
+/// A page and its zoom regions in reading order
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageRegions {
+    href: String,
+    regions: Vec<ZoomRegion>,
+}
+
+impl PageRegions {
+    /// Href (relative to the OPF) of the page
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_regions(&self) -> &[ZoomRegion] {
+        &self.regions
+    }
+}
+
+impl Epub {
+    /// Zoom regions of every spine page that has any, in reading order
+    pub fn zoom_regions(&self) -> Vec<PageRegions> {
+        enter_span!("zoom_regions");
+        let mut navigated = self.region_nav();
+        self.get_spine_files()
+            .into_iter()
+            .filter_map(|file| {
+                let regions = navigated
+                    .remove(file.get_href())
+                    .unwrap_or_else(|| kindle_regions(file.get_content()));
+                (!regions.is_empty()).then(|| PageRegions {
+                    href: file.get_href().to_string(),
+                    regions,
+                })
+            })
+            .collect()
+    }
+
+    /// Zoom regions of the page at `href`, in reading order
+    pub fn get_zoom_regions(&self, href: &str) -> Vec<ZoomRegion> {
+        self.zoom_regions()
+            .into_iter()
+            .find(|page| page.href == href)
+            .map(|page| page.regions)
+            .unwrap_or_default()
+    }
+
+    /// Regions from the region-based nav, keyed by page href
+    fn region_nav(&self) -> HashMap<String, Vec<ZoomRegion>> {
+        let mut pages: HashMap<String, Vec<ZoomRegion>> = HashMap::new();
+        let Some(nav) = self.resources.iter().find(|r| r.has_property("nav")) else {
+            return pages;
+        };
+        let Some(bytes) = self.get_resource_bytes(&nav.href) else {
+            return pages;
+        };
+        let content = String::from_utf8_lossy(&bytes);
+        let nav_path = Self::resolve_path(&self.opf_path, &nav.href);
+        let page_hrefs: HashMap<String, &str> = self
+            .resources
+            .iter()
+            .map(|r| (Self::resolve_path(&self.opf_path, &r.href), r.href.as_str()))
+            .collect();
+
+        let navs = find_elements(&content, |e| {
+            e.name == "nav" && e.has_token("epub:type", "region-based")
+        });
+        for nav in navs {
+            for link in find_elements(nav.inner, |e| e.name == "a") {
+                let Some(target) = link.attribute("href") else {
+                    continue;
+                };
+                let Some(&page) = page_hrefs.get(&href::resolve(&nav_path, target)) else {
+                    continue;
+                };
+                let kind = link
+                    .attribute("epub:type")
+                    .map(str::to_string)
+                    .or_else(|| enclosing_item_type(nav.inner, link.range.start));
+                let regions = pages.entry(page.to_string()).or_default();
+                regions.push(ZoomRegion {
+                    ordinal: regions.len() + 1,
+                    kind,
+                    rect: href::fragment(target).and_then(RegionRect::from_fragment),
+                    source_id: None,
+                    target_id: None,
+                });
+            }
+        }
+        pages
+    }
+}
+
+/// The `epub:type` of the `<li>` whose start tag last precedes `position`
+fn enclosing_item_type(html: &str, position: usize) -> Option<String> {
+    let start = html[..position].rfind("<li")?;
+    let end = start + html[start..position].find('>')?;
+    parse_attributes(&html[start + 3..end])
+        .into_iter()
+        .find(|(name, _)| name == "epub:type")
+        .map(|(_, value)| value)
+}
+
+/// Regions from Kindle `data-app-amzn-magnify` markup, by `ordinal`
+fn kindle_regions(content: &str) -> Vec<ZoomRegion> {
+    if !content.contains("data-app-amzn-magnify") {
+        return Vec::new();
+    }
+    let style_of = |id: &str| {
+        find_elements(content, |e| e.attribute("id") == Some(id))
+            .first()
+            .and_then(|e| e.attribute("style"))
+            .map(str::to_string)
+    };
+
+    let mut regions: Vec<(i64, ZoomRegion)> =
+        find_elements(content, |e| e.attribute("data-app-amzn-magnify").is_some())
+            .iter()
+            .filter_map(|element| {
+                let json = element.attribute("data-app-amzn-magnify")?;
+                let value: serde_json::Value = serde_json::from_str(json).ok()?;
+                let text = |key: &str| value.get(key)?.as_str().map(str::to_string);
+                let source_id = text("sourceId");
+                let rect = source_id
+                    .as_deref()
+                    .and_then(style_of)
+                    .and_then(|style| style_rect(&style));
+                Some((
+                    value
+                        .get("ordinal")
+                        .and_then(|o| o.as_i64())
+                        .unwrap_or(i64::MAX),
+                    ZoomRegion {
+                        ordinal: 0,
+                        kind: Some("panel".to_string()),
+                        rect,
+                        source_id,
+                        target_id: text("targetId"),
+                    },
+                ))
+            })
+            .collect();
+    regions.sort_by_key(|(ordinal, _)| *ordinal);
+    regions
+        .into_iter()
+        .enumerate()
+        .map(|(index, (_, region))| ZoomRegion {
+            ordinal: index + 1,
+            ..region
+        })
+        .collect()
+}
+
+/// A rectangle from absolute positioning in an inline `style`
+fn style_rect(style: &str) -> Option<RegionRect> {
+    let mut values: HashMap<&str, (f64, RegionUnit)> = HashMap::new();
+    for declaration in style.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let parsed = if let Some(number) = value.strip_suffix('%') {
+            number.trim().parse().ok().map(|n| (n, RegionUnit::Percent))
+        } else {
+            let number = value.strip_suffix("px").unwrap_or(value);
+            number.trim().parse().ok().map(|n| (n, RegionUnit::Pixel))
+        };
+        if let Some(parsed) = parsed {
+            values.insert(property.trim(), parsed);
+        }
+    }
+    let (x, unit) = *values.get("left")?;
+    let (y, _) = *values.get("top")?;
+    let (width, _) = *values.get("width")?;
+    let (height, _) = *values.get("height")?;
+    let units = ["top", "width", "height"].map(|property| values[property].1);
+    if units.iter().any(|u| *u != unit) {
+        return None;
+    }
+    Some(RegionRect {
+        x,
+        y,
+        width,
+        height,
+        unit,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn regions_come_from_the_nav_and_from_kindle_markup() {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="p1" href="pages/p1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="p2" href="pages/p2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="p1"/><itemref idref="p2"/>"#,
+        );
+        let nav = r#"<html><body><nav epub:type="toc"><ol><li><a href="pages/p1.xhtml">1</a></li></ol></nav>
+<nav epub:type="region-based"><ol>
+  <li epub:type="panel"><a href="pages/p1.xhtml#xywh=percent:0,0,50,40"></a></li>
+  <li><a epub:type="panel" href="pages/p1.xhtml#xywh=100,200,300,400"></a></li>
+</ol></nav></body></html>"#;
+        let p2 = r#"<html><body>
+<div id="src2" style="position:absolute; top:50%; left:0%; width:100%; height:50%"></div>
+<a class="app-amzn-magnify" data-app-amzn-magnify='{"targetId":"mag2","sourceId":"src2","ordinal":2}'></a>
+<a class="app-amzn-magnify" data-app-amzn-magnify='{"targetId":"mag1","sourceId":"src1","ordinal":1}'></a>
+</body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            (
+                "pages/p1.xhtml",
+                "<html><body><img src='p1.jpg'/></body></html>",
+            ),
+            ("pages/p2.xhtml", p2),
+        ]))
+        .unwrap();
+
+        let pages = epub.zoom_regions();
+        assert_eq!(pages.len(), 2);
+        let first = pages[0].get_regions();
+        assert_eq!(first[0].get_kind(), Some("panel"));
+        let rect = first[0].get_rect().unwrap();
+        assert_eq!(rect.get_unit(), RegionUnit::Percent);
+        assert_eq!((rect.get_width(), rect.get_height()), (50.0, 40.0));
+        assert_eq!(first[1].get_rect().unwrap().get_unit(), RegionUnit::Pixel);
+
+        let second = epub.get_zoom_regions("pages/p2.xhtml");
+        let targets: Vec<_> = second.iter().map(ZoomRegion::get_target_id).collect();
+        assert_eq!(targets, [Some("mag1"), Some("mag2")]);
+        assert_eq!(second[1].get_ordinal(), 2);
+        assert!(second[0].get_rect().is_none());
+        assert_eq!(second[1].get_rect().unwrap().get_y(), 50.0);
+    }
+}
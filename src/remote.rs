@@ -0,0 +1,312 @@
+//! Opening books over HTTP
+//!
+//! `Epub::from_url` reads a book from a web server or object store with
+//! HTTP Range requests instead of downloading it first: the zip central
+//! directory at the end of the file, then each entry as it is needed.
+//! Content documents are read while parsing, but images, fonts and media
+//! are only fetched when asked for. Requires the `remote` feature.
+//!
+//! The file is fetched in blocks of `BLOCK_SIZE` bytes, each requested at
+//! most once per book. Servers that ignore Range requests send the whole
+//! file, which is then used as if passed to `Epub::from_bytes`.
+//!
+//! Each block request carries the file's ETag (or Last-Modified date) in
+//! `If-Range`, so a book replaced on the server while it is open fails to
+//! read instead of mixing bytes from two versions.
+
+use crate::archive::Source;
+use crate::epub::Epub;
+use crate::options::ParseOptions;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+/// Bytes fetched per Range request
+pub const BLOCK_SIZE: u64 = 64 * 1024;
+
+/// A file on an HTTP server, with the blocks fetched so far
+pub(crate) struct RemoteFile {
+    url: String,
+    len: u64,
+    /// The strong ETag or Last-Modified date seen when the file was opened
+    validator: Option<String>,
+    agent: ureq::Agent,
+    blocks: Mutex<HashMap<u64, Arc<[u8]>>>,
+}
+
+impl fmt::Debug for RemoteFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteFile")
+            .field("url", &self.url)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl RemoteFile {
+    /// The block starting at `start`, fetched on first use
+    fn block(&self, start: u64) -> io::Result<Arc<[u8]>> {
+        if let Some(block) = self
+            .blocks
+            .lock()
+            .ok()
+            .and_then(|blocks| blocks.get(&start).cloned())
+        {
+            return Ok(block);
+        }
+        let end = (start + BLOCK_SIZE).min(self.len) - 1;
+        trace_debug!(url = %self.url, start, end, "fetching range");
+        let mut request = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-{}", start, end));
+        if let Some(validator) = &self.validator {
+            request = request.set("If-Range", validator);
+        }
+        let response = request.call().map_err(io::Error::other)?;
+        let changed = match &self.validator {
+            Some(expected) => {
+                response.status() == 200
+                    || validator(&response).is_some_and(|validator| validator != *expected)
+            }
+            None => false,
+        };
+        if changed {
+            return Err(io::Error::other(format!(
+                "{} changed on the server since it was opened",
+                self.url
+            )));
+        }
+        if response.status() != 206 {
+            return Err(io::Error::other(format!(
+                "expected a partial response for bytes {}-{}, got status {}",
+                start,
+                end,
+                response.status()
+            )));
+        }
+        let mut bytes = Vec::with_capacity((end - start + 1) as usize);
+        response.into_reader().read_to_end(&mut bytes)?;
+        let block: Arc<[u8]> = bytes.into();
+        if let Ok(mut blocks) = self.blocks.lock() {
+            blocks.insert(start, Arc::clone(&block));
+        }
+        Ok(block)
+    }
+}
+
+/// A seekable reader over a `RemoteFile`
+pub(crate) struct RemoteReader {
+    file: Arc<RemoteFile>,
+    position: u64,
+}
+
+impl RemoteReader {
+    pub(crate) fn new(file: Arc<RemoteFile>) -> Self {
+        Self { file, position: 0 }
+    }
+}
+
+impl Read for RemoteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.file.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let start = self.position - self.position % BLOCK_SIZE;
+        let block = self.file.block(start)?;
+        let offset = (self.position - start) as usize;
+        let available = block.len().saturating_sub(offset);
+        if available == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "server sent a short range",
+            ));
+        }
+        let count = available.min(buf.len());
+        buf[..count].copy_from_slice(&block[offset..offset + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for RemoteReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.file.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file")
+        })?;
+        Ok(self.position)
+    }
+}
+
+impl Epub {
+    /// Open the EPUB at `url` with HTTP Range requests
+    ///
+    /// # Errors
+    /// HTTP errors (including error statuses) are passed through, as are
+    /// the errors `Epub::new` can return.
+    pub fn from_url(url: &str) -> Result<Epub, Box<dyn Error>> {
+        Self::from_url_with_options(url, ParseOptions::default())
+    }
+
+    /// Like `from_url`, but with non-default parse options
+    pub fn from_url_with_options(url: &str, options: ParseOptions) -> Result<Epub, Box<dyn Error>> {
+        enter_span!("epub", url = %url);
+        Self::from_source(open(url)?, options)
+    }
+}
+
+/// Find the length of the file at `url`, or download it when the server
+/// doesn't do ranges
+fn open(url: &str) -> Result<Source, Box<dyn Error>> {
+    let agent = ureq::AgentBuilder::new().build();
+    let response = agent.get(url).set("Range", "bytes=0-0").call()?;
+    if response.status() != 206 {
+        trace_warn!(url = %url, "server ignored the range request; downloading the whole book");
+        let mut bytes = Vec::new();
+        response.into_reader().read_to_end(&mut bytes)?;
+        return Ok(Source::Bytes(bytes.into()));
+    }
+
+    // Content-Range: bytes 0-0/12345
+    let len = response
+        .header("Content-Range")
+        .and_then(|range| range.rsplit_once('/'))
+        .and_then(|(_, len)| len.trim().parse::<u64>().ok())
+        .ok_or("partial response without a usable Content-Range length")?;
+    Ok(Source::Remote(Arc::new(RemoteFile {
+        url: url.to_string(),
+        len,
+        validator: validator(&response),
+        agent,
+        blocks: Mutex::new(HashMap::new()),
+    })))
+}
+
+/// What `If-Range` can carry: a strong ETag, else the Last-Modified date
+fn validator(response: &ureq::Response) -> Option<String> {
+    response
+        .header("ETag")
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| response.header("Last-Modified"))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serve `book` on a local port, with or without Range support
+    ///
+    /// The ETag is a hash of the current contents, and a Range request
+    /// whose `If-Range` doesn't match it gets the whole file.
+    fn serve(book: Arc<Mutex<Vec<u8>>>, ranges: bool) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/book.epub", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let book = book.lock().unwrap().clone();
+                let mut hasher = DefaultHasher::new();
+                book.hash(&mut hasher);
+                let etag = format!("\"{:x}\"", hasher.finish());
+                let mut range = None;
+                let mut if_range = None;
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("if-range: ") {
+                        if_range = Some(value.trim().to_string());
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = value.trim().split_once('-').unwrap();
+                        range = Some((
+                            start.parse::<usize>().unwrap(),
+                            end.parse::<usize>().unwrap(),
+                        ));
+                    }
+                    line.clear();
+                }
+                let fresh = if_range.is_none_or(|validator| validator == etag);
+                let (status, body, extra) = match range.filter(|_| ranges && fresh) {
+                    Some((start, end)) => {
+                        let end = end.min(book.len() - 1);
+                        let header =
+                            format!("Content-Range: bytes {}-{}/{}\r\n", start, end, book.len());
+                        ("206 Partial Content", &book[start..=end], header)
+                    }
+                    None => ("200 OK", &book[..], String::new()),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nETag: {}\r\n{}Connection: close\r\n\r\n",
+                    status,
+                    body.len(),
+                    etag,
+                    extra
+                );
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn books_open_over_http() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="pic" href="pic.png" media-type="image/png"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let book = build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("pic.png", "\u{89}PNG"),
+        ]);
+
+        let (url, requests) = serve(Arc::new(Mutex::new(book.clone())), true);
+        let epub = Epub::from_url(&url).unwrap();
+        assert_eq!(epub.get_title(), Some("T"));
+        assert_eq!(
+            epub.get_resource_bytes("pic.png").unwrap(),
+            "\u{89}PNG".as_bytes()
+        );
+        // The length probe, then one block covering the whole small book
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let (url, _) = serve(Arc::new(Mutex::new(book)), false);
+        let epub = Epub::from_url(&url).unwrap();
+        assert_eq!(epub.get_title(), Some("T"));
+    }
+
+    #[test]
+    fn a_book_replaced_on_the_server_fails_to_read() {
+        let book = Arc::new(Mutex::new(vec![1u8; 2 * BLOCK_SIZE as usize]));
+        let (url, _) = serve(Arc::clone(&book), true);
+        let Source::Remote(file) = open(&url).unwrap() else {
+            panic!("expected a ranged source");
+        };
+        assert_eq!(file.block(0).unwrap().len(), BLOCK_SIZE as usize);
+
+        *book.lock().unwrap() = vec![2u8; 2 * BLOCK_SIZE as usize];
+        // Cached blocks still read; fetching a new one notices the change
+        assert!(file.block(0).is_ok());
+        let error = file.block(BLOCK_SIZE).unwrap_err();
+        assert!(error.to_string().contains("changed on the server"));
+    }
+}
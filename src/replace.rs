@@ -0,0 +1,351 @@
+//! Find and replace across a book
+//!
+//! `Epub::replace_all` works on the text of content documents, never on
+//! tags, attribute values or comments, so replacing `class` can't break the
+//! markup. Matching sees text with entities decoded (`Fish &amp; chips`
+//! matches `Fish & chips`) and only the matched characters are rewritten,
+//! so the rest of the markup keeps its original spelling. A match can't run
+//! across a tag: `<i>Moby</i>-Dick` has no match for `Moby-Dick`.
+//!
+//! A dry run returns the same matches without changing anything, so an
+//! authoring tool can preview a replacement before committing to it.
+
+use crate::epub::{Epub, is_content_media_type};
+use crate::error::EpubError;
+use crate::nav::is_navigation;
+use crate::search::snippet;
+use crate::text::{ENTITY, decode_entities};
+use crate::xhtml::MARKUP;
+use regex::{Regex, RegexBuilder};
+
+/// Elements whose text is code rather than content
+const SKIPPED: &[&str] = &["script", "style"];
+
+/// How `Epub::replace_all` reads its pattern and what it does with matches
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceOptions {
+    regex: bool,
+    ignore_case: bool,
+    whole_word: bool,
+    dry_run: bool,
+}
+
+impl ReplaceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Treat the pattern as a regular expression, and let the replacement
+    /// refer to its groups as `$1` or `${name}`
+    pub fn with_regex(mut self, regex: bool) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    pub fn get_regex(&self) -> bool {
+        self.regex
+    }
+
+    pub fn with_ignore_case(mut self, ignore_case: bool) -> Self {
+        self.ignore_case = ignore_case;
+        self
+    }
+
+    pub fn get_ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// Only match where the pattern starts and ends at word boundaries
+    pub fn with_whole_word(mut self, whole_word: bool) -> Self {
+        self.whole_word = whole_word;
+        self
+    }
+
+    pub fn get_whole_word(&self) -> bool {
+        self.whole_word
+    }
+
+    /// Report the matches without changing the book
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn get_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn compile(&self, pattern: &str) -> Result<Regex, EpubError> {
+        let pattern = if self.regex {
+            pattern.to_string()
+        } else {
+            regex::escape(pattern)
+        };
+        let pattern = if self.whole_word {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map_err(|e| EpubError::InvalidPattern(e.to_string()))
+    }
+}
+
+/// One match found by `Epub::replace_all`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceMatch {
+    href: String,
+    position: usize,
+    text: String,
+    replacement: String,
+    snippet: String,
+}
+
+impl ReplaceMatch {
+    /// Href of the document containing the match
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Byte offset of the match in the document's markup before replacing
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    /// The matched text, entities decoded
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// What the match is (or would be) replaced with
+    pub fn get_replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    /// The match with some of the surrounding text
+    pub fn get_snippet(&self) -> &str {
+        &self.snippet
+    }
+}
+
+impl Epub {
+    /// Replace every match of `pattern` in the text of the content documents
+    ///
+    /// Navigation documents are left alone. Returns the matches in reading
+    /// order; with `ReplaceOptions::with_dry_run` nothing is replaced.
+    pub fn replace_all(
+        &mut self,
+        pattern: &str,
+        replacement: &str,
+        options: &ReplaceOptions,
+    ) -> Result<Vec<ReplaceMatch>, EpubError> {
+        enter_span!("replace_all");
+        let regex = options.compile(pattern)?;
+        let mut files: Vec<_> = self
+            .all_files
+            .iter()
+            .filter(|file| is_content_media_type(&file.media_type))
+            .filter(|file| {
+                !self
+                    .get_resource_by_href(&file.href)
+                    .is_some_and(is_navigation)
+            })
+            .collect();
+        files.sort_by_key(|file| file.spine_index.unwrap_or(usize::MAX));
+
+        let mut matches = Vec::new();
+        let mut updates = Vec::new();
+        for file in files {
+            let found = find_in_document(&file.content, &regex, replacement, options.regex);
+            if found.is_empty() {
+                continue;
+            }
+            let mut content = file.content.clone();
+            for (range, found) in found.iter().rev() {
+                content.replace_range(range.clone(), &escape_text(&found.replacement));
+            }
+            matches.extend(found.into_iter().map(|(_, found)| ReplaceMatch {
+                href: file.href.to_string(),
+                ..found
+            }));
+            updates.push((file.href.to_string(), content));
+        }
+
+        if !options.dry_run {
+            for (href, content) in updates {
+                self.put_resource_bytes(&href, content.into_bytes());
+            }
+        }
+        Ok(matches)
+    }
+}
+
+/// Matches in the body text of `html`, with the byte range each covers in
+/// the markup; the href is left for the caller to fill in
+fn find_in_document(
+    html: &str,
+    regex: &Regex,
+    replacement: &str,
+    expand: bool,
+) -> Vec<(std::ops::Range<usize>, ReplaceMatch)> {
+    let mut found = Vec::new();
+    let mut position = 0;
+    let mut in_body = false;
+    let mut skipped = 0usize;
+    let check = |start: usize, end: usize, found: &mut Vec<_>| {
+        let raw = &html[start..end];
+        let (text, offsets) = decode(raw);
+        let chars: Vec<char> = text.chars().collect();
+        for caps in regex.captures_iter(&text) {
+            let whole = caps.get(0).expect("whole match");
+            if whole.is_empty() {
+                continue;
+            }
+            let mut expanded = String::new();
+            if expand {
+                caps.expand(replacement, &mut expanded);
+            } else {
+                expanded.push_str(replacement);
+            }
+            let char_start = text[..whole.start()].chars().count();
+            let char_end = char_start + whole.as_str().chars().count();
+            let range = start + offsets[whole.start()]..start + offsets[whole.end()];
+            found.push((
+                range.clone(),
+                ReplaceMatch {
+                    href: String::new(),
+                    position: range.start,
+                    text: whole.as_str().to_string(),
+                    replacement: expanded,
+                    snippet: snippet(&chars, char_start, char_end),
+                },
+            ));
+        }
+    };
+    for markup in MARKUP.captures_iter(html) {
+        let whole = markup.get(0).expect("whole match");
+        if in_body && skipped == 0 && whole.start() > position {
+            check(position, whole.start(), &mut found);
+        }
+        position = whole.end();
+        let Some(name) = markup.get(2).map(|name| name.as_str().to_ascii_lowercase()) else {
+            continue;
+        };
+        let closing = !markup[1].is_empty();
+        if name == "body" {
+            in_body = !closing;
+        } else if SKIPPED.contains(&name.as_str()) && !whole.as_str().ends_with("/>") {
+            skipped = if closing {
+                skipped.saturating_sub(1)
+            } else {
+                skipped + 1
+            };
+        }
+    }
+    found
+}
+
+/// Decode the entities in a text node, with the byte offset in `raw` of
+/// every byte of the decoded text (plus one past the end)
+fn decode(raw: &str) -> (String, Vec<usize>) {
+    let mut text = String::with_capacity(raw.len());
+    let mut offsets = Vec::with_capacity(raw.len() + 1);
+    let mut position = 0;
+    let mut push = |decoded: &str, at: usize, text: &mut String| {
+        text.push_str(decoded);
+        offsets.extend(std::iter::repeat_n(at, decoded.len()));
+    };
+    for entity in ENTITY.find_iter(raw) {
+        for (i, c) in raw[position..entity.start()].char_indices() {
+            push(c.encode_utf8(&mut [0; 4]), position + i, &mut text);
+        }
+        push(&decode_entities(entity.as_str()), entity.start(), &mut text);
+        position = entity.end();
+    }
+    for (i, c) in raw[position..].char_indices() {
+        push(c.encode_utf8(&mut [0; 4]), position + i, &mut text);
+    }
+    offsets.push(raw.len());
+    (text, offsets)
+}
+
+/// Escape text for XML content, leaving quotes as they are
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book() -> Epub {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                r#"<html><head><title>Colour</title></head><body><p class="colour">Colour &amp; colours.</p><script>colour()</script></body></html>"#,
+            ),
+            ("ch2.xhtml", "<html><body><p>No match, Mr. Grey &amp; Co.</p></body></html>"),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn dry_run_reports_matches_without_editing() {
+        let mut epub = book();
+        let options = ReplaceOptions::new()
+            .with_ignore_case(true)
+            .with_whole_word(true)
+            .with_dry_run(true);
+        let matches = epub.replace_all("colour", "color", &options).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].get_href(), "ch1.xhtml");
+        assert_eq!(matches[0].get_text(), "Colour");
+        assert_eq!(matches[0].get_snippet(), "Colour & colours.");
+        assert!(
+            epub.get_all_files()[0]
+                .get_content()
+                .contains("Colour &amp;")
+        );
+    }
+
+    #[test]
+    fn text_is_replaced_outside_tags_and_scripts() {
+        let mut epub = book();
+        let options = ReplaceOptions::new().with_regex(true);
+        let matches = epub
+            .replace_all(r"(?i)(colou?r)(s?)", "[$1$2]", &options)
+            .unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[1].get_replacement(), "[colours]");
+        assert_eq!(
+            epub.get_all_files()[0].get_content(),
+            r#"<html><head><title>Colour</title></head><body><p class="colour">[Colour] &amp; [colours].</p><script>colour()</script></body></html>"#
+        );
+
+        let matches = epub
+            .replace_all(" & Co.", " and Company", &ReplaceOptions::new())
+            .unwrap();
+        assert_eq!(matches[0].get_position(), 33);
+        assert!(
+            epub.get_all_files()[1]
+                .get_content()
+                .contains("Mr. Grey and Company</p>")
+        );
+        assert!(matches!(
+            epub.replace_all("(", "", &ReplaceOptions::new().with_regex(true)),
+            Err(EpubError::InvalidPattern(_))
+        ));
+    }
+}
@@ -0,0 +1,279 @@
+//! Diagnostics collected while parsing an EPUB
+//!
+//! The parser is lenient: unreadable files are skipped and missing metadata is
+//! replaced by fallbacks rather than failing the whole book. A `ParseReport`
+//! records each of those decisions, plus how long every stage took, so
+//! ingestion pipelines can triage problem files after the fact.
+
+use crate::enrich::Provenance;
+use std::fmt;
+use std::time::Duration;
+
+/// A manifest entry that could not be loaded
+#[derive(Debug, Clone)]
+pub struct SkippedFile {
+    href: String,
+    reason: String,
+}
+
+impl SkippedFile {
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+/// An archive entry that doesn't match `META-INF/checksums.json`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    path: String,
+    expected: Option<String>,
+    actual: Option<String>,
+}
+
+impl ChecksumMismatch {
+    #[cfg(feature = "checksums")]
+    pub(crate) fn new(path: String, expected: Option<String>, actual: Option<String>) -> Self {
+        Self {
+            path,
+            expected,
+            actual,
+        }
+    }
+
+    /// Archive path of the entry
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    /// Recorded hex digest, `None` for an entry the manifest doesn't list
+    pub fn get_expected(&self) -> Option<&str> {
+        self.expected.as_deref()
+    }
+
+    /// Digest of the entry as found, `None` if it is missing from the archive
+    pub fn get_actual(&self) -> Option<&str> {
+        self.actual.as_deref()
+    }
+}
+
+/// How long a single parse stage took
+#[derive(Debug, Clone)]
+pub struct StageTiming {
+    stage: String,
+    duration: Duration,
+}
+
+impl StageTiming {
+    pub fn get_stage(&self) -> &str {
+        &self.stage
+    }
+
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+/// Everything notable that happened while parsing a book
+#[derive(Debug, Clone, Default)]
+pub struct ParseReport {
+    skipped_files: Vec<SkippedFile>,
+    fallbacks: Vec<String>,
+    checksum_mismatches: Vec<ChecksumMismatch>,
+    scrubbed: Vec<String>,
+    enriched: Vec<Provenance>,
+    timings: Vec<StageTiming>,
+}
+
+impl ParseReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Files listed in the manifest that could not be read
+    pub fn get_skipped_files(&self) -> &[SkippedFile] {
+        &self.skipped_files
+    }
+
+    /// Human-readable descriptions of fallback decisions, in the order they were made
+    pub fn get_fallbacks(&self) -> &[String] {
+        &self.fallbacks
+    }
+
+    /// Entries that failed checksum verification, when it was requested
+    pub fn get_checksum_mismatches(&self) -> &[ChecksumMismatch] {
+        &self.checksum_mismatches
+    }
+
+    /// What `Epub::scrub_personal_data` removed, in the order it was removed
+    pub fn get_scrubbed(&self) -> &[String] {
+        &self.scrubbed
+    }
+
+    /// What `Epub::enrich_metadata` filled in, in the order it was filled in
+    pub fn get_enriched(&self) -> &[Provenance] {
+        &self.enriched
+    }
+
+    /// Duration of each parse stage, in the order they ran
+    pub fn get_timings(&self) -> &[StageTiming] {
+        &self.timings
+    }
+
+    /// Total time spent across all recorded stages
+    pub fn get_total_duration(&self) -> Duration {
+        self.timings.iter().map(|t| t.duration).sum()
+    }
+
+    /// True when nothing was skipped, no fallbacks were needed and no checksums failed
+    pub fn is_clean(&self) -> bool {
+        self.skipped_files.is_empty()
+            && self.fallbacks.is_empty()
+            && self.checksum_mismatches.is_empty()
+    }
+
+    pub(crate) fn skip(&mut self, href: impl Into<String>, reason: impl Into<String>) {
+        self.skipped_files.push(SkippedFile {
+            href: href.into(),
+            reason: reason.into(),
+        });
+    }
+
+    pub(crate) fn fallback(&mut self, description: impl Into<String>) {
+        self.fallbacks.push(description.into());
+    }
+
+    #[cfg(feature = "checksums")]
+    pub(crate) fn checksum_mismatch(&mut self, mismatch: ChecksumMismatch) {
+        self.checksum_mismatches.push(mismatch);
+    }
+
+    pub(crate) fn scrubbed(&mut self, description: impl Into<String>) {
+        self.scrubbed.push(description.into());
+    }
+
+    pub(crate) fn enriched(&mut self, provenance: Provenance) {
+        self.enriched.push(provenance);
+    }
+
+    pub(crate) fn record_timing(&mut self, stage: impl Into<String>, duration: Duration) {
+        self.timings.push(StageTiming {
+            stage: stage.into(),
+            duration,
+        });
+    }
+}
+
+impl fmt::Display for ParseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for skipped in &self.skipped_files {
+            writeln!(f, "skipped {}: {}", skipped.href, skipped.reason)?;
+        }
+        for fallback in &self.fallbacks {
+            writeln!(f, "fallback: {}", fallback)?;
+        }
+        for mismatch in &self.checksum_mismatches {
+            let problem = match (&mismatch.expected, &mismatch.actual) {
+                (None, _) => "not in checksum manifest",
+                (_, None) => "missing from archive",
+                _ => "checksum mismatch",
+            };
+            writeln!(f, "{} {}", problem, mismatch.path)?;
+        }
+        for description in &self.scrubbed {
+            writeln!(f, "scrubbed: {}", description)?;
+        }
+        for provenance in &self.enriched {
+            writeln!(
+                f,
+                "enriched {} from {}: {}",
+                provenance.get_field(),
+                provenance.get_provider(),
+                provenance.get_value()
+            )?;
+        }
+        for timing in &self.timings {
+            writeln!(f, "{}: {:?}", timing.stage, timing.duration)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::test_support::CONTAINER;
+    use std::io::{Cursor, Write};
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn malformed_books_report_what_was_skipped_and_assumed() {
+        // No identifier, no navigation document, one document missing and
+        // one that isn't UTF-8
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>T</dc:title></metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="gone" href="gone.xhtml" media-type="application/xhtml+xml"/>
+    <item id="bad" href="bad.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/><itemref idref="gone"/><itemref idref="bad"/></spine>
+</package>"#;
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in [
+            ("META-INF/container.xml", CONTAINER.as_bytes()),
+            ("content.opf", opf.as_bytes()),
+            (
+                "ch1.xhtml",
+                b"<html><body><p>One</p></body></html>".as_slice(),
+            ),
+            (
+                "bad.xhtml",
+                b"<html><body>\xff\xfe</body></html>".as_slice(),
+            ),
+        ] {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        let epub = Epub::from_bytes(zip.finish().unwrap().into_inner()).unwrap();
+        let report = epub.get_parse_report();
+
+        let skipped: Vec<_> = report
+            .get_skipped_files()
+            .iter()
+            .map(|file| (file.get_href(), file.get_reason()))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                ("gone.xhtml", "content document missing from archive"),
+                ("bad.xhtml", "content document is not valid UTF-8"),
+            ]
+        );
+        assert_eq!(
+            report.get_fallbacks(),
+            [
+                "no navigation document in manifest",
+                "no dc:identifier; identifier left empty",
+                "1 content document(s) have no navigation title; using manifest IDs",
+            ]
+        );
+        assert!(!report.is_clean());
+        assert!(
+            report
+                .get_timings()
+                .iter()
+                .any(|timing| timing.get_stage() == "files")
+        );
+        let text = report.to_string();
+        assert!(text.contains("skipped gone.xhtml: content document missing from archive\n"));
+        assert!(text.contains("fallback: no navigation document in manifest\n"));
+
+        assert!(ParseReport::new().is_clean());
+    }
+}
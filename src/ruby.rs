@@ -0,0 +1,127 @@
+//! Ruby annotations (furigana)
+//!
+//! Japanese and Chinese books gloss words with `<ruby>`: the base text
+//! followed by its reading in `<rt>`, sometimes with fallback parentheses in
+//! `<rp>`. Left alone, plain-text extraction would run base and reading
+//! together ("漢字かんじ"), so `TextOptions::with_ruby` chooses what happens
+//! to readings, and `EpubFile::get_ruby_annotations` returns them as
+//! (base, reading) pairs.
+
+use crate::epub::EpubFile;
+use crate::text::html_to_text;
+use crate::xhtml::find_elements;
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+static READING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<rt\b[^>]*>(.*?)</rt\s*>").expect("rt pattern"));
+static FALLBACK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<rp\b[^>]*>.*?</rp\s*>").expect("rp pattern"));
+
+/// What plain-text extraction does with ruby readings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RubyText {
+    /// Keep only the base text: "漢字"
+    #[default]
+    Strip,
+    /// Follow each base with its reading in parentheses: "漢字(かんじ)"
+    Parenthesized,
+}
+
+/// A base text and its reading
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RubyAnnotation {
+    base: String,
+    reading: String,
+}
+
+impl RubyAnnotation {
+    pub fn get_base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn get_reading(&self) -> &str {
+        &self.reading
+    }
+}
+
+impl EpubFile {
+    /// Every base and reading pair in the document, in order
+    ///
+    /// A `<ruby>` with several `<rt>`s gives one pair per reading.
+    pub fn get_ruby_annotations(&self) -> Vec<RubyAnnotation> {
+        let mut annotations = Vec::new();
+        for ruby in find_elements(&self.content, |e| e.name == "ruby") {
+            let inner = strip_fallbacks(ruby.inner);
+            let mut base_start = 0;
+            for cap in READING.captures_iter(&inner) {
+                let whole = cap.get(0).expect("whole match");
+                let base = html_to_text(&inner[base_start..whole.start()]).replace('\n', "");
+                let reading = html_to_text(&cap[1]).replace('\n', "");
+                base_start = whole.end();
+                if !base.is_empty() || !reading.is_empty() {
+                    annotations.push(RubyAnnotation { base, reading });
+                }
+            }
+        }
+        annotations
+    }
+}
+
+/// Apply `mode` to the ruby readings in `html`
+pub(crate) fn render(html: &str, mode: RubyText) -> String {
+    let html = strip_fallbacks(html);
+    READING
+        .replace_all(&html, |cap: &Captures| match mode {
+            RubyText::Strip => String::new(),
+            RubyText::Parenthesized => format!("({})", &cap[1]),
+        })
+        .into_owned()
+}
+
+/// Drop `<rp>` fallback parentheses, which only readers without ruby support show
+fn strip_fallbacks(html: &str) -> String {
+    FALLBACK.replace_all(html, "").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::options::TextOptions;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn readings_are_stripped_parenthesized_or_paired() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html><body><p><ruby>漢<rp>(</rp><rt>かん</rt><rp>)</rp>字<rt>じ</rt></ruby>を<ruby><rb>読</rb><rt>よ</rt></ruby>む</p></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let file = &epub.get_spine_files()[0];
+        assert_eq!(file.get_text(), "漢字を読む");
+        let parenthesized = TextOptions::new().with_ruby(RubyText::Parenthesized);
+        assert_eq!(
+            file.get_text_with(&parenthesized),
+            "漢(かん)字(じ)を読(よ)む"
+        );
+        assert_eq!(
+            epub.get_chapters()[0].get_text_with(&parenthesized),
+            "漢(かん)字(じ)を読(よ)む"
+        );
+
+        let annotations = file.get_ruby_annotations();
+        let pairs: Vec<(&str, &str)> = annotations
+            .iter()
+            .map(|a| (a.get_base(), a.get_reading()))
+            .collect();
+        assert_eq!(pairs, [("漢", "かん"), ("字", "じ"), ("読", "よ")]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Retail samples
+//!
+//! Stores hand out the opening of a book as a free sample: the front matter
+//! and the first chapter or two, followed by a page saying the sample ends
+//! there. `Epub::make_sample` builds one out of the editing API. Spine
+//! documents after the cut are removed, and links into them from the pages
+//! that remain point at the end-of-sample page instead. The navigation
+//! document and NCX are regenerated from what is left, and images, fonts and
+//! stylesheets that only the removed chapters used are pruned. The result is
+//! an ordinary `Epub`, saved like any other.
+//!
+//! The cut always falls between chapters, so a sample never ends mid-scene.
+
+use crate::epub::{Chapter, Epub};
+use crate::error::EpubError;
+use crate::nav::is_navigation;
+use crate::refs::{self, REFERENCING_MEDIA_TYPES};
+use crate::text::escape_xml;
+use crate::xhtml;
+use std::collections::HashSet;
+
+const END_TITLE: &str = "End of sample";
+
+/// How much of the book `Epub::make_sample` keeps, besides the front matter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    /// The first `n` chapters of body matter
+    Chapters(usize),
+    /// Whole chapters from the start until at least this percentage of the
+    /// book's text is included
+    Percent(f64),
+}
+
+impl Epub {
+    /// A copy of the book cut down to a sample, ending with an "End of sample" page
+    ///
+    /// Front matter is always kept. Links from the sample into the removed
+    /// part lead to the end page, and the navigation only lists what is left.
+    pub fn make_sample(&self, size: SampleSize) -> Result<Epub, EpubError> {
+        enter_span!("make_sample");
+        let cut = self.sample_cut(size);
+        let removed: Vec<String> = self.spine[cut..]
+            .iter()
+            .filter_map(|id| self.get_resource_by_id(id))
+            .map(|resource| resource.href.clone())
+            .collect();
+
+        let mut sample = self.clone();
+        for href in &removed {
+            // A document can appear in the spine more than once
+            let _ = sample.remove_resource(href);
+        }
+        let end_href = sample.append_end_page()?;
+
+        let end_path = Self::resolve_path(&sample.opf_path, &end_href);
+        let removed_paths: HashSet<String> = removed
+            .iter()
+            .map(|href| Self::resolve_path(&sample.opf_path, href))
+            .collect();
+        let redirected: Vec<(String, String)> = sample
+            .resources
+            .iter()
+            .filter(|resource| {
+                REFERENCING_MEDIA_TYPES.contains(&resource.media_type.as_str())
+                    && !is_navigation(resource)
+            })
+            .filter_map(|resource| {
+                let content = String::from_utf8(sample.get_resource_bytes(&resource.href)?).ok()?;
+                let path = Self::resolve_path(&sample.opf_path, &resource.href);
+                let content = refs::redirect(&content, &path, |target| {
+                    removed_paths.contains(target).then(|| end_path.clone())
+                })?;
+                Some((resource.href.clone(), content))
+            })
+            .collect();
+        for (href, content) in redirected {
+            sample.put_resource_bytes(&href, content.into_bytes());
+        }
+
+        sample.regenerate_navigation();
+        sample.prune_orphans();
+        Ok(sample)
+    }
+
+    /// Position in the spine of the first itemref left out of the sample
+    fn sample_cut(&self, size: SampleSize) -> usize {
+        let index = matches!(size, SampleSize::Percent(_)).then(|| self.text_index());
+        let chars_in = |chapter: &Chapter| -> usize {
+            let Some(index) = &index else {
+                return 0;
+            };
+            chapter
+                .files
+                .iter()
+                .filter_map(|file| index.get_span(&file.href))
+                .map(|span| span.get_char_count())
+                .sum()
+        };
+        let total = index.as_ref().map_or(0, |index| index.get_total_chars());
+
+        let (mut chapters, mut chars) = (0, 0);
+        for chapter in &self.chapters {
+            let Some(start) = chapter
+                .files
+                .iter()
+                .filter_map(|file| file.spine_index)
+                .min()
+            else {
+                continue;
+            };
+            let front_matter = chapter
+                .files
+                .iter()
+                .all(|file| file.semantic_role.is_front_matter());
+            let enough = match size {
+                SampleSize::Chapters(n) => chapters >= n,
+                SampleSize::Percent(percent) => chars as f64 >= total as f64 * percent / 100.0,
+            };
+            if enough && !front_matter {
+                return start;
+            }
+            chapters += usize::from(!front_matter);
+            chars += chars_in(chapter);
+        }
+        self.spine.len()
+    }
+
+    /// Add the end-of-sample page to the end of the spine and table of
+    /// contents, returning its href
+    fn append_end_page(&mut self) -> Result<String, EpubError> {
+        let language = self.metadata.language.as_deref().unwrap_or("und");
+        let message = match self.get_title() {
+            Some(title) => format!(
+                "This is the end of the sample of <i>{}</i>.",
+                escape_xml(title)
+            ),
+            None => "This is the end of the sample.".to_string(),
+        };
+        let body = format!(
+            "<section>\n<h1>{}</h1>\n<p>{}</p>\n</section>",
+            END_TITLE, message
+        );
+        let page = xhtml::document(END_TITLE, language, "", None, &body);
+
+        let href = self.unique_resource_href("sample-end", "xhtml");
+        let id = self.add_resource(&href, "application/xhtml+xml", page.into_bytes())?;
+        self.spine.push(id.clone());
+        self.reindex_spine();
+        if let Some(file) = self.all_files.iter().find(|file| *file.id == *id) {
+            self.chapters.push(Chapter {
+                title: END_TITLE.to_string(),
+                files: vec![file.clone()],
+                language: self.metadata.language.clone(),
+            });
+        }
+        self.table_of_contents
+            .add_entry(END_TITLE.to_string(), href.as_str(), 0);
+        Ok(href)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book() -> Epub {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="title" href="titlepage.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch3" href="ch3.xhtml" media-type="application/xhtml+xml"/>
+               <item id="map" href="map.png" media-type="image/png"/>"#,
+            r#"<itemref idref="title"/><itemref idref="ch1"/><itemref idref="ch2"/><itemref idref="ch3"/>"#,
+        );
+        let nav = r#"<html xmlns:epub="http://www.idpf.org/2007/ops"><body><nav epub:type="toc"><ol>
+<li><a href="ch1.xhtml">One</a></li><li><a href="ch2.xhtml">Two</a></li><li><a href="ch3.xhtml">Three</a></li>
+</ol></nav></body></html>"#;
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("nav.xhtml", nav),
+            (
+                "titlepage.xhtml",
+                "<html><body><h1>Moby-Dick</h1></body></html>",
+            ),
+            (
+                "ch1.xhtml",
+                r#"<html><body><h1>One</h1><p>Call me Ishmael. See <a href="ch3.xhtml#map">the map</a>.</p></body></html>"#,
+            ),
+            (
+                "ch2.xhtml",
+                "<html><body><h1>Two</h1><p>Some years ago, never mind how long precisely.</p></body></html>",
+            ),
+            (
+                "ch3.xhtml",
+                r#"<html><body><h1>Three</h1><img id="map" src="map.png"/></body></html>"#,
+            ),
+            ("map.png", "png"),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn sample_keeps_the_opening_and_ends_with_a_notice() {
+        let sample = book().make_sample(SampleSize::Chapters(1)).unwrap();
+        let spine: Vec<&str> = sample
+            .get_spine_files()
+            .iter()
+            .map(|file| file.get_href())
+            .collect();
+        assert_eq!(spine, ["titlepage.xhtml", "ch1.xhtml", "sample-end.xhtml"]);
+        assert!(sample.get_resource_by_href("map.png").is_none());
+
+        let ch1 = String::from_utf8(sample.get_resource_bytes("ch1.xhtml").unwrap()).unwrap();
+        assert!(ch1.contains(r#"<a href="sample-end.xhtml">the map</a>"#));
+        let nav = String::from_utf8(sample.get_resource_bytes("nav.xhtml").unwrap()).unwrap();
+        assert!(nav.contains("ch1.xhtml") && !nav.contains("ch2.xhtml"));
+        assert!(nav.contains(END_TITLE));
+
+        let saved = Epub::from_bytes(sample.to_bytes().unwrap()).unwrap();
+        assert_eq!(saved.get_spine().len(), 3);
+        assert!(
+            saved.get_spine_files()[2]
+                .get_text()
+                .contains("end of the sample")
+        );
+    }
+
+    #[test]
+    fn percent_cuts_at_a_chapter_boundary() {
+        let epub = book();
+        let spine_len = |size| epub.make_sample(size).unwrap().get_spine().len();
+        assert_eq!(spine_len(SampleSize::Percent(0.0)), 2);
+        assert_eq!(spine_len(SampleSize::Percent(30.0)), 3);
+        assert_eq!(spine_len(SampleSize::Percent(100.0)), 5);
+        assert_eq!(spine_len(SampleSize::Chapters(0)), 2);
+    }
+}
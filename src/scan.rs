@@ -0,0 +1,203 @@
+//! Scanning book text for content-rating pipelines
+//!
+//! `Epub::scan_content` extracts the text of each chapter file once and hands
+//! it to a `ContentMatcher`, which says what it found and where. Results come
+//! back with the same locators as `Epub::search`. `WordList` covers the
+//! common case of whole-word lists (profanity, slurs, trigger terms); a
+//! `Regex` works as a matcher on its own, and anything else can implement
+//! the trait.
+
+use crate::epub::Epub;
+use crate::search::snippet;
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+/// Finds matches in the plain text of one file
+pub trait ContentMatcher {
+    /// Byte ranges of `text` that match, each with a label for what was found
+    /// (a category, a rule name, ...)
+    fn find_matches(&mut self, text: &str) -> Vec<(Range<usize>, String)>;
+}
+
+/// Every match of the pattern, labelled with the pattern itself
+impl ContentMatcher for Regex {
+    fn find_matches(&mut self, text: &str) -> Vec<(Range<usize>, String)> {
+        self.find_iter(text)
+            .map(|found| (found.range(), self.as_str().to_string()))
+            .collect()
+    }
+}
+
+/// Case-insensitive whole-word lists, one per label
+#[derive(Debug, Clone, Default)]
+pub struct WordList {
+    lists: Vec<(String, Regex)>,
+}
+
+impl WordList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add words to look for under `label`
+    ///
+    /// Entries may be phrases; they only match at word boundaries, so "ass"
+    /// doesn't match "class".
+    pub fn with_words(mut self, label: &str, words: &[&str]) -> Self {
+        let alternatives: Vec<String> = words
+            .iter()
+            .filter(|word| !word.trim().is_empty())
+            .map(|word| regex::escape(word.trim()))
+            .collect();
+        if alternatives.is_empty() {
+            return self;
+        }
+        let pattern = format!(r"\b(?:{})\b", alternatives.join("|"));
+        if let Ok(regex) = RegexBuilder::new(&pattern).case_insensitive(true).build() {
+            self.lists.push((label.to_string(), regex));
+        }
+        self
+    }
+}
+
+impl ContentMatcher for WordList {
+    fn find_matches(&mut self, text: &str) -> Vec<(Range<usize>, String)> {
+        let mut matches: Vec<(Range<usize>, String)> = self
+            .lists
+            .iter()
+            .flat_map(|(label, regex)| {
+                regex
+                    .find_iter(text)
+                    .map(move |found| (found.range(), label.clone()))
+            })
+            .collect();
+        matches.sort_by_key(|(range, _)| range.start);
+        matches
+    }
+}
+
+/// Something a matcher found, and where
+#[derive(Debug, Clone)]
+pub struct ContentMatch {
+    chapter_index: usize,
+    href: String,
+    offset: usize,
+    text: String,
+    label: String,
+    snippet: String,
+}
+
+impl ContentMatch {
+    /// Index of the chapter containing the match
+    pub fn get_chapter_index(&self) -> usize {
+        self.chapter_index
+    }
+
+    /// Href of the file containing the match
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Character offset of the match within the file's extracted text
+    pub fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The matched text
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// The label the matcher gave the match
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    /// The match with some surrounding text
+    pub fn get_snippet(&self) -> &str {
+        &self.snippet
+    }
+}
+
+impl Epub {
+    /// Run `matcher` over the extracted text of every chapter file
+    ///
+    /// Matches are returned in reading order. Ranges that don't fall on
+    /// character boundaries are ignored.
+    pub fn scan_content<M: ContentMatcher + ?Sized>(&self, matcher: &mut M) -> Vec<ContentMatch> {
+        let mut matches = Vec::new();
+        for (chapter_index, chapter) in self.get_chapters().iter().enumerate() {
+            for file in chapter.get_files() {
+                let text = file.get_text();
+                let found = matcher.find_matches(&text);
+                if found.is_empty() {
+                    continue;
+                }
+                let chars: Vec<char> = text.chars().collect();
+                for (range, label) in found {
+                    let (Some(prefix), Some(matched)) = (text.get(..range.start), text.get(range))
+                    else {
+                        continue;
+                    };
+                    let offset = prefix.chars().count();
+                    matches.push(ContentMatch {
+                        chapter_index,
+                        href: file.get_href().to_string(),
+                        offset,
+                        text: matched.to_string(),
+                        label,
+                        snippet: snippet(&chars, offset, offset + matched.chars().count()),
+                    });
+                }
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn matchers_report_located_matches() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                "<html><body><p>Ça va? Darn it, the classy duel ended in BLOOD. Darn.</p></body></html>",
+            ),
+        ]))
+        .unwrap();
+
+        let mut words = WordList::new()
+            .with_words("mild", &["darn", "ass"])
+            .with_words("violence", &["blood", "duel"]);
+        let matches = epub.scan_content(&mut words);
+        let found: Vec<(&str, &str)> = matches
+            .iter()
+            .map(|m| (m.get_text(), m.get_label()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Darn", "mild"),
+                ("duel", "violence"),
+                ("BLOOD", "violence"),
+                ("Darn", "mild")
+            ]
+        );
+        assert_eq!(matches[0].get_offset(), 7);
+        assert_eq!(matches[0].get_href(), "ch1.xhtml");
+        assert!(matches[2].get_snippet().contains("ended in BLOOD."));
+
+        let mut pattern = Regex::new(r"\d+").unwrap();
+        assert!(epub.scan_content(&mut pattern).is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! Full-text search across the chapters of an EPUB
+
+use crate::epub::Epub;
+
+/// Number of characters of context shown on each side of a match
+const SNIPPET_CONTEXT: usize = 40;
+
+/// A single occurrence of a search query
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    chapter_index: usize,
+    href: String,
+    offset: usize,
+    global_offset: Option<usize>,
+    snippet: String,
+}
+
+impl SearchMatch {
+    /// Index of the chapter containing the match
+    pub fn get_chapter_index(&self) -> usize {
+        self.chapter_index
+    }
+
+    /// Href of the file containing the match
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Character offset of the match within the file's extracted text
+    pub fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Character offset of the match in the text of the whole book, as in
+    /// `TextIndex`; `None` for files outside the spine
+    pub fn get_global_offset(&self) -> Option<usize> {
+        self.global_offset
+    }
+
+    /// The match with some surrounding text
+    pub fn get_snippet(&self) -> &str {
+        &self.snippet
+    }
+}
+
+impl Epub {
+    /// Case-insensitive search of the extracted text of every chapter file
+    ///
+    /// Matches are returned in reading order.
+    pub fn search(&self, query: &str) -> Vec<SearchMatch> {
+        let needle: Vec<char> = query.chars().map(fold_char).collect();
+        let mut matches = Vec::new();
+
+        if needle.is_empty() {
+            return matches;
+        }
+        let index = self.text_index();
+
+        for (chapter_index, chapter) in self.get_chapters().iter().enumerate() {
+            for file in chapter.get_files() {
+                let text: Vec<char> = file.get_text().chars().collect();
+                let haystack: Vec<char> = text.iter().copied().map(fold_char).collect();
+
+                for offset in find_all(&haystack, &needle) {
+                    matches.push(SearchMatch {
+                        chapter_index,
+                        href: file.get_href().to_string(),
+                        offset,
+                        global_offset: index.to_global(file.get_href(), offset),
+                        snippet: snippet(&text, offset, offset + needle.len()),
+                    });
+                }
+            }
+        }
+
+        matches
+    }
+}
+
+/// The characters `start..end` of `text` with some context on each side
+pub(crate) fn snippet(text: &[char], start: usize, end: usize) -> String {
+    let start = start.saturating_sub(SNIPPET_CONTEXT);
+    let end = (end + SNIPPET_CONTEXT).min(text.len());
+    text[start..end]
+        .iter()
+        .map(|&c| if c == '\n' { ' ' } else { c })
+        .collect()
+}
+
+/// Lowercase a single character so offsets in the folded text stay aligned
+fn fold_char(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Find the start offsets of all non-overlapping occurrences of `needle`
+fn find_all(haystack: &[char], needle: &[char]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    let mut i = 0;
+
+    while i + needle.len() <= haystack.len() {
+        if haystack[i..i + needle.len()] == *needle {
+            offsets.push(i);
+            i += needle.len();
+        } else {
+            i += 1;
+        }
+    }
+
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book(chapters: &[&str]) -> Epub {
+        let manifest: String = (1..=chapters.len())
+            .map(|n| {
+                format!(
+                    r#"<item id="ch{0}" href="ch{0}.xhtml" media-type="application/xhtml+xml"/>"#,
+                    n
+                )
+            })
+            .collect();
+        let spine: String = (1..=chapters.len())
+            .map(|n| format!(r#"<itemref idref="ch{}"/>"#, n))
+            .collect();
+        let opf = package(&manifest, &spine);
+        let names: Vec<String> = (1..=chapters.len())
+            .map(|n| format!("ch{}.xhtml", n))
+            .collect();
+        let mut files = vec![("META-INF/container.xml", CONTAINER), ("content.opf", &opf)];
+        files.extend(
+            names
+                .iter()
+                .map(String::as_str)
+                .zip(chapters.iter().copied()),
+        );
+        Epub::from_bytes(build(&files)).unwrap()
+    }
+
+    #[test]
+    fn matches_ignore_case_and_count_characters() {
+        let epub = book(&[
+            "<html><body><p>Über den Fluß, über die Brücke</p></body></html>",
+            "<html><body><p>ÜBER alles</p></body></html>",
+        ]);
+
+        // Without a navigation document both files make up one chapter
+        let matches = epub.search("über");
+        let found: Vec<(usize, &str, usize)> = matches
+            .iter()
+            .map(|m| (m.get_chapter_index(), m.get_href(), m.get_offset()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0, "ch1.xhtml", 0),
+                (0, "ch1.xhtml", 15),
+                (0, "ch2.xhtml", 0)
+            ]
+        );
+        assert_eq!(matches[1].get_snippet(), "Über den Fluß, über die Brücke");
+        assert_eq!(matches[0].get_global_offset(), Some(0));
+        assert!(epub.search("").is_empty());
+        assert!(epub.search("Donau").is_empty());
+    }
+
+    #[test]
+    fn snippets_are_cut_on_character_boundaries() {
+        let text = format!("{}Ziel{}", "é".repeat(50), "ü".repeat(50));
+        let epub = book(&[&format!("<html><body><p>{}</p></body></html>", text)]);
+
+        let matches = epub.search("ZIEL");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].get_offset(), 50);
+        assert_eq!(
+            matches[0].get_snippet(),
+            format!("{}Ziel{}", "é".repeat(40), "ü".repeat(40))
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//! Semantic roles of spine items
+//!
+//! Readers often want to skip straight past the cover, title page and
+//! copyright notice. EPUB has several overlapping ways of saying what a
+//! document is for, so a file's role is taken from the first source that
+//! has an opinion:
+//!
+//! 1. an `epub:type` on the document's `<body>` or first `<section>`
+//! 2. the EPUB 3 landmarks nav or EPUB 2 `<guide>` entry pointing at it
+//! 3. well-known words in its file name or manifest ID
+//! 4. its position relative to the start of the body matter
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::LazyLock;
+
+/// What a content document is for within the book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SemanticRole {
+    Cover,
+    TitlePage,
+    Copyright,
+    Dedication,
+    TocPage,
+    /// Front matter that doesn't fit a more specific role (foreword, preface, epigraph, ...)
+    FrontMatter,
+    Body,
+    BackMatter,
+}
+
+impl SemanticRole {
+    /// True for everything that comes before the body of the book
+    pub fn is_front_matter(&self) -> bool {
+        !matches!(self, SemanticRole::Body | SemanticRole::BackMatter)
+    }
+
+    /// Map an `epub:type` or guide `type` value to a role
+    pub fn from_type(value: &str) -> Option<SemanticRole> {
+        let value = value.to_ascii_lowercase();
+        let value = value.strip_prefix("other.").unwrap_or(&value);
+        let role = match value {
+            "cover" => SemanticRole::Cover,
+            "titlepage" | "title-page" | "halftitlepage" | "fulltitle" | "title" => {
+                SemanticRole::TitlePage
+            }
+            "copyright-page" | "copyright" | "imprint" => SemanticRole::Copyright,
+            "dedication" => SemanticRole::Dedication,
+            "toc" | "loi" | "lot" | "list-of-illustrations" | "list-of-tables" => {
+                SemanticRole::TocPage
+            }
+            "frontmatter" | "foreword" | "preface" | "prologue" | "epigraph" | "introduction"
+            | "preamble" | "halftitle" => SemanticRole::FrontMatter,
+            "bodymatter" | "text" | "chapter" | "part" | "division" | "volume" => {
+                SemanticRole::Body
+            }
+            "backmatter" | "afterword" | "appendix" | "colophon" | "glossary" | "index"
+            | "bibliography" | "notes" | "endnotes" | "rearnotes" | "acknowledgments"
+            | "acknowledgements" | "contributors" | "other-credits" => SemanticRole::BackMatter,
+            _ => return None,
+        };
+        Some(role)
+    }
+
+    /// Guess a role from a file name or manifest ID
+    pub fn from_name(name: &str) -> Option<SemanticRole> {
+        let name = name.rsplit('/').next().unwrap_or(name).to_ascii_lowercase();
+        let stem = name.split('.').next().unwrap_or(&name);
+        let tokens: Vec<&str> = stem
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|token| !token.is_empty())
+            .collect();
+        let has_token = |words: &[&str]| tokens.iter().any(|token| words.contains(token));
+
+        let role = if has_token(&["cover"]) || stem.starts_with("cover") {
+            SemanticRole::Cover
+        } else if stem.contains("titlepage") || stem.contains("halftitle") || has_token(&["title"])
+        {
+            SemanticRole::TitlePage
+        } else if stem.contains("copyright") || has_token(&["imprint"]) {
+            SemanticRole::Copyright
+        } else if stem.contains("dedication") {
+            SemanticRole::Dedication
+        } else if has_token(&["toc", "contents"]) {
+            SemanticRole::TocPage
+        } else if has_token(&[
+            "foreword",
+            "preface",
+            "prologue",
+            "epigraph",
+            "introduction",
+        ]) {
+            SemanticRole::FrontMatter
+        } else if stem.contains("acknowledg")
+            || stem.contains("bibliography")
+            || has_token(&[
+                "afterword",
+                "appendix",
+                "colophon",
+                "glossary",
+                "index",
+                "endnotes",
+                "notes",
+            ])
+        {
+            SemanticRole::BackMatter
+        } else {
+            return None;
+        };
+        Some(role)
+    }
+}
+
+impl fmt::Display for SemanticRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SemanticRole::Cover => "cover",
+            SemanticRole::TitlePage => "title page",
+            SemanticRole::Copyright => "copyright",
+            SemanticRole::Dedication => "dedication",
+            SemanticRole::TocPage => "table of contents",
+            SemanticRole::FrontMatter => "front matter",
+            SemanticRole::Body => "body",
+            SemanticRole::BackMatter => "back matter",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A structural landmark from the landmarks nav or the EPUB 2 guide
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Landmark {
+    pub(crate) kind: String,
+    pub(crate) href: String,
+}
+
+impl Landmark {
+    pub fn new(kind: impl Into<String>, href: impl Into<String>) -> Self {
+        Self {
+            kind: kind.into(),
+            href: href.into(),
+        }
+    }
+
+    /// The `epub:type` (EPUB 3) or guide `type` (EPUB 2), e.g. `bodymatter` or `text`
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// Target of the landmark relative to the OPF file, including any fragment
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// Role this landmark assigns to the document it points at
+    pub fn get_role(&self) -> Option<SemanticRole> {
+        SemanticRole::from_type(&self.kind)
+    }
+
+    /// Whether this landmark marks where the body of the book begins
+    pub fn is_body_start(&self) -> bool {
+        matches!(self.kind.as_str(), "bodymatter" | "text")
+    }
+}
+
+/// Role declared by an `epub:type` on the document's `<body>` or first `<section>`
+pub(crate) fn role_from_content(html: &str) -> Option<SemanticRole> {
+    static DECLARED_TYPE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?is)<(?:body|section)\b[^>]*\bepub:type\s*=\s*["']([^"']+)["']"#)
+            .expect("declared type pattern")
+    });
+    let types = DECLARED_TYPE.captures(html)?;
+    types[1]
+        .split_whitespace()
+        .find_map(SemanticRole::from_type)
+}
+
+/// Extract (href, type) pairs from the landmarks nav of an EPUB 3 navigation document
+pub(crate) fn extract_landmarks(html: &str) -> Vec<(String, String)> {
+    let mut landmarks = Vec::new();
+
+    static NAV: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"(?is)<nav\b[^>]*\bepub:type\s*=\s*["']landmarks["'][^>]*>(.*?)</nav\s*>"#)
+            .expect("landmarks nav pattern")
+    });
+    static LINK: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"(?is)<a\b([^>]*)>").expect("link pattern"));
+    static TYPE: LazyLock<Regex> = LazyLock::new(|| {
+        Regex::new(r#"\bepub:type\s*=\s*["']([^"']+)["']"#).expect("epub:type pattern")
+    });
+    static HREF: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r#"\bhref\s*=\s*["']([^"']+)["']"#).expect("href pattern"));
+
+    for nav in NAV.captures_iter(html) {
+        for link in LINK.captures_iter(&nav[1]) {
+            let attributes = &link[1];
+            if let (Some(kind), Some(href)) = (TYPE.captures(attributes), HREF.captures(attributes))
+            {
+                landmarks.push((href[1].to_string(), kind[1].to_string()));
+            }
+        }
+    }
+
+    landmarks
+}
+
+/// Roles for a book's content documents, in the order given
+///
+/// `files` holds `(archive path, manifest id, content, spine index)` for each
+/// document; `landmarks` maps archive paths to their landmark or guide type.
+pub(crate) fn classify(
+    files: &[(&str, &str, &str, Option<usize>)],
+    landmarks: &HashMap<String, String>,
+) -> Vec<SemanticRole> {
+    // Where the body matter begins, in spine terms, if the book says so
+    let body_start = files
+        .iter()
+        .filter(|(path, ..)| {
+            landmarks
+                .get(*path)
+                .is_some_and(|kind| matches!(kind.as_str(), "bodymatter" | "text"))
+        })
+        .filter_map(|(.., spine_index)| *spine_index)
+        .min();
+
+    files
+        .iter()
+        .map(|(path, id, content, spine_index)| {
+            role_from_content(content)
+                .or_else(|| {
+                    landmarks
+                        .get(*path)
+                        .and_then(|kind| SemanticRole::from_type(kind))
+                })
+                .or_else(|| SemanticRole::from_name(path))
+                .or_else(|| SemanticRole::from_name(id))
+                .unwrap_or(match (spine_index, body_start) {
+                    (Some(index), Some(start)) if *index < start => SemanticRole::FrontMatter,
+                    _ => SemanticRole::Body,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn names_and_types() {
+        assert_eq!(
+            SemanticRole::from_name("Text/cover.xhtml"),
+            Some(SemanticRole::Cover)
+        );
+        assert_eq!(
+            SemanticRole::from_name("titlepage.xhtml"),
+            Some(SemanticRole::TitlePage)
+        );
+        assert_eq!(SemanticRole::from_name("x09_chap3.xhtml"), None);
+        assert_eq!(SemanticRole::from_name("stockholm.xhtml"), None);
+        assert_eq!(
+            SemanticRole::from_type("copyright-page"),
+            Some(SemanticRole::Copyright)
+        );
+        assert_eq!(
+            SemanticRole::from_type("other.dedication"),
+            Some(SemanticRole::Dedication)
+        );
+    }
+
+    #[test]
+    fn classify_uses_every_source() {
+        let landmarks = HashMap::from([
+            ("a.xhtml".to_string(), "toc".to_string()),
+            ("c.xhtml".to_string(), "bodymatter".to_string()),
+        ]);
+        let files = [
+            ("a.xhtml", "a", "<body>", Some(0)),
+            ("b.xhtml", "b", "<body>", Some(1)),
+            ("c.xhtml", "c", "<body>", Some(2)),
+            (
+                "d.xhtml",
+                "d",
+                r#"<body epub:type="backmatter appendix">"#,
+                Some(3),
+            ),
+            ("colophon.xhtml", "e", "<body>", Some(4)),
+        ];
+
+        assert_eq!(
+            classify(&files, &landmarks),
+            vec![
+                SemanticRole::TocPage,
+                SemanticRole::FrontMatter,
+                SemanticRole::Body,
+                SemanticRole::BackMatter,
+                SemanticRole::BackMatter,
+            ]
+        );
+    }
+
+    #[test]
+    fn landmarks_are_read_from_nav() {
+        let html = r#"<nav epub:type="toc"><a href="x.xhtml">X</a></nav>
+            <nav epub:type="landmarks"><ol>
+              <li><a epub:type="cover" href="cover.xhtml">Cover</a></li>
+              <li><a href="ch1.xhtml#start" epub:type="bodymatter">Start</a></li>
+            </ol></nav>"#;
+        assert_eq!(
+            extract_landmarks(html),
+            vec![
+                ("cover.xhtml".to_string(), "cover".to_string()),
+                ("ch1.xhtml#start".to_string(), "bodymatter".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn start_of_content_prefers_bodymatter_landmark() {
+        let container = CONTAINER.replace("content.opf", "OEBPS/content.opf");
+        let opf = package(
+            r#"<item id="nav" href="Text/nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="cover" href="Text/cover.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch1" href="Text/ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="cover"/><itemref idref="ch1"/>"#,
+        );
+        let nav = r#"<html><body><nav epub:type="landmarks"><ol>
+            <li><a epub:type="bodymatter" href="ch1.xhtml#start">Start</a></li></ol></nav></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", &container),
+            ("OEBPS/content.opf", &opf),
+            ("OEBPS/Text/nav.xhtml", nav),
+            ("OEBPS/Text/cover.xhtml", "<html><body><img/></body></html>"),
+            (
+                "OEBPS/Text/ch1.xhtml",
+                "<html><body><p id=\"start\">Call me</p></body></html>",
+            ),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            epub.start_of_content().as_deref(),
+            Some("Text/ch1.xhtml#start")
+        );
+        assert_eq!(
+            epub.get_spine_files()[0].semantic_role(),
+            SemanticRole::Cover
+        );
+    }
+
+    #[test]
+    fn start_of_content_falls_back_to_first_body_file() {
+        let opf = package(
+            r#"<item id="titlepage" href="titlepage.xhtml" media-type="application/xhtml+xml"/>
+               <item id="x09_chap3" href="x09_chap3.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="titlepage"/><itemref idref="x09_chap3"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("titlepage.xhtml", "<html><body>Moby Dick</body></html>"),
+            ("x09_chap3.xhtml", "<html><body>Loomings</body></html>"),
+        ]))
+        .unwrap();
+
+        assert_eq!(epub.start_of_content().as_deref(), Some("x09_chap3.xhtml"));
+    }
+}
@@ -0,0 +1,200 @@
+//! Saved reading state
+//!
+//! Every reader app ends up persisting the same things: which book is open,
+//! where the reader is, how far each document was scrolled and when. A
+//! `ReadingSession` holds that in one place and round-trips through JSON.
+//! Since a saved session can outlive the book it was made for, or be loaded
+//! against a different edition, `ReadingSession::validate` checks it against
+//! an `Epub` before it is trusted.
+
+use crate::epub::Epub;
+use crate::locator::Locator;
+use crate::validate::{Severity, ValidationIssue};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Where a reader is in a book, and since when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadingSession {
+    identifier: String,
+    locator: Option<Locator>,
+    /// Fraction of each document scrolled, keyed by href
+    scroll_offsets: BTreeMap<String, f64>,
+    started_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl ReadingSession {
+    /// Start a session for `epub`, at no particular position
+    pub fn new(epub: &Epub) -> Self {
+        let now = Utc::now();
+        Self {
+            identifier: epub.get_identifier().to_string(),
+            locator: None,
+            scroll_offsets: BTreeMap::new(),
+            started_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Load a session saved with `to_json`
+    pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("sessions always serialize")
+    }
+
+    /// The `dc:identifier` of the book the session belongs to
+    pub fn get_identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// The current position, if the reader has moved anywhere yet
+    pub fn get_locator(&self) -> Option<&Locator> {
+        self.locator.as_ref()
+    }
+
+    pub fn set_locator(&mut self, locator: Locator) {
+        self.locator = Some(locator);
+        self.updated_at = Utc::now();
+    }
+
+    /// How far the document `href` was scrolled, from 0.0 (top) to 1.0 (bottom)
+    pub fn get_scroll_offset(&self, href: &str) -> Option<f64> {
+        self.scroll_offsets.get(href).copied()
+    }
+
+    pub fn get_scroll_offsets(&self) -> &BTreeMap<String, f64> {
+        &self.scroll_offsets
+    }
+
+    /// Record how far `href` was scrolled; the offset is clamped to 0.0-1.0
+    pub fn set_scroll_offset(&mut self, href: &str, offset: f64) {
+        let offset = if offset.is_nan() {
+            0.0
+        } else {
+            offset.clamp(0.0, 1.0)
+        };
+        self.scroll_offsets.insert(href.to_string(), offset);
+        self.updated_at = Utc::now();
+    }
+
+    pub fn get_started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
+    /// When the position or a scroll offset last changed
+    pub fn get_updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    /// Check that the session fits `epub`
+    ///
+    /// A session for another book is an error, as are positions in
+    /// documents the book doesn't have. Anchors that no longer exist only
+    /// warn: the document is still there to open.
+    pub fn validate(&self, epub: &Epub) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        if self.identifier != epub.get_identifier() {
+            issues.push(ValidationIssue::new(
+                Severity::Error,
+                format!(
+                    "session is for book '{}', not '{}'",
+                    self.identifier,
+                    epub.get_identifier()
+                ),
+            ));
+        }
+
+        let find = |href: &str| epub.get_all_files().iter().find(|f| f.get_href() == href);
+        if let Some(locator) = &self.locator {
+            match find(locator.get_href()) {
+                None => issues.push(ValidationIssue::new(
+                    Severity::Error,
+                    format!("position is in unknown document '{}'", locator.get_href()),
+                )),
+                Some(file) => {
+                    if let Some(fragment) = locator.get_fragment() {
+                        let content = file.get_content();
+                        if !content.contains(&format!("id=\"{}\"", fragment))
+                            && !content.contains(&format!("id='{}'", fragment))
+                        {
+                            issues.push(ValidationIssue::new(
+                                Severity::Warning,
+                                format!("no element '{}' in '{}'", fragment, file.get_href()),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        for (href, offset) in &self.scroll_offsets {
+            if find(href).is_none() {
+                issues.push(ValidationIssue::new(
+                    Severity::Error,
+                    format!("scroll offset for unknown document '{}'", href),
+                ));
+            }
+            if !(0.0..=1.0).contains(offset) {
+                issues.push(ValidationIssue::new(
+                    Severity::Error,
+                    format!("scroll offset {} for '{}' is outside 0.0-1.0", offset, href),
+                ));
+            }
+        }
+
+        if self.updated_at < self.started_at {
+            issues.push(ValidationIssue::new(
+                Severity::Warning,
+                "session was updated before it started",
+            ));
+        }
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn sessions_round_trip_and_validate() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                r#"<html><body><p id="p1">One</p></body></html>"#,
+            ),
+        ]))
+        .unwrap();
+
+        let mut session = ReadingSession::new(&epub);
+        session.set_locator(Locator::new("ch1.xhtml").with_fragment("p1"));
+        session.set_scroll_offset("ch1.xhtml", 1.5);
+        assert_eq!(session.get_scroll_offset("ch1.xhtml"), Some(1.0));
+        assert!(session.validate(&epub).is_empty());
+
+        let loaded = ReadingSession::from_json(&session.to_json()).unwrap();
+        assert_eq!(loaded, session);
+        assert!(ReadingSession::from_json("{}").is_err());
+
+        let json = session
+            .to_json()
+            .replace("\"p1\"", "\"gone\"")
+            .replace("\"ch1.xhtml\": 1.0", "\"ch1.xhtml\": 2.0");
+        let issues = ReadingSession::from_json(&json).unwrap().validate(&epub);
+        let severities: Vec<Severity> = issues.iter().map(|i| i.get_severity()).collect();
+        assert_eq!(severities, [Severity::Warning, Severity::Error]);
+    }
+}
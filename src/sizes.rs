@@ -0,0 +1,258 @@
+//! Where a book's bytes go
+//!
+//! A 60 MB book is usually 50 MB of images. `Epub::size_report` reads the
+//! stored and uncompressed size of every manifest resource from the zip
+//! directory, without decompressing anything, and totals them by media
+//! type, largest first, so it's obvious what `optimize_images` or
+//! `subset_fonts` would be worth.
+//!
+//! Resources replaced since parsing haven't been compressed yet; they are
+//! reported without a compressed size and count at their uncompressed size
+//! in the totals.
+
+use crate::archive;
+use crate::epub::Epub;
+use std::fmt;
+
+/// The size of one manifest resource, from `Epub::resource_sizes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceSize {
+    href: String,
+    media_type: String,
+    compressed: Option<u64>,
+    uncompressed: u64,
+}
+
+impl ResourceSize {
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    pub fn get_media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    /// Bytes stored in the archive; `None` for a resource edited since parsing
+    pub fn get_compressed_size(&self) -> Option<u64> {
+        self.compressed
+    }
+
+    pub fn get_uncompressed_size(&self) -> u64 {
+        self.uncompressed
+    }
+
+    /// The stored size, or the uncompressed size if it isn't known
+    fn stored(&self) -> u64 {
+        self.compressed.unwrap_or(self.uncompressed)
+    }
+}
+
+/// The resources of one media type, totalled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaTypeSize {
+    media_type: String,
+    count: usize,
+    compressed: u64,
+    uncompressed: u64,
+}
+
+impl MediaTypeSize {
+    pub fn get_media_type(&self) -> &str {
+        &self.media_type
+    }
+
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+
+    pub fn get_compressed_size(&self) -> u64 {
+        self.compressed
+    }
+
+    pub fn get_uncompressed_size(&self) -> u64 {
+        self.uncompressed
+    }
+}
+
+/// Resource sizes by media type, from `Epub::size_report`
+#[derive(Debug, Clone, Default)]
+pub struct SizeReport {
+    resources: Vec<ResourceSize>,
+    media_types: Vec<MediaTypeSize>,
+}
+
+impl SizeReport {
+    /// Every resource, in manifest order
+    pub fn get_resources(&self) -> &[ResourceSize] {
+        &self.resources
+    }
+
+    /// Totals per media type, largest compressed size first
+    pub fn get_media_types(&self) -> &[MediaTypeSize] {
+        &self.media_types
+    }
+
+    pub fn get_total_compressed_size(&self) -> u64 {
+        self.media_types.iter().map(|group| group.compressed).sum()
+    }
+
+    pub fn get_total_uncompressed_size(&self) -> u64 {
+        self.media_types
+            .iter()
+            .map(|group| group.uncompressed)
+            .sum()
+    }
+
+    /// The fraction (0.0 to 1.0) of the compressed total taken by `media_type`
+    pub fn get_share(&self, media_type: &str) -> f64 {
+        let total = self.get_total_compressed_size();
+        if total == 0 {
+            return 0.0;
+        }
+        self.media_types
+            .iter()
+            .find(|group| group.media_type == media_type)
+            .map_or(0.0, |group| group.compressed as f64 / total as f64)
+    }
+
+    /// The `count` largest resources by compressed size
+    pub fn largest(&self, count: usize) -> Vec<&ResourceSize> {
+        let mut resources: Vec<&ResourceSize> = self.resources.iter().collect();
+        resources.sort_by_key(|resource| std::cmp::Reverse(resource.stored()));
+        resources.truncate(count);
+        resources
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for group in &self.media_types {
+            writeln!(
+                f,
+                "{}: {} files, {} ({:.0}%), {} uncompressed",
+                group.media_type,
+                group.count,
+                human_size(group.compressed),
+                self.get_share(&group.media_type) * 100.0,
+                human_size(group.uncompressed)
+            )?;
+        }
+        writeln!(
+            f,
+            "total: {}, {} uncompressed",
+            human_size(self.get_total_compressed_size()),
+            human_size(self.get_total_uncompressed_size())
+        )
+    }
+}
+
+impl Epub {
+    /// The stored and uncompressed size of every manifest resource
+    ///
+    /// Resources missing from the archive are left out.
+    pub fn resource_sizes(&self) -> Vec<ResourceSize> {
+        let mut archive = self.open_archive().ok();
+        self.resources
+            .iter()
+            .filter_map(|resource| {
+                let path = Self::resolve_path(&self.opf_path, &resource.href);
+                let (compressed, uncompressed) = if let Some(bytes) = self.overrides.get(&path) {
+                    (None, bytes.len() as u64)
+                } else if self.removed.contains(&path) {
+                    return None;
+                } else {
+                    let archive = archive.as_mut()?;
+                    let index =
+                        archive::entry_index(archive, &path, self.options.get_tolerant_hrefs())?;
+                    let entry = archive.by_index_raw(index).ok()?;
+                    (Some(entry.compressed_size()), entry.size())
+                };
+                Some(ResourceSize {
+                    href: resource.href.clone(),
+                    media_type: resource.media_type.clone(),
+                    compressed,
+                    uncompressed,
+                })
+            })
+            .collect()
+    }
+
+    /// Resource sizes grouped by media type
+    pub fn size_report(&self) -> SizeReport {
+        let resources = self.resource_sizes();
+        let mut media_types: Vec<MediaTypeSize> = Vec::new();
+        for resource in &resources {
+            let index = match media_types
+                .iter()
+                .position(|group| group.media_type == resource.media_type)
+            {
+                Some(index) => index,
+                None => {
+                    media_types.push(MediaTypeSize {
+                        media_type: resource.media_type.clone(),
+                        count: 0,
+                        compressed: 0,
+                        uncompressed: 0,
+                    });
+                    media_types.len() - 1
+                }
+            };
+            let group = &mut media_types[index];
+            group.count += 1;
+            group.compressed += resource.stored();
+            group.uncompressed += resource.uncompressed;
+        }
+        media_types.sort_by_key(|group| std::cmp::Reverse(group.compressed));
+        SizeReport {
+            resources,
+            media_types,
+        }
+    }
+}
+
+/// `bytes` as B, KB or MB
+fn human_size(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{} B", bytes),
+        1_000..1_000_000 => format!("{:.1} KB", bytes as f64 / 1e3),
+        _ => format!("{:.1} MB", bytes as f64 / 1e6),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn sizes_are_grouped_by_media_type() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="a" href="a.png" media-type="image/png"/>
+    <item id="b" href="b.png" media-type="image/png"/>
+    <item id="gone" href="gone.png" media-type="image/png"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = format!("<html><body><p>{}</p></body></html>", "text ".repeat(200));
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", &chapter),
+            ("a.png", &"a".repeat(3000)),
+            ("b.png", "b"),
+        ]))
+        .unwrap();
+        epub.put_resource_bytes("b.png", vec![0; 2000]);
+
+        let report = epub.size_report();
+        assert_eq!(report.get_resources().len(), 3);
+        let png = &report.get_media_types()[0];
+        assert_eq!(png.get_media_type(), "image/png");
+        assert_eq!(png.get_count(), 2);
+        assert_eq!(png.get_uncompressed_size(), 5000);
+        assert_eq!(report.largest(1)[0].get_href(), "b.png");
+        assert_eq!(report.largest(1)[0].get_compressed_size(), None);
+        assert!(report.get_share("image/png") > 0.5);
+        assert!(report.to_string().starts_with("image/png: 2 files"));
+    }
+}
@@ -0,0 +1,183 @@
+//! Media type detection from file contents
+//!
+//! Some books declare the wrong media type in the manifest: a PNG labelled
+//! `image/jpeg`, XHTML labelled `text/html`. Reading systems that trust the
+//! declaration may refuse to show the file. `sniff_media_type` looks at the
+//! first bytes instead, and `Epub::validate` warns when the two disagree.
+//! Formats without a reliable signature (CSS, JavaScript, plain text) are
+//! not detected.
+
+use crate::epub::Epub;
+
+/// Guess a media type from a file's leading bytes
+///
+/// Returns `None` when the format isn't recognised.
+pub fn sniff_media_type(bytes: &[u8]) -> Option<&'static str> {
+    let starts = |magic: &[u8]| bytes.starts_with(magic);
+    let brand = bytes.get(4..12);
+
+    if starts(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if starts(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if starts(b"GIF87a") || starts(b"GIF89a") {
+        Some("image/gif")
+    } else if starts(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else if brand == Some(b"ftypavif") || brand == Some(b"ftypavis") {
+        Some("image/avif")
+    } else if brand.is_some_and(|brand| brand.starts_with(b"ftyp")) {
+        Some("audio/mp4")
+    } else if starts(b"ID3") || (bytes.len() > 1 && bytes[0] == 0xff && bytes[1] & 0xe0 == 0xe0) {
+        Some("audio/mpeg")
+    } else if starts(b"OggS") {
+        Some("audio/ogg")
+    } else if starts(b"wOFF") {
+        Some("font/woff")
+    } else if starts(b"wOF2") {
+        Some("font/woff2")
+    } else if starts(b"OTTO") {
+        Some("font/otf")
+    } else if starts(b"\x00\x01\x00\x00") || starts(b"true") {
+        Some("font/ttf")
+    } else if starts(b"%PDF-") {
+        Some("application/pdf")
+    } else {
+        sniff_markup(bytes)
+    }
+}
+
+/// Tell XHTML, HTML, SVG and NCX apart by their root element
+fn sniff_markup(bytes: &[u8]) -> Option<&'static str> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_ascii_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    if !head.starts_with('<') {
+        return None;
+    }
+    if head.contains("<ncx") {
+        Some("application/x-dtbncx+xml")
+    } else if head.contains("<svg") && !head.contains("<html") {
+        Some("image/svg+xml")
+    } else if head.contains("<html") {
+        let xml = head.starts_with("<?xml") || head.contains("http://www.w3.org/1999/xhtml");
+        Some(if xml {
+            "application/xhtml+xml"
+        } else {
+            "text/html"
+        })
+    } else {
+        None
+    }
+}
+
+/// Whether a declared media type fits a detected one, allowing for common aliases
+///
+/// Markup without an XML declaration or namespace is detected as HTML, but
+/// may well be XHTML too, so that is accepted for a declared XHTML type.
+pub(crate) fn same_media_type(declared: &str, detected: &str) -> bool {
+    let canonical = |media_type: &str| -> String {
+        let media_type = media_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        match media_type.as_str() {
+            "image/jpg" | "image/pjpeg" => "image/jpeg".to_string(),
+            "audio/mp3" => "audio/mpeg".to_string(),
+            "audio/x-m4a" | "video/mp4" => "audio/mp4".to_string(),
+            "application/font-woff" | "application/x-font-woff" => "font/woff".to_string(),
+            // TrueType and OpenType are often declared interchangeably
+            "font/otf"
+            | "font/ttf"
+            | "font/sfnt"
+            | "application/font-sfnt"
+            | "application/vnd.ms-opentype"
+            | "application/x-font-otf"
+            | "application/x-font-ttf"
+            | "application/x-font-truetype"
+            | "application/x-font-opentype" => "font/sfnt".to_string(),
+            _ => media_type,
+        }
+    };
+    let declared = canonical(declared);
+    declared == canonical(detected)
+        || (declared == "application/xhtml+xml" && detected == "text/html")
+}
+
+impl Epub {
+    /// Detect the media type of the resource at `href` from its contents
+    ///
+    /// Returns `None` when the resource is missing or its format isn't
+    /// recognised; compare with `Resource::get_media_type` for what the
+    /// manifest declares.
+    pub fn detected_media_type(&self, href: &str) -> Option<&'static str> {
+        sniff_media_type(&self.get_resource_bytes(href)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn sniffs_common_formats() {
+        assert_eq!(
+            sniff_media_type(b"\x89PNG\r\n\x1a\n...."),
+            Some("image/png")
+        );
+        assert_eq!(sniff_media_type(b"\xff\xd8\xff\xe0"), Some("image/jpeg"));
+        assert_eq!(
+            sniff_media_type(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(
+            sniff_media_type(b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<html>"),
+            Some("application/xhtml+xml")
+        );
+        assert_eq!(
+            sniff_media_type(b"<!DOCTYPE html><html>"),
+            Some("text/html")
+        );
+        assert_eq!(
+            sniff_media_type(b"<svg xmlns=\"\"/>"),
+            Some("image/svg+xml")
+        );
+        assert_eq!(sniff_media_type(b"body { color: red }"), None);
+        assert!(same_media_type("image/jpg", "image/jpeg"));
+        assert!(same_media_type("application/vnd.ms-opentype", "font/ttf"));
+        assert!(!same_media_type("image/jpeg", "image/png"));
+        assert!(same_media_type("application/xhtml+xml", "text/html"));
+        assert!(!same_media_type("text/html", "application/xhtml+xml"));
+    }
+
+    #[test]
+    fn mislabelled_resources_are_reported() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="pic" href="pic.jpg" media-type="image/jpeg"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+            ("pic.jpg", "GIF89a\x01\x00\x01\x00"),
+        ]))
+        .unwrap();
+
+        assert_eq!(epub.detected_media_type("pic.jpg"), Some("image/gif"));
+        assert_eq!(epub.detected_media_type("ch1.xhtml"), Some("text/html"));
+        let mismatches: Vec<String> = epub
+            .validate()
+            .iter()
+            .filter(|issue| issue.get_message().contains("looks like"))
+            .map(|issue| issue.get_message().to_string())
+            .collect();
+        assert_eq!(
+            mismatches,
+            ["manifest item 'pic' is declared as 'image/jpeg' but looks like 'image/gif'"]
+        );
+    }
+}
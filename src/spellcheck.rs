@@ -0,0 +1,330 @@
+//! Spellchecking with an external checker
+//!
+//! The crate doesn't ship dictionaries. Instead `Epub::text_runs` does the
+//! part every spellchecking tool would otherwise redo: it walks the body of
+//! each spine document in reading order and yields runs of prose, each in a
+//! single language, with entities decoded and whitespace collapsed. Code,
+//! scripts and maths are left out, and a run never crosses a block
+//! boundary, so a word split over two paragraphs doesn't read as one.
+//!
+//! `Epub::spellcheck` hands each run to a callback, typically a wrapper
+//! around hunspell with one dictionary per language, and gathers what it
+//! reports into `Misspelling`s that carry a `Locator` to the nearest
+//! element with an id, so a tool can link straight to each one.
+
+use crate::epub::{Epub, EpubFile};
+use crate::language::{declared_language, non_empty};
+use crate::locator::Locator;
+use crate::nav::is_navigation;
+use crate::search::snippet;
+use crate::text::decode_entities;
+use crate::xhtml::{BLOCKS, MARKUP, VERBATIM, VOID_ELEMENTS, parse_attributes};
+use std::ops::Range;
+
+/// A run of prose in one language, inside one block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextRun {
+    href: String,
+    language: Option<String>,
+    text: String,
+    locator: Locator,
+}
+
+impl TextRun {
+    /// Href of the document the run is in
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// The language in effect: the nearest `xml:lang` or `lang`, else the
+    /// document's, else the book's `dc:language`
+    pub fn get_language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// The run's text, entities decoded and whitespace collapsed
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// The document and the nearest element with an id at or before the
+    /// start of the run
+    pub fn get_locator(&self) -> &Locator {
+        &self.locator
+    }
+
+    /// Words in the text with their byte offsets, for checkers that take
+    /// one word at a time
+    ///
+    /// A word is a run of letters and digits, with apostrophes and hyphens
+    /// allowed between letters (`don't`, `well-known`).
+    pub fn words(&self) -> Vec<(usize, &str)> {
+        let mut words = Vec::new();
+        let mut start: Option<usize> = None;
+        let mut chars = self.text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let joiner = matches!(c, '\'' | '’' | '-')
+                && start.is_some()
+                && chars.peek().is_some_and(|(_, next)| next.is_alphabetic());
+            if c.is_alphanumeric() || joiner {
+                start.get_or_insert(i);
+            } else if let Some(from) = start.take() {
+                words.push((from, &self.text[from..i]));
+            }
+        }
+        if let Some(from) = start {
+            words.push((from, &self.text[from..]));
+        }
+        words
+    }
+}
+
+/// A word the checker rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Misspelling {
+    word: String,
+    language: Option<String>,
+    locator: Locator,
+    snippet: String,
+}
+
+impl Misspelling {
+    pub fn get_word(&self) -> &str {
+        &self.word
+    }
+
+    /// The language the word was checked in
+    pub fn get_language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    pub fn get_locator(&self) -> &Locator {
+        &self.locator
+    }
+
+    /// The word with some of the surrounding text
+    pub fn get_snippet(&self) -> &str {
+        &self.snippet
+    }
+}
+
+impl Epub {
+    /// The prose of the spine documents as runs of one language, in reading
+    /// order
+    ///
+    /// Navigation documents are skipped, since they repeat the headings.
+    pub fn text_runs(&self) -> Vec<TextRun> {
+        enter_span!("text_runs");
+        self.get_spine_files()
+            .into_iter()
+            .filter(|file| {
+                !self
+                    .get_resource_by_href(&file.href)
+                    .is_some_and(is_navigation)
+            })
+            .flat_map(|file| {
+                let language = file
+                    .get_language()
+                    .or_else(|| self.metadata.language.clone());
+                document_runs(file, language)
+            })
+            .collect()
+    }
+
+    /// Run `check` over every text run and collect what it reports
+    ///
+    /// `check` returns the byte ranges of the misspelled words in the run's
+    /// text; ranges that don't fall on character boundaries are ignored.
+    pub fn spellcheck<F>(&self, mut check: F) -> Vec<Misspelling>
+    where
+        F: FnMut(&TextRun) -> Vec<Range<usize>>,
+    {
+        enter_span!("spellcheck");
+        let mut misspellings = Vec::new();
+        for run in self.text_runs() {
+            let chars: Vec<char> = run.text.chars().collect();
+            for range in check(&run) {
+                let Some(word) = run.text.get(range.clone()) else {
+                    continue;
+                };
+                let start = run.text[..range.start].chars().count();
+                let end = start + word.chars().count();
+                misspellings.push(Misspelling {
+                    word: word.to_string(),
+                    language: run.language.clone(),
+                    locator: run.locator.clone(),
+                    snippet: snippet(&chars, start, end),
+                });
+            }
+        }
+        misspellings
+    }
+}
+
+/// The text runs in the body of one document
+fn document_runs(file: &EpubFile, language: Option<String>) -> Vec<TextRun> {
+    let html = &file.content;
+    let mut runs = Vec::new();
+    let mut open: Vec<(String, Option<String>)> = Vec::new();
+    let mut verbatim = 0usize;
+    let mut in_body = false;
+    let mut last_id: Option<String> = None;
+    let mut run = String::new();
+    let mut run_language: Option<String> = None;
+    let mut run_id: Option<String> = None;
+    let mut position = 0;
+
+    let mut flush = |run: &mut String, run_language: &Option<String>, run_id: &Option<String>| {
+        let text = run.split_whitespace().collect::<Vec<_>>().join(" ");
+        run.clear();
+        if !text.chars().any(char::is_alphabetic) {
+            return;
+        }
+        let locator = Locator::new(&file.href);
+        runs.push(TextRun {
+            href: file.href.to_string(),
+            language: run_language.clone(),
+            text,
+            locator: match run_id {
+                Some(id) => locator.with_fragment(id),
+                None => locator,
+            },
+        });
+    };
+
+    for markup in MARKUP.captures_iter(html) {
+        let whole = markup.get(0).expect("whole match");
+        let current = open
+            .last()
+            .map_or(&language, |(_, language)| language)
+            .clone();
+        let text = &html[position..whole.start()];
+        if in_body && verbatim == 0 && !text.is_empty() {
+            if current != run_language && !run.trim().is_empty() {
+                flush(&mut run, &run_language, &run_id);
+            }
+            if run.trim().is_empty() {
+                run_language = current.clone();
+                run_id = last_id.clone();
+            }
+            run.push_str(&decode_entities(text));
+        }
+        position = whole.end();
+
+        let Some(name) = markup.get(2).map(|name| name.as_str().to_ascii_lowercase()) else {
+            continue;
+        };
+        let closing = !markup[1].is_empty();
+        if name == "body" {
+            in_body = !closing;
+        }
+        if BLOCKS.contains(&name.as_str()) || VERBATIM.contains(&name.as_str()) {
+            flush(&mut run, &run_language, &run_id);
+        }
+        if closing {
+            if let Some(index) = open.iter().rposition(|(open, _)| *open == name) {
+                open.truncate(index);
+            }
+            if VERBATIM.contains(&name.as_str()) {
+                verbatim = verbatim.saturating_sub(1);
+            }
+            continue;
+        }
+
+        let tag = whole.as_str();
+        let attributes = parse_attributes(&tag[1 + name.len()..tag.len() - 1]);
+        if let Some((_, id)) = attributes.iter().find(|(attribute, _)| attribute == "id") {
+            last_id = Some(id.clone());
+        }
+        let void = VOID_ELEMENTS.split('|').any(|void| void == name);
+        if !void && !tag.ends_with("/>") {
+            let language = declared_language(&attributes).map_or(current, non_empty);
+            open.push((name.clone(), language));
+            if VERBATIM.contains(&name.as_str()) {
+                verbatim += 1;
+            }
+        }
+    }
+    flush(&mut run, &run_language, &run_id);
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn book() -> Epub {
+        let opf = package(
+            r#"<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="nav"/><itemref idref="ch1"/>"#,
+        );
+        Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "nav.xhtml",
+                r#"<html><body><nav epub:type="toc"><ol><li><a href="ch1.xhtml">Teh start</a></li></ol></nav></body></html>"#,
+            ),
+            (
+                "ch1.xhtml",
+                r#"<html xml:lang="en"><head><title>Teh</title></head><body>
+<h1 id="start">Teh start</h1>
+<p id="p1">It's a <i>well-known</i> fact &amp; <span lang="fr">c'est la vie</span>, teh end.</p>
+<pre>fn teh() {}</pre>
+</body></html>"#,
+            ),
+        ]))
+        .unwrap()
+    }
+
+    #[test]
+    fn runs_split_at_blocks_and_language_changes() {
+        let runs = book().text_runs();
+        let summary: Vec<(&str, Option<&str>, Option<&str>)> = runs
+            .iter()
+            .map(|run| {
+                (
+                    run.get_text(),
+                    run.get_language(),
+                    run.get_locator().get_fragment(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Teh start", Some("en"), Some("start")),
+                ("It's a well-known fact &", Some("en"), Some("p1")),
+                ("c'est la vie", Some("fr"), Some("p1")),
+                (", teh end.", Some("en"), Some("p1")),
+            ]
+        );
+        let words: Vec<&str> = runs[1].words().into_iter().map(|(_, word)| word).collect();
+        assert_eq!(words, ["It's", "a", "well-known", "fact"]);
+    }
+
+    #[test]
+    fn spellcheck_collects_what_the_checker_reports() {
+        let misspellings = book().spellcheck(|run| {
+            run.words()
+                .into_iter()
+                .filter(|(_, word)| word.eq_ignore_ascii_case("teh"))
+                .map(|(start, word)| start..start + word.len())
+                .collect()
+        });
+        let found: Vec<(&str, String)> = misspellings
+            .iter()
+            .map(|m| (m.get_word(), m.get_locator().to_link()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Teh", "ch1.xhtml#start".to_string()),
+                ("teh", "ch1.xhtml#p1".to_string())
+            ]
+        );
+        assert_eq!(misspellings[1].get_snippet(), ", teh end.");
+    }
+}
@@ -0,0 +1,464 @@
+//! Stylesheet patterns that fight reflowable reading
+//!
+//! Reading systems let the reader pick the font size, margins and colours,
+//! and publishers' QA checklists reject CSS that gets in the way of that.
+//! `Epub::style_audit` looks for the usual offenders in stylesheets,
+//! `<style>` elements and `style` attributes: font sizes in absolute units,
+//! fixed widths, forced justification, `!important` on body text (which
+//! overrides the reader's own settings in most reading systems) and text
+//! coloured so close to its background that it disappears.
+//!
+//! The CSS is only tokenized far enough to see rules and declarations, so
+//! nothing is said about which rules actually apply to which elements.
+
+use crate::epub::{Epub, is_content_media_type};
+use crate::xhtml::{TAG, find_elements, parse_attributes};
+use regex::Regex;
+use std::fmt;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// Selectors that style running text as a whole
+const BODY_TEXT: &[&str] = &["*", "html", "body", "p", "div", "span"];
+/// Contrast ratio (as in WCAG) below which text is taken to be invisible
+const INVISIBLE_CONTRAST: f64 = 1.5;
+pub(crate) const WHITE: Rgb = (255, 255, 255);
+pub(crate) const BLACK: Rgb = (0, 0, 0);
+
+/// An sRGB colour
+pub(crate) type Rgb = (u8, u8, u8);
+
+static COMMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)/\*.*?\*/").expect("comment pattern"));
+/// A rule's selector and declarations; nested `@media` blocks match their
+/// inner rules
+static RULE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"([^{}]*)\{([^{}]*)\}").expect("rule pattern"));
+static ABSOLUTE_LENGTH: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:^|[\s(,])-?([0-9]*\.?[0-9]+)(px|pt|pc|cm|mm|in|q)\b")
+        .expect("length pattern")
+});
+
+/// What a `StyleIssue` is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StyleIssueKind {
+    /// `font-size` in px, pt or another absolute unit, which ignores the
+    /// reader's font size
+    AbsoluteFontSize,
+    /// `width` or `min-width` in an absolute unit, which overflows small screens
+    FixedWidth,
+    /// `text-align: justify`, which overrides the reader's alignment choice
+    Justified,
+    /// `!important` on a rule for body text
+    ImportantOnBodyText,
+    /// Text colour too close to its background to read
+    InvisibleText,
+    /// A dark text colour without a background, unreadable in a dark theme
+    DarkText,
+    /// A light background without a text colour, unreadable under a dark
+    /// theme's light text
+    LightBackground,
+}
+
+impl fmt::Display for StyleIssueKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AbsoluteFontSize => "absolute font size",
+            Self::FixedWidth => "fixed width",
+            Self::Justified => "justified text",
+            Self::ImportantOnBodyText => "!important on body text",
+            Self::InvisibleText => "invisible text",
+            Self::DarkText => "dark text",
+            Self::LightBackground => "light background",
+        })
+    }
+}
+
+/// One problem declaration found by `Epub::style_audit`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyleIssue {
+    kind: StyleIssueKind,
+    href: String,
+    selector: String,
+    declaration: String,
+}
+
+impl StyleIssue {
+    pub(crate) fn new(
+        kind: StyleIssueKind,
+        href: &str,
+        selector: &str,
+        declaration: String,
+    ) -> Self {
+        Self {
+            kind,
+            href: href.to_string(),
+            selector: selector.to_string(),
+            declaration,
+        }
+    }
+
+    pub fn get_kind(&self) -> StyleIssueKind {
+        self.kind
+    }
+
+    /// Href of the stylesheet, or of the document with the `<style>`
+    /// element or `style` attribute
+    pub fn get_href(&self) -> &str {
+        &self.href
+    }
+
+    /// The rule's selector; for a `style` attribute, the element's name
+    pub fn get_selector(&self) -> &str {
+        &self.selector
+    }
+
+    /// The offending declaration, as `property: value`
+    pub fn get_declaration(&self) -> &str {
+        &self.declaration
+    }
+}
+
+impl fmt::Display for StyleIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} in {} {{ {} }}",
+            self.href, self.kind, self.selector, self.declaration
+        )
+    }
+}
+
+impl Epub {
+    /// CSS that breaks reflowable reading, in manifest order
+    pub fn style_audit(&self) -> Vec<StyleIssue> {
+        enter_span!("style_audit");
+        let mut issues = Vec::new();
+        self.for_each_style_rule(|href, selector, block| {
+            issues.extend(
+                audit_rule(selector, &declarations(block))
+                    .into_iter()
+                    .map(|(kind, declaration)| StyleIssue::new(kind, href, selector, declaration)),
+            );
+        });
+        issues
+    }
+
+    /// Call `f` with the href, selector and declaration block of every rule
+    /// in stylesheets, `<style>` elements and `style` attributes, in
+    /// manifest order
+    ///
+    /// A `style` attribute's selector is the element's name.
+    pub(crate) fn for_each_style_rule(&self, mut f: impl FnMut(&str, &str, &str)) {
+        for resource in &self.resources {
+            let is_css = resource.media_type == "text/css";
+            if !is_css && !is_content_media_type(&resource.media_type) {
+                continue;
+            }
+            let Some(bytes) = self.get_resource_bytes(&resource.href) else {
+                continue;
+            };
+            let content = String::from_utf8_lossy(&bytes);
+            let mut stylesheet = |css: &str| {
+                for (selector, block) in style_rules(css) {
+                    f(&resource.href, &selector, &css[block]);
+                }
+            };
+
+            if is_css {
+                stylesheet(&content);
+                continue;
+            }
+            for style in find_elements(&content, |e| e.name == "style") {
+                stylesheet(style.inner);
+            }
+            for tag in TAG.captures_iter(&content).filter(|tag| tag[1].is_empty()) {
+                if let Some((_, style)) = parse_attributes(&tag[3])
+                    .into_iter()
+                    .find(|(name, _)| name == "style")
+                {
+                    f(&resource.href, &tag[2].to_ascii_lowercase(), &style);
+                }
+            }
+        }
+    }
+}
+
+/// A `property: value` pair in a declaration block
+#[derive(Debug, Clone)]
+pub(crate) struct Declaration {
+    /// Lowercased
+    pub(crate) property: String,
+    /// Without `!important`
+    pub(crate) value: String,
+    pub(crate) important: bool,
+    /// Byte range in the block, without surrounding whitespace or the `;`
+    pub(crate) range: Range<usize>,
+}
+
+impl Declaration {
+    /// The declaration written out again, as `property: value`
+    pub(crate) fn to_css(&self) -> String {
+        if self.important {
+            format!("{}: {} !important", self.property, self.value)
+        } else {
+            format!("{}: {}", self.property, self.value)
+        }
+    }
+}
+
+/// The selector and the byte range of the declaration block of every style
+/// rule in `css`
+///
+/// Rules inside `@media` and the like are included; `@font-face` and
+/// `@page` hold descriptors rather than styles for text, so they are not.
+pub(crate) fn style_rules(css: &str) -> Vec<(String, Range<usize>)> {
+    // Blank comments out rather than removing them, so ranges still apply
+    let masked = COMMENT.replace_all(css, |comment: &regex::Captures| {
+        " ".repeat(comment[0].len())
+    });
+    RULE.captures_iter(&masked)
+        .filter_map(|rule| {
+            let selector = rule[1].split_whitespace().collect::<Vec<_>>().join(" ");
+            if selector.is_empty() || selector.starts_with('@') {
+                return None;
+            }
+            Some((selector, rule.get(2).expect("declarations").range()))
+        })
+        .collect()
+}
+
+/// The declarations in a block, in order
+pub(crate) fn declarations(block: &str) -> Vec<Declaration> {
+    let mut found = Vec::new();
+    let mut start = 0;
+    for part in block.split(';') {
+        let offset = start;
+        start += part.len() + 1;
+        let Some((property, value)) = part.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let (value, important) = match value.rfind('!') {
+            Some(bang) if value[bang + 1..].trim().eq_ignore_ascii_case("important") => {
+                (value[..bang].trim(), true)
+            }
+            _ => (value, false),
+        };
+        let leading = part.len() - part.trim_start().len();
+        found.push(Declaration {
+            property: property.trim().to_ascii_lowercase(),
+            value: value.to_string(),
+            important,
+            range: offset + leading..offset + part.trim_end().len(),
+        });
+    }
+    found
+}
+
+/// The issues in one rule, with the declaration each is about
+fn audit_rule(selector: &str, declarations: &[Declaration]) -> Vec<(StyleIssueKind, String)> {
+    let body_text = selector.split(',').any(|selector| {
+        let element = selector
+            .split_whitespace()
+            .last()
+            .unwrap_or("")
+            .split(['.', '#', ':', '['])
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        BODY_TEXT.contains(&element.as_str())
+    });
+    let background = background_color(declarations).unwrap_or(WHITE);
+
+    let mut issues = Vec::new();
+    for declaration in declarations {
+        let value = declaration.value.as_str();
+        let kind = match declaration.property.as_str() {
+            "font-size" | "font" if has_absolute_length(value) => {
+                Some(StyleIssueKind::AbsoluteFontSize)
+            }
+            "width" | "min-width" if has_absolute_length(value) => Some(StyleIssueKind::FixedWidth),
+            "text-align" if value.eq_ignore_ascii_case("justify") => {
+                Some(StyleIssueKind::Justified)
+            }
+            "color"
+                if parse_color(value)
+                    .is_some_and(|color| contrast(color, background) < INVISIBLE_CONTRAST) =>
+            {
+                Some(StyleIssueKind::InvisibleText)
+            }
+            _ => None,
+        };
+        issues.extend(kind.map(|kind| (kind, declaration.to_css())));
+        if declaration.important && body_text {
+            issues.push((StyleIssueKind::ImportantOnBodyText, declaration.to_css()));
+        }
+    }
+    issues
+}
+
+/// The background colour a rule sets, if any
+pub(crate) fn background_color(declarations: &[Declaration]) -> Option<Rgb> {
+    declarations
+        .iter()
+        .filter(|d| d.property == "background-color" || d.property == "background")
+        .find_map(|d| parse_color(&d.value))
+}
+
+/// Whether `value` has a non-zero length in an absolute unit
+fn has_absolute_length(value: &str) -> bool {
+    ABSOLUTE_LENGTH
+        .captures_iter(value)
+        .any(|cap| cap[1].parse::<f64>().is_ok_and(|n| n != 0.0))
+}
+
+/// An sRGB colour from a hex, `rgb()` or basic named colour; the first
+/// one found, so a `background` shorthand works too
+pub(crate) fn parse_color(value: &str) -> Option<Rgb> {
+    value
+        .split_whitespace()
+        .find_map(|token| {
+            let token = token.to_ascii_lowercase();
+            if let Some(hex) = token.strip_prefix('#') {
+                if !hex.is_ascii() {
+                    return None;
+                }
+                let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+                return match hex.len() {
+                    3 | 4 => {
+                        let double = |i: usize| channel(&hex[i..i + 1].repeat(2));
+                        Some((double(0)?, double(1)?, double(2)?))
+                    }
+                    6 | 8 => Some((
+                        channel(&hex[0..2])?,
+                        channel(&hex[2..4])?,
+                        channel(&hex[4..6])?,
+                    )),
+                    _ => None,
+                };
+            }
+            if token.starts_with("rgb") {
+                return None;
+            }
+            match token.as_str() {
+                "white" => Some(WHITE),
+                "black" => Some(BLACK),
+                "silver" => Some((192, 192, 192)),
+                "gray" | "grey" => Some((128, 128, 128)),
+                "whitesmoke" => Some((245, 245, 245)),
+                "snow" => Some((255, 250, 250)),
+                "ivory" => Some((255, 255, 240)),
+                _ => None,
+            }
+        })
+        .or_else(|| parse_rgb(value))
+}
+
+/// An `rgb()` or `rgba()` colour with 0–255 channels
+fn parse_rgb(value: &str) -> Option<Rgb> {
+    let start = value.to_ascii_lowercase().find("rgb")?;
+    let args = &value[start..];
+    let args = &args[args.find('(')? + 1..args.find(')')?];
+    let channels: Vec<u8> = args
+        .split([',', ' ', '/'])
+        .filter(|channel| !channel.is_empty())
+        .take(3)
+        .map(|channel| {
+            channel
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .map(|n| n.clamp(0.0, 255.0) as u8)
+        })
+        .collect::<Option<_>>()?;
+    match channels[..] {
+        [r, g, b] => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+/// WCAG contrast ratio between two colours, from 1 to 21
+pub(crate) fn contrast(a: Rgb, b: Rgb) -> f64 {
+    let luminance = |(r, g, b): Rgb| {
+        let linear = |c: u8| {
+            let c = f64::from(c) / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+    };
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn audit_finds_reflow_breaking_css() {
+        let opf = package(
+            r#"<item id="css" href="style.css" media-type="text/css"/>
+               <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let css = r#"/* p { font-size: 9px } */
+@font-face { font-family: Serif; src: url(serif.otf) }
+body { font-size: 12pt; color: #333 !important; margin: 0 }
+@media screen { .wide { width: 600px; max-width: 100% } }
+p.note { text-align: justify; color: rgb(250, 250, 250) }
+h1 { font-size: 1.5em; width: 0px }"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("style.css", css),
+            (
+                "ch1.xhtml",
+                r#"<html><head><style>span { color: white; background: #fff }</style></head>
+<body><p style="font-size: 14px">Text</p></body></html>"#,
+            ),
+        ]))
+        .unwrap();
+
+        let issues = epub.style_audit();
+        let issues: Vec<(StyleIssueKind, &str, &str, &str)> = issues
+            .iter()
+            .map(|issue| {
+                (
+                    issue.get_kind(),
+                    issue.get_href(),
+                    issue.get_selector(),
+                    issue.get_declaration(),
+                )
+            })
+            .collect();
+        use StyleIssueKind::*;
+        assert_eq!(
+            issues,
+            [
+                (AbsoluteFontSize, "style.css", "body", "font-size: 12pt"),
+                (
+                    ImportantOnBodyText,
+                    "style.css",
+                    "body",
+                    "color: #333 !important"
+                ),
+                (FixedWidth, "style.css", ".wide", "width: 600px"),
+                (Justified, "style.css", "p.note", "text-align: justify"),
+                (
+                    InvisibleText,
+                    "style.css",
+                    "p.note",
+                    "color: rgb(250, 250, 250)"
+                ),
+                (InvisibleText, "ch1.xhtml", "span", "color: white"),
+                (AbsoluteFontSize, "ch1.xhtml", "p", "font-size: 14px"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Cleaning up `dc:subject` values
+//!
+//! Publishers fill `dc:subject` however their systems happen to: several
+//! subjects in one element (`Fantasy; Magic, Dragons`), stray whitespace,
+//! the same subject twice in different case, or bare BISAC codes such as
+//! `FIC009000` that mean nothing to a reader. `Metadata::normalized_tags`
+//! turns that into a clean tag list. BISAC codes become their headings; the
+//! built-in table knows every section and the most common fiction codes,
+//! and other codes fall back to their section's name.
+//!
+//! Values are split on `;` and `|`, and on `,` unless they look like a
+//! BISAC heading (`BODY, MIND & SPIRIT / General`), where the comma is
+//! part of the name.
+
+use crate::epub::Metadata;
+
+/// BISAC sections, by the three letters every code in them starts with
+const BISAC_SECTIONS: &[(&str, &str)] = &[
+    ("ANT", "Antiques & Collectibles"),
+    ("ARC", "Architecture"),
+    ("ART", "Art"),
+    ("BIB", "Bibles"),
+    ("BIO", "Biography & Autobiography"),
+    ("BUS", "Business & Economics"),
+    ("CGN", "Comics & Graphic Novels"),
+    ("CKB", "Cooking"),
+    ("COM", "Computers"),
+    ("CRA", "Crafts & Hobbies"),
+    ("DES", "Design"),
+    ("DRA", "Drama"),
+    ("EDU", "Education"),
+    ("FAM", "Family & Relationships"),
+    ("FIC", "Fiction"),
+    ("FOR", "Foreign Language Study"),
+    ("GAM", "Games & Activities"),
+    ("GAR", "Gardening"),
+    ("HEA", "Health & Fitness"),
+    ("HIS", "History"),
+    ("HOM", "House & Home"),
+    ("HUM", "Humor"),
+    ("JNF", "Juvenile Nonfiction"),
+    ("JUV", "Juvenile Fiction"),
+    ("LAN", "Language Arts & Disciplines"),
+    ("LAW", "Law"),
+    ("LCO", "Literary Collections"),
+    ("LIT", "Literary Criticism"),
+    ("MAT", "Mathematics"),
+    ("MED", "Medical"),
+    ("MUS", "Music"),
+    ("NAT", "Nature"),
+    ("NON", "Non-Classifiable"),
+    ("OCC", "Body, Mind & Spirit"),
+    ("PER", "Performing Arts"),
+    ("PET", "Pets"),
+    ("PHI", "Philosophy"),
+    ("PHO", "Photography"),
+    ("POE", "Poetry"),
+    ("POL", "Political Science"),
+    ("PSY", "Psychology"),
+    ("REF", "Reference"),
+    ("REL", "Religion"),
+    ("SCI", "Science"),
+    ("SEL", "Self-Help"),
+    ("SOC", "Social Science"),
+    ("SPO", "Sports & Recreation"),
+    ("STU", "Study Aids"),
+    ("TEC", "Technology & Engineering"),
+    ("TRA", "Transportation"),
+    ("TRU", "True Crime"),
+    ("TRV", "Travel"),
+    ("YAF", "Young Adult Fiction"),
+    ("YAN", "Young Adult Nonfiction"),
+];
+
+/// Common codes below the section level, named without the section; the
+/// `subject-codes` feature adds more
+const BISAC_CODES: &[(&str, &str)] = &[
+    ("FIC002000", "Action & Adventure"),
+    ("FIC004000", "Classics"),
+    ("FIC009000", "Fantasy / General"),
+    ("FIC014000", "Historical / General"),
+    ("FIC015000", "Horror"),
+    ("FIC019000", "Literary"),
+    ("FIC022000", "Mystery & Detective / General"),
+    ("FIC027000", "Romance / General"),
+    ("FIC028000", "Science Fiction / General"),
+    ("FIC031000", "Thrillers / General"),
+];
+
+impl Metadata {
+    /// The `dc:subject` values, split, trimmed, de-duplicated and with
+    /// BISAC codes replaced by their headings
+    pub fn normalized_tags(&self) -> Vec<String> {
+        normalize_tags(self.get_tags())
+    }
+}
+
+/// Normalize raw subject values as `Metadata::normalized_tags` does
+///
+/// Duplicates are compared case-insensitively; the first spelling wins.
+pub fn normalize_tags<S: AsRef<str>>(subjects: &[S]) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for subject in subjects {
+        for part in subject.as_ref().split([';', '|', '\n']) {
+            let pieces: Vec<&str> = if part.contains(" / ") {
+                vec![part]
+            } else {
+                part.split(',').collect()
+            };
+            for piece in pieces {
+                let tag = normalize_tag(piece);
+                if !tag.is_empty() && !tags.iter().any(|t| t.to_lowercase() == tag.to_lowercase()) {
+                    tags.push(tag);
+                }
+            }
+        }
+    }
+    tags
+}
+
+/// The heading for a BISAC code such as `FIC028000`
+///
+/// Codes missing from the built-in table get their section's name, or
+/// `Section / General` for the section's `000000` code.
+pub fn bisac_heading(code: &str) -> Option<String> {
+    let code = code.trim().to_ascii_uppercase();
+    if !is_bisac_code(&code) {
+        return None;
+    }
+    let (prefix, number) = code.split_at(3);
+    let section = BISAC_SECTIONS
+        .iter()
+        .find(|(known, _)| *known == prefix)
+        .map(|(_, name)| *name)?;
+    #[cfg(feature = "subject-codes")]
+    let mut codes = BISAC_CODES.iter().chain(crate::categories::BISAC_EXTENDED);
+    #[cfg(not(feature = "subject-codes"))]
+    let mut codes = BISAC_CODES.iter();
+    let heading = match codes.find(|(known, _)| *known == code) {
+        Some((_, name)) => format!("{} / {}", section, name),
+        None if number == "000000" => format!("{} / General", section),
+        None => section.to_string(),
+    };
+    Some(heading)
+}
+
+fn normalize_tag(raw: &str) -> String {
+    let tag = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    let tag = tag
+        .strip_prefix("BISAC:")
+        .or_else(|| tag.strip_prefix("bisac:"))
+        .map_or(tag.as_str(), str::trim);
+    // A code alone, or a code followed by its heading
+    let (code, rest) = tag.split_once(' ').unwrap_or((tag, ""));
+    match bisac_heading(code) {
+        Some(heading) if rest.is_empty() => heading,
+        _ if is_bisac_code(code) && !rest.is_empty() => rest.to_string(),
+        _ => tag.to_string(),
+    }
+}
+
+fn is_bisac_code(code: &str) -> bool {
+    code.len() == 9
+        && code[..3].bytes().all(|b| b.is_ascii_uppercase())
+        && code[3..].bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subjects_are_split_deduplicated_and_decoded() {
+        let subjects = [
+            " Fantasy;  Magic, dragons ",
+            "fantasy",
+            "FIC028000",
+            "BISAC: FIC999000",
+            "SCI000000",
+            "BODY, MIND & SPIRIT / General",
+            "FIC009000 FICTION / Fantasy / Epic",
+            "Dragons|Quests",
+        ];
+        assert_eq!(
+            normalize_tags(&subjects),
+            [
+                "Fantasy",
+                "Magic",
+                "dragons",
+                "Fiction / Science Fiction / General",
+                "Fiction",
+                "Science / General",
+                "BODY, MIND & SPIRIT / General",
+                "FICTION / Fantasy / Epic",
+                "Quests",
+            ]
+        );
+        assert_eq!(bisac_heading("xyz123456"), None);
+    }
+}
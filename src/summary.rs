@@ -0,0 +1,153 @@
+//! Reading a book's metadata without parsing it
+//!
+//! Cataloguing a book needs its title, authors and cover, not its text.
+//! `Epub::peek_metadata` reads only `META-INF/container.xml`, the package
+//! document and the cover image, and returns an `EpubSummary` that owns
+//! nothing else: no content documents, no archive buffer. For big books
+//! that's an order of magnitude faster than `Epub::from_bytes`.
+//! `Epub::summary` makes the same summary from a fully parsed book, with a
+//! content hash for duplicate detection as well.
+
+use crate::archive::Source;
+use crate::epub::{Epub, Metadata};
+use crate::options::ParseOptions;
+use std::error::Error;
+use std::sync::Arc;
+
+/// A book's package metadata and cover, from `Epub::peek_metadata`
+#[derive(Debug, Clone)]
+pub struct EpubSummary {
+    metadata: Metadata,
+    version: String,
+    identifiers: Vec<String>,
+    cover: Option<(String, Vec<u8>)>,
+    content_hash: Option<u64>,
+}
+
+impl EpubSummary {
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.metadata.get_title()
+    }
+
+    pub fn get_creators(&self) -> &[String] {
+        self.metadata.get_creators()
+    }
+
+    pub fn get_identifier(&self) -> &str {
+        self.metadata.get_identifier()
+    }
+
+    /// Every `dc:identifier` (ISBN, UUID, ...), in document order
+    pub fn get_identifiers(&self) -> &[String] {
+        &self.identifiers
+    }
+
+    pub fn get_language(&self) -> Option<&str> {
+        self.metadata.get_language()
+    }
+
+    /// The package `version` attribute, e.g. `3.0`
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn get_cover_bytes(&self) -> Option<&[u8]> {
+        self.cover.as_ref().map(|(_, bytes)| bytes.as_slice())
+    }
+
+    pub fn get_cover_media_type(&self) -> Option<&str> {
+        self.cover
+            .as_ref()
+            .map(|(media_type, _)| media_type.as_str())
+    }
+
+    /// `Epub::content_fingerprint` of the book; `None` from `peek_metadata`
+    pub fn get_content_hash(&self) -> Option<u64> {
+        self.content_hash
+    }
+}
+
+impl Epub {
+    /// Read only the metadata and cover of an in-memory EPUB archive
+    ///
+    /// # Errors
+    /// The same as `Epub::from_bytes`, except that problems in content
+    /// documents go unnoticed.
+    pub fn peek_metadata(bytes: impl Into<Arc<[u8]>>) -> Result<EpubSummary, Box<dyn Error>> {
+        enter_span!("peek_metadata");
+        let options = ParseOptions::default().with_metadata_only(true);
+        Ok(Self::from_source(Source::Bytes(bytes.into()), options)?.summary())
+    }
+
+    /// The book's metadata, cover and content hash as an `EpubSummary`
+    pub fn summary(&self) -> EpubSummary {
+        let cover = self
+            .get_cover()
+            .and_then(|id| self.get_resource_by_id(id))
+            .and_then(|resource| {
+                let bytes = self.get_resource_bytes(resource.get_href())?;
+                Some((resource.get_media_type().to_string(), bytes))
+            });
+        EpubSummary {
+            metadata: self.metadata.clone(),
+            version: self.version.clone(),
+            identifiers: identifiers(self),
+            cover,
+            content_hash: self.content_fingerprint(),
+        }
+    }
+}
+
+/// Every non-empty `dc:identifier`, falling back to the parsed identifier
+pub(crate) fn identifiers(epub: &Epub) -> Vec<String> {
+    let mut ids: Vec<String> = epub
+        .get_metadata_elements()
+        .iter()
+        .filter(|element| element.is_dublin_core() && element.get_local_name() == "identifier")
+        .map(|element| element.get_text().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+    if ids.is_empty() && !epub.get_identifier().is_empty() {
+        ids.push(epub.get_identifier().to_string());
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build};
+
+    #[test]
+    fn summaries_skip_content_but_keep_the_cover() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier>id</dc:identifier>
+    <dc:title>Peeked</dc:title>
+    <dc:creator>A. Author</dc:creator>
+  </metadata>
+  <manifest>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+    <item id="cover" href="cover.png" media-type="image/png" properties="cover-image"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        // The content document is missing, which a full parse would report
+        let summary = Epub::peek_metadata(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("cover.png", "\u{89}PNG"),
+        ]))
+        .unwrap();
+
+        assert_eq!(summary.get_title(), Some("Peeked"));
+        assert_eq!(summary.get_creators(), ["A. Author"]);
+        assert_eq!(summary.get_version(), "3.0");
+        assert_eq!(summary.get_cover_media_type(), Some("image/png"));
+        assert_eq!(summary.get_cover_bytes(), Some("\u{89}PNG".as_bytes()));
+    }
+}
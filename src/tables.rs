@@ -0,0 +1,201 @@
+//! Tables
+//!
+//! `EpubFile::get_tables` turns a document's `<table>`s into rows of plain
+//! text cells, for pulling data out of textbooks and reference works.
+//! Tables nested inside a cell are flattened into that cell's text.
+
+use crate::epub::EpubFile;
+use crate::text::html_to_text;
+use crate::xhtml::find_elements;
+
+/// Most columns a cell may span, as in the HTML spec
+const MAX_COLSPAN: usize = 1000;
+
+/// One cell of a table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableCell {
+    text: String,
+    header: bool,
+    colspan: usize,
+    rowspan: usize,
+}
+
+impl TableCell {
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the cell is a `<th>`
+    pub fn is_header(&self) -> bool {
+        self.header
+    }
+
+    pub fn get_colspan(&self) -> usize {
+        self.colspan
+    }
+
+    pub fn get_rowspan(&self) -> usize {
+        self.rowspan
+    }
+}
+
+/// A table's rows, in document order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    id: Option<String>,
+    caption: Option<String>,
+    rows: Vec<Vec<TableCell>>,
+}
+
+impl Table {
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn get_caption(&self) -> Option<&str> {
+        self.caption.as_deref()
+    }
+
+    /// The cells of each row as written, so spanned cells appear only once
+    pub fn get_rows(&self) -> &[Vec<TableCell>] {
+        &self.rows
+    }
+
+    /// The table as CSV, one line per row
+    ///
+    /// Cells spanning several rows or columns are written at their top-left
+    /// position and the positions they cover are left empty, so every line
+    /// has the same number of fields.
+    pub fn to_csv(&self) -> String {
+        let mut grid: Vec<Vec<Option<&str>>> = vec![Vec::new(); self.rows.len()];
+        for (r, row) in self.rows.iter().enumerate() {
+            let mut column = 0;
+            for cell in row {
+                while grid[r].get(column).is_some_and(Option::is_some) {
+                    column += 1;
+                }
+                let last_row = (r + cell.rowspan).min(self.rows.len());
+                for (offset, covered) in grid[r..last_row].iter_mut().enumerate() {
+                    for c in column..column + cell.colspan {
+                        if covered.len() <= c {
+                            covered.resize(c + 1, None);
+                        }
+                        let text = if offset == 0 && c == column {
+                            cell.get_text()
+                        } else {
+                            ""
+                        };
+                        covered[c] = Some(text);
+                    }
+                }
+                column += cell.colspan;
+            }
+        }
+
+        let width = grid.iter().map(Vec::len).max().unwrap_or(0);
+        let mut csv = String::new();
+        for row in grid {
+            let fields: Vec<String> = (0..width)
+                .map(|c| csv_field(row.get(c).copied().flatten().unwrap_or("")))
+                .collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+impl EpubFile {
+    /// Every table in this file, outermost tables only
+    pub fn get_tables(&self) -> Vec<Table> {
+        find_elements(&self.content, |e| e.name == "table")
+            .into_iter()
+            .map(|table| {
+                let rows = find_elements(table.inner, |e| e.name == "tr");
+                // A caption after the first row belongs to a nested table
+                let caption = find_elements(table.inner, |e| e.name == "caption")
+                    .first()
+                    .filter(|caption| {
+                        rows.first()
+                            .is_none_or(|row| caption.range.start < row.range.start)
+                    })
+                    .map(|caption| html_to_text(caption.inner).replace('\n', " "))
+                    .filter(|caption| !caption.is_empty());
+                Table {
+                    id: table.attribute("id").map(str::to_string),
+                    caption,
+                    rows: rows.iter().map(|row| parse_row(row.inner)).collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+fn parse_row(html: &str) -> Vec<TableCell> {
+    find_elements(html, |e| e.name == "td" || e.name == "th")
+        .iter()
+        .map(|cell| TableCell {
+            text: html_to_text(cell.inner).replace('\n', " "),
+            header: cell.name == "th",
+            colspan: span(cell.attribute("colspan")).min(MAX_COLSPAN),
+            rowspan: span(cell.attribute("rowspan")),
+        })
+        .collect()
+}
+
+/// A `colspan` or `rowspan` value; missing, zero or invalid spans count as 1
+fn span(value: Option<&str>) -> usize {
+    value
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|&span| span > 0)
+        .unwrap_or(1)
+}
+
+/// Quote a CSV field if it needs it
+pub(crate) fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::epub::Epub;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn tables_parse_into_rows_and_csv() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html><body><table id="t1"><caption>Planets</caption>
+<thead><tr><th>Name</th><th colspan="2">Size, km</th></tr></thead>
+<tbody><tr><td rowspan="2">Earth</td><td>12,742</td><td>"blue"</td></tr>
+<tr><td>6,371</td><td><table><tr><td>nested</td></tr></table></td></tr></tbody>
+</table></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let file = &epub.get_spine_files()[0];
+        let tables = file.get_tables();
+        assert_eq!(tables.len(), 1);
+        let table = &tables[0];
+        assert_eq!(table.get_id(), Some("t1"));
+        assert_eq!(table.get_caption(), Some("Planets"));
+        assert_eq!(table.get_rows().len(), 3);
+        assert!(table.get_rows()[0][1].is_header());
+        assert_eq!(table.get_rows()[0][1].get_colspan(), 2);
+        assert_eq!(table.get_rows()[2][1].get_text(), "nested");
+        assert_eq!(
+            table.to_csv(),
+            "Name,\"Size, km\",\nEarth,\"12,742\",\"\"\"blue\"\"\"\n,\"6,371\",nested\n"
+        );
+    }
+}
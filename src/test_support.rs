@@ -0,0 +1,33 @@
+//! In-memory EPUB fixtures shared by unit tests
+
+use std::io::{Cursor, Write};
+use zip::ZipWriter;
+use zip::write::SimpleFileOptions;
+
+/// A container.xml pointing at `content.opf` in the archive root
+pub(crate) const CONTAINER: &str = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#;
+
+/// Zip the given `(name, content)` pairs into an archive
+pub(crate) fn build(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in files {
+        zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+/// A minimal EPUB 3 package document with the given manifest and spine markup
+pub(crate) fn package(manifest: &str, spine: &str) -> String {
+    format!(
+        r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>T</dc:title><dc:identifier>id</dc:identifier></metadata>
+  <manifest>{}</manifest>
+  <spine>{}</spine>
+</package>"#,
+        manifest, spine
+    )
+}
@@ -0,0 +1,407 @@
+//! Plain-text extraction from XHTML content documents
+//!
+//! The extractor is intentionally lightweight: it strips markup with regular
+//! expressions rather than building a DOM, which is good enough for search,
+//! word counts and previews. Block-level elements become line breaks so the
+//! resulting text keeps its paragraph structure.
+
+use crate::options::TextOptions;
+use crate::ruby;
+use crate::xhtml::{TAG, VOID_ELEMENTS, parse_attributes};
+use regex::Regex;
+use std::fmt::Write;
+use std::sync::LazyLock;
+
+/// Elements that start a new line in extracted text
+const BLOCK_ELEMENTS: &str = "p|div|h[1-6]|li|ul|ol|tr|table|blockquote|section|article|aside|header|footer|pre|dt|dd|dl|figure|figcaption|nav|hr|body";
+
+// Compiled once and shared, since every content document goes through these
+static UNREAD: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(head|script|style)\b[^>]*>.*?</(head|script|style)\s*>")
+        .expect("unread element pattern")
+});
+static COMMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<!--.*?-->").expect("comment pattern"));
+static DIRECTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\bdir\s*=|<bd[io]\b").expect("direction pattern"));
+static LINE_BREAK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)<br\s*/?>").expect("line break pattern"));
+static BLOCK_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)</?({})\b[^>]*>", BLOCK_ELEMENTS)).expect("block tag pattern")
+});
+static BLOCK_NAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!("^(?:{}|html)$", BLOCK_ELEMENTS)).expect("block pattern"));
+static ANY_TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<[^>]*>").expect("any tag pattern"));
+static TITLE_SOURCES: LazyLock<[Regex; 2]> = LazyLock::new(|| {
+    [
+        Regex::new(r"(?is)<h[1-3]\b[^>]*>(.*?)</h[1-3]\s*>").expect("heading pattern"),
+        Regex::new(r"(?is)<title\b[^>]*>(.*?)</title\s*>").expect("title pattern"),
+    ]
+});
+static HEADING: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<h([1-6])\b([^>]*)>(.*?)</h[1-6]\s*>").expect("heading pattern")
+});
+static ID: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"\bid\s*=\s*["']([^"']+)["']"#).expect("id pattern"));
+pub(crate) static ENTITY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").expect("entity pattern")
+});
+
+/// Convert an XHTML document into plain text
+///
+/// `<head>`, `<script>` and `<style>` contents are dropped, block-level
+/// elements are turned into line breaks, common entities are decoded and
+/// runs of whitespace are collapsed. Empty lines are removed. Inline
+/// elements that set a direction (`<bdi>`, `<bdo>`, `dir`) are wrapped in
+/// the matching Unicode bidi controls so right-to-left runs display
+/// correctly once the markup is gone. Ruby readings are dropped.
+pub fn html_to_text(html: &str) -> String {
+    html_to_text_with(html, &TextOptions::default())
+}
+
+/// Convert an XHTML document into plain text, as `html_to_text` does but
+/// with `options` applied
+pub fn html_to_text_with(html: &str, options: &TextOptions) -> String {
+    // Drop elements whose contents are never part of the readable text
+    let mut text = UNREAD.replace_all(html, "").into_owned();
+    text = COMMENT.replace_all(&text, "").into_owned();
+
+    if text.contains("<rt") || text.contains("<rp") {
+        text = ruby::render(&text, options.get_ruby());
+    }
+    if DIRECTION.is_match(&text) {
+        text = insert_bidi_controls(&text);
+    }
+    if options.get_list_markers() && text.contains("<li") {
+        text = insert_list_markers(&text);
+    }
+
+    // Line breaks and block-level boundaries become newlines
+    let line_break = if options.get_br_as_space() { " " } else { "\n" };
+    text = LINE_BREAK.replace_all(&text, line_break).into_owned();
+    text = BLOCK_TAG.replace_all(&text, "\n").into_owned();
+
+    // Remove all remaining tags
+    text = ANY_TAG.replace_all(&text, "").into_owned();
+
+    let mut text = decode_entities(&text);
+    if options.get_strip_soft_hyphens() {
+        text.retain(|c| c != '\u{ad}');
+    }
+
+    let separator = if options.get_collapse_lines() {
+        " "
+    } else {
+        "\n"
+    };
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Put each list item's bullet or number at the start of its text
+///
+/// Numbering follows `start` on `<ol>` and `value` on `<li>`; items of a
+/// `<ul>`, or outside any list, get a bullet.
+fn insert_list_markers(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    // The next number of each open list; `None` for unordered lists
+    let mut lists: Vec<Option<i64>> = Vec::new();
+    let mut last = 0;
+
+    for cap in TAG.captures_iter(html) {
+        let whole = cap.get(0).expect("whole match");
+        text.push_str(&html[last..whole.end()]);
+        last = whole.end();
+        let name = cap[2].to_ascii_lowercase();
+        let closing = !cap[1].is_empty();
+        let attribute = |wanted: &str| {
+            parse_attributes(&cap[3])
+                .into_iter()
+                .find(|(attribute, _)| attribute == wanted)
+                .and_then(|(_, value)| value.trim().parse::<i64>().ok())
+        };
+        match (name.as_str(), closing) {
+            ("ol", false) => lists.push(Some(attribute("start").unwrap_or(1))),
+            ("ul", false) => lists.push(None),
+            ("ol" | "ul", true) => {
+                lists.pop();
+            }
+            ("li", false) => match lists.last_mut() {
+                Some(Some(next)) => {
+                    let number = attribute("value").unwrap_or(*next);
+                    text.push_str(&format!("{}. ", number));
+                    *next = number + 1;
+                }
+                _ => text.push_str("\u{2022} "),
+            },
+            _ => {}
+        }
+    }
+    text.push_str(&html[last..]);
+    text
+}
+
+/// Add bidi controls around inline elements that set a direction
+///
+/// `<bdo>` overrides the direction of its content (LRO/RLO ... PDF); `<bdi>`
+/// and other elements with a `dir` isolate it (LRI/RLI/FSI ... PDI). Block
+/// elements are left alone: each becomes its own line, whose direction the
+/// reader works out from its text. Elements left open are closed at the end
+/// so the controls always balance.
+fn insert_bidi_controls(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut open: Vec<(String, char)> = Vec::new();
+    let mut last = 0;
+
+    for cap in TAG.captures_iter(html) {
+        let whole = cap.get(0).expect("whole match");
+        text.push_str(&html[last..whole.start()]);
+        last = whole.end();
+        let name = cap[2].to_ascii_lowercase();
+
+        if !cap[1].is_empty() {
+            if let Some(position) = open.iter().rposition(|(open, _)| *open == name) {
+                text.extend(open.drain(position..).rev().map(|(_, close)| close));
+            }
+            text.push_str(whole.as_str());
+            continue;
+        }
+        text.push_str(whole.as_str());
+        if cap[3].trim_end().ends_with('/')
+            || BLOCK_NAME.is_match(&name)
+            || VOID_ELEMENTS.split('|').any(|void| void == name)
+        {
+            continue;
+        }
+        let dir = parse_attributes(&cap[3])
+            .into_iter()
+            .find(|(attribute, _)| attribute == "dir")
+            .map(|(_, value)| value.trim().to_ascii_lowercase());
+        let controls = match (name.as_str(), dir.as_deref()) {
+            ("bdo", Some("rtl")) => ('\u{202e}', '\u{202c}'),
+            ("bdo", Some("ltr")) => ('\u{202d}', '\u{202c}'),
+            (_, Some("rtl")) => ('\u{2067}', '\u{2069}'),
+            (_, Some("ltr")) => ('\u{2066}', '\u{2069}'),
+            (_, Some("auto")) | ("bdi", _) => ('\u{2068}', '\u{2069}'),
+            _ => continue,
+        };
+        text.push(controls.0);
+        open.push((name, controls.1));
+    }
+    text.push_str(&html[last..]);
+    text.extend(open.into_iter().rev().map(|(_, close)| close));
+    text
+}
+
+/// Find a title for a content document from its markup
+///
+/// Returns the text of the first `<h1>`-`<h3>` in the document, falling back
+/// to `<title>`. Headings without any text (e.g. an image-only `<h1>`) are
+/// skipped.
+pub fn first_heading(html: &str) -> Option<String> {
+    for re in TITLE_SOURCES.iter() {
+        for cap in re.captures_iter(html) {
+            let text = html_to_text(&cap[1]).replace('\n', " ");
+            if !text.is_empty() {
+                return Some(text);
+            }
+        }
+    }
+
+    None
+}
+
+/// Every `<h1>`-`<hN>` in a document, as `(level, text, id)` in document order
+///
+/// Headings without any text are skipped. `level` is the heading's number
+/// (1 for `<h1>`), and `id` is its `id` attribute when it has one.
+pub(crate) fn find_headings(html: &str, max_level: usize) -> Vec<(usize, String, Option<String>)> {
+    let mut headings = Vec::new();
+
+    for cap in HEADING.captures_iter(html) {
+        let level: usize = cap[1].parse().unwrap_or(6);
+        if level > max_level {
+            continue;
+        }
+        let text = html_to_text(&cap[3]).replace('\n', " ");
+        if text.is_empty() {
+            continue;
+        }
+        let id = ID.captures(&cap[2]).map(|id| id[1].to_string());
+        headings.push((level, text, id));
+    }
+
+    headings
+}
+
+/// Give the headings `find_headings` would list but that lack an `id` one
+/// from `make_id`
+///
+/// Returns `None` when every such heading already had an id.
+pub(crate) fn assign_heading_ids(
+    html: &str,
+    max_level: usize,
+    mut make_id: impl FnMut() -> String,
+) -> Option<String> {
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for cap in HEADING.captures_iter(html) {
+        let level: usize = cap[1].parse().unwrap_or(6);
+        if level > max_level || ID.is_match(&cap[2]) || html_to_text(&cap[3]).is_empty() {
+            continue;
+        }
+        let at = cap.get(1).map_or(0, |level| level.end());
+        out.push_str(&html[last..at]);
+        let _ = write!(out, " id=\"{}\"", escape_xml(&make_id()));
+        last = at;
+    }
+    if last == 0 {
+        return None;
+    }
+    out.push_str(&html[last..]);
+    Some(out)
+}
+
+/// Escape text for use in XML content or a double-quoted attribute
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Decode the named XML entities plus numeric character references
+pub fn decode_entities(text: &str) -> String {
+    ENTITY
+        .replace_all(text, |caps: &regex::Captures| {
+            let entity = &caps[1];
+            let decoded = if let Some(hex) = entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse::<u32>().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some('\u{a0}'),
+                    "shy" => Some('\u{ad}'),
+                    "zwnj" => Some('\u{200c}'),
+                    "zwj" => Some('\u{200d}'),
+                    "lrm" => Some('\u{200e}'),
+                    "rlm" => Some('\u{200f}'),
+                    "mdash" => Some('\u{2014}'),
+                    "ndash" => Some('\u{2013}'),
+                    "hellip" => Some('\u{2026}'),
+                    "lsquo" => Some('\u{2018}'),
+                    "rsquo" => Some('\u{2019}'),
+                    "ldquo" => Some('\u{201c}'),
+                    "rdquo" => Some('\u{201d}'),
+                    _ => None,
+                }
+            };
+
+            decoded
+                .map(|c| c.to_string())
+                .unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_markup_and_keeps_paragraphs() {
+        let html = r#"<html><head><title>Skip me</title></head>
+            <body><h1>Chapter  1</h1><p>Fish &amp; chips<br/>for&#160;two</p>
+            <script>var x = 1;</script></body></html>"#;
+
+        assert_eq!(html_to_text(html), "Chapter 1\nFish & chips\nfor two");
+    }
+
+    #[test]
+    fn options_change_whitespace_hyphens_and_lists() {
+        let html = r#"<p>Hy&shy;phen<br/>ated</p><ol start="3"><li>Three</li><li value="7">Seven</li><li>Eight<ul><li>Dot</li></ul></li></ol>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Hy\u{ad}phen\nated\nThree\nSeven\nEight\nDot"
+        );
+        let options = TextOptions::new()
+            .with_collapse_lines(true)
+            .with_strip_soft_hyphens(true)
+            .with_br_as_space(true)
+            .with_list_markers(true);
+        assert_eq!(
+            html_to_text_with(html, &options),
+            "Hyphen ated 3. Three 7. Seven 8. Eight \u{2022} Dot"
+        );
+    }
+
+    #[test]
+    fn inline_direction_becomes_bidi_controls() {
+        let html = r#"<p dir="rtl">Title: <span dir="rtl">שלום</span> and <bdi>مرحبا</bdi>, <bdo dir="ltr">abc</bdo> <em dir="auto">x<b>y</em>&rlm;</p>"#;
+        assert_eq!(
+            html_to_text(html),
+            "Title: \u{2067}שלום\u{2069} and \u{2068}مرحبا\u{2069}, \u{202d}abc\u{202c} \u{2068}xy\u{2069}\u{200f}"
+        );
+    }
+
+    #[test]
+    fn first_heading_prefers_headings_over_title() {
+        let html = r#"<html><head><title>Book</title></head><body>
+            <h1><img src="logo.png"/></h1><h2 class="ct">III.<br/>The <i>Spouter</i>-Inn</h2></body></html>"#;
+        assert_eq!(first_heading(html).as_deref(), Some("III. The Spouter-Inn"));
+        assert_eq!(
+            first_heading("<html><head><title>Only</title></head><body/></html>").as_deref(),
+            Some("Only")
+        );
+        assert_eq!(first_heading("<p>no headings</p>"), None);
+    }
+
+    #[test]
+    fn find_headings_respects_max_level() {
+        let html = r#"<h1 id="c1">Chapter <em>One</em></h1><h2>Part</h2><h4 id="x">Deep</h4><h2 id="e"> </h2>"#;
+        assert_eq!(
+            find_headings(html, 3),
+            vec![
+                (1, "Chapter One".to_string(), Some("c1".to_string())),
+                (2, "Part".to_string(), None),
+            ]
+        );
+        assert_eq!(find_headings(html, 1).len(), 1);
+    }
+
+    #[test]
+    fn assign_heading_ids_fills_in_missing_ids() {
+        let html = r#"<h1 id="c1">One</h1><h2 class="x">Part</h2><h4>Deep</h4><h2> </h2>"#;
+        let mut n = 0;
+        let assigned = assign_heading_ids(html, 3, || {
+            n += 1;
+            format!("h{}", n)
+        });
+        assert_eq!(
+            assigned.as_deref(),
+            Some(r#"<h1 id="c1">One</h1><h2 id="h1" class="x">Part</h2><h4>Deep</h4><h2> </h2>"#)
+        );
+        assert_eq!(assign_heading_ids(html, 1, || unreachable!()), None);
+    }
+}
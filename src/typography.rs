@@ -0,0 +1,368 @@
+//! Typographic clean-up of content documents
+//!
+//! Books exported from word processors, or typed in plain-text editors, are
+//! full of straight quotes, double hyphens and three-dot ellipses.
+//! `Epub::fix_typography` replaces them with curly quotes in the style of
+//! each document's language, em and en dashes, and the ellipsis character.
+//!
+//! Only text is touched: tags, attributes, comments, and everything inside
+//! `<code>`, `<pre>` and similar elements are left exactly as they are,
+//! since straight quotes there are usually significant. Whether a quote
+//! opens or closes depends on the character before it, which is carried
+//! across inline tags so `"<i>Yes</i>," she said` comes out right.
+
+use crate::epub::{Epub, is_content_media_type};
+use crate::nav::is_navigation;
+use crate::xhtml::{BLOCKS, MARKUP, VERBATIM};
+
+const NARROW_NBSP: char = '\u{202f}';
+
+/// Opening and closing double quotes, then single quotes
+struct Quotes {
+    double: (char, char),
+    single: (char, char),
+}
+
+const ENGLISH: Quotes = Quotes {
+    double: ('“', '”'),
+    single: ('‘', '’'),
+};
+const GERMAN: Quotes = Quotes {
+    double: ('„', '“'),
+    single: ('‚', '‘'),
+};
+const POLISH: Quotes = Quotes {
+    double: ('„', '”'),
+    single: ('‚', '’'),
+};
+const GUILLEMETS: Quotes = Quotes {
+    double: ('«', '»'),
+    single: ('“', '”'),
+};
+const FRENCH: Quotes = Quotes {
+    double: ('«', '»'),
+    single: ('‹', '›'),
+};
+const SWEDISH: Quotes = Quotes {
+    double: ('”', '”'),
+    single: ('’', '’'),
+};
+const JAPANESE: Quotes = Quotes {
+    double: ('「', '」'),
+    single: ('『', '』'),
+};
+
+/// The quotation marks used by a language, from its primary subtag
+fn quotes_for(language: &str) -> &'static Quotes {
+    let primary = language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match primary.as_str() {
+        "de" | "cs" | "sk" | "sl" | "et" | "lt" | "is" | "ka" => &GERMAN,
+        "pl" | "hu" | "ro" | "hr" | "bg" => &POLISH,
+        "es" | "it" | "pt" | "ru" | "uk" | "be" | "ca" | "el" | "no" | "nb" | "nn" => &GUILLEMETS,
+        "fr" => &FRENCH,
+        "sv" | "fi" => &SWEDISH,
+        "ja" => &JAPANESE,
+        _ => &ENGLISH,
+    }
+}
+
+/// Which fixes `Epub::fix_typography` applies
+#[derive(Debug, Clone)]
+pub struct TypographyOptions {
+    quotes: bool,
+    dashes: bool,
+    ellipses: bool,
+    language: Option<String>,
+}
+
+impl Default for TypographyOptions {
+    fn default() -> Self {
+        Self {
+            quotes: true,
+            dashes: true,
+            ellipses: true,
+            language: None,
+        }
+    }
+}
+
+impl TypographyOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Curl straight quotes and apostrophes (on by default)
+    pub fn with_quotes(mut self, quotes: bool) -> Self {
+        self.quotes = quotes;
+        self
+    }
+
+    pub fn get_quotes(&self) -> bool {
+        self.quotes
+    }
+
+    /// Turn `--` and `---` into em dashes and a spaced hyphen into a spaced
+    /// en dash (on by default)
+    pub fn with_dashes(mut self, dashes: bool) -> Self {
+        self.dashes = dashes;
+        self
+    }
+
+    pub fn get_dashes(&self) -> bool {
+        self.dashes
+    }
+
+    /// Turn `...` into `…` (on by default)
+    pub fn with_ellipses(mut self, ellipses: bool) -> Self {
+        self.ellipses = ellipses;
+        self
+    }
+
+    pub fn get_ellipses(&self) -> bool {
+        self.ellipses
+    }
+
+    /// Use this language's quotation marks everywhere, instead of each
+    /// document's declared language (or the book's `dc:language`)
+    pub fn with_language(mut self, language: impl Into<String>) -> Self {
+        self.language = Some(language.into());
+        self
+    }
+
+    pub fn get_language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+}
+
+impl Epub {
+    /// Apply typographic fixes to the text of every content document,
+    /// returning the hrefs of the documents that changed
+    pub fn fix_typography(&mut self, options: &TypographyOptions) -> Vec<String> {
+        enter_span!("fix_typography");
+        let updates: Vec<(String, String)> = self
+            .all_files
+            .iter()
+            .filter(|file| is_content_media_type(&file.media_type))
+            .filter(|file| {
+                !self
+                    .get_resource_by_href(&file.href)
+                    .is_some_and(is_navigation)
+            })
+            .filter_map(|file| {
+                let language = options
+                    .language
+                    .clone()
+                    .or_else(|| file.get_language())
+                    .or_else(|| self.metadata.language.clone())
+                    .unwrap_or_default();
+                let fixed = fix_document(&file.content, quotes_for(&language), options);
+                (fixed != file.content).then(|| (file.href.to_string(), fixed))
+            })
+            .collect();
+
+        let changed = updates.iter().map(|(href, _)| href.clone()).collect();
+        for (href, content) in updates {
+            self.put_resource_bytes(&href, content.into_bytes());
+        }
+        changed
+    }
+}
+
+/// `html` with every text node in its body fixed
+fn fix_document(html: &str, quotes: &Quotes, options: &TypographyOptions) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut position = 0;
+    let mut in_body = false;
+    let mut verbatim: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    for markup in MARKUP.captures_iter(html) {
+        let whole = markup.get(0).expect("whole match");
+        let text = &html[position..whole.start()];
+        if in_body && verbatim.is_empty() {
+            out.push_str(&fix_text(text, quotes, options, &mut previous));
+        } else {
+            out.push_str(text);
+        }
+        out.push_str(whole.as_str());
+        position = whole.end();
+
+        let Some(name) = markup.get(2).map(|name| name.as_str().to_ascii_lowercase()) else {
+            continue;
+        };
+        let closing = !markup[1].is_empty();
+        let self_closing = whole.as_str().ends_with("/>");
+        if name == "body" {
+            in_body = !closing;
+        }
+        if VERBATIM.contains(&name.as_str()) && !self_closing {
+            if closing {
+                if let Some(open) = verbatim.iter().rposition(|open| *open == name) {
+                    verbatim.truncate(open);
+                }
+            } else {
+                verbatim.push(name.clone());
+            }
+        }
+        if BLOCKS.contains(&name.as_str()) {
+            previous = None;
+        }
+    }
+    out.push_str(&html[position..]);
+    out
+}
+
+/// Fix one text node; `previous` is the last character of text before it
+fn fix_text(
+    text: &str,
+    quotes: &Quotes,
+    options: &TypographyOptions,
+    previous: &mut Option<char>,
+) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let opens = previous.is_none_or(|p| p.is_whitespace() || "([{—–-/“‘„‚«‹「『".contains(p));
+
+        if options.ellipses && chars[i..].starts_with(&['.', '.', '.']) {
+            out.push('…');
+            i += 3;
+            *previous = Some('…');
+            continue;
+        }
+        if options.dashes && c == '-' {
+            let run = chars[i..].iter().take_while(|&&c| c == '-').count();
+            if run == 2 || run == 3 {
+                out.push('—');
+                i += run;
+                *previous = Some('—');
+                continue;
+            }
+            let spaced =
+                previous.is_some_and(char::is_whitespace) && next.is_some_and(char::is_whitespace);
+            if run == 1 && spaced {
+                out.push('–');
+                i += 1;
+                *previous = Some('–');
+                continue;
+            }
+        }
+        if options.quotes && (c == '"' || c == '\'') {
+            let (open, close) = if c == '"' {
+                quotes.double
+            } else {
+                quotes.single
+            };
+            // Apostrophes: it's, rock 'n' roll, the '90s
+            let apostrophe = c == '\''
+                && (!opens
+                    || (next.is_some_and(|n| n.is_ascii_digit())
+                        && chars.get(i + 2).is_some_and(|n| n.is_ascii_digit())));
+            let mark = if apostrophe {
+                '’'
+            } else if opens {
+                open
+            } else {
+                close
+            };
+            let french = mark == '«' || mark == '‹';
+            if french && !apostrophe {
+                out.push(mark);
+                out.push(NARROW_NBSP);
+                i += 1;
+                // The space typed after the quote is replaced by the narrow one
+                while chars.get(i).is_some_and(|c| *c == ' ') {
+                    i += 1;
+                }
+            } else if (mark == '»' || mark == '›') && !apostrophe {
+                while out.ends_with(' ') {
+                    out.pop();
+                }
+                out.push(NARROW_NBSP);
+                out.push(mark);
+                i += 1;
+            } else {
+                out.push(mark);
+                i += 1;
+            }
+            *previous = Some(mark);
+            continue;
+        }
+        out.push(c);
+        *previous = Some(c);
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn fix(html: &str, language: &str) -> String {
+        fix_document(html, quotes_for(language), &TypographyOptions::new())
+    }
+
+    #[test]
+    fn text_gets_curly_quotes_dashes_and_ellipses() {
+        assert_eq!(
+            fix(
+                r#"<body><p>"<i>Yes</i>," she said -- it's the '90s... <a href="x">'Quite' - so</a></p></body>"#,
+                "en"
+            ),
+            r#"<body><p>“<i>Yes</i>,” she said — it’s the ’90s… <a href="x">‘Quite’ – so</a></p></body>"#
+        );
+        assert_eq!(
+            fix(r#"<body><p>"Ja," sagte er.</p></body>"#, "de-AT"),
+            r#"<body><p>„Ja,“ sagte er.</p></body>"#
+        );
+        assert_eq!(
+            fix(r#"<body><p>"Oui"</p></body>"#, "fr"),
+            "<body><p>«\u{202f}Oui\u{202f}»</p></body>"
+        );
+    }
+
+    #[test]
+    fn code_comments_and_head_are_left_alone() {
+        let html = r#"<head><title>"T"</title></head><body><pre>x = "a" -- b</pre><!-- "c" --><p><code>'d'</code> "e"</p></body>"#;
+        assert_eq!(
+            fix(html, "en"),
+            r#"<head><title>"T"</title></head><body><pre>x = "a" -- b</pre><!-- "c" --><p><code>'d'</code> “e”</p></body>"#
+        );
+    }
+
+    #[test]
+    fn documents_use_their_declared_language() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                r#"<html xml:lang="de"><body><p>"Ja"</p></body></html>"#,
+            ),
+            (
+                "ch2.xhtml",
+                "<html><body><p>No quotes here</p></body></html>",
+            ),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            epub.fix_typography(&TypographyOptions::new()),
+            ["ch1.xhtml"]
+        );
+        assert!(epub.get_all_files()[0].get_content().contains("„Ja“"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Structural validation of a parsed EPUB
+//!
+//! These checks cover the problems that most often break reading systems:
+//! missing required metadata, spine entries pointing nowhere, and manifest
+//! items whose files are absent from the archive. It is not a replacement
+//! for a full conformance checker such as EPUBCheck.
+
+use crate::archive;
+use crate::epub::Epub;
+use crate::sniff;
+use std::fmt;
+
+/// How serious a validation issue is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The book violates the EPUB specification
+    Error,
+    /// The book is readable but likely to misbehave somewhere
+    Warning,
+}
+
+/// A single problem found while validating an EPUB
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    severity: Severity,
+    message: String,
+}
+
+impl ValidationIssue {
+    pub(crate) fn new(severity: Severity, message: impl Into<String>) -> Self {
+        ValidationIssue {
+            severity,
+            message: message.into(),
+        }
+    }
+
+    pub fn get_severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", label, self.message)
+    }
+}
+
+impl Epub {
+    /// Check the book for structural problems
+    ///
+    /// An empty result means no issues were found.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+
+        // Required Dublin Core metadata
+        if self.get_title().is_none_or(|title| title.trim().is_empty()) {
+            issues.push(ValidationIssue::new(Severity::Error, "missing dc:title"));
+        }
+        if self
+            .get_language()
+            .is_none_or(|lang| lang.trim().is_empty())
+        {
+            issues.push(ValidationIssue::new(Severity::Error, "missing dc:language"));
+        }
+        if self.get_identifier().trim().is_empty() {
+            issues.push(ValidationIssue::new(
+                Severity::Error,
+                "missing dc:identifier",
+            ));
+        }
+
+        // Spine must reference existing manifest items
+        if self.get_spine().is_empty() {
+            issues.push(ValidationIssue::new(Severity::Error, "spine is empty"));
+        }
+        for idref in self.get_spine() {
+            if self.get_resource_by_id(idref).is_none() {
+                issues.push(ValidationIssue::new(
+                    Severity::Error,
+                    format!("spine references unknown manifest item '{}'", idref),
+                ));
+            }
+        }
+
+        // Every manifest item must exist in the archive
+        match self.open_archive() {
+            Ok(archive) => {
+                let tolerant = self.get_parse_options().get_tolerant_hrefs();
+                for resource in self.get_resources() {
+                    let path = Self::resolve_path(self.get_opf_path(), resource.get_href());
+                    if archive::entry_index(&archive, &path, tolerant).is_none() {
+                        issues.push(ValidationIssue::new(
+                            Severity::Error,
+                            format!(
+                                "manifest item '{}' points to missing file '{}'",
+                                resource.get_id(),
+                                path
+                            ),
+                        ));
+                    }
+                }
+            }
+            Err(e) => {
+                issues.push(ValidationIssue::new(
+                    Severity::Error,
+                    format!("could not re-open archive: {}", e),
+                ));
+            }
+        }
+
+        // Declared media types should match the contents
+        for resource in self.get_resources() {
+            let Some(detected) = self.detected_media_type(resource.get_href()) else {
+                continue;
+            };
+            if !sniff::same_media_type(resource.get_media_type(), detected) {
+                issues.push(ValidationIssue::new(
+                    Severity::Warning,
+                    format!(
+                        "manifest item '{}' is declared as '{}' but looks like '{}'",
+                        resource.get_id(),
+                        resource.get_media_type(),
+                        detected
+                    ),
+                ));
+            }
+        }
+
+        // Navigation and cover
+        let has_nav = self.get_resources().iter().any(|r| r.has_property("nav"));
+        let has_ncx = self
+            .get_resources()
+            .iter()
+            .any(|r| r.get_media_type() == "application/x-dtbncx+xml");
+        if !has_nav && !has_ncx {
+            issues.push(ValidationIssue::new(
+                Severity::Warning,
+                "no navigation document or NCX found",
+            ));
+        }
+
+        if let Some(cover_id) = self.get_cover()
+            && self.get_resource_by_id(cover_id).is_none()
+        {
+            issues.push(ValidationIssue::new(
+                Severity::Warning,
+                format!("cover references unknown manifest item '{}'", cover_id),
+            ));
+        }
+
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    fn messages(epub: &Epub) -> Vec<String> {
+        epub.validate().iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn missing_metadata_and_files_are_errors() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>
+               <item id="style" href="style.css" media-type="text/css"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            messages(&epub),
+            [
+                "error: missing dc:language",
+                "error: manifest item 'ch2' points to missing file 'ch2.xhtml'",
+                "error: manifest item 'style' points to missing file 'style.css'",
+                "warning: no navigation document or NCX found",
+            ]
+        );
+        let errors = epub
+            .validate()
+            .iter()
+            .filter(|issue| issue.is_error())
+            .count();
+        assert_eq!(errors, 3);
+    }
+
+    #[test]
+    fn a_complete_book_has_no_issues() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:title>T</dc:title><dc:identifier>id</dc:identifier><dc:language>en</dc:language>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+  </manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            (
+                "nav.xhtml",
+                r#"<html><body><nav><ol><li><a href="ch1.xhtml">One</a></li></ol></nav></body></html>"#,
+            ),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+
+        assert!(messages(&epub).is_empty(), "{:?}", messages(&epub));
+    }
+}
@@ -0,0 +1,257 @@
+//! Word frequencies for language-learning tools
+//!
+//! `Epub::vocabulary` counts the words of every chapter and of the whole
+//! book. Words are runs of letters, keeping apostrophes and hyphens inside
+//! them ("don't", "well-known"), and are lowercased. Scripts written without
+//! spaces, such as Chinese or Japanese, come out as whole runs of text
+//! rather than words.
+
+use crate::epub::Epub;
+use std::collections::BTreeMap;
+
+/// How often each word occurs in some text
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordFrequencies {
+    counts: BTreeMap<String, usize>,
+}
+
+impl WordFrequencies {
+    /// Count the words of a plain text
+    pub fn from_text(text: &str) -> Self {
+        let mut frequencies = Self::default();
+        for word in words(text) {
+            *frequencies.counts.entry(word).or_insert(0) += 1;
+        }
+        frequencies
+    }
+
+    /// Occurrences of `word`, which is lowercased before the lookup
+    pub fn get_count(&self, word: &str) -> usize {
+        self.counts.get(&word.to_lowercase()).copied().unwrap_or(0)
+    }
+
+    /// Every word with its count, in alphabetical order
+    pub fn get_counts(&self) -> &BTreeMap<String, usize> {
+        &self.counts
+    }
+
+    /// The `n` most frequent words, most frequent first; ties are alphabetical
+    pub fn most_common(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut words: Vec<(&str, usize)> = self
+            .counts
+            .iter()
+            .map(|(word, &count)| (word.as_str(), count))
+            .collect();
+        words.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        words.truncate(n);
+        words
+    }
+
+    /// Number of distinct words
+    pub fn get_distinct_words(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Number of words, counting repeats
+    pub fn get_total_words(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    fn merge(&mut self, other: &WordFrequencies) {
+        for (word, count) in &other.counts {
+            *self.counts.entry(word.clone()).or_insert(0) += count;
+        }
+    }
+
+    fn remove_words(&mut self, words: &[&str]) {
+        self.counts
+            .retain(|word, _| !words.contains(&word.as_str()));
+    }
+}
+
+/// Word frequencies for a whole book and for each of its chapters
+#[derive(Debug, Clone, Default)]
+pub struct Vocabulary {
+    book: WordFrequencies,
+    chapters: Vec<WordFrequencies>,
+}
+
+impl Vocabulary {
+    pub fn get_book(&self) -> &WordFrequencies {
+        &self.book
+    }
+
+    /// Frequencies per chapter, in the order of `Epub::get_chapters`
+    pub fn get_chapters(&self) -> &[WordFrequencies] {
+        &self.chapters
+    }
+
+    /// Drop the common function words of `language` ("the", "and", ...)
+    ///
+    /// Only the primary subtag counts, so "en-GB" uses the English list.
+    /// Languages without a built-in list (see `stop_words`) are left alone.
+    pub fn without_stop_words(mut self, language: &str) -> Self {
+        if let Some(words) = stop_words(language) {
+            self.book.remove_words(words);
+            for chapter in &mut self.chapters {
+                chapter.remove_words(words);
+            }
+        }
+        self
+    }
+}
+
+impl Epub {
+    /// Count the words of every chapter and of the whole book
+    ///
+    /// Stop words are kept; filter them with `Vocabulary::without_stop_words`,
+    /// for example using the book's `get_language`.
+    pub fn vocabulary(&self) -> Vocabulary {
+        let chapters: Vec<WordFrequencies> = self
+            .get_chapters()
+            .iter()
+            .map(|chapter| WordFrequencies::from_text(&chapter.get_text()))
+            .collect();
+        let mut book = WordFrequencies::default();
+        for chapter in &chapters {
+            book.merge(chapter);
+        }
+        Vocabulary { book, chapters }
+    }
+}
+
+/// Built-in stop-word list for a language, if there is one
+///
+/// Lists exist for English, German, French, Spanish, Italian, Portuguese and
+/// Dutch. They are kept short: articles, pronouns, prepositions,
+/// conjunctions and auxiliary verbs.
+pub fn stop_words(language: &str) -> Option<&'static [&'static str]> {
+    let primary = language.split(['-', '_']).next().unwrap_or("");
+    let words: &'static [&'static str] = match primary.to_ascii_lowercase().as_str() {
+        "en" => &[
+            "a", "about", "after", "all", "an", "and", "are", "as", "at", "be", "been", "but",
+            "by", "can", "could", "did", "do", "for", "from", "had", "has", "have", "he", "her",
+            "him", "his", "i", "if", "in", "into", "is", "it", "its", "me", "my", "no", "not",
+            "of", "on", "or", "our", "she", "so", "than", "that", "the", "their", "them", "then",
+            "there", "they", "this", "to", "up", "us", "was", "we", "were", "what", "when",
+            "which", "who", "will", "with", "would", "you", "your",
+        ],
+        "de" => &[
+            "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "da", "das",
+            "dass", "dem", "den", "der", "des", "die", "du", "ein", "eine", "einem", "einen",
+            "einer", "er", "es", "für", "hat", "hatte", "ich", "ihr", "im", "in", "ist", "ja",
+            "mit", "nach", "nicht", "noch", "nur", "oder", "sich", "sie", "sind", "so", "um",
+            "und", "von", "war", "was", "wie", "wir", "zu", "zum", "zur",
+        ],
+        "fr" => &[
+            "à", "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "est",
+            "et", "il", "ils", "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "mon",
+            "ne", "nous", "on", "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se", "ses",
+            "son", "sur", "ta", "te", "tu", "un", "une", "vous", "y", "était", "été",
+        ],
+        "es" => &[
+            "a", "al", "como", "con", "de", "del", "el", "ella", "en", "era", "es", "esta", "este",
+            "fue", "ha", "la", "las", "le", "lo", "los", "me", "mi", "no", "nos", "o", "para",
+            "pero", "por", "que", "se", "si", "su", "sus", "te", "tu", "un", "una", "y", "ya",
+            "yo",
+        ],
+        "it" => &[
+            "a", "al", "alla", "che", "ci", "come", "con", "da", "del", "della", "di", "e", "è",
+            "era", "gli", "ha", "i", "il", "in", "io", "la", "le", "lo", "ma", "mi", "non", "per",
+            "più", "se", "si", "su", "suo", "sua", "ti", "tu", "un", "una", "uno",
+        ],
+        "pt" => &[
+            "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "ela", "ele",
+            "em", "era", "foi", "isso", "já", "lhe", "mais", "mas", "me", "na", "não", "no", "nos",
+            "o", "os", "ou", "para", "pela", "pelo", "por", "que", "se", "seu", "sua", "um", "uma",
+        ],
+        "nl" => &[
+            "aan", "al", "als", "bij", "dan", "dat", "de", "die", "dit", "door", "een", "en", "er",
+            "had", "heeft", "het", "hij", "hem", "ik", "in", "is", "je", "maar", "me", "met",
+            "mij", "naar", "niet", "nog", "of", "om", "ook", "op", "over", "te", "tot", "uit",
+            "van", "voor", "was", "wat", "we", "zich", "zij", "zijn", "ze",
+        ],
+        _ => return None,
+    };
+    Some(words)
+}
+
+/// Lowercased words of `text`
+fn words(text: &str) -> Vec<String> {
+    let is_letter = |c: char| c.is_alphabetic() || ('\u{300}'..='\u{36f}').contains(&c);
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if is_letter(c) {
+            word.extend(c.to_lowercase());
+            continue;
+        }
+        // Apostrophes and hyphens only count between two letters
+        let joins = matches!(c, '\'' | '’' | '-' | '‐')
+            && !word.is_empty()
+            && chars.get(i + 1).is_some_and(|&next| is_letter(next));
+        if joins {
+            word.push(if c == '’' {
+                '\''
+            } else if c == '‐' {
+                '-'
+            } else {
+                c
+            });
+        } else if !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+
+    #[test]
+    fn counts_words_per_chapter_and_book() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="ch2" href="ch2.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/><itemref idref="ch2"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                "<html><body><p>The Café don’t close; the well-known café -- 42 times!</p></body></html>",
+            ),
+            (
+                "ch2.xhtml",
+                "<html><body><p>Straße, STRASSE and the end.</p></body></html>",
+            ),
+        ]))
+        .unwrap();
+
+        let vocabulary = epub.vocabulary();
+        let first = &vocabulary.get_chapters()[0];
+        assert_eq!(first.get_count("café"), 2);
+        assert_eq!(first.get_count("don't"), 1);
+        assert_eq!(first.get_count("well-known"), 1);
+        assert_eq!(first.get_count("42"), 0);
+        assert_eq!(vocabulary.get_book().get_count("the"), 3);
+        assert_eq!(
+            vocabulary.get_book().most_common(2),
+            [("the", 3), ("café", 2)]
+        );
+        assert_eq!(vocabulary.get_book().get_count("straße"), 1);
+
+        let content = vocabulary.without_stop_words("en-US");
+        assert_eq!(content.get_book().get_count("the"), 0);
+        assert_eq!(content.get_book().get_total_words(), 9);
+        assert!(stop_words("tlh").is_none());
+    }
+}
@@ -0,0 +1,453 @@
+//! Watermark (social DRM) detection
+//!
+//! Stores that sell without DRM often stamp each copy with the buyer
+//! instead: an email address or order number in the OPF metadata, a
+//! `<meta>` in the content documents, or text in an element hidden with
+//! CSS. `Epub::watermarks` finds these so tools can strip personal data
+//! before a book or an excerpt is shared. Detection is heuristic: it looks
+//! for email addresses and for names like `watermark`, `customer` or
+//! `transaction`.
+//!
+//! `Epub::scrub_personal_data` (or `WriteOptions::with_scrub_personal_data`
+//! when saving) removes what was found, along with other purchase and
+//! calibre bookkeeping, and lists each removal in the parse report.
+
+use crate::epub::Epub;
+use crate::extensions::MetadataElement;
+use crate::package::MetaEntry;
+use crate::text::html_to_text;
+use crate::xhtml::find_elements;
+use regex::Regex;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// Words in names, ids and classes that suggest purchase data
+const WATERMARK_KEYWORDS: &[&str] = &[
+    "watermark",
+    "booxtream",
+    "buyer",
+    "customer",
+    "purchaser",
+    "purchase",
+    "licensee",
+    "transaction",
+    "orderid",
+    "order-id",
+    "order_id",
+];
+
+/// Files some stores and tools add next to the book, with purchase or
+/// library data: Apple's purchase record and calibre's bookmarks
+const PERSONAL_FILES: &[&str] = &["/iTunesMetadata.plist", "/META-INF/calibre_bookmarks.txt"];
+
+/// calibre metas that record the user's library rather than the book:
+/// when it was added, their rating, their columns and categories. Series
+/// and sort keys (`calibre:series`, `calibre:title_sort`, ...) describe the
+/// book and stay.
+const CALIBRE_BOOKKEEPING: &[&str] = &[
+    "calibre:timestamp",
+    "calibre:rating",
+    "calibre:user_categories",
+    "calibre:author_link_map",
+    "calibre:link_maps",
+];
+
+/// Where a watermark was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatermarkKind {
+    /// A child of the OPF `<metadata>`
+    Metadata,
+    /// A `<meta>` in a content document's head
+    DocumentMeta,
+    /// Text in an element that isn't displayed
+    HiddenText,
+}
+
+/// A likely watermark, from `Epub::watermarks`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watermark {
+    kind: WatermarkKind,
+    href: Option<String>,
+    name: String,
+    text: String,
+    /// Byte range of the element in the content document
+    pub(crate) range: Option<Range<usize>>,
+}
+
+impl Watermark {
+    pub fn get_kind(&self) -> WatermarkKind {
+        self.kind
+    }
+
+    /// The content document it was found in; `None` for the OPF
+    pub fn get_href(&self) -> Option<&str> {
+        self.href.as_deref()
+    }
+
+    /// The metadata element, `<meta>` name or hidden element it was found in
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The watermark text, e.g. the buyer's email address
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Epub {
+    /// Likely buyer watermarks in the metadata and content documents
+    pub fn watermarks(&self) -> Vec<Watermark> {
+        static EMAIL: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").expect("email pattern")
+        });
+        let mut watermarks = Vec::new();
+
+        for element in &self.metadata_elements {
+            let (label, text) = metadata_label(element);
+            let named = has_keyword(&label) || element.get_attribute("id").is_some_and(has_keyword);
+            if named || EMAIL.is_match(text) {
+                watermarks.push(Watermark {
+                    kind: WatermarkKind::Metadata,
+                    href: None,
+                    name: label.to_string(),
+                    text: text.to_string(),
+                    range: None,
+                });
+            }
+        }
+
+        for file in &self.all_files {
+            let metas = find_elements(&file.content, |e| e.name == "meta");
+            for meta in metas {
+                let (Some(name), Some(content)) =
+                    (meta.attribute("name"), meta.attribute("content"))
+                else {
+                    continue;
+                };
+                if has_keyword(name) || EMAIL.is_match(content) {
+                    watermarks.push(Watermark {
+                        kind: WatermarkKind::DocumentMeta,
+                        href: Some(file.href.to_string()),
+                        name: name.to_string(),
+                        text: content.to_string(),
+                        range: Some(meta.range),
+                    });
+                }
+            }
+
+            let hidden = find_elements(&file.content, |e| {
+                let style = e
+                    .attribute("style")
+                    .unwrap_or("")
+                    .to_ascii_lowercase()
+                    .replace(' ', "");
+                e.attribute("hidden").is_some()
+                    || style.contains("display:none")
+                    || style.contains("visibility:hidden")
+                    || style.contains("font-size:0")
+                    || e.attribute("class").is_some_and(has_keyword)
+                    || e.attribute("id").is_some_and(has_keyword)
+            });
+            for element in hidden {
+                let text = html_to_text(element.inner).trim().to_string();
+                let named = element.attribute("class").is_some_and(has_keyword)
+                    || element.attribute("id").is_some_and(has_keyword);
+                if named || EMAIL.is_match(&text) || has_keyword(&text) {
+                    watermarks.push(Watermark {
+                        kind: WatermarkKind::HiddenText,
+                        href: Some(file.href.to_string()),
+                        name: element.name.clone(),
+                        text,
+                        range: Some(element.range),
+                    });
+                }
+            }
+        }
+        watermarks
+    }
+
+    /// Remove watermarks, purchase metadata and calibre bookkeeping
+    ///
+    /// Removes everything `watermarks` reports (metadata and `<meta>`
+    /// elements, and hidden elements with their content), calibre's library
+    /// identifiers and bookkeeping metas, and `iTunesMetadata.plist` and
+    /// `calibre_bookmarks.txt`. Each removal is described in
+    /// `ParseReport::get_scrubbed`, and the descriptions are returned.
+    pub fn scrub_personal_data(&mut self) -> Vec<String> {
+        let mut scrubbed = Vec::new();
+        let mut ranges: Vec<(String, std::ops::Range<usize>)> = Vec::new();
+
+        for watermark in self.watermarks() {
+            match (watermark.href, watermark.range) {
+                (Some(href), Some(range)) => {
+                    scrubbed.push(match watermark.kind {
+                        WatermarkKind::DocumentMeta => {
+                            format!("removed <meta name=\"{}\"> from {}", watermark.name, href)
+                        }
+                        _ => format!("removed hidden <{}> from {}", watermark.name, href),
+                    });
+                    ranges.push((href, range));
+                }
+                _ => {
+                    if self.remove_metadata(&watermark.name, &watermark.text) {
+                        scrubbed.push(format!("removed metadata {}", watermark.name));
+                    }
+                }
+            }
+        }
+
+        // A watermark can sit inside another (a hidden element in a hidden
+        // element), so merge overlapping ranges, then cut later ones first
+        // so earlier ones stay valid
+        ranges.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.start.cmp(&b.1.start)));
+        let mut merged: Vec<(String, Range<usize>)> = Vec::new();
+        for (href, range) in ranges {
+            match merged.last_mut() {
+                Some((last, outer)) if *last == href && range.start < outer.end => {
+                    outer.end = outer.end.max(range.end);
+                }
+                _ => merged.push((href, range)),
+            }
+        }
+        for (href, range) in merged.into_iter().rev() {
+            if let Some(file) = self.all_files.iter().find(|file| *file.href == *href) {
+                let mut content = file.content.clone();
+                content.replace_range(range, "");
+                self.put_resource_bytes(&href, content.into_bytes());
+            }
+        }
+
+        let bookkeeping = |meta: &MetaEntry| {
+            meta.name
+                .as_deref()
+                .or(meta.property.as_deref())
+                .is_some_and(is_calibre_bookkeeping)
+        };
+        for meta in self.meta.iter().filter(|meta| bookkeeping(meta)) {
+            let name = meta.name.as_deref().or(meta.property.as_deref());
+            scrubbed.push(format!("removed metadata {}", name.unwrap_or_default()));
+        }
+        self.meta.retain(|meta| !bookkeeping(meta));
+        self.metadata_elements
+            .retain(|element| !is_calibre_bookkeeping(&metadata_label(element).0));
+
+        // The unique identifier stays even if calibre minted it: the package
+        // needs one
+        let unique = self.unique_identifier.clone();
+        let before = self.metadata_elements.len();
+        self.metadata_elements.retain(|element| {
+            !(is_calibre_identifier(element) && element.get_attribute("id") != Some(&unique))
+        });
+        if self.metadata_elements.len() < before {
+            scrubbed.push("removed calibre dc:identifier".to_string());
+        }
+
+        for path in PERSONAL_FILES {
+            if self.get_resource_bytes(path).is_some() {
+                self.removed
+                    .insert(Self::resolve_path(&self.opf_path, path));
+                scrubbed.push(format!("removed {}", path.trim_start_matches('/')));
+            }
+        }
+
+        for description in &scrubbed {
+            self.report.scrubbed(description.clone());
+        }
+        scrubbed
+    }
+
+    /// Drop the metadata element named `name` with value `value`; whether it was found
+    fn remove_metadata(&mut self, name: &str, value: &str) -> bool {
+        self.metadata_elements.retain(|element| {
+            let (label, text) = metadata_label(element);
+            label != name || text != value
+        });
+        let before = self.meta.len();
+        self.meta.retain(|meta| {
+            let property =
+                meta.property.as_deref() == Some(name) && meta.value.as_deref() == Some(value);
+            let named =
+                meta.name.as_deref() == Some(name) && meta.content.as_deref() == Some(value);
+            !(property || named)
+        });
+        if self.meta.len() < before {
+            return true;
+        }
+
+        let metadata = &mut self.metadata;
+        let field = match name {
+            "dc:rights" => &mut metadata.rights,
+            "dc:description" => &mut metadata.description,
+            "dc:publisher" => &mut metadata.publisher,
+            _ => return false,
+        };
+        if field.as_deref() == Some(value) {
+            *field = None;
+            return true;
+        }
+        false
+    }
+}
+
+/// A metadata element's property or name, and its value
+///
+/// Dublin Core elements are labelled `dc:` whatever prefix the book used.
+fn metadata_label(element: &MetadataElement) -> (Cow<'_, str>, &str) {
+    let label = match element
+        .get_attribute("property")
+        .or_else(|| element.get_attribute("name"))
+    {
+        Some(label) => Cow::Borrowed(label),
+        None if element.is_dublin_core() => Cow::Owned(format!("dc:{}", element.get_local_name())),
+        None => Cow::Borrowed(element.get_name()),
+    };
+    let text = element
+        .get_attribute("content")
+        .unwrap_or(element.get_text());
+    (label, text)
+}
+
+/// Whether a meta name or property is calibre library bookkeeping
+fn is_calibre_bookkeeping(name: &str) -> bool {
+    CALIBRE_BOOKKEEPING.contains(&name) || name.starts_with("calibre:user_metadata")
+}
+
+/// calibre's own id for the book in the user's library:
+/// `<dc:identifier opf:scheme="calibre" id="calibre_id">`
+fn is_calibre_identifier(element: &MetadataElement) -> bool {
+    element.is_dublin_core()
+        && element.get_local_name() == "identifier"
+        && (element.get_attribute("id") == Some("calibre_id")
+            || element.get_attributes().iter().any(|(name, value)| {
+                name.rsplit(':').next() == Some("scheme") && value.eq_ignore_ascii_case("calibre")
+            }))
+}
+
+/// Whether `value` mentions one of `WATERMARK_KEYWORDS`
+fn has_keyword(value: &str) -> bool {
+    let value = value.to_ascii_lowercase();
+    WATERMARK_KEYWORDS
+        .iter()
+        .any(|keyword| value.contains(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::WriteOptions;
+    use crate::test_support::{CONTAINER, build};
+
+    #[test]
+    fn buyer_data_is_found() {
+        let opf = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">id</dc:identifier>
+    <dc:identifier opf:scheme="calibre" id="calibre_id">17</dc:identifier>
+    <dc:title>T</dc:title>
+    <dc:rights>Licensed to reader@example.com</dc:rights>
+    <meta property="store:transaction">TX-1234</meta>
+    <meta name="calibre:series" content="Dune"/>
+    <meta name="calibre:title_sort" content="T"/>
+    <meta name="calibre:timestamp" content="2024-01-02T03:04:05+00:00"/>
+  </metadata>
+  <manifest><item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/></manifest>
+  <spine><itemref idref="ch1"/></spine>
+</package>"#;
+        let chapter = r#"<html><head><meta name="customer-id" content="42"/><meta name="viewport" content="width=600"/></head>
+<body><p>One</p><div style="display: none">Sold to reader@example.com</div><p class="note">Two</p></body></html>"#;
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+
+        let marks = epub.watermarks();
+        let found: Vec<(WatermarkKind, &str, &str)> = marks
+            .iter()
+            .map(|mark| (mark.get_kind(), mark.get_name(), mark.get_text()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    WatermarkKind::Metadata,
+                    "dc:rights",
+                    "Licensed to reader@example.com"
+                ),
+                (WatermarkKind::Metadata, "store:transaction", "TX-1234"),
+                (WatermarkKind::DocumentMeta, "customer-id", "42"),
+                (
+                    WatermarkKind::HiddenText,
+                    "div",
+                    "Sold to reader@example.com"
+                ),
+            ]
+        );
+        assert_eq!(marks[2].get_href(), Some("ch1.xhtml"));
+
+        let expected = [
+            "removed metadata dc:rights",
+            "removed metadata store:transaction",
+            "removed <meta name=\"customer-id\"> from ch1.xhtml",
+            "removed hidden <div> from ch1.xhtml",
+            "removed metadata calibre:timestamp",
+            "removed calibre dc:identifier",
+        ];
+        let options = WriteOptions::new().with_scrub_personal_data(true);
+        let (_, report) = epub.to_bytes_with_report(&options).unwrap();
+        assert_eq!(report.get_scrubbed(), expected);
+        assert!(epub.get_parse_report().get_scrubbed().is_empty());
+
+        let mut epub = epub;
+        let scrubbed = epub.scrub_personal_data();
+        assert_eq!(scrubbed, expected);
+        assert_eq!(epub.get_parse_report().get_scrubbed(), scrubbed);
+        assert!(epub.watermarks().is_empty());
+
+        let saved = Epub::from_bytes(epub.to_bytes().unwrap()).unwrap();
+        assert!(saved.watermarks().is_empty());
+        let names: Vec<&str> = saved
+            .get_metadata_elements()
+            .iter()
+            .filter_map(|element| element.get_attribute("name"))
+            .collect();
+        assert_eq!(names, ["calibre:series", "calibre:title_sort"]);
+        let identifiers = saved
+            .get_metadata_elements()
+            .iter()
+            .filter(|element| element.get_local_name() == "identifier")
+            .count();
+        assert_eq!(identifiers, 1);
+        assert!(
+            saved.get_all_files()[0]
+                .content
+                .contains("<p>One</p><p class=\"note\">")
+        );
+    }
+
+    #[test]
+    fn nested_watermarks_are_removed_once() {
+        let opf = crate::test_support::package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let chapter = r#"<html><body><p>Före</p><div style="display:none">Sold to ö@example.com <span hidden="">reader@example.com</span> <meta name="customer-id" content="42"/> ü</div><p>Efter</p></body></html>"#;
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            ("ch1.xhtml", chapter),
+        ]))
+        .unwrap();
+        // The hidden <span> is part of the <div>; the <meta> is found on its own
+        assert_eq!(epub.watermarks().len(), 2);
+
+        epub.scrub_personal_data();
+        assert_eq!(
+            epub.get_all_files()[0].content,
+            "<html><body><p>Före</p><p>Efter</p></body></html>"
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Writing EPUB archives
+//!
+//! Saving re-packs the original archive: `mimetype` goes first and stored,
+//! the OPF is regenerated from the (possibly edited) package, entries that
+//! were replaced or added through the editing API are written from memory
+//! and everything else is copied over without recompressing, unless
+//! `WriteOptions` asks for different compression.
+
+use crate::epub::Epub;
+use crate::options::WriteOptions;
+use crate::report::ParseReport;
+use std::error::Error;
+use std::io::{Cursor, Seek, Write};
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+/// Where the checksum manifest lives; a stale one from the source is never copied
+pub(crate) const CHECKSUMS_PATH: &str = "META-INF/checksums.json";
+
+impl Epub {
+    /// Write the book, including any edits, to a new EPUB file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        self.save_with_options(path, &WriteOptions::default())
+    }
+
+    /// Like `save`, but with non-default write options
+    pub fn save_with_options(
+        &self,
+        path: impl AsRef<Path>,
+        options: &WriteOptions,
+    ) -> Result<(), Box<dyn Error>> {
+        let file = std::fs::File::create(path)?;
+        self.write_with_options(file, options)?;
+        Ok(())
+    }
+
+    /// Serialize the book, including any edits, as EPUB bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.to_bytes_with_options(&WriteOptions::default())
+    }
+
+    /// Like `to_bytes`, but with non-default write options
+    pub fn to_bytes_with_options(&self, options: &WriteOptions) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(self.to_bytes_with_report(options)?.0)
+    }
+
+    /// Like `to_bytes_with_options`, also returning the report of the book
+    /// as written
+    ///
+    /// The options' transforms run on a copy of the book, so this is where
+    /// to find what they did, e.g. the removals `with_scrub_personal_data`
+    /// lists in `ParseReport::get_scrubbed`.
+    pub fn to_bytes_with_report(
+        &self,
+        options: &WriteOptions,
+    ) -> Result<(Vec<u8>, ParseReport), Box<dyn Error>> {
+        let (cursor, report) = self.write_with_options(Cursor::new(Vec::new()), options)?;
+        Ok((cursor.into_inner(), report))
+    }
+
+    fn write_with_options<W: Write + Seek>(
+        &self,
+        writer: W,
+        options: &WriteOptions,
+    ) -> Result<(W, ParseReport), Box<dyn Error>> {
+        if !options.transforms_content() {
+            return Ok((self.write_to(writer, options)?, self.report.clone()));
+        }
+
+        let mut book = self.clone();
+        if options.get_externalize_images() {
+            book.externalize_embedded_images()?;
+        }
+        if options.get_prune_orphans() {
+            book.prune_orphans();
+        }
+        if options.get_scrub_personal_data() {
+            book.scrub_personal_data();
+        }
+        if let Some(typography) = options.get_typography() {
+            book.fix_typography(typography);
+        }
+        if let Some(patch) = options.get_dark_mode_patch() {
+            book.patch_dark_mode(patch);
+        }
+        #[cfg(feature = "image")]
+        if let Some(images) = options.get_image_optimization() {
+            book.optimize_images(images)?;
+        }
+        #[cfg(feature = "fonts")]
+        if options.get_font_subsetting() {
+            book.subset_fonts();
+        }
+        Ok((book.write_to(writer, options)?, book.report))
+    }
+
+    fn write_to<W: Write + Seek>(
+        &self,
+        writer: W,
+        options: &WriteOptions,
+    ) -> Result<W, Box<dyn Error>> {
+        let mut zip = ZipWriter::new(writer);
+        let timestamp = options.fixed_timestamp().map(zip_time);
+        let base = match timestamp {
+            Some(time) => SimpleFileOptions::default().last_modified_time(time),
+            None => SimpleFileOptions::default(),
+        };
+        let stored = base.compression_method(CompressionMethod::Stored);
+        let deflated = base
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(options.get_deflate_level().map(i64::from));
+        let options_for = |path: &str| {
+            if options.get_store_precompressed() && is_precompressed(&self.media_type_of(path)) {
+                stored
+            } else {
+                deflated
+            }
+        };
+
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+
+        let container = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="{}" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+            crate::text::escape_xml(&self.opf_path)
+        );
+        zip.start_file(CONTAINER_PATH, deflated)?;
+        zip.write_all(container.as_bytes())?;
+
+        let package = self.to_package();
+        let opf = match options.fixed_timestamp() {
+            Some(time) => package.to_xml_modified_at(time),
+            None => package.to_xml(),
+        };
+        zip.start_file(self.opf_path.as_str(), deflated)?;
+        zip.write_all(opf.as_bytes())?;
+
+        // Original entries (by index) followed by added or replaced ones
+        let mut archive = self.open_archive()?;
+        let mut entries: Vec<(String, Option<usize>)> = (0..archive.len())
+            .filter_map(|index| Some((archive.name_for_index(index)?.to_string(), Some(index))))
+            .filter(|(name, _)| {
+                let regenerated = name == "mimetype"
+                    || name == CONTAINER_PATH
+                    || name == CHECKSUMS_PATH
+                    || *name == self.opf_path;
+                let replaced = self.overrides.contains_key(name) || self.removed.contains(name);
+                !(regenerated || replaced || name.ends_with('/'))
+            })
+            .collect();
+        entries.extend(self.overrides.keys().map(|path| (path.clone(), None)));
+        if options.get_deterministic() {
+            entries.sort();
+        }
+
+        for (name, index) in &entries {
+            let Some(index) = *index else {
+                zip.start_file(name.as_str(), options_for(name))?;
+                zip.write_all(&self.overrides[name])?;
+                continue;
+            };
+            if options.recompresses() {
+                let mut entry = archive.by_index(index)?;
+                zip.start_file(name.as_str(), options_for(name))?;
+                std::io::copy(&mut entry, &mut zip)?;
+            } else if let Some(time) = timestamp {
+                zip.raw_copy_file_touch(archive.by_index_raw(index)?, time, None)?;
+            } else {
+                zip.raw_copy_file(archive.by_index_raw(index)?)?;
+            }
+        }
+
+        #[cfg(feature = "checksums")]
+        if options.get_checksums() {
+            use crate::checksums::digest;
+            use std::io::Read;
+
+            let mut digests = std::collections::BTreeMap::new();
+            digests.insert(CONTAINER_PATH.to_string(), digest(container.as_bytes()));
+            digests.insert(self.opf_path.clone(), digest(opf.as_bytes()));
+            for (name, index) in entries {
+                let hash = match index {
+                    Some(index) => {
+                        let mut bytes = Vec::new();
+                        archive.by_index(index)?.read_to_end(&mut bytes)?;
+                        digest(&bytes)
+                    }
+                    None => digest(&self.overrides[&name]),
+                };
+                digests.insert(name, hash);
+            }
+            zip.start_file(CHECKSUMS_PATH, deflated)?;
+            zip.write_all(crate::checksums::manifest(&digests).as_bytes())?;
+        }
+
+        Ok(zip.finish()?)
+    }
+
+    /// Media type of an archive entry: the manifest's, else guessed from the extension
+    fn media_type_of(&self, path: &str) -> String {
+        if let Some(resource) = self
+            .resources
+            .iter()
+            .find(|resource| Self::resolve_path(&self.opf_path, &resource.href) == path)
+        {
+            return resource.media_type.clone();
+        }
+        crate::href::media_type_for(path).to_string()
+    }
+}
+
+/// Formats that are compressed already and gain nothing from deflate
+fn is_precompressed(media_type: &str) -> bool {
+    let media_type = media_type.to_ascii_lowercase();
+    (media_type.starts_with("image/") && media_type != "image/svg+xml")
+        || media_type.starts_with("audio/")
+        || media_type.starts_with("video/")
+        || media_type.contains("woff")
+}
+
+/// A zip timestamp, clamped to the 1980-2107 range the format can hold
+fn zip_time(time: chrono::NaiveDateTime) -> DateTime {
+    use chrono::{Datelike, Timelike};
+    let year = time.year().clamp(1980, 2107) as u16;
+    DateTime::from_date_and_time(
+        year,
+        time.month() as u8,
+        time.day() as u8,
+        time.hour() as u8,
+        time.minute() as u8,
+        time.second() as u8,
+    )
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{CONTAINER, build, package};
+    use zip::ZipArchive;
+
+    #[test]
+    fn compression_and_timestamps_follow_the_options() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>
+               <item id="img" href="photo.png" media-type="image/png"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("content.opf", &opf),
+            (
+                "ch1.xhtml",
+                "<html><body><p>Call me Ishmael.</p></body></html>",
+            ),
+            ("photo.png", "png"),
+        ]))
+        .unwrap();
+
+        let time = chrono::NaiveDate::from_ymd_opt(2024, 5, 17)
+            .unwrap()
+            .and_hms_opt(12, 30, 0)
+            .unwrap();
+        let options = WriteOptions::new()
+            .with_deflate_level(9)
+            .with_store_precompressed(true)
+            .with_timestamp(time);
+        let bytes = epub.to_bytes_with_options(&options).unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.name_for_index(0), Some("mimetype"));
+        for (name, method) in [
+            ("mimetype", CompressionMethod::Stored),
+            ("photo.png", CompressionMethod::Stored),
+            ("ch1.xhtml", CompressionMethod::Deflated),
+        ] {
+            let entry = archive.by_name(name).unwrap();
+            assert_eq!(entry.compression(), method, "{}", name);
+            let modified = entry.last_modified().unwrap();
+            assert_eq!((modified.year(), modified.hour()), (2024, 12));
+        }
+    }
+
+    #[test]
+    fn deterministic_output_is_sorted_and_repeatable() {
+        let opf = package(
+            r#"<item id="ch1" href="ch1.xhtml" media-type="application/xhtml+xml"/>"#,
+            r#"<itemref idref="ch1"/>"#,
+        );
+        let mut epub = Epub::from_bytes(build(&[
+            ("META-INF/container.xml", CONTAINER),
+            ("zz.css", "p {}"),
+            ("content.opf", &opf),
+            ("ch1.xhtml", "<html><body><p>One</p></body></html>"),
+        ]))
+        .unwrap();
+        epub.add_resource("a.css", "text/css", b"h1 {}".to_vec())
+            .unwrap();
+
+        let options = WriteOptions::new().with_deterministic(true);
+        let first = epub.to_bytes_with_options(&options).unwrap();
+        assert_eq!(first, epub.to_bytes_with_options(&options).unwrap());
+
+        let mut archive = ZipArchive::new(Cursor::new(first)).unwrap();
+        let names: Vec<&str> = (0..archive.len())
+            .filter_map(|index| archive.name_for_index(index))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "mimetype",
+                CONTAINER_PATH,
+                "content.opf",
+                "a.css",
+                "ch1.xhtml",
+                "zz.css"
+            ]
+        );
+        let mut opf = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("content.opf").unwrap(), &mut opf)
+            .unwrap();
+        assert!(opf.contains(">1980-01-01T00:00:00Z</meta>"));
+    }
+}
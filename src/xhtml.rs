@@ -0,0 +1,327 @@
+//! Producing XHTML content documents
+//!
+//! EPUB content documents must be well-formed XML, while chapter bodies
+//! handed to the builder are usually HTML fragments. `normalize_fragment`
+//! fixes the differences that come up in practice; `document` wraps a body
+//! in the boilerplate every content document needs.
+
+use crate::text::{decode_entities, escape_xml};
+use regex::{Captures, Regex};
+use std::ops::Range;
+use std::sync::LazyLock;
+
+/// HTML elements that never have content and must be self-closed in XHTML
+pub(crate) const VOID_ELEMENTS: &str =
+    "area|base|br|col|embed|hr|img|input|link|meta|param|source|track|wbr";
+/// Elements whose text is code or notation rather than prose
+pub(crate) const VERBATIM: &[&str] = &[
+    "code", "pre", "kbd", "samp", "var", "tt", "script", "style", "math", "svg",
+];
+/// Elements that start a new run of text
+pub(crate) const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "li",
+    "dt",
+    "dd",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "td",
+    "th",
+    "br",
+    "section",
+    "aside",
+    "figcaption",
+    "caption",
+    "body",
+];
+
+/// A start or end tag: slash, name, attributes
+///
+/// Compiled on first use and shared by every book, like the other patterns
+/// run over each content document.
+pub(crate) static TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<(/?)([A-Za-z][A-Za-z0-9:_-]*)((?:[^>"']|"[^"]*"|'[^']*')*)>"#)
+        .expect("tag pattern")
+});
+/// Comments, CDATA sections, declarations and tags (slash and name as
+/// groups 1 and 2); text nodes are what lies between matches
+pub(crate) static MARKUP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"(?s)<!--.*?-->|<!\[CDATA\[.*?\]\]>|<[!?][^>]*>|<(/?)([A-Za-z][A-Za-z0-9:_-]*)(?:[^>"']|"[^"]*"|'[^']*')*>"#,
+    )
+    .expect("markup pattern")
+});
+static ATTRIBUTE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"([^\s"'>/=]+)(?:\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+)))?"#)
+        .expect("attribute pattern")
+});
+static VOID_TAG: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(r"(?i)<({})\b((?:[^>/]|/[^>])*)/?>", VOID_ELEMENTS))
+        .expect("void element pattern")
+});
+static REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"&(#[0-9]+;|#[xX][0-9a-fA-F]+;|[A-Za-z][A-Za-z0-9]*;)?").expect("entity pattern")
+});
+
+/// HTML named entities common in prose, which XML doesn't predefine
+const NAMED_ENTITIES: &[(&str, u32)] = &[
+    ("nbsp", 160),
+    ("iexcl", 161),
+    ("cent", 162),
+    ("pound", 163),
+    ("euro", 8364),
+    ("yen", 165),
+    ("sect", 167),
+    ("copy", 169),
+    ("laquo", 171),
+    ("reg", 174),
+    ("shy", 173),
+    ("deg", 176),
+    ("plusmn", 177),
+    ("para", 182),
+    ("middot", 183),
+    ("raquo", 187),
+    ("frac14", 188),
+    ("frac12", 189),
+    ("frac34", 190),
+    ("iquest", 191),
+    ("times", 215),
+    ("divide", 247),
+    ("ensp", 8194),
+    ("emsp", 8195),
+    ("thinsp", 8201),
+    ("zwnj", 8204),
+    ("zwj", 8205),
+    ("lrm", 8206),
+    ("rlm", 8207),
+    ("ndash", 8211),
+    ("mdash", 8212),
+    ("lsquo", 8216),
+    ("rsquo", 8217),
+    ("sbquo", 8218),
+    ("ldquo", 8220),
+    ("rdquo", 8221),
+    ("bdquo", 8222),
+    ("dagger", 8224),
+    ("Dagger", 8225),
+    ("bull", 8226),
+    ("hellip", 8230),
+    ("prime", 8242),
+    ("Prime", 8243),
+    ("trade", 8482),
+];
+
+/// Make an HTML fragment well-formed enough to embed in XHTML
+///
+/// Void elements are self-closed, HTML named entities become numeric
+/// references, and stray `&`s are escaped. Anything else (unclosed `<p>`s,
+/// unquoted attributes) is left for the author to fix.
+pub(crate) fn normalize_fragment(html: &str) -> String {
+    let html = VOID_TAG.replace_all(html, |cap: &Captures| {
+        format!("<{}{}/>", &cap[1], cap[2].trim_end())
+    });
+
+    REFERENCE
+        .replace_all(&html, |cap: &Captures| {
+            let Some(reference) = cap.get(1).map(|m| m.as_str()) else {
+                return "&amp;".to_string();
+            };
+            let name = reference.trim_end_matches(';');
+            if name.starts_with('#') || matches!(name, "amp" | "lt" | "gt" | "quot" | "apos") {
+                return cap[0].to_string();
+            }
+            match NAMED_ENTITIES.iter().find(|(known, _)| *known == name) {
+                Some((_, code)) => format!("&#{};", code),
+                None => format!("&amp;{}", reference),
+            }
+        })
+        .into_owned()
+}
+
+/// Byte offset of the start tag of the element whose `id` is `id`
+pub(crate) fn find_id(html: &str, id: &str) -> Option<usize> {
+    TAG.captures_iter(html)
+        .find(|cap| {
+            cap[1].is_empty()
+                && parse_attributes(&cap[3])
+                    .iter()
+                    .any(|(name, value)| name == "id" && value == id)
+        })
+        .map(|cap| cap.get(0).expect("whole match").start())
+}
+
+/// An element found by `find_elements`
+#[derive(Debug, Clone)]
+pub(crate) struct Element<'a> {
+    pub(crate) name: String,
+    pub(crate) attributes: Vec<(String, String)>,
+    /// Markup between the start and end tags
+    pub(crate) inner: &'a str,
+    /// Byte range of the whole element, tags included
+    pub(crate) range: Range<usize>,
+}
+
+impl Element<'_> {
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether a space-separated attribute such as `class` or `epub:type` lists `token`
+    pub(crate) fn has_token(&self, attribute: &str, token: &str) -> bool {
+        self.attribute(attribute)
+            .is_some_and(|value| value.split_whitespace().any(|t| t == token))
+    }
+}
+
+/// The outermost elements of `html` for which `wanted(element)` holds
+///
+/// `wanted` sees each start tag with an empty `inner`. Nested elements of
+/// the same name are matched up, so a `<div>` inside a wanted `<div>` doesn't
+/// end it early. Elements that are never closed are skipped.
+pub(crate) fn find_elements<'a>(
+    html: &'a str,
+    mut wanted: impl FnMut(&Element) -> bool,
+) -> Vec<Element<'a>> {
+    let mut found = Vec::new();
+    let mut open: Option<(Element<'a>, usize, usize)> = None;
+
+    for cap in TAG.captures_iter(html) {
+        let whole = cap.get(0).expect("whole match");
+        let closing = !cap[1].is_empty();
+        let name = cap[2].to_ascii_lowercase();
+        let self_closing = cap[3].trim_end().ends_with('/');
+
+        match &mut open {
+            None if !closing => {
+                let element = Element {
+                    name,
+                    attributes: parse_attributes(&cap[3]),
+                    inner: "",
+                    range: whole.range(),
+                };
+                if !wanted(&element) {
+                    continue;
+                }
+                if self_closing {
+                    found.push(element);
+                } else {
+                    open = Some((element, whole.end(), 1));
+                }
+            }
+            Some((element, _, depth)) if element.name == name => {
+                if closing {
+                    *depth -= 1;
+                } else if !self_closing {
+                    *depth += 1;
+                }
+                if *depth == 0 {
+                    let (mut element, inner_start, _) = open.take().expect("open element");
+                    element.inner = &html[inner_start..whole.start()];
+                    element.range = element.range.start..whole.end();
+                    found.push(element);
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// Attributes of a start tag as `(lowercased name, decoded value)`
+pub(crate) fn parse_attributes(attributes: &str) -> Vec<(String, String)> {
+    ATTRIBUTE
+        .captures_iter(attributes)
+        .map(|cap| {
+            let value = cap
+                .get(2)
+                .or_else(|| cap.get(3))
+                .or_else(|| cap.get(4))
+                .map_or("", |m| m.as_str());
+            (cap[1].to_ascii_lowercase(), decode_entities(value))
+        })
+        .collect()
+}
+
+/// A `<link>` to a stylesheet, for the `head` of `document`
+pub(crate) fn stylesheet_link(href: &str) -> String {
+    format!(
+        "<link rel=\"stylesheet\" type=\"text/css\" href=\"{}\"/>",
+        escape_xml(href)
+    )
+}
+
+/// A complete XHTML content document
+///
+/// `head` is extra markup for the `<head>`, such as `stylesheet_link`s;
+/// `body_type` becomes the body's `epub:type`.
+pub(crate) fn document(
+    title: &str,
+    language: &str,
+    head: &str,
+    body_type: Option<&str>,
+    body: &str,
+) -> String {
+    let language = escape_xml(language);
+    let head = if head.is_empty() {
+        String::new()
+    } else {
+        format!("\n  {}", head.trim())
+    };
+    let body_type = body_type
+        .map(|kind| format!(" epub:type=\"{}\"", escape_xml(kind)))
+        .unwrap_or_default();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{lang}" lang="{lang}">
+<head>
+  <meta charset="utf-8"/>
+  <title>{title}</title>{head}
+</head>
+<body{body_type}>
+{body}
+</body>
+</html>
+"#,
+        lang = language,
+        title = escape_xml(title),
+        head = head,
+        body_type = body_type,
+        body = body.trim(),
+    )
+}
+
+/// A cover page showing the image at `image_src` (relative to the page)
+pub(crate) fn cover_page(language: &str, image_src: &str) -> String {
+    let style = "<style>body { margin: 0; padding: 0; text-align: center; } img { max-width: 100%; max-height: 100vh; }</style>";
+    let body = format!("<img src=\"{}\" alt=\"Cover\"/>", escape_xml(image_src));
+    document("Cover", language, style, Some("cover"), &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fragments_become_well_formed() {
+        assert_eq!(
+            normalize_fragment(
+                r#"<p>Fish &amp; chips &mdash; R&D<br><img src="a.png" alt="x"></p><hr />"#
+            ),
+            r#"<p>Fish &amp; chips &#8212; R&amp;D<br/><img src="a.png" alt="x"/></p><hr/>"#
+        );
+        assert_eq!(
+            normalize_fragment("&#233;&unknown; & &lt;"),
+            "&#233;&amp;unknown; &amp; &lt;"
+        );
+    }
+}